@@ -0,0 +1,13 @@
+use criterion::{criterion_group, criterion_main};
+use sqlrite::bench;
+
+criterion_group!(
+    benches,
+    bench::insert,
+    bench::point_lookup,
+    bench::scan,
+    bench::sort,
+    bench::join,
+    bench::pager
+);
+criterion_main!(benches);
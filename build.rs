@@ -0,0 +1,9 @@
+fn main() {
+    // Generates the gRPC service from proto/sqlrite.proto, with a bundled protoc
+    #[cfg(feature = "grpc")]
+    {
+        let protoc = protoc_bin_vendored::protoc_bin_path().expect("protoc is not available");
+        std::env::set_var("PROTOC", protoc);
+        tonic_build::compile_protos("proto/sqlrite.proto").expect("failed to compile protos");
+    }
+}
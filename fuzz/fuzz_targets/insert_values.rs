@@ -0,0 +1,126 @@
+//! Creates a table with a random schema and inserts random rows into random columns of
+//! it. Inserts either fail with an error or leave the table consistent with its indexes.
+#![no_main]
+use arbitrary::Arbitrary;
+use libfuzzer_sys::fuzz_target;
+use sqlrite::sql::db::database::Database;
+use sqlrite::sql::process_command;
+
+#[derive(Arbitrary, Debug)]
+enum ColumnType {
+    Integer,
+    Text,
+    Real,
+    Bool,
+}
+
+#[derive(Arbitrary, Debug)]
+struct ColumnDef {
+    datatype: ColumnType,
+    primary_key: bool,
+    unique: bool,
+    not_null: bool,
+}
+
+#[derive(Arbitrary, Debug)]
+enum Literal {
+    Null,
+    Integer(i64),
+    Real(f64),
+    Text(String),
+    Bool(bool),
+}
+
+#[derive(Arbitrary, Debug)]
+struct Insert {
+    /// Indexes of the columns given values, taken modulo the number of columns
+    columns: Vec<u8>,
+    rows: Vec<Vec<Literal>>,
+}
+
+#[derive(Arbitrary, Debug)]
+struct Input {
+    columns: Vec<ColumnDef>,
+    inserts: Vec<Insert>,
+}
+
+fn create_table(columns: &[ColumnDef]) -> String {
+    let mut has_primary_key = false;
+    let definitions = columns
+        .iter()
+        .enumerate()
+        .map(|(i, column)| {
+            let datatype = match column.datatype {
+                ColumnType::Integer => "INTEGER",
+                ColumnType::Text => "TEXT",
+                ColumnType::Real => "REAL",
+                ColumnType::Bool => "BOOL",
+            };
+            let mut definition = format!("c{} {}", i, datatype);
+            if column.primary_key && !has_primary_key {
+                has_primary_key = true;
+                definition.push_str(" PRIMARY KEY");
+            } else if column.unique {
+                definition.push_str(" UNIQUE");
+            }
+            if column.not_null {
+                definition.push_str(" NOT NULL");
+            }
+            definition
+        })
+        .collect::<Vec<String>>();
+    format!("CREATE TABLE t ({});", definitions.join(", "))
+}
+
+fn literal(literal: &Literal) -> String {
+    match literal {
+        Literal::Null => "NULL".to_string(),
+        Literal::Integer(value) => value.to_string(),
+        Literal::Real(value) if value.is_finite() => format!("{:?}", value),
+        Literal::Real(_) => "NULL".to_string(),
+        Literal::Text(value) => format!("'{}'", value.replace('\'', "''")),
+        Literal::Bool(value) => value.to_string(),
+    }
+}
+
+fn insert(insert: &Insert, columns: usize) -> Option<String> {
+    if insert.columns.is_empty() || insert.rows.is_empty() {
+        return None;
+    }
+    let names = insert
+        .columns
+        .iter()
+        .map(|i| format!("c{}", *i as usize % columns))
+        .collect::<Vec<String>>();
+    let rows = insert
+        .rows
+        .iter()
+        .map(|row| {
+            let values = row.iter().map(literal).collect::<Vec<String>>();
+            format!("({})", values.join(", "))
+        })
+        .collect::<Vec<String>>();
+    Some(format!(
+        "INSERT INTO t ({}) VALUES {};",
+        names.join(", "),
+        rows.join(", ")
+    ))
+}
+
+fuzz_target!(|input: Input| {
+    if input.columns.is_empty() || input.columns.len() > 16 {
+        return;
+    }
+    let mut db = Database::new("fuzz".to_string());
+    if process_command(&create_table(&input.columns), &mut db).is_err() {
+        return;
+    }
+    for statement in input
+        .inserts
+        .iter()
+        .filter_map(|i| insert(i, input.columns.len()))
+    {
+        let _ = process_command(&statement, &mut db);
+    }
+    assert_eq!(db.tables["t"].check_integrity(), Vec::<String>::new());
+});
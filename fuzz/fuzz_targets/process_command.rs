@@ -0,0 +1,29 @@
+//! Runs arbitrary statements, one per line, through `process_command` against an in-memory
+//! database with a table already in it. Any panic is a bug: bad SQL must be an error.
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+use sqlrite::sql::db::database::Database;
+use sqlrite::sql::{process_command, process_query};
+
+const SETUP: &[&str] = &[
+    "CREATE TABLE users (id INTEGER PRIMARY KEY, name TEXT NOT NULL, email TEXT UNIQUE, age INTEGER, score REAL, active BOOL);",
+    "INSERT INTO users (name, email, age, score, active) VALUES ('josh', 'josh@example.com', 30, 1.5, true);",
+];
+
+fuzz_target!(|data: &[u8]| {
+    let input = match std::str::from_utf8(data) {
+        Ok(input) => input,
+        Err(_) => return,
+    };
+    let mut db = Database::new("fuzz".to_string());
+    for statement in SETUP {
+        process_command(statement, &mut db).expect("setup");
+    }
+    for statement in input.lines() {
+        let _ = process_command(statement, &mut db);
+        let _ = process_query(statement, &db);
+    }
+    for table in db.tables.values() {
+        assert_eq!(table.check_integrity(), Vec::<String>::new());
+    }
+});
@@ -0,0 +1,80 @@
+/*
+ * Subset of the sqlite3 C API implemented by SQLRite.
+ *
+ * Build the library with `cargo build --release --features ffi` and link against
+ * the resulting cdylib (libsqlrite.so, libsqlrite.dylib or sqlrite.dll).
+ * Databases are always in-memory, the filename given to sqlite3_open is ignored.
+ */
+#ifndef SQLRITE_SQLITE3_H
+#define SQLRITE_SQLITE3_H
+
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef struct sqlite3 sqlite3;
+typedef struct sqlite3_stmt sqlite3_stmt;
+typedef int64_t sqlite3_int64;
+typedef int (*sqlite3_callback)(void *, int, char **, char **);
+
+#define SQLITE_OK 0
+#define SQLITE_ERROR 1
+#define SQLITE_NOMEM 7
+#define SQLITE_MISUSE 21
+#define SQLITE_RANGE 25
+#define SQLITE_ROW 100
+#define SQLITE_DONE 101
+
+#define SQLITE_INTEGER 1
+#define SQLITE_FLOAT 2
+#define SQLITE_TEXT 3
+#define SQLITE_BLOB 4
+#define SQLITE_NULL 5
+
+typedef void (*sqlite3_destructor_type)(void *);
+#define SQLITE_STATIC ((sqlite3_destructor_type)0)
+#define SQLITE_TRANSIENT ((sqlite3_destructor_type)-1)
+
+const char *sqlite3_libversion(void);
+
+int sqlite3_open(const char *filename, sqlite3 **ppDb);
+int sqlite3_close(sqlite3 *db);
+int sqlite3_errcode(sqlite3 *db);
+const char *sqlite3_errmsg(sqlite3 *db);
+sqlite3_int64 sqlite3_last_insert_rowid(sqlite3 *db);
+int sqlite3_changes(sqlite3 *db);
+int sqlite3_total_changes(sqlite3 *db);
+
+int sqlite3_exec(sqlite3 *db, const char *sql, sqlite3_callback callback, void *arg, char **errmsg);
+void sqlite3_free(void *p);
+
+int sqlite3_prepare_v2(sqlite3 *db, const char *zSql, int nByte, sqlite3_stmt **ppStmt, const char **pzTail);
+int sqlite3_step(sqlite3_stmt *stmt);
+int sqlite3_reset(sqlite3_stmt *stmt);
+int sqlite3_clear_bindings(sqlite3_stmt *stmt);
+int sqlite3_finalize(sqlite3_stmt *stmt);
+
+int sqlite3_bind_parameter_count(sqlite3_stmt *stmt);
+int sqlite3_bind_null(sqlite3_stmt *stmt, int index);
+int sqlite3_bind_int(sqlite3_stmt *stmt, int index, int value);
+int sqlite3_bind_int64(sqlite3_stmt *stmt, int index, sqlite3_int64 value);
+int sqlite3_bind_double(sqlite3_stmt *stmt, int index, double value);
+int sqlite3_bind_text(sqlite3_stmt *stmt, int index, const char *text, int n, sqlite3_destructor_type destructor);
+
+int sqlite3_column_count(sqlite3_stmt *stmt);
+const char *sqlite3_column_name(sqlite3_stmt *stmt, int col);
+int sqlite3_column_type(sqlite3_stmt *stmt, int col);
+int sqlite3_column_int(sqlite3_stmt *stmt, int col);
+sqlite3_int64 sqlite3_column_int64(sqlite3_stmt *stmt, int col);
+double sqlite3_column_double(sqlite3_stmt *stmt, int col);
+const unsigned char *sqlite3_column_text(sqlite3_stmt *stmt, int col);
+const void *sqlite3_column_blob(sqlite3_stmt *stmt, int col);
+int sqlite3_column_bytes(sqlite3_stmt *stmt, int col);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* SQLRITE_SQLITE3_H */
@@ -0,0 +1,136 @@
+//! A deterministic generator of realistic data: customers with unique emails spread over a
+//! handful of countries, and their orders with a skewed distribution of statuses. The same
+//! seed always generates the same rows, so numbers from two runs compare the same work.
+
+pub use crate::seed::Rng;
+
+const FIRST_NAMES: &[&str] = &[
+    "ana", "bruno", "carla", "diego", "elena", "felix", "grace", "hugo", "iris", "joao", "kim",
+    "lucas", "maria", "nina", "omar", "paula", "quinn", "rosa", "sam", "tomas",
+];
+const LAST_NAMES: &[&str] = &[
+    "silva", "smith", "garcia", "muller", "rossi", "kim", "nguyen", "costa", "dubois", "novak",
+];
+const COUNTRIES: &[&str] = &["BR", "US", "DE", "FR", "PT", "JP", "IN", "MX"];
+/// Statuses of orders, the first ones far more common, like in a real shop
+const STATUSES: &[&str] = &[
+    "delivered",
+    "delivered",
+    "delivered",
+    "shipped",
+    "paid",
+    "refunded",
+];
+
+/// Statements creating the tables the generator fills.
+pub const SCHEMA: &[&str] = &[
+    "CREATE TABLE customers (id INTEGER PRIMARY KEY, name TEXT NOT NULL, email TEXT UNIQUE, country TEXT, active INTEGER, balance REAL);",
+    "CREATE TABLE orders (id INTEGER PRIMARY KEY, customer_id INTEGER NOT NULL, status TEXT, total REAL, created TEXT);",
+];
+
+/// Generates the rows of the tables of `SCHEMA`
+#[derive(Debug, Clone)]
+pub struct Generator {
+    rng: Rng,
+}
+
+impl Generator {
+    pub fn new(seed: u64) -> Self {
+        Generator {
+            rng: Rng::new(seed),
+        }
+    }
+
+    /// INSERT statements adding the customers `first..first + count`, `batch` rows each.
+    pub fn customers(&mut self, first: u64, count: u64, batch: usize) -> Vec<String> {
+        let rows = (first..first + count)
+            .map(|id| {
+                let name = format!(
+                    "{} {}",
+                    self.rng.pick(FIRST_NAMES),
+                    self.rng.pick(LAST_NAMES)
+                );
+                format!(
+                    "({}, '{}', '{}', '{}', {}, {}.{:02})",
+                    id,
+                    name,
+                    customer_email(id),
+                    self.rng.pick(COUNTRIES),
+                    u8::from(self.rng.below(10) != 0),
+                    self.rng.below(10_000),
+                    self.rng.below(100)
+                )
+            })
+            .collect::<Vec<String>>();
+        insert_statements(
+            "customers (id, name, email, country, active, balance)",
+            rows,
+            batch,
+        )
+    }
+
+    /// INSERT statements adding the orders `first..first + count` of customers
+    /// `1..=customers`, `batch` rows each.
+    pub fn orders(&mut self, first: u64, count: u64, customers: u64, batch: usize) -> Vec<String> {
+        let rows = (first..first + count)
+            .map(|id| {
+                format!(
+                    "({}, {}, '{}', {}.{:02}, '2024-{:02}-{:02}')",
+                    id,
+                    self.rng.below(customers) + 1,
+                    self.rng.pick(STATUSES),
+                    self.rng.below(500),
+                    self.rng.below(100),
+                    self.rng.below(12) + 1,
+                    self.rng.below(28) + 1
+                )
+            })
+            .collect::<Vec<String>>();
+        insert_statements(
+            "orders (id, customer_id, status, total, created)",
+            rows,
+            batch,
+        )
+    }
+}
+
+/// The email of the customer `id`, unique to it.
+pub fn customer_email(id: u64) -> String {
+    format!("customer{}@example.com", id)
+}
+
+fn insert_statements(target: &str, rows: Vec<String>, batch: usize) -> Vec<String> {
+    rows.chunks(batch.max(1))
+        .map(|rows| format!("INSERT INTO {} VALUES {};", target, rows.join(", ")))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::connection::Connection;
+
+    #[test]
+    fn generator_test() {
+        let statements = Generator::new(7).customers(1, 5, 2);
+        assert_eq!(statements.len(), 3);
+        assert_eq!(statements, Generator::new(7).customers(1, 5, 2));
+        assert_ne!(statements, Generator::new(8).customers(1, 5, 2));
+
+        let mut conn = Connection::open_in_memory();
+        for statement in SCHEMA {
+            conn.execute(statement).unwrap();
+        }
+        let mut generator = Generator::new(7);
+        for statement in generator
+            .customers(1, 50, 20)
+            .iter()
+            .chain(&generator.orders(1, 200, 50, 64))
+        {
+            conn.execute(statement).unwrap();
+        }
+        let db = conn.database();
+        assert_eq!(db.tables["customers"].rowids().len(), 50);
+        assert_eq!(db.tables["orders"].rowids().len(), 200);
+    }
+}
@@ -0,0 +1,177 @@
+//! Benchmarks of the engine, run with `cargo bench --features bench`, on data from the
+//! deterministic `generator`. They cover insert throughput, point lookups by rowid and
+//! through a UNIQUE index, full scans, sorts, and joins of orders with their customers, so a
+//! change to the storage or the executor is measured before and after. The pager benchmarks
+//! read and save a database file, with its pages read with system calls and from a memory
+//! mapping of the file, to tell when `PRAGMA mmap_size` pays off.
+pub mod generator;
+
+use criterion::{black_box, BatchSize, BenchmarkId, Criterion, Throughput};
+
+use crate::connection::Connection;
+use crate::sql::db::database::Database;
+use crate::sql::db::pager::MAX_MMAP_SIZE;
+use generator::{customer_email, Generator, SCHEMA};
+
+/// Seed of the data every benchmark runs on
+pub const SEED: u64 = 42;
+
+/// Opens an in-memory database with `customers` customers and 4 orders for each of them.
+pub fn populated(customers: u64) -> Connection {
+    let mut conn = Connection::open_in_memory();
+    for statement in SCHEMA {
+        conn.execute(statement).expect("schema");
+    }
+    let mut generator = Generator::new(SEED);
+    let statements = generator
+        .customers(1, customers, 500)
+        .into_iter()
+        .chain(generator.orders(1, customers * 4, customers, 500));
+    for statement in statements {
+        conn.execute(&statement).expect("generated rows");
+    }
+    conn
+}
+
+/// Inserting rows, one per statement and in batches.
+pub fn insert(c: &mut Criterion) {
+    let mut group = c.benchmark_group("insert");
+    let rows = 1000;
+    group.throughput(Throughput::Elements(rows));
+    for batch in [1, 100] {
+        group.bench_with_input(BenchmarkId::new("customers", batch), &batch, |b, batch| {
+            b.iter_batched(
+                || {
+                    let statements = Generator::new(SEED).customers(1, rows, *batch);
+                    (populated(0), statements)
+                },
+                |(mut conn, statements)| {
+                    for statement in &statements {
+                        conn.execute(statement).unwrap();
+                    }
+                    conn
+                },
+                BatchSize::LargeInput,
+            )
+        });
+    }
+    group.finish();
+}
+
+/// Looking a row up by rowid, and by the value of a UNIQUE column.
+pub fn point_lookup(c: &mut Criterion) {
+    let conn = populated(10_000);
+    let mut group = c.benchmark_group("point_lookup");
+    group.bench_function("rowid", |b| {
+        b.iter(|| conn.query(black_box("SELECT * FROM customers WHERE id = 5000;")))
+    });
+    let sql = format!(
+        "SELECT * FROM customers WHERE email = '{}';",
+        customer_email(5000)
+    );
+    group.bench_function("unique", |b| b.iter(|| conn.query(black_box(&sql))));
+    group.finish();
+}
+
+/// Reading every row, with and without a filter no index answers.
+pub fn scan(c: &mut Criterion) {
+    let conn = populated(10_000);
+    let mut group = c.benchmark_group("scan");
+    group.throughput(Throughput::Elements(40_000));
+    group.bench_function("all", |b| b.iter(|| conn.query("SELECT * FROM orders;")));
+    group.bench_function("filtered", |b| {
+        b.iter(|| conn.query("SELECT id, total FROM orders WHERE status = 'refunded';"))
+    });
+    group.finish();
+}
+
+/// Sorting every row on a column.
+pub fn sort(c: &mut Criterion) {
+    let conn = populated(10_000);
+    let mut group = c.benchmark_group("sort");
+    group.throughput(Throughput::Elements(40_000));
+    group.bench_function("real", |b| {
+        b.iter(|| conn.query("SELECT * FROM orders ORDER BY total DESC;"))
+    });
+    group.bench_function("text", |b| {
+        b.iter(|| conn.query("SELECT * FROM customers ORDER BY name, email;"))
+    });
+    group.finish();
+}
+
+/// Joining orders with their customers, looked up by the primary key of each order's
+/// customer, and read whole for each order when no index answers the join condition.
+pub fn join(c: &mut Criterion) {
+    let conn = populated(10_000);
+    let mut group = c.benchmark_group("join");
+    group.throughput(Throughput::Elements(40_000));
+    group.bench_function("indexed", |b| {
+        b.iter(|| {
+            conn.query(
+                "SELECT c.name, o.total FROM orders o JOIN customers c ON c.id = o.customer_id;",
+            )
+        })
+    });
+    // The unary + keeps the index from being used, like in SQLite: on a hundred times fewer
+    // rows, the nested loop compares every order with every customer
+    let conn = populated(100);
+    group.throughput(Throughput::Elements(400 * 100));
+    group.bench_function("nested_loop", |b| {
+        b.iter(|| {
+            conn.query(
+                "SELECT c.name, o.total FROM orders o JOIN customers c ON +c.id = o.customer_id;",
+            )
+        })
+    });
+    group.finish();
+}
+
+/// Reading a database file whole, and saving it back, with its pages read from the file
+/// and from its memory mapping. Saving compares every row stored with the rows in memory,
+/// which only reads pages when no row changed, and writes many when many rows were deleted.
+pub fn pager(c: &mut Criterion) {
+    let dir = std::env::temp_dir();
+    let path = dir.join(format!("sqlrite-bench-{}.db", std::process::id()));
+    let copy = dir.join(format!("sqlrite-bench-{}-copy.db", std::process::id()));
+    populated(10_000)
+        .database()
+        .save_to(&path)
+        .expect("database file");
+    let open = |path: &std::path::Path, mmap_size: u64| {
+        let mut conn = Connection::open_in_memory();
+        let pragma = format!("PRAGMA mmap_size = {};", mmap_size);
+        conn.execute(&pragma).expect("mmap_size");
+        conn.database_mut().open_file(path).expect("database file");
+        conn
+    };
+    let mut group = c.benchmark_group("pager");
+    for (io, mmap_size) in [("buffered", 0), ("mmap", MAX_MMAP_SIZE)] {
+        group.bench_function(BenchmarkId::new("read", io), |b| {
+            b.iter(|| Database::read_file_mapped(&path, mmap_size).unwrap())
+        });
+        let conn = open(&path, mmap_size);
+        group.bench_function(BenchmarkId::new("save_unchanged", io), |b| {
+            b.iter(|| conn.database().save().unwrap())
+        });
+        // Each save deletes the same rows from a new copy of the file
+        group.bench_function(BenchmarkId::new("save_deleted", io), |b| {
+            b.iter_batched(
+                || {
+                    std::fs::copy(&path, &copy).expect("copy of the database file");
+                    let mut conn = open(&copy, mmap_size);
+                    conn.execute("DELETE FROM orders WHERE status = 'delivered';")
+                        .unwrap();
+                    conn
+                },
+                |conn| {
+                    conn.database().save().unwrap();
+                    conn
+                },
+                BatchSize::LargeInput,
+            )
+        });
+    }
+    group.finish();
+    let _ = std::fs::remove_file(&path);
+    let _ = std::fs::remove_file(&copy);
+}
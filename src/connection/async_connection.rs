@@ -0,0 +1,219 @@
+use std::path::Path;
+use std::thread;
+
+use tokio::sync::{mpsc, oneshot};
+
+use crate::connection::rows::Rows;
+use crate::connection::Connection;
+use crate::error::{Result, SQLRiteError};
+use crate::sql::QueryResult;
+
+/// Unit of work shipped to the connection thread.
+type Job = Box<dyn FnOnce(&mut Connection) + Send + 'static>;
+
+/// Async variant of `Connection`, available with the `async` feature.
+///
+/// The underlying `Connection` lives on a dedicated background thread and every call is
+/// shipped to it through a channel, so blocking work (parsing, executing and file I/O)
+/// never runs on the async runtime's worker threads. Cloning an `AsyncConnection` is cheap
+/// and every clone talks to the same database.
+///
+/// # Examples
+///
+/// ```
+/// # #[tokio::main(flavor = "current_thread")]
+/// # async fn main() {
+/// let conn = sqlrite::AsyncConnection::open_in_memory().await.unwrap();
+/// conn.execute("CREATE TABLE users (id INTEGER PRIMARY KEY, name TEXT);")
+///     .await
+///     .unwrap();
+/// # }
+/// ```
+#[derive(Clone, Debug)]
+pub struct AsyncConnection {
+    sender: mpsc::UnboundedSender<Job>,
+}
+
+impl AsyncConnection {
+    /// Opens a connection to a transient in-memory database on a background thread.
+    pub async fn open_in_memory() -> Result<Self> {
+        Self::spawn(|| Ok(Connection::open_in_memory())).await
+    }
+
+    /// Opens a connection to the database file at `path` on a background thread, like
+    /// `Connection::open`. The file is read, and later written by `save`, on that thread.
+    pub async fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        Self::spawn(move || Connection::open(path)).await
+    }
+
+    /// Starts the connection thread, building the `Connection` on it with `open`, and
+    /// returns once it is built or failed to. The thread exits once every
+    /// `AsyncConnection` handle has been dropped.
+    async fn spawn<F>(open: F) -> Result<Self>
+    where
+        F: FnOnce() -> Result<Connection> + Send + 'static,
+    {
+        let (sender, mut receiver) = mpsc::unbounded_channel::<Job>();
+        let (opened, response) = oneshot::channel();
+        thread::Builder::new()
+            .name("sqlrite-connection".to_string())
+            .spawn(move || {
+                let mut conn = match open() {
+                    Ok(conn) => conn,
+                    Err(err) => {
+                        let _ = opened.send(Err(err));
+                        return;
+                    }
+                };
+                let _ = opened.send(Ok(()));
+                while let Some(job) = receiver.blocking_recv() {
+                    job(&mut conn);
+                }
+            })
+            .map_err(|err| {
+                SQLRiteError::Internal(format!("Could not start connection thread: {}", err))
+            })?;
+
+        response.await.map_err(|_| connection_closed())??;
+        Ok(AsyncConnection { sender })
+    }
+
+    /// Runs `f` against the underlying `Connection` on the connection thread and
+    /// returns its result once it is done.
+    pub async fn call<F, R>(&self, f: F) -> Result<R>
+    where
+        F: FnOnce(&mut Connection) -> R + Send + 'static,
+        R: Send + 'static,
+    {
+        let (reply, response) = oneshot::channel();
+        self.sender
+            .send(Box::new(move |conn: &mut Connection| {
+                // The caller may have stopped waiting, there is nobody to report that to.
+                let _ = reply.send(f(conn));
+            }))
+            .map_err(|_| connection_closed())?;
+
+        response.await.map_err(|_| connection_closed())
+    }
+
+    /// Executes a single SQL statement and returns what it did, like `Connection::execute`.
+    pub async fn execute(&self, sql: &str) -> Result<QueryResult> {
+        let sql = sql.to_string();
+        self.call(move |conn| conn.execute(&sql)).await?
+    }
+
+    /// Runs a single SELECT statement and returns a `Rows` cursor over its result.
+    pub async fn query(&self, sql: &str) -> Result<Rows> {
+        let sql = sql.to_string();
+        self.call(move |conn| conn.query(&sql)).await?
+    }
+
+    /// Writes the changes made since the database was last saved back to its file,
+    /// like `Connection::save`.
+    pub async fn save(&self) -> Result<()> {
+        self.call(|conn| conn.save()).await?
+    }
+}
+
+fn connection_closed() -> SQLRiteError {
+    SQLRiteError::Internal("Connection thread is no longer running.".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn async_connection_execute_test() {
+        let conn = AsyncConnection::open_in_memory().await.unwrap();
+
+        let response = conn
+            .execute("CREATE TABLE users (id INTEGER PRIMARY KEY, name TEXT);")
+            .await
+            .unwrap();
+        assert_eq!(response.message, "CREATE TABLE Statement executed.");
+
+        let response = conn
+            .execute("INSERT INTO users (name) VALUES ('josh');")
+            .await
+            .unwrap();
+        assert_eq!(response.rows_affected, Some(1));
+        assert_eq!(response.message, "INSERT Statement executed.");
+    }
+
+    #[tokio::test]
+    async fn async_connection_call_test() {
+        let conn = AsyncConnection::open_in_memory().await.unwrap();
+        conn.execute("CREATE TABLE users (id INTEGER PRIMARY KEY, name TEXT);")
+            .await
+            .unwrap();
+
+        let other = conn.clone();
+        let contains_table = other
+            .call(|conn| conn.database().contains_table("users".to_string()))
+            .await
+            .unwrap();
+        assert!(contains_table);
+    }
+
+    #[tokio::test]
+    async fn async_connection_query_test() {
+        let conn = AsyncConnection::open_in_memory().await.unwrap();
+        conn.execute("CREATE TABLE users (id INTEGER PRIMARY KEY, name TEXT);")
+            .await
+            .unwrap();
+        conn.execute("INSERT INTO users (name) VALUES ('josh');")
+            .await
+            .unwrap();
+
+        let mut rows = conn.query("SELECT name FROM users;").await.unwrap();
+        let row = rows.next().unwrap();
+        assert_eq!(row.get::<String>(0), Ok("josh".to_string()));
+    }
+
+    #[tokio::test]
+    async fn async_connection_open_test() {
+        let path = std::env::temp_dir().join(format!("sqlrite-async-{}.db", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        let conn = AsyncConnection::open(&path).await.unwrap();
+        conn.execute("CREATE TABLE users (id INTEGER PRIMARY KEY, name TEXT);")
+            .await
+            .unwrap();
+        conn.execute("INSERT INTO users (name) VALUES ('josh');")
+            .await
+            .unwrap();
+        assert!(!path.exists());
+        conn.save().await.unwrap();
+        assert!(path.exists());
+        drop(conn);
+
+        let conn = AsyncConnection::open(&path).await.unwrap();
+        let mut rows = conn.query("SELECT name FROM users;").await.unwrap();
+        assert_eq!(
+            rows.next().unwrap().get::<String>(0),
+            Ok("josh".to_string())
+        );
+        assert!(rows.next().is_none());
+
+        conn.execute("BEGIN;").await.unwrap();
+        assert!(conn.save().await.is_err());
+        drop(conn);
+        std::fs::remove_file(&path).unwrap();
+
+        std::fs::write(&path, "not a database").unwrap();
+        assert!(AsyncConnection::open(&path).await.is_err());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn async_connection_execute_error_test() {
+        let conn = AsyncConnection::open_in_memory().await.unwrap();
+
+        let result = conn
+            .execute("INSERT INTO users (name) VALUES ('josh');")
+            .await;
+        assert!(result.is_err());
+    }
+}
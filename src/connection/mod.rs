@@ -0,0 +1,838 @@
+#[cfg(feature = "async")]
+pub mod async_connection;
+pub mod rows;
+
+use std::cmp::Ordering;
+use std::io::Write;
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::error::Result;
+#[cfg(feature = "arrow")]
+use crate::export::arrow::{record_batch, ArrowWriter};
+use crate::export::csv::{CsvOptions, CsvWriter};
+use crate::export::export;
+use crate::export::json::JsonLinesWriter;
+#[cfg(feature = "xlsx")]
+use crate::export::xlsx::{export_workbook, XlsxWorkbook};
+use crate::import::dump::import_dump;
+use crate::import::json::import_json;
+use crate::import::ImportReport;
+use crate::seed::seed as seed_table;
+use crate::sql::db::cache::ResultCacheStats;
+use crate::sql::db::changeset::{Changeset, Session};
+use crate::sql::db::database::Database;
+use crate::sql::db::limits::Limit;
+use crate::sql::db::progress::ProgressFn;
+use crate::sql::value::Value;
+use crate::sql::vtab::VirtualTableModule;
+use crate::sql::{process_command, process_query, process_query_with_params, QueryResult};
+
+#[cfg(feature = "arrow")]
+use arrow_array::RecordBatch;
+use rows::Rows;
+
+/// A `Connection` is the entry point for using SQLRite as a library.
+/// It owns the `Database` it is connected to and runs every SQL statement
+/// through the same tokenizing, parsing and executing pipeline used by the REPL.
+///
+/// # Examples
+///
+/// ```
+/// let mut conn = sqlrite::Connection::open_in_memory();
+/// conn.execute("CREATE TABLE users (id INTEGER PRIMARY KEY, name TEXT);").unwrap();
+/// conn.execute("INSERT INTO users (name) VALUES ('josh');").unwrap();
+/// ```
+#[derive(Debug)]
+pub struct Connection {
+    db: Database,
+}
+
+impl Connection {
+    /// Opens a connection to a transient in-memory database.
+    pub fn open_in_memory() -> Self {
+        Connection {
+            db: Database::new("tempdb".to_string()),
+        }
+    }
+
+    /// Opens a connection to the database file at `path`, created on the first save if it
+    /// doesn't exist yet. Changes are written back to the file by `save` and `close`.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let mut db = Database::new("tempdb".to_string());
+        db.open_file(path.as_ref())?;
+        Ok(Connection { db })
+    }
+
+    /// Writes the changes made since the database was last saved back to its file.
+    /// Does nothing for an in-memory database, and fails while a transaction is open.
+    pub fn save(&self) -> Result<()> {
+        self.db.save()
+    }
+
+    /// Closes the connection like the REPL does as it exits: rolls back the transaction
+    /// left open and saves the database back to its file. Returns whether a transaction
+    /// was rolled back.
+    pub fn close(mut self) -> Result<bool> {
+        self.db.close()
+    }
+
+    /// Executes a single SQL statement and returns what it did: the message produced by the
+    /// engine and, for statements changing rows, the number of rows they changed.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let mut conn = sqlrite::Connection::open_in_memory();
+    /// conn.execute("CREATE TABLE users (id INTEGER PRIMARY KEY, name TEXT);").unwrap();
+    /// let result = conn
+    ///     .execute("INSERT INTO users (name) VALUES ('josh'), ('mary');")
+    ///     .unwrap();
+    /// assert_eq!(result.rows_affected, Some(2));
+    /// assert_eq!(result.last_insert_rowid, 2);
+    /// ```
+    pub fn execute(&mut self, sql: &str) -> Result<QueryResult> {
+        process_command(sql, &mut self.db)
+    }
+
+    /// Runs a single SELECT statement and returns a `Rows` cursor over its result.
+    pub fn query(&self, sql: &str) -> Result<Rows> {
+        process_query(sql, &self.db).map(Rows::new)
+    }
+
+    /// Runs a single SELECT statement with positional `?` parameters bound to `params`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use sqlrite::Value;
+    ///
+    /// let mut conn = sqlrite::Connection::open_in_memory();
+    /// conn.execute("CREATE TABLE users (id INTEGER PRIMARY KEY, name TEXT);").unwrap();
+    /// conn.execute("INSERT INTO users (name) VALUES ('josh'), ('mary');").unwrap();
+    /// let mut rows = conn
+    ///     .query_with_params("SELECT name FROM users WHERE id = ?;", &[Value::Integer(2)])
+    ///     .unwrap();
+    /// assert_eq!(rows.next().unwrap().get::<String>(0).unwrap(), "mary");
+    /// ```
+    pub fn query_with_params(&self, sql: &str, params: &[Value]) -> Result<Rows> {
+        process_query_with_params(sql, params, &self.db).map(Rows::new)
+    }
+
+    /// Registers a Rust closure as a scalar function callable from SQL expressions.
+    /// `n_args` is the number of arguments the function takes, or -1 for any number of them.
+    /// Registering a function under an existing name replaces it.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use sqlrite::Value;
+    ///
+    /// let mut conn = sqlrite::Connection::open_in_memory();
+    /// conn.create_scalar_function("slugify", 1, |args| match &args[0] {
+    ///     Value::Text(s) => Ok(Value::Text(s.to_lowercase().replace(' ', "-"))),
+    ///     other => Ok(other.clone()),
+    /// });
+    /// ```
+    pub fn create_scalar_function<F>(&mut self, name: &str, n_args: i32, func: F)
+    where
+        F: Fn(&[Value]) -> Result<Value> + Send + Sync + 'static,
+    {
+        self.db.functions.register(name, n_args, false, func);
+    }
+
+    /// Same as `create_scalar_function`, but flags the function as deterministic:
+    /// it always returns the same result for the same arguments.
+    pub fn create_deterministic_function<F>(&mut self, name: &str, n_args: i32, func: F)
+    where
+        F: Fn(&[Value]) -> Result<Value> + Send + Sync + 'static,
+    {
+        self.db.functions.register(name, n_args, true, func);
+    }
+
+    /// Registers a collating sequence under `name`, so it can be referenced from COLLATE
+    /// clauses in column definitions and ORDER BY terms. `cmp` compares two text values.
+    /// Registering a collation under an existing name replaces it.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let mut conn = sqlrite::Connection::open_in_memory();
+    /// conn.create_collation("unicode_ci", |a, b| a.to_lowercase().cmp(&b.to_lowercase()));
+    /// conn.execute("CREATE TABLE users (id INTEGER PRIMARY KEY, name TEXT COLLATE unicode_ci);")
+    ///     .unwrap();
+    /// ```
+    pub fn create_collation<F>(&mut self, name: &str, cmp: F)
+    where
+        F: Fn(&str, &str) -> Ordering + Send + Sync + 'static,
+    {
+        self.db.collations.register(name, cmp);
+    }
+
+    /// Registers a virtual table module under `name`. Tables backed by it are created with
+    /// `CREATE VIRTUAL TABLE table_name USING name(args...)` and queried with SELECT.
+    /// Registering a module under an existing name replaces it.
+    pub fn create_module<M>(&mut self, name: &str, module: M)
+    where
+        M: VirtualTableModule + 'static,
+    {
+        self.db.vtabs.register_module(name, Arc::new(module));
+    }
+
+    /// Loads the extension in the dynamic library at `path` into this connection, running its
+    /// entry point, `entry_point` or `sqlrite_extension_init` by default.
+    /// See the `extension` module for the interface extensions are written against.
+    ///
+    /// # Safety
+    ///
+    /// Loading a library runs arbitrary code from it, which must be a trustworthy SQLRite
+    /// extension built against this version of the extension API.
+    #[cfg(feature = "extensions")]
+    pub unsafe fn load_extension(&mut self, path: &str, entry_point: Option<&str>) -> Result<()> {
+        crate::extension::load_extension(&mut self.db, path, entry_point)
+    }
+
+    /// Adds a bloom filter to the index of the UNIQUE column `column` of `table_name`, or
+    /// removes it when `false_positive_rate` is `None`. With a filter, inserting a value
+    /// that isn't in the column yet doesn't look it up in the index. A false positive rate
+    /// of `bloom::DEFAULT_FALSE_POSITIVE_RATE` suits most tables.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let mut conn = sqlrite::Connection::open_in_memory();
+    /// conn.execute("CREATE TABLE users (id INTEGER PRIMARY KEY, email TEXT UNIQUE);").unwrap();
+    /// conn.set_bloom_filter("users", "email", Some(0.01)).unwrap();
+    /// ```
+    pub fn set_bloom_filter(
+        &mut self,
+        table_name: &str,
+        column: &str,
+        false_positive_rate: Option<f64>,
+    ) -> Result<()> {
+        let table_name = self.db.resolve_table_name(table_name)?;
+        self.db
+            .get_table_mut(table_name)?
+            .set_bloom_filter(column, false_positive_rate)
+    }
+
+    /// Turns dictionary encoding of the TEXT column `column` of `table_name` on or off. An
+    /// encoded column stores each distinct value once and a small code per row, for
+    /// columns with few distinct values like statuses or country codes. Queries read the
+    /// values as before.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let mut conn = sqlrite::Connection::open_in_memory();
+    /// conn.execute("CREATE TABLE orders (id INTEGER PRIMARY KEY, status TEXT);").unwrap();
+    /// conn.set_dictionary_encoding("orders", "status", true).unwrap();
+    /// ```
+    pub fn set_dictionary_encoding(
+        &mut self,
+        table_name: &str,
+        column: &str,
+        enabled: bool,
+    ) -> Result<()> {
+        let table_name = self.db.resolve_table_name(table_name)?;
+        self.db
+            .get_table_mut(table_name)?
+            .set_dictionary_encoding(column, enabled)
+    }
+
+    /// Sets a limit on the size of the statements run through this connection, returning its
+    /// previous value. A limit can't be raised past its maximum, the value it starts at, and
+    /// a negative `value` only reads it. Statements past a limit fail before they run.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use sqlrite::Limit;
+    ///
+    /// let mut conn = sqlrite::Connection::open_in_memory();
+    /// conn.set_limit(Limit::SqlLength, 100);
+    /// assert_eq!(conn.set_limit(Limit::SqlLength, -1), 100);
+    /// assert!(conn.execute(&format!("SELECT '{}';", "x".repeat(100))).is_err());
+    /// ```
+    pub fn set_limit(&mut self, limit: Limit, value: i64) -> usize {
+        self.db.limits.set(limit, value)
+    }
+
+    /// Registers `handler` to be called every `num_steps` steps of the statements run through
+    /// this connection, a step being a row read, inserted or deleted. Returning true from the
+    /// handler interrupts the statement, which fails with `SQLRiteError::Interrupted`
+    /// without changing anything. A `num_steps` of 0 or no handler removes the handler.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::time::{Duration, Instant};
+    ///
+    /// let mut conn = sqlrite::Connection::open_in_memory();
+    /// let deadline = Instant::now() + Duration::from_secs(5);
+    /// conn.progress_handler(1000, Some(move || Instant::now() > deadline));
+    /// conn.progress_handler(0, None::<fn() -> bool>);
+    /// ```
+    pub fn progress_handler<F>(&mut self, num_steps: u64, handler: Option<F>)
+    where
+        F: FnMut() -> bool + Send + 'static,
+    {
+        let handler = handler.map(|handler| Box::new(handler) as Box<ProgressFn>);
+        self.db.progress.set(num_steps, handler);
+    }
+
+    /// Keeps the results of up to `capacity` SELECT statements in memory, so that running
+    /// one of them again with the same parameters doesn't execute it, until a statement
+    /// changes one of the tables it reads. A `capacity` of 0 turns the cache off, as it is
+    /// when the connection is opened. Same as `PRAGMA result_cache = capacity`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let mut conn = sqlrite::Connection::open_in_memory();
+    /// conn.execute("CREATE TABLE users (id INTEGER PRIMARY KEY, name TEXT);").unwrap();
+    /// conn.set_result_cache(100);
+    /// conn.query("SELECT name FROM users;").unwrap();
+    /// conn.query("select name from users").unwrap();
+    /// assert_eq!(conn.result_cache_stats().hits, 1);
+    /// ```
+    pub fn set_result_cache(&mut self, capacity: usize) {
+        self.db.result_cache.set_capacity(capacity);
+    }
+
+    /// How the result cache was used since it was given its capacity.
+    pub fn result_cache_stats(&self) -> ResultCacheStats {
+        self.db.result_cache.stats()
+    }
+
+    /// Writes the table or the result of the SELECT statement `source` to `writer` as CSV,
+    /// returning the number of rows written. A table is streamed a row at a time.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use sqlrite::export::csv::CsvOptions;
+    ///
+    /// let mut conn = sqlrite::Connection::open_in_memory();
+    /// conn.execute("CREATE TABLE users (id INTEGER PRIMARY KEY, name TEXT);").unwrap();
+    /// conn.execute("INSERT INTO users (name) VALUES ('Ada, Countess');").unwrap();
+    ///
+    /// let mut csv = vec![];
+    /// conn.export_csv("users", &mut csv, CsvOptions::default()).unwrap();
+    /// assert_eq!(csv, b"id,name\r\n1,\"Ada, Countess\"\r\n");
+    /// ```
+    pub fn export_csv<W: Write>(
+        &self,
+        source: &str,
+        writer: W,
+        options: CsvOptions,
+    ) -> Result<usize> {
+        export(&self.db, source, &mut CsvWriter::new(writer, options))
+    }
+
+    /// Writes the table or the result of the SELECT statement `source` to `writer` as JSON
+    /// lines, one object per row, returning the number of rows written.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let mut conn = sqlrite::Connection::open_in_memory();
+    /// conn.execute("CREATE TABLE users (id INTEGER PRIMARY KEY, name TEXT);").unwrap();
+    /// conn.execute("INSERT INTO users (name) VALUES ('Ada');").unwrap();
+    ///
+    /// let mut json = vec![];
+    /// conn.export_json("users", &mut json).unwrap();
+    /// assert_eq!(json, b"{\"id\":1,\"name\":\"Ada\"}\n");
+    /// ```
+    pub fn export_json<W: Write>(&self, source: &str, writer: W) -> Result<usize> {
+        export(&self.db, source, &mut JsonLinesWriter::new(writer))
+    }
+
+    /// Runs the SELECT statement `sql` and returns its result as an Arrow record batch, to be
+    /// handed to DataFusion, polars or Arrow Flight.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let mut conn = sqlrite::Connection::open_in_memory();
+    /// conn.execute("CREATE TABLE users (id INTEGER PRIMARY KEY, name TEXT);").unwrap();
+    /// conn.execute("INSERT INTO users (name) VALUES ('Ada'), ('Grace');").unwrap();
+    ///
+    /// let batch = conn.query_arrow("SELECT name FROM users;").unwrap();
+    /// assert_eq!(batch.num_rows(), 2);
+    /// ```
+    #[cfg(feature = "arrow")]
+    pub fn query_arrow(&self, sql: &str) -> Result<RecordBatch> {
+        let result = process_query(sql, &self.db)?;
+        record_batch(&result.columns, &result.rows)
+    }
+
+    /// Writes the table or the result of the SELECT statement `source` to `writer` in the
+    /// Arrow IPC file format, returning the number of rows written.
+    #[cfg(feature = "arrow")]
+    pub fn export_arrow<W: Write>(&self, source: &str, writer: W) -> Result<usize> {
+        export(&self.db, source, &mut ArrowWriter::new(writer))
+    }
+
+    /// Writes each of `sources`, names of tables or SELECT statements, to a worksheet of the
+    /// spreadsheet `path`, returning the number of rows written.
+    #[cfg(feature = "xlsx")]
+    pub fn export_xlsx<P: AsRef<Path>>(&self, sources: &[&str], path: P) -> Result<usize> {
+        let mut workbook = XlsxWorkbook::new();
+        let rows = export_workbook(&self.db, sources, &mut workbook)?;
+        workbook.save(path)?;
+        Ok(rows)
+    }
+
+    /// Imports the rows of `json`, a JSON array of objects or JSON lines, into the table
+    /// `table_name`, creating it from the types of the values if it doesn't exist. Rows that
+    /// can't be stored are skipped and listed in the returned report.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let mut conn = sqlrite::Connection::open_in_memory();
+    /// conn.execute("CREATE TABLE people (name TEXT, age INTEGER);").unwrap();
+    /// let json = r#"[{"name": "Ada", "age": 36}, {"name": "Grace", "age": "unknown"}]"#;
+    /// let report = conn.import_json("people", json).unwrap();
+    /// assert_eq!(report.inserted, 1);
+    /// assert_eq!(report.skipped[0].0, 2);
+    /// ```
+    pub fn import_json(&mut self, table_name: &str, json: &str) -> Result<ImportReport> {
+        import_json(&mut self.db, json, table_name)
+    }
+
+    /// Imports `dump`, the SQL written by mysqldump or pg_dump, creating its tables with
+    /// their types translated and inserting their rows. The statements SQLRite has no
+    /// equivalent for are left out, and the rows that can't be stored skipped and listed in
+    /// the returned report by line.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let mut conn = sqlrite::Connection::open_in_memory();
+    /// let dump = "CREATE TABLE `tags` (`id` int NOT NULL, `name` varchar(32), PRIMARY KEY (`id`));
+    /// INSERT INTO `tags` VALUES (1,'rust'),(2,'sql');";
+    /// let report = conn.import_dump(dump).unwrap();
+    /// assert_eq!(report.created, vec!["tags"]);
+    /// assert_eq!(report.inserted, 2);
+    /// ```
+    pub fn import_dump(&mut self, dump: &str) -> Result<ImportReport> {
+        import_dump(&mut self.db, dump)
+    }
+
+    /// Inserts `count` generated rows into the table `table_name`, the columns of
+    /// `template` getting values of their generator, like `name=fake_name,score=real(0,100)`,
+    /// or every column a generator picked after its name and type when there is none. The
+    /// same `seed` always generates the same rows.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let mut conn = sqlrite::Connection::open_in_memory();
+    /// conn.execute("CREATE TABLE users (id INTEGER PRIMARY KEY, name TEXT, email TEXT UNIQUE);")
+    ///     .unwrap();
+    /// let report = conn
+    ///     .seed("users", 1000, Some("name=fake_name,email=fake_email"), 42)
+    ///     .unwrap();
+    /// assert_eq!(report.inserted, 1000);
+    /// ```
+    pub fn seed(
+        &mut self,
+        table_name: &str,
+        count: usize,
+        template: Option<&str>,
+        seed: u64,
+    ) -> Result<ImportReport> {
+        seed_table(&mut self.db, table_name, count, template, seed)
+    }
+
+    /// Returns the rowid of the last row inserted through this connection, 0 if there is none.
+    pub fn last_insert_rowid(&self) -> i64 {
+        self.db.counters.last_insert_rowid
+    }
+
+    /// Returns the number of rows inserted, updated or deleted by the last statement doing so.
+    pub fn changes(&self) -> i64 {
+        self.db.counters.changes
+    }
+
+    /// Returns the number of rows inserted, updated or deleted since the connection was opened.
+    pub fn total_changes(&self) -> i64 {
+        self.db.counters.total_changes
+    }
+
+    /// Starts recording the changes made to the rows of tables through this connection, until
+    /// `end_session` returns them as a changeset. Starting a session again drops the
+    /// changes recorded so far.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let mut laptop = sqlrite::Connection::open_in_memory();
+    /// let mut phone = sqlrite::Connection::open_in_memory();
+    /// for conn in [&mut laptop, &mut phone] {
+    ///     conn.execute("CREATE TABLE notes (id INTEGER PRIMARY KEY, body TEXT);").unwrap();
+    /// }
+    ///
+    /// laptop.start_session();
+    /// laptop.execute("INSERT INTO notes (body) VALUES ('buy milk');").unwrap();
+    /// let changeset = laptop.end_session();
+    ///
+    /// phone.apply_changeset(&changeset).unwrap();
+    /// assert_eq!(phone.query("SELECT body FROM notes;").unwrap().count(), 1);
+    /// ```
+    pub fn start_session(&mut self) {
+        self.db.session = Some(Session::default());
+    }
+
+    /// Stops recording changes, returning the changes made since `start_session`,
+    /// consolidated per row. Empty if no session was started.
+    pub fn end_session(&mut self) -> Changeset {
+        self.db
+            .session
+            .take()
+            .map(|session| session.changeset)
+            .unwrap_or_default()
+    }
+
+    /// Applies a changeset recorded on another database with the same tables. Either all of
+    /// its changes are applied or, if a row was changed differently here, none of them.
+    pub fn apply_changeset(&mut self, changeset: &Changeset) -> Result<()> {
+        changeset.apply(&mut self.db)
+    }
+
+    /// Returns an immutable reference to the `Database` behind this connection.
+    pub fn database(&self) -> &Database {
+        &self.db
+    }
+
+    /// Returns a mutable reference to the `Database` behind this connection.
+    pub fn database_mut(&mut self) -> &mut Database {
+        &mut self.db
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sql::db::table::DataType;
+
+    #[test]
+    fn connection_execute_test() {
+        let mut conn = Connection::open_in_memory();
+
+        let response = conn
+            .execute("CREATE TABLE users (id INTEGER PRIMARY KEY, name TEXT);")
+            .unwrap();
+        assert_eq!(response.message, "CREATE TABLE Statement executed.");
+
+        let response = conn
+            .execute("INSERT INTO users (name) VALUES ('josh');")
+            .unwrap();
+        assert_eq!(response.message, "INSERT Statement executed.");
+
+        let table = conn.database().get_table("users".to_string()).unwrap();
+        assert_eq!(table.last_rowid, 1);
+        assert_eq!(conn.last_insert_rowid(), 1);
+        assert_eq!(conn.changes(), 1);
+        assert_eq!(conn.total_changes(), 1);
+    }
+
+    #[test]
+    fn connection_open_test() {
+        let path =
+            std::env::temp_dir().join(format!("sqlrite-connection-{}.db", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        let mut conn = Connection::open(&path).unwrap();
+        conn.execute("CREATE TABLE users (id INTEGER PRIMARY KEY, name TEXT);")
+            .unwrap();
+        conn.execute("INSERT INTO users (name) VALUES ('josh');")
+            .unwrap();
+        conn.execute("BEGIN;").unwrap();
+        conn.execute("INSERT INTO users (name) VALUES ('mary');")
+            .unwrap();
+        assert!(conn.save().is_err());
+        assert_eq!(conn.close(), Ok(true));
+
+        let conn = Connection::open(&path).unwrap();
+        let mut rows = conn.query("SELECT name FROM users;").unwrap();
+        assert_eq!(
+            rows.next().unwrap().get::<String>(0),
+            Ok("josh".to_string())
+        );
+        assert!(rows.next().is_none());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn connection_query_test() {
+        let mut conn = Connection::open_in_memory();
+        conn.execute("CREATE TABLE users (id INTEGER PRIMARY KEY, name TEXT);")
+            .unwrap();
+        conn.execute("INSERT INTO users (name) VALUES ('josh');")
+            .unwrap();
+
+        let mut rows = conn.query("SELECT id, name FROM users;").unwrap();
+        assert_eq!(rows.column_names(), vec!["id", "name"]);
+
+        let row = rows.next().unwrap();
+        assert_eq!(row.get::<i64>(0), Ok(1));
+        assert_eq!(row.get_by_name::<String>("name"), Ok("josh".to_string()));
+        assert!(rows.next().is_none());
+
+        assert!(conn.query("DELETE FROM users;").is_err());
+    }
+
+    #[test]
+    fn connection_scalar_function_test() {
+        let mut conn = Connection::open_in_memory();
+        conn.create_scalar_function("slugify", 1, |args| match &args[0] {
+            Value::Text(s) => Ok(Value::Text(s.to_lowercase().replace(' ', "-"))),
+            other => Ok(other.clone()),
+        });
+        conn.create_deterministic_function("one", 0, |_| Ok(Value::Integer(1)));
+        conn.execute("CREATE TABLE posts (id INTEGER PRIMARY KEY, title TEXT);")
+            .unwrap();
+        conn.execute("INSERT INTO posts (title) VALUES ('Hello World');")
+            .unwrap();
+
+        let mut rows = conn
+            .query("SELECT SLUGIFY(title) AS slug, one() FROM posts;")
+            .unwrap();
+        assert_eq!(rows.column_names(), vec!["slug", "one()"]);
+        let row = rows.next().unwrap();
+        assert_eq!(row.get::<String>(0), Ok("hello-world".to_string()));
+        assert_eq!(row.get::<i64>(1), Ok(1));
+
+        let functions = &conn.database().functions;
+        assert!(!functions.get("slugify").unwrap().deterministic);
+        assert!(functions.get("one").unwrap().deterministic);
+
+        assert!(conn.query("SELECT slugify(title, id) FROM posts;").is_err());
+        assert!(conn.query("SELECT unknown(title) FROM posts;").is_err());
+    }
+
+    #[test]
+    fn connection_collation_test() {
+        let mut conn = Connection::open_in_memory();
+        conn.create_collation("unicode_ci", |a, b| a.to_lowercase().cmp(&b.to_lowercase()));
+        conn.execute(
+            "CREATE TABLE users (id INTEGER PRIMARY KEY, name TEXT COLLATE unicode_ci UNIQUE);",
+        )
+        .unwrap();
+        conn.execute("INSERT INTO users (name) VALUES ('Émile');")
+            .unwrap();
+        conn.execute("INSERT INTO users (name) VALUES ('bob');")
+            .unwrap();
+        conn.execute("INSERT INTO users (name) VALUES ('Alice');")
+            .unwrap();
+
+        // The column collation drives ORDER BY and the UNIQUE index
+        let names = conn
+            .query("SELECT name FROM users ORDER BY name;")
+            .unwrap()
+            .map(|row| row.get::<String>(0).unwrap())
+            .collect::<Vec<String>>();
+        assert_eq!(names, vec!["Alice", "bob", "Émile"]);
+        assert!(conn
+            .execute("INSERT INTO users (name) VALUES ('émile');")
+            .is_err());
+
+        // An explicit COLLATE overrides the column collation
+        let names = conn
+            .query("SELECT name FROM users ORDER BY name COLLATE binary DESC;")
+            .unwrap()
+            .map(|row| row.get::<String>(0).unwrap())
+            .collect::<Vec<String>>();
+        assert_eq!(names, vec!["Émile", "bob", "Alice"]);
+
+        assert!(conn
+            .query("SELECT name FROM users ORDER BY name COLLATE unknown;")
+            .is_err());
+        assert!(conn
+            .execute("CREATE TABLE tags (name TEXT COLLATE unknown);")
+            .is_err());
+    }
+
+    #[test]
+    fn connection_virtual_table_test() {
+        let mut conn = Connection::open_in_memory();
+        conn.create_module("people", crate::sql::vtab::tests::PeopleModule);
+        let response = conn
+            .execute("CREATE VIRTUAL TABLE staff USING people(mary, josh);")
+            .unwrap();
+        assert_eq!(response.message, "CREATE VIRTUAL TABLE Statement executed.");
+
+        let mut rows = conn
+            .query("SELECT name, age FROM staff ORDER BY name;")
+            .unwrap();
+        assert_eq!(
+            rows.column_types(),
+            vec![Some(&DataType::Text), Some(&DataType::Integer)]
+        );
+        let row = rows.next().unwrap();
+        assert_eq!(row.get::<String>(0), Ok("josh".to_string()));
+        assert_eq!(row.get::<i64>(1), Ok(21));
+        assert_eq!(rows.count(), 1);
+
+        assert!(conn
+            .execute("INSERT INTO staff (name) VALUES ('bob');")
+            .is_err());
+        assert!(conn.execute("CREATE TABLE staff (id INTEGER);").is_err());
+        assert!(conn
+            .execute("CREATE VIRTUAL TABLE other USING unknown(a);")
+            .is_err());
+    }
+
+    #[test]
+    fn connection_csv_virtual_table_test() {
+        let mut conn = Connection::open_in_memory();
+        conn.execute(
+            "CREATE VIRTUAL TABLE logs USING csv(data='level,message\ninfo,started\nerror,\"failed, retrying\"', header=true);",
+        )
+        .unwrap();
+
+        let rows = conn
+            .query("SELECT message FROM logs ORDER BY level;")
+            .unwrap()
+            .map(|row| row.get::<String>(0).unwrap())
+            .collect::<Vec<String>>();
+        assert_eq!(rows, vec!["failed, retrying", "started"]);
+        assert!(conn
+            .execute("INSERT INTO logs (level) VALUES ('debug');")
+            .is_err());
+    }
+
+    #[test]
+    fn connection_json_test() {
+        let mut conn = Connection::open_in_memory();
+        conn.execute("CREATE TABLE events (id INTEGER PRIMARY KEY, payload TEXT);")
+            .unwrap();
+        conn.execute(
+            r#"INSERT INTO events (payload) VALUES ('{"user": "josh", "tags": ["a", "b"]}');"#,
+        )
+        .unwrap();
+
+        let mut rows = conn
+            .query("SELECT json_extract(payload, '$.user'), json_array_length(payload, '$.tags'), json_object('id', id) FROM events;")
+            .unwrap();
+        let row = rows.next().unwrap();
+        assert_eq!(row.get::<String>(0), Ok("josh".to_string()));
+        assert_eq!(row.get::<i64>(1), Ok(2));
+        assert_eq!(row.get::<String>(2), Ok(r#"{"id":1}"#.to_string()));
+
+        let tags = conn
+            .query(r#"SELECT value FROM json_each('{"tags": ["a", "b"]}', '$.tags') ORDER BY key DESC;"#)
+            .unwrap()
+            .map(|row| row.get::<String>(0).unwrap())
+            .collect::<Vec<String>>();
+        assert_eq!(tags, vec!["b", "a"]);
+    }
+
+    #[test]
+    fn connection_progress_handler_test() {
+        use crate::error::SQLRiteError;
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use std::sync::Arc;
+
+        let mut conn = Connection::open_in_memory();
+        conn.execute("CREATE TABLE users (id INTEGER PRIMARY KEY, name TEXT);")
+            .unwrap();
+        conn.execute("INSERT INTO users (name) VALUES ('a'), ('b'), ('c'), ('d');")
+            .unwrap();
+
+        let calls = Arc::new(AtomicUsize::new(0));
+        let counted = Arc::clone(&calls);
+        conn.progress_handler(
+            2,
+            Some(move || {
+                counted.fetch_add(1, Ordering::Relaxed);
+                false
+            }),
+        );
+        assert_eq!(conn.query("SELECT name FROM users;").unwrap().count(), 4);
+        assert_eq!(calls.load(Ordering::Relaxed), 2);
+
+        // An interrupted statement changes nothing
+        conn.progress_handler(3, Some(|| true));
+        let result = conn.execute("INSERT INTO users (name) VALUES ('e'), ('f'), ('g');");
+        assert_eq!(result.unwrap_err(), SQLRiteError::Interrupted);
+        assert!(conn.query("SELECT name FROM users;").is_err());
+        conn.progress_handler(0, None::<fn() -> bool>);
+        assert_eq!(conn.query("SELECT name FROM users;").unwrap().count(), 4);
+        assert_eq!(conn.last_insert_rowid(), 4);
+    }
+
+    #[test]
+    fn connection_result_cache_test() {
+        let mut conn = Connection::open_in_memory();
+        conn.execute("CREATE TABLE users (id INTEGER PRIMARY KEY, name TEXT);")
+            .unwrap();
+        conn.execute("CREATE TABLE posts (id INTEGER PRIMARY KEY, title TEXT);")
+            .unwrap();
+        conn.execute("INSERT INTO users (name) VALUES ('a'), ('b');")
+            .unwrap();
+        conn.set_result_cache(2);
+        let names = |conn: &Connection, sql: &str, id: i64| {
+            conn.query_with_params(sql, &[Value::Integer(id)])
+                .unwrap()
+                .map(|row| row.get::<String>(0).unwrap())
+                .collect::<Vec<String>>()
+        };
+
+        let sql = "SELECT name FROM users WHERE id >= ?;";
+        assert_eq!(names(&conn, sql, 1), vec!["a", "b"]);
+        assert_eq!(
+            names(&conn, "select name  from users where id >= ?", 1),
+            vec!["a", "b"]
+        );
+        assert_eq!(names(&conn, sql, 2), vec!["b"]);
+        assert_eq!(
+            conn.result_cache_stats(),
+            ResultCacheStats {
+                hits: 1,
+                misses: 2,
+                entries: 2
+            }
+        );
+
+        // Changing another table keeps the results, changing the table forgets them
+        conn.execute("INSERT INTO posts (title) VALUES ('x');")
+            .unwrap();
+        assert_eq!(names(&conn, sql, 1), vec!["a", "b"]);
+        assert_eq!(conn.result_cache_stats().hits, 2);
+        conn.execute("INSERT INTO users (name) VALUES ('c');")
+            .unwrap();
+        assert_eq!(conn.result_cache_stats().entries, 0);
+        assert_eq!(names(&conn, sql, 1), vec!["a", "b", "c"]);
+        assert_eq!(conn.result_cache_stats().hits, 2);
+
+        // A failed statement leaves the rows unchanged, and its results are computed again
+        assert!(conn
+            .execute("INSERT INTO users (id, name) VALUES (1, 'd');")
+            .is_err());
+        assert_eq!(names(&conn, sql, 1), vec!["a", "b", "c"]);
+
+        // Statements that don't only read rows aren't cached
+        conn.query("SELECT random() FROM users;").unwrap();
+        conn.query("SELECT name FROM sqlite_master;").unwrap();
+        let stats = conn.result_cache_stats();
+        conn.query("SELECT random() FROM users;").unwrap();
+        assert_eq!(conn.result_cache_stats(), stats);
+
+        conn.execute("PRAGMA result_cache = 0;").unwrap();
+        assert_eq!(conn.result_cache_stats(), ResultCacheStats::default());
+        names(&conn, sql, 1);
+        assert_eq!(conn.result_cache_stats(), ResultCacheStats::default());
+    }
+
+    #[test]
+    fn connection_execute_error_test() {
+        let mut conn = Connection::open_in_memory();
+
+        let result = conn.execute("INSERT INTO users (name) VALUES ('josh');");
+        assert!(result.is_err());
+    }
+}
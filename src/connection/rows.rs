@@ -0,0 +1,155 @@
+use std::sync::Arc;
+
+use crate::error::{Result, SQLRiteError};
+use crate::sql::db::table::DataType;
+use crate::sql::executor::{ResultColumn, ResultSet};
+use crate::sql::value::{FromValue, Value};
+
+/// Cursor over the rows returned by `Connection::query`.
+/// Besides iterating over each `Row`, it exposes the metadata of the result columns.
+#[derive(Debug)]
+pub struct Rows {
+    columns: Arc<Vec<ResultColumn>>,
+    rows: std::vec::IntoIter<Vec<Value>>,
+}
+
+impl Rows {
+    pub fn new(result: ResultSet) -> Self {
+        Rows {
+            columns: Arc::new(result.columns),
+            rows: result.rows.into_iter(),
+        }
+    }
+
+    /// Number of columns in each row of the result.
+    pub fn column_count(&self) -> usize {
+        self.columns.len()
+    }
+
+    /// Names of the result columns, the `AS` alias when one was given.
+    pub fn column_names(&self) -> Vec<&str> {
+        self.columns.iter().map(|col| col.name.as_str()).collect()
+    }
+
+    /// Declared types of the result columns.
+    /// `None` for columns computed from an expression rather than read from a table column.
+    pub fn column_types(&self) -> Vec<Option<&DataType>> {
+        self.columns
+            .iter()
+            .map(|col| col.datatype.as_ref())
+            .collect()
+    }
+}
+
+impl Iterator for Rows {
+    type Item = Row;
+
+    fn next(&mut self) -> Option<Row> {
+        self.rows.next().map(|values| Row {
+            columns: Arc::clone(&self.columns),
+            values,
+        })
+    }
+}
+
+/// A single row of a query result.
+#[derive(Debug, PartialEq)]
+pub struct Row {
+    columns: Arc<Vec<ResultColumn>>,
+    values: Vec<Value>,
+}
+
+impl Row {
+    /// Returns the value at column `index` converted to `T`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let mut conn = sqlrite::Connection::open_in_memory();
+    /// conn.execute("CREATE TABLE users (id INTEGER PRIMARY KEY, name TEXT);").unwrap();
+    /// conn.execute("INSERT INTO users (name) VALUES ('josh');").unwrap();
+    ///
+    /// for row in conn.query("SELECT id, name FROM users;").unwrap() {
+    ///     let id: i64 = row.get(0).unwrap();
+    ///     let name: String = row.get_by_name("name").unwrap();
+    ///     println!("{} {}", id, name);
+    /// }
+    /// ```
+    pub fn get<T: FromValue>(&self, index: usize) -> Result<T> {
+        match self.values.get(index) {
+            Some(value) => T::from_value(value),
+            None => Err(SQLRiteError::General(format!(
+                "Column index {} out of range, row has {} columns",
+                index,
+                self.values.len()
+            ))),
+        }
+    }
+
+    /// Returns the value of the column named `name` converted to `T`.
+    pub fn get_by_name<T: FromValue>(&self, name: &str) -> Result<T> {
+        match self.columns.iter().position(|col| col.name == name) {
+            Some(index) => self.get(index),
+            None => Err(SQLRiteError::General(format!("No such column: {}", name))),
+        }
+    }
+
+    /// Number of columns in this row.
+    pub fn column_count(&self) -> usize {
+        self.values.len()
+    }
+
+    /// Raw values of this row.
+    pub fn values(&self) -> &[Value] {
+        &self.values
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn result_set() -> ResultSet {
+        ResultSet {
+            columns: vec![
+                ResultColumn {
+                    name: "id".to_string(),
+                    datatype: Some(DataType::Integer),
+                },
+                ResultColumn {
+                    name: "name".to_string(),
+                    datatype: None,
+                },
+            ],
+            rows: vec![
+                vec![Value::Integer(1), Value::Text("josh".to_string())],
+                vec![Value::Integer(2), Value::Null],
+            ],
+        }
+    }
+
+    #[test]
+    fn rows_metadata_test() {
+        let rows = Rows::new(result_set());
+        assert_eq!(rows.column_count(), 2);
+        assert_eq!(rows.column_names(), vec!["id", "name"]);
+        assert_eq!(rows.column_types(), vec![Some(&DataType::Integer), None]);
+    }
+
+    #[test]
+    fn row_get_test() {
+        let mut rows = Rows::new(result_set());
+
+        let row = rows.next().unwrap();
+        assert_eq!(row.column_count(), 2);
+        assert_eq!(row.get::<i64>(0), Ok(1));
+        assert_eq!(row.get_by_name::<String>("name"), Ok("josh".to_string()));
+        assert!(row.get::<i64>(2).is_err());
+        assert!(row.get_by_name::<i64>("email").is_err());
+
+        let row = rows.next().unwrap();
+        assert_eq!(row.get_by_name::<Option<String>>("name"), Ok(None));
+
+        assert!(rows.next().is_none());
+    }
+}
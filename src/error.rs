@@ -22,6 +22,15 @@ pub enum SQLRiteError {
     UnknownCommand(String),
     #[error("SQL error: {0:?}")]
     SqlError(#[from] ParserError),
+    /// An error returned by the server a remote session is connected to
+    #[error("Server error: {0}")]
+    Server(String),
+    /// A statement needed more memory than the limit set with `PRAGMA hard_heap_limit`
+    #[error("Out of memory: {0}")]
+    OutOfMemory(String),
+    /// The progress handler of the connection interrupted the statement
+    #[error("interrupted")]
+    Interrupted,
 }
 
 /// Returns SQLRiteError::General error from String
@@ -91,4 +100,29 @@ mod tests {
         let result = format!("{}", input);
         assert_eq!(result, expected);
     }
+
+    #[test]
+    fn sqlrite_display_server_test() {
+        let error_string = String::from("General error: no such table.");
+        let input = SQLRiteError::Server(error_string.clone());
+
+        let expected = format!("Server error: {}", error_string);
+        let result = format!("{}", input);
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn sqlrite_display_out_of_memory_test() {
+        let error_string = String::from("hard heap limit reached.");
+        let input = SQLRiteError::OutOfMemory(error_string.clone());
+
+        let expected = format!("Out of memory: {}", error_string);
+        let result = format!("{}", input);
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn sqlrite_display_interrupted_test() {
+        assert_eq!(format!("{}", SQLRiteError::Interrupted), "interrupted");
+    }
 }
@@ -0,0 +1,312 @@
+use std::io::Write;
+use std::sync::Arc;
+
+use arrow_array::builder::{
+    BinaryBuilder, BooleanBuilder, Float64Builder, Int64Builder, StringBuilder,
+};
+use arrow_array::{ArrayRef, RecordBatch};
+use arrow_ipc::writer::FileWriter;
+use arrow_schema::{ArrowError, DataType as ArrowType, Field, Schema, SchemaRef};
+
+use crate::error::{Result, SQLRiteError};
+use crate::export::RowWriter;
+use crate::sql::db::table::DataType;
+use crate::sql::executor::ResultColumn;
+use crate::sql::value::Value;
+
+/// Rows in each record batch an export writes, at most
+pub const BATCH_ROWS: usize = 1024;
+
+/// Converts rows to an Arrow record batch. Each column has the Arrow type of its declared
+/// type, or of its first value that isn't NULL for computed columns, and is nullable.
+pub fn record_batch(columns: &[ResultColumn], rows: &[Vec<Value>]) -> Result<RecordBatch> {
+    batch(&schema(columns, rows), rows)
+}
+
+/// The schema of the record batches holding `rows`: INTEGER columns are Int64, REAL ones
+/// Float64, TEXT ones Utf8, BOOL ones Boolean and blobs Binary. Columns of unknown type
+/// with only NULL values are Utf8.
+fn schema(columns: &[ResultColumn], rows: &[Vec<Value>]) -> SchemaRef {
+    let fields = columns
+        .iter()
+        .enumerate()
+        .map(|(i, column)| {
+            let datatype = match column.datatype {
+                Some(DataType::Integer) => Some(ArrowType::Int64),
+                Some(DataType::Real) => Some(ArrowType::Float64),
+                Some(DataType::Text) => Some(ArrowType::Utf8),
+                Some(DataType::Bool) => Some(ArrowType::Boolean),
+                _ => None,
+            };
+            let datatype = datatype
+                .or_else(|| rows.iter().find_map(|row| value_type(&row[i])))
+                .unwrap_or(ArrowType::Utf8);
+            Field::new(column.name.to_string(), datatype, true)
+        })
+        .collect::<Vec<Field>>();
+    Arc::new(Schema::new(fields))
+}
+
+fn value_type(value: &Value) -> Option<ArrowType> {
+    match value {
+        Value::Null => None,
+        Value::Integer(_) => Some(ArrowType::Int64),
+        Value::Real(_) => Some(ArrowType::Float64),
+        Value::Text(_) => Some(ArrowType::Utf8),
+        Value::Bool(_) => Some(ArrowType::Boolean),
+        Value::Blob(_) => Some(ArrowType::Binary),
+    }
+}
+
+fn batch(schema: &SchemaRef, rows: &[Vec<Value>]) -> Result<RecordBatch> {
+    let arrays = schema
+        .fields()
+        .iter()
+        .enumerate()
+        .map(|(i, field)| array(field, rows.iter().map(|row| &row[i])))
+        .collect::<Result<Vec<ArrayRef>>>()?;
+    RecordBatch::try_new(Arc::clone(schema), arrays).map_err(arrow_error)
+}
+
+/// Builds the array of the column `field` from its values. Integers are widened to Float64
+/// columns, and any value is written as text to Utf8 columns; other values of the wrong
+/// type fail the conversion.
+fn array<'a>(field: &Field, values: impl Iterator<Item = &'a Value>) -> Result<ArrayRef> {
+    let mismatch = |value: &Value| {
+        SQLRiteError::General(format!(
+            "cannot convert {} of column {} to {}",
+            value,
+            field.name(),
+            field.data_type()
+        ))
+    };
+    Ok(match field.data_type() {
+        ArrowType::Int64 => {
+            let mut builder = Int64Builder::new();
+            for value in values {
+                match value {
+                    Value::Null => builder.append_null(),
+                    Value::Integer(v) => builder.append_value(*v),
+                    value => return Err(mismatch(value)),
+                }
+            }
+            Arc::new(builder.finish())
+        }
+        ArrowType::Float64 => {
+            let mut builder = Float64Builder::new();
+            for value in values {
+                match value {
+                    Value::Null => builder.append_null(),
+                    Value::Integer(v) => builder.append_value(*v as f64),
+                    Value::Real(v) => builder.append_value(*v),
+                    value => return Err(mismatch(value)),
+                }
+            }
+            Arc::new(builder.finish())
+        }
+        ArrowType::Boolean => {
+            let mut builder = BooleanBuilder::new();
+            for value in values {
+                match value {
+                    Value::Null => builder.append_null(),
+                    Value::Bool(v) => builder.append_value(*v),
+                    value => return Err(mismatch(value)),
+                }
+            }
+            Arc::new(builder.finish())
+        }
+        ArrowType::Binary => {
+            let mut builder = BinaryBuilder::new();
+            for value in values {
+                match value {
+                    Value::Null => builder.append_null(),
+                    Value::Blob(v) => builder.append_value(v),
+                    value => return Err(mismatch(value)),
+                }
+            }
+            Arc::new(builder.finish())
+        }
+        _ => {
+            let mut builder = StringBuilder::new();
+            for value in values {
+                match value {
+                    Value::Null => builder.append_null(),
+                    Value::Text(v) => builder.append_value(v),
+                    value => builder.append_value(value.to_string()),
+                }
+            }
+            Arc::new(builder.finish())
+        }
+    })
+}
+
+fn arrow_error(err: ArrowError) -> SQLRiteError {
+    SQLRiteError::General(format!("Arrow error: {}", err))
+}
+
+/// Writes rows to the Arrow IPC file format, read by DataFusion, polars or pyarrow, in
+/// record batches of `BATCH_ROWS` rows. The schema is that of the first batch.
+pub struct ArrowWriter<W: Write> {
+    writer: Option<W>,
+    file: Option<FileWriter<W>>,
+    columns: Vec<ResultColumn>,
+    schema: Option<SchemaRef>,
+    rows: Vec<Vec<Value>>,
+}
+
+impl<W: Write> ArrowWriter<W> {
+    pub fn new(writer: W) -> Self {
+        ArrowWriter {
+            writer: Some(writer),
+            file: None,
+            columns: vec![],
+            schema: None,
+            rows: vec![],
+        }
+    }
+
+    /// Writes the rows buffered as a record batch, starting the file with the first one.
+    fn write_batch(&mut self) -> Result<()> {
+        let schema = match &self.schema {
+            Some(schema) => Arc::clone(schema),
+            None => {
+                let schema = schema(&self.columns, &self.rows);
+                self.schema = Some(Arc::clone(&schema));
+                schema
+            }
+        };
+        let file = match (&mut self.file, self.writer.take()) {
+            (Some(file), _) => file,
+            (None, Some(writer)) => self
+                .file
+                .insert(FileWriter::try_new(writer, &schema).map_err(arrow_error)?),
+            (None, None) => {
+                return Err(SQLRiteError::General(
+                    "the Arrow export is already finished".to_string(),
+                ))
+            }
+        };
+        if !self.rows.is_empty() {
+            file.write(&batch(&schema, &self.rows)?)
+                .map_err(arrow_error)?;
+            self.rows.clear();
+        }
+        Ok(())
+    }
+}
+
+impl<W: Write> RowWriter for ArrowWriter<W> {
+    fn header(&mut self, columns: &[ResultColumn]) -> Result<()> {
+        self.columns = columns.to_vec();
+        Ok(())
+    }
+
+    fn row(&mut self, values: &[Value]) -> Result<()> {
+        self.rows.push(values.to_vec());
+        if self.rows.len() >= BATCH_ROWS {
+            self.write_batch()?;
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        self.write_batch()?;
+        if let Some(file) = &mut self.file {
+            file.finish().map_err(arrow_error)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::export::export;
+    use crate::sql::db::database::Database;
+    use crate::sql::process_command;
+    use arrow_array::cast::AsArray;
+    use arrow_array::types::{Float64Type, Int64Type};
+    use arrow_ipc::reader::FileReader;
+    use std::io::Cursor;
+
+    #[test]
+    fn arrow_export_test() {
+        let mut db = Database::new("tempdb".to_string());
+        process_command(
+            "CREATE TABLE readings (id INTEGER PRIMARY KEY, sensor TEXT, value REAL, ok BOOL);",
+            &mut db,
+        )
+        .unwrap();
+        let values = (0..BATCH_ROWS + 10)
+            .map(|i| format!("('s{}', {}.5, {})", i % 3, i, i % 2 == 0))
+            .collect::<Vec<String>>()
+            .join(", ");
+        process_command(
+            &format!(
+                "INSERT INTO readings (sensor, value, ok) VALUES {};",
+                values
+            ),
+            &mut db,
+        )
+        .unwrap();
+
+        let mut output = vec![];
+        let rows = export(&db, "readings", &mut ArrowWriter::new(&mut output)).unwrap();
+        assert_eq!(rows, BATCH_ROWS + 10);
+        let reader = FileReader::try_new(Cursor::new(output), None).unwrap();
+        let fields = reader
+            .schema()
+            .fields()
+            .iter()
+            .map(|field| (field.name().to_string(), field.data_type().clone()))
+            .collect::<Vec<(String, ArrowType)>>();
+        assert_eq!(
+            fields,
+            vec![
+                ("id".to_string(), ArrowType::Int64),
+                ("sensor".to_string(), ArrowType::Utf8),
+                ("value".to_string(), ArrowType::Float64),
+                ("ok".to_string(), ArrowType::Boolean),
+            ]
+        );
+        let batches = reader.collect::<std::result::Result<Vec<RecordBatch>, _>>();
+        let batches = batches.unwrap();
+        assert_eq!(batches.len(), 2);
+        assert_eq!(batches[1].num_rows(), 10);
+        assert_eq!(
+            batches[1].column(0).as_primitive::<Int64Type>().value(0),
+            1025
+        );
+        assert_eq!(batches[1].column(1).as_string::<i32>().value(0), "s1");
+        assert_eq!(
+            batches[1].column(2).as_primitive::<Float64Type>().value(0),
+            1024.5
+        );
+
+        let columns = vec![
+            ResultColumn {
+                name: "n".to_string(),
+                datatype: None,
+            },
+            ResultColumn {
+                name: "data".to_string(),
+                datatype: None,
+            },
+        ];
+        let rows = vec![
+            vec![Value::Null, Value::Null],
+            vec![Value::Integer(2), Value::Blob(vec![1, 2])],
+        ];
+        let batch = record_batch(&columns, &rows).unwrap();
+        assert_eq!(batch.schema().field(0).data_type(), &ArrowType::Int64);
+        assert_eq!(batch.schema().field(1).data_type(), &ArrowType::Binary);
+        assert!(batch.column(0).is_null(0));
+        assert_eq!(batch.column(1).as_binary::<i32>().value(1), &[1, 2]);
+
+        let rows = vec![
+            vec![Value::Integer(1), Value::Null],
+            vec![Value::Text("x".to_string()), Value::Null],
+        ];
+        assert!(record_batch(&columns, &rows).is_err());
+    }
+}
@@ -0,0 +1,140 @@
+use std::io::Write;
+
+use crate::error::Result;
+use crate::export::{write_error, RowWriter};
+use crate::sql::executor::ResultColumn;
+use crate::sql::value::Value;
+
+/// How rows are written as CSV
+#[derive(Debug, Clone, PartialEq)]
+pub struct CsvOptions {
+    /// Character separating the fields of a record
+    pub delimiter: char,
+    /// Whether the first record holds the names of the columns
+    pub header: bool,
+}
+
+impl Default for CsvOptions {
+    fn default() -> Self {
+        CsvOptions {
+            delimiter: ',',
+            header: true,
+        }
+    }
+}
+
+/// Writes rows as CSV records following RFC 4180: records end with CRLF, and fields holding
+/// the delimiter, a double quote or a line break are quoted, their double quotes doubled.
+/// NULL is an empty field, and an empty text a quoted one, so that the two stay apart.
+pub struct CsvWriter<W: Write> {
+    writer: W,
+    options: CsvOptions,
+}
+
+impl<W: Write> CsvWriter<W> {
+    pub fn new(writer: W, options: CsvOptions) -> Self {
+        CsvWriter { writer, options }
+    }
+
+    fn record<'a>(&mut self, fields: impl Iterator<Item = Option<&'a str>>) -> Result<()> {
+        let mut line = String::new();
+        for (i, field) in fields.enumerate() {
+            if i > 0 {
+                line.push(self.options.delimiter);
+            }
+            match field {
+                None => {}
+                Some(field) if needs_quotes(field, self.options.delimiter) => {
+                    line.push('"');
+                    line.push_str(&field.replace('"', "\"\""));
+                    line.push('"');
+                }
+                Some(field) => line.push_str(field),
+            }
+        }
+        line.push_str("\r\n");
+        self.writer.write_all(line.as_bytes()).map_err(write_error)
+    }
+}
+
+fn needs_quotes(field: &str, delimiter: char) -> bool {
+    field.is_empty() || field.contains([delimiter, '"', '\r', '\n'])
+}
+
+impl<W: Write> RowWriter for CsvWriter<W> {
+    fn header(&mut self, columns: &[ResultColumn]) -> Result<()> {
+        if !self.options.header {
+            return Ok(());
+        }
+        self.record(columns.iter().map(|column| Some(column.name.as_str())))
+    }
+
+    fn row(&mut self, values: &[Value]) -> Result<()> {
+        let fields = values
+            .iter()
+            .map(|value| match value {
+                Value::Null => None,
+                value => Some(value.to_string()),
+            })
+            .collect::<Vec<Option<String>>>();
+        self.record(fields.iter().map(|field| field.as_deref()))
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        self.writer.flush().map_err(write_error)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::export::export;
+    use crate::sql::db::database::Database;
+    use crate::sql::process_command;
+
+    #[test]
+    fn csv_export_test() {
+        let mut db = Database::new("tempdb".to_string());
+        for sql in &[
+            "CREATE TABLE notes (id INTEGER PRIMARY KEY, body TEXT, score REAL);",
+            "INSERT INTO notes (body, score) VALUES ('plain', 1.5), ('say \"hi\", then\nleave', 2.0);",
+            "INSERT INTO notes (body) VALUES ('');",
+            "INSERT INTO notes (body, score) VALUES ('last', 3.0);",
+        ] {
+            process_command(sql, &mut db).unwrap();
+        }
+
+        let mut output = vec![];
+        let rows = export(
+            &db,
+            "notes",
+            &mut CsvWriter::new(&mut output, CsvOptions::default()),
+        )
+        .unwrap();
+        assert_eq!(rows, 4);
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "id,body,score\r\n\
+             1,plain,1.5\r\n\
+             2,\"say \"\"hi\"\", then\nleave\",2\r\n\
+             3,\"\",\r\n\
+             4,last,3\r\n"
+        );
+
+        let mut output = vec![];
+        let options = CsvOptions {
+            delimiter: ';',
+            header: false,
+        };
+        let query = "SELECT body, score FROM notes WHERE score > 1.5 ORDER BY score DESC;";
+        export(&db, query, &mut CsvWriter::new(&mut output, options)).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "last;3\r\n\"say \"\"hi\"\", then\nleave\";2\r\n"
+        );
+
+        let mut output = vec![];
+        let writer = &mut CsvWriter::new(&mut output, CsvOptions::default());
+        assert!(export(&db, "missing", writer).is_err());
+    }
+}
@@ -0,0 +1,95 @@
+use std::io::Write;
+
+use serde_json::{json, Map, Value as Json};
+
+use crate::error::Result;
+use crate::export::{write_error, RowWriter};
+use crate::sql::executor::ResultColumn;
+use crate::sql::params::quote_literal;
+use crate::sql::value::Value;
+
+/// Writes rows as JSON lines, one object per row keyed by the names of the columns, to be
+/// fed to `jq`, log pipelines or document stores. Values keep their type and NULL is `null`,
+/// except blobs written as their SQL literal, `X'..'`, and infinite reals, which become `null`.
+pub struct JsonLinesWriter<W: Write> {
+    writer: W,
+    columns: Vec<String>,
+}
+
+impl<W: Write> JsonLinesWriter<W> {
+    pub fn new(writer: W) -> Self {
+        JsonLinesWriter {
+            writer,
+            columns: vec![],
+        }
+    }
+}
+
+impl<W: Write> RowWriter for JsonLinesWriter<W> {
+    fn header(&mut self, columns: &[ResultColumn]) -> Result<()> {
+        self.columns = columns
+            .iter()
+            .map(|column| column.name.to_string())
+            .collect();
+        Ok(())
+    }
+
+    fn row(&mut self, values: &[Value]) -> Result<()> {
+        let mut row = Map::new();
+        for (column, value) in self.columns.iter().zip(values) {
+            let value = match value {
+                Value::Null => Json::Null,
+                Value::Integer(v) => json!(v),
+                Value::Real(v) => json!(v),
+                Value::Text(v) => json!(v),
+                Value::Bool(v) => json!(v),
+                Value::Blob(_) => json!(quote_literal(value)),
+            };
+            row.insert(column.to_string(), value);
+        }
+        let mut line = Json::Object(row).to_string();
+        line.push('\n');
+        self.writer.write_all(line.as_bytes()).map_err(write_error)
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        self.writer.flush().map_err(write_error)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::export::export;
+    use crate::sql::db::database::Database;
+    use crate::sql::process_command;
+
+    #[test]
+    fn json_lines_export_test() {
+        let mut db = Database::new("tempdb".to_string());
+        for sql in &[
+            "CREATE TABLE events (id INTEGER PRIMARY KEY, kind TEXT, cost REAL, done BOOL);",
+            "INSERT INTO events (kind, cost, done) VALUES ('say \"hi\"', 1.5, true);",
+            "INSERT INTO events (kind, done) VALUES ('idle', false);",
+        ] {
+            process_command(sql, &mut db).unwrap();
+        }
+
+        let mut output = vec![];
+        let rows = export(&db, "events", &mut JsonLinesWriter::new(&mut output)).unwrap();
+        assert_eq!(rows, 2);
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "{\"id\":1,\"kind\":\"say \\\"hi\\\"\",\"cost\":1.5,\"done\":true}\n\
+             {\"id\":2,\"kind\":\"idle\",\"cost\":null,\"done\":false}\n"
+        );
+
+        let mut output = vec![];
+        let query = "SELECT kind AS k, x'ff00' AS data FROM events WHERE id = 2;";
+        export(&db, query, &mut JsonLinesWriter::new(&mut output)).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "{\"k\":\"idle\",\"data\":\"X'FF00'\"}\n"
+        );
+    }
+}
@@ -0,0 +1,182 @@
+//! Export of tables and query results to files, as CSV, JSON lines, Arrow or XLSX. Rows are
+//! written one at a time as they are read: a table is streamed a row at a time, and so is
+//! a query as the executor produces its rows, without building the whole result in memory.
+#[cfg(feature = "arrow")]
+pub mod arrow;
+pub mod csv;
+pub mod json;
+#[cfg(feature = "xlsx")]
+pub mod xlsx;
+
+use crate::error::{Result, SQLRiteError};
+use crate::sql::db::database::Database;
+use crate::sql::db::privilege::Privilege;
+use crate::sql::executor::{ResultColumn, RowSink};
+use crate::sql::process_query_into;
+use crate::sql::value::Value;
+
+/// A format rows are exported to
+pub trait RowWriter {
+    /// Writes the names of the columns, before any row. Their declared type is known for
+    /// the columns of a table.
+    fn header(&mut self, columns: &[ResultColumn]) -> Result<()>;
+    /// Writes a row.
+    fn row(&mut self, values: &[Value]) -> Result<()>;
+    /// Writes what ends the export, once every row is written.
+    fn finish(&mut self) -> Result<()>;
+}
+
+/// Exports `source`, the name of a table or a SELECT statement, to `writer`, and returns
+/// the number of rows written.
+pub fn export(db: &Database, source: &str, writer: &mut dyn RowWriter) -> Result<usize> {
+    let source = source.trim();
+    let is_name = !source.is_empty() && !source.contains(char::is_whitespace);
+    if is_name {
+        let table_name = db.resolve_table_name(source)?;
+        if let Some(table) = db.tables.get(&table_name) {
+            db.check_privilege(Privilege::Select, Some(&table_name))?;
+            let columns = table
+                .columns
+                .iter()
+                .map(|column| ResultColumn {
+                    name: column.column_name.to_string(),
+                    datatype: Some(column.datatype.clone()),
+                })
+                .collect::<Vec<ResultColumn>>();
+            writer.header(&columns)?;
+            let mut rows = 0;
+            for rowid in table.rowids() {
+                db.progress.step()?;
+                let values = columns
+                    .iter()
+                    .map(|column| table.get_value(&column.name, rowid))
+                    .collect::<Result<Vec<Value>>>()?;
+                writer.row(&values)?;
+                rows += 1;
+            }
+            writer.finish()?;
+            return Ok(rows);
+        }
+    }
+
+    // Virtual tables, the schema catalog and queries are read like a SELECT
+    let query = if is_name {
+        format!("SELECT * FROM {};", source)
+    } else {
+        source.to_string()
+    };
+    let mut sink = WriterSink { writer, rows: 0 };
+    process_query_into(&query, db, &mut sink)?;
+    sink.writer.finish()?;
+    Ok(sink.rows)
+}
+
+/// Writes the rows of a query to the writer as the executor produces them
+struct WriterSink<'a> {
+    writer: &'a mut dyn RowWriter,
+    /// Number of rows written
+    rows: usize,
+}
+
+impl RowSink for WriterSink<'_> {
+    fn columns(&mut self, columns: &[ResultColumn]) -> Result<()> {
+        self.writer.header(columns)
+    }
+
+    fn row(&mut self, values: Vec<Value>) -> Result<()> {
+        self.writer.row(&values)?;
+        self.rows += 1;
+        Ok(())
+    }
+}
+
+/// The error of a write to the file rows are exported to.
+fn write_error(err: std::io::Error) -> SQLRiteError {
+    SQLRiteError::General(format!("cannot write the export: {}", err))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sql::process_command;
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use std::sync::Arc;
+
+    /// Records the steps the executor had counted when each row was written
+    struct StepWriter {
+        steps: Arc<AtomicU64>,
+        written: Vec<(u64, Vec<Value>)>,
+    }
+
+    impl RowWriter for StepWriter {
+        fn header(&mut self, _columns: &[ResultColumn]) -> Result<()> {
+            Ok(())
+        }
+
+        fn row(&mut self, values: &[Value]) -> Result<()> {
+            let steps = self.steps.load(Ordering::Relaxed);
+            self.written.push((steps, values.to_vec()));
+            Ok(())
+        }
+
+        fn finish(&mut self) -> Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn export_query_streamed_test() {
+        let mut db = Database::new("tempdb".to_string());
+        process_command(
+            "CREATE TABLE t (id INTEGER PRIMARY KEY, n INTEGER);",
+            &mut db,
+        )
+        .unwrap();
+        for n in 1..=3 {
+            let sql = format!("INSERT INTO t (n) VALUES ({});", n);
+            process_command(&sql, &mut db).unwrap();
+        }
+        let steps = Arc::new(AtomicU64::new(0));
+        let counted = steps.clone();
+        db.progress.set(
+            1,
+            Some(Box::new(move || {
+                counted.fetch_add(1, Ordering::Relaxed);
+                false
+            })),
+        );
+
+        // Each row is written before the next one is read
+        let mut writer = StepWriter {
+            steps: steps.clone(),
+            written: vec![],
+        };
+        let rows = export(&db, "SELECT n * 10 FROM t;", &mut writer).unwrap();
+        assert_eq!(rows, 3);
+        let at = writer
+            .written
+            .iter()
+            .map(|(at, _)| *at)
+            .collect::<Vec<u64>>();
+        assert!(at.windows(2).all(|pair| pair[0] < pair[1]), "{:?}", at);
+        let values = writer.written.into_iter().map(|(_, values)| values);
+        assert_eq!(
+            values.collect::<Vec<Vec<Value>>>(),
+            vec![
+                vec![Value::Integer(10)],
+                vec![Value::Integer(20)],
+                vec![Value::Integer(30)]
+            ]
+        );
+
+        // Sorted rows are written once every row is read
+        let mut writer = StepWriter {
+            steps,
+            written: vec![],
+        };
+        export(&db, "SELECT n FROM t ORDER BY n DESC;", &mut writer).unwrap();
+        let (at, values): (Vec<u64>, Vec<Vec<Value>>) = writer.written.into_iter().unzip();
+        assert!(at.windows(2).all(|pair| pair[0] == pair[1]), "{:?}", at);
+        assert_eq!(values[0], vec![Value::Integer(3)]);
+    }
+}
@@ -0,0 +1,202 @@
+use std::path::Path;
+
+use rust_xlsxwriter::{Format, Workbook, Worksheet, XlsxError};
+
+use crate::error::{Result, SQLRiteError};
+use crate::export::{export, RowWriter};
+use crate::sql::db::database::Database;
+use crate::sql::executor::ResultColumn;
+use crate::sql::params::quote_literal;
+use crate::sql::value::Value;
+
+/// Rows of a worksheet, its header included, at most
+const MAX_ROWS: u32 = 1_048_576;
+/// Characters of the name of a worksheet, at most
+const MAX_SHEET_NAME: usize = 31;
+
+/// A spreadsheet being exported, with a worksheet per table or query. Each worksheet starts
+/// with a bold header row, and its cells keep the type of their value: integers and reals
+/// are numbers, booleans are booleans and NULL is an empty cell.
+pub struct XlsxWorkbook {
+    workbook: Workbook,
+    header: Format,
+}
+
+impl Default for XlsxWorkbook {
+    fn default() -> Self {
+        XlsxWorkbook {
+            workbook: Workbook::new(),
+            header: Format::new().set_bold(),
+        }
+    }
+}
+
+impl XlsxWorkbook {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds the worksheet `name`, which the rows written to the returned writer go to.
+    pub fn add_sheet(&mut self, name: &str) -> Result<XlsxSheet<'_>> {
+        let worksheet = self.workbook.add_worksheet();
+        worksheet.set_name(name).map_err(xlsx_error)?;
+        Ok(XlsxSheet {
+            worksheet,
+            header: &self.header,
+            row: 0,
+        })
+    }
+
+    /// Writes the workbook to the file `path`.
+    pub fn save(&mut self, path: impl AsRef<Path>) -> Result<()> {
+        self.workbook.save(path).map_err(xlsx_error)
+    }
+
+    /// Returns the content of the workbook file.
+    pub fn to_buffer(&mut self) -> Result<Vec<u8>> {
+        self.workbook.save_to_buffer().map_err(xlsx_error)
+    }
+}
+
+/// Exports each of `sources`, names of tables or SELECT statements, to a worksheet of
+/// `workbook`, and returns the number of rows written. Worksheets are named after their
+/// table, or numbered for queries.
+pub fn export_workbook(
+    db: &Database,
+    sources: &[&str],
+    workbook: &mut XlsxWorkbook,
+) -> Result<usize> {
+    let mut names: Vec<String> = vec![];
+    let mut rows = 0;
+    for (i, source) in sources.iter().enumerate() {
+        let name = sheet_name(source, i, &names);
+        rows += export(db, source, &mut workbook.add_sheet(&name)?)?;
+        names.push(name);
+    }
+    Ok(rows)
+}
+
+/// The name of the worksheet of `source`, the `i`th source exported: the name of its table
+/// without the characters Excel forbids, or `Query N`, made unique among `names`.
+fn sheet_name(source: &str, i: usize, names: &[String]) -> String {
+    let source = source.trim();
+    let base = if source.is_empty() || source.contains(char::is_whitespace) {
+        format!("Query {}", i + 1)
+    } else {
+        source
+            .chars()
+            .map(|c| if "[]:*?/\\".contains(c) { '_' } else { c })
+            .take(MAX_SHEET_NAME)
+            .collect()
+    };
+    let taken = |name: &str| names.iter().any(|taken| taken.eq_ignore_ascii_case(name));
+    let mut name = base.clone();
+    let mut n = 2;
+    while taken(&name) {
+        let suffix = format!(" ({})", n);
+        let base = base
+            .chars()
+            .take(MAX_SHEET_NAME - suffix.len())
+            .collect::<String>();
+        name = format!("{}{}", base, suffix);
+        n += 1;
+    }
+    name
+}
+
+fn xlsx_error(err: XlsxError) -> SQLRiteError {
+    SQLRiteError::General(format!("cannot write the spreadsheet: {}", err))
+}
+
+/// The worksheet of an `XlsxWorkbook` rows are written to
+pub struct XlsxSheet<'a> {
+    worksheet: &'a mut Worksheet,
+    header: &'a Format,
+    /// Row the next row is written to
+    row: u32,
+}
+
+impl RowWriter for XlsxSheet<'_> {
+    fn header(&mut self, columns: &[ResultColumn]) -> Result<()> {
+        for (col, column) in columns.iter().enumerate() {
+            self.worksheet
+                .write_string_with_format(0, col as u16, &column.name, self.header)
+                .map_err(xlsx_error)?;
+        }
+        self.worksheet.set_freeze_panes(1, 0).map_err(xlsx_error)?;
+        self.row = 1;
+        Ok(())
+    }
+
+    fn row(&mut self, values: &[Value]) -> Result<()> {
+        if self.row >= MAX_ROWS {
+            return Err(SQLRiteError::General(format!(
+                "too many rows for a worksheet, the limit is {}",
+                MAX_ROWS - 1
+            )));
+        }
+        for (col, value) in values.iter().enumerate() {
+            let (row, col) = (self.row, col as u16);
+            let written = match value {
+                Value::Null => continue,
+                Value::Integer(v) => self.worksheet.write_number(row, col, *v as f64),
+                Value::Real(v) if v.is_finite() => self.worksheet.write_number(row, col, *v),
+                Value::Bool(v) => self.worksheet.write_boolean(row, col, *v),
+                Value::Text(v) => self.worksheet.write_string(row, col, v),
+                Value::Blob(_) => self.worksheet.write_string(row, col, quote_literal(value)),
+                value => self.worksheet.write_string(row, col, value.to_string()),
+            };
+            written.map_err(xlsx_error)?;
+        }
+        self.row += 1;
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sql::process_command;
+
+    #[test]
+    fn xlsx_export_test() {
+        let mut db = Database::new("tempdb".to_string());
+        for sql in &[
+            "CREATE TABLE sales (id INTEGER PRIMARY KEY, region TEXT, amount REAL, paid BOOL);",
+            "INSERT INTO sales (region, amount, paid) VALUES ('north', 10.5, true), ('south', 3.0, false);",
+        ] {
+            process_command(sql, &mut db).unwrap();
+        }
+
+        let mut workbook = XlsxWorkbook::new();
+        let sources = [
+            "sales",
+            "SELECT region FROM sales WHERE paid = true;",
+            "sales",
+        ];
+        assert_eq!(export_workbook(&db, &sources, &mut workbook).unwrap(), 5);
+        let names = workbook
+            .workbook
+            .worksheets()
+            .iter()
+            .map(|worksheet| worksheet.name())
+            .collect::<Vec<String>>();
+        assert_eq!(names, vec!["sales", "Query 2", "sales (2)"]);
+        assert!(workbook.to_buffer().unwrap().starts_with(b"PK"));
+
+        assert_eq!(sheet_name("a/b", 0, &[]), "a_b");
+        assert_eq!(sheet_name(&"x".repeat(40), 0, &[]).len(), MAX_SHEET_NAME);
+        let names = vec!["x".repeat(MAX_SHEET_NAME)];
+        assert_eq!(
+            sheet_name(&"x".repeat(40), 1, &names),
+            format!("{} (2)", "x".repeat(MAX_SHEET_NAME - 4))
+        );
+
+        let mut workbook = XlsxWorkbook::new();
+        assert!(export_workbook(&db, &["missing"], &mut workbook).is_err());
+    }
+}
@@ -0,0 +1,804 @@
+//! Runtime loadable extensions.
+//!
+//! An extension is a dynamic library exporting an entry point with the `ExtensionInit`
+//! signature, by default named `sqlrite_extension_init`. Once loaded, the entry point is
+//! called with the `ExtensionApi` function table, through which it registers scalar
+//! functions, collations and virtual table modules on the database that loaded it.
+//! Everything crossing the boundary uses the C ABI, so extensions can be written in any
+//! language able to export C functions.
+//!
+//! Callbacks registered by an extension may be called from any thread the database is
+//! used on, so they must be thread safe.
+use std::any::Any;
+use std::ffi::{CStr, CString};
+use std::os::raw::{c_char, c_int, c_void};
+use std::ptr;
+use std::sync::Arc;
+
+use crate::error::{Result, SQLRiteError};
+use crate::sql::db::database::Database;
+use crate::sql::db::table::DataType;
+use crate::sql::value::Value;
+use crate::sql::vtab::{VirtualColumn, VirtualCursor, VirtualTable, VirtualTableModule};
+
+/// Version of the `ExtensionApi` function table, bumped whenever it changes.
+pub const EXTENSION_API_VERSION: c_int = 1;
+
+/// Name of the entry point looked up when loading an extension without naming one.
+pub const DEFAULT_ENTRY_POINT: &str = "sqlrite_extension_init";
+
+/// Returned by extension callbacks on success.
+pub const SQLRITE_OK: c_int = 0;
+/// Returned by extension callbacks on failure.
+pub const SQLRITE_ERROR: c_int = 1;
+
+/// Storage class tags of `ExtensionValue::kind`, also used as column types.
+pub const VALUE_NULL: c_int = 0;
+pub const VALUE_INTEGER: c_int = 1;
+pub const VALUE_REAL: c_int = 2;
+pub const VALUE_TEXT: c_int = 3;
+pub const VALUE_BOOL: c_int = 4;
+pub const VALUE_BLOB: c_int = 5;
+
+/// C representation of a `Value`.
+/// Only the field matching `kind` is meaningful; booleans are stored in `integer` as 0 or 1.
+/// `text` points to `text_len` bytes of UTF-8, which need not be nul-terminated and are only
+/// valid for the duration of the call they are passed to. Blobs use the same two fields for
+/// their bytes.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct ExtensionValue {
+    pub kind: c_int,
+    pub integer: i64,
+    pub real: f64,
+    pub text: *const c_char,
+    pub text_len: usize,
+}
+
+impl ExtensionValue {
+    /// Borrows `value` as an `ExtensionValue`, valid as long as `value` is.
+    pub fn new(value: &Value) -> Self {
+        let mut ext = ExtensionValue {
+            kind: VALUE_NULL,
+            integer: 0,
+            real: 0.0,
+            text: ptr::null(),
+            text_len: 0,
+        };
+        match value {
+            Value::Null => (),
+            Value::Integer(v) => {
+                ext.kind = VALUE_INTEGER;
+                ext.integer = *v;
+            }
+            Value::Real(v) => {
+                ext.kind = VALUE_REAL;
+                ext.real = *v;
+            }
+            Value::Text(v) => {
+                ext.kind = VALUE_TEXT;
+                ext.text = v.as_ptr() as *const c_char;
+                ext.text_len = v.len();
+            }
+            Value::Bool(v) => {
+                ext.kind = VALUE_BOOL;
+                ext.integer = i64::from(*v);
+            }
+            Value::Blob(v) => {
+                ext.kind = VALUE_BLOB;
+                ext.text = v.as_ptr() as *const c_char;
+                ext.text_len = v.len();
+            }
+        }
+        ext
+    }
+
+    /// Copies the value into a `Value`.
+    ///
+    /// # Safety
+    ///
+    /// For text and blob values, `text` must point to `text_len` readable bytes.
+    pub unsafe fn to_value(&self) -> Result<Value> {
+        match self.kind {
+            VALUE_NULL => Ok(Value::Null),
+            VALUE_INTEGER => Ok(Value::Integer(self.integer)),
+            VALUE_REAL => Ok(Value::Real(self.real)),
+            VALUE_TEXT => Ok(Value::Text(text_from_raw(self.text, self.text_len))),
+            VALUE_BOOL => Ok(Value::Bool(self.integer != 0)),
+            VALUE_BLOB if self.text.is_null() || self.text_len == 0 => Ok(Value::Blob(vec![])),
+            VALUE_BLOB => Ok(Value::Blob(
+                std::slice::from_raw_parts(self.text as *const u8, self.text_len).to_vec(),
+            )),
+            kind => Err(SQLRiteError::General(format!(
+                "Unknown value kind returned by extension: {}",
+                kind
+            ))),
+        }
+    }
+}
+
+/// Collects the outcome of a callback, set through `ExtensionApi::result_value`
+/// or `ExtensionApi::result_error`.
+#[derive(Debug, Default)]
+pub struct ResultContext {
+    result: Option<Result<Value>>,
+}
+
+impl ResultContext {
+    fn into_result(self) -> Result<Value> {
+        self.result.unwrap_or(Ok(Value::Null))
+    }
+}
+
+/// Opaque handle to the database an extension is being loaded into.
+pub struct ExtensionContext<'a> {
+    db: &'a mut Database,
+    library: Option<Arc<dyn Any + Send + Sync>>,
+}
+
+/// Implementation of a scalar function: reads `argc` arguments from `argv` and reports
+/// its result through `result`.
+pub type ScalarCallback = unsafe extern "C" fn(
+    user_data: *mut c_void,
+    argc: c_int,
+    argv: *const ExtensionValue,
+    result: *mut ResultContext,
+);
+
+/// Implementation of a collation: compares two UTF-8 strings, returning a negative number,
+/// zero or a positive number.
+pub type CollationCallback = unsafe extern "C" fn(
+    user_data: *mut c_void,
+    a: *const c_char,
+    a_len: usize,
+    b: *const c_char,
+    b_len: usize,
+) -> c_int;
+
+/// Called with the `user_data` of a registration once the engine no longer needs it.
+pub type DestroyCallback = unsafe extern "C" fn(user_data: *mut c_void);
+
+/// Callbacks implementing a virtual table module.
+/// Table and cursor handles are opaque pointers owned by the extension.
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct ExtensionModule {
+    /// Creates a table from the `argc` nul-terminated USING arguments, storing its handle in `table`.
+    pub create: unsafe extern "C" fn(
+        user_data: *mut c_void,
+        argc: c_int,
+        argv: *const *const c_char,
+        table: *mut *mut c_void,
+    ) -> c_int,
+    /// Number of columns of the table.
+    pub column_count: unsafe extern "C" fn(table: *mut c_void) -> c_int,
+    /// Nul-terminated name of column `index`, owned by the table.
+    pub column_name: unsafe extern "C" fn(table: *mut c_void, index: c_int) -> *const c_char,
+    /// Type of column `index`, as one of the `VALUE_*` tags.
+    pub column_type: unsafe extern "C" fn(table: *mut c_void, index: c_int) -> c_int,
+    /// Opens a cursor over the table, storing its handle in `cursor`.
+    pub open: unsafe extern "C" fn(table: *mut c_void, cursor: *mut *mut c_void) -> c_int,
+    /// Starts a scan, positioning the cursor on the first row.
+    pub filter: unsafe extern "C" fn(
+        cursor: *mut c_void,
+        idx_num: c_int,
+        argc: c_int,
+        argv: *const ExtensionValue,
+    ) -> c_int,
+    /// Advances the cursor to the next row.
+    pub next: unsafe extern "C" fn(cursor: *mut c_void) -> c_int,
+    /// Returns non-zero once the cursor moved past the last row.
+    pub eof: unsafe extern "C" fn(cursor: *mut c_void) -> c_int,
+    /// Reports the value of column `index` for the current row through `result`.
+    pub column: unsafe extern "C" fn(
+        cursor: *mut c_void,
+        index: c_int,
+        result: *mut ResultContext,
+    ) -> c_int,
+    /// Releases a cursor.
+    pub close: unsafe extern "C" fn(cursor: *mut c_void),
+    /// Releases a table.
+    pub destroy: unsafe extern "C" fn(table: *mut c_void),
+}
+
+/// Function table handed to the entry point of an extension.
+#[repr(C)]
+pub struct ExtensionApi {
+    /// Always `EXTENSION_API_VERSION`
+    pub version: c_int,
+    /// Registers a scalar function taking `n_args` arguments, -1 for any number of them.
+    pub create_function: unsafe extern "C" fn(
+        ctx: *mut ExtensionContext,
+        name: *const c_char,
+        n_args: c_int,
+        deterministic: c_int,
+        func: ScalarCallback,
+        user_data: *mut c_void,
+        destroy: Option<DestroyCallback>,
+    ) -> c_int,
+    /// Registers a collation.
+    pub create_collation: unsafe extern "C" fn(
+        ctx: *mut ExtensionContext,
+        name: *const c_char,
+        cmp: CollationCallback,
+        user_data: *mut c_void,
+        destroy: Option<DestroyCallback>,
+    ) -> c_int,
+    /// Registers a virtual table module. `module` is copied.
+    pub create_module: unsafe extern "C" fn(
+        ctx: *mut ExtensionContext,
+        name: *const c_char,
+        module: *const ExtensionModule,
+        user_data: *mut c_void,
+        destroy: Option<DestroyCallback>,
+    ) -> c_int,
+    /// Sets the result of a callback to a copy of `value`.
+    pub result_value:
+        unsafe extern "C" fn(result: *mut ResultContext, value: *const ExtensionValue),
+    /// Sets the result of a callback to an error with a nul-terminated `message`.
+    pub result_error: unsafe extern "C" fn(result: *mut ResultContext, message: *const c_char),
+}
+
+/// Signature of the entry point exported by an extension.
+pub type ExtensionInit =
+    unsafe extern "C" fn(ctx: *mut ExtensionContext, api: *const ExtensionApi) -> c_int;
+
+static EXTENSION_API: ExtensionApi = ExtensionApi {
+    version: EXTENSION_API_VERSION,
+    create_function: api_create_function,
+    create_collation: api_create_collation,
+    create_module: api_create_module,
+    result_value: api_result_value,
+    result_error: api_result_error,
+};
+
+/// Loads the dynamic library at `path` and runs its entry point, `entry_point` or
+/// `sqlrite_extension_init` by default, against `db`.
+/// The library stays loaded for as long as anything it registered is.
+///
+/// # Safety
+///
+/// Loading a library runs arbitrary code from it, which must be a trustworthy SQLRite
+/// extension built against this version of the `ExtensionApi`.
+#[cfg(feature = "extensions")]
+pub unsafe fn load_extension(
+    db: &mut Database,
+    path: &str,
+    entry_point: Option<&str>,
+) -> Result<()> {
+    let library = libloading::Library::new(path).map_err(|err| {
+        SQLRiteError::General(format!("Could not load extension {}: {}", path, err))
+    })?;
+    let entry_point = entry_point.unwrap_or(DEFAULT_ENTRY_POINT);
+    let init: ExtensionInit = *library
+        .get::<ExtensionInit>(entry_point.as_bytes())
+        .map_err(|err| {
+            SQLRiteError::General(format!(
+                "Could not find entry point {} in {}: {}",
+                entry_point, path, err
+            ))
+        })?;
+    init_extension(db, init, Some(Arc::new(library)))
+}
+
+/// Runs the entry point of an extension against `db`.
+/// `library` is kept alive by everything the extension registers.
+///
+/// # Safety
+///
+/// `init` must be a SQLRite extension entry point built against this version of the `ExtensionApi`.
+pub unsafe fn init_extension(
+    db: &mut Database,
+    init: ExtensionInit,
+    library: Option<Arc<dyn Any + Send + Sync>>,
+) -> Result<()> {
+    let mut ctx = ExtensionContext { db, library };
+    match init(&mut ctx, &EXTENSION_API) {
+        SQLRITE_OK => Ok(()),
+        code => Err(SQLRiteError::General(format!(
+            "Extension initialization failed with code {}",
+            code
+        ))),
+    }
+}
+
+/// Pointer handed over by an extension along with the callbacks that use it.
+/// Destroyed with the extension's destroy callback once the last registration using it is dropped.
+struct UserData {
+    ptr: *mut c_void,
+    destroy: Option<DestroyCallback>,
+    // Declared last so the library is only unloaded after `destroy` ran
+    _library: Option<Arc<dyn Any + Send + Sync>>,
+}
+
+// Extensions are required to be thread safe, see the module documentation.
+unsafe impl Send for UserData {}
+unsafe impl Sync for UserData {}
+
+impl Drop for UserData {
+    fn drop(&mut self) {
+        if let Some(destroy) = self.destroy {
+            unsafe { destroy(self.ptr) }
+        }
+    }
+}
+
+unsafe fn text_from_raw(text: *const c_char, len: usize) -> String {
+    if text.is_null() {
+        return String::new();
+    }
+    let bytes = std::slice::from_raw_parts(text as *const u8, len);
+    String::from_utf8_lossy(bytes).into_owned()
+}
+
+unsafe fn name_from_raw(name: *const c_char) -> Option<String> {
+    if name.is_null() {
+        return None;
+    }
+    CStr::from_ptr(name)
+        .to_str()
+        .ok()
+        .map(|name| name.to_string())
+}
+
+unsafe extern "C" fn api_create_function(
+    ctx: *mut ExtensionContext,
+    name: *const c_char,
+    n_args: c_int,
+    deterministic: c_int,
+    func: ScalarCallback,
+    user_data: *mut c_void,
+    destroy: Option<DestroyCallback>,
+) -> c_int {
+    let (ctx, name) = match (ctx.as_mut(), name_from_raw(name)) {
+        (Some(ctx), Some(name)) => (ctx, name),
+        _ => return SQLRITE_ERROR,
+    };
+    let user_data = Arc::new(UserData {
+        ptr: user_data,
+        destroy,
+        _library: ctx.library.clone(),
+    });
+    ctx.db
+        .functions
+        .register(&name, n_args, deterministic != 0, move |args| {
+            let argv = args.iter().map(ExtensionValue::new).collect::<Vec<_>>();
+            let mut result = ResultContext::default();
+            unsafe {
+                func(
+                    user_data.ptr,
+                    argv.len() as c_int,
+                    argv.as_ptr(),
+                    &mut result,
+                );
+            }
+            result.into_result()
+        });
+    SQLRITE_OK
+}
+
+unsafe extern "C" fn api_create_collation(
+    ctx: *mut ExtensionContext,
+    name: *const c_char,
+    cmp: CollationCallback,
+    user_data: *mut c_void,
+    destroy: Option<DestroyCallback>,
+) -> c_int {
+    let (ctx, name) = match (ctx.as_mut(), name_from_raw(name)) {
+        (Some(ctx), Some(name)) => (ctx, name),
+        _ => return SQLRITE_ERROR,
+    };
+    let user_data = Arc::new(UserData {
+        ptr: user_data,
+        destroy,
+        _library: ctx.library.clone(),
+    });
+    ctx.db.collations.register(&name, move |a, b| {
+        let order = unsafe {
+            cmp(
+                user_data.ptr,
+                a.as_ptr() as *const c_char,
+                a.len(),
+                b.as_ptr() as *const c_char,
+                b.len(),
+            )
+        };
+        order.cmp(&0)
+    });
+    SQLRITE_OK
+}
+
+unsafe extern "C" fn api_create_module(
+    ctx: *mut ExtensionContext,
+    name: *const c_char,
+    module: *const ExtensionModule,
+    user_data: *mut c_void,
+    destroy: Option<DestroyCallback>,
+) -> c_int {
+    let (ctx, name, module) = match (ctx.as_mut(), name_from_raw(name), module.as_ref()) {
+        (Some(ctx), Some(name), Some(module)) => (ctx, name, *module),
+        _ => return SQLRITE_ERROR,
+    };
+    let user_data = Arc::new(UserData {
+        ptr: user_data,
+        destroy,
+        _library: ctx.library.clone(),
+    });
+    ctx.db
+        .vtabs
+        .register_module(&name, Arc::new(ModuleAdapter { module, user_data }));
+    SQLRITE_OK
+}
+
+unsafe extern "C" fn api_result_value(result: *mut ResultContext, value: *const ExtensionValue) {
+    if let (Some(result), Some(value)) = (result.as_mut(), value.as_ref()) {
+        result.result = Some(value.to_value());
+    }
+}
+
+unsafe extern "C" fn api_result_error(result: *mut ResultContext, message: *const c_char) {
+    if let Some(result) = result.as_mut() {
+        let message = name_from_raw(message).unwrap_or_else(|| "extension error".to_string());
+        result.result = Some(Err(SQLRiteError::General(message)));
+    }
+}
+
+fn check(code: c_int, operation: &str) -> Result<()> {
+    match code {
+        SQLRITE_OK => Ok(()),
+        code => Err(SQLRiteError::General(format!(
+            "Extension {} failed with code {}",
+            operation, code
+        ))),
+    }
+}
+
+/// Exposes a virtual table module registered by an extension as a `VirtualTableModule`.
+struct ModuleAdapter {
+    module: ExtensionModule,
+    user_data: Arc<UserData>,
+}
+
+impl VirtualTableModule for ModuleAdapter {
+    fn create(&self, args: &[String]) -> Result<Arc<dyn VirtualTable>> {
+        let args = args
+            .iter()
+            .map(|arg| CString::new(arg.as_str()))
+            .collect::<std::result::Result<Vec<CString>, _>>()
+            .map_err(|_| SQLRiteError::General("Invalid module argument".to_string()))?;
+        let argv = args.iter().map(|arg| arg.as_ptr()).collect::<Vec<_>>();
+        let mut table: *mut c_void = ptr::null_mut();
+        check(
+            unsafe {
+                (self.module.create)(
+                    self.user_data.ptr,
+                    argv.len() as c_int,
+                    argv.as_ptr(),
+                    &mut table,
+                )
+            },
+            "create",
+        )?;
+        Ok(Arc::new(TableAdapter {
+            handle: Arc::new(TableHandle {
+                module: self.module,
+                table,
+                _user_data: Arc::clone(&self.user_data),
+            }),
+        }))
+    }
+}
+
+/// Table created by an extension module, destroyed once the table and all its cursors are dropped.
+struct TableHandle {
+    module: ExtensionModule,
+    table: *mut c_void,
+    _user_data: Arc<UserData>,
+}
+
+unsafe impl Send for TableHandle {}
+unsafe impl Sync for TableHandle {}
+
+impl Drop for TableHandle {
+    fn drop(&mut self) {
+        unsafe { (self.module.destroy)(self.table) }
+    }
+}
+
+struct TableAdapter {
+    handle: Arc<TableHandle>,
+}
+
+impl VirtualTable for TableAdapter {
+    fn columns(&self) -> Vec<VirtualColumn> {
+        let module = &self.handle.module;
+        let table = self.handle.table;
+        let count = unsafe { (module.column_count)(table) };
+        (0..count)
+            .map(|i| {
+                let name = unsafe { name_from_raw((module.column_name)(table, i)) };
+                let datatype = match unsafe { (module.column_type)(table, i) } {
+                    VALUE_INTEGER => DataType::Integer,
+                    VALUE_REAL => DataType::Real,
+                    VALUE_TEXT => DataType::Text,
+                    VALUE_BOOL => DataType::Bool,
+                    _ => DataType::None,
+                };
+                VirtualColumn {
+                    name: name.unwrap_or_else(|| format!("column{}", i)),
+                    datatype,
+                    hidden: false,
+                }
+            })
+            .collect()
+    }
+
+    fn open(&self) -> Result<Box<dyn VirtualCursor>> {
+        let mut cursor: *mut c_void = ptr::null_mut();
+        check(
+            unsafe { (self.handle.module.open)(self.handle.table, &mut cursor) },
+            "open",
+        )?;
+        Ok(Box::new(CursorAdapter {
+            cursor,
+            table: Arc::clone(&self.handle),
+        }))
+    }
+}
+
+struct CursorAdapter {
+    cursor: *mut c_void,
+    table: Arc<TableHandle>,
+}
+
+impl Drop for CursorAdapter {
+    fn drop(&mut self) {
+        unsafe { (self.table.module.close)(self.cursor) }
+    }
+}
+
+impl VirtualCursor for CursorAdapter {
+    fn filter(&mut self, idx_num: i32, args: &[Value]) -> Result<()> {
+        let argv = args.iter().map(ExtensionValue::new).collect::<Vec<_>>();
+        check(
+            unsafe {
+                (self.table.module.filter)(self.cursor, idx_num, argv.len() as c_int, argv.as_ptr())
+            },
+            "filter",
+        )
+    }
+
+    fn next(&mut self) -> Result<()> {
+        check(unsafe { (self.table.module.next)(self.cursor) }, "next")
+    }
+
+    fn eof(&self) -> bool {
+        unsafe { (self.table.module.eof)(self.cursor) != 0 }
+    }
+
+    fn column(&self, index: usize) -> Result<Value> {
+        let mut result = ResultContext::default();
+        check(
+            unsafe { (self.table.module.column)(self.cursor, index as c_int, &mut result) },
+            "column",
+        )?;
+        result.into_result()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sql::process_query;
+    use std::cmp::Ordering;
+    use std::sync::atomic::{AtomicUsize, Ordering as AtomicOrdering};
+
+    static DESTROYED: AtomicUsize = AtomicUsize::new(0);
+
+    unsafe extern "C" fn twice(
+        _user_data: *mut c_void,
+        argc: c_int,
+        argv: *const ExtensionValue,
+        result: *mut ResultContext,
+    ) {
+        let args = std::slice::from_raw_parts(argv, argc as usize);
+        if args[0].kind != VALUE_INTEGER {
+            api_result_error(
+                result,
+                b"twice() expects an integer\0".as_ptr() as *const c_char,
+            );
+            return;
+        }
+        let value = ExtensionValue::new(&Value::Integer(args[0].integer * 2));
+        api_result_value(result, &value);
+    }
+
+    unsafe extern "C" fn by_length(
+        _user_data: *mut c_void,
+        _a: *const c_char,
+        a_len: usize,
+        _b: *const c_char,
+        b_len: usize,
+    ) -> c_int {
+        a_len as c_int - b_len as c_int
+    }
+
+    unsafe extern "C" fn count_destroy(_user_data: *mut c_void) {
+        DESTROYED.fetch_add(1, AtomicOrdering::SeqCst);
+    }
+
+    unsafe extern "C" fn test_init(ctx: *mut ExtensionContext, api: *const ExtensionApi) -> c_int {
+        let api = &*api;
+        assert_eq!(api.version, EXTENSION_API_VERSION);
+        let code = (api.create_function)(
+            ctx,
+            b"twice\0".as_ptr() as *const c_char,
+            1,
+            1,
+            twice,
+            ptr::null_mut(),
+            Some(count_destroy),
+        );
+        if code != SQLRITE_OK {
+            return code;
+        }
+        (api.create_collation)(
+            ctx,
+            b"by_length\0".as_ptr() as *const c_char,
+            by_length,
+            ptr::null_mut(),
+            None,
+        )
+    }
+
+    #[test]
+    fn init_extension_test() {
+        let mut db = Database::new("tempdb".to_string());
+        unsafe { init_extension(&mut db, test_init, None) }.unwrap();
+
+        assert!(db.functions.get("twice").unwrap().deterministic);
+        assert_eq!(
+            db.functions.call("twice", &[Value::Integer(21)]),
+            Ok(Value::Integer(42))
+        );
+        assert!(db
+            .functions
+            .call("twice", &[Value::Text("a".to_string())])
+            .is_err());
+
+        let by_length = db.collations.get("by_length").unwrap();
+        assert_eq!(by_length("bb", "a"), Ordering::Greater);
+
+        // Replacing the function releases the extension's user data
+        db.functions.register("twice", 1, true, |_| Ok(Value::Null));
+        assert_eq!(DESTROYED.load(AtomicOrdering::SeqCst), 1);
+    }
+
+    struct Counter {
+        current: i64,
+        stop: i64,
+    }
+
+    unsafe extern "C" fn counter_create(
+        _user_data: *mut c_void,
+        argc: c_int,
+        argv: *const *const c_char,
+        table: *mut *mut c_void,
+    ) -> c_int {
+        let args = std::slice::from_raw_parts(argv, argc as usize);
+        let stop = match CStr::from_ptr(args[0]).to_str().map(|s| s.parse::<i64>()) {
+            Ok(Ok(stop)) => stop,
+            _ => return SQLRITE_ERROR,
+        };
+        *table = Box::into_raw(Box::new(stop)) as *mut c_void;
+        SQLRITE_OK
+    }
+
+    unsafe extern "C" fn counter_column_count(_table: *mut c_void) -> c_int {
+        1
+    }
+
+    unsafe extern "C" fn counter_column_name(_table: *mut c_void, _index: c_int) -> *const c_char {
+        b"value\0".as_ptr() as *const c_char
+    }
+
+    unsafe extern "C" fn counter_column_type(_table: *mut c_void, _index: c_int) -> c_int {
+        VALUE_INTEGER
+    }
+
+    unsafe extern "C" fn counter_open(table: *mut c_void, cursor: *mut *mut c_void) -> c_int {
+        let stop = *(table as *mut i64);
+        *cursor = Box::into_raw(Box::new(Counter { current: 0, stop })) as *mut c_void;
+        SQLRITE_OK
+    }
+
+    unsafe extern "C" fn counter_filter(
+        cursor: *mut c_void,
+        _idx_num: c_int,
+        _argc: c_int,
+        _argv: *const ExtensionValue,
+    ) -> c_int {
+        (*(cursor as *mut Counter)).current = 1;
+        SQLRITE_OK
+    }
+
+    unsafe extern "C" fn counter_next(cursor: *mut c_void) -> c_int {
+        (*(cursor as *mut Counter)).current += 1;
+        SQLRITE_OK
+    }
+
+    unsafe extern "C" fn counter_eof(cursor: *mut c_void) -> c_int {
+        let counter = &*(cursor as *mut Counter);
+        (counter.current > counter.stop) as c_int
+    }
+
+    unsafe extern "C" fn counter_column(
+        cursor: *mut c_void,
+        _index: c_int,
+        result: *mut ResultContext,
+    ) -> c_int {
+        let value = ExtensionValue::new(&Value::Integer((*(cursor as *mut Counter)).current));
+        api_result_value(result, &value);
+        SQLRITE_OK
+    }
+
+    unsafe extern "C" fn counter_close(cursor: *mut c_void) {
+        drop(Box::from_raw(cursor as *mut Counter));
+    }
+
+    unsafe extern "C" fn counter_destroy(table: *mut c_void) {
+        drop(Box::from_raw(table as *mut i64));
+    }
+
+    static COUNTER_MODULE: ExtensionModule = ExtensionModule {
+        create: counter_create,
+        column_count: counter_column_count,
+        column_name: counter_column_name,
+        column_type: counter_column_type,
+        open: counter_open,
+        filter: counter_filter,
+        next: counter_next,
+        eof: counter_eof,
+        column: counter_column,
+        close: counter_close,
+        destroy: counter_destroy,
+    };
+
+    unsafe extern "C" fn module_init(
+        ctx: *mut ExtensionContext,
+        api: *const ExtensionApi,
+    ) -> c_int {
+        ((*api).create_module)(
+            ctx,
+            b"counter\0".as_ptr() as *const c_char,
+            &COUNTER_MODULE,
+            ptr::null_mut(),
+            None,
+        )
+    }
+
+    #[test]
+    fn extension_module_test() {
+        let mut db = Database::new("tempdb".to_string());
+        unsafe { init_extension(&mut db, module_init, None) }.unwrap();
+
+        db.vtabs
+            .create_table("numbers", "counter", &["3".to_string()])
+            .unwrap();
+        let result = process_query("SELECT value FROM numbers;", &db).unwrap();
+        assert_eq!(result.columns[0].datatype, Some(DataType::Integer));
+        assert_eq!(
+            result.rows,
+            vec![
+                vec![Value::Integer(1)],
+                vec![Value::Integer(2)],
+                vec![Value::Integer(3)],
+            ]
+        );
+
+        assert!(db
+            .vtabs
+            .create_table("broken", "counter", &["three".to_string()])
+            .is_err());
+    }
+}
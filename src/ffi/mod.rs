@@ -0,0 +1,815 @@
+//! C interface matching a subset of the sqlite3 C API, available with the `ffi` feature.
+//!
+//! The functions keep the names, signatures and result codes of their sqlite3 counterparts,
+//! so C programs limited to this subset can be linked against SQLRite's cdylib instead of
+//! SQLite, using the `include/sqlite3.h` header shipped with the crate.
+//!
+//! Databases are always transient and in-memory for now, the filename given to
+//! `sqlite3_open` is ignored. Only positional parameters
+//! (`?` and `?NNN`) can be bound.
+//!
+//! Every function taking a pointer expects it to be either null or a valid pointer of the
+//! expected type obtained from this API, as documented by SQLite.
+#![allow(non_camel_case_types, clippy::missing_safety_doc)]
+
+use std::convert::TryFrom;
+use std::ffi::{CStr, CString};
+use std::os::raw::{c_char, c_double, c_int, c_void};
+use std::ptr;
+
+use crate::connection::Connection;
+use crate::error::{Result, SQLRiteError};
+use crate::sql::executor::ResultSet;
+use crate::sql::params::{bind_parameters, parameter_count};
+use crate::sql::value::Value;
+use crate::sql::{process_query, process_query_with_params, returns_rows};
+
+pub const SQLITE_OK: c_int = 0;
+pub const SQLITE_ERROR: c_int = 1;
+pub const SQLITE_NOMEM: c_int = 7;
+pub const SQLITE_MISUSE: c_int = 21;
+pub const SQLITE_RANGE: c_int = 25;
+pub const SQLITE_ROW: c_int = 100;
+pub const SQLITE_DONE: c_int = 101;
+
+pub const SQLITE_INTEGER: c_int = 1;
+pub const SQLITE_FLOAT: c_int = 2;
+pub const SQLITE_TEXT: c_int = 3;
+pub const SQLITE_BLOB: c_int = 4;
+pub const SQLITE_NULL: c_int = 5;
+
+/// Value of `SQLITE_TRANSIENT`, asking for text passed to `sqlite3_bind_text` to be copied.
+/// Text is always copied, so the destructor argument is otherwise ignored.
+pub const SQLITE_TRANSIENT: isize = -1;
+
+const VERSION: &[u8] = concat!(env!("CARGO_PKG_VERSION"), "\0").as_bytes();
+
+/// Database connection handle
+pub struct sqlite3 {
+    conn: Connection,
+    errcode: c_int,
+    errmsg: CString,
+}
+
+impl sqlite3 {
+    /// Records the outcome of an API call, to be reported by `sqlite3_errcode` and `sqlite3_errmsg`.
+    fn set_result<T>(&mut self, result: &Result<T>) -> c_int {
+        match result {
+            Ok(_) => {
+                self.errcode = SQLITE_OK;
+                self.errmsg = CString::new("not an error").unwrap();
+            }
+            Err(err) => {
+                self.errcode = SQLITE_ERROR;
+                self.errmsg = CString::new(err.to_string().replace('\0', ""))
+                    .unwrap_or_else(|_| CString::new("unknown error").unwrap());
+            }
+        }
+        self.errcode
+    }
+}
+
+/// State of a prepared statement between calls to `sqlite3_step`
+enum StepState {
+    /// Not run yet, or reset
+    Ready,
+    /// Returning the rows of a query, `current` is the row the cursor is on
+    Rows {
+        result: ResultSet,
+        current: usize,
+    },
+    Done,
+}
+
+/// Prepared statement handle
+pub struct sqlite3_stmt {
+    db: *mut sqlite3,
+    sql: String,
+    params: Vec<Value>,
+    is_query: bool,
+    state: StepState,
+    /// Nul-terminated names of the result columns
+    names: Vec<CString>,
+    /// Text of the columns of the current row, kept alive until the cursor moves
+    texts: Vec<Option<CString>>,
+}
+
+impl sqlite3_stmt {
+    fn current_value(&self, col: c_int) -> Option<&Value> {
+        match &self.state {
+            StepState::Rows { result, current } => result
+                .rows
+                .get(*current)
+                .and_then(|row| row.get(usize::try_from(col).ok()?)),
+            _ => None,
+        }
+    }
+
+    fn column_count(&self) -> usize {
+        match &self.state {
+            StepState::Rows { result, .. } => result.columns.len(),
+            _ => self.names.len(),
+        }
+    }
+
+    fn bind(&mut self, index: c_int, value: Value) -> c_int {
+        if !matches!(self.state, StepState::Ready) {
+            return SQLITE_MISUSE;
+        }
+        match usize::try_from(index) {
+            Ok(index) if index >= 1 && index <= self.params.len() => {
+                self.params[index - 1] = value;
+                SQLITE_OK
+            }
+            _ => SQLITE_RANGE,
+        }
+    }
+
+    fn step(&mut self, conn: &mut Connection) -> Result<c_int> {
+        if let StepState::Ready = self.state {
+            if self.is_query {
+                let result = process_query_with_params(&self.sql, &self.params, conn.database())?;
+                self.names = result
+                    .columns
+                    .iter()
+                    .map(|col| CString::new(col.name.replace('\0', "")).unwrap())
+                    .collect();
+                // Positioned before the first row, moved onto it below
+                self.state = StepState::Rows {
+                    result,
+                    current: usize::MAX,
+                };
+            } else {
+                conn.execute(&bind_parameters(&self.sql, &self.params)?)?;
+                self.state = StepState::Done;
+                return Ok(SQLITE_DONE);
+            }
+        }
+
+        self.texts.clear();
+        if let StepState::Rows { result, current } = &mut self.state {
+            *current = current.wrapping_add(1);
+            if *current < result.rows.len() {
+                self.texts = vec![None; result.columns.len()];
+                return Ok(SQLITE_ROW);
+            }
+            self.state = StepState::Done;
+        }
+        Ok(SQLITE_DONE)
+    }
+}
+
+unsafe fn str_from_raw<'a>(s: *const c_char) -> Option<&'a str> {
+    if s.is_null() {
+        return None;
+    }
+    CStr::from_ptr(s).to_str().ok()
+}
+
+/// Returns the version of SQLRite implementing this API.
+#[no_mangle]
+pub extern "C" fn sqlite3_libversion() -> *const c_char {
+    VERSION.as_ptr() as *const c_char
+}
+
+/// Opens a new transient in-memory database. `filename` is ignored for now.
+#[no_mangle]
+pub unsafe extern "C" fn sqlite3_open(_filename: *const c_char, pp_db: *mut *mut sqlite3) -> c_int {
+    if pp_db.is_null() {
+        return SQLITE_MISUSE;
+    }
+    let db = Box::new(sqlite3 {
+        conn: Connection::open_in_memory(),
+        errcode: SQLITE_OK,
+        errmsg: CString::new("not an error").unwrap(),
+    });
+    *pp_db = Box::into_raw(db);
+    SQLITE_OK
+}
+
+/// Closes a database connection. Every statement prepared on it must be finalized first.
+#[no_mangle]
+pub unsafe extern "C" fn sqlite3_close(db: *mut sqlite3) -> c_int {
+    if !db.is_null() {
+        drop(Box::from_raw(db));
+    }
+    SQLITE_OK
+}
+
+/// Returns the result code of the most recent API call on `db`.
+#[no_mangle]
+pub unsafe extern "C" fn sqlite3_errcode(db: *mut sqlite3) -> c_int {
+    match db.as_ref() {
+        Some(db) => db.errcode,
+        None => SQLITE_NOMEM,
+    }
+}
+
+/// Returns the English description of the most recent error on `db`.
+/// The string is owned by `db` and valid until the next API call on it.
+#[no_mangle]
+pub unsafe extern "C" fn sqlite3_errmsg(db: *mut sqlite3) -> *const c_char {
+    match db.as_ref() {
+        Some(db) => db.errmsg.as_ptr(),
+        None => b"out of memory\0".as_ptr() as *const c_char,
+    }
+}
+
+/// Returns the rowid of the last row inserted through `db`, 0 if there is none.
+#[no_mangle]
+pub unsafe extern "C" fn sqlite3_last_insert_rowid(db: *mut sqlite3) -> i64 {
+    db.as_ref().map_or(0, |db| db.conn.last_insert_rowid())
+}
+
+/// Returns the number of rows inserted, updated or deleted by the last statement doing so.
+#[no_mangle]
+pub unsafe extern "C" fn sqlite3_changes(db: *mut sqlite3) -> c_int {
+    db.as_ref().map_or(0, |db| db.conn.changes() as c_int)
+}
+
+/// Returns the number of rows inserted, updated or deleted since `db` was opened.
+#[no_mangle]
+pub unsafe extern "C" fn sqlite3_total_changes(db: *mut sqlite3) -> c_int {
+    db.as_ref().map_or(0, |db| db.conn.total_changes() as c_int)
+}
+
+/// Signature of the callback invoked by `sqlite3_exec` for every result row.
+pub type sqlite3_callback = Option<
+    unsafe extern "C" fn(
+        arg: *mut c_void,
+        n_cols: c_int,
+        values: *mut *mut c_char,
+        names: *mut *mut c_char,
+    ) -> c_int,
+>;
+
+/// Runs a single SQL statement, calling `callback` for every result row.
+/// On error, and if `errmsg` is not null, an error message to be released with
+/// `sqlite3_free` is stored in it.
+#[no_mangle]
+pub unsafe extern "C" fn sqlite3_exec(
+    db: *mut sqlite3,
+    sql: *const c_char,
+    callback: sqlite3_callback,
+    arg: *mut c_void,
+    errmsg: *mut *mut c_char,
+) -> c_int {
+    let db = match db.as_mut() {
+        Some(db) => db,
+        None => return SQLITE_MISUSE,
+    };
+    let sql = match str_from_raw(sql) {
+        Some(sql) => sql,
+        None => return SQLITE_MISUSE,
+    };
+    if !errmsg.is_null() {
+        *errmsg = ptr::null_mut();
+    }
+
+    let result = exec(&mut db.conn, sql, callback, arg);
+    let code = db.set_result(&result);
+    if code != SQLITE_OK && !errmsg.is_null() {
+        *errmsg = db.errmsg.clone().into_raw();
+    }
+    code
+}
+
+unsafe fn exec(
+    conn: &mut Connection,
+    sql: &str,
+    callback: sqlite3_callback,
+    arg: *mut c_void,
+) -> Result<()> {
+    if returns_rows(sql)? {
+        let result = process_query(sql, conn.database())?;
+        if let Some(callback) = callback {
+            let mut names = result
+                .columns
+                .iter()
+                .map(|col| CString::new(col.name.replace('\0', "")).unwrap().into_raw())
+                .collect::<Vec<*mut c_char>>();
+            let mut aborted = false;
+            for row in &result.rows {
+                let mut values = row
+                    .iter()
+                    .map(|value| match value {
+                        Value::Null => ptr::null_mut(),
+                        value => CString::new(value.to_string().replace('\0', ""))
+                            .unwrap()
+                            .into_raw(),
+                    })
+                    .collect::<Vec<*mut c_char>>();
+                let code = callback(
+                    arg,
+                    names.len() as c_int,
+                    values.as_mut_ptr(),
+                    names.as_mut_ptr(),
+                );
+                for value in values.into_iter().filter(|v| !v.is_null()) {
+                    drop(CString::from_raw(value));
+                }
+                if code != 0 {
+                    aborted = true;
+                    break;
+                }
+            }
+            for name in names {
+                drop(CString::from_raw(name));
+            }
+            if aborted {
+                return Err(SQLRiteError::General("query aborted".to_string()));
+            }
+        }
+    } else {
+        conn.execute(sql)?;
+    }
+    Ok(())
+}
+
+/// Releases memory allocated by this API, like the error messages of `sqlite3_exec`.
+#[no_mangle]
+pub unsafe extern "C" fn sqlite3_free(p: *mut c_void) {
+    if !p.is_null() {
+        drop(CString::from_raw(p as *mut c_char));
+    }
+}
+
+/// Compiles the first `n_byte` bytes of `sql`, or all of it up to the nul terminator when
+/// `n_byte` is negative, into a prepared statement. `pz_tail`, when not null, is set to
+/// the end of the compiled SQL.
+#[no_mangle]
+pub unsafe extern "C" fn sqlite3_prepare_v2(
+    db: *mut sqlite3,
+    sql: *const c_char,
+    n_byte: c_int,
+    pp_stmt: *mut *mut sqlite3_stmt,
+    pz_tail: *mut *const c_char,
+) -> c_int {
+    if pp_stmt.is_null() {
+        return SQLITE_MISUSE;
+    }
+    *pp_stmt = ptr::null_mut();
+    let db_ref = match db.as_mut() {
+        Some(db) => db,
+        None => return SQLITE_MISUSE,
+    };
+    if sql.is_null() {
+        return SQLITE_MISUSE;
+    }
+
+    let bytes = if n_byte < 0 {
+        CStr::from_ptr(sql).to_bytes()
+    } else {
+        let len = n_byte as usize;
+        let bytes = std::slice::from_raw_parts(sql as *const u8, len);
+        // The SQL also ends at a nul terminator found before n_byte bytes
+        match bytes.iter().position(|b| *b == 0) {
+            Some(end) => &bytes[..end],
+            None => bytes,
+        }
+    };
+    if !pz_tail.is_null() {
+        *pz_tail = sql.add(bytes.len());
+    }
+
+    let result = std::str::from_utf8(bytes)
+        .map_err(|_| SQLRiteError::General("SQL is not valid UTF-8".to_string()))
+        .and_then(|sql| {
+            Ok(sqlite3_stmt {
+                db,
+                sql: sql.to_string(),
+                params: vec![Value::Null; parameter_count(sql)?],
+                is_query: returns_rows(sql)?,
+                state: StepState::Ready,
+                names: vec![],
+                texts: vec![],
+            })
+        });
+    let code = db_ref.set_result(&result);
+    if let Ok(stmt) = result {
+        *pp_stmt = Box::into_raw(Box::new(stmt));
+    }
+    code
+}
+
+/// Runs a prepared statement until its next result row, returning `SQLITE_ROW`,
+/// or until it completes, returning `SQLITE_DONE`.
+#[no_mangle]
+pub unsafe extern "C" fn sqlite3_step(stmt: *mut sqlite3_stmt) -> c_int {
+    let stmt = match stmt.as_mut() {
+        Some(stmt) => stmt,
+        None => return SQLITE_MISUSE,
+    };
+    let db = match stmt.db.as_mut() {
+        Some(db) => db,
+        None => return SQLITE_MISUSE,
+    };
+    let result = stmt.step(&mut db.conn);
+    match result {
+        Ok(code) => {
+            db.set_result(&Ok(()));
+            code
+        }
+        Err(_) => {
+            stmt.state = StepState::Done;
+            db.set_result(&result)
+        }
+    }
+}
+
+/// Resets a prepared statement so it can be run again, keeping its bound parameters.
+#[no_mangle]
+pub unsafe extern "C" fn sqlite3_reset(stmt: *mut sqlite3_stmt) -> c_int {
+    match stmt.as_mut() {
+        Some(stmt) => {
+            stmt.state = StepState::Ready;
+            stmt.texts.clear();
+            SQLITE_OK
+        }
+        None => SQLITE_OK,
+    }
+}
+
+/// Sets every parameter of a prepared statement back to NULL.
+#[no_mangle]
+pub unsafe extern "C" fn sqlite3_clear_bindings(stmt: *mut sqlite3_stmt) -> c_int {
+    if let Some(stmt) = stmt.as_mut() {
+        for param in stmt.params.iter_mut() {
+            *param = Value::Null;
+        }
+    }
+    SQLITE_OK
+}
+
+/// Destroys a prepared statement.
+#[no_mangle]
+pub unsafe extern "C" fn sqlite3_finalize(stmt: *mut sqlite3_stmt) -> c_int {
+    if !stmt.is_null() {
+        drop(Box::from_raw(stmt));
+    }
+    SQLITE_OK
+}
+
+/// Returns the number of parameters of a prepared statement.
+#[no_mangle]
+pub unsafe extern "C" fn sqlite3_bind_parameter_count(stmt: *mut sqlite3_stmt) -> c_int {
+    match stmt.as_ref() {
+        Some(stmt) => stmt.params.len() as c_int,
+        None => 0,
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn sqlite3_bind_null(stmt: *mut sqlite3_stmt, index: c_int) -> c_int {
+    match stmt.as_mut() {
+        Some(stmt) => stmt.bind(index, Value::Null),
+        None => SQLITE_MISUSE,
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn sqlite3_bind_int(
+    stmt: *mut sqlite3_stmt,
+    index: c_int,
+    value: c_int,
+) -> c_int {
+    sqlite3_bind_int64(stmt, index, i64::from(value))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn sqlite3_bind_int64(
+    stmt: *mut sqlite3_stmt,
+    index: c_int,
+    value: i64,
+) -> c_int {
+    match stmt.as_mut() {
+        Some(stmt) => stmt.bind(index, Value::Integer(value)),
+        None => SQLITE_MISUSE,
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn sqlite3_bind_double(
+    stmt: *mut sqlite3_stmt,
+    index: c_int,
+    value: c_double,
+) -> c_int {
+    match stmt.as_mut() {
+        Some(stmt) => stmt.bind(index, Value::Real(value)),
+        None => SQLITE_MISUSE,
+    }
+}
+
+/// Binds `n` bytes of UTF-8 text, or all of it up to the nul terminator when `n` is negative.
+/// The text is always copied, whatever `destructor` is.
+#[no_mangle]
+pub unsafe extern "C" fn sqlite3_bind_text(
+    stmt: *mut sqlite3_stmt,
+    index: c_int,
+    text: *const c_char,
+    n: c_int,
+    _destructor: isize,
+) -> c_int {
+    let stmt = match stmt.as_mut() {
+        Some(stmt) => stmt,
+        None => return SQLITE_MISUSE,
+    };
+    if text.is_null() {
+        return stmt.bind(index, Value::Null);
+    }
+    let bytes = if n < 0 {
+        CStr::from_ptr(text).to_bytes()
+    } else {
+        std::slice::from_raw_parts(text as *const u8, n as usize)
+    };
+    let text = String::from_utf8_lossy(bytes).into_owned();
+    stmt.bind(index, Value::Text(text))
+}
+
+/// Returns the number of columns in the result of a prepared statement.
+#[no_mangle]
+pub unsafe extern "C" fn sqlite3_column_count(stmt: *mut sqlite3_stmt) -> c_int {
+    match stmt.as_ref() {
+        Some(stmt) => stmt.column_count() as c_int,
+        None => 0,
+    }
+}
+
+/// Returns the name of result column `col`, available once the statement has been stepped.
+#[no_mangle]
+pub unsafe extern "C" fn sqlite3_column_name(stmt: *mut sqlite3_stmt, col: c_int) -> *const c_char {
+    match stmt
+        .as_ref()
+        .and_then(|stmt| stmt.names.get(usize::try_from(col).ok()?))
+    {
+        Some(name) => name.as_ptr(),
+        None => ptr::null(),
+    }
+}
+
+/// Returns the storage class of column `col` of the current row.
+#[no_mangle]
+pub unsafe extern "C" fn sqlite3_column_type(stmt: *mut sqlite3_stmt, col: c_int) -> c_int {
+    match stmt.as_ref().and_then(|stmt| stmt.current_value(col)) {
+        Some(Value::Integer(_)) | Some(Value::Bool(_)) => SQLITE_INTEGER,
+        Some(Value::Real(_)) => SQLITE_FLOAT,
+        Some(Value::Text(_)) => SQLITE_TEXT,
+        Some(Value::Blob(_)) => SQLITE_BLOB,
+        Some(Value::Null) | None => SQLITE_NULL,
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn sqlite3_column_int64(stmt: *mut sqlite3_stmt, col: c_int) -> i64 {
+    match stmt.as_ref().and_then(|stmt| stmt.current_value(col)) {
+        Some(Value::Integer(v)) => *v,
+        Some(Value::Real(v)) => *v as i64,
+        Some(Value::Bool(v)) => i64::from(*v),
+        Some(Value::Text(v)) => v.trim().parse::<i64>().unwrap_or(0),
+        Some(value @ Value::Blob(_)) => value.to_integer().unwrap_or(0),
+        Some(Value::Null) | None => 0,
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn sqlite3_column_int(stmt: *mut sqlite3_stmt, col: c_int) -> c_int {
+    sqlite3_column_int64(stmt, col) as c_int
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn sqlite3_column_double(stmt: *mut sqlite3_stmt, col: c_int) -> c_double {
+    match stmt.as_ref().and_then(|stmt| stmt.current_value(col)) {
+        Some(Value::Integer(v)) => *v as f64,
+        Some(Value::Real(v)) => *v,
+        Some(Value::Bool(v)) => f64::from(u8::from(*v)),
+        Some(Value::Text(v)) => v.trim().parse::<f64>().unwrap_or(0.0),
+        Some(value @ Value::Blob(_)) => value.to_real().unwrap_or(0.0),
+        Some(Value::Null) | None => 0.0,
+    }
+}
+
+/// Returns column `col` of the current row as nul-terminated UTF-8 text, or null for NULL.
+/// The text is owned by the statement and valid until it is stepped, reset or finalized.
+#[no_mangle]
+pub unsafe extern "C" fn sqlite3_column_text(stmt: *mut sqlite3_stmt, col: c_int) -> *const c_char {
+    let stmt = match stmt.as_mut() {
+        Some(stmt) => stmt,
+        None => return ptr::null(),
+    };
+    let text = match stmt.current_value(col) {
+        Some(Value::Null) | None => return ptr::null(),
+        Some(value) => value.to_string(),
+    };
+    let slot = &mut stmt.texts[col as usize];
+    if slot.is_none() {
+        *slot = Some(CString::new(text.replace('\0', "")).unwrap());
+    }
+    slot.as_ref().unwrap().as_ptr()
+}
+
+/// Returns the bytes of a blob in column `col` of the current row, other values are
+/// returned as their text. Null for NULL and for empty blobs.
+/// The bytes are owned by the statement and valid until it is stepped, reset or finalized.
+#[no_mangle]
+pub unsafe extern "C" fn sqlite3_column_blob(stmt: *mut sqlite3_stmt, col: c_int) -> *const c_void {
+    match stmt.as_ref().and_then(|stmt| stmt.current_value(col)) {
+        Some(Value::Blob(v)) if v.is_empty() => ptr::null(),
+        Some(Value::Blob(v)) => v.as_ptr() as *const c_void,
+        _ => sqlite3_column_text(stmt, col) as *const c_void,
+    }
+}
+
+/// Returns the length in bytes of the blob or text of column `col` of the current row.
+#[no_mangle]
+pub unsafe extern "C" fn sqlite3_column_bytes(stmt: *mut sqlite3_stmt, col: c_int) -> c_int {
+    if let Some(Value::Blob(v)) = stmt.as_ref().and_then(|stmt| stmt.current_value(col)) {
+        return v.len() as c_int;
+    }
+    let text = sqlite3_column_text(stmt, col);
+    if text.is_null() {
+        return 0;
+    }
+    CStr::from_ptr(text).to_bytes().len() as c_int
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn c(s: &str) -> CString {
+        CString::new(s).unwrap()
+    }
+
+    unsafe fn open() -> *mut sqlite3 {
+        let mut db: *mut sqlite3 = ptr::null_mut();
+        assert_eq!(sqlite3_open(c("test.db").as_ptr(), &mut db), SQLITE_OK);
+        assert!(!db.is_null());
+        db
+    }
+
+    unsafe fn prepare(db: *mut sqlite3, sql: &str) -> *mut sqlite3_stmt {
+        let mut stmt: *mut sqlite3_stmt = ptr::null_mut();
+        let code = sqlite3_prepare_v2(db, c(sql).as_ptr(), -1, &mut stmt, ptr::null_mut());
+        assert_eq!(code, SQLITE_OK);
+        stmt
+    }
+
+    #[test]
+    fn ffi_prepare_bind_step_test() {
+        unsafe {
+            let db = open();
+            let errmsg: *mut *mut c_char = ptr::null_mut();
+            let sql = c("CREATE TABLE users (id INTEGER PRIMARY KEY, name TEXT, score REAL);");
+            assert_eq!(
+                sqlite3_exec(db, sql.as_ptr(), None, ptr::null_mut(), errmsg),
+                SQLITE_OK
+            );
+
+            let insert = prepare(db, "INSERT INTO users (name, score) VALUES (?, ?);");
+            assert_eq!(sqlite3_bind_parameter_count(insert), 2);
+            for (name, score) in &[("josh", 9.5), ("o'neil", 7.0)] {
+                let name = c(name);
+                assert_eq!(
+                    sqlite3_bind_text(insert, 1, name.as_ptr(), -1, SQLITE_TRANSIENT),
+                    SQLITE_OK
+                );
+                assert_eq!(sqlite3_bind_double(insert, 2, *score), SQLITE_OK);
+                assert_eq!(sqlite3_step(insert), SQLITE_DONE);
+                assert_eq!(sqlite3_reset(insert), SQLITE_OK);
+            }
+            assert_eq!(sqlite3_bind_int(insert, 3, 1), SQLITE_RANGE);
+            assert_eq!(sqlite3_finalize(insert), SQLITE_OK);
+            assert_eq!(sqlite3_last_insert_rowid(db), 2);
+            assert_eq!(sqlite3_changes(db), 1);
+            assert_eq!(sqlite3_total_changes(db), 2);
+
+            let select = prepare(db, "SELECT id, name, score FROM users;");
+            assert_eq!(sqlite3_step(select), SQLITE_ROW);
+            assert_eq!(sqlite3_column_count(select), 3);
+            assert_eq!(
+                CStr::from_ptr(sqlite3_column_name(select, 1)).to_str(),
+                Ok("name")
+            );
+            assert_eq!(sqlite3_column_type(select, 0), SQLITE_INTEGER);
+            assert_eq!(sqlite3_column_int(select, 0), 1);
+            assert_eq!(
+                CStr::from_ptr(sqlite3_column_text(select, 1)).to_str(),
+                Ok("josh")
+            );
+            assert_eq!(sqlite3_column_bytes(select, 1), 4);
+            assert_eq!(sqlite3_column_double(select, 2), 9.5);
+
+            assert_eq!(sqlite3_step(select), SQLITE_ROW);
+            assert_eq!(
+                CStr::from_ptr(sqlite3_column_text(select, 1)).to_str(),
+                Ok("o'neil")
+            );
+            assert_eq!(sqlite3_step(select), SQLITE_DONE);
+            assert_eq!(sqlite3_column_type(select, 0), SQLITE_NULL);
+            assert_eq!(sqlite3_finalize(select), SQLITE_OK);
+
+            // ?NNN placeholders bind to the parameter they number
+            let insert = prepare(db, "INSERT INTO users (score, name) VALUES (?2, ?1);");
+            assert_eq!(sqlite3_bind_parameter_count(insert), 2);
+            let name = c("ana");
+            assert_eq!(
+                sqlite3_bind_text(insert, 1, name.as_ptr(), -1, SQLITE_TRANSIENT),
+                SQLITE_OK
+            );
+            assert_eq!(sqlite3_bind_double(insert, 2, 4.5), SQLITE_OK);
+            assert_eq!(sqlite3_step(insert), SQLITE_DONE);
+            assert_eq!(sqlite3_finalize(insert), SQLITE_OK);
+            let select = prepare(db, "SELECT name, score FROM users WHERE id = ?3;");
+            assert_eq!(sqlite3_bind_parameter_count(select), 3);
+            assert_eq!(sqlite3_bind_int(select, 3, 3), SQLITE_OK);
+            assert_eq!(sqlite3_step(select), SQLITE_ROW);
+            assert_eq!(
+                CStr::from_ptr(sqlite3_column_text(select, 0)).to_str(),
+                Ok("ana")
+            );
+            assert_eq!(sqlite3_column_double(select, 1), 4.5);
+            assert_eq!(sqlite3_finalize(select), SQLITE_OK);
+
+            assert_eq!(sqlite3_close(db), SQLITE_OK);
+        }
+    }
+
+    #[test]
+    fn ffi_errmsg_test() {
+        unsafe {
+            let db = open();
+            let mut stmt: *mut sqlite3_stmt = ptr::null_mut();
+            let sql = c("SELECT * FROM;");
+            let code = sqlite3_prepare_v2(db, sql.as_ptr(), -1, &mut stmt, ptr::null_mut());
+            assert_eq!(code, SQLITE_ERROR);
+            assert!(stmt.is_null());
+            assert_eq!(sqlite3_errcode(db), SQLITE_ERROR);
+            let errmsg = CStr::from_ptr(sqlite3_errmsg(db)).to_str().unwrap();
+            assert!(errmsg.starts_with("SQL error"));
+
+            let mut errmsg: *mut c_char = ptr::null_mut();
+            let sql = c("INSERT INTO missing (id) VALUES (1);");
+            let code = sqlite3_exec(db, sql.as_ptr(), None, ptr::null_mut(), &mut errmsg);
+            assert_eq!(code, SQLITE_ERROR);
+            assert!(!errmsg.is_null());
+            sqlite3_free(errmsg as *mut c_void);
+
+            assert_eq!(sqlite3_close(db), SQLITE_OK);
+        }
+    }
+
+    unsafe extern "C" fn collect_names(
+        arg: *mut c_void,
+        n_cols: c_int,
+        values: *mut *mut c_char,
+        _names: *mut *mut c_char,
+    ) -> c_int {
+        let names = &mut *(arg as *mut Vec<String>);
+        let values = std::slice::from_raw_parts(values, n_cols as usize);
+        names.push(CStr::from_ptr(values[0]).to_str().unwrap().to_string());
+        0
+    }
+
+    #[test]
+    fn ffi_exec_callback_test() {
+        unsafe {
+            let db = open();
+            for sql in &[
+                "CREATE TABLE users (id INTEGER PRIMARY KEY, name TEXT);",
+                "INSERT INTO users (name) VALUES ('josh');",
+                "INSERT INTO users (name) VALUES ('mary');",
+            ] {
+                let sql = c(sql);
+                let code = sqlite3_exec(db, sql.as_ptr(), None, ptr::null_mut(), ptr::null_mut());
+                assert_eq!(code, SQLITE_OK);
+            }
+
+            let mut names: Vec<String> = vec![];
+            let sql = c("SELECT name FROM users;");
+            let code = sqlite3_exec(
+                db,
+                sql.as_ptr(),
+                Some(collect_names),
+                &mut names as *mut Vec<String> as *mut c_void,
+                ptr::null_mut(),
+            );
+            assert_eq!(code, SQLITE_OK);
+            assert_eq!(names, vec!["josh", "mary"]);
+
+            // PRAGMAs return rows too, here the cid of each column
+            let mut cids: Vec<String> = vec![];
+            let sql = c("PRAGMA table_info(users);");
+            let code = sqlite3_exec(
+                db,
+                sql.as_ptr(),
+                Some(collect_names),
+                &mut cids as *mut Vec<String> as *mut c_void,
+                ptr::null_mut(),
+            );
+            assert_eq!(code, SQLITE_OK);
+            assert_eq!(cids, vec!["0", "1"]);
+
+            assert_eq!(sqlite3_close(db), SQLITE_OK);
+        }
+    }
+}
@@ -0,0 +1,965 @@
+//! Import of the SQL dumps written by mysqldump and pg_dump. Only the common subset of their
+//! output is understood: CREATE TABLE, with the types of the columns translated to the
+//! closest SQLRite type, the rows of INSERT statements, multi-row ones included, and of the
+//! COPY blocks of pg_dump. Identifiers can be quoted with backticks or double quotes, and
+//! strings escaped the way the dialect of the dump does. The other statements, SET, LOCK
+//! TABLES, CREATE SEQUENCE, GRANT and the like, have no equivalent and are left out.
+use crate::error::{Result, SQLRiteError};
+use crate::import::{identifier, insert_batch, ImportReport};
+use crate::sql::db::database::Database;
+use crate::sql::db::table::DataType;
+use crate::sql::process_command;
+use crate::sql::value::Value;
+
+/// The database a dump comes from, which tells how its strings escape characters
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Dialect {
+    /// Backslash escapes in every string
+    MySql,
+    /// Quotes doubled in strings, backslash escapes only in E'...' strings, and $$ quoting
+    Postgres,
+}
+
+impl Dialect {
+    /// The dialect of `text`, PostgreSQL if pg_dump wrote it, MySQL otherwise.
+    fn detect(text: &str) -> Dialect {
+        let postgres = text.contains("PostgreSQL database dump")
+            || text.contains("SET standard_conforming_strings")
+            || text.lines().any(|line| line.starts_with("COPY "));
+        if postgres {
+            Dialect::Postgres
+        } else {
+            Dialect::MySql
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Word(String),
+    /// An identifier quoted with backticks or double quotes
+    Quoted(String),
+    /// A string literal, its escapes decoded
+    Str(String),
+    Number(String),
+    Punct(char),
+}
+
+/// A statement of the dump, with the line each of its tokens is on, and the lines of data
+/// following it for a COPY ... FROM stdin
+#[derive(Debug, Default)]
+struct Statement {
+    tokens: Vec<Token>,
+    lines: Vec<usize>,
+    data: Vec<(usize, String)>,
+}
+
+impl Statement {
+    fn push(&mut self, token: Token, line: usize) {
+        self.tokens.push(token);
+        self.lines.push(line);
+    }
+
+    fn is_copy_from_stdin(&self) -> bool {
+        let word = |token: &Token, word: &str| match token {
+            Token::Word(w) => w.eq_ignore_ascii_case(word),
+            _ => false,
+        };
+        self.tokens.first().is_some_and(|token| word(token, "COPY"))
+            && self
+                .tokens
+                .windows(2)
+                .any(|pair| word(&pair[0], "FROM") && word(&pair[1], "stdin"))
+    }
+}
+
+/// Imports the dump `text`, creating its tables and inserting their rows. A statement or a
+/// row that can't be imported is skipped and reported by the line it starts on.
+pub fn import_dump(db: &mut Database, text: &str) -> Result<ImportReport> {
+    let statements = statements(text, Dialect::detect(text))?;
+    // pg_dump adds the keys of a table after its rows, SQLRite needs them when it is created
+    let keys = statements
+        .iter()
+        .filter_map(|statement| added_key(&mut Cursor::new(statement)))
+        .collect::<Vec<Key>>();
+    let mut report = ImportReport::default();
+    for statement in &statements {
+        let mut cursor = Cursor::new(statement);
+        let result = if cursor.keyword("CREATE") && cursor.keyword("TABLE") {
+            create_table(db, &mut cursor, &keys, &mut report)
+        } else if cursor.keyword("INSERT") {
+            insert(db, &mut cursor, &mut report)
+        } else if cursor.keyword("COPY") {
+            copy(db, &mut cursor, &statement.data, &mut report)
+        } else {
+            if added_key(&mut Cursor::new(statement)).is_none() {
+                report.ignored_statements += 1;
+            }
+            Ok(())
+        };
+        match result {
+            Ok(()) => {}
+            Err(SQLRiteError::Interrupted) => return Err(SQLRiteError::Interrupted),
+            Err(err) => report.skipped.push((statement.lines[0], err.to_string())),
+        }
+    }
+    Ok(report)
+}
+
+/// Splits `text` into its statements, comments left out.
+fn statements(text: &str, dialect: Dialect) -> Result<Vec<Statement>> {
+    let chars = text.chars().collect::<Vec<char>>();
+    let mut statements = vec![];
+    let mut statement = Statement::default();
+    let mut line = 1;
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        let next = chars.get(i + 1).copied();
+        let start = line;
+        match c {
+            '\n' => {
+                line += 1;
+                i += 1;
+            }
+            c if c.is_whitespace() => i += 1,
+            '-' if next == Some('-') => i = end_of_line(&chars, i),
+            '#' if dialect == Dialect::MySql => i = end_of_line(&chars, i),
+            '/' if next == Some('*') => {
+                let end = find(&chars, i + 2, &['*', '/']).ok_or_else(|| {
+                    SQLRiteError::General(format!("unterminated comment on line {}", start))
+                })?;
+                line += chars[i..end].iter().filter(|&&c| c == '\n').count();
+                i = end + 2;
+            }
+            ';' => {
+                i += 1;
+                if statement.tokens.is_empty() {
+                    continue;
+                }
+                if statement.is_copy_from_stdin() {
+                    // The rows follow, a line each, up to a line with \.
+                    i = end_of_line(&chars, i) + 1;
+                    line += 1;
+                    loop {
+                        if i >= chars.len() {
+                            return Err(SQLRiteError::General(format!(
+                                "unterminated COPY data on line {}",
+                                start
+                            )));
+                        }
+                        let end = end_of_line(&chars, i);
+                        let row = chars[i..end].iter().collect::<String>();
+                        let row = row.strip_suffix('\r').unwrap_or(&row);
+                        i = end + 1;
+                        line += 1;
+                        if row == "\\." {
+                            break;
+                        }
+                        statement.data.push((line - 1, row.to_string()));
+                    }
+                }
+                statements.push(std::mem::take(&mut statement));
+            }
+            '\'' => {
+                let (s, end) = string(&chars, i, dialect == Dialect::MySql, &mut line)?;
+                statement.push(Token::Str(s), start);
+                i = end;
+            }
+            '`' | '"' => {
+                let (s, end) = string(&chars, i, false, &mut line)?;
+                statement.push(Token::Quoted(s), start);
+                i = end;
+            }
+            '$' if dialect == Dialect::Postgres => {
+                let tag_end = (i + 1..chars.len())
+                    .find(|&j| !(chars[j].is_alphanumeric() || chars[j] == '_'))
+                    .filter(|&j| chars[j] == '$');
+                match tag_end {
+                    Some(tag_end) => {
+                        let tag = &chars[i..=tag_end];
+                        let end = find(&chars, tag_end + 1, tag).ok_or_else(|| {
+                            SQLRiteError::General(format!("unterminated string on line {}", start))
+                        })?;
+                        let s = chars[tag_end + 1..end].iter().collect::<String>();
+                        line += s.matches('\n').count();
+                        statement.push(Token::Str(s), start);
+                        i = end + tag.len();
+                    }
+                    None => {
+                        statement.push(Token::Punct(c), start);
+                        i += 1;
+                    }
+                }
+            }
+            c if c.is_ascii_digit() || (c == '.' && next.is_some_and(|n| n.is_ascii_digit())) => {
+                let mut end = i + 1;
+                while end < chars.len()
+                    && (chars[end].is_ascii_alphanumeric()
+                        || chars[end] == '.'
+                        || (['+', '-'].contains(&chars[end])
+                            && ['e', 'E'].contains(&chars[end - 1])))
+                {
+                    end += 1;
+                }
+                statement.push(Token::Number(chars[i..end].iter().collect()), start);
+                i = end;
+            }
+            c if c.is_alphabetic() || c == '_' => {
+                let mut end = i + 1;
+                while end < chars.len()
+                    && (chars[end].is_alphanumeric() || "_$".contains(chars[end]))
+                {
+                    end += 1;
+                }
+                let word = chars[i..end].iter().collect::<String>();
+                if dialect == Dialect::Postgres
+                    && word.eq_ignore_ascii_case("E")
+                    && chars.get(end) == Some(&'\'')
+                {
+                    let (s, end) = string(&chars, end, true, &mut line)?;
+                    statement.push(Token::Str(s), start);
+                    i = end;
+                } else {
+                    statement.push(Token::Word(word), start);
+                    i = end;
+                }
+            }
+            c => {
+                statement.push(Token::Punct(c), start);
+                i += 1;
+            }
+        }
+    }
+    if !statement.tokens.is_empty() {
+        statements.push(statement);
+    }
+    Ok(statements)
+}
+
+/// The position of the end of the line `i` is on.
+fn end_of_line(chars: &[char], i: usize) -> usize {
+    (i..chars.len())
+        .find(|&j| chars[j] == '\n')
+        .unwrap_or(chars.len())
+}
+
+/// The position of the first `pattern` in `chars` from `from`.
+fn find(chars: &[char], from: usize, pattern: &[char]) -> Option<usize> {
+    (from..chars.len()).find(|&j| chars[j..].starts_with(pattern))
+}
+
+/// The string quoted from `i`, with its quotes doubled inside it undoubled and, if
+/// `escapes`, its backslash escapes decoded, and the position after its closing quote.
+fn string(chars: &[char], i: usize, escapes: bool, line: &mut usize) -> Result<(String, usize)> {
+    let quote = chars[i];
+    let start = *line;
+    let mut s = String::new();
+    let mut j = i + 1;
+    while j < chars.len() {
+        let c = chars[j];
+        if c == '\n' {
+            *line += 1;
+        }
+        if c == quote {
+            if chars.get(j + 1) == Some(&quote) {
+                s.push(quote);
+                j += 2;
+                continue;
+            }
+            return Ok((s, j + 1));
+        }
+        if c == '\\' && escapes && j + 1 < chars.len() {
+            s.push(match chars[j + 1] {
+                '0' => '\0',
+                'b' => '\x08',
+                'n' => '\n',
+                'r' => '\r',
+                't' => '\t',
+                'Z' => '\x1a',
+                c => c,
+            });
+            j += 2;
+            continue;
+        }
+        s.push(c);
+        j += 1;
+    }
+    Err(SQLRiteError::General(format!(
+        "unterminated string on line {}",
+        start
+    )))
+}
+
+/// Reads the tokens of a statement
+struct Cursor<'a> {
+    statement: &'a Statement,
+    position: usize,
+}
+
+impl<'a> Cursor<'a> {
+    fn new(statement: &'a Statement) -> Self {
+        Cursor {
+            statement,
+            position: 0,
+        }
+    }
+
+    fn peek(&self) -> Option<&'a Token> {
+        self.statement.tokens.get(self.position)
+    }
+
+    fn next(&mut self) -> Option<&'a Token> {
+        let token = self.peek();
+        self.position += 1;
+        token
+    }
+
+    /// The line of the next token.
+    fn line(&self) -> usize {
+        let lines = &self.statement.lines;
+        lines[self.position.min(lines.len() - 1)]
+    }
+
+    /// Reads the next token if it is the keyword `keyword`.
+    fn keyword(&mut self, keyword: &str) -> bool {
+        match self.peek() {
+            Some(Token::Word(word)) if word.eq_ignore_ascii_case(keyword) => {
+                self.position += 1;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Reads the next token if it is `c`.
+    fn punct(&mut self, c: char) -> bool {
+        if self.peek() == Some(&Token::Punct(c)) {
+            self.position += 1;
+            return true;
+        }
+        false
+    }
+
+    fn expect(&mut self, c: char) -> Result<()> {
+        if self.punct(c) {
+            return Ok(());
+        }
+        Err(SQLRiteError::General(format!(
+            "expected {} on line {}",
+            c,
+            self.line()
+        )))
+    }
+
+    /// Reads a name, dropping the schema it is qualified with, as the identifier it is
+    /// given in SQLRite.
+    fn name(&mut self) -> Result<String> {
+        while let Some(Token::Word(name)) | Some(Token::Quoted(name)) = self.next() {
+            if !self.punct('.') {
+                return Ok(identifier(name));
+            }
+        }
+        Err(SQLRiteError::General(format!(
+            "expected a name on line {}",
+            self.line()
+        )))
+    }
+
+    /// Reads a list of names in parentheses.
+    fn names(&mut self) -> Result<Vec<String>> {
+        self.expect('(')?;
+        let mut names = vec![self.name()?];
+        while self.punct(',') {
+            names.push(self.name()?);
+        }
+        self.expect(')')?;
+        Ok(names)
+    }
+
+    /// Reads tokens up to the next `,` or `)` outside of parentheses.
+    fn item(&mut self) -> &'a [Token] {
+        let start = self.position;
+        let mut depth = 0;
+        while let Some(token) = self.peek() {
+            match token {
+                Token::Punct('(') => depth += 1,
+                Token::Punct(')') if depth == 0 => break,
+                Token::Punct(')') => depth -= 1,
+                Token::Punct(',') if depth == 0 => break,
+                _ => {}
+            }
+            self.position += 1;
+        }
+        &self.statement.tokens[start..self.position]
+    }
+}
+
+/// A PRIMARY KEY or UNIQUE constraint added to a column of a table by ALTER TABLE
+#[derive(Debug)]
+struct Key {
+    table_name: String,
+    column: String,
+    primary: bool,
+}
+
+/// The key added by `ALTER TABLE [ONLY] t ADD CONSTRAINT c PRIMARY KEY|UNIQUE (column)`,
+/// the way pg_dump adds them.
+fn added_key(cursor: &mut Cursor) -> Option<Key> {
+    if !(cursor.keyword("ALTER") && cursor.keyword("TABLE")) {
+        return None;
+    }
+    cursor.keyword("ONLY");
+    let table_name = cursor.name().ok()?;
+    if !(cursor.keyword("ADD") && cursor.keyword("CONSTRAINT")) {
+        return None;
+    }
+    cursor.name().ok()?;
+    let primary = cursor.keyword("PRIMARY") && cursor.keyword("KEY");
+    if !primary && !cursor.keyword("UNIQUE") {
+        return None;
+    }
+    match cursor.names().ok()?.as_slice() {
+        [column] => Some(Key {
+            table_name,
+            column: column.to_string(),
+            primary,
+        }),
+        _ => None,
+    }
+}
+
+/// The first keywords of the definitions of a CREATE TABLE that are indexes, or constraints
+/// SQLRite can't enforce
+const INDEXES: [&str; 7] = [
+    "KEY", "INDEX", "FULLTEXT", "SPATIAL", "FOREIGN", "CHECK", "EXCLUDE",
+];
+
+/// The SQLRite type of a column declared with the type `name`, of arguments `args`.
+fn column_type(name: &str, args: &[Token]) -> &'static str {
+    match name.to_lowercase().as_str() {
+        "tinyint" if args == [Token::Number("1".to_string())] => "BOOL",
+        "bool" | "boolean" => "BOOL",
+        "int" | "integer" | "tinyint" | "smallint" | "mediumint" | "bigint" | "int2" | "int4"
+        | "int8" | "serial" | "smallserial" | "bigserial" | "serial4" | "serial8" | "year" => {
+            "INTEGER"
+        }
+        "float" | "double" | "real" | "decimal" | "dec" | "numeric" | "fixed" | "float4"
+        | "float8" => "REAL",
+        _ => "TEXT",
+    }
+}
+
+/// Creates the table of `CREATE TABLE [IF NOT EXISTS] t (...)`, with the columns, their
+/// types and their PRIMARY KEY, UNIQUE and NOT NULL constraints. Keys of several columns,
+/// indexes, foreign keys, defaults and the options of the table are left out.
+fn create_table(
+    db: &mut Database,
+    cursor: &mut Cursor,
+    keys: &[Key],
+    report: &mut ImportReport,
+) -> Result<()> {
+    if cursor.keyword("IF") {
+        cursor.keyword("NOT");
+        cursor.keyword("EXISTS");
+    }
+    let table_name = cursor.name()?;
+    cursor.expect('(')?;
+    let mut columns: Vec<(String, &str, Vec<&str>)> = vec![];
+    let mut primary_key = None;
+    let mut unique = vec![];
+    for key in keys.iter().filter(|key| key.table_name == table_name) {
+        if key.primary {
+            primary_key = Some(key.column.to_string());
+        } else {
+            unique.push(key.column.to_string());
+        }
+    }
+    loop {
+        let item = cursor.item();
+        let first = match item.first() {
+            Some(Token::Word(word)) => word.to_uppercase(),
+            _ => String::new(),
+        };
+        let mut constraint = Cursor {
+            statement: cursor.statement,
+            position: cursor.position - item.len(),
+        };
+        if first == "CONSTRAINT" {
+            constraint.position += 1;
+            constraint.name()?;
+        }
+        if constraint.keyword("PRIMARY") && constraint.keyword("KEY") {
+            if let [column] = constraint.names()?.as_slice() {
+                primary_key = Some(column.to_string());
+            }
+        } else if constraint.keyword("UNIQUE") {
+            let _ = constraint.keyword("KEY") || constraint.keyword("INDEX");
+            if constraint.peek() != Some(&Token::Punct('(')) {
+                constraint.name()?;
+            }
+            if let [column] = constraint.names()?.as_slice() {
+                unique.push(column.to_string());
+            }
+        } else if first == "CONSTRAINT" || INDEXES.contains(&first.as_str()) {
+            // An index, or a constraint SQLRite can't enforce
+        } else {
+            columns.push(column(item)?);
+        }
+        if !cursor.punct(',') {
+            break;
+        }
+    }
+    cursor.expect(')')?;
+
+    let columns = columns
+        .into_iter()
+        .map(|(name, datatype, mut constraints)| {
+            if primary_key.as_ref() == Some(&name) && !constraints.contains(&"PRIMARY KEY") {
+                constraints.retain(|&constraint| constraint != "NOT NULL");
+                constraints.insert(0, "PRIMARY KEY");
+            }
+            if unique.contains(&name) && !constraints.contains(&"UNIQUE") {
+                constraints.push("UNIQUE");
+            }
+            let mut definition = format!("{} {}", name, datatype);
+            for constraint in constraints {
+                definition.push(' ');
+                definition.push_str(constraint);
+            }
+            definition
+        })
+        .collect::<Vec<String>>();
+    process_command(
+        &format!("CREATE TABLE {} ({});", table_name, columns.join(", ")),
+        db,
+    )?;
+    report.created.push(table_name);
+    Ok(())
+}
+
+/// The name, type and constraints of the column of the definition `item`.
+fn column(item: &[Token]) -> Result<(String, &'static str, Vec<&'static str>)> {
+    let name = match item.first() {
+        Some(Token::Word(name)) | Some(Token::Quoted(name)) => identifier(name),
+        _ => {
+            return Err(SQLRiteError::General(
+                "expected a column definition".to_string(),
+            ))
+        }
+    };
+    let (datatype, rest) = match item.get(1) {
+        Some(Token::Word(datatype)) => {
+            let rest = &item[2..];
+            match rest.first() {
+                Some(Token::Punct('(')) => {
+                    let end = rest
+                        .iter()
+                        .position(|token| token == &Token::Punct(')'))
+                        .unwrap_or(rest.len());
+                    (
+                        column_type(datatype, &rest[1..end]),
+                        rest.get(end + 1..).unwrap_or_default(),
+                    )
+                }
+                _ => (column_type(datatype, &[]), rest),
+            }
+        }
+        _ => ("TEXT", item.get(1..).unwrap_or_default()),
+    };
+    let words = rest
+        .iter()
+        .map(|token| match token {
+            Token::Word(word) => word.to_uppercase(),
+            _ => String::new(),
+        })
+        .collect::<Vec<String>>();
+    let mut constraints = vec![];
+    if words.windows(2).any(|pair| pair == ["PRIMARY", "KEY"]) {
+        constraints.push("PRIMARY KEY");
+    }
+    if words.iter().any(|word| word == "UNIQUE") {
+        constraints.push("UNIQUE");
+    }
+    if words.windows(2).any(|pair| pair == ["NOT", "NULL"]) && !constraints.contains(&"PRIMARY KEY")
+    {
+        constraints.push("NOT NULL");
+    }
+    Ok((name, datatype, constraints))
+}
+
+/// The columns of the table `table_name` named `names`, or all of them, by name and type.
+fn table_columns(
+    db: &Database,
+    table_name: &str,
+    names: Option<Vec<String>>,
+) -> Result<Vec<(String, DataType)>> {
+    let table = db.get_table(table_name.to_string())?;
+    let columns = table
+        .columns
+        .iter()
+        .map(|column| (column.column_name.to_string(), column.datatype.clone()));
+    match names {
+        None => Ok(columns.collect()),
+        Some(names) => names
+            .iter()
+            .map(|name| {
+                table
+                    .columns
+                    .iter()
+                    .find(|column| &column.column_name == name)
+                    .map(|column| (name.to_string(), column.datatype.clone()))
+                    .ok_or_else(|| {
+                        SQLRiteError::General(format!(
+                            "table {} has no column named {}",
+                            table_name, name
+                        ))
+                    })
+            })
+            .collect(),
+    }
+}
+
+/// The literal `value` is inserted as into the column `name` of type `datatype`, or why it
+/// can't be stored there. Booleans are also read as 1 and 0, like MySQL stores them, and t
+/// and f, like PostgreSQL writes them.
+fn literal(name: &str, value: &str, datatype: &DataType) -> std::result::Result<String, String> {
+    let valid = match datatype {
+        DataType::Integer => value.parse::<i32>().is_ok(),
+        DataType::Real => value.parse::<f64>().is_ok(),
+        DataType::Bool => match value.to_lowercase().as_str() {
+            "1" | "t" | "true" => return Ok("true".to_string()),
+            "0" | "f" | "false" => return Ok("false".to_string()),
+            _ => false,
+        },
+        _ => true,
+    };
+    if !valid {
+        return Err(format!(
+            "{} can't be stored in the {} column {}",
+            value,
+            datatype.to_string().to_uppercase(),
+            name
+        ));
+    }
+    Ok(value.to_string())
+}
+
+/// Rows of a table, each by the line it is on, its columns and their literals, inserted
+/// together while they have values for the same columns
+struct Rows<'a> {
+    table_name: &'a str,
+    columns: Vec<String>,
+    batch: Vec<(usize, Vec<Value>)>,
+}
+
+impl<'a> Rows<'a> {
+    fn new(table_name: &'a str) -> Self {
+        Rows {
+            table_name,
+            columns: vec![],
+            batch: vec![],
+        }
+    }
+
+    /// Adds the row of `values`, NULL when `None`, for the columns `columns`.
+    fn push(
+        &mut self,
+        db: &mut Database,
+        line: usize,
+        columns: &[(String, DataType)],
+        values: Vec<Option<String>>,
+        report: &mut ImportReport,
+    ) -> Result<()> {
+        if values.len() != columns.len() {
+            report.skipped.push((
+                line,
+                format!("{} values for {} columns", values.len(), columns.len()),
+            ));
+            return Ok(());
+        }
+        let mut row_columns = vec![];
+        let mut literals = vec![];
+        for ((name, datatype), value) in columns.iter().zip(values) {
+            if let Some(value) = value {
+                match literal(name, &value, datatype) {
+                    Ok(literal) => {
+                        row_columns.push(name.to_string());
+                        literals.push(Value::Text(literal));
+                    }
+                    Err(reason) => {
+                        report.skipped.push((line, reason));
+                        return Ok(());
+                    }
+                }
+            }
+        }
+        if row_columns != self.columns {
+            self.flush(db, report)?;
+            self.columns = row_columns;
+        }
+        self.batch.push((line, literals));
+        Ok(())
+    }
+
+    fn flush(&mut self, db: &mut Database, report: &mut ImportReport) -> Result<()> {
+        insert_batch(db, self.table_name, &self.columns, &self.batch, report)?;
+        self.batch.clear();
+        Ok(())
+    }
+}
+
+/// Inserts the rows of `INSERT [IGNORE] INTO t [(columns)] VALUES (...), ...`, the columns
+/// of the table in order when they aren't listed.
+fn insert(db: &mut Database, cursor: &mut Cursor, report: &mut ImportReport) -> Result<()> {
+    while !cursor.keyword("INTO") {
+        if cursor.next().is_none() {
+            return Err(SQLRiteError::General("expected INTO".to_string()));
+        }
+    }
+    let table_name = db.resolve_table_name(&cursor.name()?)?;
+    let names = match cursor.peek() {
+        Some(Token::Punct('(')) => Some(cursor.names()?),
+        _ => None,
+    };
+    let columns = table_columns(db, &table_name, names)?;
+    if !cursor.keyword("VALUES") {
+        return Err(SQLRiteError::NotImplemented(
+            "only INSERT ... VALUES can be imported".to_string(),
+        ));
+    }
+    let mut rows = Rows::new(&table_name);
+    loop {
+        let line = cursor.line();
+        cursor.expect('(')?;
+        let mut values = vec![];
+        let mut invalid = None;
+        loop {
+            match value(cursor.item()) {
+                Ok(value) => values.push(value),
+                Err(reason) => invalid = invalid.or(Some(reason)),
+            }
+            if !cursor.punct(',') {
+                break;
+            }
+        }
+        cursor.expect(')')?;
+        match invalid {
+            Some(reason) => report.skipped.push((line, reason)),
+            None => rows.push(db, line, &columns, values, report)?,
+        }
+        if !cursor.punct(',') {
+            break;
+        }
+    }
+    rows.flush(db, report)
+}
+
+/// The value of the literal `tokens`, `None` for NULL, or why it can't be imported.
+fn value(tokens: &[Token]) -> std::result::Result<Option<String>, String> {
+    let value = match tokens {
+        [Token::Str(s)] => s.to_string(),
+        // A charset introducer, like _binary or _utf8mb4
+        [Token::Word(charset), Token::Str(s)] if charset.starts_with('_') => s.to_string(),
+        [Token::Number(n)] | [Token::Punct('+'), Token::Number(n)] => n.to_string(),
+        [Token::Punct('-'), Token::Number(n)] => format!("-{}", n),
+        [Token::Word(word)] if word.eq_ignore_ascii_case("NULL") => return Ok(None),
+        [Token::Word(word)]
+            if word.eq_ignore_ascii_case("TRUE") || word.eq_ignore_ascii_case("FALSE") =>
+        {
+            word.to_lowercase()
+        }
+        _ => {
+            let text = tokens
+                .iter()
+                .map(|token| match token {
+                    Token::Word(s) | Token::Number(s) => s.to_string(),
+                    Token::Quoted(s) => format!("\"{}\"", s),
+                    Token::Str(s) => format!("'{}'", s),
+                    Token::Punct(c) => c.to_string(),
+                })
+                .collect::<String>();
+            return Err(format!("unsupported value {}", text));
+        }
+    };
+    Ok(Some(value))
+}
+
+/// Inserts the rows of `COPY t (columns) FROM stdin`, `data`, a line each with its values
+/// separated by tabs.
+fn copy(
+    db: &mut Database,
+    cursor: &mut Cursor,
+    data: &[(usize, String)],
+    report: &mut ImportReport,
+) -> Result<()> {
+    let table_name = db.resolve_table_name(&cursor.name()?)?;
+    let names = match cursor.peek() {
+        Some(Token::Punct('(')) => Some(cursor.names()?),
+        _ => None,
+    };
+    let columns = table_columns(db, &table_name, names)?;
+    let mut rows = Rows::new(&table_name);
+    for (line, row) in data {
+        let values = row.split('\t').map(copy_value).collect();
+        rows.push(db, *line, &columns, values, report)?;
+    }
+    rows.flush(db, report)
+}
+
+/// The value of a field of COPY data, `None` for \N, with its backslash escapes decoded.
+fn copy_value(field: &str) -> Option<String> {
+    if field == "\\N" {
+        return None;
+    }
+    let mut value = String::with_capacity(field.len());
+    let mut chars = field.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            value.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('b') => value.push('\x08'),
+            Some('f') => value.push('\x0c'),
+            Some('n') => value.push('\n'),
+            Some('r') => value.push('\r'),
+            Some('t') => value.push('\t'),
+            Some('v') => value.push('\x0b'),
+            Some(c) => value.push(c),
+            None => value.push('\\'),
+        }
+    }
+    Some(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sql::process_query;
+    use crate::sql::value::Value;
+
+    fn rows(db: &mut Database, sql: &str) -> Vec<Vec<Value>> {
+        process_query(sql, db).unwrap().rows
+    }
+
+    #[test]
+    fn import_mysql_dump_test() {
+        let dump = "-- MySQL dump 10.13
+/*!40101 SET NAMES utf8mb4 */;
+DROP TABLE IF EXISTS `users`;
+CREATE TABLE `users` (
+  `id` int(11) NOT NULL AUTO_INCREMENT,
+  `name` varchar(255) NOT NULL,
+  `email` varchar(255) DEFAULT NULL,
+  `score` decimal(10,2) DEFAULT '0.00',
+  `active` tinyint(1) NOT NULL DEFAULT '1',
+  PRIMARY KEY (`id`),
+  UNIQUE KEY `email_idx` (`email`),
+  KEY `name_idx` (`name`)
+) ENGINE=InnoDB AUTO_INCREMENT=4 DEFAULT CHARSET=utf8mb4;
+LOCK TABLES `users` WRITE;
+/*!40000 ALTER TABLE `users` DISABLE KEYS */;
+INSERT INTO `users` VALUES (1,'O\\'Brien','ob@example.com',1.50,1),(2,'Ann; \\\"A\\\"',NULL,-2.25,0),
+(3,'Bad','bad@example.com','x',1),(4,'Now','now@example.com',NOW(),1);
+/*!40000 ALTER TABLE `users` ENABLE KEYS */;
+UNLOCK TABLES;
+";
+        let mut db = Database::new("test".to_string());
+        let report = import_dump(&mut db, dump).unwrap();
+        assert_eq!(report.created, vec!["users"]);
+        assert_eq!(report.inserted, 2);
+        assert_eq!(report.ignored_statements, 3);
+        assert_eq!(
+            report.skipped,
+            vec![
+                (17, "x can't be stored in the REAL column score".to_string()),
+                (17, "unsupported value NOW()".to_string()),
+            ]
+        );
+
+        let table = db.get_table("users".to_string()).unwrap();
+        let id = &table.columns[0];
+        assert!(id.is_pk && id.datatype == DataType::Integer);
+        assert!(table.columns[2].is_unique);
+        assert_eq!(table.columns[3].datatype, DataType::Real);
+        assert_eq!(table.columns[4].datatype, DataType::Bool);
+        assert_eq!(
+            rows(&mut db, "SELECT id, name, score, active FROM users;"),
+            vec![
+                vec![
+                    Value::Integer(1),
+                    Value::Text("O'Brien".to_string()),
+                    Value::Real(1.5),
+                    Value::Bool(true),
+                ],
+                vec![
+                    Value::Integer(2),
+                    Value::Text("Ann; \"A\"".to_string()),
+                    Value::Real(-2.25),
+                    Value::Bool(false),
+                ],
+            ]
+        );
+    }
+
+    #[test]
+    fn import_postgres_dump_test() {
+        let dump = "--
+-- PostgreSQL database dump
+--
+
+SET statement_timeout = 0;
+SELECT pg_catalog.set_config('search_path', '', false);
+
+CREATE TABLE public.notes (
+    id integer NOT NULL,
+    body text,
+    done boolean DEFAULT false
+);
+
+CREATE FUNCTION public.touch() RETURNS trigger AS $$ BEGIN RETURN NEW; END; $$ LANGUAGE plpgsql;
+
+COPY public.notes (id, body, done) FROM stdin;
+1\tit's a\\ttab\tt
+2\t\\N\tf
+three\tno\tf
+\\.
+
+INSERT INTO public.notes VALUES (4, E'line\\nbreak', true);
+
+ALTER TABLE ONLY public.notes
+    ADD CONSTRAINT notes_pkey PRIMARY KEY (id);
+";
+        let mut db = Database::new("test".to_string());
+        let report = import_dump(&mut db, dump).unwrap();
+        assert_eq!(report.created, vec!["notes"]);
+        assert_eq!(report.inserted, 3);
+        assert_eq!(report.ignored_statements, 3);
+        assert_eq!(
+            report.skipped,
+            vec![(
+                19,
+                "three can't be stored in the INTEGER column id".to_string()
+            )]
+        );
+        assert!(db.get_table("notes".to_string()).unwrap().columns[0].is_pk);
+        assert_eq!(
+            rows(&mut db, "SELECT id, body, done FROM notes WHERE id <> 2;"),
+            vec![
+                vec![
+                    Value::Integer(1),
+                    Value::Text("it's a\ttab".to_string()),
+                    Value::Bool(true),
+                ],
+                vec![
+                    Value::Integer(4),
+                    Value::Text("line\nbreak".to_string()),
+                    Value::Bool(true),
+                ],
+            ]
+        );
+
+        assert!(import_dump(&mut db, "INSERT INTO notes VALUES (5, 'open").is_err());
+    }
+}
@@ -0,0 +1,268 @@
+use serde_json::{Map, Value as Json};
+use std::convert::TryFrom;
+
+use crate::error::{Result, SQLRiteError};
+use crate::import::{identifier, insert_batch, ImportReport};
+use crate::sql::db::database::Database;
+use crate::sql::db::table::DataType;
+use crate::sql::process_command;
+use crate::sql::value::Value;
+
+/// A row of the file: its number, and its values by column, or why it can't be imported
+type Record = (usize, std::result::Result<Map<String, Json>, String>);
+
+/// Imports the rows of `text`, a JSON array of objects or JSON lines, an object per line,
+/// into the table `table_name`. The keys of each object are matched to the columns of the
+/// table, which is created if it doesn't exist, with a column per key typed after the
+/// values of the key. Rows with a value of the wrong type for its column, or that the
+/// table rejects, are skipped and reported.
+pub fn import_json(db: &mut Database, text: &str, table_name: &str) -> Result<ImportReport> {
+    let records = parse_records(text)?;
+    let mut report = ImportReport::default();
+    let table_name = db.resolve_table_name(table_name)?;
+    if db.vtabs.contains_table(&table_name) {
+        return Err(SQLRiteError::NotImplemented(format!(
+            "cannot import into the virtual table {}",
+            table_name
+        )));
+    }
+    if !db.tables.contains_key(&table_name) {
+        process_command(&create_table_sql(&table_name, &records), db)?;
+        report.created.push(table_name.to_string());
+    }
+    let columns = db.tables[&table_name]
+        .columns
+        .iter()
+        .map(|column| (column.column_name.to_string(), column.datatype.clone()))
+        .collect::<Vec<(String, DataType)>>();
+
+    // Consecutive rows with values for the same columns are inserted together
+    let mut batch: Vec<(usize, Vec<Value>)> = vec![];
+    let mut batch_columns: Vec<String> = vec![];
+    for (number, record) in records {
+        let row = record.and_then(|object| {
+            let mut row = vec![];
+            for (key, value) in &object {
+                let column = columns
+                    .iter()
+                    .find(|(name, _)| name == key || *name == identifier(key));
+                match column {
+                    Some((name, datatype)) => {
+                        if let Some(literal) = literal(name, value, datatype)? {
+                            row.push((name.to_string(), Value::Text(literal)));
+                        }
+                    }
+                    None if !report.ignored_keys.contains(key) => {
+                        report.ignored_keys.push(key.to_string())
+                    }
+                    None => {}
+                }
+            }
+            Ok(row)
+        });
+        let row = match row {
+            Ok(row) => row,
+            Err(reason) => {
+                report.skipped.push((number, reason));
+                continue;
+            }
+        };
+        let (row_columns, literals): (Vec<String>, Vec<Value>) = row.into_iter().unzip();
+        if row_columns != batch_columns {
+            insert_batch(db, &table_name, &batch_columns, &batch, &mut report)?;
+            batch.clear();
+            batch_columns = row_columns;
+        }
+        batch.push((number, literals));
+    }
+    insert_batch(db, &table_name, &batch_columns, &batch, &mut report)?;
+    Ok(report)
+}
+
+/// Reads the rows of `text`, a JSON array or JSON lines, blank lines being left out.
+fn parse_records(text: &str) -> Result<Vec<Record>> {
+    let object = |value: Json| match value {
+        Json::Object(object) => Ok(object),
+        value => Err(format!("{} is not an object", value)),
+    };
+    if text.trim_start().starts_with('[') {
+        let values = serde_json::from_str::<Vec<Json>>(text)
+            .map_err(|err| SQLRiteError::General(format!("invalid JSON: {}", err)))?;
+        return Ok(values
+            .into_iter()
+            .enumerate()
+            .map(|(i, value)| (i + 1, object(value)))
+            .collect());
+    }
+    Ok(text
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(i, line)| {
+            let value =
+                serde_json::from_str::<Json>(line).map_err(|err| format!("invalid JSON: {}", err));
+            (i + 1, value.and_then(object))
+        })
+        .collect())
+}
+
+/// The CREATE TABLE statement of the table the rows are imported into, with a column for
+/// each key of the rows, in the order they first appear. A key whose values all are 32-bit
+/// integers is an INTEGER column, all numbers a REAL one, all booleans a BOOL one, and
+/// anything else, or only NULL, a TEXT column.
+fn create_table_sql(table_name: &str, records: &[Record]) -> String {
+    let mut keys: Vec<(String, Option<DataType>)> = vec![];
+    for object in records
+        .iter()
+        .filter_map(|(_, record)| record.as_ref().ok())
+    {
+        for (key, value) in object {
+            let position = match keys.iter().position(|(name, _)| name == key) {
+                Some(position) => position,
+                None => {
+                    keys.push((key.to_string(), None));
+                    keys.len() - 1
+                }
+            };
+            let datatype = match value {
+                Json::Null => continue,
+                Json::Number(n) if n.as_i64().is_some_and(|n| i32::try_from(n).is_ok()) => {
+                    DataType::Integer
+                }
+                Json::Number(n) if n.is_f64() => DataType::Real,
+                Json::Bool(_) => DataType::Bool,
+                _ => DataType::Text,
+            };
+            let known = &mut keys[position].1;
+            *known = Some(match (known.take(), datatype) {
+                (None, datatype) => datatype,
+                (Some(known), datatype) if known == datatype => datatype,
+                (Some(DataType::Integer), DataType::Real)
+                | (Some(DataType::Real), DataType::Integer) => DataType::Real,
+                _ => DataType::Text,
+            });
+        }
+    }
+    let columns = keys
+        .iter()
+        .map(|(key, datatype)| {
+            let datatype = match datatype {
+                Some(DataType::Integer) => "INTEGER",
+                Some(DataType::Real) => "REAL",
+                Some(DataType::Bool) => "BOOL",
+                _ => "TEXT",
+            };
+            format!("{} {}", identifier(key), datatype)
+        })
+        .collect::<Vec<String>>();
+    format!("CREATE TABLE {} ({});", table_name, columns.join(", "))
+}
+
+/// The literal `value` is inserted as into the column `name` of type `datatype`, `None` for
+/// NULL, or why it can't be stored there. TEXT columns take any value, as JSON text for
+/// arrays and objects.
+fn literal(
+    name: &str,
+    value: &Json,
+    datatype: &DataType,
+) -> std::result::Result<Option<String>, String> {
+    let literal = match (datatype, value) {
+        (_, Json::Null) => return Ok(None),
+        (DataType::Integer, Json::Number(n))
+            if n.as_i64().is_some_and(|n| i32::try_from(n).is_ok()) =>
+        {
+            n.to_string()
+        }
+        (DataType::Real, Json::Number(n)) => n.to_string(),
+        (DataType::Bool, Json::Bool(b)) => b.to_string(),
+        (DataType::Integer, _) | (DataType::Real, _) | (DataType::Bool, _) => {
+            return Err(format!(
+                "{} can't be stored in the {} column {}",
+                value,
+                datatype.to_string().to_uppercase(),
+                name
+            ))
+        }
+        (_, Json::String(s)) => s.to_string(),
+        (_, value) => value.to_string(),
+    };
+    Ok(Some(literal))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sql::process_query;
+    use crate::sql::value::Value;
+
+    #[test]
+    fn import_json_test() {
+        let mut db = Database::new("tempdb".to_string());
+        let text = r#"[
+            {"name": "Ada", "age": 36, "score": 1, "tags": ["math"], "active": true},
+            {"name": "Grace", "age": null, "score": 2.5, "active": false},
+            {"name": "Alan", "age": "forty-one", "first seen": "1912"}
+        ]"#;
+        let report = import_json(&mut db, text, "people").unwrap();
+        assert_eq!(report.inserted, 3);
+        assert_eq!(report.created, vec!["people"]);
+        assert!(report.skipped.is_empty());
+        let result = process_query("SELECT * FROM people;", &db).unwrap();
+        let columns = result
+            .columns
+            .iter()
+            .map(|column| column.name.as_str())
+            .collect::<Vec<&str>>();
+        assert_eq!(
+            columns,
+            vec!["name", "age", "score", "tags", "active", "first_seen"]
+        );
+        let table = &db.tables["people"];
+        let datatypes = table
+            .columns
+            .iter()
+            .map(|column| column.datatype.to_string())
+            .collect::<Vec<String>>();
+        assert_eq!(
+            datatypes,
+            vec!["Text", "Text", "Real", "Text", "Boolean", "Text"]
+        );
+        assert_eq!(result.rows[0][3], Value::Text("[\"math\"]".to_string()));
+
+        let text = "{\"id\": 1, \"body\": \"first\", \"extra\": 1}\n\
+                    \n\
+                    {\"id\": \"two\", \"body\": \"second\"}\n\
+                    not json\n\
+                    [1, 2]\n\
+                    {\"id\": 1, \"body\": \"duplicate\"}\n\
+                    {\"body\": \"third\"}\n";
+        process_command(
+            "CREATE TABLE notes (id INTEGER PRIMARY KEY, body TEXT);",
+            &mut db,
+        )
+        .unwrap();
+        let report = import_json(&mut db, text, "notes").unwrap();
+        assert_eq!(report.inserted, 2);
+        assert!(report.created.is_empty());
+        assert_eq!(
+            report
+                .skipped
+                .iter()
+                .map(|(n, _)| *n)
+                .collect::<Vec<usize>>(),
+            vec![3, 4, 5, 6]
+        );
+        assert!(report.skipped[0].1.contains("INTEGER column id"));
+        assert_eq!(report.ignored_keys, vec!["extra"]);
+        let result = process_query("SELECT body FROM notes;", &db).unwrap();
+        assert_eq!(
+            result.rows,
+            vec![
+                vec![Value::Text("first".to_string())],
+                vec![Value::Text("third".to_string())]
+            ]
+        );
+
+        assert!(import_json(&mut db, "[{\"a\": 1}", "notes").is_err());
+    }
+}
@@ -0,0 +1,92 @@
+//! Import of rows into tables from files: JSON, and the SQL dumps of MySQL and PostgreSQL.
+//! A row that can't be stored is skipped rather than failing the whole import, and reported
+//! with the reason why, so that the rows of a large file that are fine don't have to wait
+//! for the others to be fixed.
+pub mod dump;
+pub mod json;
+
+use crate::error::{Result, SQLRiteError};
+use crate::sql::db::database::Database;
+use crate::sql::executor::modify::insert_into_table;
+use crate::sql::executor::trigger::{has_triggers, in_transaction};
+use crate::sql::value::Value;
+
+/// The outcome of an import
+#[derive(Debug, Default, PartialEq)]
+pub struct ImportReport {
+    /// Rows inserted
+    pub inserted: usize,
+    /// Tables created
+    pub created: Vec<String>,
+    /// Rows skipped, by number in the file from 1, or line for dumps, with the reason why
+    pub skipped: Vec<(usize, String)>,
+    /// Keys of the rows matching no column of the table, whose values were left out
+    pub ignored_keys: Vec<String>,
+    /// Statements of a dump left out, that have no equivalent in SQLRite
+    pub ignored_statements: usize,
+}
+
+/// The identifier a table or a column named `name` in the file is given, with the
+/// characters that can't be part of an identifier replaced by underscores.
+pub(crate) fn identifier(name: &str) -> String {
+    let mut identifier = name
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect::<String>();
+    if !identifier.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_') {
+        identifier.insert(0, '_');
+    }
+    identifier
+}
+
+/// Inserts `rows`, numbered, into the columns `columns` of the table. If they can't all be
+/// inserted, they are inserted one by one, the rows the table rejects being skipped. Text
+/// is read as the type of its column, like the literals of the file it comes from.
+pub(crate) fn insert_batch(
+    db: &mut Database,
+    table_name: &str,
+    columns: &[String],
+    rows: &[(usize, Vec<Value>)],
+    report: &mut ImportReport,
+) -> Result<()> {
+    if rows.is_empty() {
+        return Ok(());
+    }
+    let values = rows
+        .iter()
+        .map(|(_, row)| row.clone())
+        .collect::<Vec<Vec<Value>>>();
+    if let Ok(inserted) = insert(db, table_name, columns, &values) {
+        report.inserted += inserted as usize;
+        return Ok(());
+    }
+    for (number, row) in rows {
+        match insert(db, table_name, columns, std::slice::from_ref(row)) {
+            Ok(inserted) => report.inserted += inserted as usize,
+            Err(SQLRiteError::Interrupted) => return Err(SQLRiteError::Interrupted),
+            Err(err) => report.skipped.push((*number, err.to_string())),
+        }
+    }
+    Ok(())
+}
+
+/// Inserts `rows` like an INSERT statement, as a statement of its own.
+fn insert(
+    db: &mut Database,
+    table_name: &str,
+    columns: &[String],
+    rows: &[Vec<Value>],
+) -> Result<i64> {
+    let result = if has_triggers(db, table_name) || db.records_changes() {
+        in_transaction(db, |db| {
+            insert_into_table(db, table_name, columns, rows, None)
+        })
+    } else {
+        insert_into_table(db, table_name, columns, rows, None)
+    };
+    if let Ok(inserted) = result {
+        db.counters.record(inserted);
+    }
+    db.end_statement(result.is_ok())?;
+    result
+}
@@ -0,0 +1,46 @@
+//! SQLRite is a simple embedded database modeled off SQLite, but developed with Rust.
+//!
+//! Besides the `sqlrite` REPL binary, which can also serve a database over TCP to remote
+//! REPL sessions, the engine can be embedded in other Rust programs through a
+//! [`Connection`](connection::Connection), and from async services through the feature-gated `AsyncConnection`. With the `ffi` feature, the `cdylib` also exports a
+//! subset of the sqlite3 C API, declared in `include/sqlite3.h`, and with the `wasm` feature
+//! the engine can be compiled to WebAssembly and driven from JavaScript. The `replication`
+//! feature keeps read-only replicas of a database in sync with a leader, and changesets
+//! recorded by a connection's session sync databases offline.
+#[macro_use]
+extern crate prettytable;
+
+#[cfg(feature = "bench")]
+pub mod bench;
+pub mod connection;
+pub mod error;
+pub mod export;
+pub mod extension;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+pub mod import;
+pub mod logictest;
+#[cfg(feature = "cli")]
+pub mod meta_command;
+#[cfg(feature = "cli")]
+pub mod repl;
+#[cfg(feature = "replication")]
+pub mod replication;
+pub mod seed;
+#[cfg(feature = "cli")]
+pub mod server;
+#[cfg(feature = "cli")]
+pub mod shutdown;
+pub mod sql;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+
+#[cfg(feature = "async")]
+pub use connection::async_connection::AsyncConnection;
+pub use connection::rows::{Row, Rows};
+pub use connection::Connection;
+pub use error::{Result, SQLRiteError};
+pub use sql::db::cache::ResultCacheStats;
+pub use sql::db::changeset::Changeset;
+pub use sql::db::limits::Limit;
+pub use sql::value::{FromValue, Value};
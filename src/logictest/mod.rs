@@ -0,0 +1,498 @@
+//! Runner of [sqllogictest](https://www.sqlite.org/sqllogictest/doc/trunk/about.wiki)
+//! scripts, the format of the large corpora SQLite and other engines validate their SQL
+//! against. A script is a list of records separated by blank lines:
+//!
+//! ```text
+//! statement ok
+//! CREATE TABLE t1 (a INTEGER, b TEXT)
+//!
+//! statement error no such column
+//! SELECT c FROM t1
+//!
+//! query IT rowsort
+//! SELECT a, b FROM t1
+//! ----
+//! 1
+//! one
+//! ```
+//!
+//! Query results are listed one value per line, or one row per line with its values
+//! separated by spaces, or as `N values hashing to MD5` for large results. `skipif` and
+//! `onlyif` conditions name the engine `sqlrite`. Each script runs against a fresh
+//! in-memory database, and the records that fail are reported with their line, so a
+//! whole corpus can be run to see how much of it the engine passes.
+use std::fs;
+use std::path::Path;
+
+use crate::error::{Result, SQLRiteError};
+use crate::sql::db::database::Database;
+use crate::sql::value::Value;
+use crate::sql::{process_command, process_query, returns_rows};
+
+/// Name of the engine in `skipif` and `onlyif` conditions
+pub const ENGINE_NAME: &str = "sqlrite";
+
+/// How the rows of a query are sorted before they are compared
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum SortMode {
+    NoSort,
+    RowSort,
+    ValueSort,
+}
+
+/// Results a query is expected to return
+#[derive(Debug, PartialEq, Clone)]
+pub enum Expected {
+    /// The lines listing the results
+    Lines(Vec<String>),
+    /// The number of values and the MD5 hash of their lines
+    Hash { values: usize, hash: String },
+}
+
+/// What a record checks
+#[derive(Debug, PartialEq, Clone)]
+pub enum RecordKind {
+    /// A statement that succeeds, or fails with an error containing the message, if any,
+    /// ignoring case
+    Statement { ok: bool, error: Option<String> },
+    /// A query returning columns of `types`, one of `I`, `R` or `T` per column
+    Query {
+        types: String,
+        sort: SortMode,
+        expected: Expected,
+    },
+    /// Stops the script
+    Halt,
+}
+
+/// A record of a script
+#[derive(Debug, PartialEq, Clone)]
+pub struct Record {
+    /// Line the record starts on
+    pub line: usize,
+    pub kind: RecordKind,
+    pub sql: String,
+    /// The conditions of the record leave this engine out
+    pub skipped: bool,
+}
+
+/// A record whose statement or query didn't do what it expected
+#[derive(Debug, PartialEq, Clone)]
+pub struct Failure {
+    pub line: usize,
+    pub sql: String,
+    pub message: String,
+}
+
+/// The outcome of running a script
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct Report {
+    pub passed: usize,
+    pub skipped: usize,
+    pub failures: Vec<Failure>,
+}
+
+impl Report {
+    pub fn is_success(&self) -> bool {
+        self.failures.is_empty()
+    }
+}
+
+/// Parses the records of a script.
+pub fn parse(script: &str) -> Result<Vec<Record>> {
+    let lines: Vec<&str> = script.lines().collect();
+    let mut records = vec![];
+    let mut skipped = false;
+    let mut i = 0;
+    while i < lines.len() {
+        let line = lines[i].trim_end();
+        let words: Vec<&str> = line.split_whitespace().collect();
+        i += 1;
+        let start = i;
+        match words.as_slice() {
+            [] => continue,
+            [comment, ..] if comment.starts_with('#') => continue,
+            ["hash-threshold", ..] => continue,
+            ["skipif", engine, ..] => {
+                skipped |= *engine == ENGINE_NAME;
+                continue;
+            }
+            ["onlyif", engine, ..] => {
+                skipped |= *engine != ENGINE_NAME;
+                continue;
+            }
+            ["halt"] => {
+                if !skipped {
+                    records.push(Record {
+                        line: start,
+                        kind: RecordKind::Halt,
+                        sql: String::new(),
+                        skipped,
+                    });
+                }
+                skipped = false;
+                continue;
+            }
+            ["statement", "ok"] | ["statement", "error", ..] => {
+                let error = words.get(2..).filter(|message| !message.is_empty());
+                let sql = take_until(&lines, &mut i, |line| line.trim().is_empty());
+                records.push(Record {
+                    line: start,
+                    kind: RecordKind::Statement {
+                        ok: words[1] == "ok",
+                        error: error.map(|message| message.join(" ")),
+                    },
+                    sql,
+                    skipped,
+                });
+            }
+            ["query", types, rest @ ..] => {
+                let sort = match rest.first().copied() {
+                    None | Some("nosort") => SortMode::NoSort,
+                    Some("rowsort") => SortMode::RowSort,
+                    Some("valuesort") => SortMode::ValueSort,
+                    Some(mode) => return Err(script_error(start, &format!("sort mode {}", mode))),
+                };
+                let sql = take_until(&lines, &mut i, |line| {
+                    line.trim().is_empty() || line.trim_end() == "----"
+                });
+                let mut results = vec![];
+                if lines.get(i).map(|line| line.trim_end()) == Some("----") {
+                    i += 1;
+                    while i < lines.len() && !lines[i].trim().is_empty() {
+                        results.push(lines[i].trim_end().to_string());
+                        i += 1;
+                    }
+                }
+                records.push(Record {
+                    line: start,
+                    kind: RecordKind::Query {
+                        types: types.to_string(),
+                        sort,
+                        expected: expected(results),
+                    },
+                    sql,
+                    skipped,
+                });
+            }
+            _ => return Err(script_error(start, line)),
+        }
+        skipped = false;
+    }
+    Ok(records)
+}
+
+/// Joins the lines from `i` until one matching `end`, leaving `i` on it.
+fn take_until(lines: &[&str], i: &mut usize, end: impl Fn(&str) -> bool) -> String {
+    let mut sql = vec![];
+    while *i < lines.len() && !end(lines[*i]) {
+        sql.push(lines[*i]);
+        *i += 1;
+    }
+    sql.join("\n")
+}
+
+fn expected(results: Vec<String>) -> Expected {
+    if let [line] = results.as_slice() {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        if let [values, "values", "hashing", "to", hash] = words.as_slice() {
+            if let Ok(values) = values.parse() {
+                return Expected::Hash {
+                    values,
+                    hash: hash.to_string(),
+                };
+            }
+        }
+    }
+    Expected::Lines(results)
+}
+
+fn script_error(line: usize, message: &str) -> SQLRiteError {
+    SQLRiteError::General(format!("unsupported record at line {}: {}", line, message))
+}
+
+/// Runs a script against a fresh in-memory database.
+pub fn run(script: &str) -> Result<Report> {
+    let mut db = Database::new("logictest".to_string());
+    let mut report = Report::default();
+    for record in parse(script)? {
+        if record.skipped {
+            report.skipped += 1;
+            continue;
+        }
+        let outcome = match &record.kind {
+            RecordKind::Halt => break,
+            RecordKind::Statement { ok, error } => {
+                check_statement(&mut db, &record.sql, *ok, error.as_deref())
+            }
+            RecordKind::Query {
+                types,
+                sort,
+                expected,
+            } => check_query(&db, &record.sql, types, *sort, expected),
+        };
+        match outcome {
+            Ok(()) => report.passed += 1,
+            Err(message) => report.failures.push(Failure {
+                line: record.line,
+                sql: record.sql.to_string(),
+                message,
+            }),
+        }
+    }
+    Ok(report)
+}
+
+/// Runs the script in the file at `path`.
+pub fn run_file<P: AsRef<Path>>(path: P) -> Result<Report> {
+    let script = fs::read_to_string(path.as_ref())
+        .map_err(|err| SQLRiteError::General(format!("{}: {}", path.as_ref().display(), err)))?;
+    run(&script)
+}
+
+fn execute(db: &mut Database, sql: &str) -> Result<()> {
+    if returns_rows(sql)? {
+        process_query(sql, db).map(|_| ())
+    } else {
+        process_command(sql, db).map(|_| ())
+    }
+}
+
+fn check_statement(
+    db: &mut Database,
+    sql: &str,
+    ok: bool,
+    error: Option<&str>,
+) -> std::result::Result<(), String> {
+    match (execute(db, sql), ok) {
+        (Ok(()), true) => Ok(()),
+        (Ok(()), false) => Err("statement succeeded, an error was expected".to_string()),
+        (Err(err), true) => Err(format!("statement failed: {}", err)),
+        (Err(err), false) => match error {
+            Some(error)
+                if !err
+                    .to_string()
+                    .to_lowercase()
+                    .contains(&error.to_lowercase()) =>
+            {
+                Err(format!(
+                    "statement failed with \"{}\", expected \"{}\"",
+                    err, error
+                ))
+            }
+            _ => Ok(()),
+        },
+    }
+}
+
+fn check_query(
+    db: &Database,
+    sql: &str,
+    types: &str,
+    sort: SortMode,
+    expected: &Expected,
+) -> std::result::Result<(), String> {
+    let result = process_query(sql, db).map_err(|err| format!("query failed: {}", err))?;
+    if result.columns.len() != types.len() {
+        return Err(format!(
+            "query returned {} columns, expected {}",
+            result.columns.len(),
+            types.len()
+        ));
+    }
+    let mut rows: Vec<Vec<String>> = result
+        .rows
+        .iter()
+        .map(|row| {
+            row.iter()
+                .zip(types.chars())
+                .map(|(value, kind)| format_value(value, kind))
+                .collect()
+        })
+        .collect();
+    match sort {
+        SortMode::NoSort => {}
+        SortMode::RowSort => rows.sort(),
+        SortMode::ValueSort => {
+            let mut values: Vec<String> = rows.into_iter().flatten().collect();
+            values.sort();
+            rows = values.into_iter().map(|value| vec![value]).collect();
+        }
+    }
+    let values: Vec<String> = rows.iter().flatten().cloned().collect();
+
+    let matches = match expected {
+        Expected::Lines(lines) => {
+            *lines == values || *lines == rows.iter().map(|row| row.join(" ")).collect::<Vec<_>>()
+        }
+        Expected::Hash {
+            values: count,
+            hash,
+        } => {
+            let lines: String = values.iter().map(|value| format!("{}\n", value)).collect();
+            *count == values.len() && *hash == hex(&md5(lines.as_bytes()))
+        }
+    };
+    if matches {
+        Ok(())
+    } else {
+        Err(format!("query returned {:?}", values))
+    }
+}
+
+/// Formats a value the way sqllogictest lists it, as an integer for `I` columns, with 3
+/// decimals for `R` columns, and as text otherwise.
+fn format_value(value: &Value, kind: char) -> String {
+    if value.is_null() {
+        return "NULL".to_string();
+    }
+    match kind {
+        'I' => value.to_integer().unwrap_or(0).to_string(),
+        'R' => format!("{:.3}", value.to_real().unwrap_or(0.0)),
+        _ => match value.to_text().unwrap_or_default() {
+            text if text.is_empty() => "(empty)".to_string(),
+            text => text
+                .chars()
+                .map(|c| if (' '..='~').contains(&c) { c } else { '@' })
+                .collect(),
+        },
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// The MD5 digest of `data`, as specified by RFC 1321, which sqllogictest hashes large
+/// results with.
+fn md5(data: &[u8]) -> [u8; 16] {
+    const S: [u32; 64] = [
+        7, 12, 17, 22, 7, 12, 17, 22, 7, 12, 17, 22, 7, 12, 17, 22, 5, 9, 14, 20, 5, 9, 14, 20, 5,
+        9, 14, 20, 5, 9, 14, 20, 4, 11, 16, 23, 4, 11, 16, 23, 4, 11, 16, 23, 4, 11, 16, 23, 6, 10,
+        15, 21, 6, 10, 15, 21, 6, 10, 15, 21, 6, 10, 15, 21,
+    ];
+    // The integer part of abs(sin(i + 1)) * 2^32
+    let k: Vec<u32> = (0..64)
+        .map(|i| ((i as f64 + 1.0).sin().abs() * 4_294_967_296.0) as u32)
+        .collect();
+    let mut h: [u32; 4] = [0x67452301, 0xefcdab89, 0x98badcfe, 0x10325476];
+    // Padded with a 1 bit, zeros, and the length in bits, to a multiple of 64 bytes
+    let mut message = data.to_vec();
+    message.push(0x80);
+    while message.len() % 64 != 56 {
+        message.push(0);
+    }
+    message.extend_from_slice(&((data.len() as u64).wrapping_mul(8)).to_le_bytes());
+
+    for block in message.chunks(64) {
+        let mut m = [0u32; 16];
+        for (i, word) in block.chunks(4).enumerate() {
+            m[i] = u32::from_le_bytes([word[0], word[1], word[2], word[3]]);
+        }
+        let [mut a, mut b, mut c, mut d] = h;
+        for i in 0..64 {
+            let (f, g) = match i / 16 {
+                0 => ((b & c) | (!b & d), i),
+                1 => ((d & b) | (!d & c), (5 * i + 1) % 16),
+                2 => (b ^ c ^ d, (3 * i + 5) % 16),
+                _ => (c ^ (b | !d), (7 * i) % 16),
+            };
+            let f = f.wrapping_add(a).wrapping_add(k[i]).wrapping_add(m[g]);
+            a = d;
+            d = c;
+            c = b;
+            b = b.wrapping_add(f.rotate_left(S[i]));
+        }
+        h[0] = h[0].wrapping_add(a);
+        h[1] = h[1].wrapping_add(b);
+        h[2] = h[2].wrapping_add(c);
+        h[3] = h[3].wrapping_add(d);
+    }
+    let mut digest = [0u8; 16];
+    for (i, word) in h.iter().enumerate() {
+        digest[i * 4..i * 4 + 4].copy_from_slice(&word.to_le_bytes());
+    }
+    digest
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn md5_test() {
+        assert_eq!(hex(&md5(b"")), "d41d8cd98f00b204e9800998ecf8427e");
+        assert_eq!(
+            hex(&md5(b"The quick brown fox jumps over the lazy dog")),
+            "9e107d9d372bb6826bd81d3542a419d6"
+        );
+    }
+
+    #[test]
+    fn run_script_test() {
+        let script = "
+# A table of numbers
+statement ok
+CREATE TABLE t1 (id INTEGER PRIMARY KEY, a INTEGER, b TEXT)
+
+statement ok
+INSERT INTO t1 (a, b) VALUES (3, 'three'), (1, 'one'), (2, '')
+
+statement error no such column
+SELECT c FROM t1
+
+query IT rowsort
+SELECT a, b FROM t1
+----
+1
+one
+2
+(empty)
+3
+three
+
+query I nosort
+SELECT a FROM t1 ORDER BY a DESC
+----
+3
+2
+1
+
+query IT
+SELECT a, b FROM t1 WHERE a = 2
+----
+1 one
+
+query I valuesort
+SELECT a FROM t1
+----
+3 values hashing to c0710d6b4f15dfa88f600b0e6b624077
+
+skipif sqlrite
+query I
+SELECT unsupported()
+----
+1
+
+onlyif sqlite
+statement ok
+CREATE INDEX i1 ON t1 (a)
+
+statement ok
+INSERT INTO t1 (a) VALUES (4)
+
+halt
+
+statement ok
+this isn't run
+";
+        let report = run(script).unwrap();
+        assert_eq!(report.skipped, 2);
+        assert_eq!(report.passed, 7);
+        assert_eq!(report.failures.len(), 1);
+        assert_eq!(report.failures[0].line, 29);
+        assert!(report.failures[0].message.contains("[\"2\", \"(empty)\"]"));
+
+        assert!(parse("statement maybe\nSELECT 1").is_err());
+    }
+}
@@ -1,31 +1,197 @@
 extern crate clap;
-#[macro_use]
-extern crate prettytable;
 
-mod error;
-mod meta_command;
-mod repl;
-mod sql;
-
-use meta_command::handle_meta_command;
-use repl::{get_command_type, get_config, CommandType, REPLHelper};
-use sql::db::database::Database;
-use sql::process_command;
+use sqlrite::meta_command::{handle_meta_command, MetaCommand};
+use sqlrite::repl::{
+    execute_sql, get_command_type, get_config, output_width, record, CommandType, REPLHelper,
+};
+use sqlrite::server::client::Client;
+use sqlrite::server::{Credentials, Server, DEFAULT_PORT};
+use sqlrite::shutdown::{self, signal_name, Input, Terminal};
+use sqlrite::sql::db::database::Database;
+use sqlrite::sql::db::diff::schema_diff;
+use sqlrite::sql::strip_comments;
 
 use rustyline::error::ReadlineError;
 use rustyline::Editor;
 
-use clap::{crate_authors, crate_description, crate_name, crate_version, Command};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc};
+
+use clap::{crate_authors, crate_description, crate_name, crate_version, Arg, ArgMatches, Command};
+
+/// The database the REPL runs commands against
+enum Session {
+    Local(Box<Database>),
+    Remote(Client),
+}
 
 fn main() -> rustyline::Result<()> {
     env_logger::init();
 
-    let _matches = Command::new(crate_name!())
+    let credential_args = [
+        Arg::new("user")
+            .long("user")
+            .takes_value(true)
+            .help("User clients authenticate as"),
+        Arg::new("password")
+            .long("password")
+            .takes_value(true)
+            .requires("user")
+            .help("Password of the user, read from SQLRITE_PASSWORD if not given"),
+    ];
+    let serve = Command::new("serve")
+        .about("Serves a transient in-memory database to remote clients over TCP")
+        .arg(
+            Arg::new("address")
+                .long("address")
+                .takes_value(true)
+                .help("Address to listen on, 127.0.0.1:7878 by default"),
+        )
+        .arg(
+            Arg::new("pg-address")
+                .long("pg-address")
+                .takes_value(true)
+                .help("Address to also accept PostgreSQL clients on, like 127.0.0.1:5432"),
+        )
+        .arg(
+            Arg::new("http-address")
+                .long("http-address")
+                .takes_value(true)
+                .help("Address to also serve the HTTP/JSON API on, like 127.0.0.1:8080"),
+        )
+        .args(&credential_args);
+    // The gRPC service is only built with the grpc feature
+    #[cfg(feature = "grpc")]
+    let serve = serve.arg(
+        Arg::new("grpc-address")
+            .long("grpc-address")
+            .takes_value(true)
+            .help("Address to also serve the gRPC service on, like 127.0.0.1:50051"),
+    );
+    let matches = Command::new(crate_name!())
         .version(crate_version!())
         .author(crate_authors!())
         .about(crate_description!())
+        .subcommand(serve)
+        .subcommand(
+            Command::new("connect")
+                .about("Opens a REPL session on the database of a server")
+                .arg(
+                    Arg::new("address")
+                        .required(true)
+                        .help("Address of the server, as host:port"),
+                )
+                .args(&credential_args),
+        )
+        .subcommand(
+            Command::new("diff-schema")
+                .about("Prints the statements bringing the schema of a database to that of another")
+                .arg(
+                    Arg::new("from")
+                        .required(true)
+                        .help("File of the database to migrate"),
+                )
+                .arg(
+                    Arg::new("to")
+                        .required(true)
+                        .help("File of the database with the schema to migrate to"),
+                ),
+        )
         .get_matches();
 
+    let session = match matches.subcommand() {
+        Some(("serve", args)) => {
+            let address = args
+                .value_of("address")
+                .map(|address| address.to_string())
+                .unwrap_or_else(|| format!("127.0.0.1:{}", DEFAULT_PORT));
+            let result = Server::bind(&address, credentials(args)).and_then(|server| {
+                let handle = server.shutdown_handle();
+                shutdown::on_termination(move |signal| {
+                    println!("Received {}, shutting down.", signal_name(signal));
+                    match handle.shutdown() {
+                        Ok(true) => println!("Rolled back the open transaction."),
+                        Ok(false) => {}
+                        Err(err) => eprintln!("An error occured: {}", err),
+                    }
+                })?;
+                println!("Serving a transient in-memory database on {}.", address);
+                if let Some(pg_address) = args.value_of("pg-address") {
+                    let pg_address = server.listen_postgres(pg_address)?;
+                    println!("Accepting PostgreSQL clients on {}.", pg_address);
+                }
+                if let Some(http_address) = args.value_of("http-address") {
+                    let http_address = server.listen_http(http_address)?;
+                    println!("Serving the HTTP API on {}.", http_address);
+                }
+                #[cfg(feature = "grpc")]
+                if let Some(grpc_address) = args.value_of("grpc-address") {
+                    let grpc_address = server.listen_grpc(grpc_address)?;
+                    println!("Serving the gRPC service on {}.", grpc_address);
+                }
+                server.serve()
+            });
+            if let Err(err) = result {
+                eprintln!("An error occured: {}", err);
+                std::process::exit(1);
+            }
+            return Ok(());
+        }
+        Some(("connect", args)) => {
+            let address = args.value_of("address").unwrap_or_default();
+            let result = Client::connect(address).and_then(|mut client| {
+                if let Some(credentials) = credentials(args) {
+                    client.authenticate(&credentials.user, &credentials.password)?;
+                }
+                Ok(client)
+            });
+            match result {
+                Ok(client) => Session::Remote(client),
+                Err(err) => {
+                    eprintln!("An error occured: {}", err);
+                    std::process::exit(1);
+                }
+            }
+        }
+        Some(("diff-schema", args)) => {
+            let read =
+                |name| Database::read_file(Path::new(args.value_of(name).unwrap_or_default()));
+            let result =
+                read("from").and_then(|from| read("to").and_then(|to| schema_diff(&from, &to)));
+            match result {
+                Ok(statements) => {
+                    for statement in statements {
+                        println!("{}", statement);
+                    }
+                    return Ok(());
+                }
+                Err(err) => {
+                    eprintln!("An error occured: {}", err);
+                    std::process::exit(1);
+                }
+            }
+        }
+        _ => Session::Local(Box::new(Database::new("tempdb".to_string()))),
+    };
+    run_repl(session)
+}
+
+/// Reads the credentials given to `serve` and `connect`.
+fn credentials(args: &ArgMatches) -> Option<Credentials> {
+    let user = args.value_of("user")?;
+    let password = args
+        .value_of("password")
+        .map(|password| password.to_string())
+        .or_else(|| std::env::var("SQLRITE_PASSWORD").ok())
+        .unwrap_or_default();
+    Some(Credentials {
+        user: user.to_string(),
+        password,
+    })
+}
+
+fn run_repl(mut session: Session) -> rustyline::Result<()> {
     // Starting Rustyline with a default configuration
     let config = get_config();
 
@@ -43,47 +209,112 @@ fn main() -> rustyline::Result<()> {
         println!("No previous history.");
     }
 
+    // The history is appended after each command, so exiting on SIGTERM or a hang up has
+    // the terminal to give back as it was found, and the REPL to stop reading commands once
+    // the one running is done, to close the database like .exit does on its own thread
+    let terminal = Terminal::save();
+    let input = Input::current();
+    let terminated = Arc::new(AtomicBool::new(false));
+    let (closed, closing) = mpsc::channel::<()>();
+    let shutdown_terminated = Arc::clone(&terminated);
+    let result = shutdown::on_termination(move |signal| {
+        terminal.restore();
+        println!("\nReceived {}, shutting down.", signal_name(signal));
+        shutdown_terminated.store(true, Ordering::SeqCst);
+        input.close();
+        // Returns once the REPL is done, and dropped the sender
+        let _ = closing.recv();
+    });
+    if let Err(err) = result {
+        eprintln!("An error occured: {}", err);
+    }
+
     // Friendly intro message for the user
     println!(
-        "{} - {}\n{}{}{}{}",
+        "{} - {}\n{}{}{}",
         crate_name!(),
         crate_version!(),
         "Enter .exit to quit.\n",
         "Enter .help for usage hints.\n",
-        "Connected to a transient in-memory database.\n",
-        "Use '.open FILENAME' to reopen on a persistent database."
+        match &session {
+            Session::Local(_) => concat!(
+                "Connected to a transient in-memory database.\n",
+                "Use '.open FILENAME' to reopen on a persistent database."
+            ),
+            Session::Remote(_) => "Connected to a remote database.",
+        }
     );
 
-    let mut db = Database::new("tempdb".to_string());
-
     loop {
-        let p = format!("sqlrite> ");
+        // An open transaction shows in the prompt, its statements not committed yet
+        let p = match &session {
+            Session::Local(db) if db.transaction.is_open() => "sqlrite*> ".to_string(),
+            _ => "sqlrite> ".to_string(),
+        };
         repl.helper_mut().expect("No helper found").colored_prompt =
             format!("\x1b[1;32m{}\x1b[0m", p);
         // Source for ANSI Color information: http://www.perpetualpc.net/6429_colors.html#color_list
         // http://bixense.com/clicolors/
 
         let readline = repl.readline(&p);
+        if terminated.load(Ordering::SeqCst) {
+            break;
+        }
         match readline {
             Ok(command) => {
                 repl.add_history_entry(command.as_str());
+                let _ = repl.append_history("history");
+                // Input holding nothing but comments has nothing to run
+                if strip_comments(&command).trim().is_empty() {
+                    continue;
+                }
                 // Parsing user's input and returning and enum of repl::CommandType
                 match get_command_type(&command.trim().to_owned()) {
                     CommandType::SQLCommand(_cmd) => {
                         // process_command takes care of tokenizing, parsing and executing
                         // the SQL Statement and returning a Result<String, SQLRiteError>
-                        let _ = match process_command(&command, &mut db) {
+                        let result = match &mut session {
+                            // Rows are rendered for the width of the terminal
+                            Session::Local(db) => execute_sql(&command, db, output_width(&repl)),
+                            Session::Remote(client) => client.execute(&command),
+                        };
+                        record(&mut repl, &command, &result);
+                        match result {
                             Ok(response) => println!("{}", response),
                             Err(err) => eprintln!("An error occured: {}", err),
-                        };
+                        }
                     }
                     CommandType::MetaCommand(cmd) => {
                         // handle_meta_command parses and executes the MetaCommand
                         // and returns a Result<String, SQLRiteError>
-                        let _ = match handle_meta_command(cmd, &mut repl) {
+                        // A remote session only runs .exit itself
+                        let recorded = !matches!(cmd, MetaCommand::Record(_));
+                        let result = match &mut session {
+                            Session::Local(db) => handle_meta_command(cmd, &mut repl, db),
+                            Session::Remote(_) if cmd == MetaCommand::Exit => break,
+                            Session::Remote(client) => client.execute(&command),
+                        };
+                        // Starting and ending the transcript isn't part of it
+                        if recorded {
+                            record(&mut repl, &command, &result);
+                        }
+                        match result {
                             Ok(response) => println!("{}", response),
                             Err(err) => eprintln!("An error occured: {}", err),
-                        };
+                        }
+                    }
+                }
+                // An autosave that failed after a statement, or one run by .read, is reported,
+                // and so are the backups done since the last command
+                if let Session::Local(db) = &mut session {
+                    if let Some(err) = db.autosave.take_error() {
+                        eprintln!("An error occured: autosave failed: {}", err);
+                    }
+                    for (path, result) in db.backups.finished() {
+                        match result {
+                            Ok(()) => println!("Backup into {} done.", path.display()),
+                            Err(err) => eprintln!("An error occured: backup failed: {}", err),
+                        }
                     }
                 }
             }
@@ -100,6 +331,21 @@ fn main() -> rustyline::Result<()> {
         }
     }
     repl.append_history("history").unwrap();
+    if let Session::Local(db) = &mut session {
+        close(db);
+    }
+    drop(closed);
 
     Ok(())
 }
+
+/// Closes the database of a local REPL as it exits: a database opened with .open is saved
+/// back to its file, and the backups still being written are waited for. A transaction
+/// left open is rolled back, like in SQLite.
+fn close(db: &mut Database) {
+    match db.close() {
+        Ok(true) => println!("Rolled back the open transaction."),
+        Ok(false) => {}
+        Err(err) => eprintln!("An error occured: {}", err),
+    }
+}
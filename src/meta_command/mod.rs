@@ -1,14 +1,54 @@
 use crate::error::{Result, SQLRiteError};
 
-use crate::repl::REPLHelper;
+use crate::export::csv::{CsvOptions, CsvWriter};
+use crate::export::json::JsonLinesWriter;
+use crate::import::dump::import_dump;
+use crate::import::json::import_json;
+use crate::repl::transcript::{parse_transcript, Recorder};
+use crate::repl::{execute_sql, split_script, terminal_width, REPLHelper};
+use crate::seed::seed as seed_table;
+use crate::sql::db::autosave::AutosaveInterval;
+use crate::sql::db::database::{write_new_file, Database, MAIN_SCHEMA, TEMP_SCHEMA};
+use crate::sql::db::diff::schema_diff;
+use crate::sql::db::file::page_counts;
+use crate::sql::db::format::FORMAT_VERSION;
+use crate::sql::db::journal::journal_path;
+use crate::sql::db::limits::Limit;
+use crate::sql::db::memory::PAGE_SIZE;
+use crate::sql::function::random::next_u64;
+use crate::sql::{process_command, process_query};
 use rustyline::Editor;
 use std::fmt;
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::Path;
 
 #[derive(Debug, PartialEq)]
 pub enum MetaCommand {
     Exit,
     Help,
     Open(String),
+    Save(String),
+    Load(String),
+    Read(String),
+    Record(String),
+    Replay(String),
+    Tables,
+    Schema(String),
+    IntegrityCheck,
+    DbInfo,
+    Stats,
+    Limit(String),
+    Profile(String),
+    Autosave(String),
+    Autocommit(String),
+    Export(String),
+    Import(String),
+    Seed(String),
+    Clone(String),
+    Snapshot(String),
+    Backup(String),
+    SchemaDiff(String),
     Unknown,
 }
 
@@ -19,6 +59,27 @@ impl fmt::Display for MetaCommand {
             MetaCommand::Exit => f.write_str(".exit"),
             MetaCommand::Help => f.write_str(".help"),
             MetaCommand::Open(_) => f.write_str(".open"),
+            MetaCommand::Save(_) => f.write_str(".save"),
+            MetaCommand::Load(_) => f.write_str(".load"),
+            MetaCommand::Read(_) => f.write_str(".read"),
+            MetaCommand::Record(_) => f.write_str(".record"),
+            MetaCommand::Replay(_) => f.write_str(".replay"),
+            MetaCommand::Tables => f.write_str(".tables"),
+            MetaCommand::Schema(_) => f.write_str(".schema"),
+            MetaCommand::IntegrityCheck => f.write_str(".integrity_check"),
+            MetaCommand::DbInfo => f.write_str(".dbinfo"),
+            MetaCommand::Stats => f.write_str(".stats"),
+            MetaCommand::Limit(_) => f.write_str(".limit"),
+            MetaCommand::Profile(_) => f.write_str(".profile"),
+            MetaCommand::Autosave(_) => f.write_str(".autosave"),
+            MetaCommand::Autocommit(_) => f.write_str(".autocommit"),
+            MetaCommand::Export(_) => f.write_str(".export"),
+            MetaCommand::Import(_) => f.write_str(".import"),
+            MetaCommand::Seed(_) => f.write_str(".seed"),
+            MetaCommand::Clone(_) => f.write_str(".clone"),
+            MetaCommand::Snapshot(_) => f.write_str(".snapshot"),
+            MetaCommand::Backup(_) => f.write_str(".backup"),
+            MetaCommand::SchemaDiff(_) => f.write_str(".schemadiff"),
             MetaCommand::Unknown => f.write_str("Unknown command"),
         }
     }
@@ -32,39 +93,863 @@ impl MetaCommand {
             ".exit" => MetaCommand::Exit,
             ".help" => MetaCommand::Help,
             ".open" => MetaCommand::Open(command),
+            ".save" => MetaCommand::Save(command),
+            ".load" => MetaCommand::Load(command),
+            ".read" => MetaCommand::Read(command),
+            ".record" => MetaCommand::Record(command),
+            ".replay" => MetaCommand::Replay(command),
+            ".tables" => MetaCommand::Tables,
+            ".schema" => MetaCommand::Schema(command),
+            ".integrity_check" => MetaCommand::IntegrityCheck,
+            ".dbinfo" => MetaCommand::DbInfo,
+            ".stats" => MetaCommand::Stats,
+            ".limit" => MetaCommand::Limit(command),
+            ".profile" => MetaCommand::Profile(command),
+            ".autosave" => MetaCommand::Autosave(command),
+            ".autocommit" => MetaCommand::Autocommit(command),
+            ".export" => MetaCommand::Export(command),
+            ".import" => MetaCommand::Import(command),
+            ".seed" => MetaCommand::Seed(command),
+            ".clone" => MetaCommand::Clone(command),
+            ".snapshot" => MetaCommand::Snapshot(command),
+            ".backup" => MetaCommand::Backup(command),
+            ".schemadiff" => MetaCommand::SchemaDiff(command),
             _ => MetaCommand::Unknown,
         }
     }
 }
 
-pub fn handle_meta_command(command: MetaCommand, repl: &mut Editor<REPLHelper>) -> Result<String> {
+pub fn handle_meta_command(
+    command: MetaCommand,
+    repl: &mut Editor<REPLHelper>,
+    db: &mut Database,
+) -> Result<String> {
     match command {
         MetaCommand::Exit => {
             repl.append_history("history").unwrap();
+            db.close()?;
             std::process::exit(0)
         }
-        MetaCommand::Help => Ok(format!(
-            "{}{}{}{}{}{}{}{}",
-            "Special commands:\n",
-            ".help            - Display this message\n",
-            ".open <FILENAME> - Close existing database and reopen FILENAME\n",
-            ".save <FILENAME> - Write in-memory database into FILENAME\n",
-            ".read <FILENAME> - Read input from FILENAME\n",
-            ".tables          - List names of tables\n",
-            ".ast <QUERY>     - Show the abstract syntax tree for QUERY.\n",
-            ".exit            - Quits this application"
-        )),
-        MetaCommand::Open(args) => Ok(format!("To be implemented: {}", args)),
+        MetaCommand::Help => Ok(help()),
+        MetaCommand::Open(args) => open(&args, db),
+        MetaCommand::Save(args) => save(&args, db),
+        MetaCommand::Load(args) => load_extension(&args, db),
+        MetaCommand::Read(args) => read(&args, repl, db),
+        MetaCommand::Record(args) => record(&args, repl),
+        MetaCommand::Replay(args) => replay(&args, repl, db),
+        MetaCommand::Tables => list_tables(db),
+        MetaCommand::Schema(args) => show_schema(&args, db),
+        MetaCommand::IntegrityCheck => Ok(integrity_check(db)),
+        MetaCommand::DbInfo => dbinfo(db),
+        MetaCommand::Stats => Ok(stats(db)),
+        MetaCommand::Limit(args) => limit(&args, db),
+        MetaCommand::Profile(args) => profile(&args, db),
+        MetaCommand::Autosave(args) => autosave(&args, db),
+        MetaCommand::Autocommit(args) => autocommit(&args, db),
+        MetaCommand::Export(args) => export(&args, db),
+        MetaCommand::Import(args) => import(&args, db),
+        MetaCommand::Seed(args) => seed(&args, db),
+        MetaCommand::Clone(args) => clone(&args, db),
+        MetaCommand::Snapshot(args) => snapshot(&args, db),
+        MetaCommand::Backup(args) => backup(&args, db),
+        MetaCommand::SchemaDiff(args) => schemadiff(&args, db),
         MetaCommand::Unknown => Err(SQLRiteError::UnknownCommand(format!(
             "Unknown command or invalid arguments. Enter '.help'"
         ))),
     }
 }
 
+/// Handles a meta command sent by a remote session to the database of a server. Commands
+/// acting on the client, like `.exit`, are handled by the client itself.
+pub fn handle_remote_meta_command(command: MetaCommand, db: &Database) -> Result<String> {
+    match command {
+        MetaCommand::Help => Ok(help()),
+        MetaCommand::Tables => list_tables(db),
+        MetaCommand::Schema(args) => show_schema(&args, db),
+        MetaCommand::IntegrityCheck => Ok(integrity_check(db)),
+        MetaCommand::DbInfo => dbinfo(db),
+        MetaCommand::Stats => Ok(stats(db)),
+        MetaCommand::Unknown => Err(SQLRiteError::UnknownCommand(
+            "Unknown command or invalid arguments. Enter '.help'".to_string(),
+        )),
+        command => Err(SQLRiteError::NotImplemented(format!(
+            "{} is not available on a remote database.",
+            command
+        ))),
+    }
+}
+
+/// Usage of the meta commands, shown by `.help`
+pub fn help() -> String {
+    format!(
+        "{}{}{}{}{}{}{}{}{}{}{}{}{}{}{}{}{}{}{}{}{}{}{}{}{}{}",
+        "Special commands:\n",
+        ".help            - Display this message\n",
+        ".open <FILENAME> - Close existing database and reopen FILENAME\n",
+        ".save <FILENAME> - Write the database into FILENAME, a SQLite file with --sqlite, binary with --snapshot\n",
+        ".clone NEWFILE   - Copy the database, rebuilt compactly, into the new file NEWFILE\n",
+        ".snapshot FILE   - Write the committed database to the new file FILE, not waiting for writers\n",
+        ".backup FILE     - Back up the database into FILE in the background, while statements run\n",
+        ".read <FILENAME> - Read input from FILENAME\n",
+        ".record FILE|off - Record the commands and their output into the transcript FILE\n",
+        ".replay FILE     - Run a transcript again, with --compare report where the output differs\n",
+        ".tables          - List names of tables\n",
+        ".schema [TABLE]  - Show the CREATE statements, only of TABLE if given\n",
+        ".schemadiff FILE - Show the statements bringing the schema to that of FILE\n",
+        ".integrity_check - Check that the indexes are consistent with the rows\n",
+        ".dbinfo          - Show status information about the database\n",
+        ".stats           - Show the memory and temporary storage used by statements\n",
+        ".limit [NAME N]  - Show the limits on statements, or set limit NAME to N\n",
+        ".profile on|off  - Profile the operators of each statement into sqlrite_profile\n",
+        ".autosave [WHEN] - Save the open file every 30s, 100 statements, 1000 changes, or off\n",
+        ".autocommit MODE - Run statements in a transaction until COMMIT with off, each alone with on\n",
+        ".export FMT S F  - Export table or query S to file F as csv, json, arrow or xlsx\n",
+        ".import <ARGS>   - Import rows: --json FILE TABLE, or a MySQL or PostgreSQL --dump FILE\n",
+        ".seed TABLE N    - Insert N generated rows, options --template 'col=gen,...' --seed S\n",
+        ".ast <QUERY>     - Show the abstract syntax tree for QUERY.\n",
+        ".load <FILE>     - Load an extension from the library FILE\n",
+        ".exit            - Quits this application"
+    )
+}
+
+/// Handles `.tables`, listing the tables of the main database, the temporary tables and
+/// the tables of the attached databases, read from their `sqlite_master` catalog.
+fn list_tables(db: &Database) -> Result<String> {
+    let mut schemas = vec![MAIN_SCHEMA.to_string(), TEMP_SCHEMA.to_string()];
+    schemas.extend(db.attached.iter().cloned());
+
+    let mut names: Vec<String> = vec![];
+    for schema in &schemas {
+        let result = process_query(
+            &format!(
+                "SELECT name FROM {}.sqlite_master WHERE type = 'table' ORDER BY name;",
+                schema
+            ),
+            db,
+        )?;
+        for row in result.rows {
+            let name = row[0].to_string();
+            match schema.as_str() {
+                MAIN_SCHEMA => names.push(name),
+                schema => names.push(format!("{}.{}", schema, name)),
+            }
+        }
+    }
+    Ok(names.join("\n"))
+}
+
+/// Handles `.schema [TABLE]`, showing the SQL every object of the main database was
+/// created with, or only the objects of TABLE.
+fn show_schema(command: &str, db: &Database) -> Result<String> {
+    let args: Vec<&str> = command.split_whitespace().collect();
+    let table = match args.len() {
+        1 => None,
+        2 => Some(args[1]),
+        _ => return Err(SQLRiteError::General("Usage: .schema [TABLE]".to_string())),
+    };
+    let result = process_query("SELECT tbl_name, sql FROM sqlite_master;", db)?;
+    let statements = result
+        .rows
+        .iter()
+        .filter(|row| table.is_none_or(|table| row[0].to_string().eq_ignore_ascii_case(table)))
+        .filter(|row| !row[1].is_null())
+        .map(|row| format!("{};", row[1]))
+        .collect::<Vec<String>>();
+    Ok(statements.join("\n"))
+}
+
+/// Handles `.integrity_check`, listing the problems found in the database, or `ok`.
+fn integrity_check(db: &Database) -> String {
+    let problems = db.check_integrity();
+    if problems.is_empty() {
+        "ok".to_string()
+    } else {
+        problems.join("\n")
+    }
+}
+
+/// Handles `.profile [on|off]`, turning the profiling of statements on or off like
+/// `PRAGMA profile`, or telling whether it is on.
+fn profile(command: &str, db: &mut Database) -> Result<String> {
+    let args: Vec<&str> = command.split_whitespace().collect();
+    match args.get(1..) {
+        Some(["on"]) => db.set_profile(true),
+        Some(["off"]) => db.set_profile(false),
+        Some([]) => {}
+        _ => return Err(SQLRiteError::General("Usage: .profile on|off".to_string())),
+    }
+    Ok(format!(
+        "profile: {}, {} statements profiled",
+        if db.settings.profile { "on" } else { "off" },
+        db.profiler.statements()
+    ))
+}
+
+/// Handles `.autocommit [on|off]`, whether each statement is its own transaction, showing
+/// whether a transaction is open.
+fn autocommit(command: &str, db: &mut Database) -> Result<String> {
+    match split_args(command).get(1..) {
+        Some([mode]) if mode == "on" => db.set_autocommit(true)?,
+        Some([mode]) if mode == "off" => db.set_autocommit(false)?,
+        Some([]) => {}
+        _ => {
+            return Err(SQLRiteError::General(
+                "Usage: .autocommit [on|off]".to_string(),
+            ))
+        }
+    }
+    Ok(format!(
+        "autocommit: {}{}",
+        if db.settings.autocommit { "on" } else { "off" },
+        if db.transaction.is_open() {
+            ", transaction open"
+        } else {
+            ""
+        }
+    ))
+}
+
+/// Handles `.autosave [off|DURATION|N statements|N changes]`, when the changes are saved
+/// back to the file the database was opened from, like `30s`, `5m` or `100 statements`.
+fn autosave(command: &str, db: &mut Database) -> Result<String> {
+    let text = command.trim_start_matches(".autosave").trim();
+    if !text.is_empty() {
+        let interval = AutosaveInterval::parse(text).ok_or_else(|| {
+            SQLRiteError::General(
+                "Usage: .autosave [off|DURATION|N statements|N changes]".to_string(),
+            )
+        })?;
+        db.autosave.set_interval(interval);
+    }
+    Ok(format!(
+        "autosave: {}, {} statements unsaved, {} autosaves{}",
+        db.autosave.interval(),
+        db.autosave.unsaved_statements(),
+        db.autosave.saves(),
+        match db.path {
+            Some(_) => "",
+            None => " (no file is open)",
+        }
+    ))
+}
+
+/// Handles `.export csv [--delimiter C] [--no-header] SOURCE FILE`,
+/// `.export json|arrow SOURCE FILE` and `.export xlsx SOURCE... FILE`, writing the table or
+/// query SOURCE to FILE as CSV, as JSON lines, in the Arrow IPC file format or as a
+/// spreadsheet with a worksheet per SOURCE. SOURCE can be quoted to hold the spaces of a
+/// query.
+fn export(command: &str, db: &Database) -> Result<String> {
+    let usage = || {
+        SQLRiteError::General(
+            "Usage: .export csv [--delimiter C] [--no-header] SOURCE FILE\n       \
+             .export json|arrow SOURCE FILE\n       \
+             .export xlsx SOURCE... FILE"
+                .to_string(),
+        )
+    };
+    let args = split_args(command);
+    let mut options = CsvOptions::default();
+    let (format, mut rest) = match args.get(1..) {
+        Some([format, rest @ ..])
+            if ["csv", "json", "arrow", "xlsx"].contains(&format.as_str()) =>
+        {
+            (format.as_str(), rest)
+        }
+        _ => return Err(usage()),
+    };
+    if format == "xlsx" {
+        return match rest {
+            [sources @ .., path] if !sources.is_empty() => {
+                let sources = sources.iter().map(String::as_str).collect::<Vec<&str>>();
+                let rows = export_xlsx(db, &sources, path)?;
+                Ok(format!("{} rows exported to {}.", rows, path))
+            }
+            _ => Err(usage()),
+        };
+    }
+    let csv = format == "csv";
+    let (source, path) = loop {
+        match rest {
+            [flag, delimiter, tail @ ..] if csv && flag == "--delimiter" => {
+                let mut chars = delimiter.chars();
+                options.delimiter = match (chars.next(), chars.next()) {
+                    (Some(delimiter), None) => delimiter,
+                    _ => return Err(usage()),
+                };
+                rest = tail;
+            }
+            [flag, tail @ ..] if csv && flag == "--no-header" => {
+                options.header = false;
+                rest = tail;
+            }
+            [source, path] => break (source, path),
+            _ => return Err(usage()),
+        }
+    };
+    let rows = match format {
+        "csv" => crate::export::export(db, source, &mut CsvWriter::new(create(path)?, options))?,
+        "json" => crate::export::export(db, source, &mut JsonLinesWriter::new(create(path)?))?,
+        _ => export_arrow(db, source, path)?,
+    };
+    Ok(format!("{} rows exported to {}.", rows, path))
+}
+
+/// Creates the file `path` an export is written to.
+fn create(path: &str) -> Result<BufWriter<File>> {
+    let file = File::create(path)
+        .map_err(|err| SQLRiteError::General(format!("cannot create {}: {}", path, err)))?;
+    Ok(BufWriter::new(file))
+}
+
+#[cfg(feature = "xlsx")]
+fn export_xlsx(db: &Database, sources: &[&str], path: &str) -> Result<usize> {
+    let mut workbook = crate::export::xlsx::XlsxWorkbook::new();
+    let rows = crate::export::xlsx::export_workbook(db, sources, &mut workbook)?;
+    workbook.save(path)?;
+    Ok(rows)
+}
+
+#[cfg(not(feature = "xlsx"))]
+fn export_xlsx(_db: &Database, _sources: &[&str], _path: &str) -> Result<usize> {
+    Err(SQLRiteError::NotImplemented(
+        "SQLRite was built without XLSX support.".to_string(),
+    ))
+}
+
+#[cfg(feature = "arrow")]
+fn export_arrow(db: &Database, source: &str, path: &str) -> Result<usize> {
+    let writer = &mut crate::export::arrow::ArrowWriter::new(create(path)?);
+    crate::export::export(db, source, writer)
+}
+
+#[cfg(not(feature = "arrow"))]
+fn export_arrow(_db: &Database, _source: &str, _path: &str) -> Result<usize> {
+    Err(SQLRiteError::NotImplemented(
+        "SQLRite was built without Arrow support.".to_string(),
+    ))
+}
+
+/// Handles `.read FILENAME`, running the statements and the meta commands of the script
+/// FILENAME in order and returning their output. A statement that fails is reported with
+/// the line it starts on, and the script goes on.
+fn read(command: &str, repl: &mut Editor<REPLHelper>, db: &mut Database) -> Result<String> {
+    let path = match split_args(command).as_slice() {
+        [_, path] => path.to_string(),
+        _ => return Err(SQLRiteError::General("Usage: .read FILENAME".to_string())),
+    };
+    let text = std::fs::read_to_string(&path)
+        .map_err(|err| SQLRiteError::General(format!("cannot read {}: {}", path, err)))?;
+    let mut output = vec![];
+    for (line, statement) in split_script(&text) {
+        let result = if statement.starts_with('.') {
+            handle_meta_command(MetaCommand::new(statement), repl, db)
+        } else {
+            process_command(&statement, db).map(|result| result.to_string())
+        };
+        match result {
+            Ok(result) => output.push(result),
+            Err(SQLRiteError::Interrupted) => return Err(SQLRiteError::Interrupted),
+            Err(err) => output.push(format!("Error near line {}: {}", line, err)),
+        }
+    }
+    Ok(output.join("\n"))
+}
+
+/// Handles `.record FILE`, recording the commands of the session and their output into the
+/// transcript FILE, and `.record off`, ending the transcript.
+fn record(command: &str, repl: &mut Editor<REPLHelper>) -> Result<String> {
+    let helper = repl
+        .helper_mut()
+        .ok_or_else(|| SQLRiteError::Internal("No helper found".to_string()))?;
+    match split_args(command).get(1..) {
+        Some([off]) if off == "off" => match helper.recorder.take() {
+            Some(recorder) => Ok(format!("Transcript written to {}.", recorder.path())),
+            None => Ok("Not recording.".to_string()),
+        },
+        Some([path]) => {
+            helper.recorder = Some(Recorder::create(path, terminal_width())?);
+            Ok(format!("Recording into {}.", path))
+        }
+        _ => Err(SQLRiteError::General("Usage: .record FILE|off".to_string())),
+    }
+}
+
+/// Handles `.replay FILE [--compare]`, running the commands of the transcript FILE again,
+/// their results rendered for the width they were recorded for. Without `--compare`, their
+/// output is returned like `.read` does. With it, only the commands whose output differs
+/// from the recorded one are reported. `.record`, `.replay` and `.exit` aren't run again.
+fn replay(command: &str, repl: &mut Editor<REPLHelper>, db: &mut Database) -> Result<String> {
+    let args = split_args(command);
+    let (path, compare) = match args.get(1..) {
+        Some([path]) => (path, false),
+        Some([path, flag]) if flag == "--compare" => (path, true),
+        _ => {
+            return Err(SQLRiteError::General(
+                "Usage: .replay FILE [--compare]".to_string(),
+            ))
+        }
+    };
+    let text = std::fs::read_to_string(path)
+        .map_err(|err| SQLRiteError::General(format!("cannot read {}: {}", path, err)))?;
+    let transcript = parse_transcript(&text)?;
+    let mut output = vec![];
+    let (mut replayed, mut diverged) = (0, 0);
+    for entry in &transcript.entries {
+        let result = if entry.command.starts_with('.') {
+            match MetaCommand::new(entry.command.clone()) {
+                MetaCommand::Exit | MetaCommand::Record(_) | MetaCommand::Replay(_) => continue,
+                command => handle_meta_command(command, repl, db),
+            }
+        } else {
+            execute_sql(&entry.command, db, transcript.width)
+        };
+        if let Err(SQLRiteError::Interrupted) = result {
+            return Err(SQLRiteError::Interrupted);
+        }
+        replayed += 1;
+        if !compare {
+            output.push(match result {
+                Ok(result) => result,
+                Err(err) => format!("Error near line {}: {}", entry.line, err),
+            });
+        } else if !entry.matches(&result) {
+            diverged += 1;
+            let show = |output: std::result::Result<&str, String>| match output {
+                Ok(output) => indent(output),
+                Err(err) => indent(&format!("error: {}", err)),
+            };
+            output.push(format!(
+                "Line {}: {}\n  expected:\n{}\n  got:\n{}",
+                entry.line,
+                entry.command,
+                show(entry.output.as_deref().map_err(|err| err.to_string())),
+                show(result.as_deref().map_err(|err| err.to_string())),
+            ));
+        }
+    }
+    if compare {
+        output.push(format!(
+            "{} command{} replayed, {} diverged.",
+            replayed,
+            if replayed == 1 { "" } else { "s" },
+            diverged
+        ));
+    }
+    Ok(output.join("\n"))
+}
+
+/// `text` with each of its lines indented, for it to stand out in a report.
+fn indent(text: &str) -> String {
+    text.lines()
+        .map(|line| format!("    {}", line))
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+/// Handles `.open FILENAME`, saving the database open so far to its file if it has one,
+/// and opening the database in FILENAME, or a new one saved there on exit. Tells when a
+/// save of the file that was interrupted had to be rolled back, and when the file was
+/// upgraded from an older version of the file format.
+fn open(command: &str, db: &mut Database) -> Result<String> {
+    let args = split_args(command);
+    let path = match args.get(1..) {
+        Some([path]) => path,
+        _ => return Err(SQLRiteError::General("Usage: .open FILENAME".to_string())),
+    };
+    let opened = db.open_file(Path::new(path))?;
+    let mut message = match opened.existed {
+        true => format!("Opened {}.", path),
+        false => format!("Opened a new database, saved to {} on exit.", path),
+    };
+    if let Some(pages) = opened.recovered {
+        message.push_str(&format!(
+            " The last save of the file was interrupted and has been rolled back: {} pages restored from {}.",
+            pages,
+            journal_path(Path::new(path)).display()
+        ));
+    }
+    if let Some(version) = opened.upgraded {
+        message.push_str(&format!(
+            " It was written in version {} of the file format, and has been upgraded to version {}.",
+            version, FORMAT_VERSION
+        ));
+    }
+    Ok(message)
+}
+
+/// Handles `.save [--sqlite|--snapshot] FILENAME`, writing the database into FILENAME,
+/// replacing what it held, as a SQLite database file with `--sqlite`, or as a binary
+/// snapshot with `--snapshot`. The database stays saved to the file it was opened from, if
+/// any.
+fn save(command: &str, db: &Database) -> Result<String> {
+    let args = split_args(command);
+    let path = match args.get(1..) {
+        Some([path]) => {
+            db.save_to(Path::new(path))?;
+            path
+        }
+        Some([flag, path]) if flag == "--sqlite" => {
+            db.save_sqlite(Path::new(path))?;
+            path
+        }
+        Some([flag, path]) if flag == "--snapshot" => {
+            db.save_snapshot(Path::new(path))?;
+            path
+        }
+        _ => {
+            return Err(SQLRiteError::General(
+                "Usage: .save [--sqlite|--snapshot] FILENAME".to_string(),
+            ))
+        }
+    };
+    Ok(format!("Database saved to {}.", path))
+}
+
+/// Handles `.clone NEWFILE`, copying the main database into NEWFILE like `VACUUM INTO`.
+fn clone(command: &str, db: &Database) -> Result<String> {
+    let args = split_args(command);
+    match args.get(1..) {
+        Some([path]) => {
+            db.vacuum_into(Path::new(path))?;
+            Ok(format!("Database cloned into {}.", path))
+        }
+        _ => Err(SQLRiteError::General("Usage: .clone NEWFILE".to_string())),
+    }
+}
+
+/// Handles `.snapshot FILE` on the database of the REPL, which has no other connection
+/// writing to it, like `.clone`. A server answers it for its clients itself, see
+/// `snapshot_path`.
+fn snapshot(command: &str, db: &Database) -> Result<String> {
+    let path = snapshot_path(command)?;
+    write_new_file(Path::new(&path), &db.serialize_compact()?)?;
+    Ok(format!("Snapshot written to {}.", path))
+}
+
+/// Handles `.backup FILE`, starting an online backup of the database into FILE, which the
+/// REPL reports once it is written.
+fn backup(command: &str, db: &mut Database) -> Result<String> {
+    match split_args(command).get(1..) {
+        Some([path]) => {
+            let backup = db.backup(Path::new(path))?;
+            db.backups.push(backup);
+            Ok(format!("Backing up the database into {}.", path))
+        }
+        _ => Err(SQLRiteError::General("Usage: .backup FILE".to_string())),
+    }
+}
+
+/// The file of a `.snapshot FILE` command.
+pub fn snapshot_path(command: &str) -> Result<String> {
+    match split_args(command).get(1..) {
+        Some([path]) => Ok(path.to_string()),
+        _ => Err(SQLRiteError::General("Usage: .snapshot FILE".to_string())),
+    }
+}
+
+/// Handles `.schemadiff FILE`, showing the statements bringing the schema of the main
+/// database to that of the database in FILE.
+fn schemadiff(command: &str, db: &Database) -> Result<String> {
+    let args = split_args(command);
+    let path = match args.get(1..) {
+        Some([path]) => path,
+        _ => return Err(SQLRiteError::General("Usage: .schemadiff FILE".to_string())),
+    };
+    let other = Database::read_file(Path::new(path))?;
+    let statements = schema_diff(db, &other)?;
+    if statements.is_empty() {
+        return Ok("The schemas are identical.".to_string());
+    }
+    Ok(statements.join("\n"))
+}
+
+/// Rows skipped by `.import` that are listed, at most
+const SKIPPED_SHOWN: usize = 10;
+
+/// Handles `.import --json FILE TABLE`, importing the rows of FILE, a JSON array of objects
+/// or JSON lines, into TABLE, created if it doesn't exist, and `.import --dump FILE`,
+/// importing the tables and rows of FILE, written by mysqldump or pg_dump. The rows skipped
+/// are listed.
+fn import(command: &str, db: &mut Database) -> Result<String> {
+    let args = split_args(command);
+    let read = |path: &str| {
+        std::fs::read_to_string(path)
+            .map_err(|err| SQLRiteError::General(format!("cannot read {}: {}", path, err)))
+    };
+    let (mut lines, report) = match args.get(1..) {
+        Some([format, path, table_name]) if format == "--json" => {
+            let report = import_json(db, &read(path)?, table_name)?;
+            let line = format!(
+                "{} rows imported into {}{}, {} skipped.",
+                report.inserted,
+                table_name,
+                if report.created.is_empty() {
+                    ""
+                } else {
+                    ", created"
+                },
+                report.skipped.len()
+            );
+            (vec![line], report)
+        }
+        Some([format, path]) if format == "--dump" => {
+            let report = import_dump(db, &read(path)?)?;
+            let mut line = format!("{} rows imported", report.inserted);
+            if !report.created.is_empty() {
+                line.push_str(&format!(", tables created: {}", report.created.join(", ")));
+            }
+            line.push_str(&format!(
+                ", {} skipped, {} statements ignored.",
+                report.skipped.len(),
+                report.ignored_statements
+            ));
+            (vec![line], report)
+        }
+        _ => {
+            return Err(SQLRiteError::General(
+                "Usage: .import --json FILE TABLE, or .import --dump FILE".to_string(),
+            ))
+        }
+    };
+    // Rows of a dump are numbered by line
+    let unit = if args[1] == "--dump" { "line" } else { "row" };
+    for (number, reason) in report.skipped.iter().take(SKIPPED_SHOWN) {
+        lines.push(format!("{} {} skipped: {}", unit, number, reason));
+    }
+    if report.skipped.len() > SKIPPED_SHOWN {
+        lines.push(format!(
+            "... and {} more rows skipped",
+            report.skipped.len() - SKIPPED_SHOWN
+        ));
+    }
+    if !report.ignored_keys.is_empty() {
+        lines.push(format!(
+            "keys matching no column: {}",
+            report.ignored_keys.join(", ")
+        ));
+    }
+    Ok(lines.join("\n"))
+}
+
+/// Handles `.seed TABLE N [--template TEMPLATE] [--seed S]`, inserting N generated rows
+/// into TABLE. The same seed always generates the same rows, a random one is used if none
+/// is given.
+fn seed(command: &str, db: &mut Database) -> Result<String> {
+    const USAGE: &str = "Usage: .seed TABLE N [--template 'column=generator,...'] [--seed S]";
+    let usage = || SQLRiteError::General(USAGE.to_string());
+    let args = split_args(command);
+    let (table_name, count) = match args.get(1..3) {
+        Some([table_name, count]) => (table_name, count.parse::<usize>().map_err(|_| usage())?),
+        _ => return Err(usage()),
+    };
+    let mut template = None;
+    let mut seed = None;
+    let mut options = args[3..].iter();
+    while let Some(option) = options.next() {
+        match (option.as_str(), options.next()) {
+            ("--template", Some(value)) => template = Some(value.as_str()),
+            ("--seed", Some(value)) => seed = Some(value.parse::<u64>().map_err(|_| usage())?),
+            _ => return Err(usage()),
+        }
+    }
+    let report = seed_table(
+        db,
+        table_name,
+        count,
+        template,
+        seed.unwrap_or_else(next_u64),
+    )?;
+    let mut lines = vec![format!(
+        "{} rows seeded into {}, {} skipped.",
+        report.inserted,
+        table_name,
+        report.skipped.len()
+    )];
+    for (number, reason) in report.skipped.iter().take(SKIPPED_SHOWN) {
+        lines.push(format!("row {} skipped: {}", number, reason));
+    }
+    if report.skipped.len() > SKIPPED_SHOWN {
+        lines.push(format!(
+            "... and {} more rows skipped",
+            report.skipped.len() - SKIPPED_SHOWN
+        ));
+    }
+    Ok(lines.join("\n"))
+}
+
+/// Splits the arguments of a meta command on whitespace, keeping together what is quoted
+/// with double or single quotes, in which a doubled quote stands for a quote.
+fn split_args(command: &str) -> Vec<String> {
+    let mut args = vec![];
+    let mut chars = command.trim().chars().peekable();
+    while let Some(c) = chars.next() {
+        if c.is_whitespace() {
+            continue;
+        }
+        let mut arg = String::new();
+        if c == '"' || c == '\'' {
+            let quote = c;
+            while let Some(c) = chars.next() {
+                match c {
+                    c if c == quote && chars.peek() == Some(&quote) => {
+                        arg.push(quote);
+                        chars.next();
+                    }
+                    c if c == quote => break,
+                    c => arg.push(c),
+                }
+            }
+        } else {
+            arg.push(c);
+            while let Some(c) = chars.next_if(|c| !c.is_whitespace()) {
+                arg.push(c);
+            }
+        }
+        args.push(arg);
+    }
+    args
+}
+
+/// Handles `.dbinfo`, the metadata of the database and the number of rows and indexes of
+/// each of its tables, the quick health check to run first when something looks off.
+fn dbinfo(db: &Database) -> Result<String> {
+    let (file, size, (page_count, free_pages)) = match &db.path {
+        Some(path) => (
+            path.display().to_string(),
+            std::fs::metadata(path).map_or(0, |metadata| metadata.len()),
+            page_counts(path)?,
+        ),
+        None => (":memory:".to_string(), 0, (0, 0)),
+    };
+    let mut names = db.tables.keys().collect::<Vec<&String>>();
+    names.sort();
+    let indexes = names
+        .iter()
+        .map(|name| db.tables[*name].autoindexes().len())
+        .sum::<usize>();
+    let mut lines = vec![
+        ("database file", file),
+        ("file size", size.to_string()),
+        ("page size", PAGE_SIZE.to_string()),
+        ("page count", page_count.to_string()),
+        ("free pages", free_pages.to_string()),
+        ("text encoding", "1 (utf8)".to_string()),
+        ("journal mode", db.settings.journal_mode.to_string()),
+        ("cache size", db.settings.cache_size.to_string()),
+        ("schema version", db.schema_version.to_string()),
+        ("user version", db.user_version.to_string()),
+        ("attached databases", db.attached.len().to_string()),
+        ("number of tables", names.len().to_string()),
+        ("number of indexes", indexes.to_string()),
+        ("number of triggers", db.triggers.len().to_string()),
+        ("number of views", "0".to_string()),
+        ("virtual tables", db.vtabs.table_names().len().to_string()),
+        ("cache memory", db.cache_memory().to_string()),
+        ("memory high-water", db.memory.peak().to_string()),
+    ]
+    .into_iter()
+    .map(|(label, value)| format!("{:<20} {}", format!("{}:", label), value))
+    .collect::<Vec<String>>();
+    for name in names {
+        let table = &db.tables[name];
+        lines.push(format!(
+            "{:<20} {} rows, {} indexes",
+            format!("table {}:", name),
+            table.rowids().len(),
+            table.autoindexes().len()
+        ));
+    }
+    Ok(lines.join("\n"))
+}
+
+/// Handles `.limit [NAME [N]]`, listing the limits on the statements of the connection,
+/// showing the limit NAME, or setting it to N first.
+fn limit(command: &str, db: &mut Database) -> Result<String> {
+    let args: Vec<&str> = command.split_whitespace().collect();
+    let usage = || SQLRiteError::General("Usage: .limit [NAME [N]]".to_string());
+    let limits = match args.get(1..) {
+        Some([]) => Limit::ALL.to_vec(),
+        Some([name]) | Some([name, _]) => match Limit::from_name(name) {
+            Some(limit) => vec![limit],
+            None => return Err(SQLRiteError::General(format!("unknown limit: {}", name))),
+        },
+        _ => return Err(usage()),
+    };
+    if let Some(value) = args.get(2) {
+        let value = value.parse::<i64>().map_err(|_| usage())?;
+        db.limits.set(limits[0], value);
+    }
+    Ok(limits
+        .into_iter()
+        .map(|limit| format!("{:>20} {}", limit.to_string(), db.limits.get(limit)))
+        .collect::<Vec<String>>()
+        .join("\n"))
+}
+
+/// Handles `.stats`, the memory and temporary storage the statements of the connection
+/// are using, and the most they used at once.
+fn stats(db: &Database) -> String {
+    let temp_store = if db.temp.in_memory() {
+        "memory".to_string()
+    } else {
+        db.temp.location().display().to_string()
+    };
+    vec![
+        (
+            "memory used",
+            format!("{} (max {}) bytes", db.memory.used(), db.memory.peak()),
+        ),
+        ("soft heap limit", db.memory.soft_limit().to_string()),
+        ("hard heap limit", db.memory.hard_limit().to_string()),
+        ("cache memory", db.cache_memory().to_string()),
+        ("temp store", temp_store),
+        (
+            "temp files",
+            format!("{} ({} created)", db.temp.open_files(), db.temp.created()),
+        ),
+        (
+            "temp storage used",
+            format!("{} (max {}) bytes", db.temp.used(), db.temp.peak()),
+        ),
+    ]
+    .into_iter()
+    .map(|(label, value)| format!("{:<20} {}", format!("{}:", label), value))
+    .collect::<Vec<String>>()
+    .join("\n")
+}
+
+/// Handles `.load <FILE> [ENTRY]`, loading the extension library FILE into `db`.
+#[cfg(feature = "extensions")]
+fn load_extension(command: &str, db: &mut Database) -> Result<String> {
+    let args: Vec<&str> = command.split_whitespace().collect();
+    match args.len() {
+        2 | 3 => {
+            // The user explicitly asked to run the library's code
+            unsafe { crate::extension::load_extension(db, args[1], args.get(2).copied())? };
+            Ok(format!("Extension {} loaded.", args[1]))
+        }
+        _ => Err(SQLRiteError::General(
+            "Usage: .load <FILE> [ENTRY]".to_string(),
+        )),
+    }
+}
+
+#[cfg(not(feature = "extensions"))]
+fn load_extension(_command: &str, _db: &mut Database) -> Result<String> {
+    Err(SQLRiteError::NotImplemented(
+        "SQLRite was built without extension support.".to_string(),
+    ))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::repl::{get_config, REPLHelper};
+    use crate::sql::db::fault::FaultInjector;
+    use crate::sql::db::journal::Journal;
+    use crate::sql::db::pager::Pager;
+    use crate::sql::process_command;
+    use crate::sql::value::Value;
 
     #[test]
     fn get_meta_command_exit_test() {
@@ -80,7 +965,8 @@ mod tests {
 
         let inputed_command = MetaCommand::Help;
 
-        let result = handle_meta_command(inputed_command, &mut repl);
+        let mut db = Database::new("tempdb".to_string());
+        let result = handle_meta_command(inputed_command, &mut repl, &mut db);
         assert_eq!(result.is_ok(), true);
     }
 
@@ -98,10 +984,344 @@ mod tests {
 
         let inputed_command = MetaCommand::Open(".open database.db".to_string());
 
-        let result = handle_meta_command(inputed_command, &mut repl);
+        let mut db = Database::new("tempdb".to_string());
+        let result = handle_meta_command(inputed_command, &mut repl, &mut db);
         assert_eq!(result.is_ok(), true);
     }
 
+    #[test]
+    fn open_and_save_test() {
+        let config = get_config();
+        let helper = REPLHelper::default();
+        let mut repl = Editor::with_config(config);
+        repl.set_helper(Some(helper));
+
+        let path = |name: &str| {
+            std::env::temp_dir().join(format!("sqlrite-open-{}-{}.db", name, std::process::id()))
+        };
+        let (first, second) = (path("first"), path("second"));
+        let mut open = |db: &mut Database, path: &std::path::Path| {
+            let command = format!(".open {}", path.display());
+            handle_meta_command(MetaCommand::new(command), &mut repl, db)
+        };
+        let mut db = Database::new("tempdb".to_string());
+        assert_eq!(
+            open(&mut db, &first).unwrap(),
+            format!(
+                "Opened a new database, saved to {} on exit.",
+                first.display()
+            )
+        );
+        assert!(!first.exists());
+        process_command(
+            "CREATE TABLE users (id INTEGER PRIMARY KEY, name TEXT);",
+            &mut db,
+        )
+        .unwrap();
+        process_command("INSERT INTO users (name) VALUES ('josh');", &mut db).unwrap();
+
+        // Opening another database saves the one open before
+        open(&mut db, &second).unwrap();
+        assert!(first.exists());
+        assert!(db.tables.is_empty());
+        assert_eq!(
+            open(&mut db, &first).unwrap(),
+            format!("Opened {}.", first.display())
+        );
+        let result = process_query("SELECT name FROM users;", &db).unwrap();
+        assert_eq!(result.rows, vec![vec![Value::Text("josh".to_string())]]);
+        assert_eq!(db.path, Some(first.clone()));
+
+        // A file that isn't a database leaves the database open as it was
+        std::fs::write(&second, "not a database").unwrap();
+        assert!(open(&mut db, &second).is_err());
+        assert_eq!(db.path, Some(first.clone()));
+        assert!(db.tables.contains_key("users"));
+
+        process_command("INSERT INTO users (name) VALUES ('mary');", &mut db).unwrap();
+        db.save().unwrap();
+        let saved = Database::read_file(&first).unwrap();
+        assert_eq!(saved.tables["users"].rowids().len(), 2);
+        std::fs::remove_file(&first).unwrap();
+        std::fs::remove_file(&second).unwrap();
+    }
+
+    #[test]
+    fn autosave_test() {
+        let config = get_config();
+        let helper = REPLHelper::default();
+        let mut repl = Editor::with_config(config);
+        repl.set_helper(Some(helper));
+
+        let path = std::env::temp_dir().join(format!("sqlrite-autosave-{}.db", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let mut db = Database::new("tempdb".to_string());
+        let mut autosave = |command: &str, db: &mut Database| {
+            handle_meta_command(MetaCommand::new(command.to_string()), &mut repl, db)
+        };
+        assert_eq!(
+            autosave(".autosave 2 statements", &mut db).unwrap(),
+            "autosave: every 2 statements, 0 statements unsaved, 0 autosaves (no file is open)"
+        );
+        assert!(autosave(".autosave sometimes", &mut db).is_err());
+        db.open_file(&path).unwrap();
+        process_command(
+            "CREATE TABLE users (id INTEGER PRIMARY KEY, name TEXT);",
+            &mut db,
+        )
+        .unwrap();
+        assert!(!path.exists());
+        process_command("SELECT * FROM users;", &mut db).unwrap();
+        process_command("INSERT INTO users (name) VALUES ('josh');", &mut db).unwrap();
+        assert_eq!(
+            Database::read_file(&path).unwrap().tables["users"].rowids(),
+            vec![1]
+        );
+        process_command("INSERT INTO users (name) VALUES ('mary');", &mut db).unwrap();
+        assert_eq!(
+            autosave(".autosave", &mut db).unwrap(),
+            "autosave: every 2 statements, 1 statements unsaved, 1 autosaves"
+        );
+
+        // The autosave interval belongs to the connection, kept by .open
+        autosave(".autosave off", &mut db).unwrap();
+        db.open_file(&path).unwrap();
+        assert_eq!(db.tables["users"].rowids(), vec![1, 2]);
+        assert_eq!(
+            autosave(".autosave", &mut db).unwrap(),
+            "autosave: off, 0 statements unsaved, 1 autosaves"
+        );
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn autocommit_test() {
+        let config = get_config();
+        let helper = REPLHelper::default();
+        let mut repl = Editor::with_config(config);
+        repl.set_helper(Some(helper));
+
+        let mut db = Database::new("tempdb".to_string());
+        let mut autocommit = |command: &str, db: &mut Database| {
+            handle_meta_command(MetaCommand::new(command.to_string()), &mut repl, db)
+        };
+        process_command(
+            "CREATE TABLE users (id INTEGER PRIMARY KEY, name TEXT);",
+            &mut db,
+        )
+        .unwrap();
+        assert_eq!(
+            autocommit(".autocommit", &mut db).unwrap(),
+            "autocommit: on"
+        );
+        assert!(db.is_autocommit());
+
+        // With autocommit off, the statements run in a transaction until it ends
+        assert_eq!(
+            autocommit(".autocommit off", &mut db).unwrap(),
+            "autocommit: off"
+        );
+        assert!(!db.is_autocommit());
+        process_command("INSERT INTO users (name) VALUES ('josh');", &mut db).unwrap();
+        process_command("INSERT INTO users (name) VALUES ('mary');", &mut db).unwrap();
+        assert_eq!(
+            autocommit(".autocommit", &mut db).unwrap(),
+            "autocommit: off, transaction open"
+        );
+        assert!(autocommit(".autocommit on", &mut db).is_err());
+        process_command("ROLLBACK;", &mut db).unwrap();
+        assert!(db.tables["users"].rowids().is_empty());
+        process_command("INSERT INTO users (name) VALUES ('josh');", &mut db).unwrap();
+        process_command("COMMIT;", &mut db).unwrap();
+        assert_eq!(db.tables["users"].rowids(), vec![1]);
+        assert_eq!(
+            autocommit(".autocommit on", &mut db).unwrap(),
+            "autocommit: on"
+        );
+        process_command("INSERT INTO users (name) VALUES ('mary');", &mut db).unwrap();
+        assert!(!db.transaction.is_open());
+        assert!(autocommit(".autocommit maybe", &mut db).is_err());
+    }
+
+    #[test]
+    fn recover_test() {
+        let config = get_config();
+        let helper = REPLHelper::default();
+        let mut repl = Editor::with_config(config);
+        repl.set_helper(Some(helper));
+
+        let path = std::env::temp_dir().join(format!("sqlrite-recover-{}.db", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let mut db = Database::new("tempdb".to_string());
+        db.open_file(&path).unwrap();
+        process_command(
+            "CREATE TABLE users (id INTEGER PRIMARY KEY, name TEXT);",
+            &mut db,
+        )
+        .unwrap();
+        process_command("INSERT INTO users (name) VALUES ('josh');", &mut db).unwrap();
+        db.save().unwrap();
+        let before = std::fs::read(&path).unwrap();
+
+        // A save interrupted once its pages were written leaves their journal behind
+        process_command("INSERT INTO users (name) VALUES ('mary');", &mut db).unwrap();
+        db.save().unwrap();
+        let journal = Journal {
+            page_count: (before.len() / PAGE_SIZE) as u32,
+            pages: before
+                .chunks(PAGE_SIZE)
+                .enumerate()
+                .map(|(id, page)| (id as u32, page.to_vec()))
+                .collect(),
+        };
+        journal
+            .write(&journal_path(&path), &mut FaultInjector::default())
+            .unwrap();
+
+        let mut db = Database::new("tempdb".to_string());
+        let command = MetaCommand::new(format!(".open {}", path.display()));
+        assert_eq!(
+            handle_meta_command(command, &mut repl, &mut db).unwrap(),
+            format!(
+                "Opened {}. The last save of the file was interrupted and has been rolled back: {} pages restored from {}.",
+                path.display(),
+                journal.pages.len(),
+                journal_path(&path).display()
+            )
+        );
+        assert!(!journal_path(&path).exists());
+        assert_eq!(std::fs::read(&path).unwrap(), before);
+        let result = process_query("SELECT name FROM users;", &db).unwrap();
+        assert_eq!(result.rows, vec![vec![Value::Text("josh".to_string())]]);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn save_test() {
+        let config = get_config();
+        let helper = REPLHelper::default();
+        let mut repl = Editor::with_config(config);
+        repl.set_helper(Some(helper));
+
+        let path = std::env::temp_dir().join(format!("sqlrite-save-{}.db", std::process::id()));
+        let mut db = Database::new("tempdb".to_string());
+        process_command(
+            "CREATE TABLE users (id INTEGER PRIMARY KEY, name TEXT);",
+            &mut db,
+        )
+        .unwrap();
+        process_command("INSERT INTO users (name) VALUES ('josh');", &mut db).unwrap();
+        let command = MetaCommand::new(format!(".save {}", path.display()));
+        assert_eq!(
+            command,
+            MetaCommand::Save(format!(".save {}", path.display()))
+        );
+        assert_eq!(
+            handle_meta_command(command, &mut repl, &mut db).unwrap(),
+            format!("Database saved to {}.", path.display())
+        );
+        assert_eq!(db.path, None);
+
+        // Saving again replaces the file
+        process_command("INSERT INTO users (name) VALUES ('mary');", &mut db).unwrap();
+        let command = MetaCommand::new(format!(".save {}", path.display()));
+        handle_meta_command(command, &mut repl, &mut db).unwrap();
+        let saved = Database::read_file(&path).unwrap();
+        assert_eq!(saved.tables["users"].rowids().len(), 2);
+
+        let command = MetaCommand::new(format!(".save --snapshot {}", path.display()));
+        handle_meta_command(command, &mut repl, &mut db).unwrap();
+        let saved = Database::read_file(&path).unwrap();
+        assert_eq!(saved.tables["users"].rowids().len(), 2);
+
+        let command = MetaCommand::new(format!(".backup {}", path.display()));
+        assert_eq!(
+            handle_meta_command(command, &mut repl, &mut db).unwrap(),
+            format!("Backing up the database into {}.", path.display())
+        );
+        db.backups.wait().unwrap();
+        assert!(Pager::is_database_file(&path));
+        std::fs::remove_file(&path).unwrap();
+
+        let missing = std::env::temp_dir()
+            .join("sqlrite-no-such-dir")
+            .join("x.db");
+        let command = MetaCommand::new(format!(".save {}", missing.display()));
+        assert!(handle_meta_command(command, &mut repl, &mut db).is_err());
+        let command = MetaCommand::new(".save".to_string());
+        assert!(handle_meta_command(command, &mut repl, &mut db).is_err());
+    }
+
+    #[test]
+    fn dbinfo_test() {
+        let config = get_config();
+        let mut repl = Editor::with_config(config);
+        repl.set_helper(Some(REPLHelper::default()));
+
+        let path = std::env::temp_dir().join(format!("sqlrite-dbinfo-{}.db", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let mut db = Database::new("tempdb".to_string());
+        db.open_file(&path).unwrap();
+        process_command(
+            "CREATE TABLE users (id INTEGER PRIMARY KEY, name TEXT);",
+            &mut db,
+        )
+        .unwrap();
+        for _ in 0..200 {
+            process_command(
+                "INSERT INTO users (name) VALUES (printf('%.100c', 'x'));",
+                &mut db,
+            )
+            .unwrap();
+        }
+        db.save().unwrap();
+        process_command("DELETE FROM users WHERE id > 10;", &mut db).unwrap();
+        db.save().unwrap();
+
+        // The file and its pages as the pager counts them
+        let size = std::fs::metadata(&path).unwrap().len();
+        let (page_count, free_pages) = page_counts(&path).unwrap();
+        assert_eq!(size, page_count as u64 * PAGE_SIZE as u64);
+        assert!(page_count > 1);
+        assert!(free_pages > 0);
+
+        // Reported by a database the saved file is opened in
+        let mut db = Database::new("tempdb".to_string());
+        let command = MetaCommand::new(format!(".open {}", path.display()));
+        handle_meta_command(command, &mut repl, &mut db).unwrap();
+        let result = handle_meta_command(MetaCommand::DbInfo, &mut repl, &mut db).unwrap();
+        let line = |label: &str, value: String| format!("{:<20} {}", format!("{}:", label), value);
+        assert!(result.contains(&line("database file", path.display().to_string())));
+        assert!(result.contains(&line("file size", size.to_string())));
+        assert!(result.contains(&line("page count", page_count.to_string())));
+        assert!(result.contains(&line("free pages", free_pages.to_string())));
+        assert!(result.contains("table users:         10 rows, 0 indexes"));
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn get_meta_command_load_test() {
+        // Starting Rustyline with a default configuration
+        let config = get_config();
+
+        // Getting a new Rustyline Helper
+        let helper = REPLHelper::default();
+
+        // Initiatlizing Rustyline Editor with set config and setting helper
+        let mut repl = Editor::with_config(config);
+        repl.set_helper(Some(helper));
+
+        let mut db = Database::new("tempdb".to_string());
+        let inputed_command = MetaCommand::new(".load ./does_not_exist.so".to_string());
+        assert_eq!(
+            inputed_command,
+            MetaCommand::Load(".load ./does_not_exist.so".to_string())
+        );
+
+        let result = handle_meta_command(inputed_command, &mut repl, &mut db);
+        assert!(result.is_err());
+    }
+
     #[test]
     fn get_meta_command_unknown_command_test() {
         // Starting Rustyline with a default configuration
@@ -116,7 +1336,8 @@ mod tests {
 
         let inputed_command = MetaCommand::Unknown;
 
-        let result = handle_meta_command(inputed_command, &mut repl);
+        let mut db = Database::new("tempdb".to_string());
+        let result = handle_meta_command(inputed_command, &mut repl, &mut db);
         assert_eq!(result.is_err(), true);
     }
 
@@ -132,4 +1353,298 @@ mod tests {
         assert_eq!(format!("{}", open), ".open");
         assert_eq!(format!("{}", unknown), "Unknown command");
     }
+
+    #[test]
+    fn get_meta_command_tables_and_schema_test() {
+        let config = get_config();
+        let helper = REPLHelper::default();
+        let mut repl = Editor::with_config(config);
+        repl.set_helper(Some(helper));
+
+        let mut db = Database::new("tempdb".to_string());
+        for sql in &[
+            "CREATE TABLE users (id INTEGER PRIMARY KEY, email TEXT UNIQUE);",
+            "CREATE TABLE audit (id INTEGER PRIMARY KEY, email TEXT);",
+            "CREATE TRIGGER log_users AFTER INSERT ON users BEGIN INSERT INTO audit (email) VALUES (NEW.email); END;",
+            "ATTACH ':memory:' AS aux;",
+            "CREATE TABLE aux.logs (id INTEGER PRIMARY KEY);",
+        ] {
+            process_command(sql, &mut db).unwrap();
+        }
+
+        let result =
+            handle_meta_command(MetaCommand::new(".tables".to_string()), &mut repl, &mut db);
+        assert_eq!(result.unwrap(), "audit\nusers\naux.logs");
+
+        let result = handle_meta_command(
+            MetaCommand::new(".schema users".to_string()),
+            &mut repl,
+            &mut db,
+        );
+        assert_eq!(
+            result.unwrap(),
+            "CREATE TABLE users (id INTEGER PRIMARY KEY, email TEXT UNIQUE);\n\
+             CREATE TRIGGER log_users AFTER INSERT ON users BEGIN INSERT INTO audit (email) VALUES (NEW.email); END;"
+        );
+
+        let result = handle_meta_command(
+            MetaCommand::new(".integrity_check".to_string()),
+            &mut repl,
+            &mut db,
+        );
+        assert_eq!(result.unwrap(), "ok");
+
+        process_command(
+            "INSERT INTO users (email) VALUES ('a@example.com');",
+            &mut db,
+        )
+        .unwrap();
+        let result = handle_meta_command(MetaCommand::DbInfo, &mut repl, &mut db).unwrap();
+        assert!(result.contains("schema version:      4"));
+        assert!(result.contains("number of tables:    3"));
+        assert!(result.contains("number of triggers:  1"));
+        assert!(result.contains("table users:         1 rows, 1 indexes"));
+        assert!(result.contains("table aux.logs:      0 rows, 0 indexes"));
+        assert!(result.contains("database file:       :memory:"));
+        assert!(result.contains("page count:          0"));
+
+        {
+            let mut file = db.temp.create().unwrap();
+            file.write(b"spilled").unwrap();
+        }
+        let result =
+            handle_meta_command(MetaCommand::new(".stats".to_string()), &mut repl, &mut db)
+                .unwrap();
+        assert!(result.contains("temp store:          memory"));
+        assert!(result.contains("temp files:          0 (1 created)"));
+        assert!(result.contains("temp storage used:   0 (max 7) bytes"));
+
+        let result = handle_meta_command(
+            MetaCommand::new(".limit column 50".to_string()),
+            &mut repl,
+            &mut db,
+        );
+        assert_eq!(result.unwrap(), "              column 50");
+        let result =
+            handle_meta_command(MetaCommand::new(".limit".to_string()), &mut repl, &mut db);
+        assert_eq!(result.unwrap().lines().count(), Limit::ALL.len());
+        assert!(handle_meta_command(
+            MetaCommand::new(".limit depth 5".to_string()),
+            &mut repl,
+            &mut db
+        )
+        .is_err());
+
+        let result = handle_meta_command(
+            MetaCommand::new(".profile on".to_string()),
+            &mut repl,
+            &mut db,
+        );
+        assert_eq!(result.unwrap(), "profile: on, 0 statements profiled");
+        process_command(
+            "INSERT INTO users (email) VALUES ('b@example.com');",
+            &mut db,
+        )
+        .unwrap();
+        let result =
+            handle_meta_command(MetaCommand::new(".profile".to_string()), &mut repl, &mut db);
+        assert_eq!(result.unwrap(), "profile: on, 1 statements profiled");
+        assert!(handle_meta_command(
+            MetaCommand::new(".profile maybe".to_string()),
+            &mut repl,
+            &mut db
+        )
+        .is_err());
+
+        let path = std::env::temp_dir().join(format!("sqlrite-export-{}", std::process::id()));
+        let command = format!(
+            ".export csv --delimiter ; \"SELECT email FROM users WHERE id > 1\" {}",
+            path.display()
+        );
+        let result = handle_meta_command(MetaCommand::new(command), &mut repl, &mut db);
+        assert_eq!(
+            result.unwrap(),
+            format!("1 rows exported to {}.", path.display())
+        );
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            "email\r\nb@example.com\r\n"
+        );
+        std::fs::remove_file(&path).unwrap();
+        let command = format!(".export json users {}", path.display());
+        let result = handle_meta_command(MetaCommand::new(command), &mut repl, &mut db);
+        assert_eq!(
+            result.unwrap(),
+            format!("2 rows exported to {}.", path.display())
+        );
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            "{\"id\":1,\"email\":\"a@example.com\"}\n{\"id\":2,\"email\":\"b@example.com\"}\n"
+        );
+        std::fs::remove_file(&path).unwrap();
+        assert!(handle_meta_command(
+            MetaCommand::new(".export json --no-header users out.json".to_string()),
+            &mut repl,
+            &mut db
+        )
+        .is_err());
+        std::fs::write(
+            &path,
+            "{\"email\": \"c@example.com\"}\n{\"email\": 3, \"id\": \"x\"}\n",
+        )
+        .unwrap();
+        let command = format!(".import --json {} users", path.display());
+        let result = handle_meta_command(MetaCommand::new(command), &mut repl, &mut db);
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(
+            result.unwrap(),
+            "1 rows imported into users, 1 skipped.\n\
+             row 2 skipped: \"x\" can't be stored in the INTEGER column id"
+        );
+        std::fs::write(
+            &path,
+            "CREATE TABLE `labels` (`id` int, `name` varchar(8));\n\
+             SET NAMES utf8;\n\
+             INSERT INTO `labels` VALUES (1,'a'),('b','b');\n",
+        )
+        .unwrap();
+        let command = format!(".import --dump {}", path.display());
+        let result = handle_meta_command(MetaCommand::new(command), &mut repl, &mut db);
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(
+            result.unwrap(),
+            "1 rows imported, tables created: labels, 1 skipped, 1 statements ignored.\n\
+             line 3 skipped: b can't be stored in the INTEGER column id"
+        );
+        std::fs::write(
+            &path,
+            "-- a script; with comments\n\
+             /* the rows */ INSERT INTO labels (id, name) VALUES (7, 'g'); -- done\n\
+             .tables\n\
+             INSERT INTO labels (id) VALUES ('h');\n",
+        )
+        .unwrap();
+        let command = format!(".read {}", path.display());
+        let result = handle_meta_command(MetaCommand::new(command), &mut repl, &mut db);
+        std::fs::remove_file(&path).unwrap();
+        let output = result.unwrap();
+        let lines = output.lines().collect::<Vec<&str>>();
+        assert!(lines[0].starts_with("INSERT Statement executed. 1 row affected"));
+        assert!(lines.contains(&"labels"));
+        assert!(lines[lines.len() - 1].starts_with("Error near line 4: "));
+        let command = format!(".clone {}", path.display());
+        let result = handle_meta_command(MetaCommand::new(command.clone()), &mut repl, &mut db);
+        assert_eq!(
+            result.unwrap(),
+            format!("Database cloned into {}.", path.display())
+        );
+        assert!(handle_meta_command(MetaCommand::new(command), &mut repl, &mut db).is_err());
+        let command = format!(".schemadiff {}", path.display());
+        let result = handle_meta_command(MetaCommand::new(command.clone()), &mut repl, &mut db);
+        assert_eq!(result.unwrap(), "The schemas are identical.");
+        process_command("CREATE TABLE tags (id INTEGER PRIMARY KEY);", &mut db).unwrap();
+        let result = handle_meta_command(MetaCommand::new(command), &mut repl, &mut db);
+        assert_eq!(result.unwrap(), "DROP TABLE tags;");
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(
+            split_args(r#".export csv "a ""b"" c" out.csv"#),
+            vec![".export", "csv", "a \"b\" c", "out.csv"]
+        );
+        assert_eq!(
+            split_args(".seed t 1 --template 'a=pick(x y,it''s)'"),
+            vec![".seed", "t", "1", "--template", "a=pick(x y,it's)"]
+        );
+    }
+
+    #[test]
+    fn get_meta_command_seed_test() {
+        let config = get_config();
+        let helper = REPLHelper::default();
+        let mut repl = Editor::with_config(config);
+        repl.set_helper(Some(helper));
+        let mut db = Database::new("tempdb".to_string());
+        process_command(
+            "CREATE TABLE users (id INTEGER PRIMARY KEY, name TEXT, email TEXT UNIQUE, score REAL);",
+            &mut db,
+        )
+        .unwrap();
+
+        let command = ".seed users 100 --template 'name=fake_name,email=fake_email,score=real(0,100)' --seed 42";
+        let result = handle_meta_command(MetaCommand::new(command.to_string()), &mut repl, &mut db);
+        assert_eq!(result.unwrap(), "100 rows seeded into users, 0 skipped.");
+        let result = handle_meta_command(
+            MetaCommand::new(".seed users 10".to_string()),
+            &mut repl,
+            &mut db,
+        );
+        assert_eq!(result.unwrap(), "10 rows seeded into users, 0 skipped.");
+        assert_eq!(db.tables["users"].rowids().len(), 110);
+
+        let command = ".seed users 2 --template 'email=pick(a@example.com)'";
+        let result = handle_meta_command(MetaCommand::new(command.to_string()), &mut repl, &mut db);
+        let output = result.unwrap();
+        assert!(output.starts_with("1 rows seeded into users, 1 skipped.\nrow 2 skipped: "));
+        for command in [".seed users", ".seed users many", ".seed users 1 --seed"] {
+            let result =
+                handle_meta_command(MetaCommand::new(command.to_string()), &mut repl, &mut db);
+            assert!(result.is_err());
+        }
+    }
+
+    #[test]
+    fn get_meta_command_record_test() {
+        let config = get_config();
+        let helper = REPLHelper::default();
+        let mut repl = Editor::with_config(config);
+        repl.set_helper(Some(helper));
+        let mut db = Database::new("tempdb".to_string());
+        let path = std::env::temp_dir().join(format!("sqlrite-record-{}.sql", std::process::id()));
+        let path = path.to_str().unwrap();
+
+        let command = MetaCommand::new(format!(".record {}", path));
+        let result = handle_meta_command(command, &mut repl, &mut db);
+        assert_eq!(result.unwrap(), format!("Recording into {}.", path));
+        for command in [
+            "CREATE TABLE users (id INTEGER PRIMARY KEY, name TEXT);",
+            "INSERT INTO users (name) VALUES ('ana');",
+            "SELECT name FROM users;",
+            "SELECT * FROM missing;",
+            ".tables",
+        ] {
+            let result = if command.starts_with('.') {
+                handle_meta_command(MetaCommand::new(command.to_string()), &mut repl, &mut db)
+            } else {
+                execute_sql(command, &mut db, crate::repl::output_width(&repl))
+            };
+            crate::repl::record(&mut repl, command, &result);
+        }
+        let result = handle_meta_command(
+            MetaCommand::new(".record off".to_string()),
+            &mut repl,
+            &mut db,
+        );
+        assert_eq!(result.unwrap(), format!("Transcript written to {}.", path));
+
+        // Replayed on a new database, the session prints what it printed
+        let mut db = Database::new("tempdb".to_string());
+        let command = MetaCommand::new(format!(".replay {} --compare", path));
+        let result = handle_meta_command(command, &mut repl, &mut db);
+        assert_eq!(result.unwrap(), "5 commands replayed, 0 diverged.");
+
+        // Replayed again, the table exists and holds a row more
+        let command = MetaCommand::new(format!(".replay {} --compare", path));
+        let result = handle_meta_command(command, &mut repl, &mut db).unwrap();
+        assert!(result.starts_with("Line 2: CREATE TABLE users"));
+        assert!(result.contains("Line 4: INSERT INTO users"));
+        assert!(result.contains("Line 6: SELECT name FROM users;"));
+        assert!(result.ends_with("5 commands replayed, 3 diverged."));
+
+        let command = MetaCommand::new(format!(".replay {}", path));
+        let result = handle_meta_command(command, &mut repl, &mut db).unwrap();
+        assert!(result.contains("Error near line 2: "));
+        std::fs::remove_file(path).unwrap();
+
+        let command = MetaCommand::new(".replay".to_string());
+        assert!(handle_meta_command(command, &mut repl, &mut db).is_err());
+    }
 }
@@ -1,15 +1,22 @@
+pub mod transcript;
+
+use crate::error;
 use crate::meta_command::*;
+use crate::sql::db::database::Database;
 use crate::sql::*;
+use transcript::Recorder;
 
 use std::borrow::Cow::{self, Borrowed, Owned};
 
+use sqlparser::ast::Statement;
+
 use rustyline::config::OutputStreamType;
 use rustyline::error::ReadlineError;
 use rustyline::highlight::{Highlighter, MatchingBracketHighlighter};
 use rustyline::hint::{Hinter, HistoryHinter};
 use rustyline::validate::Validator;
 use rustyline::validate::{ValidationContext, ValidationResult};
-use rustyline::{CompletionType, Config, Context, EditMode};
+use rustyline::{CompletionType, Config, Context, EditMode, Editor};
 use rustyline_derive::{Completer, Helper};
 
 /// We have two different types of commands MetaCommand and SQLCommand
@@ -34,6 +41,8 @@ pub struct REPLHelper {
     pub colored_prompt: String,
     pub hinter: HistoryHinter,
     pub highlighter: MatchingBracketHighlighter,
+    /// Transcript the session is recorded into, started by `.record`
+    pub recorder: Option<Recorder>,
 }
 
 // Implementing the Default trait to give our struct a default value
@@ -43,6 +52,7 @@ impl Default for REPLHelper {
             highlighter: MatchingBracketHighlighter::new(),
             hinter: HistoryHinter {},
             colored_prompt: "".to_owned(),
+            recorder: None,
         }
     }
 }
@@ -67,10 +77,13 @@ impl Validator for REPLHelper {
     // is valid or not along with an option message to display about the result.
     fn validate(&self, ctx: &mut ValidationContext) -> Result<ValidationResult, ReadlineError> {
         use ValidationResult::{Incomplete, /*Invalid,*/ Valid};
-        let input = ctx.input();
-        let result = if input.starts_with(".") {
+        // A comment after the `;` ending a statement doesn't keep it from being submitted,
+        // and a line of comments alone is submitted as is
+        let stripped = strip_comments(ctx.input());
+        let input = stripped.trim_end();
+        let result = if input.starts_with(".") || input.trim().is_empty() {
             Valid(None)
-        } else if !input.ends_with(';') {
+        } else if !input.ends_with(';') || is_unterminated_trigger(input) {
             Incomplete
         } else {
             Valid(None)
@@ -79,6 +92,117 @@ impl Validator for REPLHelper {
     }
 }
 
+/// Returns true if `input` is a CREATE TRIGGER statement whose body isn't closed by `END;`
+/// yet, the statements of the body end with `;` too.
+pub(crate) fn is_unterminated_trigger(input: &str) -> bool {
+    let input = strip_comments(input);
+    let words = input
+        .split_whitespace()
+        .take(3)
+        .map(|word| word.to_uppercase())
+        .collect::<Vec<String>>();
+    let is_trigger = match words.as_slice() {
+        [create, trigger, ..] if create == "CREATE" && trigger == "TRIGGER" => true,
+        [create, temp, trigger] if create == "CREATE" && trigger == "TRIGGER" => {
+            temp == "TEMP" || temp == "TEMPORARY"
+        }
+        _ => false,
+    };
+    let last_word = input
+        .trim_end_matches(|c: char| c == ';' || c.is_whitespace())
+        .rsplit(|c: char| !(c.is_alphanumeric() || c == '_'))
+        .next()
+        .unwrap_or_default();
+    is_trigger && !last_word.eq_ignore_ascii_case("END")
+}
+
+/// State of the scan of a script by `split`
+#[derive(Clone, Copy, PartialEq)]
+enum Scan {
+    Code,
+    /// In a string or a quoted identifier, closed by the character
+    Quoted(char),
+    LineComment,
+    BlockComment,
+}
+
+/// Splits `sql` into its statements, at the `;` outside of strings, quoted identifiers,
+/// comments and trigger bodies. Statements holding nothing but comments are left out.
+pub(crate) fn split_statements(sql: &str) -> Vec<String> {
+    split(sql, false)
+        .into_iter()
+        .map(|(_, statement)| statement)
+        .collect()
+}
+
+/// Splits the script `text`, read by `.read`, into its statements and its meta commands,
+/// each by the line it starts on. A meta command is a line starting with `.` between two
+/// statements.
+pub(crate) fn split_script(text: &str) -> Vec<(usize, String)> {
+    split(text, true)
+}
+
+fn split(sql: &str, meta_commands: bool) -> Vec<(usize, String)> {
+    let mut statements = vec![];
+    let mut statement = String::new();
+    // Whether the statement read holds nothing but blanks and comments so far
+    let mut blank = true;
+    let mut start = 1;
+    let mut line = 1;
+    let mut scan = Scan::Code;
+    let mut chars = sql.chars().peekable();
+    while let Some(c) = chars.next() {
+        let next = chars.peek().copied();
+        let comment = (c == '-' && next == Some('-')) || (c == '/' && next == Some('*'));
+        if scan == Scan::Code && blank && !c.is_whitespace() && !comment {
+            if c == '.' && meta_commands {
+                let mut command = c.to_string();
+                while let Some(c) = chars.next_if(|&c| c != '\n') {
+                    command.push(c);
+                }
+                statements.push((line, command));
+                statement.clear();
+                continue;
+            }
+            blank = false;
+            start = line;
+        }
+        if c == '\n' {
+            line += 1;
+        }
+        statement.push(c);
+        match scan {
+            Scan::Code => match c {
+                '-' if next == Some('-') => scan = Scan::LineComment,
+                '/' if next == Some('*') => {
+                    statement.extend(chars.next());
+                    scan = Scan::BlockComment;
+                }
+                '\'' | '"' | '`' => scan = Scan::Quoted(c),
+                '[' => scan = Scan::Quoted(']'),
+                ';' if !is_unterminated_trigger(&statement) => {
+                    statements.push((start, std::mem::take(&mut statement)));
+                    blank = true;
+                }
+                _ => {}
+            },
+            Scan::Quoted(quote) if c == quote => scan = Scan::Code,
+            Scan::LineComment if c == '\n' => scan = Scan::Code,
+            Scan::BlockComment if c == '*' && next == Some('/') => {
+                statement.extend(chars.next());
+                scan = Scan::Code;
+            }
+            _ => {}
+        }
+    }
+    statements.push((start, statement));
+    statements
+        .into_iter()
+        .map(|(start, statement)| (start, statement.trim().to_string()))
+        .filter(|(_, statement)| !matches!(strip_comments(statement).trim(), "" | ";"))
+        .collect()
+}
+
 // Implementing syntax highlighter with ANSI color.
 impl Highlighter for REPLHelper {
     // Takes the prompt and returns the highlighted version (with ANSI color).
@@ -121,6 +245,93 @@ pub fn get_config() -> Config {
         .build()
 }
 
+/// Width of the terminal the REPL prints to, which results are rendered for. `COLUMNS`
+/// overrides the width the terminal reports. Without it, output that isn't a terminal has
+/// no width, and results are printed whole.
+pub fn terminal_width() -> Option<usize> {
+    if let Some(columns) = std::env::var("COLUMNS")
+        .ok()
+        .and_then(|columns| columns.parse::<usize>().ok())
+        .filter(|&columns| columns > 0)
+    {
+        return Some(columns);
+    }
+    #[cfg(unix)]
+    {
+        // Safety: the size is only read when ioctl filled it
+        let mut size: libc::winsize = unsafe { std::mem::zeroed() };
+        let filled = unsafe { libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, &mut size) };
+        if filled == 0 && size.ws_col > 0 {
+            return Some(usize::from(size.ws_col));
+        }
+    }
+    None
+}
+
+/// Runs the SQL statement `command` of the REPL, returning what the REPL prints for it: the
+/// rows of a query rendered for a terminal `width` characters wide, or the outcome of
+/// another statement. The REPL also prints the schema of a table it creates, and the rows
+/// of a table it inserts into.
+pub fn execute_sql(
+    command: &str,
+    db: &mut Database,
+    width: Option<usize>,
+) -> error::Result<String> {
+    match returns_rows(command) {
+        Ok(true) => process_query(command, db).map(|result| result.render(width)),
+        _ => {
+            let result = process_command(command, db)?;
+            print_table_changed(command, db);
+            Ok(result.to_string())
+        }
+    }
+}
+
+/// Prints the schema of the table `command` created, or the rows of the table it inserted
+/// into, if it is a CREATE TABLE or an INSERT.
+fn print_table_changed(command: &str, db: &Database) {
+    let (table_name, created) = match parse_statement(command) {
+        Ok(Statement::CreateTable { name, .. }) => (name, true),
+        Ok(Statement::Insert { table_name, .. }) => (table_name, false),
+        _ => return,
+    };
+    let table = db
+        .resolve_table_name(&table_name.to_string())
+        .ok()
+        .and_then(|table_name| db.tables.get(&table_name));
+    match table {
+        Some(table) if created => {
+            let _ = table.print_table_schema();
+        }
+        Some(table) => table.print_table_data(),
+        None => {}
+    }
+}
+
+/// Width results are rendered for: that of the terminal, or the one of the transcript
+/// being recorded, so that it can be replayed whatever the terminal.
+pub fn output_width(repl: &Editor<REPLHelper>) -> Option<usize> {
+    match repl.helper().and_then(|helper| helper.recorder.as_ref()) {
+        Some(recorder) => recorder.width(),
+        None => terminal_width(),
+    }
+}
+
+/// Appends `command` and its output to the transcript being recorded, if any. A transcript
+/// that can't be written anymore is stopped.
+pub fn record(repl: &mut Editor<REPLHelper>, command: &str, output: &error::Result<String>) {
+    let helper = match repl.helper_mut() {
+        Some(helper) => helper,
+        None => return,
+    };
+    if let Some(recorder) = &mut helper.recorder {
+        if let Err(err) = recorder.record(command, output) {
+            eprintln!("An error occured: {}, recording stopped", err);
+            helper.recorder = None;
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -142,4 +353,51 @@ mod tests {
         let result = get_command_type(&input);
         assert_eq!(result, expected);
     }
+
+    #[test]
+    fn is_unterminated_trigger_test() {
+        assert!(is_unterminated_trigger(
+            "CREATE TRIGGER t AFTER INSERT ON users BEGIN DELETE FROM logs;"
+        ));
+        assert!(!is_unterminated_trigger(
+            "create temp trigger t after insert on users begin delete from logs; end;"
+        ));
+        assert!(!is_unterminated_trigger("DELETE FROM logs;"));
+        assert!(!is_unterminated_trigger(
+            "-- audit\nCREATE TRIGGER t AFTER INSERT ON users BEGIN DELETE FROM logs; END; -- done"
+        ));
+        assert!(is_unterminated_trigger(
+            "/* audit */ CREATE TRIGGER t AFTER INSERT ON users BEGIN DELETE FROM logs; -- END;"
+        ));
+    }
+
+    #[test]
+    fn split_script_test() {
+        let script = "-- setup; not a statement\n\
+                      CREATE TABLE t (a TEXT); /* a; b */ INSERT INTO t (a) VALUES ('x;--y');\n\
+                      .tables\n\
+                      /* the\n rows */\n\
+                      SELECT a -- a;\n\
+                      FROM t;\n\
+                      -- the end";
+        assert_eq!(
+            split_script(script),
+            vec![
+                (
+                    2,
+                    "-- setup; not a statement\nCREATE TABLE t (a TEXT);".to_string()
+                ),
+                (
+                    2,
+                    "/* a; b */ INSERT INTO t (a) VALUES ('x;--y');".to_string()
+                ),
+                (3, ".tables".to_string()),
+                (6, "/* the\n rows */\nSELECT a -- a;\nFROM t;".to_string()),
+            ]
+        );
+        assert_eq!(
+            split_statements(".tables; SELECT 1"),
+            vec![".tables;", "SELECT 1"]
+        );
+    }
 }
@@ -0,0 +1,259 @@
+//! Transcripts of REPL sessions, written by `.record` and run again by `.replay`. A
+//! transcript holds each command as it was entered, followed by what the REPL printed for
+//! it, each line of the output commented out behind `-- > `, and of an error behind `-- ! `.
+//! The file is also a script `.read` can run, and a bug report anyone can read.
+//!
+//! ```text
+//! -- SQLRite transcript, rendered for 80 columns
+//! CREATE TABLE users (id INTEGER PRIMARY KEY, name TEXT);
+//! -- > CREATE TABLE Statement executed.
+//! SELECT name FROM user;
+//! -- ! General error: Table not found.
+//! ```
+use std::fs::File;
+use std::io::Write;
+
+use crate::error::{Result, SQLRiteError};
+
+/// First line of a transcript, followed by the width results were rendered for, if any
+const HEADER: &str = "-- SQLRite transcript";
+
+/// Prefix of the lines of output
+const OUTPUT: &str = "-- >";
+
+/// Prefix of the lines of an error
+const ERROR: &str = "-- !";
+
+/// Records the commands of a REPL session and their output into a transcript
+#[derive(Debug)]
+pub struct Recorder {
+    file: File,
+    path: String,
+    /// Width of the terminal results are rendered for while recording
+    width: Option<usize>,
+}
+
+impl Recorder {
+    /// Starts the transcript `path`, of a session whose results are rendered for a terminal
+    /// `width` characters wide. An existing file is replaced.
+    pub fn create(path: &str, width: Option<usize>) -> Result<Recorder> {
+        let file = File::create(path)
+            .map_err(|err| SQLRiteError::General(format!("cannot create {}: {}", path, err)))?;
+        let header = match width {
+            Some(width) => format!("{}, rendered for {} columns\n", HEADER, width),
+            None => format!("{}\n", HEADER),
+        };
+        let mut recorder = Recorder {
+            file,
+            path: path.to_string(),
+            width,
+        };
+        recorder.write(&header)?;
+        Ok(recorder)
+    }
+
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    pub fn width(&self) -> Option<usize> {
+        self.width
+    }
+
+    /// Appends `command` and what the REPL printed for it. Each command is written as soon
+    /// as it ran, a session that ends abruptly keeps its transcript.
+    pub fn record(&mut self, command: &str, output: &Result<String>) -> Result<()> {
+        let mut text = command.trim_end().to_string();
+        text.push('\n');
+        let (prefix, output) = match output {
+            Ok(output) => (OUTPUT, output.clone()),
+            Err(err) => (ERROR, err.to_string()),
+        };
+        text.push_str(&comment_out(prefix, &output));
+        self.write(&text)
+    }
+
+    fn write(&mut self, text: &str) -> Result<()> {
+        self.file
+            .write_all(text.as_bytes())
+            .map_err(|err| SQLRiteError::General(format!("cannot write {}: {}", self.path, err)))
+    }
+}
+
+/// `text` with each of its lines behind `prefix`, an empty text taking a line.
+fn comment_out(prefix: &str, text: &str) -> String {
+    let mut lines = text.lines().collect::<Vec<&str>>();
+    if lines.is_empty() {
+        lines.push("");
+    }
+    lines
+        .iter()
+        .map(|line| match line.is_empty() {
+            true => format!("{}\n", prefix),
+            false => format!("{} {}\n", prefix, line),
+        })
+        .collect()
+}
+
+/// A command of a transcript, with what the REPL printed for it when it was recorded
+#[derive(Debug, PartialEq)]
+pub struct Entry {
+    /// Line of the transcript the command starts on
+    pub line: usize,
+    pub command: String,
+    /// The output of the command, or the message of its error
+    pub output: std::result::Result<String, String>,
+}
+
+impl Entry {
+    /// Returns true if `output` is what the command printed when it was recorded.
+    pub fn matches(&self, output: &Result<String>) -> bool {
+        match (&self.output, output) {
+            (Ok(expected), Ok(output)) => normalize(expected) == normalize(output),
+            (Err(expected), Err(err)) => normalize(expected) == normalize(&err.to_string()),
+            _ => false,
+        }
+    }
+}
+
+/// `text` as a transcript holds it: its lines without their trailing blanks.
+fn normalize(text: &str) -> Vec<&str> {
+    text.lines().map(|line| line.trim_end()).collect()
+}
+
+/// A transcript read back: the width its results were rendered for, and its commands.
+#[derive(Debug, PartialEq)]
+pub struct Transcript {
+    pub width: Option<usize>,
+    pub entries: Vec<Entry>,
+}
+
+/// Reads the transcript `text`.
+pub fn parse_transcript(text: &str) -> Result<Transcript> {
+    let mut lines = text.lines().enumerate();
+    let width = match lines.next() {
+        Some((_, header)) if header.starts_with(HEADER) => header
+            .trim_start_matches(HEADER)
+            .trim_start_matches(", rendered for ")
+            .trim_end_matches(" columns")
+            .parse::<usize>()
+            .ok(),
+        _ => {
+            return Err(SQLRiteError::General(
+                "not a transcript written by .record".to_string(),
+            ))
+        }
+    };
+    let mut entries: Vec<Entry> = vec![];
+    // The lines of the command read, and of its output once it started
+    let mut command: Vec<&str> = vec![];
+    let mut start = 0;
+    let mut output: Vec<&str> = vec![];
+    let mut failed = false;
+    let mut push = |start: usize, command: &mut Vec<&str>, output: &mut Vec<&str>, failed| {
+        let text = output.join("\n");
+        entries.push(Entry {
+            line: start,
+            command: command.join("\n"),
+            output: if failed { Err(text) } else { Ok(text) },
+        });
+        command.clear();
+        output.clear();
+    };
+    for (i, line) in lines {
+        let prefix = [OUTPUT, ERROR]
+            .iter()
+            .find(|prefix| line.starts_with(*prefix));
+        match prefix {
+            Some(prefix) if !command.is_empty() => {
+                failed = *prefix == ERROR;
+                let line = &line[prefix.len()..];
+                output.push(line.strip_prefix(' ').unwrap_or(line));
+            }
+            // Output without a command is a comment
+            Some(_) => {}
+            None => {
+                if !output.is_empty() {
+                    push(start, &mut command, &mut output, failed);
+                }
+                if command.is_empty() {
+                    if line.trim().is_empty() {
+                        continue;
+                    }
+                    start = i + 1;
+                }
+                command.push(line);
+            }
+        }
+    }
+    if !command.is_empty() {
+        push(start, &mut command, &mut output, failed);
+    }
+    Ok(Transcript { width, entries })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn transcript_test() {
+        let path = std::env::temp_dir().join(format!("sqlrite-transcript-{}", std::process::id()));
+        let path = path.to_str().unwrap();
+        let mut recorder = Recorder::create(path, Some(80)).unwrap();
+        recorder
+            .record(
+                "CREATE TABLE users (\n  id INTEGER PRIMARY KEY\n);",
+                &Ok("CREATE TABLE Statement executed.".to_string()),
+            )
+            .unwrap();
+        recorder
+            .record(
+                "SELECT * FROM users;",
+                &Ok("+----+\n| id |\n+----+\n\n".to_string()),
+            )
+            .unwrap();
+        recorder
+            .record(
+                "SELECT * FROM user;",
+                &Err(SQLRiteError::General("no such table".to_string())),
+            )
+            .unwrap();
+        recorder.record(".tables", &Ok(String::new())).unwrap();
+        let text = std::fs::read_to_string(path).unwrap();
+        std::fs::remove_file(path).unwrap();
+        assert_eq!(
+            text,
+            "-- SQLRite transcript, rendered for 80 columns\n\
+             CREATE TABLE users (\n  id INTEGER PRIMARY KEY\n);\n\
+             -- > CREATE TABLE Statement executed.\n\
+             SELECT * FROM users;\n\
+             -- > +----+\n-- > | id |\n-- > +----+\n-- >\n\
+             SELECT * FROM user;\n\
+             -- ! General error: no such table\n\
+             .tables\n\
+             -- >\n"
+        );
+
+        let transcript = parse_transcript(&text).unwrap();
+        assert_eq!(transcript.width, Some(80));
+        assert_eq!(transcript.entries.len(), 4);
+        let create = &transcript.entries[0];
+        assert_eq!(create.line, 2);
+        assert_eq!(
+            create.command,
+            "CREATE TABLE users (\n  id INTEGER PRIMARY KEY\n);"
+        );
+        assert!(create.matches(&Ok("CREATE TABLE Statement executed.".to_string())));
+        assert!(!create.matches(&Ok("INSERT Statement executed.".to_string())));
+        let select = &transcript.entries[1];
+        assert_eq!(select.line, 6);
+        assert!(select.matches(&Ok("+----+\n| id |\n+----+\n".to_string())));
+        let error = &transcript.entries[2];
+        assert!(error.matches(&Err(SQLRiteError::General("no such table".to_string()))));
+        assert!(!error.matches(&Ok("General error: no such table".to_string())));
+        assert!(transcript.entries[3].matches(&Ok(String::new())));
+
+        assert!(parse_transcript("SELECT 1;").is_err());
+    }
+}
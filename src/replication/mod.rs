@@ -0,0 +1,410 @@
+//! Leader–follower replication: a `Leader` runs the statements changing its database and
+//! ships each of them, once committed, as a numbered frame to its followers, which apply
+//! them in order to a read-only replica. The statements of a transaction are held back
+//! until it is committed, and shipped together in one frame; those of a transaction rolled
+//! back are never shipped. A follower joining late first catches up from a snapshot of the
+//! whole database.
+//!
+//! The database has no write-ahead log yet, so frames carry the SQL of the statements
+//! rather than pages: statements have to be deterministic to produce the same replica, and
+//! the leader refuses those calling a function that isn't, like `random()` or `uuid4()`.
+//! Virtual tables aren't part of snapshots, they are only replicated by frames.
+//!
+//! Messages are written as one JSON document per line to any `Write`, like a `TcpStream`
+//! or a `File`, and read back by the follower from the matching `BufRead`.
+use std::io::{BufRead, Write};
+
+use serde::{Deserialize, Serialize};
+use sqlparser::tokenizer::Token;
+
+use crate::connection::rows::Rows;
+use crate::connection::Connection;
+use crate::error::{Result, SQLRiteError};
+use crate::sql::db::database::Database;
+use crate::sql::parser::transaction::{parse_transaction_statement, TransactionStatement};
+use crate::sql::{returns_rows, tokenize, QueryResult};
+
+/// A message sent from the leader to its followers
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub enum Message {
+    /// The whole database, as of frame `seq`
+    Snapshot { seq: u64, database: Box<Database> },
+    /// The statements of a committed transaction, or a statement run outside of one, the
+    /// `seq`-th frame changing the database
+    Frame { seq: u64, statements: Vec<String> },
+}
+
+impl Message {
+    /// Writes the message as a line of JSON, flushing it so it is shipped right away.
+    pub fn write_to(&self, writer: &mut dyn Write) -> Result<()> {
+        let line = serde_json::to_string(self).map_err(replication_error)?;
+        writeln!(writer, "{}", line).map_err(replication_error)?;
+        writer.flush().map_err(replication_error)
+    }
+
+    /// Reads the next message, `None` once the leader closed the stream.
+    pub fn read_from(reader: &mut dyn BufRead) -> Result<Option<Message>> {
+        let mut line = String::new();
+        if reader.read_line(&mut line).map_err(replication_error)? == 0 {
+            return Ok(None);
+        }
+        serde_json::from_str(&line)
+            .map(Some)
+            .map_err(replication_error)
+    }
+}
+
+fn replication_error(err: impl std::fmt::Display) -> SQLRiteError {
+    SQLRiteError::General(format!("Replication error: {}", err))
+}
+
+/// The connection every change goes through, shipping the changes to its followers.
+pub struct Leader {
+    conn: Connection,
+    /// Number of frames committed so far
+    seq: u64,
+    /// Statements of the transaction open, shipped once it is committed
+    pending: Vec<String>,
+    followers: Vec<Box<dyn Write + Send>>,
+}
+
+impl Leader {
+    /// Makes `conn` the leader, its database is the initial state of the followers.
+    pub fn new(conn: Connection) -> Self {
+        Leader {
+            conn,
+            seq: 0,
+            pending: vec![],
+            followers: vec![],
+        }
+    }
+
+    /// Executes a statement. If it changed the database, it is shipped to every follower
+    /// before returning, or once the transaction it is part of is committed. Statements
+    /// calling a function that isn't deterministic are refused, the followers would get
+    /// other values. Followers whose stream fails are dropped.
+    pub fn execute(&mut self, sql: &str) -> Result<QueryResult> {
+        if returns_rows(sql)? {
+            return self.conn.execute(sql);
+        }
+        if let Some(name) = nondeterministic_function(sql, self.conn.database())? {
+            return Err(replication_error(format!(
+                "{}() isn't deterministic, statements calling it can't be replicated",
+                name
+            )));
+        }
+        let result = self.conn.execute(sql)?;
+        if parse_transaction_statement(sql)? == Some(TransactionStatement::Rollback) {
+            self.pending.clear();
+            return Ok(result);
+        }
+        self.pending.push(sql.to_string());
+        if self.conn.database().transaction.is_open() {
+            return Ok(result);
+        }
+
+        self.seq += 1;
+        let frame = Message::Frame {
+            seq: self.seq,
+            statements: std::mem::take(&mut self.pending),
+        };
+        self.followers
+            .retain_mut(|follower| match frame.write_to(follower) {
+                Ok(()) => true,
+                Err(err) => {
+                    log::warn!("Dropping follower: {}", err);
+                    false
+                }
+            });
+        Ok(result)
+    }
+
+    /// Runs a query against the leader's database.
+    pub fn query(&self, sql: &str) -> Result<Rows> {
+        self.conn.query(sql)
+    }
+
+    /// Adds a follower reading from the other end of `stream`, sending it a snapshot of the
+    /// database first so it catches up with the frames already committed.
+    pub fn add_follower<W: Write + Send + 'static>(&mut self, stream: W) -> Result<()> {
+        let mut stream: Box<dyn Write + Send> = Box::new(stream);
+        self.snapshot()?.write_to(&mut stream)?;
+        self.followers.push(stream);
+        Ok(())
+    }
+
+    /// Returns a snapshot of the database, as of the last committed frame: without the
+    /// changes of the transaction open, if any.
+    pub fn snapshot(&self) -> Result<Message> {
+        let db = self.conn.database();
+        let database = match db.transaction.committed() {
+            Some(committed) => committed?,
+            None => db.snapshot()?,
+        };
+        Ok(Message::Snapshot {
+            seq: self.seq,
+            database: Box::new(database),
+        })
+    }
+
+    /// Number of frames committed so far.
+    pub fn seq(&self) -> u64 {
+        self.seq
+    }
+
+    /// Number of followers the frames are shipped to.
+    pub fn follower_count(&self) -> usize {
+        self.followers.len()
+    }
+}
+
+/// A read-only replica of the leader's database.
+pub struct Follower {
+    conn: Connection,
+    /// Sequence number of the last frame applied
+    seq: u64,
+    /// Frames can only be applied after a snapshot
+    synced: bool,
+}
+
+impl Follower {
+    /// Creates a follower with an empty replica, the functions, collations and modules the
+    /// replicated statements use can be registered on `conn`.
+    pub fn new(conn: Connection) -> Self {
+        Follower {
+            conn,
+            seq: 0,
+            synced: false,
+        }
+    }
+
+    /// Applies a message from the leader. Frames have to be applied in order, a gap means
+    /// the follower missed changes and has to catch up from a new snapshot.
+    pub fn apply(&mut self, message: Message) -> Result<()> {
+        match message {
+            Message::Snapshot { seq, database } => {
+                self.conn.database_mut().restore(*database);
+                self.seq = seq;
+                self.synced = true;
+            }
+            Message::Frame { seq, statements } => {
+                if !self.synced || seq != self.seq + 1 {
+                    return Err(replication_error(format!(
+                        "expected frame {}, received frame {}",
+                        self.seq + 1,
+                        seq
+                    )));
+                }
+                for sql in &statements {
+                    self.conn.execute(sql)?;
+                }
+                self.seq = seq;
+            }
+        }
+        Ok(())
+    }
+
+    /// Applies every message read from `reader` until the leader closes the stream,
+    /// returning the number of messages applied.
+    pub fn follow(&mut self, reader: &mut dyn BufRead) -> Result<usize> {
+        let mut applied = 0;
+        while let Some(message) = Message::read_from(reader)? {
+            self.apply(message)?;
+            applied += 1;
+        }
+        Ok(applied)
+    }
+
+    /// Runs a query against the replica.
+    pub fn query(&self, sql: &str) -> Result<Rows> {
+        self.conn.query(sql)
+    }
+
+    /// Sequence number of the last frame applied.
+    pub fn seq(&self) -> u64 {
+        self.seq
+    }
+}
+
+/// The first function called by the statement `sql` that isn't deterministic, like
+/// `random()`, if any.
+fn nondeterministic_function(sql: &str, db: &Database) -> Result<Option<String>> {
+    let tokens = tokenize(sql)?;
+    let mut tokens = tokens
+        .iter()
+        .filter(|token| !matches!(token, Token::Whitespace(_)))
+        .peekable();
+    while let Some(token) = tokens.next() {
+        if let (Token::Word(word), Some(Token::LParen)) = (token, tokens.peek()) {
+            let random = db
+                .functions
+                .get(&word.value)
+                .is_some_and(|function| !function.deterministic);
+            if random {
+                return Ok(Some(word.value.clone()));
+            }
+        }
+    }
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufReader, Cursor};
+    use std::net::{TcpListener, TcpStream};
+    use std::sync::{Arc, Mutex};
+
+    /// A stream writing into a buffer shared with the test
+    #[derive(Clone, Default)]
+    struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn names(rows: Rows) -> Vec<String> {
+        rows.map(|row| row.get::<String>(0).unwrap()).collect()
+    }
+
+    #[test]
+    fn snapshot_and_frames_test() {
+        let mut leader = Leader::new(Connection::open_in_memory());
+        leader
+            .execute("CREATE TABLE users (id INTEGER PRIMARY KEY, name TEXT);")
+            .unwrap();
+        leader
+            .execute("INSERT INTO users (name) VALUES ('josh');")
+            .unwrap();
+
+        // Joins late, catching up from the snapshot
+        let buffer = SharedBuffer::default();
+        leader.add_follower(buffer.clone()).unwrap();
+        leader
+            .execute("INSERT INTO users (name) VALUES ('mary');")
+            .unwrap();
+        // Queries aren't shipped
+        leader.query("SELECT name FROM users;").unwrap();
+        assert_eq!(leader.seq(), 3);
+
+        let mut follower = Follower::new(Connection::open_in_memory());
+        let data = buffer.0.lock().unwrap().clone();
+        assert_eq!(follower.follow(&mut Cursor::new(data)).unwrap(), 2);
+        assert_eq!(follower.seq(), 3);
+        assert_eq!(
+            names(follower.query("SELECT name FROM users;").unwrap()),
+            vec!["josh", "mary"]
+        );
+
+        // Frames have to follow each other
+        let frame = Message::Frame {
+            seq: 5,
+            statements: vec!["INSERT INTO users (name) VALUES ('bob');".to_string()],
+        };
+        assert!(follower.apply(frame).is_err());
+    }
+
+    #[test]
+    fn transactions_test() {
+        let mut leader = Leader::new(Connection::open_in_memory());
+        leader
+            .execute("CREATE TABLE users (id INTEGER PRIMARY KEY, name TEXT);")
+            .unwrap();
+        let buffer = SharedBuffer::default();
+        leader.add_follower(buffer.clone()).unwrap();
+        let messages = || {
+            let data = buffer.0.lock().unwrap().clone();
+            let mut reader = Cursor::new(data);
+            let mut messages = vec![];
+            while let Some(message) = Message::read_from(&mut reader).unwrap() {
+                messages.push(message);
+            }
+            messages
+        };
+
+        // Held back until committed, then shipped in one frame
+        leader.execute("BEGIN;").unwrap();
+        leader
+            .execute("INSERT INTO users (name) VALUES ('josh');")
+            .unwrap();
+        assert_eq!(messages().len(), 1);
+        // A follower joining meanwhile doesn't see the transaction
+        let late = SharedBuffer::default();
+        leader.add_follower(late.clone()).unwrap();
+        leader.execute("COMMIT;").unwrap();
+        assert_eq!(
+            messages().pop(),
+            Some(Message::Frame {
+                seq: 2,
+                statements: vec![
+                    "BEGIN;".to_string(),
+                    "INSERT INTO users (name) VALUES ('josh');".to_string(),
+                    "COMMIT;".to_string(),
+                ],
+            })
+        );
+
+        // Nothing of a transaction rolled back is shipped
+        leader.execute("BEGIN;").unwrap();
+        leader
+            .execute("INSERT INTO users (name) VALUES ('mary');")
+            .unwrap();
+        leader.execute("ROLLBACK;").unwrap();
+        assert_eq!(messages().len(), 2);
+        assert_eq!(leader.seq(), 2);
+
+        // Statements whose values would differ on the followers are refused
+        for sql in [
+            "INSERT INTO users (name) VALUES (hex(randomblob(4)));",
+            "INSERT INTO users (id, name) VALUES (abs(RANDOM()) % 100, 'bob');",
+            "UPDATE users SET name = uuid4();",
+            "INSERT INTO users (name) SELECT uuid7();",
+        ] {
+            let err = leader.execute(sql).unwrap_err();
+            assert!(err.to_string().contains("isn't deterministic"), "{}", err);
+        }
+        assert_eq!(leader.seq(), 2);
+        leader.query("SELECT random();").unwrap();
+
+        let mut follower = Follower::new(Connection::open_in_memory());
+        let data = late.0.lock().unwrap().clone();
+        follower.follow(&mut Cursor::new(data)).unwrap();
+        assert_eq!(follower.seq(), 2);
+        assert_eq!(
+            names(follower.query("SELECT name FROM users;").unwrap()),
+            vec!["josh"]
+        );
+    }
+
+    #[test]
+    fn tcp_follower_test() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let replica = std::thread::spawn(move || {
+            let stream = TcpStream::connect(address).unwrap();
+            let mut follower = Follower::new(Connection::open_in_memory());
+            follower.follow(&mut BufReader::new(stream)).unwrap();
+            names(follower.query("SELECT name FROM users;").unwrap())
+        });
+
+        let mut leader = Leader::new(Connection::open_in_memory());
+        let (stream, _) = listener.accept().unwrap();
+        leader.add_follower(stream).unwrap();
+        leader
+            .execute("CREATE TABLE users (id INTEGER PRIMARY KEY, name TEXT);")
+            .unwrap();
+        leader
+            .execute("INSERT INTO users (name) VALUES ('josh');")
+            .unwrap();
+        // Closing the leader closes the stream, which ends `follow`
+        drop(leader);
+
+        assert_eq!(replica.join().unwrap(), vec!["josh"]);
+    }
+}
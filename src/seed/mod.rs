@@ -0,0 +1,430 @@
+//! Generation of synthetic rows, for demos and benchmarks: `.seed users 100000` fills a
+//! table with rows that look real, each column getting values of a generator. The
+//! generators are given by a template, like `name=fake_name,score=real(0,100)`, or picked
+//! after the name and the type of each column. The rows are inserted in large batches,
+//! which load the tables in bulk and index them once per batch.
+use std::fmt;
+
+use crate::error::{Result, SQLRiteError};
+use crate::import::{insert_batch, ImportReport};
+use crate::sql::db::database::Database;
+use crate::sql::db::table::{Column, DataType};
+use crate::sql::value::Value;
+
+/// Rows inserted together, as a single INSERT statement
+const BATCH_ROWS: usize = 10_000;
+
+const FIRST_NAMES: &[&str] = &[
+    "Ana", "Bruno", "Carla", "Diego", "Elena", "Felix", "Grace", "Hugo", "Iris", "Joao", "Kim",
+    "Lucas", "Maria", "Nina", "Omar", "Paula", "Quinn", "Rosa", "Sam", "Tomas",
+];
+const LAST_NAMES: &[&str] = &[
+    "Silva", "Smith", "Garcia", "Muller", "Rossi", "Kim", "Nguyen", "Costa", "Dubois", "Novak",
+];
+const COUNTRIES: &[&str] = &["BR", "US", "DE", "FR", "PT", "JP", "IN", "MX"];
+const CITIES: &[&str] = &[
+    "Lisbon",
+    "Porto",
+    "Sao Paulo",
+    "New York",
+    "Berlin",
+    "Paris",
+    "Tokyo",
+    "Mumbai",
+    "Mexico City",
+    "Toronto",
+];
+const COMPANY_WORDS: &[&str] = &[
+    "Acme", "Globex", "Initech", "Umbrella", "Stark", "Wayne", "Hooli", "Vandelay",
+];
+const COMPANY_SUFFIXES: &[&str] = &["Inc", "Ltd", "LLC", "GmbH", "SA"];
+const DOMAINS: &[&str] = &["example.com", "example.org", "example.net"];
+const WORDS: &[&str] = &[
+    "alpha", "bravo", "charlie", "delta", "echo", "foxtrot", "golf", "hotel", "india", "juliet",
+    "kilo", "lima", "mike", "november", "oscar", "papa",
+];
+
+/// A small, fast pseudo-random number generator (SplitMix64). Not for anything but data.
+#[derive(Debug, Clone)]
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        Rng { state: seed }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// A number in `0..bound`.
+    pub fn below(&mut self, bound: u64) -> u64 {
+        self.next_u64() % bound
+    }
+
+    /// One of `choices`, picked uniformly.
+    pub fn pick<'a>(&mut self, choices: &[&'a str]) -> &'a str {
+        choices[self.below(choices.len() as u64) as usize]
+    }
+}
+
+/// How the values of a column are generated
+#[derive(Debug, Clone, PartialEq)]
+pub enum Generator {
+    /// A first name and a last name
+    Name,
+    FirstName,
+    LastName,
+    /// An email address, unique to the row
+    Email,
+    /// A two-letter country code
+    Country,
+    City,
+    Company,
+    Phone,
+    Word,
+    /// A date between January 1st of the first year and December 28th of the last one
+    Date(i64, i64),
+    /// An integer between the bounds, both included
+    Int(i64, i64),
+    /// A real between the bounds, with 2 decimals
+    Real(f64, f64),
+    Bool,
+    /// The number of the row, unique to it
+    Seq,
+    /// One of the choices, picked uniformly
+    Pick(Vec<String>),
+    Null,
+}
+
+impl Generator {
+    /// Parses a generator of a template, like `fake_email` or `int(1, 10)`.
+    pub fn parse(text: &str) -> Result<Generator> {
+        let text = text.trim();
+        let (name, args) = match text.split_once('(') {
+            Some((name, args)) => match args.strip_suffix(')') {
+                Some(args) => (
+                    name.trim(),
+                    args.split(',').map(str::trim).collect::<Vec<&str>>(),
+                ),
+                None => return Err(invalid(text)),
+            },
+            None => (text, vec![]),
+        };
+        let bounds = |default: (f64, f64)| -> Result<(f64, f64)> {
+            match args.as_slice() {
+                [] => Ok(default),
+                [low, high] => match (low.parse::<f64>(), high.parse::<f64>()) {
+                    (Ok(low), Ok(high)) if low <= high => Ok((low, high)),
+                    _ => Err(invalid(text)),
+                },
+                _ => Err(invalid(text)),
+            }
+        };
+        let name = name.to_lowercase();
+        let generator = match name.as_str() {
+            "fake_name" => Generator::Name,
+            "fake_first_name" => Generator::FirstName,
+            "fake_last_name" => Generator::LastName,
+            "fake_email" => Generator::Email,
+            "fake_country" => Generator::Country,
+            "fake_city" => Generator::City,
+            "fake_company" => Generator::Company,
+            "fake_phone" => Generator::Phone,
+            "fake_word" => Generator::Word,
+            "date" => {
+                let (from, to) = bounds((2000.0, 2024.0))?;
+                Generator::Date(from as i64, to as i64)
+            }
+            "int" => {
+                let (low, high) = bounds((0.0, 1000.0))?;
+                Generator::Int(low as i64, high as i64)
+            }
+            "real" => {
+                let (low, high) = bounds((0.0, 1000.0))?;
+                Generator::Real(low, high)
+            }
+            "bool" => Generator::Bool,
+            "seq" => Generator::Seq,
+            "pick" if !args.is_empty() => {
+                Generator::Pick(args.iter().map(|arg| arg.to_string()).collect())
+            }
+            "null" => Generator::Null,
+            _ => return Err(invalid(text)),
+        };
+        if !args.is_empty() && !matches!(name.as_str(), "date" | "int" | "real" | "pick") {
+            return Err(invalid(text));
+        }
+        Ok(generator)
+    }
+
+    /// The generator picked for `column` when the template doesn't give one, after its
+    /// name, its type and whether its values have to be unique.
+    pub fn for_column(column: &Column) -> Generator {
+        let name = column.column_name.to_lowercase();
+        let unique = column.is_unique || column.is_pk;
+        match column.datatype {
+            DataType::Integer if unique => Generator::Seq,
+            DataType::Integer => Generator::Int(0, 1000),
+            DataType::Real => Generator::Real(0.0, 1000.0),
+            DataType::Bool => Generator::Bool,
+            _ if name.contains("email") => Generator::Email,
+            _ if unique => Generator::Seq,
+            _ if name.contains("first_name") => Generator::FirstName,
+            _ if name.contains("last_name") => Generator::LastName,
+            _ if name.contains("name") => Generator::Name,
+            _ if name.contains("country") => Generator::Country,
+            _ if name.contains("city") => Generator::City,
+            _ if name.contains("company") => Generator::Company,
+            _ if name.contains("phone") => Generator::Phone,
+            _ if name.contains("date") || name.ends_with("_at") => Generator::Date(2000, 2024),
+            _ => Generator::Word,
+        }
+    }
+
+    /// The value of the row numbered `row`, as the literal of an INSERT statement, `None`
+    /// for NULL.
+    pub fn generate(&self, rng: &mut Rng, row: i64) -> Option<String> {
+        let literal = match self {
+            Generator::Name => format!("{} {}", rng.pick(FIRST_NAMES), rng.pick(LAST_NAMES)),
+            Generator::FirstName => rng.pick(FIRST_NAMES).to_string(),
+            Generator::LastName => rng.pick(LAST_NAMES).to_string(),
+            Generator::Email => format!(
+                "{}.{}{}@{}",
+                rng.pick(FIRST_NAMES).to_lowercase(),
+                rng.pick(LAST_NAMES).to_lowercase(),
+                row,
+                rng.pick(DOMAINS)
+            ),
+            Generator::Country => rng.pick(COUNTRIES).to_string(),
+            Generator::City => rng.pick(CITIES).to_string(),
+            Generator::Company => {
+                format!("{} {}", rng.pick(COMPANY_WORDS), rng.pick(COMPANY_SUFFIXES))
+            }
+            Generator::Phone => format!("+1 555-{:03}-{:04}", rng.below(1000), rng.below(10_000)),
+            Generator::Word => rng.pick(WORDS).to_string(),
+            Generator::Date(from, to) => format!(
+                "{:04}-{:02}-{:02}",
+                from + rng.below((to - from + 1) as u64) as i64,
+                rng.below(12) + 1,
+                rng.below(28) + 1
+            ),
+            Generator::Int(low, high) => {
+                let span = (high - low) as u64 + 1;
+                (low + rng.below(span) as i64).to_string()
+            }
+            Generator::Real(low, high) => {
+                let fraction = rng.below(1 << 53) as f64 / (1u64 << 53) as f64;
+                format!("{:.2}", low + (high - low) * fraction)
+            }
+            Generator::Bool => (rng.below(2) == 1).to_string(),
+            Generator::Seq => row.to_string(),
+            Generator::Pick(choices) => choices[rng.below(choices.len() as u64) as usize].clone(),
+            Generator::Null => return None,
+        };
+        Some(literal)
+    }
+}
+
+impl fmt::Display for Generator {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Generator::Name => f.write_str("fake_name"),
+            Generator::FirstName => f.write_str("fake_first_name"),
+            Generator::LastName => f.write_str("fake_last_name"),
+            Generator::Email => f.write_str("fake_email"),
+            Generator::Country => f.write_str("fake_country"),
+            Generator::City => f.write_str("fake_city"),
+            Generator::Company => f.write_str("fake_company"),
+            Generator::Phone => f.write_str("fake_phone"),
+            Generator::Word => f.write_str("fake_word"),
+            Generator::Date(from, to) => write!(f, "date({},{})", from, to),
+            Generator::Int(low, high) => write!(f, "int({},{})", low, high),
+            Generator::Real(low, high) => write!(f, "real({},{})", low, high),
+            Generator::Bool => f.write_str("bool"),
+            Generator::Seq => f.write_str("seq"),
+            Generator::Pick(choices) => write!(f, "pick({})", choices.join(",")),
+            Generator::Null => f.write_str("null"),
+        }
+    }
+}
+
+fn invalid(generator: &str) -> SQLRiteError {
+    SQLRiteError::General(format!("unknown generator {}", generator))
+}
+
+/// Parses a template, `column=generator` pairs separated by commas, into the generator of
+/// each column, in the order they are given.
+pub fn parse_template(template: &str) -> Result<Vec<(String, Generator)>> {
+    // The commas separating the arguments of a generator don't end it
+    let mut items = vec![];
+    let (mut depth, mut start) = (0, 0);
+    for (i, c) in template.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' => depth -= 1,
+            ',' if depth == 0 => {
+                items.push(&template[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    items.push(&template[start..]);
+    items
+        .into_iter()
+        .filter(|item| !item.trim().is_empty())
+        .map(|item| match item.split_once('=') {
+            Some((column, generator)) => {
+                Ok((column.trim().to_string(), Generator::parse(generator)?))
+            }
+            None => Err(SQLRiteError::General(format!(
+                "expected column=generator in the template, got {}",
+                item.trim()
+            ))),
+        })
+        .collect()
+}
+
+/// Inserts `count` generated rows into the table `table_name`. The columns of `template`
+/// get values of their generator, the others are left out, or when there is no template
+/// every column gets values of the generator picked for it, but an INTEGER PRIMARY KEY
+/// which is assigned its rowid. The same `seed` always generates the same rows. Rows the
+/// table rejects, like those breaking a UNIQUE constraint, are skipped and reported.
+pub fn seed(
+    db: &mut Database,
+    table_name: &str,
+    count: usize,
+    template: Option<&str>,
+    seed: u64,
+) -> Result<ImportReport> {
+    let table_name = db.resolve_table_name(table_name)?;
+    let table = match db.tables.get(&table_name) {
+        Some(table) => table,
+        None => {
+            return Err(SQLRiteError::General(format!(
+                "no such table: {}",
+                table_name
+            )))
+        }
+    };
+    let generators = match template {
+        Some(template) => {
+            let generators = parse_template(template)?;
+            if let Some((column, _)) = generators
+                .iter()
+                .find(|(column, _)| !table.contains_column(column.to_string()))
+            {
+                return Err(SQLRiteError::General(format!(
+                    "table {} has no column named {}",
+                    table_name, column
+                )));
+            }
+            generators
+        }
+        None => table
+            .columns
+            .iter()
+            .filter(|column| !(column.is_pk && column.datatype == DataType::Integer))
+            .map(|column| {
+                (
+                    column.column_name.to_string(),
+                    Generator::for_column(column),
+                )
+            })
+            .collect(),
+    };
+    let (columns, generators): (Vec<String>, Vec<Generator>) = generators.into_iter().unzip();
+
+    // Rows are numbered after the last rowid, so unique values stay unique across seeds
+    let first = table.last_rowid.wrapping_add(1);
+    let mut rng = Rng::new(seed);
+    let mut report = ImportReport::default();
+    let mut batch = Vec::with_capacity(BATCH_ROWS.min(count));
+    for number in 0..count {
+        let row = first.wrapping_add(number as i64);
+        let values = generators
+            .iter()
+            .map(|generator| match generator.generate(&mut rng, row) {
+                Some(literal) => Value::Text(literal),
+                None => Value::Null,
+            })
+            .collect::<Vec<Value>>();
+        batch.push((number + 1, values));
+        if batch.len() == BATCH_ROWS {
+            insert_batch(db, &table_name, &columns, &batch, &mut report)?;
+            batch.clear();
+        }
+    }
+    insert_batch(db, &table_name, &columns, &batch, &mut report)?;
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sql::{process_command, process_query};
+
+    #[test]
+    fn parse_template_test() {
+        assert_eq!(
+            parse_template("name=fake_name, email=fake_email,score=real(0, 100),tier=pick(a,b)")
+                .unwrap(),
+            vec![
+                ("name".to_string(), Generator::Name),
+                ("email".to_string(), Generator::Email),
+                ("score".to_string(), Generator::Real(0.0, 100.0)),
+                (
+                    "tier".to_string(),
+                    Generator::Pick(vec!["a".to_string(), "b".to_string()])
+                ),
+            ]
+        );
+        assert!(parse_template("name=fake_nickname").is_err());
+        assert!(parse_template("score=real(100, 0)").is_err());
+        assert!(parse_template("name").is_err());
+        assert!(parse_template("name=fake_name(1)").is_err());
+    }
+
+    #[test]
+    fn seed_test() {
+        let mut db = Database::new("tempdb".to_string());
+        process_command(
+            "CREATE TABLE users (id INTEGER PRIMARY KEY, name TEXT, email TEXT UNIQUE, score REAL, active BOOL);",
+            &mut db,
+        )
+        .unwrap();
+        let report = seed(&mut db, "users", 12_000, None, 7).unwrap();
+        assert_eq!(report.inserted, 12_000);
+        assert!(report.skipped.is_empty());
+
+        let report = seed(
+            &mut db,
+            "users",
+            100,
+            Some("name=fake_first_name,score=real(0,10)"),
+            7,
+        )
+        .unwrap();
+        assert_eq!(report.inserted, 100);
+        let result = process_query(
+            "SELECT name, score FROM users WHERE id > 12000 AND score <= 10;",
+            &db,
+        )
+        .unwrap();
+        assert_eq!(result.rows.len(), 100);
+
+        // Every row of a seed breaking a UNIQUE constraint is skipped
+        let report = seed(&mut db, "users", 3, Some("email=pick(a@example.com)"), 7).unwrap();
+        assert_eq!((report.inserted, report.skipped.len()), (1, 2));
+
+        assert!(seed(&mut db, "users", 1, Some("nickname=fake_name"), 7).is_err());
+        assert!(seed(&mut db, "posts", 1, None, 7).is_err());
+    }
+}
@@ -0,0 +1,45 @@
+use std::io::BufReader;
+use std::net::TcpStream;
+
+use crate::error::{Result, SQLRiteError};
+use crate::server::protocol::{protocol_error, read_message, write_message, Request, Response};
+
+/// A session on a remote database, opened with `Client::connect`
+pub struct Client {
+    reader: BufReader<TcpStream>,
+    writer: TcpStream,
+}
+
+impl Client {
+    /// Connects to the server listening on `address`, like `localhost:7878`.
+    pub fn connect(address: &str) -> Result<Self> {
+        let writer = TcpStream::connect(address).map_err(protocol_error)?;
+        let reader = BufReader::new(writer.try_clone().map_err(protocol_error)?);
+        Ok(Client { reader, writer })
+    }
+
+    /// Authenticates the session with the credentials the server was started with.
+    pub fn authenticate(&mut self, user: &str, password: &str) -> Result<()> {
+        self.send(Request::Auth {
+            user: user.to_string(),
+            password: password.to_string(),
+        })
+        .map(|_| ())
+    }
+
+    /// Runs a SQL statement or a meta command on the server, returning its output.
+    pub fn execute(&mut self, command: &str) -> Result<String> {
+        self.send(Request::Execute {
+            command: command.to_string(),
+        })
+    }
+
+    fn send(&mut self, request: Request) -> Result<String> {
+        write_message(&mut self.writer, &request)?;
+        match read_message(&mut self.reader)? {
+            Some(Response::Ok { output }) => Ok(output),
+            Some(Response::Error { message }) => Err(SQLRiteError::Server(message)),
+            None => Err(protocol_error("the server closed the connection")),
+        }
+    }
+}
@@ -0,0 +1,406 @@
+//! gRPC service of the server, generated from `proto/sqlrite.proto`, for strongly-typed
+//! clients in other languages. Available with the `grpc` feature.
+//!
+//! The rows of a query are streamed in batches through a bounded channel: when a client
+//! reads slowly, HTTP/2 flow control stops the channel from being drained and the batches
+//! wait on the server instead of piling up in the connection.
+use std::net::{SocketAddr, TcpListener};
+use std::thread;
+
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::{ReceiverStream, TcpListenerStream};
+use tonic::{Request, Response, Status};
+
+use crate::error::{Result, SQLRiteError};
+use crate::server::http::basic_credentials;
+use crate::server::protocol::protocol_error;
+use crate::server::session::{SharedDatabase, DATABASE_LOCKED};
+use crate::server::{Credentials, DatabaseHandle, Identity, Outcome, Server};
+use crate::sql::db::privilege::is_permission_denied;
+use crate::sql::executor::ResultSet;
+use crate::sql::params::bind_parameters;
+use crate::sql::returns_rows;
+
+/// Code generated from `proto/sqlrite.proto`
+pub mod proto {
+    tonic::include_proto!("sqlrite.v1");
+}
+
+use proto::sql_rite_server::{SqlRite, SqlRiteServer};
+use proto::{
+    value, BeginTransactionRequest, BeginTransactionResponse, Column, ExecuteResponse,
+    QueryResponse, Row, StatementRequest, TransactionRequest, TransactionResponse,
+};
+
+/// Number of rows sent in each message of a query's stream
+const ROWS_PER_BATCH: usize = 256;
+/// Number of batches waiting for the client before the stream stops producing more
+const BUFFERED_BATCHES: usize = 4;
+
+impl Server {
+    /// Also serves the gRPC service on `address`, on a thread of its own running an async
+    /// runtime, sharing the database with the other clients. Returns the address listened on.
+    pub fn listen_grpc(&self, address: &str) -> Result<SocketAddr> {
+        let listener = TcpListener::bind(address).map_err(protocol_error)?;
+        let local_addr = listener.local_addr().map_err(protocol_error)?;
+        listener.set_nonblocking(true).map_err(protocol_error)?;
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .enable_all()
+            .build()
+            .map_err(protocol_error)?;
+        let service = GrpcService {
+            db: self.db.clone(),
+            credentials: self.credentials.clone(),
+        };
+        thread::spawn(move || {
+            runtime.block_on(async move {
+                let result = match tokio::net::TcpListener::from_std(listener) {
+                    Ok(listener) => {
+                        tonic::transport::Server::builder()
+                            .add_service(SqlRiteServer::new(service))
+                            .serve_with_incoming(TcpListenerStream::new(listener))
+                            .await
+                    }
+                    Err(err) => {
+                        log::warn!("Stopped accepting gRPC clients: {}", err);
+                        return;
+                    }
+                };
+                if let Err(err) = result {
+                    log::warn!("Stopped accepting gRPC clients: {}", err);
+                }
+            })
+        });
+        Ok(local_addr)
+    }
+}
+
+struct GrpcService {
+    db: DatabaseHandle,
+    credentials: Option<Credentials>,
+}
+
+impl GrpcService {
+    /// Runs `f` on the database thread, without blocking the runtime while it waits.
+    async fn call<F, R>(&self, f: F) -> std::result::Result<R, Status>
+    where
+        F: FnOnce(&mut SharedDatabase) -> Result<R> + Send + 'static,
+        R: Send + 'static,
+    {
+        let db = self.db.clone();
+        tokio::task::spawn_blocking(move || db.call(f))
+            .await
+            .map_err(|err| Status::internal(err.to_string()))?
+            .and_then(|result| result)
+            .map_err(to_status)
+    }
+
+    /// Authenticates the client of a request, which gives its user and password with HTTP
+    /// Basic auth in the `authorization` metadata.
+    async fn authenticate<T>(&self, request: &Request<T>) -> std::result::Result<Identity, Status> {
+        let login = request
+            .metadata()
+            .get("authorization")
+            .and_then(|value| value.to_str().ok())
+            .and_then(basic_credentials);
+        let peer = request.remote_addr();
+        let credentials = self.credentials.clone();
+        let db = self.db.clone();
+        // Hashing the password blocks, like the database thread
+        tokio::task::spawn_blocking(move || {
+            let login = login
+                .as_ref()
+                .map(|(user, password)| (user.as_str(), password.as_str()));
+            db.login(credentials.as_ref(), login, peer)
+        })
+        .await
+        .map_err(|err| Status::internal(err.to_string()))?
+        .map_err(to_status)?
+        .ok_or_else(|| Status::unauthenticated("authentication required"))
+    }
+}
+
+#[tonic::async_trait]
+impl SqlRite for GrpcService {
+    async fn execute(
+        &self,
+        request: Request<StatementRequest>,
+    ) -> std::result::Result<Response<ExecuteResponse>, Status> {
+        let identity = self.authenticate(&request).await?;
+        let request = request.into_inner();
+        let response = self
+            .call(move |db| {
+                let sql = bind(&request)?;
+                if returns_rows(&sql)? {
+                    return Err(SQLRiteError::General(
+                        "the statement returns rows, use Query".to_string(),
+                    ));
+                }
+                match db.execute(&identity, session(&request), &sql)? {
+                    Outcome::Done(result) => Ok(ExecuteResponse {
+                        message: result.message,
+                        changes: result.rows_affected.unwrap_or(0),
+                        last_insert_rowid: result.last_insert_rowid,
+                    }),
+                    Outcome::Rows(_) => Ok(ExecuteResponse::default()),
+                }
+            })
+            .await?;
+        Ok(Response::new(response))
+    }
+
+    type QueryStream = ReceiverStream<std::result::Result<QueryResponse, Status>>;
+
+    async fn query(
+        &self,
+        request: Request<StatementRequest>,
+    ) -> std::result::Result<Response<Self::QueryStream>, Status> {
+        let identity = self.authenticate(&request).await?;
+        let request = request.into_inner();
+        let result = self
+            .call(move |db| {
+                let sql = bind(&request)?;
+                if !returns_rows(&sql)? {
+                    return Err(SQLRiteError::General(
+                        "the statement returns no rows, use Execute".to_string(),
+                    ));
+                }
+                match db.execute(&identity, session(&request), &sql)? {
+                    Outcome::Rows(result) => Ok(result),
+                    Outcome::Done(_) => Ok(ResultSet::default()),
+                }
+            })
+            .await?;
+
+        let (sender, receiver) = mpsc::channel(BUFFERED_BATCHES);
+        tokio::spawn(async move {
+            let mut columns = result
+                .columns
+                .iter()
+                .map(|column| Column {
+                    name: column.name.to_string(),
+                    declared_type: column
+                        .datatype
+                        .as_ref()
+                        .map_or(String::new(), |datatype| datatype.to_string()),
+                })
+                .collect::<Vec<Column>>();
+            let mut rows = result.rows.into_iter().peekable();
+            // The columns are sent even when there are no rows
+            while !columns.is_empty() || rows.peek().is_some() {
+                let batch = QueryResponse {
+                    columns: std::mem::take(&mut columns),
+                    rows: rows
+                        .by_ref()
+                        .take(ROWS_PER_BATCH)
+                        .map(|row| Row {
+                            values: row.iter().map(to_proto).collect(),
+                        })
+                        .collect(),
+                };
+                // Waits while the client is behind, stops if it went away
+                if sender.send(Ok(batch)).await.is_err() {
+                    break;
+                }
+            }
+        });
+        Ok(Response::new(ReceiverStream::new(receiver)))
+    }
+
+    async fn begin_transaction(
+        &self,
+        request: Request<BeginTransactionRequest>,
+    ) -> std::result::Result<Response<BeginTransactionResponse>, Status> {
+        self.authenticate(&request).await?;
+        let session = self.call(|db| db.begin()).await?;
+        Ok(Response::new(BeginTransactionResponse { session }))
+    }
+
+    async fn commit(
+        &self,
+        request: Request<TransactionRequest>,
+    ) -> std::result::Result<Response<TransactionResponse>, Status> {
+        self.authenticate(&request).await?;
+        let session = request.into_inner().session;
+        self.call(move |db| db.commit(&session)).await?;
+        Ok(Response::new(TransactionResponse {}))
+    }
+
+    async fn rollback(
+        &self,
+        request: Request<TransactionRequest>,
+    ) -> std::result::Result<Response<TransactionResponse>, Status> {
+        self.authenticate(&request).await?;
+        let session = request.into_inner().session;
+        self.call(move |db| db.rollback(&session)).await?;
+        Ok(Response::new(TransactionResponse {}))
+    }
+}
+
+/// The statement of a request, with its parameters bound.
+fn bind(request: &StatementRequest) -> Result<String> {
+    let params = request
+        .params
+        .iter()
+        .map(from_proto)
+        .collect::<Vec<crate::sql::value::Value>>();
+    bind_parameters(&request.sql, &params)
+}
+
+fn session(request: &StatementRequest) -> Option<&str> {
+    Some(request.session.as_str()).filter(|session| !session.is_empty())
+}
+
+fn to_status(err: SQLRiteError) -> Status {
+    match &err {
+        SQLRiteError::General(message) if message == DATABASE_LOCKED => {
+            Status::aborted(err.to_string())
+        }
+        err if is_permission_denied(err) => Status::permission_denied(err.to_string()),
+        SQLRiteError::NotImplemented(_) => Status::unimplemented(err.to_string()),
+        SQLRiteError::Internal(_) => Status::internal(err.to_string()),
+        _ => Status::invalid_argument(err.to_string()),
+    }
+}
+
+fn to_proto(value: &crate::sql::value::Value) -> proto::Value {
+    use crate::sql::value::Value;
+    let kind = match value {
+        Value::Null => None,
+        Value::Integer(v) => Some(value::Kind::Integer(*v)),
+        Value::Real(v) => Some(value::Kind::Real(*v)),
+        Value::Text(v) => Some(value::Kind::Text(v.to_string())),
+        Value::Bool(v) => Some(value::Kind::Boolean(*v)),
+        Value::Blob(v) => Some(value::Kind::Blob(v.clone())),
+    };
+    proto::Value { kind }
+}
+
+fn from_proto(value: &proto::Value) -> crate::sql::value::Value {
+    use crate::sql::value::Value;
+    match &value.kind {
+        None => Value::Null,
+        Some(value::Kind::Integer(v)) => Value::Integer(*v),
+        Some(value::Kind::Real(v)) => Value::Real(*v),
+        Some(value::Kind::Text(v)) => Value::Text(v.to_string()),
+        Some(value::Kind::Boolean(v)) => Value::Bool(*v),
+        Some(value::Kind::Blob(v)) => Value::Blob(v.clone()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::proto::sql_rite_client::SqlRiteClient;
+    use super::*;
+    use crate::server::http::base64;
+
+    fn start_server(credentials: Option<Credentials>) -> String {
+        let server = Server::bind("127.0.0.1:0", credentials).unwrap();
+        let address = server.listen_grpc("127.0.0.1:0").unwrap();
+        thread::spawn(move || server.serve());
+        format!("http://{}", address)
+    }
+
+    fn statement(sql: &str) -> StatementRequest {
+        StatementRequest {
+            sql: sql.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn execute_and_query_test() {
+        let mut client = SqlRiteClient::connect(start_server(None)).await.unwrap();
+        client
+            .execute(statement(
+                "CREATE TABLE users (id INTEGER PRIMARY KEY, name TEXT);",
+            ))
+            .await
+            .unwrap();
+        for i in 0..300 {
+            let mut request = statement("INSERT INTO users (name) VALUES (?);");
+            request.params = vec![to_proto(&crate::sql::value::Value::Text(format!(
+                "user {}",
+                i
+            )))];
+            let response = client.execute(request).await.unwrap().into_inner();
+            assert_eq!(response.last_insert_rowid, i + 1);
+        }
+
+        let mut stream = client
+            .query(statement("SELECT id, name FROM users;"))
+            .await
+            .unwrap()
+            .into_inner();
+        let first = stream.message().await.unwrap().unwrap();
+        assert_eq!(first.columns.len(), 2);
+        assert_eq!(first.columns[0].declared_type, "Integer");
+        assert_eq!(first.rows.len(), ROWS_PER_BATCH);
+        let second = stream.message().await.unwrap().unwrap();
+        assert!(second.columns.is_empty());
+        assert_eq!(second.rows.len(), 300 - ROWS_PER_BATCH);
+        assert!(stream.message().await.unwrap().is_none());
+
+        let status = client
+            .execute(statement("SELECT * FROM users;"))
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), tonic::Code::InvalidArgument);
+    }
+
+    #[tokio::test]
+    async fn transaction_test() {
+        let mut client = SqlRiteClient::connect(start_server(None)).await.unwrap();
+        client
+            .execute(statement("CREATE TABLE users (id INTEGER PRIMARY KEY);"))
+            .await
+            .unwrap();
+        let session = client
+            .begin_transaction(BeginTransactionRequest {})
+            .await
+            .unwrap()
+            .into_inner()
+            .session;
+        let mut insert = statement("INSERT INTO users (id) VALUES (1);");
+        insert.session = session.to_string();
+        client.execute(insert).await.unwrap();
+
+        let status = client
+            .query(statement("SELECT * FROM users;"))
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), tonic::Code::Aborted);
+        client
+            .rollback(TransactionRequest { session })
+            .await
+            .unwrap();
+
+        let mut stream = client
+            .query(statement("SELECT * FROM users;"))
+            .await
+            .unwrap()
+            .into_inner();
+        assert!(stream.message().await.unwrap().unwrap().rows.is_empty());
+    }
+
+    #[tokio::test]
+    async fn authentication_test() {
+        let address = start_server(Some(Credentials {
+            user: "admin".to_string(),
+            password: "secret".to_string(),
+        }));
+        let mut client = SqlRiteClient::connect(address).await.unwrap();
+        let status = client
+            .query(statement("SELECT * FROM sqlite_master;"))
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), tonic::Code::Unauthenticated);
+
+        let mut request = Request::new(statement("SELECT * FROM sqlite_master;"));
+        request.metadata_mut().insert(
+            "authorization",
+            format!("Basic {}", base64("admin:secret")).parse().unwrap(),
+        );
+        client.query(request).await.unwrap();
+    }
+}
@@ -0,0 +1,571 @@
+//! HTTP/JSON API of the server, for web applications and `curl`:
+//!
+//! - `POST /query` with `{"sql": "SELECT ...", "params": [...]}` returns
+//!   `{"columns": [...], "rows": [[...], ...]}`;
+//! - `POST /execute` runs a statement returning no rows, and returns
+//!   `{"message": ..., "changes": ..., "last_insert_rowid": ...}`;
+//! - `POST /begin` opens a transaction and returns `{"session": token}`. Statements given
+//!   the token as `"session"` run in the transaction, until `POST /commit` or
+//!   `POST /rollback` with `{"session": token}` ends it.
+//!
+//! Parameters bind to the `?` placeholders of the statement. Errors are returned as
+//! `{"error": message}`. With credentials, requests authenticate with HTTP Basic auth.
+//! Every connection serves a single request.
+//!
+//! `GET /metrics` returns the metrics of the server for Prometheus to scrape.
+use std::io::{BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::thread;
+
+use serde::Deserialize;
+use serde_json::{json, Value as JsonValue};
+
+use crate::error::{Result, SQLRiteError};
+use crate::server::protocol::protocol_error;
+use crate::server::session::{SharedDatabase, DATABASE_LOCKED};
+use crate::server::{Credentials, DatabaseHandle, Identity, Outcome, Server};
+use crate::sql::db::privilege::is_permission_denied;
+use crate::sql::params::bind_parameters;
+use crate::sql::returns_rows;
+use crate::sql::value::Value;
+
+/// Largest request body accepted, in bytes
+const MAX_BODY_SIZE: usize = 16 * 1024 * 1024;
+
+impl Server {
+    /// Also serves the HTTP API on `address`, on a thread of its own, sharing the database
+    /// with the other clients. Returns the address listened on.
+    pub fn listen_http(&self, address: &str) -> Result<SocketAddr> {
+        let listener = TcpListener::bind(address).map_err(protocol_error)?;
+        let local_addr = listener.local_addr().map_err(protocol_error)?;
+        let db = self.db.clone();
+        let credentials = self.credentials.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let stream = match stream {
+                    Ok(stream) => stream,
+                    Err(err) => {
+                        log::warn!("Stopped accepting HTTP clients: {}", err);
+                        return;
+                    }
+                };
+                let db = db.clone();
+                let credentials = credentials.clone();
+                thread::spawn(move || {
+                    if let Err(err) = handle_client(stream, &db, credentials.as_ref()) {
+                        log::warn!("Closing HTTP connection: {}", err);
+                    }
+                });
+            }
+        });
+        Ok(local_addr)
+    }
+}
+
+/// The parts of an HTTP request the API uses
+struct HttpRequest {
+    method: String,
+    path: String,
+    authorization: Option<String>,
+    body: Vec<u8>,
+}
+
+/// An HTTP response, with a JSON body but for the metrics
+struct HttpResponse {
+    status: u16,
+    content_type: &'static str,
+    body: String,
+}
+
+impl HttpResponse {
+    fn ok(body: JsonValue) -> Self {
+        HttpResponse {
+            status: 200,
+            content_type: "application/json",
+            body: body.to_string(),
+        }
+    }
+
+    fn error(status: u16, message: &str) -> Self {
+        HttpResponse {
+            status,
+            content_type: "application/json",
+            body: json!({ "error": message }).to_string(),
+        }
+    }
+
+    /// A response in the Prometheus text exposition format
+    fn metrics(body: String) -> Self {
+        HttpResponse {
+            status: 200,
+            content_type: "text/plain; version=0.0.4",
+            body,
+        }
+    }
+
+    fn write_to(&self, writer: &mut dyn Write) -> Result<()> {
+        let reason = match self.status {
+            200 => "OK",
+            400 => "Bad Request",
+            401 => "Unauthorized",
+            404 => "Not Found",
+            405 => "Method Not Allowed",
+            409 => "Conflict",
+            413 => "Payload Too Large",
+            _ => "Internal Server Error",
+        };
+        let mut head = format!(
+            "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n",
+            self.status,
+            reason,
+            self.content_type,
+            self.body.len()
+        );
+        if self.status == 401 {
+            head.push_str("WWW-Authenticate: Basic realm=\"SQLRite\"\r\n");
+        }
+        head.push_str("\r\n");
+        writer
+            .write_all(head.as_bytes())
+            .and_then(|_| writer.write_all(self.body.as_bytes()))
+            .and_then(|_| writer.flush())
+            .map_err(protocol_error)
+    }
+}
+
+/// The body of `/query` and `/execute`
+#[derive(Deserialize)]
+struct StatementRequest {
+    sql: String,
+    #[serde(default)]
+    params: Vec<JsonValue>,
+    session: Option<String>,
+}
+
+/// The body of `/commit` and `/rollback`
+#[derive(Deserialize)]
+struct SessionRequest {
+    session: String,
+}
+
+fn handle_client(
+    stream: TcpStream,
+    db: &DatabaseHandle,
+    credentials: Option<&Credentials>,
+) -> Result<()> {
+    let peer = stream.peer_addr().ok();
+    let mut reader = BufReader::new(stream.try_clone().map_err(protocol_error)?);
+    let mut writer = stream;
+    let _connection = db.metrics.connection("http");
+    let response = match read_request(&mut reader)? {
+        Ok(request) => {
+            let login = request.authorization.as_deref().and_then(basic_credentials);
+            let login = login
+                .as_ref()
+                .map(|(user, password)| (user.as_str(), password.as_str()));
+            let identity = db.login(credentials, login, peer)?;
+            match identity {
+                Some(identity) => route(&request, &identity, db)?,
+                None => HttpResponse::error(401, "authentication required"),
+            }
+        }
+        Err(response) => response,
+    };
+    response.write_to(&mut writer)
+}
+
+/// Reads a request, or the response rejecting it when it can't be served.
+fn read_request(
+    reader: &mut dyn BufRead,
+) -> Result<std::result::Result<HttpRequest, HttpResponse>> {
+    let mut line = String::new();
+    reader.read_line(&mut line).map_err(protocol_error)?;
+    let mut parts = line.split_whitespace();
+    let (method, path) = match (parts.next(), parts.next()) {
+        (Some(method), Some(path)) => (method.to_string(), path.to_string()),
+        _ => return Ok(Err(HttpResponse::error(400, "invalid request line"))),
+    };
+
+    let mut content_length = 0;
+    let mut authorization = None;
+    loop {
+        line.clear();
+        if reader.read_line(&mut line).map_err(protocol_error)? == 0 {
+            break;
+        }
+        let header = line.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            let value = value.trim();
+            if name.eq_ignore_ascii_case("content-length") {
+                content_length = match value.parse::<usize>() {
+                    Ok(length) => length,
+                    Err(_) => return Ok(Err(HttpResponse::error(400, "invalid Content-Length"))),
+                };
+            } else if name.eq_ignore_ascii_case("authorization") {
+                authorization = Some(value.to_string());
+            }
+        }
+    }
+    if content_length > MAX_BODY_SIZE {
+        return Ok(Err(HttpResponse::error(413, "request body too large")));
+    }
+    let mut body = vec![0; content_length];
+    reader.read_exact(&mut body).map_err(protocol_error)?;
+    Ok(Ok(HttpRequest {
+        method,
+        path,
+        authorization,
+        body,
+    }))
+}
+
+/// Answers a request for one of the endpoints of the API.
+fn route(request: &HttpRequest, identity: &Identity, db: &DatabaseHandle) -> Result<HttpResponse> {
+    let path = request.path.split('?').next().unwrap_or_default();
+    if path == "/metrics" {
+        if request.method != "GET" {
+            return Ok(HttpResponse::error(405, "/metrics only accepts GET"));
+        }
+        return Ok(HttpResponse::metrics(db.call(|db| db.render_metrics())?));
+    }
+    if !matches!(
+        path,
+        "/query" | "/execute" | "/begin" | "/commit" | "/rollback"
+    ) {
+        return Ok(HttpResponse::error(404, "no such endpoint"));
+    }
+    if request.method != "POST" {
+        return Ok(HttpResponse::error(405, "endpoints only accept POST"));
+    }
+    let result = match path {
+        "/begin" => db
+            .call(|db| db.begin())?
+            .map(|token| json!({ "session": token })),
+        "/commit" | "/rollback" => {
+            let token = match serde_json::from_slice::<SessionRequest>(&request.body) {
+                Ok(body) => body.session,
+                Err(err) => return Ok(HttpResponse::error(400, &err.to_string())),
+            };
+            let commit = path == "/commit";
+            db.call(move |db| {
+                if commit {
+                    db.commit(&token)
+                } else {
+                    db.rollback(&token)
+                }
+            })?
+            .map(|_| json!({}))
+        }
+        _ => {
+            let body = match serde_json::from_slice::<StatementRequest>(&request.body) {
+                Ok(body) => body,
+                Err(err) => return Ok(HttpResponse::error(400, &err.to_string())),
+            };
+            let query = path == "/query";
+            let identity = identity.clone();
+            db.call(move |db| run_statement(body, query, &identity, db))?
+        }
+    };
+    Ok(match result {
+        Ok(body) => HttpResponse::ok(body),
+        Err(err) if matches!(&err, SQLRiteError::General(message) if message == DATABASE_LOCKED) => {
+            HttpResponse::error(409, &err.to_string())
+        }
+        Err(err) if is_permission_denied(&err) => HttpResponse::error(403, &err.to_string()),
+        Err(err) => HttpResponse::error(400, &err.to_string()),
+    })
+}
+
+/// Runs the statement of a `/query` request, or of an `/execute` request.
+fn run_statement(
+    body: StatementRequest,
+    query: bool,
+    identity: &Identity,
+    db: &mut SharedDatabase,
+) -> Result<JsonValue> {
+    let params = body
+        .params
+        .iter()
+        .map(json_to_value)
+        .collect::<Result<Vec<Value>>>()?;
+    let sql = bind_parameters(&body.sql, &params)?;
+    if returns_rows(&sql)? != query {
+        return Err(SQLRiteError::General(if query {
+            "the statement returns no rows, use /execute".to_string()
+        } else {
+            "the statement returns rows, use /query".to_string()
+        }));
+    }
+    let token = body.session.as_deref();
+    match db.execute(identity, token, &sql)? {
+        Outcome::Rows(result) => Ok(json!({
+            "columns": result.columns.iter().map(|column| column.name.as_str()).collect::<Vec<&str>>(),
+            "rows": result
+                .rows
+                .iter()
+                .map(|row| row.iter().map(value_to_json).collect::<Vec<JsonValue>>())
+                .collect::<Vec<Vec<JsonValue>>>(),
+        })),
+        Outcome::Done(result) => Ok(json!({
+            "message": result.message,
+            "changes": result.rows_affected.unwrap_or(0),
+            "last_insert_rowid": result.last_insert_rowid,
+        })),
+    }
+}
+
+fn json_to_value(value: &JsonValue) -> Result<Value> {
+    match value {
+        JsonValue::Null => Ok(Value::Null),
+        JsonValue::Bool(v) => Ok(Value::Bool(*v)),
+        JsonValue::Number(n) => Ok(match n.as_i64() {
+            Some(v) => Value::Integer(v),
+            None => Value::Real(n.as_f64().unwrap_or(f64::NAN)),
+        }),
+        JsonValue::String(v) => Ok(Value::Text(v.to_string())),
+        value => Err(SQLRiteError::General(format!(
+            "parameters can't be arrays or objects: {}",
+            value
+        ))),
+    }
+}
+
+/// Converts a value to JSON, blobs being encoded in base64.
+fn value_to_json(value: &Value) -> JsonValue {
+    match value {
+        Value::Null => JsonValue::Null,
+        Value::Integer(v) => json!(v),
+        // Infinities and NaN have no JSON representation, they become null
+        Value::Real(v) => json!(v),
+        Value::Text(v) => json!(v),
+        Value::Bool(v) => json!(v),
+        Value::Blob(v) => json!(base64(v)),
+    }
+}
+
+/// Encodes `data` in standard base64, with padding.
+pub(crate) fn base64(data: impl AsRef<[u8]>) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut encoded = String::new();
+    for chunk in data.as_ref().chunks(3) {
+        let bytes = [
+            chunk[0],
+            *chunk.get(1).unwrap_or(&0),
+            *chunk.get(2).unwrap_or(&0),
+        ];
+        let n = u32::from_be_bytes([0, bytes[0], bytes[1], bytes[2]]);
+        for i in 0..4 {
+            if i <= chunk.len() {
+                encoded.push(ALPHABET[(n >> (18 - 6 * i) & 63) as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
+
+/// The user and password of an `Authorization: Basic` header.
+pub(crate) fn basic_credentials(authorization: &str) -> Option<(String, String)> {
+    let decoded = unbase64(authorization.strip_prefix("Basic ")?.trim())?;
+    let decoded = String::from_utf8(decoded).ok()?;
+    let (user, password) = decoded.split_once(':')?;
+    Some((user.to_string(), password.to_string()))
+}
+
+/// Decodes base64 with padding, `None` if `encoded` isn't valid.
+fn unbase64(encoded: &str) -> Option<Vec<u8>> {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    if !encoded.len().is_multiple_of(4) {
+        return None;
+    }
+    let mut decoded = vec![];
+    for chunk in encoded.as_bytes().chunks(4) {
+        let padding = chunk.iter().rev().take_while(|b| **b == b'=').count();
+        if padding > 2 {
+            return None;
+        }
+        let mut n: u32 = 0;
+        for b in &chunk[..4 - padding] {
+            n = (n << 6) | ALPHABET.iter().position(|a| a == b)? as u32;
+        }
+        n <<= 6 * padding;
+        decoded.extend_from_slice(&n.to_be_bytes()[1..4 - padding]);
+    }
+    Some(decoded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+
+    /// Sends a POST request, returning the status and the JSON body of the response
+    fn post(
+        address: SocketAddr,
+        path: &str,
+        body: JsonValue,
+        auth: Option<&str>,
+    ) -> (u16, JsonValue) {
+        let mut stream = TcpStream::connect(address).unwrap();
+        let body = body.to_string();
+        let auth = auth.map_or(String::new(), |auth| {
+            format!("Authorization: Basic {}\r\n", base64(auth))
+        });
+        write!(
+            stream,
+            "POST {} HTTP/1.1\r\nHost: localhost\r\n{}Content-Length: {}\r\n\r\n{}",
+            path,
+            auth,
+            body.len(),
+            body
+        )
+        .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        let (head, body) = response.split_once("\r\n\r\n").unwrap();
+        let status = head.split_whitespace().nth(1).unwrap().parse().unwrap();
+        (status, serde_json::from_str(body).unwrap())
+    }
+
+    fn start_server(credentials: Option<Credentials>) -> SocketAddr {
+        let server = Server::bind("127.0.0.1:0", credentials).unwrap();
+        let address = server.listen_http("127.0.0.1:0").unwrap();
+        thread::spawn(move || server.serve());
+        address
+    }
+
+    #[test]
+    fn query_and_execute_test() {
+        let address = start_server(None);
+        let sql = |sql: &str| json!({ "sql": sql });
+
+        let (status, _) = post(
+            address,
+            "/execute",
+            sql("CREATE TABLE users (id INTEGER PRIMARY KEY, name TEXT, score REAL);"),
+            None,
+        );
+        assert_eq!(status, 200);
+        let (status, body) = post(
+            address,
+            "/execute",
+            json!({
+                "sql": "INSERT INTO users (name, score) VALUES (?, ?);",
+                "params": ["o'neil", 2.5],
+            }),
+            None,
+        );
+        assert_eq!(status, 200);
+        assert_eq!(body["changes"], 1);
+        assert_eq!(body["last_insert_rowid"], 1);
+
+        let (status, body) = post(
+            address,
+            "/query",
+            json!({ "sql": "SELECT id, name, score FROM users WHERE id = ?;", "params": [1] }),
+            None,
+        );
+        assert_eq!(status, 200);
+        assert_eq!(
+            body,
+            json!({ "columns": ["id", "name", "score"], "rows": [[1, "o'neil", 2.5]] })
+        );
+
+        let (status, body) = post(address, "/query", sql("SELECT * FROM missing;"), None);
+        assert_eq!(status, 400);
+        assert!(body["error"].is_string());
+        assert_eq!(
+            post(address, "/query", sql("DELETE FROM users;"), None).0,
+            400
+        );
+        assert_eq!(post(address, "/nope", json!({}), None).0, 404);
+    }
+
+    #[test]
+    fn transaction_test() {
+        let address = start_server(None);
+        post(
+            address,
+            "/execute",
+            json!({ "sql": "CREATE TABLE users (id INTEGER PRIMARY KEY);" }),
+            None,
+        );
+
+        let (_, body) = post(address, "/begin", json!({}), None);
+        let session = body["session"].as_str().unwrap().to_string();
+        let insert = json!({ "sql": "INSERT INTO users (id) VALUES (1);", "session": session });
+        assert_eq!(post(address, "/execute", insert, None).0, 200);
+        // Other sessions are locked out until the transaction ends
+        let select = json!({ "sql": "SELECT * FROM users;" });
+        assert_eq!(post(address, "/query", select.clone(), None).0, 409);
+
+        let (status, _) = post(address, "/rollback", json!({ "session": session }), None);
+        assert_eq!(status, 200);
+        let (_, body) = post(address, "/query", select, None);
+        assert_eq!(body["rows"], json!([]));
+    }
+
+    #[test]
+    fn authentication_test() {
+        let address = start_server(Some(Credentials {
+            user: "admin".to_string(),
+            password: "secret".to_string(),
+        }));
+        let select = json!({ "sql": "SELECT * FROM sqlite_master;" });
+        assert_eq!(post(address, "/query", select.clone(), None).0, 401);
+        assert_eq!(
+            post(address, "/query", select.clone(), Some("admin:wrong")).0,
+            401
+        );
+        assert_eq!(post(address, "/query", select, Some("admin:secret")).0, 200);
+    }
+
+    #[test]
+    fn metrics_test() {
+        let address = start_server(None);
+        let create = json!({ "sql": "CREATE TABLE users (id INTEGER PRIMARY KEY);" });
+        assert_eq!(post(address, "/execute", create, None).0, 200);
+        let select = json!({ "sql": "SELECT * FROM missing;" });
+        assert_eq!(post(address, "/query", select, None).0, 400);
+
+        let mut stream = TcpStream::connect(address).unwrap();
+        write!(stream, "GET /metrics HTTP/1.1\r\nHost: localhost\r\n\r\n").unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 200 OK"));
+        assert!(response.contains("Content-Type: text/plain; version=0.0.4"));
+        for line in &[
+            "sqlrite_statements_total{type=\"create\"} 1",
+            "sqlrite_statement_errors_total{type=\"select\"} 1",
+            "sqlrite_connections_total{protocol=\"http\"} 3",
+            // The connection scraping the metrics
+            "sqlrite_active_connections{protocol=\"http\"} 1",
+            "sqlrite_table_rows{table=\"users\"} 0",
+        ] {
+            assert!(response.lines().any(|l| l == *line), "missing {}", line);
+        }
+        assert_eq!(post(address, "/metrics", json!({}), None).0, 405);
+    }
+
+    #[test]
+    fn base64_test() {
+        assert_eq!(base64(""), "");
+        assert_eq!(base64("f"), "Zg==");
+        assert_eq!(base64("fo"), "Zm8=");
+        assert_eq!(base64("foo"), "Zm9v");
+        assert_eq!(base64("admin:secret"), "YWRtaW46c2VjcmV0");
+        for data in ["", "f", "fo", "foo", "admin:secret"] {
+            assert_eq!(unbase64(&base64(data)), Some(data.as_bytes().to_vec()));
+        }
+        assert_eq!(unbase64("Zm9"), None);
+        assert_eq!(unbase64("Zm9*"), None);
+        assert_eq!(
+            basic_credentials("Basic YWRtaW46c2VjcmV0"),
+            Some(("admin".to_string(), "secret".to_string()))
+        );
+    }
+}
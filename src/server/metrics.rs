@@ -0,0 +1,326 @@
+//! Metrics of the server, exported in the Prometheus text format at `GET /metrics` of the
+//! HTTP API:
+//!
+//! - `sqlrite_statements_total` and `sqlrite_statement_errors_total`, by statement type;
+//! - `sqlrite_statement_duration_seconds`, a histogram of statement latencies by type;
+//! - `sqlrite_connections_total` and `sqlrite_active_connections`, by protocol, but for
+//!   the gRPC service whose connections are managed by tonic;
+//! - `sqlrite_transactions_total`, by how the transactions of the sessions ended;
+//! - `sqlrite_transaction_open`, `sqlrite_tables` and `sqlrite_table_rows`, read from the
+//!   database when scraped.
+//!
+//! The database of the server lives in memory, it has no page cache, write-ahead log or
+//! checkpoints to report on.
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::{Mutex, MutexGuard};
+use std::time::Duration;
+
+use crate::sql::db::database::Database;
+
+/// Upper bounds of the buckets of the latency histograms, in seconds
+const LATENCY_BUCKETS: [f64; 10] = [0.0001, 0.0005, 0.001, 0.005, 0.01, 0.05, 0.1, 0.5, 1.0, 5.0];
+
+/// Statement types counted on their own, the others are counted as `other`
+const STATEMENT_TYPES: [&str; 13] = [
+    "select", "insert", "update", "delete", "create", "drop", "alter", "pragma", "with", "values",
+    "begin", "commit", "rollback",
+];
+
+/// Counters of the statements of one type
+#[derive(Default)]
+struct StatementStats {
+    count: u64,
+    errors: u64,
+    /// Number of statements that took at most each of the `LATENCY_BUCKETS`
+    buckets: [u64; LATENCY_BUCKETS.len()],
+    /// Total time spent running the statements, in seconds
+    seconds: f64,
+}
+
+/// Counters of the connections over one protocol
+#[derive(Default, Clone, Copy)]
+struct ConnectionStats {
+    total: u64,
+    active: u64,
+}
+
+/// Metrics of a server, shared by the database thread and the threads of the clients
+#[derive(Default)]
+pub struct Metrics {
+    statements: Mutex<BTreeMap<&'static str, StatementStats>>,
+    connections: Mutex<BTreeMap<&'static str, ConnectionStats>>,
+    transactions: Mutex<BTreeMap<&'static str, u64>>,
+}
+
+/// Counts a connection as active until it is dropped.
+pub struct ConnectionGuard<'a> {
+    metrics: &'a Metrics,
+    protocol: &'static str,
+}
+
+impl Drop for ConnectionGuard<'_> {
+    fn drop(&mut self) {
+        if let Some(stats) = lock(&self.metrics.connections).get_mut(self.protocol) {
+            stats.active -= 1;
+        }
+    }
+}
+
+impl Metrics {
+    /// Records that a statement ran for `elapsed`, failing or not.
+    pub fn record_statement(&self, sql: &str, elapsed: Duration, failed: bool) {
+        let mut statements = lock(&self.statements);
+        let stats = statements.entry(statement_type(sql)).or_default();
+        let seconds = elapsed.as_secs_f64();
+        stats.count += 1;
+        if failed {
+            stats.errors += 1;
+        }
+        stats.seconds += seconds;
+        for (bucket, bound) in stats.buckets.iter_mut().zip(LATENCY_BUCKETS.iter()) {
+            if seconds <= *bound {
+                *bucket += 1;
+            }
+        }
+    }
+
+    /// Records that a client connected over `protocol`, until the returned guard is dropped.
+    pub fn connection(&self, protocol: &'static str) -> ConnectionGuard<'_> {
+        let mut connections = lock(&self.connections);
+        let stats = connections.entry(protocol).or_default();
+        stats.total += 1;
+        stats.active += 1;
+        ConnectionGuard {
+            metrics: self,
+            protocol,
+        }
+    }
+
+    /// Records that a transaction ended, `outcome` being `commit`, `rollback` or `expired`.
+    pub fn record_transaction(&self, outcome: &'static str) {
+        *lock(&self.transactions).entry(outcome).or_default() += 1;
+    }
+
+    /// Renders the metrics in the Prometheus text exposition format, with the gauges read
+    /// from `db`.
+    pub fn render(&self, db: &Database, transaction_open: bool) -> String {
+        let mut out = String::new();
+        let statements = lock(&self.statements);
+        header(
+            &mut out,
+            "sqlrite_statements_total",
+            "counter",
+            "Statements run, by type.",
+        );
+        for (kind, stats) in statements.iter() {
+            let _ = writeln!(
+                out,
+                "sqlrite_statements_total{{type=\"{}\"}} {}",
+                kind, stats.count
+            );
+        }
+        header(
+            &mut out,
+            "sqlrite_statement_errors_total",
+            "counter",
+            "Statements that failed, by type.",
+        );
+        for (kind, stats) in statements.iter() {
+            let _ = writeln!(
+                out,
+                "sqlrite_statement_errors_total{{type=\"{}\"}} {}",
+                kind, stats.errors
+            );
+        }
+        header(
+            &mut out,
+            "sqlrite_statement_duration_seconds",
+            "histogram",
+            "Time spent running statements, by type.",
+        );
+        for (kind, stats) in statements.iter() {
+            for (bucket, bound) in stats.buckets.iter().zip(LATENCY_BUCKETS.iter()) {
+                let _ = writeln!(
+                    out,
+                    "sqlrite_statement_duration_seconds_bucket{{type=\"{}\",le=\"{}\"}} {}",
+                    kind, bound, bucket
+                );
+            }
+            let _ = writeln!(
+                out,
+                "sqlrite_statement_duration_seconds_bucket{{type=\"{}\",le=\"+Inf\"}} {}",
+                kind, stats.count
+            );
+            let _ = writeln!(
+                out,
+                "sqlrite_statement_duration_seconds_sum{{type=\"{}\"}} {}",
+                kind, stats.seconds
+            );
+            let _ = writeln!(
+                out,
+                "sqlrite_statement_duration_seconds_count{{type=\"{}\"}} {}",
+                kind, stats.count
+            );
+        }
+        drop(statements);
+
+        let connections = lock(&self.connections);
+        header(
+            &mut out,
+            "sqlrite_connections_total",
+            "counter",
+            "Connections accepted, by protocol.",
+        );
+        for (protocol, stats) in connections.iter() {
+            let _ = writeln!(
+                out,
+                "sqlrite_connections_total{{protocol=\"{}\"}} {}",
+                protocol, stats.total
+            );
+        }
+        header(
+            &mut out,
+            "sqlrite_active_connections",
+            "gauge",
+            "Connections currently open, by protocol.",
+        );
+        for (protocol, stats) in connections.iter() {
+            let _ = writeln!(
+                out,
+                "sqlrite_active_connections{{protocol=\"{}\"}} {}",
+                protocol, stats.active
+            );
+        }
+        drop(connections);
+
+        header(
+            &mut out,
+            "sqlrite_transactions_total",
+            "counter",
+            "Transactions ended, by outcome.",
+        );
+        for (outcome, count) in lock(&self.transactions).iter() {
+            let _ = writeln!(
+                out,
+                "sqlrite_transactions_total{{outcome=\"{}\"}} {}",
+                outcome, count
+            );
+        }
+        header(
+            &mut out,
+            "sqlrite_transaction_open",
+            "gauge",
+            "Whether a session has a transaction open.",
+        );
+        let _ = writeln!(out, "sqlrite_transaction_open {}", transaction_open as u8);
+        header(
+            &mut out,
+            "sqlrite_tables",
+            "gauge",
+            "Tables in the database.",
+        );
+        let _ = writeln!(out, "sqlrite_tables {}", db.tables.len());
+        header(
+            &mut out,
+            "sqlrite_table_rows",
+            "gauge",
+            "Rows in each table of the database.",
+        );
+        let mut tables = db.tables.iter().collect::<Vec<_>>();
+        tables.sort_by(|a, b| a.0.cmp(b.0));
+        for (name, table) in tables {
+            let _ = writeln!(
+                out,
+                "sqlrite_table_rows{{table=\"{}\"}} {}",
+                escape_label(name),
+                table.rowids().len()
+            );
+        }
+        out
+    }
+}
+
+fn header(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+}
+
+/// Escapes a label value, table names being able to contain quotes.
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// The type of a statement, from its first keyword.
+fn statement_type(sql: &str) -> &'static str {
+    let keyword = sql
+        .trim_start()
+        .split(|c: char| !c.is_ascii_alphabetic())
+        .next()
+        .unwrap_or_default()
+        .to_lowercase();
+    STATEMENT_TYPES
+        .iter()
+        .find(|kind| **kind == keyword)
+        .copied()
+        .unwrap_or("other")
+}
+
+/// Locks the counters, which stay consistent even if a thread panicked holding them.
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sql::process_command;
+
+    #[test]
+    fn render_test() {
+        let metrics = Metrics::default();
+        metrics.record_statement("select 1;", Duration::from_micros(300), false);
+        metrics.record_statement("  INSERT INTO t VALUES (1);", Duration::from_secs(2), true);
+        metrics.record_statement("VACUUM;", Duration::from_millis(1), false);
+        {
+            let _connection = metrics.connection("postgres");
+            let _connection = metrics.connection("postgres");
+        }
+        let _connection = metrics.connection("http");
+        metrics.record_transaction("commit");
+
+        let mut db = Database::new("tempdb".to_string());
+        process_command(
+            "CREATE TABLE users (id INTEGER PRIMARY KEY, name TEXT);",
+            &mut db,
+        )
+        .unwrap();
+        process_command("INSERT INTO users (name) VALUES ('josh');", &mut db).unwrap();
+        let text = metrics.render(&db, false);
+        for line in &[
+            "# TYPE sqlrite_statement_duration_seconds histogram",
+            "sqlrite_statements_total{type=\"select\"} 1",
+            "sqlrite_statements_total{type=\"other\"} 1",
+            "sqlrite_statement_errors_total{type=\"insert\"} 1",
+            "sqlrite_statement_duration_seconds_bucket{type=\"select\",le=\"0.0001\"} 0",
+            "sqlrite_statement_duration_seconds_bucket{type=\"select\",le=\"0.0005\"} 1",
+            "sqlrite_statement_duration_seconds_bucket{type=\"insert\",le=\"1\"} 0",
+            "sqlrite_statement_duration_seconds_bucket{type=\"insert\",le=\"+Inf\"} 1",
+            "sqlrite_statement_duration_seconds_sum{type=\"insert\"} 2",
+            "sqlrite_connections_total{protocol=\"postgres\"} 2",
+            "sqlrite_active_connections{protocol=\"postgres\"} 0",
+            "sqlrite_active_connections{protocol=\"http\"} 1",
+            "sqlrite_transactions_total{outcome=\"commit\"} 1",
+            "sqlrite_transaction_open 0",
+            "sqlrite_tables 1",
+            "sqlrite_table_rows{table=\"users\"} 1",
+        ] {
+            assert!(text.lines().any(|l| l == *line), "missing {}", line);
+        }
+    }
+}
@@ -0,0 +1,559 @@
+//! Network server mode: `SQLRite serve` shares one database between the clients connecting
+//! over TCP, and `SQLRite connect` opens a REPL session on a remote database.
+//!
+//! Every client gets its own thread, which ships the commands of the client to the thread
+//! owning the database, like `AsyncConnection` does. The commands of all the clients run
+//! there one at a time, so each of them sees the changes of the others. A transaction a
+//! client begins belongs to its connection, and is rolled back if the client disconnects.
+//!
+//! Besides its own protocol, the server can also accept PostgreSQL clients, and serve an
+//! HTTP/JSON API and a gRPC service whose sessions can run transactions. The HTTP API also
+//! exports the metrics of the server for Prometheus.
+pub mod client;
+#[cfg(feature = "grpc")]
+pub mod grpc;
+pub mod http;
+pub mod metrics;
+pub mod postgres;
+pub mod protocol;
+pub mod session;
+
+use std::io::BufReader;
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::path::Path;
+use std::sync::{mpsc, Arc};
+use std::thread;
+
+use crate::error::{Result, SQLRiteError};
+use crate::meta_command::{handle_remote_meta_command, snapshot_path, MetaCommand};
+use crate::sql::db::database::{write_new_file, Database};
+use crate::sql::executor::pragma::execute_pragma;
+use crate::sql::executor::ResultSet;
+use crate::sql::parser::pragma::parse_pragma;
+use crate::sql::{process_command, process_query, returns_rows, QueryResult};
+
+use metrics::Metrics;
+use protocol::{protocol_error, read_message, write_message, Request, Response};
+use session::{ClientSession, SharedDatabase};
+use subtle::ConstantTimeEq;
+
+/// Port the server listens on when none is given
+pub const DEFAULT_PORT: u16 = 7878;
+
+/// The user and password clients have to authenticate with
+#[derive(Debug, Clone, PartialEq)]
+pub struct Credentials {
+    pub user: String,
+    pub password: String,
+}
+
+impl Credentials {
+    /// Returns true if the credentials given by a client are these. Both are compared in
+    /// constant time, so the time taken doesn't tell how much of them matched.
+    pub fn check(&self, user: &str, password: &str) -> bool {
+        let user = self.user.as_bytes().ct_eq(user.as_bytes());
+        let password = self.password.as_bytes().ct_eq(password.as_bytes());
+        (user & password).into()
+    }
+}
+
+/// Unit of work shipped to the database thread.
+type Job = Box<dyn FnOnce(&mut SharedDatabase) + Send + 'static>;
+
+/// Handle on the database thread, shared by the threads of the clients
+#[derive(Clone)]
+pub(crate) struct DatabaseHandle {
+    sender: mpsc::Sender<Job>,
+    pub(crate) metrics: Arc<Metrics>,
+}
+
+impl DatabaseHandle {
+    /// Starts the thread owning a transient in-memory database, which exits once every
+    /// handle has been dropped.
+    fn spawn() -> Result<Self> {
+        let (sender, receiver) = mpsc::channel::<Job>();
+        let metrics = Arc::new(Metrics::default());
+        let db_metrics = Arc::clone(&metrics);
+        thread::Builder::new()
+            .name("sqlrite-server".to_string())
+            .spawn(move || {
+                let mut db = SharedDatabase::new(Database::new("tempdb".to_string()), db_metrics);
+                while let Ok(job) = receiver.recv() {
+                    job(&mut db);
+                }
+            })
+            .map_err(|err| {
+                SQLRiteError::Internal(format!("Could not start database thread: {}", err))
+            })?;
+        Ok(DatabaseHandle { sender, metrics })
+    }
+
+    /// Runs `f` against the database on its thread and returns its result once it is done.
+    pub(crate) fn call<F, R>(&self, f: F) -> Result<R>
+    where
+        F: FnOnce(&mut SharedDatabase) -> R + Send + 'static,
+        R: Send + 'static,
+    {
+        let (reply, response) = mpsc::channel();
+        self.sender
+            .send(Box::new(move |db: &mut SharedDatabase| {
+                // The client may have disconnected, there is nobody to report that to.
+                let _ = reply.send(f(db));
+            }))
+            .map_err(database_closed)?;
+        response.recv().map_err(database_closed)
+    }
+
+    /// Authenticates a client connecting from `peer` with the user and password of
+    /// `login`, `None` if it gave none. Clients with the `credentials` of the server own the
+    /// database, those with the password of a user created with `CREATE USER` run their
+    /// statements as that user. Without credentials, the other clients own the database
+    /// until a user is created: then only the users are let in, for a client not to get
+    /// round the privileges of a user by connecting under another name. Returns `None` if
+    /// authentication fails.
+    ///
+    /// Only the user is looked up on the database thread: its password is hashed on the
+    /// calling thread, so that a login doesn't hold up the statements of the other clients.
+    pub(crate) fn login(
+        &self,
+        credentials: Option<&Credentials>,
+        login: Option<(&str, &str)>,
+        peer: Option<SocketAddr>,
+    ) -> Result<Option<Identity>> {
+        let name = login.map(|(name, _)| name.to_string());
+        let (user, has_users) =
+            self.call(move |db| (name.and_then(|name| db.user(&name)), db.has_users()))?;
+        Ok(match login {
+            Some((name, password))
+                if user
+                    .as_ref()
+                    .is_some_and(|user| user.check_password(password)) =>
+            {
+                Some(Identity::new(Some(name), true, peer))
+            }
+            Some((name, password))
+                if credentials
+                    .map_or(!has_users, |credentials| credentials.check(name, password)) =>
+            {
+                Some(Identity::new(Some(name), false, peer))
+            }
+            None if credentials.is_none() && !has_users => Some(Identity::new(None, false, peer)),
+            _ => None,
+        })
+    }
+}
+
+/// A server sharing a transient in-memory database with the clients connecting to it
+pub struct Server {
+    listener: TcpListener,
+    db: DatabaseHandle,
+    credentials: Option<Credentials>,
+}
+
+impl Server {
+    /// Listens on `address` for clients. Without credentials, any client can connect.
+    pub fn bind(address: &str, credentials: Option<Credentials>) -> Result<Self> {
+        let listener = TcpListener::bind(address).map_err(protocol_error)?;
+        Ok(Server {
+            listener,
+            db: DatabaseHandle::spawn()?,
+            credentials,
+        })
+    }
+
+    /// Address the server listens on, with the port picked by the system when binding port 0.
+    pub fn local_addr(&self) -> Result<SocketAddr> {
+        self.listener.local_addr().map_err(protocol_error)
+    }
+
+    /// A handle to shut the server down from another thread, like a signal handler.
+    pub fn shutdown_handle(&self) -> ShutdownHandle {
+        ShutdownHandle {
+            db: self.db.clone(),
+        }
+    }
+
+    /// Accepts clients until the listener fails, serving each of them on its own thread.
+    pub fn serve(&self) -> Result<()> {
+        for stream in self.listener.incoming() {
+            let stream = stream.map_err(protocol_error)?;
+            let db = self.db.clone();
+            let credentials = self.credentials.clone();
+            thread::spawn(move || {
+                let peer = stream.peer_addr().map(|addr| addr.to_string());
+                if let Err(err) = handle_client(stream, &db, credentials.as_ref()) {
+                    log::warn!("Closing connection of {:?}: {}", peer, err);
+                }
+            });
+        }
+        Ok(())
+    }
+}
+
+/// Handle to shut a server down, returned by `Server::shutdown_handle`
+#[derive(Clone)]
+pub struct ShutdownHandle {
+    db: DatabaseHandle,
+}
+
+impl ShutdownHandle {
+    /// Rolls back the transaction a client has open, once the statement running is done, so
+    /// that the process can exit. Returns true if a transaction was open.
+    pub fn shutdown(&self) -> Result<bool> {
+        self.db.call(|db| db.shutdown())?
+    }
+}
+
+/// Answers the requests of a client until it disconnects. Clients that fail to
+/// authenticate are disconnected.
+fn handle_client(
+    stream: TcpStream,
+    db: &DatabaseHandle,
+    credentials: Option<&Credentials>,
+) -> Result<()> {
+    let peer = stream.peer_addr().ok();
+    let mut reader = BufReader::new(stream.try_clone().map_err(protocol_error)?);
+    let mut writer = stream;
+    let mut identity = db.login(credentials, None, peer)?;
+    let _connection = db.metrics.connection("native");
+    let mut session = ClientSession::new(db);
+
+    while let Some(request) = read_message::<Request>(&mut reader)? {
+        let response = match (request, &identity) {
+            (Request::Auth { user, password }, _) => {
+                identity = db.login(credentials, Some((&user, &password)), peer)?;
+                if identity.is_none() {
+                    let response = Response::Error {
+                        message: "authentication failed".to_string(),
+                    };
+                    return write_message(&mut writer, &response);
+                }
+                Response::Ok {
+                    output: String::new(),
+                }
+            }
+            (Request::Execute { .. }, None) => {
+                let response = Response::Error {
+                    message: "authentication required".to_string(),
+                };
+                return write_message(&mut writer, &response);
+            }
+            (Request::Execute { command }, Some(identity)) => {
+                match run_command(command, identity.clone(), &mut session)? {
+                    Ok(output) => Response::Ok { output },
+                    Err(err) => Response::Error {
+                        message: err.to_string(),
+                    },
+                }
+            }
+        };
+        write_message(&mut writer, &response)?;
+    }
+    Ok(())
+}
+
+/// Who an authenticated client is
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Identity {
+    /// How the client is named in the audit log: the user it authenticated as, and the
+    /// address it connected from
+    pub(crate) name: String,
+    /// The user created with `CREATE USER` its statements run as, `None` for the owner of
+    /// the database, who may do anything
+    pub(crate) user: Option<String>,
+}
+
+impl Identity {
+    pub(crate) fn new(user: Option<&str>, restricted: bool, peer: Option<SocketAddr>) -> Self {
+        let peer = peer.map_or("unknown".to_string(), |peer| peer.to_string());
+        let name = match user {
+            Some(user) if !user.is_empty() => format!("{}@{}", user, peer),
+            _ => peer,
+        };
+        Identity {
+            name,
+            user: user.filter(|_| restricted).map(|user| user.to_string()),
+        }
+    }
+}
+
+fn database_closed<E>(_: E) -> SQLRiteError {
+    SQLRiteError::Internal("The database thread has stopped.".to_string())
+}
+
+/// Runs a command sent by `identity` on the database thread, in the transaction the client
+/// has open in `session`, returning the result of the command. `.snapshot` only serializes
+/// the committed database on the database thread, the file is written from the thread of the
+/// client while the others keep running statements.
+fn run_command(
+    command: String,
+    identity: Identity,
+    session: &mut ClientSession,
+) -> Result<Result<String>> {
+    let trimmed = command.trim();
+    if !trimmed.starts_with('.')
+        || !matches!(
+            MetaCommand::new(trimmed.to_string()),
+            MetaCommand::Snapshot(_)
+        )
+    {
+        return session.call(move |db, token| execute(&command, &identity, token, db));
+    }
+    let path = match snapshot_path(trimmed) {
+        Ok(path) => path,
+        Err(err) => return Ok(Err(err)),
+    };
+    let bytes = session.call(move |db, _| db.committed(&identity))?;
+    Ok(bytes.and_then(|bytes| {
+        write_new_file(Path::new(&path), &bytes)?;
+        Ok(format!("Snapshot written to {}.", path))
+    }))
+}
+
+/// Runs a command sent by a client whose transaction, if it has one open, is the one of the
+/// session `token`, returning what the REPL would show: the message of a statement, or the
+/// rows of a query formatted as a table.
+fn execute(
+    command: &str,
+    identity: &Identity,
+    token: &mut Option<String>,
+    db: &mut SharedDatabase,
+) -> Result<String> {
+    let command = command.trim();
+    if command.starts_with('.') {
+        let db = db.session(token.as_deref())?;
+        return handle_remote_meta_command(MetaCommand::new(command.to_string()), db);
+    }
+    match db.execute_in_session(identity, token, command)? {
+        Outcome::Rows(result) => Ok(result.to_string()),
+        Outcome::Done(result) => Ok(result.to_string()),
+    }
+}
+
+/// What running a statement produced
+pub(crate) enum Outcome {
+    /// The rows of a query or of a PRAGMA
+    Rows(ResultSet),
+    /// What a statement returning no rows did
+    Done(QueryResult),
+}
+
+/// Runs a SQL statement, keeping the rows of queries for the protocol to send them.
+pub(crate) fn execute_statement(sql: &str, db: &mut Database) -> Result<Outcome> {
+    if let Some(pragma) = parse_pragma(sql)? {
+        return Ok(Outcome::Rows(execute_pragma(db, &pragma)?));
+    }
+    if returns_rows(sql)? {
+        Ok(Outcome::Rows(process_query(sql, db)?))
+    } else {
+        Ok(Outcome::Done(process_command(sql, db)?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::client::Client;
+    use super::session::DATABASE_LOCKED;
+    use super::*;
+
+    fn start_server(credentials: Option<Credentials>) -> String {
+        let server = Server::bind("127.0.0.1:0", credentials).unwrap();
+        let address = server.local_addr().unwrap().to_string();
+        thread::spawn(move || server.serve());
+        address
+    }
+
+    #[test]
+    fn shared_database_test() {
+        let address = start_server(None);
+        let mut first = Client::connect(&address).unwrap();
+        let mut second = Client::connect(&address).unwrap();
+
+        first
+            .execute("CREATE TABLE users (id INTEGER PRIMARY KEY, name TEXT);")
+            .unwrap();
+        first
+            .execute("INSERT INTO users (name) VALUES ('josh');")
+            .unwrap();
+
+        // Changes of one client are seen by the others
+        let output = second.execute("SELECT name FROM users;").unwrap();
+        assert!(output.contains("josh"));
+        assert_eq!(second.execute(".tables").unwrap(), "users");
+        assert!(matches!(
+            second.execute("SELECT * FROM missing;"),
+            Err(SQLRiteError::Server(_))
+        ));
+        assert!(second.execute(".open other.db").is_err());
+
+        // Snapshots are written by the server, to a file of its own
+        let path = std::env::temp_dir().join(format!("sqlrite-snapshot-{}.db", std::process::id()));
+        let command = format!(".snapshot {}", path.display());
+        let output = first.execute(&command).unwrap();
+        assert_eq!(output, format!("Snapshot written to {}.", path.display()));
+        assert!(first.execute(&command).is_err());
+        let snapshot = Database::read_file(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(snapshot.tables["users"].rowids(), vec![1]);
+    }
+
+    #[test]
+    fn transaction_test() {
+        let address = start_server(None);
+        let mut first = Client::connect(&address).unwrap();
+        let mut second = Client::connect(&address).unwrap();
+        first
+            .execute("CREATE TABLE users (id INTEGER PRIMARY KEY);")
+            .unwrap();
+
+        // The transaction of a client locks the others out until it ends
+        first.execute("BEGIN;").unwrap();
+        first.execute("INSERT INTO users (id) VALUES (1);").unwrap();
+        let locked =
+            |result: Result<String>| result.unwrap_err().to_string().contains(DATABASE_LOCKED);
+        assert!(locked(second.execute("INSERT INTO users (id) VALUES (2);")));
+        assert!(locked(second.execute("ROLLBACK;")));
+        assert!(locked(second.execute("BEGIN;")));
+        assert!(first.execute("BEGIN;").is_err());
+        first.execute("SAVEPOINT inserted;").unwrap();
+        first.execute("COMMIT;").unwrap();
+        assert!(second
+            .execute("INSERT INTO users (id) VALUES (1);")
+            .is_err());
+
+        // The transaction of a client that disconnects is rolled back
+        first.execute("BEGIN;").unwrap();
+        first.execute("INSERT INTO users (id) VALUES (2);").unwrap();
+        drop(first);
+        let mut third = Client::connect(&address).unwrap();
+        let mut attempts = 0;
+        while let Err(err) = third.execute("BEGIN;") {
+            assert!(err.to_string().contains(DATABASE_LOCKED));
+            attempts += 1;
+            assert!(attempts < 100, "the transaction was never rolled back");
+            thread::sleep(std::time::Duration::from_millis(10));
+        }
+        third.execute("INSERT INTO users (id) VALUES (2);").unwrap();
+        third.execute("COMMIT;").unwrap();
+        assert!(third.execute("COMMIT;").is_err());
+        second
+            .execute("INSERT INTO users (id) VALUES (3);")
+            .unwrap();
+    }
+
+    #[test]
+    fn audit_test() {
+        let address = start_server(Some(Credentials {
+            user: "admin".to_string(),
+            password: "secret".to_string(),
+        }));
+        let mut client = Client::connect(&address).unwrap();
+        client.authenticate("admin", "secret").unwrap();
+        client
+            .execute("CREATE TABLE users (id INTEGER PRIMARY KEY);")
+            .unwrap();
+        client.execute("PRAGMA audit = TABLE;").unwrap();
+        client
+            .execute("INSERT INTO users (id) VALUES (1);")
+            .unwrap();
+
+        // Changes are recorded as made by the user, from the address of its connection
+        let output = client.execute("SELECT user FROM sqlrite_audit;").unwrap();
+        assert!(output.contains("admin@127.0.0.1:"));
+    }
+
+    #[test]
+    fn privileges_test() {
+        let address = start_server(Some(Credentials {
+            user: "admin".to_string(),
+            password: "secret".to_string(),
+        }));
+        let mut admin = Client::connect(&address).unwrap();
+        admin.authenticate("admin", "secret").unwrap();
+        admin
+            .execute("CREATE TABLE users (id INTEGER PRIMARY KEY, name TEXT);")
+            .unwrap();
+        admin
+            .execute("CREATE USER reporting WITH PASSWORD 'report';")
+            .unwrap();
+        admin
+            .execute("GRANT SELECT ON DATABASE TO reporting;")
+            .unwrap();
+
+        // A reporting user is restricted to reading the tables
+        let mut reporting = Client::connect(&address).unwrap();
+        assert!(reporting.authenticate("reporting", "wrong").is_err());
+        let mut reporting = Client::connect(&address).unwrap();
+        reporting.authenticate("reporting", "report").unwrap();
+        reporting.execute("SELECT * FROM users;").unwrap();
+        let err = reporting
+            .execute("INSERT INTO users (name) VALUES ('josh');")
+            .unwrap_err();
+        assert!(err.to_string().contains("permission denied"));
+        assert!(reporting.execute("CREATE TABLE t (id INTEGER);").is_err());
+        assert!(reporting
+            .execute("GRANT INSERT ON users TO reporting;")
+            .is_err());
+
+        admin
+            .execute("REVOKE SELECT ON DATABASE FROM reporting;")
+            .unwrap();
+        assert!(reporting.execute("SELECT * FROM users;").is_err());
+    }
+
+    #[test]
+    fn unknown_user_test() {
+        let address = start_server(None);
+        let mut owner = Client::connect(&address).unwrap();
+        owner
+            .execute("CREATE TABLE users (id INTEGER PRIMARY KEY);")
+            .unwrap();
+        owner
+            .execute("CREATE USER reporting WITH PASSWORD 'report';")
+            .unwrap();
+        owner
+            .execute("GRANT INSERT ON users TO reporting;")
+            .unwrap();
+        owner
+            .execute("REVOKE INSERT ON users FROM reporting;")
+            .unwrap();
+
+        let mut reporting = Client::connect(&address).unwrap();
+        reporting.authenticate("reporting", "report").unwrap();
+        let err = reporting
+            .execute("INSERT INTO users (id) VALUES (1);")
+            .unwrap_err();
+        assert!(err.to_string().contains("permission denied"));
+
+        // Once there are users, the privileges revoked can't be had under another name
+        let mut other = Client::connect(&address).unwrap();
+        assert!(other.authenticate("admin", "anything").is_err());
+        let mut anonymous = Client::connect(&address).unwrap();
+        assert!(anonymous
+            .execute("INSERT INTO users (id) VALUES (1);")
+            .is_err());
+        owner.execute("INSERT INTO users (id) VALUES (1);").unwrap();
+    }
+
+    #[test]
+    fn authentication_test() {
+        let address = start_server(Some(Credentials {
+            user: "admin".to_string(),
+            password: "secret".to_string(),
+        }));
+
+        let mut client = Client::connect(&address).unwrap();
+        assert!(client.execute("SELECT 1;").is_err());
+
+        let mut client = Client::connect(&address).unwrap();
+        assert!(client.authenticate("admin", "wrong").is_err());
+        // The server closes the connection after a failed authentication
+        assert!(client.execute("SELECT 1;").is_err());
+
+        let mut client = Client::connect(&address).unwrap();
+        client.authenticate("admin", "secret").unwrap();
+        client
+            .execute("CREATE TABLE users (id INTEGER PRIMARY KEY);")
+            .unwrap();
+    }
+}
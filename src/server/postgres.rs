@@ -0,0 +1,568 @@
+//! Enough of the PostgreSQL frontend/backend protocol (version 3.0) for `psql` and the
+//! usual Postgres drivers to run queries against the database of a server: the startup
+//! handshake with cleartext password authentication, and the simple query protocol.
+//!
+//! Rows are sent in text format. The extended query protocol, used for prepared
+//! statements, isn't supported: its messages are answered with an error.
+use std::io::{BufReader, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::thread;
+
+use crate::error::{Result, SQLRiteError};
+use crate::repl::split_statements;
+use crate::server::protocol::protocol_error;
+use crate::server::session::{ClientSession, SharedDatabase, DATABASE_LOCKED};
+use crate::server::{Credentials, DatabaseHandle, Identity, Outcome, Server};
+use crate::sql::db::privilege::is_permission_denied;
+use crate::sql::db::table::DataType;
+use crate::sql::executor::ResultSet;
+use crate::sql::value::Value;
+
+/// Port PostgreSQL servers listen on
+pub const DEFAULT_PORT: u16 = 5432;
+
+const PROTOCOL_VERSION: i32 = 196608;
+const SSL_REQUEST: i32 = 80877103;
+const GSSENC_REQUEST: i32 = 80877104;
+const CANCEL_REQUEST: i32 = 80877102;
+
+/// Versions reported to clients, some of them check `server_version`
+const SERVER_VERSION: &str = "14.0 (SQLRite)";
+
+/// Object ids of the PostgreSQL types values are sent as
+const INT8_OID: i32 = 20;
+const FLOAT8_OID: i32 = 701;
+const TEXT_OID: i32 = 25;
+const BOOL_OID: i32 = 16;
+const BYTEA_OID: i32 = 17;
+
+impl Server {
+    /// Also accepts PostgreSQL clients on `address`, on a thread of their own, sharing the
+    /// database with the other clients. Returns the address listened on.
+    pub fn listen_postgres(&self, address: &str) -> Result<SocketAddr> {
+        let listener = TcpListener::bind(address).map_err(protocol_error)?;
+        let local_addr = listener.local_addr().map_err(protocol_error)?;
+        let db = self.db.clone();
+        let credentials = self.credentials.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let stream = match stream {
+                    Ok(stream) => stream,
+                    Err(err) => {
+                        log::warn!("Stopped accepting PostgreSQL clients: {}", err);
+                        return;
+                    }
+                };
+                let db = db.clone();
+                let credentials = credentials.clone();
+                thread::spawn(move || {
+                    if let Err(err) = handle_client(stream, &db, credentials.as_ref()) {
+                        log::warn!("Closing PostgreSQL connection: {}", err);
+                    }
+                });
+            }
+        });
+        Ok(local_addr)
+    }
+}
+
+/// A message sent by the server, built as its type and payload
+struct BackendMessage {
+    tag: u8,
+    payload: Vec<u8>,
+}
+
+impl BackendMessage {
+    fn new(tag: u8) -> Self {
+        BackendMessage {
+            tag,
+            payload: vec![],
+        }
+    }
+
+    fn i16(mut self, value: i16) -> Self {
+        self.payload.extend_from_slice(&value.to_be_bytes());
+        self
+    }
+
+    fn i32(mut self, value: i32) -> Self {
+        self.payload.extend_from_slice(&value.to_be_bytes());
+        self
+    }
+
+    fn bytes(mut self, value: &[u8]) -> Self {
+        self.payload.extend_from_slice(value);
+        self
+    }
+
+    /// Appends a null-terminated string
+    fn string(mut self, value: &str) -> Self {
+        self.payload.extend_from_slice(value.as_bytes());
+        self.payload.push(0);
+        self
+    }
+
+    fn write_to(&self, writer: &mut dyn Write) -> Result<()> {
+        let mut message = vec![self.tag];
+        message.extend_from_slice(&(self.payload.len() as i32 + 4).to_be_bytes());
+        message.extend_from_slice(&self.payload);
+        writer.write_all(&message).map_err(protocol_error)
+    }
+}
+
+/// Reads a big-endian Int32.
+fn read_i32(reader: &mut dyn Read) -> Result<i32> {
+    let mut buf = [0; 4];
+    reader.read_exact(&mut buf).map_err(protocol_error)?;
+    Ok(i32::from_be_bytes(buf))
+}
+
+/// Reads the `length` bytes of a message body, the length including its own 4 bytes.
+fn read_body(reader: &mut dyn Read, length: i32) -> Result<Vec<u8>> {
+    if !(4..=1 << 30).contains(&length) {
+        return Err(protocol_error(format!("invalid message length {}", length)));
+    }
+    let mut body = vec![0; length as usize - 4];
+    reader.read_exact(&mut body).map_err(protocol_error)?;
+    Ok(body)
+}
+
+/// Reads the next message of the client, `None` once it closed the connection.
+fn read_message(reader: &mut dyn Read) -> Result<Option<(u8, Vec<u8>)>> {
+    let mut tag = [0; 1];
+    if reader.read(&mut tag).map_err(protocol_error)? == 0 {
+        return Ok(None);
+    }
+    let length = read_i32(reader)?;
+    Ok(Some((tag[0], read_body(reader, length)?)))
+}
+
+/// Reads the null-terminated string at the start of `bytes`.
+fn read_string(bytes: &[u8]) -> String {
+    let end = bytes.iter().position(|b| *b == 0).unwrap_or(bytes.len());
+    String::from_utf8_lossy(&bytes[..end]).to_string()
+}
+
+/// Runs the session of a client: the startup handshake, then its queries until it
+/// terminates the session.
+fn handle_client(
+    stream: TcpStream,
+    db: &DatabaseHandle,
+    credentials: Option<&Credentials>,
+) -> Result<()> {
+    let peer = stream.peer_addr().ok();
+    let mut reader = BufReader::new(stream.try_clone().map_err(protocol_error)?);
+    let mut writer = stream;
+
+    let user = match startup(&mut reader, &mut writer)? {
+        Some(user) => user,
+        None => return Ok(()),
+    };
+    let _connection = db.metrics.connection("postgres");
+    let owner = credentials.cloned();
+    let identity = if db.call(move |db| db.needs_password(owner.as_ref()))? {
+        BackendMessage::new(b'R').i32(3).write_to(&mut writer)?;
+        writer.flush().map_err(protocol_error)?;
+        let password = match read_message(&mut reader)? {
+            Some((b'p', body)) => read_string(&body),
+            _ => return Ok(()),
+        };
+        match db.login(credentials, Some((&user, &password)), peer)? {
+            Some(identity) => identity,
+            None => {
+                error_response(
+                    "28P01",
+                    &format!("password authentication failed for user \"{}\"", user),
+                )
+                .write_to(&mut writer)?;
+                return writer.flush().map_err(protocol_error);
+            }
+        }
+    } else {
+        Identity::new(Some(&user), false, peer)
+    };
+    BackendMessage::new(b'R').i32(0).write_to(&mut writer)?;
+    for (name, value) in [
+        ("server_version", SERVER_VERSION),
+        ("server_encoding", "UTF8"),
+        ("client_encoding", "UTF8"),
+        ("DateStyle", "ISO, MDY"),
+        ("integer_datetimes", "on"),
+        ("standard_conforming_strings", "on"),
+    ] {
+        BackendMessage::new(b'S')
+            .string(name)
+            .string(value)
+            .write_to(&mut writer)?;
+    }
+    // Cancelling queries isn't supported, the key is never checked
+    BackendMessage::new(b'K')
+        .i32(std::process::id() as i32)
+        .i32(0)
+        .write_to(&mut writer)?;
+    let mut session = ClientSession::new(db);
+    ready_for_query(&mut writer, &session)?;
+
+    // After an error in the extended protocol, messages are ignored until the next Sync
+    let mut skipping = false;
+    while let Some((tag, body)) = read_message(&mut reader)? {
+        match tag {
+            b'Q' => {
+                let sql = read_string(&body);
+                let identity = identity.clone();
+                let messages =
+                    session.call(move |db, token| simple_query(&sql, &identity, token, db))?;
+                for message in messages {
+                    message.write_to(&mut writer)?;
+                }
+                ready_for_query(&mut writer, &session)?;
+            }
+            b'X' => break,
+            b'S' => {
+                skipping = false;
+                ready_for_query(&mut writer, &session)?;
+            }
+            // Flush
+            b'H' => writer.flush().map_err(protocol_error)?,
+            _ if skipping => {}
+            _ => {
+                skipping = true;
+                error_response("0A000", "the extended query protocol isn't supported")
+                    .write_to(&mut writer)?;
+                writer.flush().map_err(protocol_error)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Reads the startup message, declining encryption requests, and returns the user the
+/// client connects as. `None` if the client gave up, or only wanted to cancel a query.
+fn startup(reader: &mut dyn Read, writer: &mut dyn Write) -> Result<Option<String>> {
+    loop {
+        let length = read_i32(reader)?;
+        let body = read_body(reader, length)?;
+        if body.len() < 4 {
+            return Err(protocol_error("invalid startup message"));
+        }
+        let code = i32::from_be_bytes([body[0], body[1], body[2], body[3]]);
+        match code {
+            SSL_REQUEST | GSSENC_REQUEST => {
+                writer.write_all(b"N").map_err(protocol_error)?;
+                writer.flush().map_err(protocol_error)?;
+            }
+            CANCEL_REQUEST => return Ok(None),
+            PROTOCOL_VERSION => {
+                // The parameters are pairs of null-terminated names and values
+                let params = body[4..]
+                    .split(|b| *b == 0)
+                    .map(|s| String::from_utf8_lossy(s).to_string())
+                    .collect::<Vec<String>>();
+                let user = params
+                    .chunks(2)
+                    .find(|pair| pair[0] == "user")
+                    .and_then(|pair| pair.get(1).cloned())
+                    .unwrap_or_default();
+                return Ok(Some(user));
+            }
+            code => {
+                error_response(
+                    "08P01",
+                    &format!(
+                        "unsupported frontend protocol {}.{}",
+                        code >> 16,
+                        code & 0xffff
+                    ),
+                )
+                .write_to(writer)?;
+                writer.flush().map_err(protocol_error)?;
+                return Ok(None);
+            }
+        }
+    }
+}
+
+fn ready_for_query(writer: &mut dyn Write, session: &ClientSession) -> Result<()> {
+    // Idle, or in a transaction block once the client began one
+    let status = if session.in_transaction() { b"T" } else { b"I" };
+    BackendMessage::new(b'Z').bytes(status).write_to(writer)?;
+    writer.flush().map_err(protocol_error)
+}
+
+/// An ErrorResponse with the SQLSTATE `code`.
+fn error_response(code: &str, message: &str) -> BackendMessage {
+    BackendMessage::new(b'E')
+        .bytes(b"S")
+        .string("ERROR")
+        .bytes(b"V")
+        .string("ERROR")
+        .bytes(b"C")
+        .string(code)
+        .bytes(b"M")
+        .string(message)
+        .bytes(&[0])
+}
+
+/// Runs the statements of a simple Query message, in the transaction of the session `token`
+/// if the client has one open, returning the messages answering it. Like PostgreSQL, the
+/// statements after one that fails aren't run.
+fn simple_query(
+    sql: &str,
+    identity: &Identity,
+    token: &mut Option<String>,
+    db: &mut SharedDatabase,
+) -> Vec<BackendMessage> {
+    let statements = split_statements(sql);
+    if statements.is_empty() {
+        return vec![BackendMessage::new(b'I')];
+    }
+    let mut messages = vec![];
+    for statement in statements {
+        match db.execute_in_session(identity, token, &statement) {
+            Ok(Outcome::Rows(result)) if !result.columns.is_empty() => {
+                messages.push(row_description(&result));
+                for row in &result.rows {
+                    messages.push(data_row(row));
+                }
+                messages.push(
+                    BackendMessage::new(b'C').string(&format!("SELECT {}", result.rows.len())),
+                );
+            }
+            Ok(outcome) => {
+                let rows_affected = match outcome {
+                    Outcome::Done(result) => result.rows_affected.unwrap_or(0),
+                    Outcome::Rows(_) => 0,
+                };
+                messages
+                    .push(BackendMessage::new(b'C').string(&command_tag(&statement, rows_affected)))
+            }
+            Err(err) => {
+                let code = match &err {
+                    SQLRiteError::General(message) if message == DATABASE_LOCKED => "55P03",
+                    err if is_permission_denied(err) => "42501",
+                    SQLRiteError::SqlError(_) => "42601",
+                    SQLRiteError::NotImplemented(_) => "0A000",
+                    SQLRiteError::OutOfMemory(_) => "53200",
+                    SQLRiteError::Interrupted => "57014",
+                    _ => "XX000",
+                };
+                messages.push(error_response(code, &err.to_string()));
+                break;
+            }
+        }
+    }
+    messages
+}
+
+/// Tag of the CommandComplete message of a statement returning no rows, like `INSERT 0 1`.
+fn command_tag(statement: &str, rows_affected: i64) -> String {
+    let mut words = statement
+        .split(|c: char| !c.is_alphabetic())
+        .filter(|word| !word.is_empty())
+        .map(|word| word.to_uppercase());
+    let command = words.next().unwrap_or_default();
+    match command.as_str() {
+        "INSERT" => format!("INSERT 0 {}", rows_affected),
+        "UPDATE" | "DELETE" => format!("{} {}", command, rows_affected),
+        "CREATE" | "DROP" => {
+            let object = words
+                .find(|word| !matches!(word.as_str(), "TEMP" | "TEMPORARY" | "VIRTUAL" | "UNIQUE"))
+                .unwrap_or_default();
+            format!("{} {}", command, object)
+        }
+        _ => command,
+    }
+}
+
+/// The RowDescription of a result set. Columns computed by expressions get the type of
+/// their first value that isn't NULL.
+fn row_description(result: &ResultSet) -> BackendMessage {
+    let mut message = BackendMessage::new(b'T').i16(result.columns.len() as i16);
+    for (i, column) in result.columns.iter().enumerate() {
+        let oid = match &column.datatype {
+            Some(DataType::Integer) => INT8_OID,
+            Some(DataType::Real) => FLOAT8_OID,
+            Some(DataType::Bool) => BOOL_OID,
+            Some(DataType::Text) => TEXT_OID,
+            _ => result
+                .rows
+                .iter()
+                .map(|row| &row[i])
+                .find(|value| !value.is_null())
+                .map_or(TEXT_OID, value_oid),
+        };
+        let size = match oid {
+            INT8_OID | FLOAT8_OID => 8,
+            BOOL_OID => 1,
+            _ => -1,
+        };
+        message = message
+            .string(&column.name)
+            // Not a column of a PostgreSQL table
+            .i32(0)
+            .i16(0)
+            .i32(oid)
+            .i16(size)
+            .i32(-1)
+            // Text format
+            .i16(0);
+    }
+    message
+}
+
+fn value_oid(value: &Value) -> i32 {
+    match value {
+        Value::Integer(_) => INT8_OID,
+        Value::Real(_) => FLOAT8_OID,
+        Value::Bool(_) => BOOL_OID,
+        Value::Blob(_) => BYTEA_OID,
+        Value::Null | Value::Text(_) => TEXT_OID,
+    }
+}
+
+/// A DataRow with the values in text format, NULL being sent as length -1.
+fn data_row(row: &[Value]) -> BackendMessage {
+    let mut message = BackendMessage::new(b'D').i16(row.len() as i16);
+    for value in row {
+        let text = match value {
+            Value::Null => {
+                message = message.i32(-1);
+                continue;
+            }
+            Value::Bool(v) => if *v { "t" } else { "f" }.to_string(),
+            Value::Blob(v) => format!(
+                "\\x{}",
+                v.iter().map(|b| format!("{:02x}", b)).collect::<String>()
+            ),
+            value => value.to_string(),
+        };
+        message = message.i32(text.len() as i32).bytes(text.as_bytes());
+    }
+    message
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A minimal PostgreSQL client, reading the messages answering what it sent
+    struct TestClient {
+        stream: TcpStream,
+    }
+
+    impl TestClient {
+        fn connect(address: SocketAddr, user: &str) -> Self {
+            let mut stream = TcpStream::connect(address).unwrap();
+            let mut body = PROTOCOL_VERSION.to_be_bytes().to_vec();
+            for s in ["user", user, "database", "main", ""] {
+                body.extend_from_slice(s.as_bytes());
+                body.push(0);
+            }
+            stream
+                .write_all(&(body.len() as i32 + 4).to_be_bytes())
+                .unwrap();
+            stream.write_all(&body).unwrap();
+            TestClient { stream }
+        }
+
+        fn send(&mut self, tag: u8, body: &str) {
+            BackendMessage::new(tag)
+                .string(body)
+                .write_to(&mut self.stream)
+                .unwrap();
+        }
+
+        fn read_one(&mut self) -> (u8, Vec<u8>) {
+            read_message(&mut self.stream).unwrap().unwrap()
+        }
+
+        /// Reads messages until ReadyForQuery or an error closing the connection
+        fn read_until_ready(&mut self) -> Vec<(u8, Vec<u8>)> {
+            let mut messages = vec![];
+            while let Some((tag, body)) = read_message(&mut self.stream).unwrap() {
+                messages.push((tag, body));
+                if tag == b'Z' {
+                    break;
+                }
+            }
+            messages
+        }
+    }
+
+    fn start_server(credentials: Option<Credentials>) -> SocketAddr {
+        let server = Server::bind("127.0.0.1:0", credentials).unwrap();
+        let address = server.listen_postgres("127.0.0.1:0").unwrap();
+        thread::spawn(move || server.serve());
+        address
+    }
+
+    #[test]
+    fn simple_query_test() {
+        let mut client = TestClient::connect(start_server(None), "josh");
+        let tags = |messages: &[(u8, Vec<u8>)]| messages.iter().map(|m| m.0).collect::<Vec<u8>>();
+        let startup = client.read_until_ready();
+        assert_eq!(tags(&startup)[0], b'R');
+        assert_eq!(*tags(&startup).last().unwrap(), b'Z');
+
+        client.send(
+            b'Q',
+            "CREATE TABLE users (id INTEGER PRIMARY KEY, name TEXT); \
+             INSERT INTO users (name) VALUES ('josh');",
+        );
+        let messages = client.read_until_ready();
+        assert_eq!(tags(&messages), b"CCZ");
+        assert_eq!(read_string(&messages[0].1), "CREATE TABLE");
+        assert_eq!(read_string(&messages[1].1), "INSERT 0 1");
+
+        client.send(b'Q', "SELECT id, name FROM users;");
+        let messages = client.read_until_ready();
+        assert_eq!(tags(&messages), b"TDCZ");
+        // Two columns, the first one named id and typed int8
+        assert_eq!(&messages[0].1[..2], &[0, 2]);
+        assert_eq!(read_string(&messages[0].1[2..]), "id");
+        assert_eq!(&messages[0].1[11..15], &INT8_OID.to_be_bytes());
+        assert_eq!(
+            messages[1].1,
+            [&[0, 2, 0, 0, 0, 1][..], b"1", &[0, 0, 0, 4], b"josh"].concat()
+        );
+        assert_eq!(read_string(&messages[2].1), "SELECT 1");
+
+        client.send(b'Q', "SELECT * FROM missing;");
+        assert_eq!(tags(&client.read_until_ready()), b"EZ");
+        client.send(b'Q', " ; ");
+        assert_eq!(tags(&client.read_until_ready()), b"IZ");
+        client.send(b'X', "");
+    }
+
+    #[test]
+    fn password_authentication_test() {
+        let address = start_server(Some(Credentials {
+            user: "admin".to_string(),
+            password: "secret".to_string(),
+        }));
+
+        // Asked for a cleartext password
+        let mut client = TestClient::connect(address, "admin");
+        assert_eq!(client.read_one(), (b'R', 3i32.to_be_bytes().to_vec()));
+        client.send(b'p', "wrong");
+        assert_eq!(client.read_until_ready()[0].0, b'E');
+
+        let mut client = TestClient::connect(address, "admin");
+        client.read_one();
+        client.send(b'p', "secret");
+        let messages = client.read_until_ready();
+        assert_eq!(messages[0], (b'R', 0i32.to_be_bytes().to_vec()));
+    }
+
+    #[test]
+    fn split_statements_test() {
+        assert_eq!(
+            split_statements("SELECT ';' FROM t; CREATE TRIGGER t AFTER INSERT ON u BEGIN DELETE FROM u; END; SELECT 1"),
+            vec![
+                "SELECT ';' FROM t;",
+                "CREATE TRIGGER t AFTER INSERT ON u BEGIN DELETE FROM u; END;",
+                "SELECT 1",
+            ]
+        );
+    }
+}
@@ -0,0 +1,48 @@
+//! The wire protocol between the server and its clients: every message is a JSON document
+//! on its own line. A client sends requests and reads one response to each of them.
+use std::io::{BufRead, Write};
+
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+use crate::error::{Result, SQLRiteError};
+
+/// A message sent by a client
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub enum Request {
+    /// Authenticates the session, required first when the server has credentials
+    Auth { user: String, password: String },
+    /// Runs a SQL statement or a meta command, as typed in the REPL
+    Execute { command: String },
+}
+
+/// The answer of the server to a request
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub enum Response {
+    /// The request succeeded, with the output the REPL shows
+    Ok { output: String },
+    /// The request failed, with the error message
+    Error { message: String },
+}
+
+/// Writes a message as a line of JSON, flushing it so it is sent right away.
+pub fn write_message<T: Serialize>(writer: &mut dyn Write, message: &T) -> Result<()> {
+    let line = serde_json::to_string(message).map_err(protocol_error)?;
+    writeln!(writer, "{}", line).map_err(protocol_error)?;
+    writer.flush().map_err(protocol_error)
+}
+
+/// Reads the next message, `None` once the other side closed the connection.
+pub fn read_message<T: DeserializeOwned>(reader: &mut dyn BufRead) -> Result<Option<T>> {
+    let mut line = String::new();
+    if reader.read_line(&mut line).map_err(protocol_error)? == 0 {
+        return Ok(None);
+    }
+    serde_json::from_str(&line)
+        .map(Some)
+        .map_err(protocol_error)
+}
+
+pub(crate) fn protocol_error(err: impl std::fmt::Display) -> SQLRiteError {
+    SQLRiteError::General(format!("Connection error: {}", err))
+}
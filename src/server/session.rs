@@ -0,0 +1,413 @@
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::error::{Result, SQLRiteError};
+use crate::server::metrics::Metrics;
+use crate::server::{execute_statement, Credentials, DatabaseHandle, Identity, Outcome};
+use crate::sql::db::database::Database;
+use crate::sql::db::privilege::{User, PERMISSION_DENIED};
+use crate::sql::parser::transaction::{parse_transaction_statement, TransactionStatement};
+
+/// Time after which a transaction nobody used is rolled back, if another session needs
+/// the database
+pub const TRANSACTION_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Error message of the statements run while another session has a transaction open
+pub const DATABASE_LOCKED: &str = "database is locked";
+
+/// The database of a server, with the transaction a session may have open on it
+pub struct SharedDatabase {
+    db: Database,
+    transaction: Option<Transaction>,
+    metrics: Arc<Metrics>,
+}
+
+/// The session whose transaction is open on the database, identified by its token. The
+/// savepoints of the transaction are those of the database, undone by its `ROLLBACK`.
+struct Transaction {
+    token: String,
+    last_used: Instant,
+}
+
+impl SharedDatabase {
+    pub fn new(db: Database, metrics: Arc<Metrics>) -> Self {
+        SharedDatabase {
+            db,
+            transaction: None,
+            metrics,
+        }
+    }
+
+    /// The database, to read what the last statement did
+    pub fn database(&self) -> &Database {
+        &self.db
+    }
+
+    /// Returns the database for a statement of the session `token`, `None` for the
+    /// sessions without a transaction. While a transaction is open, only its session can
+    /// use the database, like SQLite the others get a "database is locked" error.
+    pub fn session(&mut self, token: Option<&str>) -> Result<&mut Database> {
+        self.expire_transaction();
+        match (&mut self.transaction, token) {
+            (Some(transaction), Some(token)) if transaction.token == token => {
+                transaction.last_used = Instant::now();
+            }
+            (Some(_), _) => return Err(SQLRiteError::General(DATABASE_LOCKED.to_string())),
+            (None, Some(_)) => return Err(no_transaction()),
+            (None, None) => {}
+        }
+        Ok(&mut self.db)
+    }
+
+    /// The user `name` created with `CREATE USER`, for a login to check its password
+    /// against off the database thread.
+    pub(crate) fn user(&self, name: &str) -> Option<User> {
+        self.db.access.user(name).cloned()
+    }
+
+    /// Returns true if a client has to give a password: when the server has `credentials`,
+    /// or once users were created with `CREATE USER`.
+    pub(crate) fn needs_password(&self, credentials: Option<&Credentials>) -> bool {
+        credentials.is_some() || self.has_users()
+    }
+
+    /// Returns true if users were created with `CREATE USER`.
+    pub(crate) fn has_users(&self) -> bool {
+        !self.db.access.users.is_empty()
+    }
+
+    /// Runs a SQL statement for the session `token` of a client, recording it in the
+    /// metrics, and the changes it makes in the audit log as made by the client. The
+    /// statement needs the privileges of the user the client authenticated as. Without a
+    /// session, the statement can't begin a transaction: the clients of the API begin one
+    /// with a request of their own.
+    pub(crate) fn execute(
+        &mut self,
+        identity: &Identity,
+        token: Option<&str>,
+        sql: &str,
+    ) -> Result<Outcome> {
+        let mut session = token.map(str::to_string);
+        let result = self.execute_in_session(identity, &mut session, sql);
+        if token.is_none() {
+            if let Some(token) = session {
+                self.rollback(&token)?;
+                return Err(SQLRiteError::General(
+                    "cannot start a transaction without a session: begin one with the API"
+                        .to_string(),
+                ));
+            }
+        }
+        result
+    }
+
+    /// Runs a SQL statement for a client connected to the server, whose `session` holds the
+    /// token of the transaction it has open. A statement beginning a transaction, like
+    /// `BEGIN` or `SAVEPOINT`, opens it for the session, and the statement ending it, like
+    /// `COMMIT` or `ROLLBACK`, closes it, the other sessions getting "database is locked"
+    /// errors in between.
+    pub(crate) fn execute_in_session(
+        &mut self,
+        identity: &Identity,
+        session: &mut Option<String>,
+        sql: &str,
+    ) -> Result<Outcome> {
+        self.expire_transaction();
+        if session.as_deref().is_some_and(|token| !self.holds(token)) {
+            *session = None;
+            return Err(SQLRiteError::General(format!(
+                "the transaction of this session was rolled back after {} seconds unused",
+                TRANSACTION_TIMEOUT.as_secs()
+            )));
+        }
+        let db = self.session(session.as_deref())?;
+        db.audit.user = Some(identity.name.to_string());
+        db.current_user = identity.user.clone();
+        let started = Instant::now();
+        let result = execute_statement(sql, db);
+        self.metrics
+            .record_statement(sql, started.elapsed(), result.is_err());
+
+        match (session.as_ref(), self.db.transaction.is_open()) {
+            (None, true) => match new_token() {
+                Ok(token) => {
+                    self.transaction = Some(Transaction {
+                        token: token.clone(),
+                        last_used: Instant::now(),
+                    });
+                    *session = Some(token);
+                }
+                Err(err) => {
+                    self.db.rollback()?;
+                    return Err(err);
+                }
+            },
+            (Some(_), false) => {
+                self.transaction = None;
+                *session = None;
+                let outcome = match parse_transaction_statement(sql) {
+                    Ok(Some(TransactionStatement::Rollback)) => "rollback",
+                    _ => "commit",
+                };
+                self.metrics.record_transaction(outcome);
+            }
+            _ => {}
+        }
+        result
+    }
+
+    /// Serializes the database as committed, like `Database::serialize_compact`, for a
+    /// snapshot written while the other sessions keep writing. While a session has a
+    /// transaction open, that is the database as it was when the transaction began: the
+    /// snapshot neither waits for the transaction nor sees its changes. Only the owner of the
+    /// database can take a snapshot.
+    pub(crate) fn committed(&mut self, identity: &Identity) -> Result<Vec<u8>> {
+        if identity.user.is_some() {
+            return Err(SQLRiteError::General(format!(
+                "{}: only the owner of the database can take a snapshot",
+                PERMISSION_DENIED
+            )));
+        }
+        self.expire_transaction();
+        match self.db.transaction.committed() {
+            Some(committed) => committed?.serialize_compact(),
+            None => {
+                self.db.current_user = None;
+                self.db.serialize_compact()
+            }
+        }
+    }
+
+    /// Renders the metrics of the server, with the gauges read from the database.
+    pub fn render_metrics(&self) -> String {
+        self.metrics.render(&self.db, self.transaction.is_some())
+    }
+
+    /// Begins a transaction, returning the token of the session it belongs to.
+    pub fn begin(&mut self) -> Result<String> {
+        self.expire_transaction();
+        if self.transaction.is_some() {
+            return Err(SQLRiteError::General(DATABASE_LOCKED.to_string()));
+        }
+        let token = new_token()?;
+        self.db.begin()?;
+        self.transaction = Some(Transaction {
+            token: token.clone(),
+            last_used: Instant::now(),
+        });
+        Ok(token)
+    }
+
+    /// Ends the transaction of the session `token`, keeping its changes.
+    pub fn commit(&mut self, token: &str) -> Result<()> {
+        self.session(Some(token))?;
+        self.transaction = None;
+        self.metrics.record_transaction("commit");
+        self.db.commit()
+    }
+
+    /// Ends the transaction of the session `token`, undoing its changes.
+    pub fn rollback(&mut self, token: &str) -> Result<()> {
+        self.session(Some(token))?;
+        if self.transaction.take().is_some() {
+            self.db.rollback()?;
+            self.metrics.record_transaction("rollback");
+        }
+        Ok(())
+    }
+
+    /// Rolls back the open transaction whatever session it belongs to, for the server to
+    /// exit without leaving it half applied. Returns true if a transaction was open.
+    pub fn shutdown(&mut self) -> Result<bool> {
+        match self.transaction.take() {
+            Some(_) => {
+                self.db.rollback()?;
+                self.metrics.record_transaction("rollback");
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    /// Whether the transaction open is the one of the session `token`.
+    fn holds(&self, token: &str) -> bool {
+        self.transaction
+            .as_ref()
+            .is_some_and(|transaction| transaction.token == token)
+    }
+
+    /// Rolls back the open transaction if its session abandoned it.
+    fn expire_transaction(&mut self) {
+        if let Some(transaction) = &self.transaction {
+            if transaction.last_used.elapsed() > TRANSACTION_TIMEOUT {
+                log::warn!("Rolling back an abandoned transaction");
+                self.transaction = None;
+                let _ = self.db.rollback();
+                self.metrics.record_transaction("expired");
+            }
+        }
+    }
+}
+
+/// The session of a client connected to the server, holding the token of the transaction
+/// the client has open. The transaction is rolled back once the session is dropped, when
+/// the client disconnects, for the other clients not to wait for it until it expires.
+pub(crate) struct ClientSession {
+    db: DatabaseHandle,
+    token: Option<String>,
+}
+
+impl ClientSession {
+    pub(crate) fn new(db: &DatabaseHandle) -> Self {
+        ClientSession {
+            db: db.clone(),
+            token: None,
+        }
+    }
+
+    /// Whether the client has a transaction open.
+    pub(crate) fn in_transaction(&self) -> bool {
+        self.token.is_some()
+    }
+
+    /// Runs `f` on the database thread with the token of the transaction of the session,
+    /// which `f` may begin or end, and returns its result once it is done.
+    pub(crate) fn call<F, R>(&mut self, f: F) -> Result<R>
+    where
+        F: FnOnce(&mut SharedDatabase, &mut Option<String>) -> R + Send + 'static,
+        R: Send + 'static,
+    {
+        let mut token = self.token.take();
+        let (result, token) = self.db.call(move |db| {
+            let result = f(db, &mut token);
+            (result, token)
+        })?;
+        self.token = token;
+        Ok(result)
+    }
+}
+
+impl Drop for ClientSession {
+    fn drop(&mut self) {
+        if let Some(token) = self.token.take() {
+            log::warn!("Rolling back the transaction of a client that disconnected");
+            // The transaction may have expired already, or the database thread stopped
+            let _ = self.db.call(move |db| db.rollback(&token));
+        }
+    }
+}
+
+fn no_transaction() -> SQLRiteError {
+    SQLRiteError::General("no transaction is open for this session".to_string())
+}
+
+/// A token that can't be guessed from the previous ones: 128 bits from the random number
+/// generator of the operating system.
+fn new_token() -> Result<String> {
+    let mut bytes = [0u8; 16];
+    getrandom::getrandom(&mut bytes).map_err(|err| {
+        SQLRiteError::General(format!("cannot generate a session token: {}", err))
+    })?;
+    Ok(bytes.iter().map(|b| format!("{:02x}", b)).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sql::db::file::read_image;
+    use crate::sql::{process_command, process_query};
+
+    #[test]
+    fn transaction_test() {
+        let mut shared = SharedDatabase::new(
+            Database::new("tempdb".to_string()),
+            Arc::new(Metrics::default()),
+        );
+        let db = shared.session(None).unwrap();
+        process_command("CREATE TABLE users (id INTEGER PRIMARY KEY);", db).unwrap();
+        // Statements run without a session can't leave a transaction open
+        let owner = Identity::new(None, false, None);
+        assert!(shared.execute(&owner, None, "BEGIN;").is_err());
+        assert!(shared.session(None).is_ok());
+
+        let token = shared.begin().unwrap();
+        process_command(
+            "INSERT INTO users (id) VALUES (1);",
+            shared.session(Some(&token)).unwrap(),
+        )
+        .unwrap();
+        // Other sessions wait for the transaction to end
+        assert!(shared.session(None).is_err());
+        assert!(shared.begin().is_err());
+        shared.rollback(&token).unwrap();
+
+        let db = shared.session(None).unwrap();
+        let result = process_query("SELECT * FROM users;", db).unwrap();
+        assert!(result.rows.is_empty());
+        assert!(shared.commit(&token).is_err());
+
+        let token = shared.begin().unwrap();
+        process_command(
+            "INSERT INTO users (id) VALUES (1);",
+            shared.session(Some(&token)).unwrap(),
+        )
+        .unwrap();
+        shared.commit(&token).unwrap();
+        let db = shared.session(None).unwrap();
+        let result = process_query("SELECT * FROM users;", db).unwrap();
+        assert_eq!(result.rows.len(), 1);
+    }
+
+    #[test]
+    fn committed_test() {
+        let mut shared = SharedDatabase::new(
+            Database::new("tempdb".to_string()),
+            Arc::new(Metrics::default()),
+        );
+        let owner = Identity::new(None, false, None);
+        let db = shared.session(None).unwrap();
+        process_command("CREATE TABLE users (id INTEGER PRIMARY KEY);", db).unwrap();
+        process_command("INSERT INTO users (id) VALUES (1);", db).unwrap();
+
+        // The changes of an open transaction aren't part of the snapshot
+        let token = shared.begin().unwrap();
+        process_command(
+            "INSERT INTO users (id) VALUES (2);",
+            shared.session(Some(&token)).unwrap(),
+        )
+        .unwrap();
+        let bytes = shared.committed(&owner).unwrap();
+        let snapshot = read_image(&bytes).unwrap();
+        assert_eq!(snapshot.tables["users"].rowids(), vec![1]);
+        shared.commit(&token).unwrap();
+        let bytes = shared.committed(&owner).unwrap();
+        let snapshot = read_image(&bytes).unwrap();
+        assert_eq!(snapshot.tables["users"].rowids(), vec![1, 2]);
+
+        let user = Identity::new(Some("ana"), true, None);
+        assert!(shared.committed(&user).is_err());
+    }
+
+    #[test]
+    fn shutdown_test() {
+        let mut shared = SharedDatabase::new(
+            Database::new("tempdb".to_string()),
+            Arc::new(Metrics::default()),
+        );
+        let db = shared.session(None).unwrap();
+        process_command("CREATE TABLE users (id INTEGER PRIMARY KEY);", db).unwrap();
+        assert!(!shared.shutdown().unwrap());
+
+        let token = shared.begin().unwrap();
+        process_command(
+            "INSERT INTO users (id) VALUES (1);",
+            shared.session(Some(&token)).unwrap(),
+        )
+        .unwrap();
+        assert!(shared.shutdown().unwrap());
+        let db = shared.session(None).unwrap();
+        let result = process_query("SELECT * FROM users;", db).unwrap();
+        assert!(result.rows.is_empty());
+        assert!(shared.rollback(&token).is_err());
+    }
+}
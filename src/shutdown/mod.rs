@@ -0,0 +1,194 @@
+//! Clean shutdown of the process when it receives SIGTERM, or SIGHUP when its terminal
+//! hangs up, instead of dying wherever it stands. The signal handler only writes the number
+//! of the signal to a pipe, about the only thing a handler can safely do. A thread waiting
+//! on the pipe then runs the shutdown of the server, like rolling back the transaction a
+//! client left open, or has the REPL stop reading commands and close its database on its
+//! own thread, and exits the process.
+use crate::error::Result;
+
+/// Calls `shutdown` with the number of the signal on a thread of its own when the process
+/// receives SIGTERM or SIGHUP, and exits the process once it returns. Only the first
+/// signal is handled, the process is on its way out. Does nothing on platforms without
+/// these signals.
+#[cfg(unix)]
+pub fn on_termination<F>(shutdown: F) -> Result<()>
+where
+    F: FnOnce(i32) + Send + 'static,
+{
+    use std::io;
+    use std::sync::atomic::{AtomicI32, Ordering};
+    use std::thread;
+
+    use crate::error::SQLRiteError;
+
+    /// Write end of the pipe the handler writes the signals to
+    static PIPE: AtomicI32 = AtomicI32::new(-1);
+
+    extern "C" fn handle(signal: libc::c_int) {
+        let byte = signal as u8;
+        // Safety: write is async-signal-safe, and nothing else is done here
+        unsafe {
+            libc::write(
+                PIPE.load(Ordering::Relaxed),
+                &byte as *const u8 as *const libc::c_void,
+                1,
+            );
+        }
+    }
+
+    let os_error = |call: &str| {
+        SQLRiteError::Internal(format!(
+            "Could not handle termination signals, {} failed: {}",
+            call,
+            io::Error::last_os_error()
+        ))
+    };
+    let mut fds = [0; 2];
+    // Safety: fds has room for the two descriptors of the pipe
+    if unsafe { libc::pipe(fds.as_mut_ptr()) } != 0 {
+        return Err(os_error("pipe"));
+    }
+    let (read, write) = (fds[0], fds[1]);
+    PIPE.store(write, Ordering::Relaxed);
+
+    thread::Builder::new()
+        .name("sqlrite-shutdown".to_string())
+        .spawn(move || {
+            let mut byte = 0u8;
+            loop {
+                // Safety: byte has room for the single byte read
+                let n = unsafe { libc::read(read, &mut byte as *mut u8 as *mut libc::c_void, 1) };
+                if n == 1 {
+                    break;
+                }
+                if n < 0 && io::Error::last_os_error().kind() == io::ErrorKind::Interrupted {
+                    continue;
+                }
+                return;
+            }
+            shutdown(i32::from(byte));
+            std::process::exit(0);
+        })
+        .map_err(|err| {
+            SQLRiteError::Internal(format!("Could not start shutdown thread: {}", err))
+        })?;
+
+    for signal in [libc::SIGTERM, libc::SIGHUP] {
+        // Safety: the action is fully initialized before it is installed, and the handler
+        // only calls async-signal-safe functions
+        unsafe {
+            let mut action: libc::sigaction = std::mem::zeroed();
+            action.sa_sigaction = handle as extern "C" fn(libc::c_int) as libc::sighandler_t;
+            action.sa_flags = libc::SA_RESTART;
+            libc::sigemptyset(&mut action.sa_mask);
+            if libc::sigaction(signal, &action, std::ptr::null_mut()) != 0 {
+                return Err(os_error("sigaction"));
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub fn on_termination<F>(_shutdown: F) -> Result<()>
+where
+    F: FnOnce(i32) + Send + 'static,
+{
+    Ok(())
+}
+
+/// The modes of the terminal the REPL runs in, as they were before the line editor changed
+/// them, to hand the terminal back as it was found when exiting in the middle of a line.
+#[derive(Clone, Copy)]
+pub struct Terminal {
+    #[cfg(unix)]
+    modes: Option<libc::termios>,
+}
+
+impl Terminal {
+    /// Saves the modes of the terminal on the standard input, if it is one.
+    pub fn save() -> Terminal {
+        #[cfg(unix)]
+        {
+            // Safety: tcgetattr fills the termios it is given, which is only used if it succeeds
+            let mut modes: libc::termios = unsafe { std::mem::zeroed() };
+            let saved = unsafe { libc::tcgetattr(libc::STDIN_FILENO, &mut modes) } == 0;
+            Terminal {
+                modes: if saved { Some(modes) } else { None },
+            }
+        }
+        #[cfg(not(unix))]
+        Terminal {}
+    }
+
+    /// Restores the modes saved.
+    pub fn restore(&self) {
+        #[cfg(unix)]
+        if let Some(modes) = &self.modes {
+            // Safety: modes were filled by tcgetattr
+            unsafe {
+                libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, modes);
+            }
+        }
+    }
+}
+
+/// The thread reading the standard input, for the shutdown thread to have it read an end of
+/// file, like Ctrl-D, so that the REPL exits as it does then: on its own thread, which the
+/// database it holds can't leave.
+#[derive(Clone, Copy)]
+pub struct Input {
+    /// The `pthread_t` of the thread, an integer or a pointer depending on the platform
+    #[cfg(unix)]
+    thread: usize,
+}
+
+impl Input {
+    /// The input of the current thread.
+    pub fn current() -> Input {
+        #[cfg(unix)]
+        {
+            // Safety: pthread_self has no preconditions
+            Input {
+                thread: unsafe { libc::pthread_self() } as usize,
+            }
+        }
+        #[cfg(not(unix))]
+        Input {}
+    }
+
+    /// Replaces the standard input with /dev/null, and interrupts the read the thread may be
+    /// waiting in with SIGTERM, which restarts it on /dev/null. Input already buffered by
+    /// the thread can still be read.
+    pub fn close(&self) {
+        #[cfg(unix)]
+        // Safety: the path is nul-terminated, and the descriptors are those just opened
+        // and of the standard input
+        unsafe {
+            let null = libc::open(
+                b"/dev/null\0".as_ptr() as *const libc::c_char,
+                libc::O_RDONLY,
+            );
+            if null >= 0 {
+                libc::dup2(null, libc::STDIN_FILENO);
+                libc::close(null);
+            }
+            libc::pthread_kill(self.thread as libc::pthread_t, libc::SIGTERM);
+        }
+    }
+}
+
+/// Name of the signal numbered `signal`, for the message printed when shutting down.
+pub fn signal_name(signal: i32) -> &'static str {
+    #[cfg(unix)]
+    {
+        if signal == libc::SIGTERM {
+            return "SIGTERM";
+        }
+        if signal == libc::SIGHUP {
+            return "SIGHUP";
+        }
+    }
+    let _ = signal;
+    "signal"
+}
@@ -0,0 +1,114 @@
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
+
+use crate::error::{Result, SQLRiteError};
+
+/// Signature of the functions used to compare two text values under a collation.
+pub type CollationFn = dyn Fn(&str, &str) -> Ordering + Send + Sync + 'static;
+
+/// The registry of collating sequences available to a `Database`, referenced by name from
+/// `COLLATE` clauses in column definitions and expressions.
+/// Comes with SQLite's built-in BINARY, NOCASE and RTRIM collations.
+/// Collation names are case insensitive.
+#[derive(Clone)]
+pub struct CollationRegistry {
+    collations: HashMap<String, Arc<CollationFn>>,
+}
+
+impl CollationRegistry {
+    pub fn new() -> Self {
+        let mut registry = CollationRegistry {
+            collations: HashMap::new(),
+        };
+        registry.register("binary", |a, b| a.cmp(b));
+        registry.register("nocase", |a, b| {
+            a.to_ascii_lowercase().cmp(&b.to_ascii_lowercase())
+        });
+        registry.register("rtrim", |a, b| a.trim_end().cmp(b.trim_end()));
+        registry
+    }
+
+    /// Registers `cmp` under `name`, replacing any collation previously registered with it.
+    pub fn register<F>(&mut self, name: &str, cmp: F)
+    where
+        F: Fn(&str, &str) -> Ordering + Send + Sync + 'static,
+    {
+        self.collations.insert(name.to_lowercase(), Arc::new(cmp));
+    }
+
+    /// Returns true if a collation is registered under `name`.
+    pub fn contains(&self, name: &str) -> bool {
+        self.collations.contains_key(&name.to_lowercase())
+    }
+
+    /// Returns the comparison function of the collation registered under `name`.
+    pub fn get(&self, name: &str) -> Result<Arc<CollationFn>> {
+        match self.collations.get(&name.to_lowercase()) {
+            Some(cmp) => Ok(Arc::clone(cmp)),
+            None => Err(SQLRiteError::General(format!(
+                "no such collation sequence: {}",
+                name
+            ))),
+        }
+    }
+}
+
+impl Default for CollationRegistry {
+    fn default() -> Self {
+        CollationRegistry::new()
+    }
+}
+
+impl fmt::Debug for CollationRegistry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut names: Vec<&String> = self.collations.keys().collect();
+        names.sort();
+        f.debug_struct("CollationRegistry")
+            .field("collations", &names)
+            .finish()
+    }
+}
+
+impl PartialEq for CollationRegistry {
+    // Functions can't be compared, two registries are equal when they expose the same collations
+    fn eq(&self, other: &Self) -> bool {
+        self.collations.len() == other.collations.len()
+            && self
+                .collations
+                .keys()
+                .all(|name| other.collations.contains_key(name))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builtin_collations_test() {
+        let collations = CollationRegistry::new();
+
+        let binary = collations.get("BINARY").unwrap();
+        assert_eq!(binary("abc", "ABC"), Ordering::Greater);
+
+        let nocase = collations.get("nocase").unwrap();
+        assert_eq!(nocase("abc", "ABC"), Ordering::Equal);
+
+        let rtrim = collations.get("rtrim").unwrap();
+        assert_eq!(rtrim("abc  ", "abc"), Ordering::Equal);
+
+        assert!(collations.get("unicode_ci").is_err());
+    }
+
+    #[test]
+    fn register_collation_test() {
+        let mut collations = CollationRegistry::new();
+        collations.register("reverse", |a, b| b.cmp(a));
+
+        assert!(collations.contains("REVERSE"));
+        let reverse = collations.get("reverse").unwrap();
+        assert_eq!(reverse("a", "b"), Ordering::Greater);
+    }
+}
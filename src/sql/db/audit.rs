@@ -0,0 +1,399 @@
+//! Audit log of the changes made to the rows of tables, turned on for a database with
+//! `PRAGMA audit = TABLE` or `PRAGMA audit = 'path/to/audit.log'`, and off again with
+//! `PRAGMA audit = OFF`.
+//!
+//! Every row a statement inserts, updates or deletes is recorded with its values before and
+//! after the change, once the statement and the triggers it fired have succeeded. Records
+//! are appended to the `sqlrite_audit` table kept with the database, or as JSON lines to the
+//! file. Nothing can change the records once they are written, `sqlrite_audit` is read-only.
+//! Changes to virtual tables aren't recorded, their data belongs to their module.
+use std::fs::OpenOptions;
+use std::io::Write;
+
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value as Json};
+
+use crate::error::{Result, SQLRiteError};
+use crate::sql::params::quote_literal;
+use crate::sql::value::Value;
+
+/// Name the audit table is queried under
+pub const AUDIT_TABLE_NAME: &str = "sqlrite_audit";
+
+/// Where the changes are recorded
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub enum AuditMode {
+    Off,
+    /// In the `sqlrite_audit` table
+    Table,
+    /// Appended as JSON lines to the file at this path
+    File(String),
+}
+
+impl AuditMode {
+    /// The mode set by `PRAGMA audit = value`: `OFF`, `TABLE` or the path of a file.
+    pub fn from_pragma(value: &Value) -> Result<Self> {
+        let value = value.to_text().unwrap_or_default();
+        match value.to_lowercase().as_str() {
+            "off" | "no" | "false" | "0" => Ok(AuditMode::Off),
+            "table" => Ok(AuditMode::Table),
+            "" => Err(SQLRiteError::General(
+                "PRAGMA audit takes OFF, TABLE or the path of a file".to_string(),
+            )),
+            _ => Ok(AuditMode::File(value)),
+        }
+    }
+
+    /// The value of `PRAGMA audit`
+    pub fn to_pragma(&self) -> String {
+        match self {
+            AuditMode::Off => "off".to_string(),
+            AuditMode::Table => "table".to_string(),
+            AuditMode::File(path) => path.to_string(),
+        }
+    }
+}
+
+/// The kinds of changes recorded
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone, Copy)]
+pub enum AuditOperation {
+    Insert,
+    Update,
+    Delete,
+}
+
+impl AuditOperation {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AuditOperation::Insert => "INSERT",
+            AuditOperation::Update => "UPDATE",
+            AuditOperation::Delete => "DELETE",
+        }
+    }
+}
+
+/// A change made to a row, a row of `sqlrite_audit`
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub struct AuditRecord {
+    /// Increases by one with each record of the database
+    pub id: i64,
+    /// When the change was made, as `YYYY-MM-DD HH:MM:SS` in UTC like SQLite's `datetime()`
+    pub timestamp: String,
+    /// User or connection that made the change, if the application named it
+    pub user: Option<String>,
+    pub operation: AuditOperation,
+    pub table_name: String,
+    pub rowid: i64,
+    /// Values of the row before the change as a JSON object, `None` for inserts
+    pub old: Option<String>,
+    /// Values of the row after the change as a JSON object, `None` for deletes
+    pub new: Option<String>,
+}
+
+impl AuditRecord {
+    fn to_json(&self) -> Json {
+        json!({
+            "id": self.id,
+            "timestamp": self.timestamp,
+            "user": self.user,
+            "operation": self.operation.as_str(),
+            "table": self.table_name,
+            "rowid": self.rowid,
+            "old": self.old.as_deref().map(parse_row),
+            "new": self.new.as_deref().map(parse_row),
+        })
+    }
+}
+
+/// The audit settings and records of a database
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+pub struct AuditLog {
+    pub mode: AuditMode,
+    /// Records kept in the `sqlrite_audit` table
+    pub records: Vec<AuditRecord>,
+    /// Id of the last record made, in the table or in a file
+    last_id: i64,
+    /// Who the changes are recorded as made by, set by the application for its connection
+    #[serde(skip)]
+    pub user: Option<String>,
+    /// Records of the statement running, kept once it succeeds
+    #[serde(skip)]
+    pending: Vec<AuditRecord>,
+    /// Records waiting for the transaction they were made in to be committed, before
+    /// being written to the file
+    #[serde(skip)]
+    held: Option<Vec<AuditRecord>>,
+}
+
+impl Default for AuditLog {
+    fn default() -> Self {
+        AuditLog {
+            mode: AuditMode::Off,
+            records: vec![],
+            last_id: 0,
+            user: None,
+            pending: vec![],
+            held: None,
+        }
+    }
+}
+
+impl AuditLog {
+    /// Returns true if changes are being recorded.
+    pub fn is_enabled(&self) -> bool {
+        self.mode != AuditMode::Off
+    }
+
+    /// Records a change to the row `rowid` of `table_name`. `old` and `new` are the values of
+    /// its `columns` before and after the change.
+    pub fn record(
+        &mut self,
+        operation: AuditOperation,
+        table_name: &str,
+        rowid: i64,
+        columns: &[String],
+        old: Option<&[Value]>,
+        new: Option<&[Value]>,
+    ) {
+        if !self.is_enabled() {
+            return;
+        }
+        self.last_id += 1;
+        self.pending.push(AuditRecord {
+            id: self.last_id,
+            timestamp: timestamp(),
+            user: self.user.clone(),
+            operation,
+            table_name: table_name.to_string(),
+            rowid,
+            old: old.map(|values| row_json(columns, values)),
+            new: new.map(|values| row_json(columns, values)),
+        });
+    }
+
+    /// Keeps the records of the statement that just succeeded.
+    pub fn commit(&mut self) -> Result<()> {
+        if self.pending.is_empty() {
+            return Ok(());
+        }
+        let records = std::mem::take(&mut self.pending);
+        match (&self.mode, &mut self.held) {
+            (AuditMode::Off, _) => Ok(()),
+            (AuditMode::Table, _) => {
+                self.records.extend(records);
+                Ok(())
+            }
+            (AuditMode::File(_), Some(held)) => {
+                held.extend(records);
+                Ok(())
+            }
+            (AuditMode::File(path), None) => append_to_file(path, &records),
+        }
+    }
+
+    /// Forgets the records of the statement that just failed, whose changes were undone.
+    pub fn discard(&mut self) {
+        self.last_id -= self.pending.len() as i64;
+        self.pending.clear();
+    }
+
+    /// Holds back the records written to a file until `release`, for the application to
+    /// only write the changes of the transactions it commits.
+    pub fn hold(&mut self) {
+        self.held.get_or_insert_with(Vec::new);
+    }
+
+    /// Takes over the records `other` holds back, keeping those made before the last record
+    /// of this log, for a database rolled back to an earlier copy of it to still hold back
+    /// the records of its transaction that weren't undone.
+    pub fn keep_held(&mut self, other: &mut AuditLog) {
+        let last_id = self.last_id;
+        self.held = other.held.take().map(|mut held| {
+            held.retain(|record| record.id <= last_id);
+            held
+        });
+    }
+
+    /// Writes the records held back since `hold` if `keep` is set, and forgets them otherwise.
+    pub fn release(&mut self, keep: bool) -> Result<()> {
+        match (self.held.take(), &self.mode) {
+            (Some(records), AuditMode::File(path)) if keep && !records.is_empty() => {
+                append_to_file(path, &records)
+            }
+            (Some(records), _) if !keep => {
+                self.last_id -= records.len() as i64;
+                Ok(())
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Appends `records` to the file at `path` as JSON lines, creating it if needed.
+fn append_to_file(path: &str, records: &[AuditRecord]) -> Result<()> {
+    let mut lines = String::new();
+    for record in records {
+        lines.push_str(&record.to_json().to_string());
+        lines.push('\n');
+    }
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .and_then(|mut file| file.write_all(lines.as_bytes()))
+        .map_err(|err| SQLRiteError::General(format!("could not write the audit log: {}", err)))
+}
+
+/// The values of a row as a JSON object text, blobs being written as their SQL literal.
+fn row_json(columns: &[String], values: &[Value]) -> String {
+    let mut row = Map::new();
+    for (column, value) in columns.iter().zip(values) {
+        let value = match value {
+            Value::Null => Json::Null,
+            Value::Integer(v) => json!(v),
+            Value::Real(v) => json!(v),
+            Value::Text(v) => json!(v),
+            Value::Bool(v) => json!(v),
+            Value::Blob(_) => json!(quote_literal(value)),
+        };
+        row.insert(column.to_string(), value);
+    }
+    Json::Object(row).to_string()
+}
+
+fn parse_row(row: &str) -> Json {
+    serde_json::from_str(row).unwrap_or(Json::Null)
+}
+
+/// The current time, as `YYYY-MM-DD HH:MM:SS` in UTC.
+fn timestamp() -> String {
+    let seconds = (crate::sql::function::uuid::unix_millis() / 1000) as i64;
+    let (days, time) = (seconds.div_euclid(86400), seconds.rem_euclid(86400));
+    // Civil date of a number of days since 1970-01-01, from Howard Hinnant's algorithm
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+        year,
+        month,
+        day,
+        time / 3600,
+        time % 3600 / 60,
+        time % 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sql::db::database::Database;
+    use crate::sql::{process_command, process_query};
+
+    #[test]
+    fn audit_table_test() {
+        let mut db = Database::new("tempdb".to_string());
+        process_command(
+            "CREATE TABLE users (id INTEGER PRIMARY KEY, name TEXT);",
+            &mut db,
+        )
+        .unwrap();
+        process_command("INSERT INTO users (name) VALUES ('before');", &mut db).unwrap();
+
+        process_command("PRAGMA audit = TABLE;", &mut db).unwrap();
+        db.audit.user = Some("josh".to_string());
+        process_command(
+            "INSERT INTO users (name) VALUES ('josh'), ('mary');",
+            &mut db,
+        )
+        .unwrap();
+        // A failing statement records nothing
+        assert!(process_command("INSERT INTO users (id) VALUES (2);", &mut db).is_err());
+
+        let result = process_query(
+            "SELECT id, user, operation, table_name, rowid, old_values, new_values FROM sqlrite_audit;",
+            &db,
+        )
+        .unwrap();
+        let text = |s: &str| Value::Text(s.to_string());
+        assert_eq!(
+            result.rows,
+            vec![
+                vec![
+                    Value::Integer(1),
+                    text("josh"),
+                    text("INSERT"),
+                    text("users"),
+                    Value::Integer(2),
+                    Value::Null,
+                    text(r#"{"id":2,"name":"josh"}"#),
+                ],
+                vec![
+                    Value::Integer(2),
+                    text("josh"),
+                    text("INSERT"),
+                    text("users"),
+                    Value::Integer(3),
+                    Value::Null,
+                    text(r#"{"id":3,"name":"mary"}"#),
+                ],
+            ]
+        );
+        let result = process_query("PRAGMA audit;", &db).unwrap();
+        assert_eq!(result.rows, vec![vec![text("table")]]);
+        // The records can only be read
+        assert!(process_command("INSERT INTO sqlrite_audit (id) VALUES (9);", &mut db).is_err());
+        assert!(process_command("CREATE TABLE sqlrite_audit (id INTEGER);", &mut db).is_err());
+
+        process_command("PRAGMA audit = OFF;", &mut db).unwrap();
+        process_command("INSERT INTO users (name) VALUES ('anne');", &mut db).unwrap();
+        assert_eq!(db.audit.records.len(), 2);
+    }
+
+    #[test]
+    fn audit_file_test() {
+        let path = std::env::temp_dir().join(format!("sqlrite-audit-{}.log", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let mut db = Database::new("tempdb".to_string());
+        process_command("CREATE TABLE users (id INTEGER PRIMARY KEY);", &mut db).unwrap();
+        process_command(
+            &format!("PRAGMA audit = '{}';", path.to_str().unwrap()),
+            &mut db,
+        )
+        .unwrap();
+        process_command("INSERT INTO users (id) VALUES (1);", &mut db).unwrap();
+
+        // Records of a transaction are only written once it is committed
+        db.audit.hold();
+        process_command("INSERT INTO users (id) VALUES (2);", &mut db).unwrap();
+        db.audit.release(false).unwrap();
+        db.audit.hold();
+        process_command("INSERT INTO users (id) VALUES (3);", &mut db).unwrap();
+        db.audit.release(true).unwrap();
+
+        let log = std::fs::read_to_string(&path).unwrap();
+        let _ = std::fs::remove_file(&path);
+        let records = log
+            .lines()
+            .map(|line| serde_json::from_str::<Json>(line).unwrap())
+            .collect::<Vec<Json>>();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0]["operation"], "INSERT");
+        assert_eq!(records[0]["new"], json!({ "id": 1 }));
+        assert_eq!(records[1]["rowid"], 3);
+        assert!(records[1]["old"].is_null());
+    }
+
+    #[test]
+    fn timestamp_test() {
+        let timestamp = timestamp();
+        assert_eq!(timestamp.len(), 19);
+        assert!(timestamp.starts_with("20"));
+    }
+}
@@ -0,0 +1,205 @@
+//! Autosave of a database opened from a file. Its changes are only written to the file by
+//! saving it, on `.exit` or `.open` otherwise, so a crash loses everything done since it
+//! was opened. With autosave, the database is saved again as statements end, once its
+//! oldest unsaved change is old enough, or enough statements or rows changed it since it
+//! was last saved, which bounds the work a crash can lose. Set from the REPL with
+//! `.autosave`.
+//!
+//! The database belongs to the connection running the statements, so the checks are made
+//! when statements end rather than by a thread of their own: a database left idle keeps
+//! its last changes unsaved until the next statement, or until it is closed.
+use std::fmt;
+use std::time::{Duration, Instant};
+
+/// When a database changed since it was last saved is saved again
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AutosaveInterval {
+    /// Only when the database is closed
+    Off,
+    /// Once its oldest unsaved change is this old
+    Every(Duration),
+    /// Once this many statements changed it
+    Statements(u64),
+    /// Once this many rows changed
+    Changes(u64),
+}
+
+impl AutosaveInterval {
+    /// Reads an interval the way `.autosave` takes it: `off`, a duration like `30s` or `5m`,
+    /// `N statements` or `N changes`. `None` if it isn't one, or isn't greater than 0.
+    pub fn parse(text: &str) -> Option<AutosaveInterval> {
+        let words = text.split_whitespace().collect::<Vec<&str>>();
+        let count = |n: &str| n.parse::<u64>().ok().filter(|n| *n > 0);
+        match words.as_slice() {
+            ["off"] => Some(AutosaveInterval::Off),
+            [n, "statements"] | [n, "statement"] => count(n).map(AutosaveInterval::Statements),
+            [n, "changes"] | [n, "change"] => count(n).map(AutosaveInterval::Changes),
+            [duration] => {
+                let seconds = match (duration.strip_suffix('s'), duration.strip_suffix('m')) {
+                    (Some(n), _) => count(n)?,
+                    (_, Some(n)) => count(n)?.checked_mul(60)?,
+                    _ => return None,
+                };
+                Some(AutosaveInterval::Every(Duration::from_secs(seconds)))
+            }
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for AutosaveInterval {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AutosaveInterval::Off => f.write_str("off"),
+            AutosaveInterval::Every(duration) => write!(f, "every {}s", duration.as_secs()),
+            AutosaveInterval::Statements(n) => write!(f, "every {} statements", n),
+            AutosaveInterval::Changes(n) => write!(f, "every {} changes", n),
+        }
+    }
+}
+
+/// What tells a database changed: its number of rows changed since it was opened, its
+/// schema version and its user version
+pub type ChangeMark = (i64, i64, i64);
+
+/// The autosave of a connection, with the changes made since the database was last saved
+#[derive(Debug, Clone, PartialEq)]
+pub struct Autosave {
+    interval: AutosaveInterval,
+    /// The database when it was last saved
+    saved: ChangeMark,
+    /// The database when the last statement ended
+    last: ChangeMark,
+    /// When the oldest change not saved yet was made, `None` if there is none
+    dirty_since: Option<Instant>,
+    /// Statements that changed the database since it was last saved
+    statements: u64,
+    /// Number of times the database was saved by autosave
+    saves: u64,
+    /// Why the last autosave failed, until it is reported
+    error: Option<String>,
+}
+
+impl Default for Autosave {
+    fn default() -> Self {
+        Autosave {
+            interval: AutosaveInterval::Off,
+            saved: (0, 0, 0),
+            last: (0, 0, 0),
+            dirty_since: None,
+            statements: 0,
+            saves: 0,
+            error: None,
+        }
+    }
+}
+
+impl Autosave {
+    pub fn interval(&self) -> AutosaveInterval {
+        self.interval
+    }
+
+    pub fn set_interval(&mut self, interval: AutosaveInterval) {
+        self.interval = interval;
+    }
+
+    /// Number of statements that changed the database since it was last saved.
+    pub fn unsaved_statements(&self) -> u64 {
+        self.statements
+    }
+
+    /// Number of times the database was saved by autosave.
+    pub fn saves(&self) -> u64 {
+        self.saves
+    }
+
+    /// Records that a statement ended with the database at `mark`, returning true if it
+    /// is time to save it.
+    pub fn statement_ended(&mut self, mark: ChangeMark) -> bool {
+        if mark != self.last {
+            self.last = mark;
+            self.statements += 1;
+            self.dirty_since.get_or_insert_with(Instant::now);
+        }
+        let dirty_since = match self.dirty_since {
+            Some(dirty_since) if mark != self.saved => dirty_since,
+            _ => return false,
+        };
+        match self.interval {
+            AutosaveInterval::Off => false,
+            AutosaveInterval::Every(duration) => dirty_since.elapsed() >= duration,
+            AutosaveInterval::Statements(n) => self.statements >= n,
+            AutosaveInterval::Changes(n) => (mark.0 - self.saved.0).unsigned_abs() >= n,
+        }
+    }
+
+    /// Records that the database was saved at `mark`, by autosave if `automatic`.
+    pub fn saved(&mut self, mark: ChangeMark, automatic: bool) {
+        self.saved = mark;
+        self.last = mark;
+        self.dirty_since = None;
+        self.statements = 0;
+        if automatic {
+            self.saves += 1;
+            self.error = None;
+        }
+    }
+
+    /// Records that saving the database failed with `error`, the changes staying unsaved
+    /// to be saved by the next statement.
+    pub fn failed(&mut self, error: String) {
+        self.error = Some(error);
+    }
+
+    /// Why the last autosave failed, if it did since this was last called.
+    pub fn take_error(&mut self) -> Option<String> {
+        self.error.take()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn autosave_test() {
+        assert_eq!(
+            AutosaveInterval::parse("30s"),
+            Some(AutosaveInterval::Every(Duration::from_secs(30)))
+        );
+        assert_eq!(
+            AutosaveInterval::parse("2m"),
+            Some(AutosaveInterval::Every(Duration::from_secs(120)))
+        );
+        assert_eq!(
+            AutosaveInterval::parse("10 statements"),
+            Some(AutosaveInterval::Statements(10))
+        );
+        assert_eq!(AutosaveInterval::parse("off"), Some(AutosaveInterval::Off));
+        for invalid in ["", "0s", "10", "10 rows", "-1 changes"] {
+            assert_eq!(AutosaveInterval::parse(invalid), None);
+        }
+        assert_eq!(
+            AutosaveInterval::Changes(5).to_string(),
+            "every 5 changes".to_string()
+        );
+
+        let mut autosave = Autosave::default();
+        assert!(!autosave.statement_ended((3, 1, 0)));
+        autosave.set_interval(AutosaveInterval::Statements(2));
+        // Statements that change nothing aren't counted
+        assert!(!autosave.statement_ended((3, 1, 0)));
+        assert!(autosave.statement_ended((4, 1, 0)));
+        autosave.saved((4, 1, 0), true);
+        assert_eq!((autosave.unsaved_statements(), autosave.saves()), (0, 1));
+
+        autosave.set_interval(AutosaveInterval::Changes(10));
+        assert!(!autosave.statement_ended((10, 1, 0)));
+        assert!(autosave.statement_ended((14, 1, 0)));
+        autosave.set_interval(AutosaveInterval::Every(Duration::from_secs(0)));
+        assert!(autosave.statement_ended((14, 1, 0)));
+        autosave.saved((14, 1, 0), false);
+        assert!(!autosave.statement_ended((14, 1, 0)));
+        assert_eq!(autosave.saves(), 1);
+    }
+}
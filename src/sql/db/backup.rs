@@ -0,0 +1,175 @@
+//! Online backups of a database, taken with `Database::backup` or `.backup FILE`. The
+//! database is only held while an image of it is taken in memory, consistent as it is
+//! between two statements; the image is then written to the file of the backup by a thread
+//! of its own, pages synced to the disk, while the connection keeps running statements.
+//!
+//! The backups still being written are kept with the connection, which reports them once
+//! they are done and waits for them before it exits.
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::thread::{self, JoinHandle};
+
+use crate::error::{Result, SQLRiteError};
+
+/// A backup being written to its file
+pub struct Backup {
+    path: PathBuf,
+    writer: JoinHandle<Result<()>>,
+}
+
+impl Backup {
+    /// Starts writing the backup to `path` with `write`, on a thread of its own.
+    pub fn start<F>(path: &Path, write: F) -> Backup
+    where
+        F: FnOnce(&Path) -> Result<()> + Send + 'static,
+    {
+        let target = path.to_path_buf();
+        Backup {
+            path: path.to_path_buf(),
+            writer: thread::spawn(move || write(&target)),
+        }
+    }
+
+    /// File the backup is written to.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Whether the backup is done writing, successfully or not.
+    pub fn is_finished(&self) -> bool {
+        self.writer.is_finished()
+    }
+
+    /// Waits for the backup to be written, returning whether it was.
+    pub fn wait(self) -> Result<()> {
+        let path = self.path;
+        self.writer.join().unwrap_or_else(|_| {
+            Err(SQLRiteError::Internal(format!(
+                "the backup to {} panicked",
+                path.display()
+            )))
+        })
+    }
+}
+
+impl fmt::Debug for Backup {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Backup")
+            .field("path", &self.path)
+            .field("finished", &self.is_finished())
+            .finish()
+    }
+}
+
+/// The backups of a connection still being written, or done but not reported yet
+#[derive(Debug, Default)]
+pub struct Backups {
+    running: Vec<Backup>,
+}
+
+impl Backups {
+    /// Keeps `backup` until it is done.
+    pub fn push(&mut self, backup: Backup) {
+        self.running.push(backup);
+    }
+
+    /// Whether a backup to `path` is still being written.
+    pub fn is_running(&self, path: &Path) -> bool {
+        self.running
+            .iter()
+            .any(|backup| backup.path == path && !backup.is_finished())
+    }
+
+    /// Takes the backups that are done, with whether each was written.
+    pub fn finished(&mut self) -> Vec<(PathBuf, Result<()>)> {
+        let (finished, running) = std::mem::take(&mut self.running)
+            .into_iter()
+            .partition::<Vec<Backup>, _>(Backup::is_finished);
+        self.running = running;
+        finished
+            .into_iter()
+            .map(|backup| (backup.path.clone(), backup.wait()))
+            .collect()
+    }
+
+    /// Waits for every backup still being written, failing with the error of the first
+    /// one that couldn't be.
+    pub fn wait(&mut self) -> Result<()> {
+        std::mem::take(&mut self.running)
+            .into_iter()
+            .map(Backup::wait)
+            .fold(Ok(()), Result::and)
+    }
+}
+
+/// Backups are always equal, they belong to the connection, not to the database.
+impl PartialEq for Backups {
+    fn eq(&self, _: &Self) -> bool {
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sql::db::database::Database;
+    use crate::sql::process_command;
+    use crate::sql::value::Value;
+
+    #[test]
+    fn backup_test() {
+        let path = std::env::temp_dir().join(format!("sqlrite-backup-{}.db", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let mut db = Database::new("tempdb".to_string());
+        process_command(
+            "CREATE TABLE users (id INTEGER PRIMARY KEY, name TEXT);",
+            &mut db,
+        )
+        .unwrap();
+        process_command("INSERT INTO users (name) VALUES ('josh');", &mut db).unwrap();
+        let backup = db.backup(&path).unwrap();
+        assert_eq!(backup.path(), path.as_path());
+        // Changes made once the backup started aren't in it
+        process_command("INSERT INTO users (name) VALUES ('ana');", &mut db).unwrap();
+        backup.wait().unwrap();
+        let backed_up = Database::read_file(&path).unwrap();
+        assert_eq!(backed_up.tables["users"].rowids(), vec![1]);
+        assert_eq!(
+            backed_up.tables["users"].get_value("name", 1).unwrap(),
+            Value::Text("josh".to_string())
+        );
+        assert_eq!(db.path, None);
+
+        // A backup replaces the file
+        db.backups.push(db.backup(&path).unwrap());
+        db.backups.wait().unwrap();
+        assert_eq!(
+            Database::read_file(&path).unwrap().tables["users"]
+                .rowids()
+                .len(),
+            2
+        );
+
+        // A backup isn't taken into a file another one is still writing
+        let (release, released) = std::sync::mpsc::channel::<()>();
+        db.backups.push(Backup::start(&path, move |_| {
+            released.recv().unwrap();
+            Ok(())
+        }));
+        assert!(db.backup(&path).is_err());
+        assert!(db.backups.finished().is_empty());
+        release.send(()).unwrap();
+        db.backups.wait().unwrap();
+
+        // Nor into the file the database is saved to
+        db.open_file(&path).unwrap();
+        assert!(db.backup(&path).is_err());
+        db.path = None;
+
+        let missing = std::env::temp_dir()
+            .join("sqlrite-no-such-dir")
+            .join("x.db");
+        assert!(db.backup(&missing).unwrap().wait().is_err());
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,147 @@
+//! Bloom filters kept next to the indexes of UNIQUE columns, so that checking a new value
+//! isn't already there, the common case of an insert, is answered without looking it up
+//! in the index: a value the filter has never seen is definitely not in the column. Joins
+//! looking up the rows of a UNIQUE column skip the values of the other table it doesn't have.
+//!
+//! Filters are optional and configured per index with `Table::set_bloom_filter`. Only the
+//! configuration is saved with the table, the bits are rebuilt from the index the first
+//! time the filter is needed, and again once it holds more values than it was sized for.
+//! Values removed from the index stay in the filter until it is rebuilt, which only costs
+//! a lookup in the index. Columns compared with a collation can't use a filter, values
+//! that are equal under it don't hash the same.
+use serde::{Deserialize, Serialize};
+
+/// False positive rate of a filter when none is given
+pub const DEFAULT_FALSE_POSITIVE_RATE: f64 = 0.01;
+
+/// Number of values a filter is sized for at least
+const MIN_CAPACITY: usize = 1024;
+
+/// A bloom filter over the values of an index
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub struct BloomFilter {
+    /// Share of the values not in the filter it may still claim to contain
+    pub false_positive_rate: f64,
+    /// The bits, empty until the filter is built
+    #[serde(skip)]
+    bits: Vec<u64>,
+    /// Number of bits set for each value
+    #[serde(skip)]
+    hashes: u32,
+    /// Number of values the filter was sized for
+    #[serde(skip)]
+    capacity: usize,
+    /// Number of values inserted since the filter was built
+    #[serde(skip)]
+    items: usize,
+}
+
+impl BloomFilter {
+    /// A filter that isn't built yet, with the given false positive rate.
+    pub fn new(false_positive_rate: f64) -> Self {
+        BloomFilter {
+            false_positive_rate,
+            bits: vec![],
+            hashes: 0,
+            capacity: 0,
+            items: 0,
+        }
+    }
+
+    /// Returns true once the filter was built, until then it may contain anything.
+    pub fn is_built(&self) -> bool {
+        !self.bits.is_empty()
+    }
+
+    /// Returns true if the filter has to be rebuilt before values are inserted: it isn't
+    /// built yet, or holds more values than it was sized for.
+    pub fn needs_rebuild(&self) -> bool {
+        !self.is_built() || self.items >= self.capacity
+    }
+
+    /// Bytes of memory taken by the bits of the filter.
+    pub fn memory(&self) -> usize {
+        self.bits.len() * std::mem::size_of::<u64>()
+    }
+
+    /// Frees the bits of the filter, which is rebuilt the next time it is needed.
+    pub fn release(&mut self) {
+        self.bits = vec![];
+        self.capacity = 0;
+        self.items = 0;
+    }
+
+    /// Empties the filter, sizing it for twice `items` values.
+    pub fn reset(&mut self, items: usize) {
+        let capacity = (items * 2).max(MIN_CAPACITY);
+        let rate = self.false_positive_rate.clamp(1e-9, 0.5);
+        let ln2 = std::f64::consts::LN_2;
+        let bits = (-(capacity as f64) * rate.ln() / (ln2 * ln2)).ceil() as usize;
+        let words = bits.div_ceil(64);
+        self.bits = vec![0; words];
+        self.hashes = ((words * 64) as f64 / capacity as f64 * ln2)
+            .round()
+            .clamp(1.0, 16.0) as u32;
+        self.capacity = capacity;
+        self.items = 0;
+    }
+
+    /// Adds `key` to the filter, which must be built.
+    pub fn insert(&mut self, key: &[u8]) {
+        for bit in self.positions(key) {
+            self.bits[bit / 64] |= 1 << (bit % 64);
+        }
+        self.items += 1;
+    }
+
+    /// Returns false if `key` was definitely never inserted, true if it may have been.
+    pub fn may_contain(&self, key: &[u8]) -> bool {
+        !self.is_built()
+            || self
+                .positions(key)
+                .all(|bit| self.bits[bit / 64] & (1 << (bit % 64)) != 0)
+    }
+
+    /// The bits of `key`, by double hashing its FNV-1a hash.
+    fn positions(&self, key: &[u8]) -> impl Iterator<Item = usize> {
+        let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+        for byte in key {
+            hash ^= u64::from(*byte);
+            hash = hash.wrapping_mul(0x0100_0000_01b3);
+        }
+        let first = hash;
+        // SplitMix64 finalizer, for a second hash independent enough from the first
+        let mut second = hash.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        second = (second ^ (second >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        second = (second ^ (second >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        second ^= second >> 31;
+        let bits = (self.bits.len() * 64) as u64;
+        (0..u64::from(self.hashes))
+            .map(move |i| (first.wrapping_add(i.wrapping_mul(second | 1)) % bits) as usize)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bloom_filter_test() {
+        let mut filter = BloomFilter::new(DEFAULT_FALSE_POSITIVE_RATE);
+        assert!(filter.may_contain(b"anything"));
+        assert!(filter.needs_rebuild());
+
+        filter.reset(1000);
+        for i in 0..2000 {
+            filter.insert(format!("user{}@example.com", i).as_bytes());
+        }
+        assert!(filter.needs_rebuild());
+        for i in 0..2000 {
+            assert!(filter.may_contain(format!("user{}@example.com", i).as_bytes()));
+        }
+        let false_positives = (0..10000)
+            .filter(|i| filter.may_contain(format!("other{}@example.com", i).as_bytes()))
+            .count();
+        assert!(false_positives < 300, "{} false positives", false_positives);
+    }
+}
@@ -0,0 +1,703 @@
+//! B+trees of the rows of a table, keyed by ROWID, stored in the pages of a pager. Each
+//! `Table` keeps its rows in a tree of its own, see `row_store`, that statements insert,
+//! look up, scan and delete rows in, and a database file saves the rows of each table in a
+//! tree of the file.
+//! Like the table B-trees of SQLite, the rows are in the leaves, in ROWID order, each leaf
+//! pointing to the next one so that a range of rows is read leaf after leaf. Interior
+//! pages only hold keys: each of their children holds the rows whose ROWID is at most the
+//! key after it, the last child the rows past every key. A row too big to leave room for
+//! several in a leaf is kept in a chain of overflow pages, the leaf keeping where it starts.
+//!
+//! A page is read into a `Node`, changed, and written back whole. A leaf or interior page
+//! that doesn't fit in its page any more is split in two, the new page taking the second
+//! half of its rows or children, and its parent the key between them. When the root is
+//! split, its halves move to new pages and it becomes their parent, so that the root of a
+//! tree never moves.
+//!
+//! Pages a tree no longer uses go back to the free-list of the pager: the overflow pages
+//! of the rows deleted or replaced, the leaves left empty by deletes and the interior
+//! pages left without children, and every page of a tree that is destroyed.
+use std::convert::TryInto;
+use std::ops::{Bound, RangeBounds};
+
+use crate::error::Result;
+use crate::sql::db::memory::PAGE_SIZE;
+use crate::sql::db::pager::{malformed, read_u32, PageId, Pager};
+
+const INTERIOR: u8 = 1;
+const LEAF: u8 = 2;
+const OVERFLOW: u8 = 3;
+
+/// Bytes before the cells of a leaf or interior page: the type of page, the number of
+/// cells, and the next leaf or the last child
+const NODE_HEADER: usize = 7;
+/// Bytes before the data of an overflow page: its type, the next page and the length
+const OVERFLOW_HEADER: usize = 9;
+/// Bytes of a child and its key in an interior page
+const ENTRY_SIZE: usize = 12;
+/// Bytes of a row kept in a leaf at most, past which it goes to overflow pages. It leaves
+/// room for at least four rows in a leaf, so that the halves of a split leaf always fit.
+const MAX_LOCAL: usize = (PAGE_SIZE - NODE_HEADER) / 4 - 12;
+
+/// A B+tree of rows keyed by ROWID, from its root page
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BTree {
+    root: PageId,
+}
+
+/// The row of a leaf
+#[derive(Debug, Clone, PartialEq)]
+struct Cell {
+    rowid: i64,
+    payload: Payload,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Payload {
+    Local(Vec<u8>),
+    /// A row of `length` bytes kept in the chain of overflow pages starting at `first`
+    Overflow {
+        length: u32,
+        first: PageId,
+    },
+}
+
+/// A leaf or interior page, read
+#[derive(Debug, Clone, PartialEq)]
+enum Node {
+    Leaf {
+        cells: Vec<Cell>,
+        /// The next leaf, 0 for the last one
+        next: PageId,
+    },
+    Interior {
+        /// Each child with the greatest ROWID it may hold
+        entries: Vec<(PageId, i64)>,
+        /// The child holding the rows past every key
+        right: PageId,
+    },
+}
+
+impl BTree {
+    /// Creates an empty tree in a new page of `pager`.
+    pub fn create(pager: &mut Pager) -> Result<BTree> {
+        let root = pager.allocate()?;
+        let leaf = Node::Leaf {
+            cells: vec![],
+            next: 0,
+        };
+        pager.write(root, leaf.encode())?;
+        Ok(BTree { root })
+    }
+
+    /// The tree whose root is the page `root`.
+    pub fn open(root: PageId) -> BTree {
+        BTree { root }
+    }
+
+    /// The page the root of the tree is in, which never changes.
+    pub fn root(&self) -> PageId {
+        self.root
+    }
+
+    /// Stores `payload` as the row `rowid`, replacing the row it was.
+    pub fn insert(&self, pager: &mut Pager, rowid: i64, payload: &[u8]) -> Result<()> {
+        let payload = match payload.len() {
+            length if length > MAX_LOCAL => Payload::Overflow {
+                length: length as u32,
+                first: write_overflow(pager, payload)?,
+            },
+            _ => Payload::Local(payload.to_vec()),
+        };
+        let split = insert_into(pager, self.root, Cell { rowid, payload })?;
+        if let Some((key, right)) = split {
+            // The root keeps its page: its first half moves to a new page, and it becomes
+            // the parent of both halves
+            let left = pager.allocate()?;
+            let moved = pager.read(self.root)?.to_vec();
+            pager.write(left, moved)?;
+            let root = Node::Interior {
+                entries: vec![(left, key)],
+                right,
+            };
+            pager.write(self.root, root.encode())?;
+        }
+        Ok(())
+    }
+
+    /// The row `rowid`, if the tree has it.
+    pub fn get(&self, pager: &mut Pager, rowid: i64) -> Result<Option<Vec<u8>>> {
+        let leaf = find_leaf(pager, self.root, rowid)?;
+        let cell = match read_node(pager, leaf)? {
+            Node::Leaf { cells, .. } => cells.into_iter().find(|cell| cell.rowid == rowid),
+            Node::Interior { .. } => return Err(malformed()),
+        };
+        cell.map(|cell| read_payload(pager, &cell.payload))
+            .transpose()
+    }
+
+    /// The rows whose ROWID is in `range`, in ROWID order.
+    pub fn scan(
+        &self,
+        pager: &mut Pager,
+        range: impl RangeBounds<i64>,
+    ) -> Result<Vec<(i64, Vec<u8>)>> {
+        let start = match range.start_bound() {
+            Bound::Included(start) => *start,
+            Bound::Excluded(start) if *start == i64::MAX => return Ok(vec![]),
+            Bound::Excluded(start) => start + 1,
+            Bound::Unbounded => i64::MIN,
+        };
+        let mut rows = vec![];
+        let mut leaf = find_leaf(pager, self.root, start)?;
+        loop {
+            let (cells, next) = match read_node(pager, leaf)? {
+                Node::Leaf { cells, next } => (cells, next),
+                Node::Interior { .. } => return Err(malformed()),
+            };
+            for cell in cells {
+                if cell.rowid < start {
+                    continue;
+                }
+                if !range.contains(&cell.rowid) {
+                    return Ok(rows);
+                }
+                rows.push((cell.rowid, read_payload(pager, &cell.payload)?));
+            }
+            if next == 0 {
+                return Ok(rows);
+            }
+            leaf = next;
+        }
+    }
+
+    /// The ROWIDs of the rows of the tree, in order, without reading the rows.
+    pub fn rowids(&self, pager: &mut Pager) -> Result<Vec<i64>> {
+        let mut rowids = vec![];
+        let mut leaf = find_leaf(pager, self.root, i64::MIN)?;
+        loop {
+            match read_node(pager, leaf)? {
+                Node::Leaf { cells, next } => {
+                    rowids.extend(cells.iter().map(|cell| cell.rowid));
+                    if next == 0 {
+                        return Ok(rowids);
+                    }
+                    leaf = next;
+                }
+                Node::Interior { .. } => return Err(malformed()),
+            }
+        }
+    }
+
+    /// Removes the row `rowid`, returning true if the tree had it. Pages left with fewer
+    /// rows aren't merged, but a leaf left empty is taken out of the tree and freed, the
+    /// leaf before it linked to the one after it.
+    pub fn delete(&self, pager: &mut Pager, rowid: i64) -> Result<bool> {
+        let (leaf, path) = find_path(pager, self.root, rowid)?;
+        let (mut cells, next) = match read_node(pager, leaf)? {
+            Node::Leaf { cells, next } => (cells, next),
+            Node::Interior { .. } => return Err(malformed()),
+        };
+        let position = match cells.iter().position(|cell| cell.rowid == rowid) {
+            Some(position) => position,
+            None => return Ok(false),
+        };
+        let cell = cells.remove(position);
+        free_payload(pager, &cell.payload)?;
+        if !cells.is_empty() || path.is_empty() {
+            pager.write(leaf, Node::Leaf { cells, next }.encode())?;
+            return Ok(true);
+        }
+        if let Some(previous) = previous_leaf(pager, &path)? {
+            let cells = match read_node(pager, previous)? {
+                Node::Leaf { cells, .. } => cells,
+                Node::Interior { .. } => return Err(malformed()),
+            };
+            pager.write(previous, Node::Leaf { cells, next }.encode())?;
+        }
+        remove_child(pager, self.root, &path, leaf)?;
+        Ok(true)
+    }
+
+    /// Frees every page of the tree, its root included, after which it can't be used.
+    pub fn destroy(self, pager: &mut Pager) -> Result<()> {
+        let mut pages = vec![self.root];
+        let mut freed = 0;
+        while let Some(page) = pages.pop() {
+            // A tree with more pages than the file has a cycle
+            freed += 1;
+            if freed > pager.page_count() {
+                return Err(malformed());
+            }
+            match read_node(pager, page)? {
+                Node::Leaf { cells, .. } => {
+                    for cell in cells {
+                        free_payload(pager, &cell.payload)?;
+                    }
+                }
+                Node::Interior { entries, right } => {
+                    pages.extend(entries.iter().map(|(child, _)| *child));
+                    pages.push(right);
+                }
+            }
+            pager.free(page)?;
+        }
+        Ok(())
+    }
+}
+
+/// The leaf of the subtree rooted at `page` that holds the row `rowid`, with the interior
+/// pages above it from `page` down, and the position of the child taken in each.
+fn find_path(
+    pager: &mut Pager,
+    page: PageId,
+    rowid: i64,
+) -> Result<(PageId, Vec<(PageId, usize)>)> {
+    let mut page = page;
+    let mut path = vec![];
+    for _ in 0..=pager.page_count() {
+        match read_node(pager, page)? {
+            Node::Leaf { .. } => return Ok((page, path)),
+            Node::Interior { entries, right } => {
+                let position = entries
+                    .iter()
+                    .position(|(_, key)| rowid <= *key)
+                    .unwrap_or(entries.len());
+                path.push((page, position));
+                page = entries.get(position).map_or(right, |(child, _)| *child);
+            }
+        }
+    }
+    Err(malformed())
+}
+
+/// The leaf before the one `path` leads to, the last leaf of the child before it at the
+/// deepest interior page that has one. `None` for the first leaf of the tree.
+fn previous_leaf(pager: &mut Pager, path: &[(PageId, usize)]) -> Result<Option<PageId>> {
+    let (page, position) = match path.iter().rev().find(|(_, position)| *position > 0) {
+        Some(step) => *step,
+        None => return Ok(None),
+    };
+    let mut page = match read_node(pager, page)? {
+        Node::Interior { entries, .. } => entries.get(position - 1).ok_or_else(malformed)?.0,
+        Node::Leaf { .. } => return Err(malformed()),
+    };
+    for _ in 0..=pager.page_count() {
+        match read_node(pager, page)? {
+            Node::Leaf { .. } => return Ok(Some(page)),
+            Node::Interior { right, .. } => page = right,
+        }
+    }
+    Err(malformed())
+}
+
+/// Takes the page `child`, that `path` leads to, out of the tree and frees it. A parent
+/// left without children is taken out and freed in turn, the root becoming an empty leaf.
+fn remove_child(
+    pager: &mut Pager,
+    root: PageId,
+    path: &[(PageId, usize)],
+    child: PageId,
+) -> Result<()> {
+    let mut child = child;
+    for (parent, position) in path.iter().rev() {
+        pager.free(child)?;
+        let (mut entries, mut right) = match read_node(pager, *parent)? {
+            Node::Interior { entries, right } => (entries, right),
+            Node::Leaf { .. } => return Err(malformed()),
+        };
+        // The rows the child would hold go to the child after it, or before it when it
+        // was the last child
+        if *position < entries.len() {
+            entries.remove(*position);
+        } else if let Some((last, _)) = entries.pop() {
+            right = last;
+        } else if *parent == root {
+            let leaf = Node::Leaf {
+                cells: vec![],
+                next: 0,
+            };
+            return pager.write(root, leaf.encode());
+        } else {
+            child = *parent;
+            continue;
+        }
+        return pager.write(*parent, Node::Interior { entries, right }.encode());
+    }
+    Ok(())
+}
+
+/// Inserts `cell` into the subtree rooted at `page`, returning the key and the new page
+/// of its second half if it was split.
+fn insert_into(pager: &mut Pager, page: PageId, cell: Cell) -> Result<Option<(i64, PageId)>> {
+    match read_node(pager, page)? {
+        Node::Leaf { mut cells, next } => {
+            match cells.binary_search_by_key(&cell.rowid, |cell| cell.rowid) {
+                Ok(position) => {
+                    let replaced = std::mem::replace(&mut cells[position], cell);
+                    free_payload(pager, &replaced.payload)?;
+                }
+                Err(position) => cells.insert(position, cell),
+            }
+            let node = Node::Leaf { cells, next };
+            if node.size() <= PAGE_SIZE {
+                pager.write(page, node.encode())?;
+                return Ok(None);
+            }
+            let mut cells = match node {
+                Node::Leaf { cells, .. } => cells,
+                Node::Interior { .. } => unreachable!(),
+            };
+            // The first half keeps at least half of the bytes
+            let total = cells.iter().map(Cell::size).sum::<usize>();
+            let mut kept = 0;
+            let mut half = 0;
+            while kept * 2 < total && half < cells.len() - 1 {
+                kept += cells[half].size();
+                half += 1;
+            }
+            let second = cells.split_off(half.max(1));
+            let key = cells[cells.len() - 1].rowid;
+            let new_page = pager.allocate()?;
+            pager.write(
+                new_page,
+                Node::Leaf {
+                    cells: second,
+                    next,
+                }
+                .encode(),
+            )?;
+            pager.write(
+                page,
+                Node::Leaf {
+                    cells,
+                    next: new_page,
+                }
+                .encode(),
+            )?;
+            Ok(Some((key, new_page)))
+        }
+        Node::Interior { mut entries, right } => {
+            let position = entries.iter().position(|(_, key)| cell.rowid <= *key);
+            let child = position.map_or(right, |position| entries[position].0);
+            let mut right = right;
+            match insert_into(pager, child, cell)? {
+                None => return Ok(None),
+                // The child keeps the rows up to `key`, the new page those after it
+                Some((key, new_page)) => match position {
+                    Some(position) => {
+                        let bound = entries[position].1;
+                        entries[position] = (child, key);
+                        entries.insert(position + 1, (new_page, bound));
+                    }
+                    None => {
+                        entries.push((child, key));
+                        right = new_page;
+                    }
+                },
+            }
+            let node = Node::Interior { entries, right };
+            if node.size() <= PAGE_SIZE {
+                pager.write(page, node.encode())?;
+                return Ok(None);
+            }
+            let mut entries = match node {
+                Node::Interior { entries, .. } => entries,
+                Node::Leaf { .. } => unreachable!(),
+            };
+            // The middle child becomes the last one of the first half, its key the key
+            // between the halves
+            let mut second = entries.split_off(entries.len() / 2);
+            let (middle, key) = second.remove(0);
+            let new_page = pager.allocate()?;
+            pager.write(
+                new_page,
+                Node::Interior {
+                    entries: second,
+                    right,
+                }
+                .encode(),
+            )?;
+            pager.write(
+                page,
+                Node::Interior {
+                    entries,
+                    right: middle,
+                }
+                .encode(),
+            )?;
+            Ok(Some((key, new_page)))
+        }
+    }
+}
+
+/// The leaf of the subtree rooted at `page` that holds the row `rowid`, if there is one.
+fn find_leaf(pager: &mut Pager, page: PageId, rowid: i64) -> Result<PageId> {
+    let mut page = page;
+    // A tree deeper than there are pages has a cycle
+    for _ in 0..=pager.page_count() {
+        match read_node(pager, page)? {
+            Node::Leaf { .. } => return Ok(page),
+            Node::Interior { entries, right } => {
+                page = entries
+                    .iter()
+                    .find(|(_, key)| rowid <= *key)
+                    .map_or(right, |(child, _)| *child);
+            }
+        }
+    }
+    Err(malformed())
+}
+
+fn read_node(pager: &mut Pager, page: PageId) -> Result<Node> {
+    Node::decode(pager.read(page)?)
+}
+
+/// Writes `payload` into a chain of new overflow pages, returning the first one.
+fn write_overflow(pager: &mut Pager, payload: &[u8]) -> Result<PageId> {
+    let chunks = payload
+        .chunks(PAGE_SIZE - OVERFLOW_HEADER)
+        .collect::<Vec<&[u8]>>();
+    let pages = chunks
+        .iter()
+        .map(|_| pager.allocate())
+        .collect::<Result<Vec<PageId>>>()?;
+    for (i, chunk) in chunks.iter().enumerate() {
+        let next = pages.get(i + 1).copied().unwrap_or(0);
+        let mut data = vec![OVERFLOW];
+        data.extend_from_slice(&next.to_be_bytes());
+        data.extend_from_slice(&(chunk.len() as u32).to_be_bytes());
+        data.extend_from_slice(chunk);
+        pager.write(pages[i], data)?;
+    }
+    Ok(pages[0])
+}
+
+/// Frees the overflow pages of the row `payload`, if it has any.
+fn free_payload(pager: &mut Pager, payload: &Payload) -> Result<()> {
+    let mut page = match payload {
+        Payload::Local(_) => return Ok(()),
+        Payload::Overflow { first, .. } => *first,
+    };
+    let mut freed = 0;
+    while page != 0 {
+        // A chain longer than the file has a cycle
+        if freed > pager.page_count() {
+            return Err(malformed());
+        }
+        let data = pager.read(page)?;
+        if data[0] != OVERFLOW {
+            return Err(malformed());
+        }
+        let next = read_u32(data, 1);
+        pager.free(page)?;
+        freed += 1;
+        page = next;
+    }
+    Ok(())
+}
+
+/// The bytes of the row `payload`, read from its overflow pages if it has any.
+fn read_payload(pager: &mut Pager, payload: &Payload) -> Result<Vec<u8>> {
+    let (length, first) = match payload {
+        Payload::Local(bytes) => return Ok(bytes.clone()),
+        Payload::Overflow { length, first } => (*length as usize, *first),
+    };
+    let mut bytes = Vec::with_capacity(length);
+    let mut page = first;
+    while bytes.len() < length {
+        let data = pager.read(page)?;
+        let chunk = read_u32(data, 5) as usize;
+        if data[0] != OVERFLOW || chunk == 0 || chunk > PAGE_SIZE - OVERFLOW_HEADER {
+            return Err(malformed());
+        }
+        bytes.extend_from_slice(&data[OVERFLOW_HEADER..OVERFLOW_HEADER + chunk]);
+        page = read_u32(data, 1);
+        if page == 0 && bytes.len() < length {
+            return Err(malformed());
+        }
+    }
+    Ok(bytes)
+}
+
+impl Cell {
+    /// Bytes of the cell in its leaf.
+    fn size(&self) -> usize {
+        12 + match &self.payload {
+            Payload::Local(bytes) => bytes.len(),
+            Payload::Overflow { .. } => 4,
+        }
+    }
+}
+
+impl Node {
+    /// Bytes of the page once encoded, which may be more than fit in one.
+    fn size(&self) -> usize {
+        NODE_HEADER
+            + match self {
+                Node::Leaf { cells, .. } => cells.iter().map(Cell::size).sum::<usize>(),
+                Node::Interior { entries, .. } => entries.len() * ENTRY_SIZE,
+            }
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        let mut data = Vec::with_capacity(self.size());
+        match self {
+            Node::Leaf { cells, next } => {
+                data.push(LEAF);
+                data.extend_from_slice(&(cells.len() as u16).to_be_bytes());
+                data.extend_from_slice(&next.to_be_bytes());
+                for cell in cells {
+                    data.extend_from_slice(&cell.rowid.to_be_bytes());
+                    match &cell.payload {
+                        Payload::Local(bytes) => {
+                            data.extend_from_slice(&(bytes.len() as u32).to_be_bytes());
+                            data.extend_from_slice(bytes);
+                        }
+                        Payload::Overflow { length, first } => {
+                            data.extend_from_slice(&length.to_be_bytes());
+                            data.extend_from_slice(&first.to_be_bytes());
+                        }
+                    }
+                }
+            }
+            Node::Interior { entries, right } => {
+                data.push(INTERIOR);
+                data.extend_from_slice(&(entries.len() as u16).to_be_bytes());
+                data.extend_from_slice(&right.to_be_bytes());
+                for (child, key) in entries {
+                    data.extend_from_slice(&child.to_be_bytes());
+                    data.extend_from_slice(&key.to_be_bytes());
+                }
+            }
+        }
+        data
+    }
+
+    fn decode(data: &[u8]) -> Result<Node> {
+        let count = u16::from_be_bytes([data[1], data[2]]) as usize;
+        let pointer = read_u32(data, 3);
+        let mut offset = NODE_HEADER;
+        // Reads the next `n` bytes of the page
+        let mut take = |n: usize| -> Result<&[u8]> {
+            let bytes = data.get(offset..offset + n).ok_or_else(malformed)?;
+            offset += n;
+            Ok(bytes)
+        };
+        match data[0] {
+            LEAF => {
+                let mut cells = Vec::with_capacity(count);
+                for _ in 0..count {
+                    let rowid = i64::from_be_bytes(take(8)?.try_into().unwrap());
+                    let length = read_u32(take(4)?, 0);
+                    let payload = match length as usize {
+                        length if length > MAX_LOCAL => Payload::Overflow {
+                            length: length as u32,
+                            first: read_u32(take(4)?, 0),
+                        },
+                        length => Payload::Local(take(length)?.to_vec()),
+                    };
+                    cells.push(Cell { rowid, payload });
+                }
+                Ok(Node::Leaf {
+                    cells,
+                    next: pointer,
+                })
+            }
+            INTERIOR => {
+                let mut entries = Vec::with_capacity(count);
+                for _ in 0..count {
+                    let child = read_u32(take(4)?, 0);
+                    let key = i64::from_be_bytes(take(8)?.try_into().unwrap());
+                    entries.push((child, key));
+                }
+                Ok(Node::Interior {
+                    entries,
+                    right: pointer,
+                })
+            }
+            _ => Err(malformed()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn btree_test() {
+        let mut pager = Pager::memory();
+        let tree = BTree::create(&mut pager).unwrap();
+        assert_eq!(tree.get(&mut pager, 1).unwrap(), None);
+
+        // Enough rows, in no order, for leaves and interior pages to be split
+        let rowids = (0..5000i64)
+            .map(|i| (i * 7919) % 5000)
+            .collect::<Vec<i64>>();
+        for rowid in &rowids {
+            let payload = format!("row {}", rowid).repeat(10);
+            tree.insert(&mut pager, *rowid, payload.as_bytes()).unwrap();
+        }
+        assert!(pager.page_count() > 100);
+        assert_eq!(tree.root(), 1);
+        assert_eq!(
+            tree.get(&mut pager, 4321).unwrap(),
+            Some("row 4321".repeat(10).into_bytes())
+        );
+        let rows = tree.scan(&mut pager, ..).unwrap();
+        assert_eq!(rows.len(), 5000);
+        assert!(rows.windows(2).all(|pair| pair[0].0 < pair[1].0));
+        let mut sorted = rowids.clone();
+        sorted.sort_unstable();
+        assert_eq!(tree.rowids(&mut pager).unwrap(), sorted);
+        let range = tree.scan(&mut pager, 100..=102).unwrap();
+        assert_eq!(
+            range.iter().map(|(rowid, _)| *rowid).collect::<Vec<i64>>(),
+            vec![100, 101, 102]
+        );
+        assert!(tree.scan(&mut pager, 6000..).unwrap().is_empty());
+
+        // Replacing a row, with one big enough to overflow
+        let big = vec![7u8; 3 * PAGE_SIZE];
+        tree.insert(&mut pager, 10, &big).unwrap();
+        assert_eq!(tree.get(&mut pager, 10).unwrap(), Some(big));
+        assert_eq!(tree.scan(&mut pager, ..).unwrap().len(), 5000);
+
+        assert!(tree.delete(&mut pager, 10).unwrap());
+        assert!(!tree.delete(&mut pager, 10).unwrap());
+        assert_eq!(tree.get(&mut pager, 10).unwrap(), None);
+        for rowid in 0..2000 {
+            tree.delete(&mut pager, rowid).unwrap();
+        }
+        let rows = tree.scan(&mut pager, ..).unwrap();
+        assert_eq!(rows.len(), 3000);
+        assert_eq!(rows[0].0, 2000);
+
+        // The tree reads the same from the pages flushed
+        pager.flush().unwrap();
+        let tree = BTree::open(tree.root());
+        assert_eq!(tree.scan(&mut pager, 4998..).unwrap().len(), 2);
+
+        // The pages freed by the deletes are reused before the database grows
+        assert!(pager.free_page_count() > 10);
+        let page_count = pager.page_count();
+        for rowid in 0..500 {
+            let payload = format!("row {}", rowid).repeat(10);
+            tree.insert(&mut pager, rowid, payload.as_bytes()).unwrap();
+        }
+        assert_eq!(pager.page_count(), page_count);
+        assert_eq!(tree.scan(&mut pager, ..).unwrap().len(), 3500);
+
+        // Deleting every row leaves only the root, destroying the tree frees it too
+        for rowid in 0..5000 {
+            tree.delete(&mut pager, rowid).unwrap();
+        }
+        assert!(tree.scan(&mut pager, ..).unwrap().is_empty());
+        assert_eq!(pager.free_page_count(), pager.page_count() - 2);
+        tree.insert(&mut pager, 1, b"again").unwrap();
+        assert_eq!(tree.get(&mut pager, 1).unwrap(), Some(b"again".to_vec()));
+        tree.destroy(&mut pager).unwrap();
+        assert_eq!(pager.free_page_count(), pager.page_count() - 1);
+    }
+}
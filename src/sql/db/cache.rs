@@ -0,0 +1,335 @@
+//! The result cache of a connection, off until it is given a capacity with
+//! `PRAGMA result_cache` or `Connection::set_result_cache`. The result of a SELECT is kept
+//! under its normalized text and its parameters, and a later run of the same SELECT is
+//! served from memory instead of being executed again, which pays off for read-mostly
+//! workloads polling the same queries, like dashboards.
+//!
+//! A result is only kept with the generation of each table the statement names, and is
+//! thrown away as soon as a statement writes to one of them or the schema changes. The
+//! statements whose result doesn't only depend on the rows of their tables aren't cached:
+//! those reading virtual tables or the tables of the database itself, and those calling
+//! functions that aren't deterministic, like `random()` or `changes()`.
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+use sqlparser::keywords::{RESERVED_FOR_COLUMN_ALIAS, RESERVED_FOR_TABLE_ALIAS};
+use sqlparser::tokenizer::Token;
+
+use crate::sql::db::database::Database;
+use crate::sql::db::schema::is_reserved_name;
+use crate::sql::db::table::Table;
+use crate::sql::executor::ResultSet;
+use crate::sql::function::connection;
+use crate::sql::params::quote_literal;
+use crate::sql::parser::virtual_table::is_keyword;
+use crate::sql::value::Value;
+
+/// Stamp of the rows of a table, a new one is taken each time they are written.
+#[derive(Debug, Clone, Copy)]
+pub struct Generation(u64);
+
+impl Generation {
+    /// Takes a stamp no table had before.
+    pub fn next() -> Generation {
+        static NEXT: AtomicU64 = AtomicU64::new(0);
+        Generation(NEXT.fetch_add(1, Ordering::Relaxed))
+    }
+}
+
+/// Generations are always equal, tables with the same rows are equal however they were
+/// written.
+impl PartialEq for Generation {
+    fn eq(&self, _: &Self) -> bool {
+        true
+    }
+}
+
+/// How the result cache of a connection was used since it was given its capacity
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ResultCacheStats {
+    /// Statements served from the cache
+    pub hits: u64,
+    /// Statements that could have been served from the cache, but were executed
+    pub misses: u64,
+    /// Results kept in the cache
+    pub entries: usize,
+}
+
+/// A result kept in the cache, with what it was computed from
+#[derive(Debug)]
+struct Entry {
+    /// Schema version of the database when the result was computed
+    schema_version: i64,
+    /// Tables named by the statement, with their generation
+    tables: Vec<(String, Generation)>,
+    result: ResultSet,
+    /// When the result was last used, the least recently used one is evicted first
+    used: u64,
+}
+
+impl Entry {
+    /// Returns true if the rows and the schema the result was computed from are unchanged.
+    fn is_current(&self, tables: &HashMap<String, Table>, schema_version: i64) -> bool {
+        self.schema_version == schema_version
+            && self.tables.iter().all(|(name, generation)| {
+                tables
+                    .get(name)
+                    .is_some_and(|table| table.generation().0 == generation.0)
+            })
+    }
+}
+
+#[derive(Debug, Default)]
+struct CacheState {
+    entries: HashMap<String, Entry>,
+    /// Incremented each time a result is used
+    clock: u64,
+    hits: u64,
+    misses: u64,
+}
+
+/// The results of the last SELECT statements run by a connection
+#[derive(Default)]
+pub struct ResultCache {
+    /// Results kept at most, the cache is off when it is 0
+    capacity: usize,
+    state: Mutex<CacheState>,
+}
+
+impl ResultCache {
+    /// Keeps at most `capacity` results, forgetting the ones kept so far and the
+    /// statistics. A capacity of 0 turns the cache off.
+    pub fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity;
+        *self.state() = CacheState::default();
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn stats(&self) -> ResultCacheStats {
+        let state = self.state();
+        ResultCacheStats {
+            hits: state.hits,
+            misses: state.misses,
+            entries: state.entries.len(),
+        }
+    }
+
+    /// Forgets the results kept, they are computed again when needed.
+    pub fn clear(&self) {
+        self.state().entries.clear();
+    }
+
+    /// Returns the result kept under `key` if the tables it was computed from are unchanged.
+    /// A result computed from tables changed since is forgotten.
+    pub fn get(&self, key: &str, db: &Database) -> Option<ResultSet> {
+        let mut state = self.state();
+        state.clock += 1;
+        let clock = state.clock;
+        let result = match state.entries.get_mut(key) {
+            Some(entry) if entry.is_current(&db.tables, db.schema_version) => {
+                entry.used = clock;
+                Some(entry.result.clone())
+            }
+            Some(_) => {
+                state.entries.remove(key);
+                None
+            }
+            None => None,
+        };
+        match result {
+            Some(_) => state.hits += 1,
+            None => state.misses += 1,
+        }
+        result
+    }
+
+    /// Keeps `result` under `key`, computed from the current rows of `tables`, evicting the
+    /// least recently used result if the cache is full.
+    pub fn insert(&self, key: String, tables: Vec<String>, result: ResultSet, db: &Database) {
+        if self.capacity == 0 {
+            return;
+        }
+        let tables = tables
+            .into_iter()
+            .filter_map(|name| {
+                let generation = db.tables.get(&name)?.generation();
+                Some((name, generation))
+            })
+            .collect();
+        let mut state = self.state();
+        if !state.entries.contains_key(&key) && state.entries.len() >= self.capacity {
+            let oldest = state
+                .entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.used)
+                .map(|(key, _)| key.clone());
+            if let Some(oldest) = oldest {
+                state.entries.remove(&oldest);
+            }
+        }
+        state.clock += 1;
+        let entry = Entry {
+            schema_version: db.schema_version,
+            tables,
+            result,
+            used: state.clock,
+        };
+        state.entries.insert(key, entry);
+    }
+
+    /// Forgets the results computed from tables that changed since, or from another schema.
+    pub fn evict_stale(&self, tables: &HashMap<String, Table>, schema_version: i64) {
+        self.state()
+            .entries
+            .retain(|_, entry| entry.is_current(tables, schema_version));
+    }
+
+    fn state(&self) -> std::sync::MutexGuard<'_, CacheState> {
+        self.state.lock().unwrap_or_else(|err| err.into_inner())
+    }
+}
+
+impl fmt::Debug for ResultCache {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ResultCache")
+            .field("capacity", &self.capacity)
+            .field("stats", &self.stats())
+            .finish()
+    }
+}
+
+/// Result caches are always equal, they belong to the connection, not to the database.
+impl PartialEq for ResultCache {
+    fn eq(&self, _: &Self) -> bool {
+        true
+    }
+}
+
+/// The key the result of the statement made of `tokens` is kept under once `params` are
+/// bound to it: its text with the comments dropped, each run of whitespace made a single
+/// space and the reserved keywords uppercased, followed by the parameters. Other words are
+/// kept as they are, they may be names.
+pub fn cache_key(tokens: &[Token], params: &[Value]) -> String {
+    let mut key = String::new();
+    for token in tokens {
+        match token {
+            Token::Whitespace(_) => {
+                if !key.is_empty() && !key.ends_with(' ') {
+                    key.push(' ');
+                }
+            }
+            Token::SemiColon => {}
+            Token::Word(word)
+                if word.quote_style.is_none()
+                    && (RESERVED_FOR_TABLE_ALIAS.contains(&word.keyword)
+                        || RESERVED_FOR_COLUMN_ALIAS.contains(&word.keyword)) =>
+            {
+                key.push_str(&word.value.to_uppercase())
+            }
+            token => key.push_str(&token.to_string()),
+        }
+    }
+    let mut key = key.trim_end().to_string();
+    for param in params {
+        key.push('\0');
+        key.push_str(&quote_literal(param));
+    }
+    key
+}
+
+/// The tables of `db` named by the statement made of `tokens`, whose changes make its
+/// result stale, or `None` if its result can't be cached: it isn't a SELECT, or it reads
+/// something else than the rows of its tables.
+pub fn cached_tables(tokens: &[Token], db: &Database) -> Option<Vec<String>> {
+    let tokens = tokens
+        .iter()
+        .filter(|token| !matches!(token, Token::Whitespace(_)))
+        .collect::<Vec<&Token>>();
+    if !is_keyword(tokens.first().copied(), "SELECT") {
+        return None;
+    }
+    let mut tables = vec![];
+    let mut i = 0;
+    while i < tokens.len() {
+        let mut name = match tokens[i] {
+            Token::Word(word) => word.value.clone(),
+            _ => {
+                i += 1;
+                continue;
+            }
+        };
+        i += 1;
+        // A table of an attached database is named `schema.table`
+        if let (Some(Token::Period), Some(Token::Word(word))) = (tokens.get(i), tokens.get(i + 1)) {
+            name = format!("{}.{}", name, word.value);
+            i += 2;
+        }
+        if let Some(Token::LParen) = tokens.get(i) {
+            let random = db
+                .functions
+                .get(&name)
+                .is_some_and(|function| !function.deterministic);
+            if random
+                || connection::call(db, &name, &[]).is_some()
+                || db.vtabs.contains_module(&name)
+            {
+                return None;
+            }
+            continue;
+        }
+        if is_reserved_name(&name) || db.vtabs.get_table(&name).is_some() {
+            return None;
+        }
+        if let Ok(key) = db.resolve_table_name(&name) {
+            if db.tables.contains_key(&key) && !tables.contains(&key) {
+                tables.push(key);
+            }
+        }
+    }
+    Some(tables)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sql::tokenize;
+
+    #[test]
+    fn cache_key_test() {
+        let key = |sql: &str, params: &[Value]| cache_key(&tokenize(sql).unwrap(), params);
+        assert_eq!(
+            key(
+                "select  name -- the name\n from users\n\twhere id = ?;",
+                &[Value::Integer(1)]
+            ),
+            "SELECT name FROM users WHERE id = ?\u{0}1"
+        );
+        assert_eq!(
+            key("SELECT name FROM users WHERE id = ?", &[Value::Integer(1)]),
+            key(
+                "select name from users /* by id */ where id = ?;",
+                &[Value::Integer(1)]
+            )
+        );
+        assert_ne!(
+            key("SELECT Name FROM users;", &[]),
+            key("SELECT name FROM users;", &[])
+        );
+        assert_ne!(
+            key("SELECT * FROM users WHERE name = 'a  b';", &[]),
+            key("SELECT * FROM users WHERE name = 'a b';", &[])
+        );
+        assert_ne!(
+            key("SELECT * FROM users WHERE id = ?;", &[Value::Integer(1)]),
+            key(
+                "SELECT * FROM users WHERE id = ?;",
+                &[Value::Text("1".to_string())]
+            )
+        );
+    }
+}
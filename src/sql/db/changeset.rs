@@ -0,0 +1,304 @@
+//! Sessions recording the changes made to the rows of tables into a changeset, modeled on
+//! SQLite's session extension. A changeset can be inspected, serialized to be shipped to
+//! another device, inverted to undo it, and applied to another database with the same
+//! tables, which makes it the primitive needed to sync databases offline.
+//!
+//! Changes are recorded once the statement making them succeeded, with the changes of the
+//! triggers it fired, and consolidated per row: a row inserted then updated is a single
+//! insert of its last values, a row inserted then deleted isn't in the changeset at all.
+//! Changes to virtual tables aren't recorded, their data belongs to their module.
+use serde::{Deserialize, Serialize};
+
+use crate::error::{Result, SQLRiteError};
+use crate::sql::db::audit::AuditOperation;
+use crate::sql::db::database::Database;
+use crate::sql::executor::trigger::in_transaction;
+use crate::sql::value::Value;
+
+/// A change made to a row of a table
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub struct Change {
+    pub operation: AuditOperation,
+    pub table_name: String,
+    pub rowid: i64,
+    /// Columns the values are given for
+    pub columns: Vec<String>,
+    /// Values of the row before the change, `None` for inserts
+    pub old: Option<Vec<Value>>,
+    /// Values of the row after the change, `None` for deletes
+    pub new: Option<Vec<Value>>,
+}
+
+impl Change {
+    /// The change undoing this one.
+    pub fn invert(&self) -> Change {
+        let operation = match self.operation {
+            AuditOperation::Insert => AuditOperation::Delete,
+            AuditOperation::Update => AuditOperation::Update,
+            AuditOperation::Delete => AuditOperation::Insert,
+        };
+        Change {
+            operation,
+            table_name: self.table_name.to_string(),
+            rowid: self.rowid,
+            columns: self.columns.clone(),
+            old: self.new.clone(),
+            new: self.old.clone(),
+        }
+    }
+}
+
+/// The changes made during a session, in the order they were made
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone, Default)]
+pub struct Changeset {
+    pub changes: Vec<Change>,
+}
+
+impl Changeset {
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.changes.len()
+    }
+
+    /// Adds `change`, consolidating it with the change already made to the same row.
+    pub fn push(&mut self, change: Change) {
+        let position = self
+            .changes
+            .iter()
+            .rposition(|c| c.table_name == change.table_name && c.rowid == change.rowid);
+        let previous = match position {
+            Some(position) => self.changes.remove(position),
+            None => return self.changes.push(change),
+        };
+        let (operation, old, new) = match (previous.operation, change.operation) {
+            (AuditOperation::Insert, AuditOperation::Update) => {
+                (AuditOperation::Insert, None, change.new)
+            }
+            (AuditOperation::Insert, AuditOperation::Delete) => return,
+            (AuditOperation::Update, AuditOperation::Update)
+            | (AuditOperation::Delete, AuditOperation::Insert) => {
+                if previous.old == change.new {
+                    return;
+                }
+                (AuditOperation::Update, previous.old, change.new)
+            }
+            (AuditOperation::Update, AuditOperation::Delete) => {
+                (AuditOperation::Delete, previous.old, None)
+            }
+            // A row can't be inserted twice, nor changed once deleted
+            _ => {
+                self.changes.push(previous);
+                return self.changes.push(change);
+            }
+        };
+        self.changes.push(Change {
+            operation,
+            old,
+            new,
+            ..change
+        });
+    }
+
+    /// The changeset undoing this one: its changes inverted, in reverse order.
+    pub fn invert(&self) -> Changeset {
+        Changeset {
+            changes: self.changes.iter().rev().map(Change::invert).collect(),
+        }
+    }
+
+    /// Serializes the changeset as JSON, to be shipped to another database.
+    pub fn to_json(&self) -> Result<String> {
+        serde_json::to_string(self).map_err(changeset_error)
+    }
+
+    /// Reads back a changeset serialized with `to_json`.
+    pub fn from_json(json: &str) -> Result<Changeset> {
+        serde_json::from_str(json).map_err(changeset_error)
+    }
+
+    /// Applies the changes to `db`, whose tables must have the rows as they were before
+    /// the changes. Either all of them are applied or, if a row was changed differently in
+    /// `db`, none of them are and the conflict is returned as an error. Triggers aren't
+    /// fired, the changeset already has the changes of the triggers of the other database.
+    pub fn apply(&self, db: &mut Database) -> Result<()> {
+        let result = in_transaction(db, |db| {
+            for change in &self.changes {
+                apply_change(db, change)?;
+            }
+            Ok(())
+        });
+        db.end_statement(result.is_ok())?;
+        result
+    }
+}
+
+fn apply_change(db: &mut Database, change: &Change) -> Result<()> {
+    let table = db
+        .tables
+        .get_mut(&change.table_name)
+        .ok_or_else(|| changeset_error(format!("no such table: {}", change.table_name)))?;
+    let current = if table.contains_rowid(change.rowid) {
+        Some(
+            change
+                .columns
+                .iter()
+                .map(|column| table.get_value(column, change.rowid))
+                .collect::<Result<Vec<Value>>>()?,
+        )
+    } else {
+        None
+    };
+    if current != change.old {
+        return Err(changeset_error(format!(
+            "conflict on {} of row {} in {}",
+            change.operation.as_str(),
+            change.rowid,
+            change.table_name
+        )));
+    }
+    db.faults.check(change.operation.as_str())?;
+    let table = db.tables.get_mut(&change.table_name).unwrap();
+    match &change.new {
+        Some(values) => table.write_row(change.rowid, &change.columns, values)?,
+        None => {
+            table.delete_row(change.rowid);
+        }
+    }
+    db.record_change(
+        change.operation,
+        &change.table_name,
+        change.rowid,
+        &change.columns,
+        change.old.as_deref(),
+        change.new.as_deref(),
+    );
+    Ok(())
+}
+
+fn changeset_error(err: impl std::fmt::Display) -> SQLRiteError {
+    SQLRiteError::General(format!("Changeset error: {}", err))
+}
+
+/// A session recording the changes made to a database, started with
+/// `Connection::start_session`
+#[derive(Debug, Default, PartialEq)]
+pub struct Session {
+    /// Changes of the statements that succeeded
+    pub changeset: Changeset,
+    /// Changes of the statement running, kept once it succeeds
+    pending: Vec<Change>,
+}
+
+impl Session {
+    /// Records a change to the row `rowid` of `table_name`. `old` and `new` are the values of
+    /// its `columns` before and after the change.
+    pub fn record(
+        &mut self,
+        operation: AuditOperation,
+        table_name: &str,
+        rowid: i64,
+        columns: &[String],
+        old: Option<&[Value]>,
+        new: Option<&[Value]>,
+    ) {
+        self.pending.push(Change {
+            operation,
+            table_name: table_name.to_string(),
+            rowid,
+            columns: columns.to_vec(),
+            old: old.map(|values| values.to_vec()),
+            new: new.map(|values| values.to_vec()),
+        });
+    }
+
+    /// Keeps the changes of the statement that just succeeded.
+    pub fn commit(&mut self) {
+        for change in std::mem::take(&mut self.pending) {
+            self.changeset.push(change);
+        }
+    }
+
+    /// Forgets the changes of the statement that just failed, which were undone.
+    pub fn discard(&mut self) {
+        self.pending.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sql::process_command;
+
+    fn database() -> Database {
+        let mut db = Database::new("tempdb".to_string());
+        process_command(
+            "CREATE TABLE notes (id INTEGER PRIMARY KEY, body TEXT UNIQUE);",
+            &mut db,
+        )
+        .unwrap();
+        db
+    }
+
+    fn change(operation: AuditOperation, old: Option<&str>, new: Option<&str>) -> Change {
+        let row = |body: &str| vec![Value::Integer(1), Value::Text(body.to_string())];
+        Change {
+            operation,
+            table_name: "notes".to_string(),
+            rowid: 1,
+            columns: vec!["id".to_string(), "body".to_string()],
+            old: old.map(row),
+            new: new.map(row),
+        }
+    }
+
+    #[test]
+    fn consolidate_test() {
+        let mut changeset = Changeset::default();
+        changeset.push(change(AuditOperation::Insert, None, Some("a")));
+        changeset.push(change(AuditOperation::Update, Some("a"), Some("b")));
+        assert_eq!(
+            changeset.changes,
+            vec![change(AuditOperation::Insert, None, Some("b"))]
+        );
+        changeset.push(change(AuditOperation::Delete, Some("b"), None));
+        assert!(changeset.is_empty());
+
+        changeset.push(change(AuditOperation::Delete, Some("a"), None));
+        changeset.push(change(AuditOperation::Insert, None, Some("c")));
+        assert_eq!(
+            changeset.changes,
+            vec![change(AuditOperation::Update, Some("a"), Some("c"))]
+        );
+        changeset.push(change(AuditOperation::Update, Some("c"), Some("a")));
+        assert!(changeset.is_empty());
+    }
+
+    #[test]
+    fn apply_and_invert_test() {
+        let mut db = database();
+        db.session = Some(Session::default());
+        process_command("INSERT INTO notes (body) VALUES ('first');", &mut db).unwrap();
+        process_command("INSERT INTO notes (body) VALUES ('second');", &mut db).unwrap();
+        assert!(process_command("INSERT INTO notes (body) VALUES ('first');", &mut db).is_err());
+        let changeset = db.session.take().unwrap().changeset;
+        assert_eq!(changeset.len(), 2);
+
+        // Shipped to another database with the same tables
+        let changeset = Changeset::from_json(&changeset.to_json().unwrap()).unwrap();
+        let mut other = database();
+        changeset.apply(&mut other).unwrap();
+        assert_eq!(
+            other.tables["notes"].get_value("body", 2).unwrap(),
+            Value::Text("second".to_string())
+        );
+        // The rows are already there
+        assert!(changeset.apply(&mut other).is_err());
+        assert_eq!(other.tables["notes"].rowids(), vec![1, 2]);
+
+        changeset.invert().apply(&mut other).unwrap();
+        assert!(other.tables["notes"].rowids().is_empty());
+    }
+}
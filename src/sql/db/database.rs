@@ -1,7 +1,36 @@
 use crate::error::{Result, SQLRiteError};
-use crate::sql::db::table::Table;
+use crate::sql::collation::CollationRegistry;
+use crate::sql::db::audit::{AuditLog, AuditOperation};
+use crate::sql::db::autosave::{Autosave, ChangeMark};
+use crate::sql::db::backup::{Backup, Backups};
+use crate::sql::db::cache::ResultCache;
+use crate::sql::db::changeset::Session;
+use crate::sql::db::fault::FaultInjector;
+use crate::sql::db::file::{read_database, recover, write_database, write_image};
+use crate::sql::db::format::{file_format, FileFormat, FORMAT_VERSION};
+use crate::sql::db::journal::JournalMode;
+use crate::sql::db::limits::{Limit, Limits};
+use crate::sql::db::memory::{cache_budget, MemoryAccountant};
+use crate::sql::db::pager::Pager;
+use crate::sql::db::privilege::{AccessControl, Privilege, PERMISSION_DENIED};
+use crate::sql::db::progress::Progress;
+use crate::sql::db::schema::{SchemaObject, SchemaObjectType};
+use crate::sql::db::snapshot_file::{is_snapshot_file, read_snapshot_file, write_snapshot_file};
+use crate::sql::db::sqlite_file::{is_sqlite_file, read_sqlite_file, write_sqlite_file};
+use crate::sql::db::stats::{analyze_table, TableStats};
+use crate::sql::db::table::{serialize_sorted, Table};
+use crate::sql::db::temp::TempStore;
+use crate::sql::db::transaction::Transaction;
+use crate::sql::db::trigger::Trigger;
+use crate::sql::executor::profile::Profiler;
+use crate::sql::function::FunctionRegistry;
+use crate::sql::value::Value;
+use crate::sql::vtab::VirtualTableRegistry;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::{Path, PathBuf};
 
 /// The database is represented by this structure.assert_eq!
 #[derive(Serialize, Deserialize, PartialEq, Debug)]
@@ -9,7 +38,184 @@ pub struct Database {
     /// Name of this database. (schema name, not filename)
     pub db_name: String,
     /// HashMap of tables in this database
+    #[serde(serialize_with = "serialize_sorted")]
     pub tables: HashMap<String, Table>,
+    /// The schema catalog queried as `sqlite_master`: every table, index and trigger with
+    /// the SQL it was created with, in the order they were created
+    #[serde(default)]
+    pub schema: Vec<SchemaObject>,
+    /// Triggers created with CREATE TRIGGER, in the order they were created
+    #[serde(default)]
+    pub triggers: Vec<Trigger>,
+    /// Statistics of the tables gathered by ANALYZE, by table name
+    #[serde(default)]
+    pub stats: BTreeMap<String, TableStats>,
+    /// Scalar functions registered by the application, callable from SQL expressions
+    #[serde(skip)]
+    pub functions: FunctionRegistry,
+    /// Collating sequences available to COLLATE clauses, including the ones registered by the application
+    #[serde(skip)]
+    pub collations: CollationRegistry,
+    /// Virtual table modules registered by the application and the virtual tables created with them
+    #[serde(skip)]
+    pub vtabs: VirtualTableRegistry,
+    /// Rowid and row counts of the statements run against this database since it was opened
+    #[serde(skip)]
+    pub counters: ChangeCounters,
+    /// Names of the triggers currently running, a trigger never fires itself recursively
+    #[serde(skip)]
+    pub running_triggers: Vec<String>,
+    /// Schema names of the databases attached with ATTACH DATABASE, in the order they were
+    /// attached. Their tables are kept with the others, named `schema.table`.
+    #[serde(skip)]
+    pub attached: Vec<String>,
+    /// The files the attached databases were read from, by schema name, which their tables
+    /// are saved back to. Databases attached as `:memory:` have none.
+    #[serde(skip)]
+    pub attached_files: BTreeMap<String, AttachedFile>,
+    /// Integer the application can store in the database, read and set with PRAGMA user_version
+    #[serde(default)]
+    pub user_version: i64,
+    /// Incremented each time the schema changes, read with PRAGMA schema_version
+    #[serde(default)]
+    pub schema_version: i64,
+    /// Audit settings of the database and the changes recorded in its `sqlrite_audit` table
+    #[serde(default)]
+    pub audit: AuditLog,
+    /// Session recording the changes made to rows into a changeset, if one was started
+    #[serde(skip)]
+    pub session: Option<Session>,
+    /// Users created with CREATE USER and the privileges granted to them
+    #[serde(default)]
+    pub access: AccessControl,
+    /// User the statements of the connection run as, whose privileges are checked.
+    /// `None` for the owner of the database, who may do anything.
+    #[serde(skip)]
+    pub current_user: Option<String>,
+    /// Settings of the connection changed with PRAGMA statements
+    #[serde(skip)]
+    pub settings: Settings,
+    /// Faults injected into the writes of rows, to test how statements fail
+    #[serde(skip)]
+    pub faults: FaultInjector,
+    /// Memory reserved by the statements running, and its limits
+    #[serde(skip)]
+    pub memory: MemoryAccountant,
+    /// Profiles of the last statements run while `PRAGMA profile` is on
+    #[serde(skip)]
+    pub profiler: Profiler,
+    /// Temporary files of the statements running, and where they are kept
+    #[serde(skip)]
+    pub temp: TempStore,
+    /// Limits on the size of the statements run, set with `Connection::set_limit`
+    #[serde(skip)]
+    pub limits: Limits,
+    /// Progress handler called as statements run, set with `Connection::progress_handler`
+    #[serde(skip)]
+    pub progress: Progress,
+    /// Results of the last SELECT statements, kept while `PRAGMA result_cache` is on
+    #[serde(skip)]
+    pub result_cache: ResultCache,
+    /// File the database was opened from with `.open`, which its changes are saved back to.
+    /// `None` for a transient in-memory database.
+    #[serde(skip)]
+    pub path: Option<PathBuf>,
+    /// When the changes are saved back to the file the database was opened from, set with
+    /// `.autosave`
+    #[serde(skip)]
+    pub autosave: Autosave,
+    /// Backups taken with `.backup` still being written, or done but not reported yet
+    #[serde(skip)]
+    pub backups: Backups,
+    /// The transaction open with BEGIN or SAVEPOINT, and its savepoints
+    #[serde(skip)]
+    pub transaction: Transaction,
+}
+
+/// What `Database::open_file` found opening a file
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Opened {
+    /// Whether the file existed, a new database being opened otherwise
+    pub existed: bool,
+    /// Number of pages rolled back from the journal of a save that was interrupted, if
+    /// the file had one
+    pub recovered: Option<usize>,
+    /// Version of the file format the file was written in, if it was older than the
+    /// current one and the file has been upgraded
+    pub upgraded: Option<u32>,
+}
+
+/// The file a database was attached from with ATTACH DATABASE
+#[derive(Debug, Clone, PartialEq)]
+pub struct AttachedFile {
+    /// File the tables of the attached database are saved back to
+    pub path: PathBuf,
+    /// User version of the attached database, kept in its file
+    pub user_version: i64,
+    /// Schema version of the attached database when it was read, kept in its file
+    pub schema_version: i64,
+}
+
+/// The settings of a connection that PRAGMA statements read and change.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Settings {
+    /// Whether foreign key constraints are enforced, off by default like in SQLite
+    pub foreign_keys: bool,
+    /// Suggested number of pages kept in memory, or KiB of memory if negative
+    pub cache_size: i64,
+    /// How the rollback journal of the database file is kept, always `memory` or `off` for
+    /// in-memory databases
+    pub journal_mode: String,
+    /// Bytes at the start of the database file memory-mapped while it is read and saved,
+    /// 0 to read it with system calls, the default
+    pub mmap_size: u64,
+    /// Whether the operators of each statement are profiled into `sqlrite_profile`
+    pub profile: bool,
+    /// Whether each statement is its own transaction, on by default. Set with `.autocommit`.
+    pub autocommit: bool,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            foreign_keys: false,
+            cache_size: -2000,
+            journal_mode: "memory".to_string(),
+            mmap_size: 0,
+            profile: false,
+            autocommit: true,
+        }
+    }
+}
+
+/// Schema name of the database opened first, which unqualified new tables are created in
+pub const MAIN_SCHEMA: &str = "main";
+
+/// Schema name of the temporary tables of the connection, created with CREATE TEMP TABLE,
+/// which are never saved
+pub const TEMP_SCHEMA: &str = "temp";
+
+/// How many databases can be attached at the same time, SQLITE_MAX_ATTACHED
+pub const MAX_ATTACHED: usize = 10;
+
+/// The counters SQLite keeps for each connection, read by the `last_insert_rowid()`,
+/// `changes()` and `total_changes()` SQL functions.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct ChangeCounters {
+    /// Rowid of the last row inserted into a table
+    pub last_insert_rowid: i64,
+    /// Number of rows inserted, updated or deleted by the last statement doing so
+    pub changes: i64,
+    /// Number of rows inserted, updated or deleted since the database was opened
+    pub total_changes: i64,
+}
+
+impl ChangeCounters {
+    /// Records that a statement inserted, updated or deleted `changes` rows.
+    pub fn record(&mut self, changes: i64) {
+        self.changes = changes;
+        self.total_changes += changes;
+    }
 }
 
 impl Database {
@@ -18,12 +224,40 @@ impl Database {
     /// # Examples
     ///
     /// ```
-    /// let mut db = sql::db::database::Database::new("my_db".to_string());
+    /// let mut db = sqlrite::sql::db::database::Database::new("my_db".to_string());
     /// ```
     pub fn new(db_name: String) -> Self {
         Database {
             db_name,
             tables: HashMap::new(),
+            schema: vec![],
+            triggers: vec![],
+            stats: BTreeMap::new(),
+            functions: FunctionRegistry::new(),
+            collations: CollationRegistry::new(),
+            vtabs: VirtualTableRegistry::new(),
+            counters: ChangeCounters::default(),
+            running_triggers: vec![],
+            attached: vec![],
+            attached_files: BTreeMap::new(),
+            user_version: 0,
+            schema_version: 0,
+            audit: AuditLog::default(),
+            session: None,
+            access: AccessControl::default(),
+            current_user: None,
+            settings: Settings::default(),
+            faults: FaultInjector::default(),
+            memory: MemoryAccountant::default(),
+            profiler: Profiler::default(),
+            temp: TempStore::default(),
+            limits: Limits::default(),
+            progress: Progress::default(),
+            result_cache: ResultCache::default(),
+            path: None,
+            autosave: Autosave::default(),
+            backups: Backups::default(),
+            transaction: Transaction::default(),
         }
     }
 
@@ -33,6 +267,415 @@ impl Database {
         self.tables.contains_key(&table_name)
     }
 
+    /// Returns true if the name is already taken by a table or a virtual table.
+    ///
+    pub fn contains_table_name(&self, table_name: &str) -> bool {
+        self.tables.contains_key(table_name) || self.vtabs.contains_table(table_name)
+    }
+
+    /// Attaches the database in the file `filename` under `schema_name`, or a new empty
+    /// database if there is no such file, written once the database is saved. Its tables
+    /// are saved back to the file along with the main database, and when it is detached.
+    /// A `:memory:` or empty filename attaches a new in-memory database, like in SQLite.
+    /// Like in SQLite too, no database is attached while a transaction is open.
+    ///
+    pub fn attach(&mut self, filename: &str, schema_name: &str) -> Result<()> {
+        if self.transaction.is_open() {
+            return Err(SQLRiteError::General(
+                "cannot ATTACH database within transaction".to_string(),
+            ));
+        }
+        if schema_name.eq_ignore_ascii_case(MAIN_SCHEMA)
+            || schema_name.eq_ignore_ascii_case("temp")
+            || self.schema_name(schema_name).is_some()
+        {
+            return Err(SQLRiteError::General(format!(
+                "database {} is already in use",
+                schema_name
+            )));
+        }
+        if self.attached.len() >= self.limits.get(Limit::Attached) {
+            return Err(SQLRiteError::General(format!(
+                "too many attached databases - max {}",
+                self.limits.get(Limit::Attached)
+            )));
+        }
+        if !filename.is_empty() && filename != ":memory:" {
+            self.attach_file(Path::new(filename), schema_name)?;
+        }
+        self.attached.push(schema_name.to_string());
+        Ok(())
+    }
+
+    /// Reads the database in the file `path` into this one, its tables, indexes, triggers
+    /// and statistics named after `schema_name` like the ones created in it.
+    fn attach_file(&mut self, path: &Path, schema_name: &str) -> Result<()> {
+        let same_file = |other: &Path| {
+            other == path
+                || matches!(
+                    (other.canonicalize(), path.canonicalize()),
+                    (Ok(other), Ok(path)) if other == path
+                )
+        };
+        if self.path.as_deref().is_some_and(same_file)
+            || self
+                .attached_files
+                .values()
+                .any(|file| same_file(&file.path))
+        {
+            return Err(SQLRiteError::General(format!(
+                "database {} is already attached",
+                path.display()
+            )));
+        }
+        let attached = match path.exists() {
+            true => {
+                recover(path)?;
+                Database::read_file_mapped(path, self.settings.mmap_size)?
+            }
+            false => Database::new(schema_name.to_string()),
+        };
+        let prefixed = |name: &str| format!("{}.{}", schema_name, name);
+        if let Some(trigger) = attached
+            .triggers
+            .iter()
+            .find(|trigger| self.triggers.iter().any(|other| other.name == trigger.name))
+        {
+            return Err(SQLRiteError::General(format!(
+                "trigger {} already exists",
+                trigger.name
+            )));
+        }
+        for (name, mut table) in attached.tables {
+            table.tb_name = prefixed(&name);
+            self.tables.insert(prefixed(&name), table);
+        }
+        for mut object in attached.schema {
+            if object.object_type != SchemaObjectType::Trigger {
+                object.name = prefixed(&object.name);
+            }
+            object.tbl_name = prefixed(&object.tbl_name);
+            self.schema.push(object);
+        }
+        for mut trigger in attached.triggers {
+            trigger.table_name = prefixed(&trigger.table_name);
+            self.triggers.push(trigger);
+        }
+        for (name, stats) in attached.stats {
+            self.stats.insert(prefixed(&name), stats);
+        }
+        self.attached_files.insert(
+            schema_name.to_string(),
+            AttachedFile {
+                path: path.to_path_buf(),
+                user_version: attached.user_version,
+                schema_version: attached.schema_version,
+            },
+        );
+        self.schema_version += 1;
+        Ok(())
+    }
+
+    /// Detaches the database attached under `schema_name`, dropping its tables and the
+    /// triggers on them. A database attached from a file is saved back to it first. Not
+    /// while a transaction is open, like `attach`.
+    ///
+    pub fn detach(&mut self, schema_name: &str) -> Result<()> {
+        if self.transaction.is_open() {
+            return Err(SQLRiteError::General(
+                "cannot DETACH database within transaction".to_string(),
+            ));
+        }
+        let schema_name = match self.schema_name(schema_name) {
+            Some(TEMP_SCHEMA) => {
+                return Err(SQLRiteError::General(format!(
+                    "cannot detach database {}",
+                    schema_name
+                )))
+            }
+            Some(name) => name.to_string(),
+            None if schema_name.eq_ignore_ascii_case(MAIN_SCHEMA) => {
+                return Err(SQLRiteError::General(format!(
+                    "cannot detach database {}",
+                    schema_name
+                )))
+            }
+            None => {
+                return Err(SQLRiteError::General(format!(
+                    "no such database: {}",
+                    schema_name
+                )))
+            }
+        };
+        if let Some(file) = self.attached_files.get(&schema_name) {
+            self.save_file(self.attached_copy(&schema_name)?, &file.path, "delete")?;
+        }
+        self.attached_files.remove(&schema_name);
+        self.drop_schema(&schema_name);
+        Ok(())
+    }
+
+    /// Drops the tables of the database attached under `schema_name`, and everything on them.
+    fn drop_schema(&mut self, schema_name: &str) {
+        let prefix = format!("{}.", schema_name);
+        self.tables.retain(|name, _| !name.starts_with(&prefix));
+        self.schema
+            .retain(|object| !object.tbl_name.starts_with(&prefix));
+        self.triggers
+            .retain(|trigger| !trigger.table_name.starts_with(&prefix));
+        self.stats.retain(|name, _| !name.starts_with(&prefix));
+        for name in self.vtabs.table_names() {
+            if name.starts_with(&prefix) {
+                self.vtabs.drop_table(&name);
+            }
+        }
+        self.attached.retain(|name| name != schema_name);
+    }
+
+    /// Adds the table `table`, created with the CREATE TABLE statement `sql`, to the
+    /// database, listing it in the schema catalog with its automatic indexes.
+    pub fn add_table(&mut self, table: Table, sql: &str) {
+        let table_name = table.tb_name.to_string();
+        self.schema.push(SchemaObject::new(
+            SchemaObjectType::Table,
+            &table_name,
+            &table_name,
+            Some(sql),
+        ));
+        for (index_name, _) in table.autoindexes() {
+            let index_name = match table_name.split_once('.') {
+                Some((schema, _)) => format!("{}.{}", schema, index_name),
+                None => index_name,
+            };
+            self.schema.push(SchemaObject::new(
+                SchemaObjectType::Index,
+                &index_name,
+                &table_name,
+                None,
+            ));
+        }
+        self.tables.insert(table_name, table);
+        self.schema_version += 1;
+    }
+
+    /// Returns true if `schema_name` is the main database or an attached one.
+    ///
+    pub fn contains_schema(&self, schema_name: &str) -> bool {
+        schema_name.eq_ignore_ascii_case(MAIN_SCHEMA) || self.schema_name(schema_name).is_some()
+    }
+
+    /// Returns the name `schema_name` was attached under, or `temp` for the temp schema,
+    /// schema names aren't case sensitive.
+    ///
+    fn schema_name(&self, schema_name: &str) -> Option<&str> {
+        if schema_name.eq_ignore_ascii_case(TEMP_SCHEMA) {
+            return Some(TEMP_SCHEMA);
+        }
+        self.attached
+            .iter()
+            .find(|name| name.eq_ignore_ascii_case(schema_name))
+            .map(|name| name.as_str())
+    }
+
+    /// Returns the name a new table named `table_name`, optionally qualified with a schema
+    /// name, is stored under: unqualified and `main.` names are in the main database,
+    /// names qualified with an attached schema keep it as `schema.table`.
+    ///
+    pub fn table_key(&self, table_name: &str) -> Result<String> {
+        match table_name.split_once('.') {
+            None => Ok(table_name.to_string()),
+            Some((schema, name)) if schema.eq_ignore_ascii_case(MAIN_SCHEMA) => {
+                Ok(name.to_string())
+            }
+            Some((schema, name)) => match self.schema_name(schema) {
+                Some(schema) => Ok(format!("{}.{}", schema, name)),
+                None => Err(SQLRiteError::General(format!(
+                    "unknown database {}",
+                    schema
+                ))),
+            },
+        }
+    }
+
+    /// Returns the name the existing table named `table_name` is stored under. Like in
+    /// SQLite, an unqualified name refers to the temporary table if there is one, then to
+    /// the table of the main database, otherwise to the table of the first attached
+    /// database having it.
+    ///
+    pub fn resolve_table_name(&self, table_name: &str) -> Result<String> {
+        if table_name.contains('.') {
+            return self.table_key(table_name);
+        }
+        let temp_name = format!("{}.{}", TEMP_SCHEMA, table_name);
+        if self.contains_table_name(&temp_name) {
+            return Ok(temp_name);
+        }
+        if self.contains_table_name(table_name) {
+            return Ok(table_name.to_string());
+        }
+        Ok(self
+            .attached
+            .iter()
+            .map(|schema| format!("{}.{}", schema, table_name))
+            .find(|key| self.contains_table_name(key))
+            .unwrap_or_else(|| table_name.to_string()))
+    }
+
+    /// Gathers the statistics of the tables named by `target` for the planner: a table,
+    /// every table of an attached database or of `main`, or every table when `None`.
+    ///
+    pub fn analyze(&mut self, target: Option<&str>) -> Result<()> {
+        let mut names = match target {
+            None => self.tables.keys().cloned().collect::<Vec<String>>(),
+            Some(target) if self.contains_schema(target) => {
+                let prefix = self
+                    .schema_name(target)
+                    .map(|schema_name| format!("{}.", schema_name));
+                self.tables
+                    .keys()
+                    .filter(|name| match &prefix {
+                        Some(prefix) => name.starts_with(prefix),
+                        None => !name.contains('.'),
+                    })
+                    .cloned()
+                    .collect()
+            }
+            Some(target) => {
+                let name = self.resolve_table_name(target)?;
+                if !self.tables.contains_key(&name) {
+                    return Err(SQLRiteError::General(format!("no such table: {}", target)));
+                }
+                vec![name]
+            }
+        };
+        names.sort();
+        for name in names {
+            // The statistics tell the values of the table
+            self.check_privilege(Privilege::Select, Some(&name))?;
+            let stats = analyze_table(&self.tables[&name], &self.collations)?;
+            self.stats.insert(name, stats);
+        }
+        Ok(())
+    }
+
+    /// Checks that the indexes of every table are in step with its rows, returning a
+    /// description of each problem found, none if the database is sound.
+    pub fn check_integrity(&self) -> Vec<String> {
+        let mut names = self.tables.keys().collect::<Vec<&String>>();
+        names.sort();
+        names
+            .into_iter()
+            .flat_map(|name| self.tables[name].check_integrity())
+            .collect()
+    }
+
+    /// Returns true if the changes made to rows are recorded, in the audit log or a session.
+    pub fn records_changes(&self) -> bool {
+        self.audit.is_enabled() || self.session.is_some()
+    }
+
+    /// Records a change to the row `rowid` of `table_name` in the audit log and the
+    /// session. `old` and `new` are the values of its `columns` before and after the change.
+    pub fn record_change(
+        &mut self,
+        operation: AuditOperation,
+        table_name: &str,
+        rowid: i64,
+        columns: &[String],
+        old: Option<&[Value]>,
+        new: Option<&[Value]>,
+    ) {
+        self.audit
+            .record(operation, table_name, rowid, columns, old, new);
+        if let Some(session) = &mut self.session {
+            session.record(operation, table_name, rowid, columns, old, new);
+        }
+    }
+
+    /// Keeps the changes recorded for the statement that just ran if it succeeded, and
+    /// forgets them if it failed and its changes were undone. The caches are released if
+    /// they grew past `PRAGMA cache_size`, or a statement went past the soft heap limit,
+    /// the temporary files the statement left behind are removed, and the results cached
+    /// from the tables the statement changed are forgotten.
+    pub fn end_statement(&mut self, succeeded: bool) -> Result<()> {
+        self.temp.end_statement();
+        self.result_cache
+            .evict_stale(&self.tables, self.schema_version);
+        if self.memory.take_soft_limit_exceeded()
+            || self.cache_memory() > cache_budget(self.settings.cache_size)
+        {
+            self.release_caches();
+        }
+        if let Some(session) = &mut self.session {
+            if succeeded {
+                session.commit();
+            } else {
+                session.discard();
+            }
+        }
+        if succeeded {
+            self.audit.commit()
+        } else {
+            self.audit.discard();
+            Ok(())
+        }
+    }
+
+    /// Bytes of memory taken by the caches of the tables.
+    pub fn cache_memory(&self) -> usize {
+        self.tables.values().map(Table::cache_memory).sum()
+    }
+
+    /// Releases the memory of the caches of the tables and of the cached results, they are
+    /// rebuilt when needed.
+    pub fn release_caches(&mut self) {
+        self.result_cache.clear();
+        for table in self.tables.values_mut() {
+            table.release_caches();
+        }
+    }
+
+    /// Turns the profiling of statements on or off. Turning it on starts over, forgetting
+    /// the statements profiled before.
+    pub fn set_profile(&mut self, enabled: bool) {
+        if enabled && !self.settings.profile {
+            self.profiler.clear();
+        }
+        self.settings.profile = enabled;
+    }
+
+    /// Fails unless the current user may use `privilege` on `table_name`, or on the whole
+    /// database when it is `None`. The statements of triggers aren't checked.
+    pub fn check_privilege(&self, privilege: Privilege, table_name: Option<&str>) -> Result<()> {
+        match &self.current_user {
+            Some(user)
+                if self.running_triggers.is_empty()
+                    && !self.access.allows(user, privilege, table_name) =>
+            {
+                Err(SQLRiteError::General(format!(
+                    "{}: {} needs the {} privilege on {}",
+                    PERMISSION_DENIED,
+                    user,
+                    privilege.as_str(),
+                    table_name.unwrap_or("the database")
+                )))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Fails unless the statements run as the owner of the database, the only one who can
+    /// manage users and privileges.
+    pub fn check_owner(&self) -> Result<()> {
+        match &self.current_user {
+            Some(user) => Err(SQLRiteError::General(format!(
+                "{}: {} can't manage users and privileges",
+                PERMISSION_DENIED, user
+            ))),
+            None => Ok(()),
+        }
+    }
+
     /// Returns an immutable reference of `sql::db::table::Table` if the database contains a
     /// table with the specified key as a table name.
     ///
@@ -54,6 +697,548 @@ impl Database {
             Err(SQLRiteError::General(String::from("Table not found.")))
         }
     }
+
+    /// Returns a copy of the data of the database: its tables, schema and versions.
+    /// What is registered on the connection, like functions and virtual tables, isn't copied.
+    pub fn snapshot(&self) -> Result<Database> {
+        let json = serde_json::to_string(self)
+            .map_err(|err| SQLRiteError::Internal(format!("{}", err)))?;
+        serde_json::from_str(&json).map_err(|err| SQLRiteError::Internal(format!("{}", err)))
+    }
+
+    /// Writes a copy of the main database to the new file `path`, like `VACUUM INTO` in
+    /// SQLite: its schema and rows, with the indexes rebuilt from the rows and without the
+    /// caches, in a database file without free pages, as `save` writes it to a new file. The
+    /// attached databases aren't part of the copy, nor is what is registered on the
+    /// connection. Fails if `path` already exists.
+    pub fn vacuum_into(&self, path: &Path) -> Result<()> {
+        write_new_file(path, &self.serialize_compact()?)
+    }
+
+    /// Serializes a copy of the main database the way `vacuum_into` writes it to a file.
+    /// The copy is taken at once, so the bytes can be written out while the database keeps
+    /// changing.
+    pub fn serialize_compact(&self) -> Result<Vec<u8>> {
+        write_image(self.compact_copy()?)
+    }
+
+    /// A copy of the main database as it is written to a file: its schema and rows, with
+    /// the indexes rebuilt from the rows and without the caches, nor the temporary tables.
+    fn compact_copy(&self) -> Result<Database> {
+        let mut copy = self.snapshot()?;
+        for schema_name in self
+            .attached
+            .iter()
+            .map(String::as_str)
+            .chain([TEMP_SCHEMA])
+        {
+            copy.drop_schema(schema_name);
+        }
+        for (name, table) in copy.tables.iter_mut() {
+            self.check_privilege(Privilege::Select, Some(name))?;
+            table.rebuild_indexes(&self.collations)?;
+            table.release_caches();
+        }
+        Ok(copy)
+    }
+
+    /// A copy of the database attached under `schema_name` as it is written to its file,
+    /// like `compact_copy`: its tables named without the schema name, with the versions it
+    /// was read with, and without the audit log of the main database.
+    fn attached_copy(&self, schema_name: &str) -> Result<Database> {
+        let prefix = format!("{}.", schema_name);
+        let unprefixed = |name: &str| name.strip_prefix(&prefix).map(str::to_string);
+        let mut copy = self.snapshot()?;
+        copy.tables = std::mem::take(&mut copy.tables)
+            .into_iter()
+            .filter_map(|(name, mut table)| {
+                let name = unprefixed(&name)?;
+                table.tb_name = name.to_string();
+                Some((name, table))
+            })
+            .collect();
+        copy.schema = std::mem::take(&mut copy.schema)
+            .into_iter()
+            .filter_map(|mut object| {
+                object.tbl_name = unprefixed(&object.tbl_name)?;
+                object.name = unprefixed(&object.name).unwrap_or(object.name);
+                object.sql = object.sql.map(|sql| unqualified_sql(&sql, schema_name));
+                Some(object)
+            })
+            .collect();
+        copy.triggers = std::mem::take(&mut copy.triggers)
+            .into_iter()
+            .filter_map(|mut trigger| {
+                trigger.table_name = unprefixed(&trigger.table_name)?;
+                Some(trigger)
+            })
+            .collect();
+        copy.stats = std::mem::take(&mut copy.stats)
+            .into_iter()
+            .filter_map(|(name, stats)| Some((unprefixed(&name)?, stats)))
+            .collect();
+        copy.audit = AuditLog::default();
+        copy.access = AccessControl::default();
+        if let Some(file) = self.attached_files.get(schema_name) {
+            copy.user_version = file.user_version;
+            copy.schema_version = file.schema_version;
+        }
+        for (name, table) in copy.tables.iter_mut() {
+            self.check_privilege(Privilege::Select, Some(&format!("{}{}", prefix, name)))?;
+            table.rebuild_indexes(&self.collations)?;
+            table.release_caches();
+        }
+        Ok(copy)
+    }
+
+    /// Reads the database in the file `path`, a database file written by `save` or
+    /// `vacuum_into`, a SQLite database file, a JSON copy written by `vacuum_into` before
+    /// it wrote database files, or a snapshot written by `save_snapshot`.
+    pub fn read_file(path: &Path) -> Result<Database> {
+        Database::read_file_mapped(path, 0)
+    }
+
+    /// Reads the database in the file `path` like `read_file`, the first `mmap_size` bytes
+    /// of a database file memory-mapped, see `PRAGMA mmap_size`.
+    pub fn read_file_mapped(path: &Path, mmap_size: u64) -> Result<Database> {
+        match file_format(path)? {
+            FileFormat::Database { .. } => read_database(path, mmap_size),
+            FileFormat::Sqlite => read_sqlite_file(path),
+            FileFormat::Snapshot => read_snapshot_file(path),
+            FileFormat::Json => {
+                let json = std::fs::read_to_string(path).map_err(|err| {
+                    SQLRiteError::General(format!("cannot read {}: {}", path.display(), err))
+                })?;
+                let mut db: Database = serde_json::from_str(&json).map_err(|err| {
+                    SQLRiteError::General(format!(
+                        "{} is a malformed copy of a database: {}",
+                        path.display(),
+                        err
+                    ))
+                })?;
+                // A copy has no version of the file format, those written before the
+                // indexes kept every row of a value are brought in step this way
+                for table in db.tables.values_mut() {
+                    table.rebuild_indexes(&db.collations)?;
+                }
+                Ok(db)
+            }
+            FileFormat::Unknown => Err(SQLRiteError::General(format!(
+                "{} is not a database file",
+                path.display()
+            ))),
+        }
+    }
+
+    /// Replaces the data of the database with the data of `snapshot`, keeping what is
+    /// registered on the connection and its settings.
+    pub fn restore(&mut self, mut snapshot: Database) {
+        snapshot.functions = std::mem::take(&mut self.functions);
+        snapshot.collations = std::mem::take(&mut self.collations);
+        snapshot.vtabs = std::mem::take(&mut self.vtabs);
+        snapshot.attached = std::mem::take(&mut self.attached);
+        snapshot.attached_files = std::mem::take(&mut self.attached_files);
+        snapshot.settings = self.settings.clone();
+        snapshot.audit.user = self.audit.user.take();
+        snapshot.audit.keep_held(&mut self.audit);
+        snapshot.current_user = self.current_user.take();
+        snapshot.session = self.session.take();
+        snapshot.faults = std::mem::take(&mut self.faults);
+        snapshot.memory = std::mem::take(&mut self.memory);
+        snapshot.profiler = std::mem::take(&mut self.profiler);
+        snapshot.temp = std::mem::take(&mut self.temp);
+        snapshot.limits = self.limits.clone();
+        snapshot.progress = std::mem::take(&mut self.progress);
+        snapshot.path = self.path.take();
+        snapshot.autosave = std::mem::take(&mut self.autosave);
+        snapshot.backups = std::mem::take(&mut self.backups);
+        snapshot.transaction = std::mem::take(&mut self.transaction);
+        *self = snapshot;
+    }
+
+    /// Begins a transaction with `BEGIN`. The changes made until it is committed are
+    /// undone by `rollback`, and aren't saved to the file of the database, nor written to
+    /// an audit file, before.
+    pub fn begin(&mut self) -> Result<()> {
+        if self.transaction.is_open() {
+            return Err(SQLRiteError::General(
+                "cannot start a transaction within a transaction".to_string(),
+            ));
+        }
+        let snapshot = self.snapshot()?;
+        self.transaction.push(None, snapshot);
+        self.audit.hold();
+        Ok(())
+    }
+
+    /// Turns autocommit on or off, with `.autocommit`. With autocommit off, statements run
+    /// in a transaction begun before the first of them, like after `BEGIN`, until `COMMIT`
+    /// or `ROLLBACK` ends it and the next statement begins another. Autocommit is only
+    /// turned back on once no transaction is open.
+    pub fn set_autocommit(&mut self, on: bool) -> Result<()> {
+        if on && self.transaction.is_open() {
+            return Err(SQLRiteError::General(
+                "cannot turn autocommit on within a transaction: COMMIT or ROLLBACK it first"
+                    .to_string(),
+            ));
+        }
+        self.settings.autocommit = on;
+        Ok(())
+    }
+
+    /// Whether the next statement is its own transaction: autocommit is on and no
+    /// transaction is open, like `sqlite3_get_autocommit`.
+    pub fn is_autocommit(&self) -> bool {
+        self.settings.autocommit && !self.transaction.is_open()
+    }
+
+    /// Commits the open transaction with `COMMIT`, keeping its changes.
+    pub fn commit(&mut self) -> Result<()> {
+        if self.transaction.end().is_none() {
+            return Err(SQLRiteError::General(
+                "cannot commit - no transaction is active".to_string(),
+            ));
+        }
+        self.audit.release(true)
+    }
+
+    /// Rolls back the open transaction with `ROLLBACK`, undoing its changes.
+    pub fn rollback(&mut self) -> Result<()> {
+        let snapshot = self.transaction.end().ok_or_else(|| {
+            SQLRiteError::General("cannot rollback - no transaction is active".to_string())
+        })?;
+        self.audit.release(false)?;
+        self.roll_back_to(snapshot);
+        Ok(())
+    }
+
+    /// Sets the savepoint `name` with `SAVEPOINT`, beginning a transaction if none is open,
+    /// which releasing the savepoint commits.
+    pub fn savepoint(&mut self, name: &str) -> Result<()> {
+        let snapshot = self.snapshot()?;
+        if !self.transaction.is_open() {
+            self.audit.hold();
+        }
+        self.transaction.push(Some(name.to_string()), snapshot);
+        Ok(())
+    }
+
+    /// Releases the savepoint `name` with `RELEASE`, and the savepoints set after it,
+    /// keeping the changes made since. Releasing the savepoint that began the transaction
+    /// commits it.
+    pub fn release(&mut self, name: &str) -> Result<()> {
+        match self.transaction.release(name)? {
+            true => self.audit.release(true),
+            false => Ok(()),
+        }
+    }
+
+    /// Undoes the changes made since the savepoint `name` was set with `ROLLBACK TO`,
+    /// releasing the savepoints set after it. The savepoint and the transaction stay open.
+    pub fn rollback_to(&mut self, name: &str) -> Result<()> {
+        let snapshot = self.transaction.rollback_to(name)?;
+        self.roll_back_to(snapshot);
+        Ok(())
+    }
+
+    /// Restores `snapshot` the database was copied to by a transaction, keeping the
+    /// counters of the changes made, which count the changes undone too, like in SQLite.
+    fn roll_back_to(&mut self, snapshot: Database) {
+        let counters = self.counters;
+        self.restore(snapshot);
+        self.counters = counters;
+    }
+
+    /// Opens the database in the file `path` in place of this one, or a new empty database
+    /// if there is no such file, which is only written once the database is saved. The
+    /// database open before is saved to its own file first, if it has one, and its attached
+    /// databases are detached. What is registered on the connection is kept, but the
+    /// journal mode is back to `delete`, like in SQLite. A save of the file that was
+    /// interrupted, by a crash or a power loss, is rolled back from its journal before the
+    /// file is read, and a file written in an older version of the file format is upgraded
+    /// to the current one. Returns whether the file existed, what was rolled back, and the
+    /// version the file was upgraded from.
+    pub fn open_file(&mut self, path: &Path) -> Result<Opened> {
+        // Saved first, for the changes to be read back when the same file is opened again
+        self.save()?;
+        let existed = path.exists();
+        let recovered = match existed {
+            true => recover(path)?,
+            false => None,
+        };
+        let upgraded = match existed {
+            true => match file_format(path)? {
+                FileFormat::Database { version } if version < FORMAT_VERSION => Some(version),
+                _ => None,
+            },
+            false => None,
+        };
+        let opened = match existed {
+            true => Database::read_file_mapped(path, self.settings.mmap_size)?,
+            false => Database::new(self.db_name.clone()),
+        };
+        self.restore(opened);
+        self.attached.clear();
+        self.attached_files.clear();
+        self.path = Some(path.to_path_buf());
+        self.settings.journal_mode = "delete".to_string();
+        if upgraded.is_some() {
+            self.save()?;
+        }
+        self.autosave.saved(self.change_mark(), false);
+        Ok(Opened {
+            existed,
+            recovered,
+            upgraded,
+        })
+    }
+
+    /// Closes the database as the REPL exits: rolls back the transaction left open, like
+    /// SQLite, saves the database back to its file and waits for the backups still being
+    /// written. Returns whether a transaction was rolled back.
+    pub fn close(&mut self) -> Result<bool> {
+        let rolled_back = self.transaction.is_open();
+        if rolled_back {
+            self.rollback()?;
+        }
+        self.save()?;
+        self.backups.wait()?;
+        Ok(rolled_back)
+    }
+
+    /// Writes the database to the database file it was opened from, only the rows that
+    /// changed since it was last saved there. A SQLite database file is written whole again
+    /// like with `save_sqlite`, a snapshot like with `save_snapshot`, and a file that isn't
+    /// a database file yet is replaced like with `save_to`. The databases attached from
+    /// files are saved back to them the same way. Does nothing for a transient in-memory
+    /// database, and fails while a transaction is open, whose changes aren't committed.
+    pub fn save(&self) -> Result<()> {
+        if self.transaction.is_open() {
+            return Err(SQLRiteError::General(
+                "cannot save the database within a transaction: COMMIT or ROLLBACK it first"
+                    .to_string(),
+            ));
+        }
+        for (schema_name, file) in &self.attached_files {
+            self.save_file(self.attached_copy(schema_name)?, &file.path, "delete")?;
+        }
+        match &self.path {
+            Some(path) => self.save_file(self.compact_copy()?, path, &self.settings.journal_mode),
+            None => Ok(()),
+        }
+    }
+
+    /// Writes `copy` to the file `path` the way `save` does, journaled with `journal_mode`.
+    fn save_file(&self, copy: Database, path: &Path, journal_mode: &str) -> Result<()> {
+        if is_sqlite_file(path) {
+            return replace_with(path, |written| {
+                remove_file(written)?;
+                write_sqlite_file(&copy, &self.collations, written)
+            });
+        }
+        if is_snapshot_file(path) {
+            return replace_with(path, |written| {
+                remove_file(written)?;
+                write_snapshot_file(&copy, written)
+            });
+        }
+        if Pager::is_database_file(path) {
+            return write_database(
+                copy,
+                path,
+                JournalMode::new(journal_mode),
+                self.settings.mmap_size,
+            );
+        }
+        replace_with(path, |written| {
+            remove_file(written)?;
+            // A new file has nothing to roll back to
+            write_database(copy, written, JournalMode::Off, 0)
+        })
+    }
+
+    /// Saves the database to the file it was opened from if autosave tells it is time to,
+    /// after a statement ended, once no transaction is open. A save that fails is retried
+    /// after the next statement, its error kept for `Autosave::take_error`.
+    pub fn autosave(&mut self) {
+        let has_file = self.path.is_some() || !self.attached_files.is_empty();
+        // Changes stay unsaved until the transaction they are made in is committed
+        if !has_file
+            || !self.autosave.statement_ended(self.change_mark())
+            || self.transaction.is_open()
+        {
+            return;
+        }
+        match self.save() {
+            Ok(()) => self.autosave.saved(self.change_mark(), true),
+            Err(err) => self.autosave.failed(err.to_string()),
+        }
+    }
+
+    /// What tells the database changed since autosave last saw it.
+    fn change_mark(&self) -> ChangeMark {
+        (
+            self.counters.total_changes,
+            self.schema_version,
+            self.user_version,
+        )
+    }
+
+    /// Writes the main database into a new database file replacing the file `path`, what
+    /// `save` writes, without changing which file the database is saved to.
+    pub fn save_to(&self, path: &Path) -> Result<()> {
+        let copy = self.compact_copy()?;
+        replace_with(path, |written| {
+            remove_file(written)?;
+            // A new file has nothing to roll back to
+            write_database(copy, written, JournalMode::Off, 0)
+        })
+    }
+
+    /// Writes the main database into a new SQLite database file replacing the file `path`,
+    /// which `sqlite3` can open, without changing which file the database is saved to.
+    pub fn save_sqlite(&self, path: &Path) -> Result<()> {
+        let copy = self.compact_copy()?;
+        replace_with(path, |written| {
+            remove_file(written)?;
+            write_sqlite_file(&copy, &self.collations, written)
+        })
+    }
+
+    /// Starts an online backup of the main database into a new database file replacing the
+    /// file `path`. The database is only held while a consistent image of it is taken in
+    /// memory, which a thread of its own writes to `path` while statements keep running, so
+    /// the changes made once it started aren't in it. Fails if `path` is a file the
+    /// database is saved to, or the file of a backup still being written.
+    pub fn backup(&self, path: &Path) -> Result<Backup> {
+        let saved_to = self
+            .path
+            .iter()
+            .chain(self.attached_files.values().map(|file| &file.path));
+        for saved_to in saved_to {
+            if saved_to == path {
+                return Err(SQLRiteError::General(format!(
+                    "cannot back up the database into {}, which it is saved to",
+                    path.display()
+                )));
+            }
+        }
+        if self.backups.is_running(path) {
+            return Err(SQLRiteError::General(format!(
+                "a backup into {} is still being written",
+                path.display()
+            )));
+        }
+        // The image is serialized to be sent to the thread writing it, tables not being Send
+        let image = bincode::serialize(&self.compact_copy()?)
+            .map_err(|err| SQLRiteError::Internal(format!("{}", err)))?;
+        Ok(Backup::start(path, move |path| {
+            let copy: Database = bincode::deserialize(&image)
+                .map_err(|err| SQLRiteError::Internal(format!("{}", err)))?;
+            replace_with(path, |written| {
+                remove_file(written)?;
+                // A new file has nothing to roll back to
+                write_database(copy, written, JournalMode::Off, 0)
+            })
+        }))
+    }
+
+    /// Writes a binary snapshot of the main database replacing the file `path`, which only
+    /// this version of SQLRite reads back, without changing which file the database is
+    /// saved to.
+    pub fn save_snapshot(&self, path: &Path) -> Result<()> {
+        let copy = self.compact_copy()?;
+        replace_with(path, |written| {
+            remove_file(written)?;
+            write_snapshot_file(&copy, written)
+        })
+    }
+}
+
+/// Writes `bytes` to the new file `path`, synced to the disk. Fails if `path` already exists.
+pub fn write_new_file(path: &Path, bytes: &[u8]) -> Result<()> {
+    let file_error = |err: std::io::Error| {
+        SQLRiteError::General(format!("cannot write {}: {}", path.display(), err))
+    };
+    let mut file = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(path)
+        .map_err(file_error)?;
+    file.write_all(bytes).map_err(file_error)?;
+    file.sync_all().map_err(file_error)
+}
+
+/// Replaces the content of the file `path` with `bytes`, synced to the disk. The bytes are
+/// written to a new file renamed over `path`, so that a failed write leaves it as it was.
+pub fn replace_file(path: &Path, bytes: &[u8]) -> Result<()> {
+    replace_with(path, |written| {
+        let write = || -> std::io::Result<()> {
+            let mut file = OpenOptions::new()
+                .write(true)
+                .create(true)
+                .truncate(true)
+                .open(written)?;
+            file.write_all(bytes)?;
+            file.sync_all()
+        };
+        write().map_err(|err| {
+            SQLRiteError::General(format!("cannot write {}: {}", path.display(), err))
+        })
+    })
+}
+
+/// The CREATE statement `sql` of an object of the database attached under `schema_name`
+/// without the schema name qualifying what it creates, `CREATE TABLE aux.t (...)` becoming
+/// `CREATE TABLE t (...)`, the way it is read back from the file of that database.
+fn unqualified_sql(sql: &str, schema_name: &str) -> String {
+    let head = &sql[..sql.find('(').unwrap_or(sql.len())];
+    let qualifier = format!("{}.", schema_name.to_ascii_lowercase());
+    match head.to_ascii_lowercase().find(&qualifier) {
+        Some(at) => format!("{}{}", &sql[..at], &sql[at + qualifier.len()..]),
+        None => sql.to_string(),
+    }
+}
+
+/// Removes the file `path` if it exists.
+fn remove_file(path: &Path) -> Result<()> {
+    match std::fs::remove_file(path) {
+        Err(err) if err.kind() != std::io::ErrorKind::NotFound => Err(SQLRiteError::General(
+            format!("cannot write {}: {}", path.display(), err),
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// Replaces the file `path` with the file `write` writes, synced to the disk, at the path
+/// it is given next to `path`. That file is renamed over `path` once written, and removed
+/// if writing it fails, leaving `path` as it was.
+pub fn replace_with<F>(path: &Path, write: F) -> Result<()>
+where
+    F: FnOnce(&Path) -> Result<()>,
+{
+    let file_error = |err: std::io::Error| {
+        SQLRiteError::General(format!("cannot write {}: {}", path.display(), err))
+    };
+    let mut written = path.as_os_str().to_os_string();
+    written.push("-new");
+    let written = PathBuf::from(written);
+    let renamed = write(&written).and_then(|_| std::fs::rename(&written, path).map_err(file_error));
+    if let Err(err) = renamed {
+        let _ = std::fs::remove_file(&written);
+        return Err(err);
+    }
+    // The rename itself is only durable once the directory is synced
+    #[cfg(unix)]
+    if let Some(parent) = path
+        .parent()
+        .filter(|parent| !parent.as_os_str().is_empty())
+    {
+        std::fs::File::open(parent)
+            .and_then(|dir| dir.sync_all())
+            .map_err(file_error)?;
+    }
+    Ok(())
 }
 
 #[cfg(test)]
@@ -127,4 +1312,300 @@ mod tests {
         assert_eq!(table.columns.len(), 4);
         assert_eq!(table.last_rowid, 1);
     }
+
+    #[test]
+    fn attach_database_test() {
+        use crate::sql::value::Value;
+        use crate::sql::{process_command, process_query};
+
+        let mut db = Database::new("tempdb".to_string());
+        process_command("ATTACH DATABASE ':memory:' AS aux;", &mut db).unwrap();
+        assert!(process_command("ATTACH ':memory:' AS AUX;", &mut db).is_err());
+        assert!(process_command("ATTACH ':memory:' AS main;", &mut db).is_err());
+
+        process_command(
+            "CREATE TABLE aux.users (id INTEGER PRIMARY KEY, name TEXT);",
+            &mut db,
+        )
+        .unwrap();
+        process_command("INSERT INTO aux.users (name) VALUES ('josh');", &mut db).unwrap();
+        assert!(db.contains_table("aux.users".to_string()));
+        assert!(!db.contains_table("users".to_string()));
+
+        // Unqualified names find the table of the attached database
+        let result = process_query("SELECT name FROM users;", &db).unwrap();
+        assert_eq!(result.rows, vec![vec![Value::Text("josh".to_string())]]);
+
+        // ...unless the main database has a table with the same name
+        process_command(
+            "CREATE TABLE users (id INTEGER PRIMARY KEY, name TEXT);",
+            &mut db,
+        )
+        .unwrap();
+        process_command("INSERT INTO main.users (name) VALUES ('mary');", &mut db).unwrap();
+        let result = process_query("SELECT name FROM users;", &db).unwrap();
+        assert_eq!(result.rows, vec![vec![Value::Text("mary".to_string())]]);
+        let result = process_query("SELECT name FROM aux.users;", &db).unwrap();
+        assert_eq!(result.rows, vec![vec![Value::Text("josh".to_string())]]);
+        assert!(process_query("SELECT name FROM nope.users;", &db).is_err());
+
+        // Tables of the attached database are joined with those of the main one
+        process_command("INSERT INTO users (name) VALUES ('josh');", &mut db).unwrap();
+        let result = process_query(
+            "SELECT users.id, a.id FROM users, aux.users a WHERE a.name = users.name;",
+            &db,
+        )
+        .unwrap();
+        assert_eq!(
+            result.rows,
+            vec![vec![Value::Integer(2), Value::Integer(1)]]
+        );
+
+        process_command("DETACH DATABASE aux;", &mut db).unwrap();
+        assert!(!db.contains_table("aux.users".to_string()));
+        assert!(db.contains_table("users".to_string()));
+        assert!(process_command("DETACH aux;", &mut db).is_err());
+        assert!(process_command("DETACH main;", &mut db).is_err());
+    }
+
+    #[test]
+    fn attach_file_test() {
+        use crate::sql::value::Value;
+        use crate::sql::{process_command, process_query};
+
+        let path = std::env::temp_dir().join(format!("sqlrite-attach-{}.db", std::process::id()));
+        let attach = format!("ATTACH DATABASE '{}' AS other;", path.display());
+        let mut db = Database::new("tempdb".to_string());
+        process_command(&attach, &mut db).unwrap();
+        for sql in &[
+            "CREATE TABLE other.users (id INTEGER PRIMARY KEY, email TEXT UNIQUE);",
+            "INSERT INTO other.users (email) VALUES ('a@example.com'), ('b@example.com');",
+            "CREATE TABLE users (id INTEGER PRIMARY KEY, email TEXT);",
+        ] {
+            process_command(sql, &mut db).unwrap();
+        }
+        // The file is written when the database is detached
+        assert!(!path.exists());
+        process_command("DETACH DATABASE other;", &mut db).unwrap();
+        assert!(path.exists());
+        assert!(process_command(&attach, &mut db).is_ok());
+        let twice = format!("ATTACH DATABASE '{}' AS twice;", path.display());
+        assert!(process_command(&twice, &mut db).is_err());
+
+        let result = process_query(
+            "SELECT id FROM other.users WHERE email = 'b@example.com';",
+            &db,
+        )
+        .unwrap();
+        assert_eq!(result.rows, vec![vec![Value::Integer(2)]]);
+        assert!(db.tables["other.users"].check_integrity().is_empty());
+        assert!(db
+            .schema
+            .iter()
+            .any(|object| object.name.starts_with("other.sqlite_autoindex_")));
+        let result = process_query("PRAGMA database_list;", &db).unwrap();
+        assert_eq!(result.rows[1][2], Value::Text(path.display().to_string()));
+
+        // The file only has the tables of the attached database, named without its schema
+        process_command(
+            "INSERT INTO other.users (email) VALUES ('c@example.com');",
+            &mut db,
+        )
+        .unwrap();
+        db.save().unwrap();
+        let file = Database::read_file(&path).unwrap();
+        assert_eq!(file.tables.keys().collect::<Vec<&String>>(), vec!["users"]);
+        assert_eq!(file.tables["users"].last_rowid, 3);
+        assert!(file.schema.iter().all(|object| object.tbl_name == "users"));
+        assert!(file.schema[0]
+            .sql
+            .as_deref()
+            .unwrap()
+            .starts_with("CREATE TABLE users"));
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn temp_table_test() {
+        use crate::sql::value::Value;
+        use crate::sql::{process_command, process_query};
+
+        let mut db = Database::new("tempdb".to_string());
+        for sql in &[
+            "CREATE TABLE users (id INTEGER PRIMARY KEY, name TEXT);",
+            "INSERT INTO users (name) VALUES ('josh');",
+            "CREATE TEMP TABLE users (id INTEGER PRIMARY KEY, name TEXT UNIQUE);",
+            "CREATE TEMPORARY TABLE temp.scratch (id INTEGER PRIMARY KEY);",
+        ] {
+            process_command(sql, &mut db).unwrap();
+        }
+        assert!(db.contains_table("temp.users".to_string()));
+        assert!(db.contains_table("temp.scratch".to_string()));
+        assert!(process_command("CREATE TEMP TABLE main.t (id INTEGER);", &mut db).is_err());
+        assert!(process_command("DETACH DATABASE temp;", &mut db).is_err());
+
+        // Unqualified names find the temporary table first
+        process_command("INSERT INTO users (name) VALUES ('mary');", &mut db).unwrap();
+        let result = process_query("SELECT name FROM users;", &db).unwrap();
+        assert_eq!(result.rows, vec![vec![Value::Text("mary".to_string())]]);
+        let result = process_query("SELECT name FROM main.users;", &db).unwrap();
+        assert_eq!(result.rows, vec![vec![Value::Text("josh".to_string())]]);
+        let result = process_query(
+            "SELECT name FROM sqlite_temp_master WHERE type = 'table' ORDER BY name;",
+            &db,
+        )
+        .unwrap();
+        assert_eq!(
+            result.rows,
+            vec![
+                vec![Value::Text("scratch".to_string())],
+                vec![Value::Text("users".to_string())]
+            ]
+        );
+        let result = process_query("PRAGMA database_list;", &db).unwrap();
+        assert_eq!(result.rows[1][1], Value::Text("temp".to_string()));
+
+        // Temporary tables are never saved
+        let path = std::env::temp_dir().join(format!("sqlrite-temp-{}.db", std::process::id()));
+        db.save_to(&path).unwrap();
+        let saved = Database::read_file(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(saved.tables.keys().collect::<Vec<&String>>(), vec!["users"]);
+        assert!(saved.schema.iter().all(|object| object.tbl_name == "users"));
+    }
+
+    #[test]
+    fn vacuum_into_test() {
+        use crate::sql::value::Value;
+        use crate::sql::{process_command, process_query};
+
+        let mut db = Database::new("tempdb".to_string());
+        for sql in &[
+            "CREATE TABLE users (id INTEGER PRIMARY KEY, email TEXT UNIQUE);",
+            "INSERT INTO users (email) VALUES ('a@example.com'), ('b@example.com');",
+            "ATTACH DATABASE ':memory:' AS aux;",
+            "CREATE TABLE aux.logs (id INTEGER PRIMARY KEY, line TEXT);",
+        ] {
+            process_command(sql, &mut db).unwrap();
+        }
+        let path = std::env::temp_dir().join(format!("sqlrite-vacuum-{}.db", std::process::id()));
+        db.vacuum_into(&path).unwrap();
+        assert!(db.vacuum_into(&path).is_err());
+        let copy = Database::read_file(&path).unwrap();
+
+        // The copy is the database file saving the database to a new file writes
+        assert_eq!(
+            file_format(&path).unwrap(),
+            FileFormat::Database {
+                version: FORMAT_VERSION
+            }
+        );
+        let saved =
+            std::env::temp_dir().join(format!("sqlrite-vacuum-{}.saved.db", std::process::id()));
+        db.path = Some(saved.clone());
+        db.save().unwrap();
+        assert_eq!(
+            std::fs::read(&saved).unwrap(),
+            std::fs::read(&path).unwrap()
+        );
+        std::fs::remove_file(&saved).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(copy.tables.keys().collect::<Vec<&String>>(), vec!["users"]);
+        assert!(copy.schema.iter().all(|object| object.tbl_name == "users"));
+        assert!(copy.tables["users"].check_integrity().is_empty());
+        let result =
+            process_query("SELECT id FROM users WHERE email = 'b@example.com';", &copy).unwrap();
+        assert_eq!(result.rows, vec![vec![Value::Integer(2)]]);
+    }
+
+    #[test]
+    fn analyze_test() {
+        use crate::sql::executor::filter::ScanConstraint;
+        use crate::sql::executor::source::{by_selectivity, Source};
+        use crate::sql::value::Value;
+        use crate::sql::vtab::ConstraintOp;
+        use crate::sql::{process_command, process_query};
+
+        let mut db = Database::new("tempdb".to_string());
+        process_command(
+            "CREATE TABLE orders (id INTEGER PRIMARY KEY, status TEXT, total INTEGER);",
+            &mut db,
+        )
+        .unwrap();
+        for i in 1..=60 {
+            let status = if i % 10 == 0 { "refunded" } else { "delivered" };
+            let sql = format!(
+                "INSERT INTO orders (id, status, total) VALUES ({}, '{}', {});",
+                i, status, i
+            );
+            process_command(&sql, &mut db).unwrap();
+        }
+        assert!(process_command("ANALYZE nope;", &mut db).is_err());
+        process_command("ANALYZE main;", &mut db).unwrap();
+        assert_eq!(db.stats["orders"].rows, 60);
+
+        let result = process_query(
+            "SELECT lower, rows FROM sqlrite_stat WHERE col = 'status' AND kind = 'mcv';",
+            &db,
+        )
+        .unwrap();
+        assert_eq!(
+            result.rows,
+            vec![
+                vec![Value::Text("delivered".to_string()), Value::Integer(54)],
+                vec![Value::Text("refunded".to_string()), Value::Integer(6)],
+            ]
+        );
+        let result = process_query(
+            "SELECT lower, upper, rows FROM sqlrite_stat WHERE col = 'total' AND kind = 'column';",
+            &db,
+        )
+        .unwrap();
+        assert_eq!(
+            result.rows,
+            vec![vec![
+                Value::Integer(1),
+                Value::Integer(60),
+                Value::Integer(60)
+            ]]
+        );
+        assert!(process_command("CREATE TABLE sqlrite_stat (id INTEGER);", &mut db).is_err());
+
+        // The constraint keeping the fewest rows is checked first
+        let constraint = |column: &str, op, value| ScanConstraint {
+            column: column.to_string(),
+            op,
+            value,
+        };
+        let constraints = vec![
+            constraint(
+                "status",
+                ConstraintOp::Eq,
+                Value::Text("delivered".to_string()),
+            ),
+            constraint("total", ConstraintOp::Gt, Value::Integer(55)),
+            constraint(
+                "status",
+                ConstraintOp::Eq,
+                Value::Text("refunded".to_string()),
+            ),
+        ];
+        let columns = Source::from_table(&db.tables["orders"]).columns;
+        let planned = by_selectivity(&db, "orders", &columns, &constraints).unwrap();
+        assert_eq!(
+            planned,
+            vec![
+                constraints[1].clone(),
+                constraints[2].clone(),
+                constraints[0].clone()
+            ]
+        );
+        let result = process_query(
+            "SELECT id FROM orders WHERE status = 'refunded' AND total > 35;",
+            &db,
+        )
+        .unwrap();
+        assert_eq!(result.rows.len(), 3);
+    }
 }
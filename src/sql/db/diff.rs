@@ -0,0 +1,268 @@
+//! Differences between the schemas of two databases, as the statements bringing the schema
+//! of one to that of the other, to review a migration before running it. Tables are compared
+//! column by column: columns added or dropped are altered in place, while a table whose
+//! columns or constraints changed is rebuilt, copying its rows to a new table replacing it.
+//! A change in the order of the columns alone isn't a difference.
+use sqlparser::ast::{ColumnDef, Statement, TableConstraint};
+
+use crate::error::Result;
+use crate::sql::db::database::Database;
+use crate::sql::db::schema::{SchemaObject, SchemaObjectType};
+use crate::sql::parse_statement;
+
+/// Returns the statements bringing the schema of the main database `from` to that of `to`,
+/// none if they have the same schema.
+pub fn schema_diff(from: &Database, to: &Database) -> Result<Vec<String>> {
+    let (old, new) = (objects(from), objects(to));
+    let find = |objects: &[&'_ SchemaObject], object: &SchemaObject| {
+        objects
+            .iter()
+            .find(|other| other.object_type == object.object_type && other.name == object.name)
+            .map(|other| other.sql.clone())
+    };
+    let tables = |objects: &[&'_ SchemaObject]| {
+        objects
+            .iter()
+            .filter(|object| object.object_type == SchemaObjectType::Table)
+            .map(|object| object.name.to_string())
+            .collect::<Vec<String>>()
+    };
+    let (old_tables, new_tables) = (tables(&old), tables(&new));
+
+    // Tables altered in place keep their triggers, rebuilt ones lose them
+    let mut created = vec![];
+    let mut altered = vec![];
+    let mut rebuilt = vec![];
+    for object in new
+        .iter()
+        .filter(|object| object.object_type == SchemaObjectType::Table)
+    {
+        let old_sql = match find(&old, object) {
+            None => {
+                created.push(format!("{};", sql(object)));
+                continue;
+            }
+            Some(old_sql) if old_sql == object.sql => continue,
+            Some(old_sql) => old_sql.unwrap_or_default(),
+        };
+        match alter_table(&object.name, &old_sql, &sql(object)) {
+            Some(statements) => altered.extend(statements),
+            None => {
+                rebuilt.push(object.name.to_string());
+                altered.extend(rebuild_table(&object.name, &old_sql, &sql(object)));
+            }
+        }
+    }
+
+    let mut dropped = vec![];
+    for object in old
+        .iter()
+        .filter(|object| object.object_type != SchemaObjectType::Table)
+    {
+        let table_gone =
+            !new_tables.contains(&object.tbl_name) || rebuilt.contains(&object.tbl_name);
+        if table_gone && object.object_type == SchemaObjectType::Trigger {
+            // Dropped with their table
+            continue;
+        }
+        if find(&new, object) != Some(object.sql.clone()) || table_gone {
+            dropped.push(format!(
+                "DROP {} {};",
+                object.object_type.as_str().to_uppercase(),
+                object.name
+            ));
+        }
+    }
+    for name in old_tables.iter().filter(|name| !new_tables.contains(name)) {
+        dropped.push(format!("DROP TABLE {};", name));
+    }
+
+    let mut statements = dropped;
+    statements.extend(created);
+    statements.extend(altered);
+    for object in new
+        .iter()
+        .filter(|object| object.object_type != SchemaObjectType::Table)
+    {
+        let recreated =
+            rebuilt.contains(&object.tbl_name) && object.object_type == SchemaObjectType::Trigger;
+        if find(&old, object) != Some(object.sql.clone()) || recreated {
+            statements.push(format!("{};", sql(object)));
+        }
+    }
+    Ok(statements)
+}
+
+/// The objects of the main database with the SQL they were created with, the indexes
+/// created automatically being part of their table.
+fn objects(db: &Database) -> Vec<&SchemaObject> {
+    db.schema
+        .iter()
+        .filter(|object| object.sql.is_some())
+        .filter(|object| {
+            !db.attached
+                .iter()
+                .any(|schema_name| object.tbl_name.starts_with(&format!("{}.", schema_name)))
+        })
+        .collect()
+}
+
+fn sql(object: &SchemaObject) -> String {
+    object.sql.clone().unwrap_or_default()
+}
+
+/// The columns and constraints of the table created by `sql`, `None` for a virtual table.
+fn table_definition(sql: &str) -> Option<(Vec<ColumnDef>, Vec<TableConstraint>)> {
+    match parse_statement(sql).ok()? {
+        Statement::CreateTable {
+            columns,
+            constraints,
+            ..
+        } => Some((columns, constraints)),
+        _ => None,
+    }
+}
+
+/// The statements adding the columns of `new_sql` missing from `old_sql` and dropping
+/// the columns no longer in it, `None` if the table has to be rebuilt instead because the
+/// definition of a column or a constraint changed.
+fn alter_table(name: &str, old_sql: &str, new_sql: &str) -> Option<Vec<String>> {
+    let (old_columns, old_constraints) = table_definition(old_sql)?;
+    let (new_columns, new_constraints) = table_definition(new_sql)?;
+    if old_constraints != new_constraints {
+        return None;
+    }
+    let mut statements = vec![];
+    for column in &old_columns {
+        match new_columns.iter().find(|new| new.name == column.name) {
+            Some(new) if new != column => return None,
+            Some(_) => {}
+            None => statements.push(format!("ALTER TABLE {} DROP COLUMN {};", name, column.name)),
+        }
+    }
+    for column in &new_columns {
+        if !old_columns.iter().any(|old| old.name == column.name) {
+            let definition =
+                column_sql(new_sql, &column.name.value).unwrap_or_else(|| column.to_string());
+            statements.push(format!("ALTER TABLE {} ADD COLUMN {};", name, definition));
+        }
+    }
+    Some(statements)
+}
+
+/// The definition of the column `name` in `sql`, the CREATE TABLE statement of its table,
+/// as it was written: the parser writes types back under other names than the ones the
+/// engine knows, like INT for INTEGER.
+fn column_sql(sql: &str, name: &str) -> Option<String> {
+    let body = &sql[sql.find('(')? + 1..sql.rfind(')')?];
+    let mut definitions = vec![];
+    let (mut depth, mut quote, mut start) = (0, None, 0);
+    for (i, c) in body.char_indices() {
+        match (c, quote) {
+            ('\'' | '"', None) => quote = Some(c),
+            (c, Some(open)) if c == open => quote = None,
+            (_, Some(_)) => {}
+            ('(', None) => depth += 1,
+            (')', None) => depth -= 1,
+            (',', None) if depth == 0 => {
+                definitions.push(body[start..i].trim());
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    definitions.push(body[start..].trim());
+    definitions
+        .into_iter()
+        .find(|definition| {
+            let first = definition.split_whitespace().next().unwrap_or_default();
+            first.trim_matches(|c| c == '"' || c == '`') == name
+        })
+        .map(|definition| definition.to_string())
+}
+
+/// The statements rebuilding the table `name` as created by `new_sql`: creating the new
+/// table, copying the rows of the columns it keeps and replacing the old table with it.
+fn rebuild_table(name: &str, old_sql: &str, new_sql: &str) -> Vec<String> {
+    let (old_columns, new_columns, body) = match (
+        table_definition(old_sql),
+        table_definition(new_sql),
+        new_sql.find('('),
+    ) {
+        (Some((old_columns, _)), Some((new_columns, _)), Some(body)) => {
+            (old_columns, new_columns, &new_sql[body..])
+        }
+        // Virtual tables are created again, their data belongs to their module
+        _ => return vec![format!("DROP TABLE {};", name), format!("{};", new_sql)],
+    };
+    let new_name = format!("{}_new", name);
+    let kept = new_columns
+        .iter()
+        .filter(|column| old_columns.iter().any(|old| old.name == column.name))
+        .map(|column| column.name.to_string())
+        .collect::<Vec<String>>();
+    let mut statements = vec![format!("CREATE TABLE {} {};", new_name, body)];
+    if !kept.is_empty() {
+        statements.push(format!(
+            "INSERT INTO {} ({}) SELECT {} FROM {};",
+            new_name,
+            kept.join(", "),
+            kept.join(", "),
+            name
+        ));
+    }
+    statements.push(format!("DROP TABLE {};", name));
+    statements.push(format!("ALTER TABLE {} RENAME TO {};", new_name, name));
+    statements
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sql::process_command;
+
+    fn database(statements: &[&str]) -> Database {
+        let mut db = Database::new("tempdb".to_string());
+        for sql in statements {
+            process_command(sql, &mut db).unwrap();
+        }
+        db
+    }
+
+    #[test]
+    fn schema_diff_test() {
+        let old = database(&[
+            "CREATE TABLE users (id INTEGER PRIMARY KEY, name TEXT, age INTEGER);",
+            "CREATE TABLE orders (id INTEGER PRIMARY KEY, total REAL);",
+            "CREATE TABLE logs (id INTEGER PRIMARY KEY, line TEXT);",
+            "CREATE TRIGGER log_users AFTER INSERT ON users BEGIN INSERT INTO logs (line) VALUES (NEW.name); END;",
+            "CREATE TRIGGER log_orders AFTER INSERT ON orders BEGIN INSERT INTO logs (line) VALUES ('order'); END;",
+        ]);
+        let new = database(&[
+            "CREATE TABLE users (id INTEGER PRIMARY KEY, name TEXT, email TEXT UNIQUE);",
+            "CREATE TABLE orders (id INTEGER PRIMARY KEY, total INTEGER);",
+            "CREATE TABLE logs (id INTEGER PRIMARY KEY, line TEXT);",
+            "CREATE TABLE tags (id INTEGER PRIMARY KEY, tag TEXT);",
+            "CREATE TRIGGER log_orders AFTER INSERT ON orders BEGIN INSERT INTO logs (line) VALUES ('order'); END;",
+        ]);
+        assert_eq!(
+            schema_diff(&old, &new).unwrap(),
+            vec![
+                "DROP TRIGGER log_users;",
+                "CREATE TABLE tags (id INTEGER PRIMARY KEY, tag TEXT);",
+                "ALTER TABLE users DROP COLUMN age;",
+                "ALTER TABLE users ADD COLUMN email TEXT UNIQUE;",
+                "CREATE TABLE orders_new (id INTEGER PRIMARY KEY, total INTEGER);",
+                "INSERT INTO orders_new (id, total) SELECT id, total FROM orders;",
+                "DROP TABLE orders;",
+                "ALTER TABLE orders_new RENAME TO orders;",
+                "CREATE TRIGGER log_orders AFTER INSERT ON orders BEGIN INSERT INTO logs (line) VALUES ('order'); END;",
+            ]
+        );
+        assert_eq!(
+            schema_diff(&new, &old).unwrap()[..2],
+            ["DROP TABLE tags;", "ALTER TABLE users DROP COLUMN email;"]
+        );
+        assert!(schema_diff(&new, &new).unwrap().is_empty());
+    }
+}
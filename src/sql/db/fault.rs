@@ -0,0 +1,425 @@
+//! Fault injection for testing the storage engine. A `FaultInjector` is armed to fail one
+//! of the next writes, the way a disk returns an I/O error in the middle of a statement or
+//! of a save, or to fail every write from one on, the way a process crashes. Every statement
+//! writing rows checks the injector of its database before each write, and the pager checks
+//! its own before each write of its journal and of the pages it flushes. Unarmed, it never
+//! fails and costs a comparison.
+//!
+//! The tests of this module run randomized sequences of inserts, updates, deletes and
+//! commits against a model of the table, failing writes and crashing at arbitrary points,
+//! and check after each step that the database is the model: a failed statement changed
+//! nothing, the indexes agree with the rows, and a crash recovers the last commit. The
+//! same sequences are saved to a database file with faults injected into the pager: a save
+//! that fails, or crashes and is recovered from its journal, leaves the rows of the last
+//! save in the file.
+use crate::error::{Result, SQLRiteError};
+
+/// Fails a chosen write, once, or every write from it on like a crash
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct FaultInjector {
+    /// Number of writes that succeed before the next one fails, `None` when unarmed
+    countdown: Option<usize>,
+    /// Whether the writes after the one failed fail too, until the injector is cleared
+    crash: bool,
+    /// Number of faults injected since the database was opened
+    pub injected: usize,
+}
+
+impl FaultInjector {
+    /// Arms the injector so that `writes` writes succeed and the next one fails.
+    pub fn fail_after(&mut self, writes: usize) {
+        self.countdown = Some(writes);
+        self.crash = false;
+    }
+
+    /// Arms the injector so that `writes` writes succeed and every later one fails, the
+    /// way nothing is written any more once the process crashed.
+    pub fn crash_after(&mut self, writes: usize) {
+        self.countdown = Some(writes);
+        self.crash = true;
+    }
+
+    /// Disarms the injector.
+    pub fn clear(&mut self) {
+        self.countdown = None;
+        self.crash = false;
+    }
+
+    pub fn is_armed(&self) -> bool {
+        self.countdown.is_some()
+    }
+
+    /// Called before a write, fails if this is the write the injector was armed for.
+    pub fn check(&mut self, operation: &str) -> Result<()> {
+        match self.countdown {
+            Some(0) => {
+                if !self.crash {
+                    self.countdown = None;
+                }
+                self.injected += 1;
+                Err(SQLRiteError::General(format!(
+                    "disk I/O error: fault injected on {}",
+                    operation
+                )))
+            }
+            Some(writes) => {
+                self.countdown = Some(writes - 1);
+                Ok(())
+            }
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sql::db::audit::AuditOperation;
+    use crate::sql::db::changeset::{Change, Changeset};
+    use crate::sql::db::database::Database;
+    use crate::sql::db::file::{read_database, recover, write_database, write_pages};
+    use crate::sql::db::journal::{journal_path, Journal, JournalMode};
+    use crate::sql::db::pager::Pager;
+    use crate::sql::process_command;
+    use crate::sql::value::Value;
+    use std::collections::BTreeMap;
+
+    /// Rows of the table by rowid: email and n
+    type Model = BTreeMap<i64, (String, i64)>;
+
+    const COLUMNS: [&str; 3] = ["id", "email", "n"];
+
+    /// xorshift64*, enough to pick operations
+    struct Rng(u64);
+
+    impl Rng {
+        fn below(&mut self, bound: usize) -> usize {
+            self.0 ^= self.0 >> 12;
+            self.0 ^= self.0 << 25;
+            self.0 ^= self.0 >> 27;
+            (self.0.wrapping_mul(0x2545_f491_4f6c_dd1d) >> 33) as usize % bound
+        }
+    }
+
+    /// A database, the model of its table, and the image and model of its last commit
+    struct Harness {
+        rng: Rng,
+        db: Database,
+        model: Model,
+        disk: Vec<u8>,
+        committed: Model,
+        next_id: i64,
+    }
+
+    impl Harness {
+        fn new(seed: u64) -> Self {
+            let mut db = Database::new("faults".to_string());
+            process_command(
+                "CREATE TABLE items (id INTEGER PRIMARY KEY, email TEXT UNIQUE, n INTEGER);",
+                &mut db,
+            )
+            .unwrap();
+            let disk = serde_json::to_vec(&db).unwrap();
+            Harness {
+                rng: Rng(seed.wrapping_mul(0x9e37_79b9_7f4a_7c15) | 1),
+                db,
+                model: Model::new(),
+                disk,
+                committed: Model::new(),
+                next_id: 1,
+            }
+        }
+
+        /// An email from a small pool, so that UNIQUE is often violated.
+        fn email(&mut self) -> String {
+            format!("user{}@example.com", self.rng.below(40))
+        }
+
+        /// Arms a fault on one of the next `writes` writes, or sometimes none, and returns
+        /// the index of the write that fails.
+        fn arm(&mut self, writes: usize) -> Option<usize> {
+            if self.rng.below(3) != 0 {
+                return None;
+            }
+            let fault = self.rng.below(writes + 1);
+            self.db.faults.fail_after(fault);
+            Some(fault)
+        }
+
+        /// Runs `op`, which was expected to succeed if `expected` is some model, and
+        /// keeps the model it leads to.
+        fn expect(&mut self, result: Result<()>, expected: Option<Model>, op: &str) {
+            match expected {
+                Some(model) => {
+                    assert!(result.is_ok(), "{} failed: {:?}", op, result);
+                    self.model = model;
+                }
+                None => assert!(result.is_err(), "{} succeeded", op),
+            }
+            self.db.faults.clear();
+        }
+
+        fn insert(&mut self) {
+            let count = self.rng.below(4) + 1;
+            let rows = (0..count)
+                .map(|i| {
+                    (
+                        self.next_id + i as i64,
+                        self.email(),
+                        self.rng.below(1000) as i64,
+                    )
+                })
+                .collect::<Vec<_>>();
+            self.next_id += count as i64;
+            let fault = self.arm(count);
+
+            let mut expected = Some(self.model.clone());
+            for (i, (id, email, n)) in rows.iter().enumerate() {
+                if let Some(model) = &mut expected {
+                    if fault == Some(i) || model.values().any(|(e, _)| e == email) {
+                        expected = None;
+                    } else {
+                        model.insert(*id, (email.to_string(), *n));
+                    }
+                }
+            }
+            let sql = format!(
+                "INSERT INTO items (id, email, n) VALUES {};",
+                rows.iter()
+                    .map(|(id, email, n)| format!("({}, '{}', {})", id, email, n))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            );
+            let result = process_command(&sql, &mut self.db).map(|_| ());
+            self.expect(result, expected, &sql);
+        }
+
+        /// Updates or deletes a few distinct rows with one changeset.
+        fn change(&mut self, delete: bool) {
+            let mut rowids = self.model.keys().copied().collect::<Vec<i64>>();
+            let mut changes = vec![];
+            for _ in 0..self.rng.below(3) + 1 {
+                if rowids.is_empty() {
+                    break;
+                }
+                let rowid = rowids.swap_remove(self.rng.below(rowids.len()));
+                let (email, n) = self.model[&rowid].clone();
+                let old = vec![
+                    Value::Integer(rowid),
+                    Value::Text(email.to_string()),
+                    Value::Integer(n),
+                ];
+                let new = if delete {
+                    None
+                } else if self.rng.below(2) == 0 {
+                    Some(vec![
+                        Value::Integer(rowid),
+                        Value::Text(self.email()),
+                        Value::Integer(n + 1),
+                    ])
+                } else {
+                    Some(vec![
+                        Value::Integer(rowid),
+                        Value::Text(email),
+                        Value::Integer(n + 1),
+                    ])
+                };
+                changes.push(Change {
+                    operation: if delete {
+                        AuditOperation::Delete
+                    } else {
+                        AuditOperation::Update
+                    },
+                    table_name: "items".to_string(),
+                    rowid,
+                    columns: COLUMNS.iter().map(|c| c.to_string()).collect(),
+                    old: Some(old),
+                    new,
+                });
+            }
+            let fault = self.arm(changes.len());
+
+            let mut expected = Some(self.model.clone());
+            for (i, change) in changes.iter().enumerate() {
+                if let Some(model) = &mut expected {
+                    let new = change
+                        .new
+                        .as_ref()
+                        .map(|new| (new[1].to_text().unwrap(), new[2].to_integer().unwrap()));
+                    let taken = new.as_ref().is_some_and(|(email, _)| {
+                        model
+                            .iter()
+                            .any(|(rowid, (e, _))| e == email && *rowid != change.rowid)
+                    });
+                    if fault == Some(i) || taken {
+                        expected = None;
+                    } else {
+                        match new {
+                            Some(row) => model.insert(change.rowid, row),
+                            None => model.remove(&change.rowid),
+                        };
+                    }
+                }
+            }
+            let result = Changeset { changes }.apply(&mut self.db);
+            self.expect(result, expected, if delete { "delete" } else { "update" });
+        }
+
+        /// Writes the image of the database, unless it crashes while writing it, in which
+        /// case the partly written image must be rejected and the last commit recovered.
+        fn commit(&mut self) {
+            let image = serde_json::to_vec(&self.db).unwrap();
+            if self.rng.below(4) == 0 {
+                let torn = &image[..self.rng.below(image.len())];
+                assert!(serde_json::from_slice::<Database>(torn).is_err());
+                self.crash();
+            } else {
+                self.disk = image;
+                self.committed = self.model.clone();
+            }
+        }
+
+        /// Drops the database with the changes made since the last commit and opens it
+        /// again from the image of the commit.
+        fn crash(&mut self) {
+            let mut db = Database::new("faults".to_string());
+            db.restore(serde_json::from_slice(&self.disk).unwrap());
+            self.db = db;
+            self.model = self.committed.clone();
+        }
+
+        fn verify(&self, step: usize) {
+            assert_eq!(rows(&self.db), self.model, "rows differ at step {}", step);
+            assert_eq!(
+                self.db.check_integrity(),
+                Vec::<String>::new(),
+                "at step {}",
+                step
+            );
+        }
+    }
+
+    /// The rows of the table of `db`, as they are modeled.
+    fn rows(db: &Database) -> Model {
+        let table = &db.tables["items"];
+        table
+            .rowids()
+            .into_iter()
+            .map(|rowid| {
+                let email = table.get_value("email", rowid).unwrap().to_text().unwrap();
+                let n = table.get_value("n", rowid).unwrap().to_integer().unwrap();
+                (rowid, (email, n))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn fault_injector_test() {
+        let mut faults = FaultInjector::default();
+        assert!(faults.check("insert").is_ok());
+        faults.fail_after(1);
+        assert!(faults.is_armed());
+        assert!(faults.check("insert").is_ok());
+        let err = faults.check("insert").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "General error: disk I/O error: fault injected on insert"
+        );
+        assert!(!faults.is_armed());
+        assert!(faults.check("insert").is_ok());
+        assert_eq!(faults.injected, 1);
+
+        // A crash fails every write from the one armed until cleared
+        faults.crash_after(0);
+        assert!(faults.check("page write").is_err());
+        assert!(faults.check("sync").is_err());
+        assert_eq!(faults.injected, 3);
+        faults.clear();
+        assert!(faults.check("page write").is_ok());
+    }
+
+    #[test]
+    fn randomized_faults_and_crashes_test() {
+        for seed in 1..=8 {
+            let mut harness = Harness::new(seed);
+            for step in 0..150 {
+                match harness.rng.below(10) {
+                    0..=3 => harness.insert(),
+                    4 | 5 => harness.change(false),
+                    6 => harness.change(true),
+                    7 | 8 => harness.commit(),
+                    _ => harness.crash(),
+                }
+                harness.verify(step);
+            }
+        }
+    }
+
+    #[test]
+    fn randomized_save_faults_test() {
+        let path = std::env::temp_dir().join(format!("sqlrite-faults-{}.db", std::process::id()));
+        let journal = journal_path(&path);
+        let modes = [
+            JournalMode::Delete,
+            JournalMode::Truncate,
+            JournalMode::Persist,
+        ];
+        for seed in 1..=4 {
+            let _ = std::fs::remove_file(&path);
+            let mut harness = Harness::new(seed);
+            let db = harness.db.snapshot().unwrap();
+            write_database(db, &path, JournalMode::Delete, 0).unwrap();
+            let mut saved = Model::new();
+            for step in 0..40 {
+                for _ in 0..harness.rng.below(8) + 1 {
+                    match harness.rng.below(4) {
+                        0 | 1 => harness.insert(),
+                        2 => harness.change(false),
+                        _ => harness.change(true),
+                    }
+                }
+
+                // The save fails at one of the writes of its journal or of its pages, or
+                // the process crashes there, or it succeeds
+                let mut pager = Pager::open(&path).unwrap();
+                pager.set_journal_mode(modes[harness.rng.below(modes.len())]);
+                let crash = harness.rng.below(2) == 0;
+                if harness.rng.below(4) != 0 {
+                    let writes = harness.rng.below(20);
+                    match crash {
+                        true => pager.faults.crash_after(writes),
+                        false => pager.faults.fail_after(writes),
+                    }
+                }
+                let result = write_pages(harness.db.snapshot().unwrap(), &mut pager);
+                assert_eq!(
+                    result.is_err(),
+                    pager.faults.injected > 0,
+                    "at step {}",
+                    step
+                );
+                drop(pager);
+                match result {
+                    Ok(()) => saved = harness.model.clone(),
+                    // Restarted from the file, once rolled back from its hot journal
+                    Err(_) if crash => {
+                        recover(&path).unwrap();
+                        assert_eq!(Journal::read(&journal).unwrap(), None);
+                        let mut db = Database::new("faults".to_string());
+                        db.restore(read_database(&path, 0).unwrap());
+                        harness.db = db;
+                        harness.model = saved.clone();
+                    }
+                    Err(_) => {}
+                }
+
+                let read = read_database(&path, 0).unwrap();
+                assert_eq!(rows(&read), saved, "saved rows differ at step {}", step);
+                assert_eq!(read.check_integrity(), Vec::<String>::new());
+                harness.verify(step);
+            }
+        }
+        std::fs::remove_file(&path).unwrap();
+        let _ = std::fs::remove_file(&journal);
+    }
+}
@@ -0,0 +1,265 @@
+//! The database file: a database saved in the pages of a `Pager`. The rows of each table
+//! are stored in a B+tree of their own, keyed by ROWID, each row a record of its values in
+//! the order of the columns. The catalog, a B+tree whose root is page 1, holds the rest of
+//! the database, its schema, indexes and settings, serialized without the rows, and the
+//! root page of the B+tree of each table.
+//!
+//! The B+trees statements read and write the rows of a table through are kept in memory,
+//! see `row_store`: a database is read from its file in whole, and saving it again only
+//! writes the rows that changed since, each B+tree of the file brought in step with the
+//! rows of its table by inserting the rows that aren't stored as they are and deleting
+//! those that no longer exist. The pages the rows deleted and the tables dropped
+//! took are freed, and reused by the rows saved next before the file grows. A save that was
+//! interrupted is rolled back from its journal when the file is opened again.
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::path::Path;
+
+use crate::error::{Result, SQLRiteError};
+use crate::sql::db::btree::BTree;
+use crate::sql::db::database::Database;
+use crate::sql::db::format::migrate;
+use crate::sql::db::journal::{journal_path, Journal, JournalMode};
+use crate::sql::db::pager::{malformed, PageId, Pager};
+use crate::sql::db::record::{decode_record, encode_record};
+use crate::sql::db::table::Table;
+
+/// Root page of the catalog
+const CATALOG_ROOT: PageId = 1;
+/// Key of the database, without its rows, in the catalog
+const DATABASE_KEY: i64 = 0;
+/// Key of the root pages of the tables in the catalog
+const ROOTS_KEY: i64 = 1;
+
+/// Saves `db` into the database file `path`, created if it doesn't exist, writing only the
+/// rows that changed since it was last saved there. The pages changed are journaled the
+/// way `journal_mode` tells, so that a save that fails leaves the file as it was, and the
+/// rows stored are compared from the first `mmap_size` bytes of the file memory-mapped.
+pub fn write_database(
+    db: Database,
+    path: &Path,
+    journal_mode: JournalMode,
+    mmap_size: u64,
+) -> Result<()> {
+    let mut pager = Pager::open(path)?;
+    pager.set_journal_mode(journal_mode);
+    pager.set_mmap_size(mmap_size)?;
+    write_pages(db, &mut pager)
+}
+
+/// Saves `db` into the pages of `pager` the way `write_database` does, and flushes them.
+pub fn write_pages(mut db: Database, pager: &mut Pager) -> Result<()> {
+    let catalog = match pager.page_count() {
+        1 => BTree::create(pager)?,
+        _ => BTree::open(CATALOG_ROOT),
+    };
+    let mut roots = read_roots(&catalog, pager)?;
+    // The pages of the tables dropped since go to the free-list, to be reused
+    let dropped = roots
+        .keys()
+        .filter(|name| !db.tables.contains_key(*name))
+        .cloned()
+        .collect::<Vec<String>>();
+    for name in dropped {
+        if let Some(root) = roots.remove(&name) {
+            BTree::open(root).destroy(pager)?;
+        }
+    }
+    // In the order of their names, for the same database to be written to the same pages
+    let mut tables = db.tables.iter_mut().collect::<Vec<(&String, &mut Table)>>();
+    tables.sort_by(|a, b| a.0.cmp(b.0));
+    for (name, table) in tables {
+        let tree = match roots.get(name) {
+            Some(root) => BTree::open(*root),
+            None => BTree::create(pager)?,
+        };
+        roots.insert(name.to_string(), tree.root());
+        let stored = tree
+            .scan(pager, ..)?
+            .into_iter()
+            .collect::<HashMap<i64, Vec<u8>>>();
+        let mut rowids = BTreeSet::new();
+        for (rowid, values) in table.scan_rows(..) {
+            let record = encode_record(&values);
+            if stored.get(&rowid) != Some(&record) {
+                tree.insert(pager, rowid, &record)?;
+            }
+            rowids.insert(rowid);
+        }
+        for rowid in stored.keys().filter(|rowid| !rowids.contains(rowid)) {
+            tree.delete(pager, *rowid)?;
+        }
+        table.clear_rows();
+    }
+    catalog.insert(pager, DATABASE_KEY, &to_json(&db)?)?;
+    catalog.insert(pager, ROOTS_KEY, &to_json(&roots)?)?;
+    // Writing the header upgrades a file in an older version of the file format
+    pager.set_schema_cookie(db.schema_version as u32);
+    pager.flush()
+}
+
+/// The bytes of a database file holding `db`, as `write_pages` saves it into a new file, for
+/// a copy of the database written somewhere else than a file it was opened from.
+pub fn write_image(db: Database) -> Result<Vec<u8>> {
+    let mut pager = Pager::memory();
+    write_pages(db, &mut pager)?;
+    Ok(pager.into_bytes())
+}
+
+/// Reads the database saved in the database file `path`, its first `mmap_size` bytes
+/// memory-mapped, migrated to the current version of the file format if it is older.
+pub fn read_database(path: &Path, mmap_size: u64) -> Result<Database> {
+    let mut pager = Pager::open(path)?;
+    pager.set_mmap_size(mmap_size)?;
+    read_pages(&mut pager)
+}
+
+/// Reads the database saved in `bytes`, the bytes of a database file like `write_image`
+/// returns them, the way `read_database` reads a file.
+pub fn read_image(bytes: &[u8]) -> Result<Database> {
+    read_pages(&mut Pager::from_bytes(bytes)?)
+}
+
+/// Reads the database saved in the pages of `pager`, like `read_database`.
+fn read_pages(pager: &mut Pager) -> Result<Database> {
+    let catalog = BTree::open(CATALOG_ROOT);
+    let database = catalog.get(pager, DATABASE_KEY)?.ok_or_else(malformed)?;
+    let mut db: Database = serde_json::from_slice(&database).map_err(|_| malformed())?;
+    // The header is only written with the catalog, files written before it had a schema
+    // cookie have none
+    if pager.format_version() > 1 && pager.schema_cookie() != db.schema_version as u32 {
+        return Err(malformed());
+    }
+    let roots = read_roots(&catalog, pager)?;
+    for (name, table) in db.tables.iter_mut() {
+        let tree = BTree::open(*roots.get(name).ok_or_else(malformed)?);
+        for (rowid, record) in tree.scan(pager, ..)? {
+            table.load_row(rowid, &decode_record(&record)?);
+        }
+    }
+    migrate(&mut db, pager.format_version())?;
+    Ok(db)
+}
+
+/// Rolls the database file `path` back from the hot journal left by a save that was
+/// interrupted, if there is one, returning the number of pages written back.
+pub fn recover(path: &Path) -> Result<Option<usize>> {
+    if Journal::read(&journal_path(path))?.is_none() {
+        return Ok(None);
+    }
+    Ok(Pager::open(path)?.recovered())
+}
+
+/// Number of pages on the free-list of the database file `path`, waiting to be reused.
+/// 0 for a file that isn't a database file.
+pub fn free_page_count(path: &Path) -> Result<u32> {
+    Ok(page_counts(path)?.1)
+}
+
+/// Number of pages of the database file `path`, and of those on its free-list. 0 and 0
+/// for a file that isn't a database file.
+pub fn page_counts(path: &Path) -> Result<(u32, u32)> {
+    if !Pager::is_database_file(path) {
+        return Ok((0, 0));
+    }
+    let pager = Pager::open(path)?;
+    Ok((pager.page_count(), pager.free_page_count()))
+}
+
+/// The root page of the B+tree of each table saved in the file, by table name.
+fn read_roots(catalog: &BTree, pager: &mut Pager) -> Result<BTreeMap<String, PageId>> {
+    match catalog.get(pager, ROOTS_KEY)? {
+        Some(roots) => serde_json::from_slice(&roots).map_err(|_| malformed()),
+        None => Ok(BTreeMap::new()),
+    }
+}
+
+fn to_json<T: serde::Serialize>(value: &T) -> Result<Vec<u8>> {
+    serde_json::to_vec(value).map_err(|err| SQLRiteError::Internal(format!("{}", err)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sql::db::pager::MAX_MMAP_SIZE;
+    use crate::sql::process_command;
+    use crate::sql::value::Value;
+
+    #[test]
+    fn database_file_test() {
+        let path = std::env::temp_dir().join(format!("sqlrite-file-{}.db", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let mut db = Database::new("tempdb".to_string());
+        process_command(
+            "CREATE TABLE users (id INTEGER PRIMARY KEY, name TEXT UNIQUE, score REAL);",
+            &mut db,
+        )
+        .unwrap();
+        for i in 0..500 {
+            let insert = format!(
+                "INSERT INTO users (name, score) VALUES ('user {}', {}.5);",
+                i, i
+            );
+            process_command(&insert, &mut db).unwrap();
+        }
+        write_database(db.snapshot().unwrap(), &path, JournalMode::Delete, 0).unwrap();
+        assert!(Pager::is_database_file(&path));
+
+        let read = read_database(&path, 0).unwrap();
+        let users = &read.tables["users"];
+        assert_eq!(users.rowids().len(), 500);
+        assert_eq!(
+            users.row_values(42),
+            vec![
+                Value::Integer(42),
+                Value::Text("user 41".to_string()),
+                Value::Real(41.5)
+            ]
+        );
+        assert_eq!(read.schema, db.schema);
+        assert!(users.check_integrity().is_empty());
+
+        // Saving again only changes the rows that changed
+        process_command("DELETE FROM users WHERE id > 10;", &mut db).unwrap();
+        process_command("INSERT INTO users (id, name) VALUES (20, 'josh');", &mut db).unwrap();
+        write_database(
+            db.snapshot().unwrap(),
+            &path,
+            JournalMode::Delete,
+            MAX_MMAP_SIZE,
+        )
+        .unwrap();
+        // Memory-mapped or not, the same rows are read
+        let read = read_database(&path, MAX_MMAP_SIZE).unwrap();
+        assert_eq!(read.tables["users"].rowids().len(), 11);
+        assert_eq!(
+            read.tables["users"].get_value("name", 20).unwrap(),
+            Value::Text("josh".to_string())
+        );
+
+        // The pages of the rows deleted are reused by the rows inserted next, and those of
+        // a table dropped by the rows of another
+        let length = std::fs::metadata(&path).unwrap().len();
+        for i in 0..200 {
+            let insert = format!("INSERT INTO users (name) VALUES ('new user {}');", i);
+            process_command(&insert, &mut db).unwrap();
+        }
+        write_database(db.snapshot().unwrap(), &path, JournalMode::Delete, 0).unwrap();
+        assert_eq!(std::fs::metadata(&path).unwrap().len(), length);
+        db.tables.remove("users");
+        process_command(
+            "CREATE TABLE logs (id INTEGER PRIMARY KEY, line TEXT);",
+            &mut db,
+        )
+        .unwrap();
+        for i in 0..300 {
+            let insert = format!("INSERT INTO logs (line) VALUES ('line {}');", i);
+            process_command(&insert, &mut db).unwrap();
+        }
+        write_database(db.snapshot().unwrap(), &path, JournalMode::Delete, 0).unwrap();
+        assert_eq!(std::fs::metadata(&path).unwrap().len(), length);
+        let read = read_database(&path, 0).unwrap();
+        assert_eq!(read.tables["logs"].rowids().len(), 300);
+        assert!(free_page_count(&path).unwrap() > 0);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
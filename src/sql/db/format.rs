@@ -0,0 +1,166 @@
+//! Versions of the database file format. The header of a database file written by the
+//! pager tells the version of the format it was written in, next to the magic string and
+//! the schema cookie, so that a file is never misread by a version of SQLRite that doesn't
+//! know its format:
+//!
+//! - version 1, the files written before there were versions, which have none in their
+//!   header, nor a free-list or a schema cookie;
+//! - version 2, with the free-list of the pages freed, the version and the schema cookie;
+//! - version 3, where the index of a column lists every row having each of its values,
+//!   not only one of them, and INTEGER columns hold 64-bit integers instead of 32-bit ones.
+//!
+//! A file in an older version is read by migrating it up to the current version one
+//! version at a time, and is saved back in the current version. A file in a version newer
+//! than `FORMAT_VERSION` is rejected with an error telling so.
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+use crate::error::{Result, SQLRiteError};
+use crate::sql::db::database::Database;
+use crate::sql::db::pager::Pager;
+use crate::sql::db::snapshot_file::is_snapshot_file;
+use crate::sql::db::sqlite_file::is_sqlite_file;
+
+/// Version of the file format the database files are written in
+pub const FORMAT_VERSION: u32 = 3;
+
+/// What a file holds, told from its first bytes
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FileFormat {
+    /// A database file written by the pager, in the version of the file format its header tells
+    Database { version: u32 },
+    /// A SQLite 3 database file
+    Sqlite,
+    /// A JSON copy of a database, written by `vacuum_into`, `.clone` or `.snapshot` before
+    /// they wrote database files
+    Json,
+    /// A binary snapshot of a database, written by `.save --snapshot`
+    Snapshot,
+    /// Anything else, which isn't a database
+    Unknown,
+}
+
+/// Tells what the file `path` holds from its first bytes.
+pub fn file_format(path: &Path) -> Result<FileFormat> {
+    if let Some(version) = Pager::format_version_of(path) {
+        return Ok(FileFormat::Database { version });
+    }
+    if is_sqlite_file(path) {
+        return Ok(FileFormat::Sqlite);
+    }
+    if is_snapshot_file(path) {
+        return Ok(FileFormat::Snapshot);
+    }
+    let mut start = vec![];
+    File::open(path)
+        .and_then(|file| file.take(64).read_to_end(&mut start))
+        .map_err(|err| SQLRiteError::General(format!("cannot read {}: {}", path.display(), err)))?;
+    match start.iter().find(|byte| !byte.is_ascii_whitespace()) {
+        Some(b'{') => Ok(FileFormat::Json),
+        _ => Ok(FileFormat::Unknown),
+    }
+}
+
+/// Fails unless `version` is a version of the file format this version of SQLRite reads.
+pub fn check_version(version: u32) -> Result<()> {
+    if version > FORMAT_VERSION {
+        return Err(SQLRiteError::General(format!(
+            "the database file is in version {} of the file format, newer than version {} this version of SQLRite reads: upgrade SQLRite to open it",
+            version, FORMAT_VERSION
+        )));
+    }
+    Ok(())
+}
+
+/// Brings `db`, read from a database file written in version `version` of the file
+/// format with its rows, up to the current version.
+pub fn migrate(db: &mut Database, version: u32) -> Result<()> {
+    check_version(version)?;
+    for from in version..FORMAT_VERSION {
+        match from {
+            // Version 1 only lacked what the header of version 2 has: all its pages are in
+            // use, and the schema cookie is written from the schema version when it is saved
+            1 => {}
+            // The indexes of version 2 kept one row for each value, those of columns that
+            // aren't UNIQUE missing the other rows having it, and their bloom filters hashed
+            // 32-bit integers. Rebuilding the indexes rebuilds the filters with them.
+            2 => {
+                for table in db.tables.values_mut() {
+                    table.rebuild_indexes(&db.collations)?;
+                }
+            }
+            _ => {
+                return Err(SQLRiteError::Internal(format!(
+                    "no migration from version {} of the file format",
+                    from
+                )))
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sql::process_command;
+    use crate::sql::value::Value;
+
+    #[test]
+    fn format_test() {
+        let path = std::env::temp_dir().join(format!("sqlrite-format-{}.db", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let mut db = Database::new("tempdb".to_string());
+        process_command(
+            "CREATE TABLE users (id INTEGER PRIMARY KEY, name TEXT);",
+            &mut db,
+        )
+        .unwrap();
+        process_command("INSERT INTO users (name) VALUES ('josh');", &mut db).unwrap();
+        db.save_to(&path).unwrap();
+        assert_eq!(
+            file_format(&path).unwrap(),
+            FileFormat::Database {
+                version: FORMAT_VERSION
+            }
+        );
+
+        // A file written before there were versions is read, and upgraded when saved
+        let mut bytes = std::fs::read(&path).unwrap();
+        bytes[32..40].copy_from_slice(&[0; 8]);
+        std::fs::write(&path, &bytes).unwrap();
+        assert_eq!(
+            file_format(&path).unwrap(),
+            FileFormat::Database { version: 1 }
+        );
+        let mut opened = Database::new("tempdb".to_string());
+        assert_eq!(opened.open_file(&path).unwrap().upgraded, Some(1));
+        assert_eq!(
+            opened.tables["users"].get_value("name", 1).unwrap(),
+            Value::Text("josh".to_string())
+        );
+        assert_eq!(
+            file_format(&path).unwrap(),
+            FileFormat::Database {
+                version: FORMAT_VERSION
+            }
+        );
+        assert_eq!(opened.open_file(&path).unwrap().upgraded, None);
+
+        // A file in a newer version isn't misread
+        let mut bytes = std::fs::read(&path).unwrap();
+        bytes[32..36].copy_from_slice(&(FORMAT_VERSION + 1).to_be_bytes());
+        std::fs::write(&path, &bytes).unwrap();
+        let err = Database::read_file(&path).unwrap_err();
+        assert!(err.to_string().contains("newer than version 3"));
+
+        std::fs::write(&path, " {\"db_name\": 1}").unwrap();
+        assert_eq!(file_format(&path).unwrap(), FileFormat::Json);
+        std::fs::write(&path, "not a database").unwrap();
+        assert_eq!(file_format(&path).unwrap(), FileFormat::Unknown);
+        let err = Database::read_file(&path).unwrap_err();
+        assert!(err.to_string().ends_with("is not a database file"));
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,209 @@
+//! The rollback journal of a database file, like SQLite's. Before the pager writes the
+//! pages changed since the last flush, it copies what they held to the journal file next
+//! to the database, `<file>-journal`, and syncs it to the disk. Once the pages are written
+//! and synced, the journal is finished and the flush has committed. A flush that fails
+//! copies the pages of the journal back, and a journal left behind by a process that was
+//! interrupted, a hot journal, tells the file needs to be rolled back the same way.
+//!
+//! The journal starts with a header: its magic string, the number of pages the file had
+//! and the number of pages journaled. Each page follows, with its number and a checksum
+//! of its content, so that a page only partly written is told apart. The journal mode of
+//! the pager sets how the journal is finished, deleted, truncated or its header zeroed, or
+//! kept in memory only, which rolls back failed flushes but not interrupted ones.
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+
+use crate::error::{Result, SQLRiteError};
+use crate::sql::db::fault::FaultInjector;
+use crate::sql::db::memory::PAGE_SIZE;
+use crate::sql::db::pager::{read_u32, PageId};
+
+/// First bytes of a journal file, until it is finished
+const MAGIC: &[u8; 16] = b"SQLRite journal\0";
+/// Bytes of the header: the magic string, the number of pages of the database file and
+/// the number of pages journaled
+const HEADER_SIZE: usize = 24;
+
+/// How the journal of a database file is kept, set with `PRAGMA journal_mode`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum JournalMode {
+    /// The journal file is deleted when a flush commits
+    Delete,
+    /// The journal file is truncated to nothing when a flush commits
+    Truncate,
+    /// The journal file is kept, its header zeroed, when a flush commits
+    Persist,
+    /// The original pages are only kept in memory, to roll back a flush that fails
+    Memory,
+    /// Nothing is journaled, a flush that fails leaves the file as it is
+    Off,
+}
+
+impl JournalMode {
+    /// The journal mode named `mode`, as `PRAGMA journal_mode` names it. Modes the pager
+    /// doesn't have, like WAL, are DELETE.
+    pub fn new(mode: &str) -> JournalMode {
+        match mode.to_lowercase().as_str() {
+            "truncate" => JournalMode::Truncate,
+            "persist" => JournalMode::Persist,
+            "memory" => JournalMode::Memory,
+            "off" => JournalMode::Off,
+            _ => JournalMode::Delete,
+        }
+    }
+}
+
+/// What a file held before a flush: its number of pages and the pages it changes
+#[derive(Debug, Clone, PartialEq)]
+pub struct Journal {
+    pub page_count: u32,
+    pub pages: Vec<(PageId, Vec<u8>)>,
+}
+
+/// The journal file of the database file `path`.
+pub fn journal_path(path: &Path) -> PathBuf {
+    let mut journal = path.as_os_str().to_os_string();
+    journal.push("-journal");
+    PathBuf::from(journal)
+}
+
+impl Journal {
+    /// Writes the journal into the journal file `path`, replacing what it held, synced to
+    /// the disk. The header and each page are writes `faults` can fail, leaving the journal
+    /// written up to there.
+    pub fn write(&self, path: &Path, faults: &mut FaultInjector) -> Result<()> {
+        let mut bytes = Vec::with_capacity(HEADER_SIZE + self.pages.len() * (PAGE_SIZE + 8));
+        bytes.extend_from_slice(MAGIC);
+        bytes.extend_from_slice(&self.page_count.to_be_bytes());
+        bytes.extend_from_slice(&(self.pages.len() as u32).to_be_bytes());
+        for (id, page) in &self.pages {
+            bytes.extend_from_slice(&id.to_be_bytes());
+            bytes.extend_from_slice(page);
+            bytes.extend_from_slice(&checksum(*id, page).to_be_bytes());
+        }
+        let mut file = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(path)
+            .map_err(|err| journal_error(path, err))?;
+        let records = bytes[HEADER_SIZE..].chunks(PAGE_SIZE + 8);
+        for chunk in std::iter::once(&bytes[..HEADER_SIZE]).chain(records) {
+            faults.check("journal write")?;
+            file.write_all(chunk)
+                .map_err(|err| journal_error(path, err))?;
+        }
+        file.sync_all().map_err(|err| journal_error(path, err))
+    }
+
+    /// Reads the hot journal in the journal file `path`, if there is one. The pages read
+    /// stop at the first one that wasn't written whole: the file was only changed once
+    /// the whole journal was synced.
+    pub fn read(path: &Path) -> Result<Option<Journal>> {
+        let mut bytes = vec![];
+        match File::open(path) {
+            Ok(mut file) => file
+                .read_to_end(&mut bytes)
+                .map_err(|err| journal_error(path, err))?,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(journal_error(path, err)),
+        };
+        if bytes.len() < HEADER_SIZE || &bytes[..MAGIC.len()] != MAGIC {
+            return Ok(None);
+        }
+        let page_count = read_u32(&bytes, 16);
+        let count = read_u32(&bytes, 20) as usize;
+        let pages = bytes[HEADER_SIZE..]
+            .chunks_exact(PAGE_SIZE + 8)
+            .take(count)
+            .map(|record| {
+                let id = read_u32(record, 0);
+                let page = record[4..4 + PAGE_SIZE].to_vec();
+                (id, page, read_u32(record, 4 + PAGE_SIZE))
+            })
+            .take_while(|(id, page, sum)| checksum(*id, page) == *sum)
+            .map(|(id, page, _)| (id, page))
+            .collect();
+        Ok(Some(Journal { page_count, pages }))
+    }
+
+    /// Finishes the journal file `path` of a flush that committed, the way `mode` keeps it,
+    /// so that it isn't hot any more.
+    pub fn finish(path: &Path, mode: JournalMode) -> Result<()> {
+        let finished = match mode {
+            JournalMode::Delete => match std::fs::remove_file(path) {
+                Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(()),
+                result => result,
+            },
+            JournalMode::Truncate => OpenOptions::new()
+                .write(true)
+                .open(path)
+                .and_then(|file| file.set_len(0).and_then(|_| file.sync_all())),
+            JournalMode::Persist => {
+                OpenOptions::new()
+                    .write(true)
+                    .open(path)
+                    .and_then(|mut file| {
+                        file.write_all(&[0; HEADER_SIZE])?;
+                        file.sync_all()
+                    })
+            }
+            JournalMode::Memory | JournalMode::Off => Ok(()),
+        };
+        finished.map_err(|err| journal_error(path, err))
+    }
+}
+
+/// Checksum of the page `id` in the journal.
+fn checksum(id: PageId, page: &[u8]) -> u32 {
+    page.iter().fold(id.wrapping_mul(31), |sum, byte| {
+        sum.wrapping_mul(31).wrapping_add(u32::from(*byte))
+    })
+}
+
+fn journal_error(path: &Path, err: std::io::Error) -> SQLRiteError {
+    SQLRiteError::General(format!("cannot use journal {}: {}", path.display(), err))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn journal_test() {
+        let path = std::env::temp_dir().join(format!("sqlrite-{}.db-journal", std::process::id()));
+        assert_eq!(Journal::read(&path).unwrap(), None);
+        let journal = Journal {
+            page_count: 3,
+            pages: vec![(1, vec![1; PAGE_SIZE]), (2, vec![2; PAGE_SIZE])],
+        };
+        journal.write(&path, &mut FaultInjector::default()).unwrap();
+        assert_eq!(Journal::read(&path).unwrap(), Some(journal.clone()));
+
+        // A page written in part isn't read
+        let mut bytes = std::fs::read(&path).unwrap();
+        bytes.truncate(bytes.len() - 10);
+        std::fs::write(&path, &bytes).unwrap();
+        let read = Journal::read(&path).unwrap().unwrap();
+        assert_eq!(read.pages, journal.pages[..1].to_vec());
+
+        // Nor are the pages after a write that failed
+        let mut faults = FaultInjector::default();
+        faults.fail_after(2);
+        assert!(journal.write(&path, &mut faults).is_err());
+        let read = Journal::read(&path).unwrap().unwrap();
+        assert_eq!(read.pages, journal.pages[..1].to_vec());
+
+        journal.write(&path, &mut FaultInjector::default()).unwrap();
+        Journal::finish(&path, JournalMode::Persist).unwrap();
+        assert!(path.exists());
+        assert_eq!(Journal::read(&path).unwrap(), None);
+        journal.write(&path, &mut FaultInjector::default()).unwrap();
+        Journal::finish(&path, JournalMode::Truncate).unwrap();
+        assert_eq!(std::fs::metadata(&path).unwrap().len(), 0);
+        Journal::finish(&path, JournalMode::Delete).unwrap();
+        assert!(!path.exists());
+        assert_eq!(JournalMode::new("WAL"), JournalMode::Delete);
+    }
+}
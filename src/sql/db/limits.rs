@@ -0,0 +1,347 @@
+//! Runtime limits of a connection, like `sqlite3_limit()` in SQLite. They cap the length of
+//! a statement, the depth of its expressions, its number of columns, the rows of its VALUES
+//! clause and the databases attached, and are checked before a statement is parsed and
+//! planned, so that a hostile or accidental pathological statement fails fast instead of
+//! exhausting the memory or the stack. The length of the strings and blobs functions
+//! return is capped as well, checked as they are returned.
+//!
+//! Each limit starts at its maximum and can only be lowered, like in SQLite where the
+//! maximums are fixed when SQLite is compiled.
+use std::fmt;
+
+use sqlparser::ast::{
+    Expr, FunctionArg, FunctionArgExpr, Query, SelectItem, SetExpr, Statement, Values,
+};
+use sqlparser::tokenizer::Token;
+
+use crate::error::{Result, SQLRiteError};
+use crate::sql::db::database::MAX_ATTACHED;
+use crate::sql::value::Value;
+
+/// A limit of the connection, set with `Connection::set_limit` or `.limit`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Limit {
+    /// Bytes of a string or blob returned by a function, SQLITE_LIMIT_LENGTH
+    Length,
+    /// Bytes of the text of a statement, SQLITE_LIMIT_SQL_LENGTH
+    SqlLength,
+    /// Depth of the tree of an expression, parentheses included, SQLITE_LIMIT_EXPR_DEPTH
+    ExprDepth,
+    /// Columns of a table, of the result of a SELECT and of an INSERT or UPDATE,
+    /// SQLITE_LIMIT_COLUMN
+    Column,
+    /// Rows of a VALUES clause
+    ValuesRows,
+    /// Databases attached at the same time, SQLITE_LIMIT_ATTACHED
+    Attached,
+}
+
+impl Limit {
+    /// Every limit, in the order `.limit` lists them.
+    pub const ALL: [Limit; 6] = [
+        Limit::Length,
+        Limit::SqlLength,
+        Limit::ExprDepth,
+        Limit::Column,
+        Limit::ValuesRows,
+        Limit::Attached,
+    ];
+
+    /// The largest value of the limit, and its value when a connection is opened.
+    pub fn maximum(self) -> usize {
+        match self {
+            Limit::Length => 1_000_000_000,
+            Limit::SqlLength => 1_000_000_000,
+            Limit::ExprDepth => 1000,
+            Limit::Column => 2000,
+            Limit::ValuesRows => 100_000,
+            Limit::Attached => MAX_ATTACHED,
+        }
+    }
+
+    /// Finds the limit named `name`, as `.limit` names them.
+    pub fn from_name(name: &str) -> Option<Limit> {
+        Limit::ALL
+            .iter()
+            .copied()
+            .find(|limit| limit.to_string().eq_ignore_ascii_case(name))
+    }
+}
+
+impl fmt::Display for Limit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Limit::Length => "length",
+            Limit::SqlLength => "sql_length",
+            Limit::ExprDepth => "expr_depth",
+            Limit::Column => "column",
+            Limit::ValuesRows => "values_rows",
+            Limit::Attached => "attached",
+        })
+    }
+}
+
+/// The limits of a connection
+#[derive(Debug, Clone, PartialEq)]
+pub struct Limits {
+    values: [usize; 6],
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Limits {
+            values: Limit::ALL.map(Limit::maximum),
+        }
+    }
+}
+
+impl Limits {
+    pub fn get(&self, limit: Limit) -> usize {
+        self.values[limit as usize]
+    }
+
+    /// Sets `limit` to `value`, or to its maximum if `value` is larger, and returns its
+    /// previous value. A negative `value` leaves the limit unchanged, like in SQLite.
+    pub fn set(&mut self, limit: Limit, value: i64) -> usize {
+        let previous = self.get(limit);
+        if value >= 0 {
+            self.values[limit as usize] = (value as usize).min(limit.maximum());
+        }
+        previous
+    }
+
+    /// Checks the text of a statement before it is parsed: its length, and how deep its
+    /// parentheses nest, which the parser would otherwise recurse into.
+    pub fn check_sql(&self, sql: &str, tokens: &[Token]) -> Result<()> {
+        if sql.len() > self.get(Limit::SqlLength) {
+            return Err(SQLRiteError::General(format!(
+                "statement too long: {} bytes, the limit is {}",
+                sql.len(),
+                self.get(Limit::SqlLength)
+            )));
+        }
+        let mut depth = 0usize;
+        for token in tokens {
+            match token {
+                Token::LParen => {
+                    depth += 1;
+                    self.check_depth(depth)?;
+                }
+                Token::RParen => depth = depth.saturating_sub(1),
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks the length of a string or blob `value` returned by a function.
+    pub fn check_length(&self, value: &Value) -> Result<()> {
+        let length = match value {
+            Value::Text(text) => text.len(),
+            Value::Blob(bytes) => bytes.len(),
+            _ => return Ok(()),
+        };
+        if length > self.get(Limit::Length) {
+            return Err(SQLRiteError::General("string or blob too big".to_string()));
+        }
+        Ok(())
+    }
+
+    /// Checks a parsed statement before it is planned: the depth of its expressions, its
+    /// number of columns and the rows of its VALUES clause.
+    pub fn check_statement(&self, statement: &Statement) -> Result<()> {
+        match statement {
+            Statement::Query(query) => self.check_query(query),
+            Statement::Insert {
+                columns, source, ..
+            } => {
+                self.check_columns(columns.len())?;
+                self.check_query(source)
+            }
+            Statement::CreateTable { columns, .. } => self.check_columns(columns.len()),
+            Statement::Update {
+                assignments,
+                selection,
+                ..
+            } => {
+                self.check_columns(assignments.len())?;
+                let values = assignments.iter().map(|assignment| &assignment.value);
+                self.check_exprs(values.chain(selection))
+            }
+            Statement::Delete { selection, .. } => self.check_exprs(selection),
+            _ => Ok(()),
+        }
+    }
+
+    fn check_query(&self, query: &Query) -> Result<()> {
+        match &query.body {
+            SetExpr::Select(select) => {
+                self.check_columns(select.projection.len())?;
+                let projection = select.projection.iter().filter_map(|item| match item {
+                    SelectItem::UnnamedExpr(expr) | SelectItem::ExprWithAlias { expr, .. } => {
+                        Some(expr)
+                    }
+                    _ => None,
+                });
+                self.check_exprs(projection.chain(&select.selection))?;
+            }
+            SetExpr::Values(Values(rows)) => {
+                if rows.len() > self.get(Limit::ValuesRows) {
+                    return Err(SQLRiteError::General(format!(
+                        "too many rows in VALUES: {}, the limit is {}",
+                        rows.len(),
+                        self.get(Limit::ValuesRows)
+                    )));
+                }
+                for row in rows {
+                    self.check_columns(row.len())?;
+                    self.check_exprs(row)?;
+                }
+            }
+            _ => {}
+        }
+        self.check_exprs(query.order_by.iter().map(|term| &term.expr))
+    }
+
+    fn check_columns(&self, columns: usize) -> Result<()> {
+        if columns > self.get(Limit::Column) {
+            return Err(SQLRiteError::General(format!(
+                "too many columns: {}, the limit is {}",
+                columns,
+                self.get(Limit::Column)
+            )));
+        }
+        Ok(())
+    }
+
+    fn check_depth(&self, depth: usize) -> Result<()> {
+        if depth > self.get(Limit::ExprDepth) {
+            return Err(SQLRiteError::General(format!(
+                "Expression tree is too large (maximum depth {})",
+                self.get(Limit::ExprDepth)
+            )));
+        }
+        Ok(())
+    }
+
+    /// Checks the depth of each of `exprs`. The trees are walked without recursing, a tree
+    /// too deep must fail rather than overflow the stack.
+    fn check_exprs<'a>(&self, exprs: impl IntoIterator<Item = &'a Expr>) -> Result<()> {
+        let mut stack = exprs
+            .into_iter()
+            .map(|expr| (expr, 1))
+            .collect::<Vec<(&Expr, usize)>>();
+        while let Some((expr, depth)) = stack.pop() {
+            self.check_depth(depth)?;
+            stack.extend(children(expr).into_iter().map(|child| (child, depth + 1)));
+        }
+        Ok(())
+    }
+}
+
+/// The operands of `expr`, for the expressions the executor evaluates.
+fn children(expr: &Expr) -> Vec<&Expr> {
+    match expr {
+        Expr::BinaryOp { left, right, .. } => vec![left, right],
+        Expr::UnaryOp { expr, .. }
+        | Expr::Nested(expr)
+        | Expr::Collate { expr, .. }
+        | Expr::IsNull(expr)
+        | Expr::IsNotNull(expr)
+        | Expr::Cast { expr, .. } => vec![expr],
+        Expr::Between {
+            expr, low, high, ..
+        } => vec![expr, low, high],
+        Expr::Tuple(exprs) => exprs.iter().collect(),
+        Expr::InList { expr, list, .. } => {
+            let mut children = vec![expr.as_ref()];
+            children.extend(list);
+            children
+        }
+        Expr::Case {
+            operand,
+            conditions,
+            results,
+            else_result,
+        } => operand
+            .iter()
+            .map(|operand| operand.as_ref())
+            .chain(conditions)
+            .chain(results)
+            .chain(else_result.iter().map(|result| result.as_ref()))
+            .collect(),
+        Expr::Function(function) => function
+            .args
+            .iter()
+            .filter_map(|arg| match arg {
+                FunctionArg::Unnamed(FunctionArgExpr::Expr(expr)) => Some(expr),
+                _ => None,
+            })
+            .collect(),
+        Expr::Trim { expr, trim_where } => {
+            let mut children = vec![expr.as_ref()];
+            children.extend(trim_where.iter().map(|(_, chars)| chars.as_ref()));
+            children
+        }
+        Expr::Substring {
+            expr,
+            substring_from,
+            substring_for,
+        } => std::iter::once(expr)
+            .chain(substring_from)
+            .chain(substring_for)
+            .map(|expr| expr.as_ref())
+            .collect(),
+        _ => vec![],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sql::{parse_statement, tokenize};
+
+    fn check(limits: &Limits, sql: &str) -> Result<()> {
+        limits.check_sql(sql, &tokenize(sql)?)?;
+        limits.check_statement(&parse_statement(sql)?)
+    }
+
+    #[test]
+    fn limits_test() {
+        let mut limits = Limits::default();
+        assert_eq!(limits.get(Limit::ExprDepth), 1000);
+        assert_eq!(limits.set(Limit::ExprDepth, 3), 1000);
+        assert_eq!(limits.set(Limit::ExprDepth, -1), 3);
+        assert_eq!(limits.set(Limit::Attached, 100), MAX_ATTACHED);
+        assert_eq!(limits.get(Limit::Attached), MAX_ATTACHED);
+        assert_eq!(Limit::from_name("EXPR_DEPTH"), Some(Limit::ExprDepth));
+        assert_eq!(Limit::from_name("depth"), None);
+
+        assert!(check(&limits, "SELECT (1 + 2) FROM t WHERE a = 1;").is_ok());
+        assert!(check(&limits, "SELECT ((((1)))) FROM t;").is_err());
+        assert!(check(&limits, "SELECT * FROM t WHERE a = 1 AND b = 2 AND c = 3;").is_err());
+        assert!(check(&limits, "SELECT * FROM t ORDER BY abs(-(a + 1));").is_err());
+        assert!(check(&limits, "DELETE FROM t WHERE a IN (1, 2 + 3 * 4);").is_err());
+
+        limits.set(Limit::Column, 2);
+        limits.set(Limit::ValuesRows, 2);
+        assert!(check(&limits, "INSERT INTO t (a, b) VALUES (1, 2), (3, 4);").is_ok());
+        assert!(check(
+            &limits,
+            "INSERT INTO t (a, b) VALUES (1, 2), (3, 4), (5, 6);"
+        )
+        .is_err());
+        assert!(check(&limits, "INSERT INTO t (a, b, c) VALUES (1, 2, 3);").is_err());
+        assert!(check(&limits, "SELECT a, b, c FROM t;").is_err());
+        assert!(check(&limits, "CREATE TABLE t (a INTEGER, b TEXT, c TEXT);").is_err());
+
+        limits.set(Limit::SqlLength, 10);
+        assert!(check(&limits, "SELECT * FROM t;").is_err());
+
+        limits.set(Limit::Length, 3);
+        assert!(limits.check_length(&Value::Text("abc".to_string())).is_ok());
+        assert!(limits.check_length(&Value::Blob(vec![0; 4])).is_err());
+        assert!(limits.check_length(&Value::Integer(12345)).is_ok());
+        assert_eq!(Limit::from_name("sql_length"), Some(Limit::SqlLength));
+    }
+}
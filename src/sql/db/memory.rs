@@ -0,0 +1,198 @@
+//! Accounting of the memory used by the statements of a connection. Every buffer a
+//! statement fills, the rows it reads, the tuples it sorts and the results it
+//! materializes, is reserved with the `MemoryAccountant` of the database before it grows,
+//! and released when the statement is done with it.
+//!
+//! Two limits are set with PRAGMAs, like in SQLite. Past `PRAGMA hard_heap_limit` a
+//! reservation fails, and the statement with it, with an out of memory error instead of the
+//! process being killed. `PRAGMA soft_heap_limit` is advisory: once a statement went past
+//! it, the caches of the database are released, as they are when they grow past
+//! `PRAGMA cache_size`. The only caches for now are the bloom filters of the indexes, which
+//! are rebuilt from the index the next time they are needed.
+use std::mem::size_of;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::error::{Result, SQLRiteError};
+use crate::sql::value::Value;
+
+/// Size of a page of the cache, what a positive `PRAGMA cache_size` counts
+pub const PAGE_SIZE: usize = 4096;
+
+/// The memory reserved by the statements of a connection, and its limits
+#[derive(Debug, Default)]
+pub struct MemoryAccountant {
+    /// Bytes reserved at the moment
+    used: AtomicUsize,
+    /// Most bytes reserved at once since the database was opened
+    peak: AtomicUsize,
+    /// Most bytes reserved at once since the soft limit was last checked
+    recent_peak: AtomicUsize,
+    /// Advisory limit in bytes, 0 for none
+    soft_limit: AtomicUsize,
+    /// Limit in bytes past which reservations fail, 0 for none
+    hard_limit: AtomicUsize,
+}
+
+impl MemoryAccountant {
+    pub fn used(&self) -> usize {
+        self.used.load(Ordering::Relaxed)
+    }
+
+    pub fn peak(&self) -> usize {
+        self.peak.load(Ordering::Relaxed)
+    }
+
+    pub fn soft_limit(&self) -> usize {
+        self.soft_limit.load(Ordering::Relaxed)
+    }
+
+    pub fn hard_limit(&self) -> usize {
+        self.hard_limit.load(Ordering::Relaxed)
+    }
+
+    /// Sets the advisory limit, 0 for none.
+    pub fn set_soft_limit(&self, bytes: usize) {
+        self.soft_limit.store(bytes, Ordering::Relaxed);
+    }
+
+    /// Sets the limit past which reservations fail, 0 for none.
+    pub fn set_hard_limit(&self, bytes: usize) {
+        self.hard_limit.store(bytes, Ordering::Relaxed);
+    }
+
+    /// Returns true if the memory reserved went past the soft limit since the last time
+    /// this was checked.
+    pub fn take_soft_limit_exceeded(&self) -> bool {
+        let limit = self.soft_limit();
+        let peak = self.recent_peak.swap(self.used(), Ordering::Relaxed);
+        limit > 0 && peak > limit
+    }
+
+    /// Reserves `bytes`, released when the returned reservation is dropped.
+    pub fn reserve(&self, bytes: usize) -> Result<Reservation<'_>> {
+        let mut reservation = Reservation {
+            accountant: self,
+            bytes: 0,
+        };
+        reservation.resize(bytes)?;
+        Ok(reservation)
+    }
+
+    fn grow(&self, bytes: usize) -> Result<()> {
+        let used = self.used.fetch_add(bytes, Ordering::Relaxed) + bytes;
+        let limit = self.hard_limit();
+        if limit > 0 && used > limit {
+            self.used.fetch_sub(bytes, Ordering::Relaxed);
+            return Err(SQLRiteError::OutOfMemory(format!(
+                "a statement needs more than the {} bytes of the hard heap limit",
+                limit
+            )));
+        }
+        self.peak.fetch_max(used, Ordering::Relaxed);
+        self.recent_peak.fetch_max(used, Ordering::Relaxed);
+        Ok(())
+    }
+
+    fn shrink(&self, bytes: usize) {
+        self.used.fetch_sub(bytes, Ordering::Relaxed);
+    }
+}
+
+/// Accountants are equal if they have the same limits, what they have reserved depends on
+/// the statements running.
+impl PartialEq for MemoryAccountant {
+    fn eq(&self, other: &Self) -> bool {
+        self.soft_limit() == other.soft_limit() && self.hard_limit() == other.hard_limit()
+    }
+}
+
+/// Memory reserved by a buffer of a statement
+#[derive(Debug)]
+pub struct Reservation<'a> {
+    accountant: &'a MemoryAccountant,
+    bytes: usize,
+}
+
+impl Reservation<'_> {
+    pub fn bytes(&self) -> usize {
+        self.bytes
+    }
+
+    /// Grows or shrinks the reservation to `bytes`. Fails, keeping what was reserved, if
+    /// growing goes past the hard limit.
+    pub fn resize(&mut self, bytes: usize) -> Result<()> {
+        if bytes > self.bytes {
+            self.accountant.grow(bytes - self.bytes)?;
+        } else {
+            self.accountant.shrink(self.bytes - bytes);
+        }
+        self.bytes = bytes;
+        Ok(())
+    }
+}
+
+impl Drop for Reservation<'_> {
+    fn drop(&mut self) {
+        self.accountant.shrink(self.bytes);
+    }
+}
+
+/// Bytes of memory `cache_size` allows caches to use: a number of pages when positive, of
+/// KiB when negative.
+pub fn cache_budget(cache_size: i64) -> usize {
+    if cache_size < 0 {
+        cache_size.unsigned_abs() as usize * 1024
+    } else {
+        cache_size as usize * PAGE_SIZE
+    }
+}
+
+/// Estimated bytes of memory taken by `value`.
+pub fn value_size(value: &Value) -> usize {
+    size_of::<Value>()
+        + match value {
+            Value::Text(text) => text.capacity(),
+            Value::Blob(blob) => blob.capacity(),
+            _ => 0,
+        }
+}
+
+/// Estimated bytes of memory taken by `rows`.
+pub fn rows_size(rows: &[Vec<Value>]) -> usize {
+    rows.iter()
+        .map(|row| size_of::<Vec<Value>>() + row.iter().map(value_size).sum::<usize>())
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn memory_accountant_test() {
+        let accountant = MemoryAccountant::default();
+        accountant.set_hard_limit(1000);
+        {
+            let mut first = accountant.reserve(600).unwrap();
+            let second = accountant.reserve(300).unwrap();
+            assert_eq!(accountant.used(), 900);
+            assert!(matches!(
+                first.resize(800),
+                Err(SQLRiteError::OutOfMemory(_))
+            ));
+            assert_eq!(first.bytes(), 600);
+            drop(second);
+            first.resize(800).unwrap();
+            assert!(accountant.reserve(300).is_err());
+        }
+        assert_eq!(accountant.used(), 0);
+        assert_eq!(accountant.peak(), 900);
+
+        accountant.set_soft_limit(500);
+        assert!(accountant.take_soft_limit_exceeded());
+        assert!(!accountant.take_soft_limit_exceeded());
+
+        assert_eq!(cache_budget(-2000), 2000 * 1024);
+        assert_eq!(cache_budget(10), 10 * PAGE_SIZE);
+    }
+}
@@ -1,2 +1,28 @@
+pub mod audit;
+pub mod autosave;
+pub mod backup;
+pub mod bloom;
+pub mod btree;
+pub mod cache;
+pub mod changeset;
 pub mod database;
+pub mod diff;
+pub mod fault;
+pub mod file;
+pub mod format;
+pub mod journal;
+pub mod limits;
+pub mod memory;
+pub mod pager;
+pub mod privilege;
+pub mod progress;
+pub mod record;
+pub mod row_store;
+pub mod schema;
+pub mod snapshot_file;
+pub mod sqlite_file;
+pub mod stats;
 pub mod table;
+pub mod temp;
+pub mod transaction;
+pub mod trigger;
@@ -0,0 +1,807 @@
+//! The pager: a database file read and written as pages of `PAGE_SIZE` bytes, numbered
+//! from 0, the way SQLite's pager works. Page 0 holds the header of the file: the magic
+//! string telling it is a database file, the version of the file format, see `format`, the
+//! number of pages in it and the schema cookie. Pages are read
+//! into a cache the first time they are used, changed there, and written back to the
+//! file by `flush`, which syncs it to the disk. What the pages held before is written to
+//! the rollback journal first, so that a flush either writes all of them or, when it
+//! fails, none. The structures stored in the file, like the B+trees of the rows of the
+//! tables, are built on top of the pages.
+//!
+//! Pages no longer used, like those of deleted rows or dropped tables, are freed onto the
+//! free-list and reused by the next pages allocated before the file grows, like SQLite's
+//! freelist: trunk pages chained from the header, each listing free pages. A freed page
+//! is only written when it becomes a trunk itself.
+//!
+//! With the `mmap` feature, the start of the file can be memory-mapped instead, like with
+//! SQLite's `PRAGMA mmap_size`: the pages in the mapping that aren't changed are read from
+//! it without being copied into the cache, saving a system call and a copy for each page
+//! read. Pages written still go through the cache and are written to the file by `flush`.
+use std::collections::{BTreeSet, HashMap};
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use crate::error::{Result, SQLRiteError};
+use crate::sql::db::fault::FaultInjector;
+use crate::sql::db::format::{check_version, FORMAT_VERSION};
+use crate::sql::db::journal::{journal_path, Journal, JournalMode};
+use crate::sql::db::memory::PAGE_SIZE;
+#[cfg(feature = "mmap")]
+use memmap2::{Mmap, MmapOptions};
+
+/// Number of a page of a database file
+pub type PageId = u32;
+
+/// First bytes of every database file written by the pager
+pub const MAGIC: &[u8; 16] = b"SQLRite format 1";
+
+/// Bytes of the header at the start of page 0: the magic string, the page size, the number
+/// of pages, the first trunk page of the free-list, the number of free pages, the version
+/// of the file format and the schema cookie
+const HEADER_SIZE: usize = 40;
+/// Offset of the version of the file format in the header, 0 in the files written before
+/// there were versions
+const VERSION_OFFSET: usize = 32;
+
+/// Bytes of a trunk page of the free-list before the pages it lists: the next trunk page
+/// and the number of pages listed
+const TRUNK_HEADER: usize = 8;
+/// Number of free pages a trunk page lists at most
+const TRUNK_CAPACITY: usize = (PAGE_SIZE - TRUNK_HEADER) / 4;
+
+/// Greatest number of bytes of a file memory-mapped, SQLITE_MAX_MMAP_SIZE
+#[cfg(feature = "mmap")]
+pub const MAX_MMAP_SIZE: u64 = 0x7fff_0000;
+/// Greatest number of bytes of a file memory-mapped, none without the `mmap` feature
+#[cfg(not(feature = "mmap"))]
+pub const MAX_MMAP_SIZE: u64 = 0;
+
+/// Where the pages are kept
+#[derive(Debug)]
+enum Storage {
+    File { file: File, path: PathBuf },
+    Memory(Vec<Vec<u8>>),
+}
+
+/// The pages of a database file, cached in memory as they are read
+#[derive(Debug)]
+pub struct Pager {
+    storage: Storage,
+    /// Number of pages in the file, the header page included, once the cache is flushed
+    page_count: u32,
+    /// Number of pages the file had when it was last flushed
+    stored_page_count: u32,
+    /// First trunk page of the free-list, 0 when no page is free
+    free_trunk: PageId,
+    /// Number of pages on the free-list, the trunk pages included
+    free_count: u32,
+    /// Version of the file format the file was written in when it was opened
+    format_version: u32,
+    /// Schema version of the database stored in the file, changed with its schema
+    schema_cookie: u32,
+    /// How the original pages are journaled while the cache is flushed
+    journal_mode: JournalMode,
+    /// Number of pages rolled back from a hot journal when the file was opened
+    recovered: Option<usize>,
+    /// Bytes at the start of the file to memory-map, 0 to read every page from the file
+    mmap_size: u64,
+    /// The memory-mapped start of the file, as many whole pages of it as `mmap_size` allows
+    #[cfg(feature = "mmap")]
+    map: Option<Mmap>,
+    /// Pages read or written since the file was opened
+    cache: HashMap<PageId, Vec<u8>>,
+    /// Pages of the cache changed since they were read
+    dirty: BTreeSet<PageId>,
+    /// Faults injected into the writes of the journal and of the pages, to test how
+    /// flushes fail and are recovered
+    pub faults: FaultInjector,
+}
+
+impl Pager {
+    /// Opens the database file `path`, creating it with only its header page if it doesn't
+    /// exist or is empty. Fails if it isn't a database file. A hot journal left by a flush
+    /// that was interrupted is rolled back first, see `recovered`.
+    pub fn open(path: &Path) -> Result<Pager> {
+        let file_error = |err: std::io::Error| {
+            SQLRiteError::General(format!("cannot open {}: {}", path.display(), err))
+        };
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)
+            .map_err(file_error)?;
+        let mut pager = Pager::with_storage(Storage::File {
+            file,
+            path: path.to_path_buf(),
+        });
+        pager.recovered = pager.rollback()?;
+        let length = match &pager.storage {
+            Storage::File { file, .. } => file.metadata().map_err(file_error)?.len(),
+            Storage::Memory(_) => 0,
+        };
+        match length {
+            0 => pager.initialize(),
+            _ => pager.read_header()?,
+        }
+        Ok(pager)
+    }
+
+    /// A database kept in memory, with only its header page.
+    pub fn memory() -> Pager {
+        let mut pager = Pager::with_storage(Storage::Memory(vec![]));
+        pager.initialize();
+        pager
+    }
+
+    /// A database kept in memory, read from `bytes`, the pages of a database file one after
+    /// the other like `into_bytes` returns them. Fails if they aren't a database file.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Pager> {
+        let pages = bytes
+            .chunks(PAGE_SIZE)
+            .map(|page| {
+                let mut page = page.to_vec();
+                page.resize(PAGE_SIZE, 0);
+                page
+            })
+            .collect::<Vec<Vec<u8>>>();
+        let mut pager = Pager::with_storage(Storage::Memory(pages));
+        match bytes.len() {
+            0 => pager.initialize(),
+            _ => pager.read_header()?,
+        }
+        Ok(pager)
+    }
+
+    /// The pages of a database kept in memory as they were last flushed, one after the
+    /// other like in a database file. Empty for the pager of a file, whose bytes are the file.
+    pub fn into_bytes(self) -> Vec<u8> {
+        match self.storage {
+            Storage::Memory(mut pages) => {
+                pages.resize(self.stored_page_count as usize, vec![0; PAGE_SIZE]);
+                pages.concat()
+            }
+            Storage::File { .. } => vec![],
+        }
+    }
+
+    /// A pager of the pages kept in `storage`, which has no page until its header is
+    /// written or read.
+    fn with_storage(storage: Storage) -> Pager {
+        Pager {
+            storage,
+            page_count: 0,
+            stored_page_count: 0,
+            free_trunk: 0,
+            free_count: 0,
+            format_version: FORMAT_VERSION,
+            schema_cookie: 0,
+            journal_mode: JournalMode::Delete,
+            recovered: None,
+            mmap_size: 0,
+            #[cfg(feature = "mmap")]
+            map: None,
+            cache: HashMap::new(),
+            dirty: BTreeSet::new(),
+            faults: FaultInjector::default(),
+        }
+    }
+
+    /// Returns true if the file `path` starts like a database file written by the pager.
+    pub fn is_database_file(path: &Path) -> bool {
+        let mut magic = [0; MAGIC.len()];
+        File::open(path)
+            .and_then(|mut file| file.read_exact(&mut magic))
+            .is_ok()
+            && &magic == MAGIC
+    }
+
+    /// The version of the file format the database file `path` was written in, `None` if it
+    /// isn't a database file written by the pager.
+    pub fn format_version_of(path: &Path) -> Option<u32> {
+        let mut header = [0; HEADER_SIZE];
+        File::open(path)
+            .and_then(|mut file| file.read_exact(&mut header))
+            .ok()
+            .filter(|_| &header[..MAGIC.len()] == MAGIC)
+            .map(|_| read_version(&header))
+    }
+
+    /// The version of the file format the file was written in when it was opened. A file
+    /// is always flushed in the current version, `FORMAT_VERSION`, the header upgraded.
+    pub fn format_version(&self) -> u32 {
+        self.format_version
+    }
+
+    /// The schema cookie stored in the header, the schema version of the database in the file.
+    pub fn schema_cookie(&self) -> u32 {
+        self.schema_cookie
+    }
+
+    /// Stores `cookie` as the schema cookie in the header.
+    pub fn set_schema_cookie(&mut self, cookie: u32) {
+        self.schema_cookie = cookie;
+        self.write_header();
+    }
+
+    /// Sets how the original pages are journaled while the cache is flushed.
+    pub fn set_journal_mode(&mut self, mode: JournalMode) {
+        self.journal_mode = mode;
+    }
+
+    /// Memory-maps the first `size` bytes of the file, at most `MAX_MMAP_SIZE`, so that the
+    /// pages in them are read from the mapping rather than from the file. 0 reads every
+    /// page from the file, like a database kept in memory always does.
+    pub fn set_mmap_size(&mut self, size: u64) -> Result<()> {
+        self.mmap_size = size.min(MAX_MMAP_SIZE);
+        self.remap()
+    }
+
+    /// Number of bytes of the file memory-mapped.
+    pub fn mapped_size(&self) -> u64 {
+        self.mapped().map_or(0, |map| map.len() as u64)
+    }
+
+    /// Number of pages in the database, the header page included.
+    pub fn page_count(&self) -> u32 {
+        self.page_count
+    }
+
+    /// The page `id`, read from the file the first time it is used, or from the mapping of
+    /// the file while it is unchanged.
+    pub fn read(&mut self, id: PageId) -> Result<&[u8]> {
+        if id >= self.page_count {
+            return Err(malformed());
+        }
+        if !self.cache.contains_key(&id) && self.mapped_page(id).is_none() {
+            let page = self.read_stored(id)?;
+            self.cache.insert(id, page);
+        }
+        match self.cache.get(&id) {
+            Some(page) => Ok(page),
+            None => self.mapped_page(id).ok_or_else(malformed),
+        }
+    }
+
+    /// Replaces the content of the page `id` with `data`, padded with zeros to a page.
+    /// The page is written to the file when the pager is flushed.
+    pub fn write(&mut self, id: PageId, mut data: Vec<u8>) -> Result<()> {
+        if id >= self.page_count || data.len() > PAGE_SIZE {
+            return Err(SQLRiteError::Internal(format!(
+                "cannot write {} bytes to page {} of {}",
+                data.len(),
+                id,
+                self.page_count
+            )));
+        }
+        data.resize(PAGE_SIZE, 0);
+        self.cache.insert(id, data);
+        self.dirty.insert(id);
+        Ok(())
+    }
+
+    /// Number of pages on the free-list, waiting to be reused.
+    pub fn free_page_count(&self) -> u32 {
+        self.free_count
+    }
+
+    /// Adds an empty page to the database, returning its number: a page of the free-list
+    /// if there is one, otherwise a page at the end of the database.
+    pub fn allocate(&mut self) -> Result<PageId> {
+        let id = match self.free_trunk {
+            0 => {
+                self.page_count += 1;
+                self.page_count - 1
+            }
+            trunk => {
+                let mut data = self.read(trunk)?.to_vec();
+                let count = read_u32(&data, 4) as usize;
+                if count > TRUNK_CAPACITY {
+                    return Err(malformed());
+                }
+                let id = match count {
+                    // An empty trunk page is reused itself, the next one taking its place
+                    0 => {
+                        self.free_trunk = read_u32(&data, 0);
+                        trunk
+                    }
+                    _ => {
+                        let id = read_u32(&data, TRUNK_HEADER + (count - 1) * 4);
+                        data[4..8].copy_from_slice(&(count as u32 - 1).to_be_bytes());
+                        self.write(trunk, data)?;
+                        id
+                    }
+                };
+                if id == 0 || id >= self.page_count {
+                    return Err(malformed());
+                }
+                self.free_count = self.free_count.saturating_sub(1);
+                id
+            }
+        };
+        self.cache.insert(id, vec![0; PAGE_SIZE]);
+        self.dirty.insert(id);
+        self.write_header();
+        Ok(id)
+    }
+
+    /// Puts the page `id` on the free-list, to be reused by the next page allocated. What
+    /// it held is left as it was, unless it becomes a trunk page of the free-list.
+    pub fn free(&mut self, id: PageId) -> Result<()> {
+        if id == 0 || id >= self.page_count {
+            return Err(SQLRiteError::Internal(format!(
+                "cannot free page {} of {}",
+                id, self.page_count
+            )));
+        }
+        let trunk = self.free_trunk;
+        if trunk != 0 {
+            let mut data = self.read(trunk)?.to_vec();
+            let count = read_u32(&data, 4) as usize;
+            if count < TRUNK_CAPACITY {
+                let offset = TRUNK_HEADER + count * 4;
+                data[offset..offset + 4].copy_from_slice(&id.to_be_bytes());
+                data[4..8].copy_from_slice(&(count as u32 + 1).to_be_bytes());
+                self.write(trunk, data)?;
+                self.free_count += 1;
+                self.write_header();
+                return Ok(());
+            }
+        }
+        // The page becomes the first trunk page, listing no page yet
+        let mut data = trunk.to_be_bytes().to_vec();
+        data.extend_from_slice(&0u32.to_be_bytes());
+        self.write(id, data)?;
+        self.free_trunk = id;
+        self.free_count += 1;
+        self.write_header();
+        Ok(())
+    }
+
+    /// Writes the pages changed since the last flush to the file, and syncs it to the disk.
+    /// The pages are journaled first: if writing them fails, what they held is written back
+    /// and the error returned, the changes in the cache being lost.
+    pub fn flush(&mut self) -> Result<()> {
+        let journal = self.write_journal()?;
+        if let Err(err) = self.write_pages() {
+            if let Some(journal) = journal {
+                // A journal that couldn't be written back stays hot, to be rolled back later
+                let _ = self.restore(&journal);
+            }
+            return Err(err);
+        }
+        self.stored_page_count = self.page_count;
+        // The pages added since are mapped too
+        self.remap()?;
+        if let Storage::File { path, .. } = &self.storage {
+            self.faults.check("journal finish")?;
+            Journal::finish(&journal_path(path), self.journal_mode)?;
+        }
+        Ok(())
+    }
+
+    /// The number of pages rolled back when the file was opened, from the hot journal of a
+    /// flush that was interrupted, if there was one.
+    pub fn recovered(&self) -> Option<usize> {
+        self.recovered
+    }
+
+    /// Rolls the file back to what it held before a flush that didn't finish, from the hot
+    /// journal it left behind, returning the number of pages written back if there was one.
+    fn rollback(&mut self) -> Result<Option<usize>> {
+        let journal = match &self.storage {
+            Storage::File { path, .. } => Journal::read(&journal_path(path))?,
+            Storage::Memory(_) => None,
+        };
+        match journal {
+            Some(journal) => {
+                self.restore(&journal)?;
+                Ok(Some(journal.pages.len()))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Journals what the pages changed since the last flush held, writing the journal to
+    /// its file unless it is only kept in memory. `None` without a journal.
+    fn write_journal(&mut self) -> Result<Option<Journal>> {
+        if self.journal_mode == JournalMode::Off {
+            return Ok(None);
+        }
+        // The pages the file had are unchanged until the cache is flushed
+        let mut pages = vec![];
+        let dirty = self.dirty.clone();
+        for id in dirty.range(..self.stored_page_count) {
+            pages.push((*id, self.read_stored(*id)?));
+        }
+        let journal = Journal {
+            page_count: self.stored_page_count,
+            pages,
+        };
+        if let (Storage::File { path, .. }, true) =
+            (&self.storage, self.journal_mode != JournalMode::Memory)
+        {
+            journal.write(&journal_path(path), &mut self.faults)?;
+        }
+        Ok(Some(journal))
+    }
+
+    /// Writes the pages changed since the last flush where the pages are kept.
+    fn write_pages(&mut self) -> Result<()> {
+        let dirty = std::mem::take(&mut self.dirty);
+        for id in &dirty {
+            self.faults.check("page write")?;
+            let page = &self.cache[id];
+            match &mut self.storage {
+                Storage::File { file, path } => {
+                    let write_error = |err: std::io::Error| {
+                        SQLRiteError::General(format!("cannot write {}: {}", path.display(), err))
+                    };
+                    file.seek(SeekFrom::Start(*id as u64 * PAGE_SIZE as u64))
+                        .map_err(write_error)?;
+                    file.write_all(page).map_err(write_error)?;
+                }
+                Storage::Memory(pages) => {
+                    if pages.len() <= *id as usize {
+                        pages.resize(*id as usize + 1, vec![0; PAGE_SIZE]);
+                    }
+                    pages[*id as usize] = page.clone();
+                }
+            }
+        }
+        if let Storage::File { file, path } = &mut self.storage {
+            self.faults.check("sync")?;
+            file.sync_all().map_err(|err| {
+                SQLRiteError::General(format!("cannot write {}: {}", path.display(), err))
+            })?;
+        }
+        Ok(())
+    }
+
+    /// Writes the pages of `journal` back where the pages are kept, cutting the pages added
+    /// since, and finishes the journal file. The cache is emptied.
+    fn restore(&mut self, journal: &Journal) -> Result<()> {
+        self.cache.clear();
+        self.dirty.clear();
+        // Reading the pages cut from the mapping would fault, and some systems can't cut a
+        // file that is mapped
+        self.unmap();
+        match &mut self.storage {
+            Storage::File { file, path } => {
+                let write_error = |err: std::io::Error| {
+                    SQLRiteError::General(format!("cannot write {}: {}", path.display(), err))
+                };
+                for (id, page) in &journal.pages {
+                    self.faults.check("page write")?;
+                    file.seek(SeekFrom::Start(*id as u64 * PAGE_SIZE as u64))
+                        .map_err(write_error)?;
+                    file.write_all(page).map_err(write_error)?;
+                }
+                file.set_len(journal.page_count as u64 * PAGE_SIZE as u64)
+                    .and_then(|_| file.sync_all())
+                    .map_err(write_error)?;
+                // Only a finished journal is never rolled back again
+                Journal::finish(&journal_path(path), JournalMode::Delete)?;
+            }
+            Storage::Memory(pages) => {
+                for (id, page) in &journal.pages {
+                    pages[*id as usize] = page.clone();
+                }
+                pages.truncate(journal.page_count as usize);
+            }
+        }
+        self.page_count = journal.page_count;
+        self.stored_page_count = journal.page_count;
+        self.free_trunk = 0;
+        self.free_count = 0;
+        // The free-list is back to what the header written back says
+        if self.page_count > 0 {
+            self.read_header()?;
+        }
+        self.remap()
+    }
+
+    /// Maps the first `mmap_size` bytes of the file again, as many whole pages of them as
+    /// the file has, after its length changed.
+    #[cfg(feature = "mmap")]
+    fn remap(&mut self) -> Result<()> {
+        self.unmap();
+        let (file, path) = match &self.storage {
+            Storage::File { file, path } => (file, path),
+            Storage::Memory(_) => return Ok(()),
+        };
+        let map_error = |err: std::io::Error| {
+            SQLRiteError::General(format!("cannot map {}: {}", path.display(), err))
+        };
+        let length = file
+            .metadata()
+            .map_err(map_error)?
+            .len()
+            .min(self.mmap_size);
+        let length = length as usize / PAGE_SIZE * PAGE_SIZE;
+        if length > 0 {
+            // Safety: the file is only changed through the pager, which maps it again when
+            // its length changes. Like with SQLite, another process cutting the file while it
+            // is mapped makes reading the pages cut fault.
+            let map = unsafe { MmapOptions::new().len(length).map(file) }.map_err(map_error)?;
+            self.map = Some(map);
+        }
+        Ok(())
+    }
+
+    #[cfg(not(feature = "mmap"))]
+    fn remap(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    #[cfg(feature = "mmap")]
+    fn unmap(&mut self) {
+        self.map = None;
+    }
+
+    #[cfg(not(feature = "mmap"))]
+    fn unmap(&mut self) {}
+
+    /// The memory-mapped start of the file, if it is mapped.
+    #[cfg(feature = "mmap")]
+    fn mapped(&self) -> Option<&[u8]> {
+        self.map.as_deref()
+    }
+
+    #[cfg(not(feature = "mmap"))]
+    fn mapped(&self) -> Option<&[u8]> {
+        None
+    }
+
+    /// The page `id` in the mapping of the file, if it is mapped.
+    fn mapped_page(&self, id: PageId) -> Option<&[u8]> {
+        let start = id as usize * PAGE_SIZE;
+        self.mapped()?.get(start..start + PAGE_SIZE)
+    }
+
+    /// Writes the header of a new database into page 0.
+    fn initialize(&mut self) {
+        self.page_count = 1;
+        self.cache.insert(0, vec![0; PAGE_SIZE]);
+        self.write_header();
+    }
+
+    /// Writes the header, with the number of pages, the free-list and the schema cookie,
+    /// in the current version of the file format, into page 0.
+    fn write_header(&mut self) {
+        let (page_count, free_trunk, free_count, schema_cookie) = (
+            self.page_count,
+            self.free_trunk,
+            self.free_count,
+            self.schema_cookie,
+        );
+        let header = self.cache.entry(0).or_insert_with(|| vec![0; PAGE_SIZE]);
+        header[..MAGIC.len()].copy_from_slice(MAGIC);
+        header[16..20].copy_from_slice(&(PAGE_SIZE as u32).to_be_bytes());
+        header[20..24].copy_from_slice(&page_count.to_be_bytes());
+        header[24..28].copy_from_slice(&free_trunk.to_be_bytes());
+        header[28..VERSION_OFFSET].copy_from_slice(&free_count.to_be_bytes());
+        header[VERSION_OFFSET..36].copy_from_slice(&FORMAT_VERSION.to_be_bytes());
+        header[36..HEADER_SIZE].copy_from_slice(&schema_cookie.to_be_bytes());
+        self.dirty.insert(0);
+    }
+
+    /// Reads the number of pages, the free-list and the schema cookie from the header of
+    /// the file, checking it is a database in a version of the file format this one reads.
+    /// Files written before there was a free-list have none.
+    fn read_header(&mut self) -> Result<()> {
+        let header = self.read_stored(0)?;
+        if &header[..MAGIC.len()] != MAGIC {
+            return Err(SQLRiteError::General("file is not a database".to_string()));
+        }
+        self.format_version = read_version(&header);
+        check_version(self.format_version)?;
+        self.schema_cookie = read_u32(&header, 36);
+        if read_u32(&header, 16) as usize != PAGE_SIZE {
+            return Err(malformed());
+        }
+        self.page_count = read_u32(&header, 20);
+        self.stored_page_count = self.page_count;
+        self.free_trunk = read_u32(&header, 24);
+        self.free_count = read_u32(&header, 28);
+        if self.free_trunk >= self.page_count || self.free_count >= self.page_count {
+            return Err(malformed());
+        }
+        self.cache.insert(0, header);
+        Ok(())
+    }
+
+    /// Reads the page `id` from where the pages are kept, all zeros past the end.
+    fn read_stored(&mut self, id: PageId) -> Result<Vec<u8>> {
+        if let Some(page) = self.mapped_page(id) {
+            return Ok(page.to_vec());
+        }
+        let mut page = vec![0; PAGE_SIZE];
+        match &mut self.storage {
+            Storage::File { file, path } => {
+                let read_error = |err: std::io::Error| {
+                    SQLRiteError::General(format!("cannot read {}: {}", path.display(), err))
+                };
+                file.seek(SeekFrom::Start(id as u64 * PAGE_SIZE as u64))
+                    .map_err(read_error)?;
+                let mut read = 0;
+                while read < PAGE_SIZE {
+                    match file.read(&mut page[read..]).map_err(read_error)? {
+                        0 => break,
+                        n => read += n,
+                    }
+                }
+            }
+            Storage::Memory(pages) => {
+                if let Some(stored) = pages.get(id as usize) {
+                    page.copy_from_slice(stored);
+                }
+            }
+        }
+        Ok(page)
+    }
+}
+
+/// The error of a database file whose pages don't hold what they should.
+pub fn malformed() -> SQLRiteError {
+    SQLRiteError::General("database disk image is malformed".to_string())
+}
+
+/// The version of the file format in `header`, files written before there were versions
+/// being in version 1.
+fn read_version(header: &[u8]) -> u32 {
+    read_u32(header, VERSION_OFFSET).max(1)
+}
+
+/// Reads the big-endian integer at `offset` of `bytes`.
+pub fn read_u32(bytes: &[u8], offset: usize) -> u32 {
+    let mut be = [0; 4];
+    be.copy_from_slice(&bytes[offset..offset + 4]);
+    u32::from_be_bytes(be)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pager_test() {
+        let path = std::env::temp_dir().join(format!("sqlrite-pager-{}.db", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let mut pager = Pager::open(&path).unwrap();
+        assert_eq!(pager.page_count(), 1);
+        let id = pager.allocate().unwrap();
+        assert_eq!(id, 1);
+        pager.write(id, b"rows".to_vec()).unwrap();
+        assert!(pager.write(5, vec![]).is_err());
+        pager.flush().unwrap();
+        assert!(Pager::is_database_file(&path));
+        assert_eq!(
+            std::fs::metadata(&path).unwrap().len(),
+            2 * PAGE_SIZE as u64
+        );
+
+        let mut pager = Pager::open(&path).unwrap();
+        assert_eq!(pager.page_count(), 2);
+        assert_eq!(&pager.read(1).unwrap()[..4], b"rows");
+        assert!(pager.read(2).is_err());
+        std::fs::remove_file(&path).unwrap();
+
+        std::fs::write(&path, "not a database").unwrap();
+        assert!(!Pager::is_database_file(&path));
+        assert!(Pager::open(&path).is_err());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn rollback_test() {
+        let path = std::env::temp_dir().join(format!("sqlrite-rollback-{}.db", std::process::id()));
+        let journal = journal_path(&path);
+        let _ = std::fs::remove_file(&path);
+        let mut pager = Pager::open(&path).unwrap();
+        let id = pager.allocate().unwrap();
+        pager.write(id, b"before".to_vec()).unwrap();
+        pager.flush().unwrap();
+        assert!(!journal.exists());
+
+        // A flush interrupted once the pages are written leaves a hot journal
+        pager.write(id, b"after".to_vec()).unwrap();
+        let added = pager.allocate().unwrap();
+        pager.write(added, b"added".to_vec()).unwrap();
+        pager.write_journal().unwrap();
+        pager.write_pages().unwrap();
+        drop(pager);
+        assert!(journal.exists());
+        assert_eq!(
+            std::fs::metadata(&path).unwrap().len(),
+            3 * PAGE_SIZE as u64
+        );
+
+        let mut pager = Pager::open(&path).unwrap();
+        assert_eq!(pager.recovered(), Some(2));
+        assert!(!journal.exists());
+        assert_eq!(pager.page_count(), 2);
+        assert_eq!(&pager.read(id).unwrap()[..6], b"before");
+        assert_eq!(Pager::open(&path).unwrap().recovered(), None);
+        assert_eq!(
+            std::fs::metadata(&path).unwrap().len(),
+            2 * PAGE_SIZE as u64
+        );
+
+        // Only the pages kept in memory are journaled
+        pager.set_journal_mode(JournalMode::Memory);
+        pager.write(id, b"after".to_vec()).unwrap();
+        let kept = pager.write_journal().unwrap().unwrap();
+        assert_eq!(kept.page_count, 2);
+        assert_eq!(kept.pages.len(), 1);
+        assert!(!journal.exists());
+        pager.set_journal_mode(JournalMode::Off);
+        assert_eq!(pager.write_journal().unwrap(), None);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn free_list_test() {
+        let path = std::env::temp_dir().join(format!("sqlrite-free-{}.db", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let mut pager = Pager::open(&path).unwrap();
+        let pages = (0..TRUNK_CAPACITY + 10)
+            .map(|_| pager.allocate().unwrap())
+            .collect::<Vec<PageId>>();
+        assert!(pager.free(0).is_err());
+        // Enough pages freed for the free-list to take two trunk pages
+        for id in &pages {
+            pager.free(*id).unwrap();
+        }
+        assert_eq!(pager.free_page_count(), pages.len() as u32);
+        pager.flush().unwrap();
+
+        let mut pager = Pager::open(&path).unwrap();
+        assert_eq!(pager.free_page_count(), pages.len() as u32);
+        let page_count = pager.page_count();
+        let mut reused = (0..pages.len())
+            .map(|_| pager.allocate().unwrap())
+            .collect::<Vec<PageId>>();
+        assert_eq!(pager.free_page_count(), 0);
+        assert_eq!(pager.page_count(), page_count);
+        assert_eq!(pager.read(reused[0]).unwrap(), &[0; PAGE_SIZE][..]);
+        reused.sort_unstable();
+        assert_eq!(reused, pages);
+        // Once the free-list is empty the file grows again
+        assert_eq!(pager.allocate().unwrap(), page_count);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[cfg(feature = "mmap")]
+    #[test]
+    fn mmap_test() {
+        let path = std::env::temp_dir().join(format!("sqlrite-mmap-{}.db", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let mut pager = Pager::open(&path).unwrap();
+        let id = pager.allocate().unwrap();
+        pager.write(id, b"mapped".to_vec()).unwrap();
+        pager.flush().unwrap();
+
+        let mut pager = Pager::open(&path).unwrap();
+        pager.set_mmap_size(PAGE_SIZE as u64 + 1).unwrap();
+        assert_eq!(pager.mapped_size(), PAGE_SIZE as u64);
+        pager.set_mmap_size(MAX_MMAP_SIZE).unwrap();
+        assert_eq!(pager.mapped_size(), 2 * PAGE_SIZE as u64);
+        assert_eq!(&pager.read(id).unwrap()[..6], b"mapped");
+        assert!(!pager.cache.contains_key(&id));
+
+        // Pages written are read from the cache, and mapped once flushed
+        pager.write(id, b"written".to_vec()).unwrap();
+        let added = pager.allocate().unwrap();
+        assert_eq!(&pager.read(id).unwrap()[..7], b"written");
+        pager.flush().unwrap();
+        assert_eq!(pager.mapped_size(), 3 * PAGE_SIZE as u64);
+        assert_eq!(pager.mapped_page(added), Some(&[0; PAGE_SIZE][..]));
+        assert_eq!(&pager.mapped_page(id).unwrap()[..7], b"written");
+        pager.set_mmap_size(0).unwrap();
+        assert_eq!(pager.mapped_size(), 0);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,316 @@
+//! Users and the privileges granted to them, for a server to restrict what its clients can
+//! do. Statements run without a user, like those of the application embedding the
+//! database, may do anything; those run as a user created with `CREATE USER` need a
+//! privilege for every table they read or change:
+//!
+//! - `SELECT` to read a table, `INSERT`, `UPDATE` and `DELETE` to change its rows;
+//! - `DDL` to create or drop triggers on a table, or, granted on the whole database, to
+//!   create tables, attach databases and change settings with PRAGMA statements.
+//!
+//! Privileges are granted on a table with `GRANT SELECT, INSERT ON users TO name`, or on
+//! every table with `GRANT SELECT ON DATABASE TO name`, and revoked with `REVOKE`. Only
+//! statements run without a user can manage users and privileges. The statements of
+//! triggers aren't checked, creating a trigger needs the `DDL` privilege on its table.
+use hmac::Hmac;
+use pbkdf2::pbkdf2;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use subtle::ConstantTimeEq;
+
+use crate::error::{Result, SQLRiteError};
+
+/// Start of the message of the errors of statements a user isn't allowed to run
+pub const PERMISSION_DENIED: &str = "permission denied";
+
+/// Returns true if `err` is the error of a statement its user isn't allowed to run.
+pub fn is_permission_denied(err: &SQLRiteError) -> bool {
+    matches!(err, SQLRiteError::General(message) if message.starts_with(PERMISSION_DENIED))
+}
+
+/// What a privilege allows a user to do
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone, Copy)]
+pub enum Privilege {
+    Select,
+    Insert,
+    Update,
+    Delete,
+    /// Changing the schema
+    Ddl,
+}
+
+impl Privilege {
+    /// Every privilege, granted by `GRANT ALL`
+    pub const ALL: [Privilege; 5] = [
+        Privilege::Select,
+        Privilege::Insert,
+        Privilege::Update,
+        Privilege::Delete,
+        Privilege::Ddl,
+    ];
+
+    /// The privilege named `name` in GRANT and REVOKE statements.
+    pub fn from_name(name: &str) -> Option<Privilege> {
+        match name.to_uppercase().as_str() {
+            "SELECT" => Some(Privilege::Select),
+            "INSERT" => Some(Privilege::Insert),
+            "UPDATE" => Some(Privilege::Update),
+            "DELETE" => Some(Privilege::Delete),
+            "DDL" => Some(Privilege::Ddl),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Privilege::Select => "SELECT",
+            Privilege::Insert => "INSERT",
+            Privilege::Update => "UPDATE",
+            Privilege::Delete => "DELETE",
+            Privilege::Ddl => "DDL",
+        }
+    }
+}
+
+/// A privilege granted to a user
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub struct Grant {
+    pub user: String,
+    pub privilege: Privilege,
+    /// The table the privilege applies to, `None` for every table of the database
+    pub table: Option<String>,
+}
+
+/// Iterations of PBKDF2 the passwords of new users are hashed with, as OWASP recommends
+/// for PBKDF2-HMAC-SHA256. Fewer in tests, which create users in unoptimized builds.
+const PASSWORD_ITERATIONS: u32 = if cfg!(test) { 1_000 } else { 600_000 };
+
+/// A user created with `CREATE USER`
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub struct User {
+    pub name: String,
+    /// Salt the password is hashed with, from the random source of the OS, in hexadecimal
+    salt: String,
+    /// PBKDF2-HMAC-SHA256 of the password with the salt, in hexadecimal
+    password_hash: String,
+    /// Iterations of PBKDF2 the password is hashed with
+    iterations: u32,
+}
+
+impl User {
+    fn new(name: &str, password: &str, iterations: u32) -> Result<User> {
+        let mut salt = [0u8; 16];
+        getrandom::getrandom(&mut salt).map_err(|err| {
+            SQLRiteError::General(format!("cannot generate a password salt: {}", err))
+        })?;
+        let salt = hex(&salt);
+        Ok(User {
+            name: name.to_string(),
+            password_hash: hash_password(&salt, password, iterations),
+            salt,
+            iterations,
+        })
+    }
+
+    /// Returns true if `password` is the password of the user. Hashing it takes a while on
+    /// purpose, servers check passwords away from the thread running statements.
+    pub fn check_password(&self, password: &str) -> bool {
+        let hash = hash_password(&self.salt, password, self.iterations);
+        // In constant time, so the time taken doesn't tell how much of the hash matched
+        hash.as_bytes().ct_eq(self.password_hash.as_bytes()).into()
+    }
+}
+
+/// The hash of `password` with `salt` after `iterations` of PBKDF2-HMAC-SHA256, in
+/// hexadecimal.
+fn hash_password(salt: &str, password: &str, iterations: u32) -> String {
+    let mut hash = [0u8; 32];
+    pbkdf2::<Hmac<Sha256>>(password.as_bytes(), salt.as_bytes(), iterations, &mut hash);
+    hex(&hash)
+}
+
+/// The users of a database and their privileges
+#[derive(Serialize, Deserialize, PartialEq, Debug, Default)]
+pub struct AccessControl {
+    pub users: Vec<User>,
+    pub grants: Vec<Grant>,
+}
+
+impl AccessControl {
+    /// Creates the user `name`, failing if it exists.
+    pub fn create_user(&mut self, name: &str, password: &str) -> Result<()> {
+        if self.contains_user(name) {
+            return Err(SQLRiteError::General(format!(
+                "user {} already exists",
+                name
+            )));
+        }
+        self.users
+            .push(User::new(name, password, PASSWORD_ITERATIONS)?);
+        Ok(())
+    }
+
+    /// Drops the user `name` with its privileges, failing if there is none unless
+    /// `if_exists` is set.
+    pub fn drop_user(&mut self, name: &str, if_exists: bool) -> Result<()> {
+        match self.users.iter().position(|user| user.name == name) {
+            Some(position) => {
+                self.users.remove(position);
+                self.grants.retain(|grant| grant.user != name);
+                Ok(())
+            }
+            None if if_exists => Ok(()),
+            None => Err(no_such_user(name)),
+        }
+    }
+
+    /// Returns true if there is a user `name`.
+    pub fn contains_user(&self, name: &str) -> bool {
+        self.users.iter().any(|user| user.name == name)
+    }
+
+    /// The user `name`, if there is one.
+    pub fn user(&self, name: &str) -> Option<&User> {
+        self.users.iter().find(|user| user.name == name)
+    }
+
+    /// Returns true if `password` is the password of the user `name`.
+    pub fn authenticate(&self, name: &str, password: &str) -> bool {
+        self.user(name)
+            .is_some_and(|user| user.check_password(password))
+    }
+
+    /// Grants `privileges` on `table`, every table if `None`, to the user `name`.
+    pub fn grant(
+        &mut self,
+        privileges: &[Privilege],
+        table: Option<&str>,
+        name: &str,
+    ) -> Result<()> {
+        if !self.contains_user(name) {
+            return Err(no_such_user(name));
+        }
+        for privilege in privileges {
+            let grant = Grant {
+                user: name.to_string(),
+                privilege: *privilege,
+                table: table.map(|table| table.to_string()),
+            };
+            if !self.grants.contains(&grant) {
+                self.grants.push(grant);
+            }
+        }
+        Ok(())
+    }
+
+    /// Revokes `privileges` granted on `table`, every table if `None`, from the user `name`.
+    /// Like in PostgreSQL, revoking a privilege on a table leaves it granted on the
+    /// database if it was.
+    pub fn revoke(
+        &mut self,
+        privileges: &[Privilege],
+        table: Option<&str>,
+        name: &str,
+    ) -> Result<()> {
+        if !self.contains_user(name) {
+            return Err(no_such_user(name));
+        }
+        self.grants.retain(|grant| {
+            grant.user != name
+                || grant.table.as_deref() != table
+                || !privileges.contains(&grant.privilege)
+        });
+        Ok(())
+    }
+
+    /// Returns true if the user `name` was granted `privilege` on `table`, or on the whole
+    /// database. `table` is `None` for what needs the privilege on the whole database.
+    pub fn allows(&self, name: &str, privilege: Privilege, table: Option<&str>) -> bool {
+        self.grants.iter().any(|grant| {
+            grant.user == name
+                && grant.privilege == privilege
+                && (grant.table.is_none() || grant.table.as_deref() == table)
+        })
+    }
+}
+
+fn no_such_user(name: &str) -> SQLRiteError {
+    SQLRiteError::General(format!("no such user: {}", name))
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sql::db::database::Database;
+    use crate::sql::{process_command, process_query};
+
+    #[test]
+    fn hash_password_test() {
+        // RFC 7914, section 11
+        assert_eq!(
+            hash_password("salt", "passwd", 1),
+            "55ac046e56e3089fec1691c22544b605f94185216dde0465e68b9d57c20dacbc"
+        );
+        assert_eq!(
+            hash_password("salt", "password", 4096),
+            "c5e478d59288c841aa530db6845c4c8d962893a001ce4e11a4963873aa98134a"
+        );
+    }
+
+    #[test]
+    fn password_test() {
+        let mut access = AccessControl::default();
+        access.create_user("reporting", "pw").unwrap();
+        access.create_user("audit", "pw").unwrap();
+        assert_eq!(access.users[0].iterations, PASSWORD_ITERATIONS);
+        assert_ne!(access.users[0].salt, access.users[1].salt);
+        assert_ne!(access.users[0].password_hash, access.users[1].password_hash);
+        assert!(access.authenticate("reporting", "pw"));
+        assert!(!access.authenticate("reporting", "pw2"));
+        assert!(!access.authenticate("nobody", "pw"));
+        assert!(access.user("audit").unwrap().check_password("pw"));
+    }
+
+    #[test]
+    fn privileges_test() {
+        let mut db = Database::new("tempdb".to_string());
+        process_command(
+            "CREATE TABLE users (id INTEGER PRIMARY KEY, name TEXT);",
+            &mut db,
+        )
+        .unwrap();
+        process_command("CREATE TABLE secrets (id INTEGER PRIMARY KEY);", &mut db).unwrap();
+        process_command("CREATE USER reporting WITH PASSWORD 'pw';", &mut db).unwrap();
+        process_command("GRANT SELECT ON users TO reporting;", &mut db).unwrap();
+        assert!(db.access.authenticate("reporting", "pw"));
+        assert!(!db.access.authenticate("reporting", "wrong"));
+        assert!(process_command("GRANT SELECT ON missing TO reporting;", &mut db).is_err());
+        assert!(process_command("GRANT SELECT ON users TO nobody;", &mut db).is_err());
+
+        db.current_user = Some("reporting".to_string());
+        process_query("SELECT * FROM users;", &db).unwrap();
+        assert!(process_query("SELECT * FROM secrets;", &db).is_err());
+        assert!(process_command("INSERT INTO users (name) VALUES ('josh');", &mut db).is_err());
+        assert!(process_command("CREATE TABLE mine (id INTEGER);", &mut db).is_err());
+        assert!(process_command("PRAGMA user_version = 3;", &mut db).is_err());
+        // Only the owner manages privileges
+        assert!(process_command("GRANT INSERT ON users TO reporting;", &mut db).is_err());
+
+        db.current_user = None;
+        process_command("GRANT INSERT, DELETE ON DATABASE TO reporting;", &mut db).unwrap();
+        db.current_user = Some("reporting".to_string());
+        process_command("INSERT INTO secrets (id) VALUES (1);", &mut db).unwrap();
+        assert!(process_query("SELECT * FROM secrets;", &db).is_err());
+
+        db.current_user = None;
+        process_command("REVOKE ALL PRIVILEGES ON DATABASE FROM reporting;", &mut db).unwrap();
+        process_command("REVOKE SELECT ON users FROM reporting;", &mut db).unwrap();
+        assert!(db.access.grants.is_empty());
+        process_command("DROP USER reporting;", &mut db).unwrap();
+        assert!(process_command("DROP USER reporting;", &mut db).is_err());
+        process_command("DROP USER IF EXISTS reporting;", &mut db).unwrap();
+    }
+}
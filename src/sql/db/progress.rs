@@ -0,0 +1,97 @@
+//! The progress handler of a connection, like `sqlite3_progress_handler()` in SQLite. The
+//! executor counts a step for each row it reads, filters, inserts or deletes, and calls the
+//! handler every N steps. A handler returning true interrupts the statement running, which
+//! fails with `SQLRiteError::Interrupted` and undoes its changes, so that an application
+//! can keep its UI responsive during a long statement and offer to cancel it.
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+use crate::error::{Result, SQLRiteError};
+
+/// A progress handler, returning true to interrupt the statement running
+pub type ProgressFn = dyn FnMut() -> bool + Send + 'static;
+
+/// The progress handler of a connection, with the steps counted since it was last called
+#[derive(Default)]
+pub struct Progress {
+    /// Steps between two calls of the handler
+    interval: u64,
+    handler: Option<Mutex<Box<ProgressFn>>>,
+    /// Steps counted since the handler was last called
+    steps: AtomicU64,
+}
+
+impl Progress {
+    /// Sets the handler called every `interval` steps, or removes it when it is `None` or
+    /// `interval` is 0.
+    pub fn set(&mut self, interval: u64, handler: Option<Box<ProgressFn>>) {
+        self.handler = handler.filter(|_| interval > 0).map(Mutex::new);
+        self.interval = interval;
+        self.steps.store(0, Ordering::Relaxed);
+    }
+
+    /// Counts a step of the statement running, calling the handler if it is time to.
+    /// Fails if the handler interrupts the statement.
+    pub fn step(&self) -> Result<()> {
+        let handler = match &self.handler {
+            Some(handler) => handler,
+            None => return Ok(()),
+        };
+        if self.steps.fetch_add(1, Ordering::Relaxed) + 1 < self.interval {
+            return Ok(());
+        }
+        self.steps.store(0, Ordering::Relaxed);
+        let mut handler = handler.lock().unwrap_or_else(|err| err.into_inner());
+        if handler() {
+            return Err(SQLRiteError::Interrupted);
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Debug for Progress {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Progress")
+            .field("interval", &self.interval)
+            .field("handler", &self.handler.is_some())
+            .finish()
+    }
+}
+
+/// Progress handlers are always equal, they belong to the connection, not to the database.
+impl PartialEq for Progress {
+    fn eq(&self, _: &Self) -> bool {
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::AtomicUsize;
+    use std::sync::Arc;
+
+    #[test]
+    fn progress_test() {
+        let mut progress = Progress::default();
+        assert!(progress.step().is_ok());
+
+        let calls = Arc::new(AtomicUsize::new(0));
+        let counted = Arc::clone(&calls);
+        progress.set(
+            3,
+            Some(Box::new(move || {
+                counted.fetch_add(1, Ordering::Relaxed) + 1 >= 2
+            })),
+        );
+        for _ in 0..5 {
+            progress.step().unwrap();
+        }
+        assert_eq!(calls.load(Ordering::Relaxed), 1);
+        assert_eq!(progress.step(), Err(SQLRiteError::Interrupted));
+
+        progress.set(0, Some(Box::new(|| true)));
+        assert!(progress.step().is_ok());
+    }
+}
@@ -0,0 +1,107 @@
+//! Records: the values of a row encoded as bytes, the way rows are stored in the B+trees
+//! of a database file. Each value is a byte telling its storage class, followed by the
+//! value itself: integers and reals in 8 big-endian bytes, booleans in one byte, and text
+//! and blobs in as many bytes as the 4 bytes before them tell. NULL is the byte alone.
+use std::convert::TryInto;
+
+use crate::error::Result;
+use crate::sql::db::pager::malformed;
+use crate::sql::value::Value;
+
+const NULL: u8 = 0;
+const INTEGER: u8 = 1;
+const REAL: u8 = 2;
+const TEXT: u8 = 3;
+const BOOL: u8 = 4;
+const BLOB: u8 = 5;
+
+/// Encodes the values of a row.
+pub fn encode_record(values: &[Value]) -> Vec<u8> {
+    let mut bytes = vec![];
+    for value in values {
+        match value {
+            Value::Null => bytes.push(NULL),
+            Value::Integer(n) => {
+                bytes.push(INTEGER);
+                bytes.extend_from_slice(&n.to_be_bytes());
+            }
+            Value::Real(r) => {
+                bytes.push(REAL);
+                bytes.extend_from_slice(&r.to_be_bytes());
+            }
+            Value::Bool(b) => {
+                bytes.push(BOOL);
+                bytes.push(*b as u8);
+            }
+            Value::Text(text) => {
+                bytes.push(TEXT);
+                bytes.extend_from_slice(&(text.len() as u32).to_be_bytes());
+                bytes.extend_from_slice(text.as_bytes());
+            }
+            Value::Blob(blob) => {
+                bytes.push(BLOB);
+                bytes.extend_from_slice(&(blob.len() as u32).to_be_bytes());
+                bytes.extend_from_slice(blob);
+            }
+        }
+    }
+    bytes
+}
+
+/// Decodes the values of a row encoded by `encode_record`.
+pub fn decode_record(bytes: &[u8]) -> Result<Vec<Value>> {
+    let mut values = vec![];
+    let mut rest = bytes;
+    while let Some((&class, tail)) = rest.split_first() {
+        rest = tail;
+        let value = match class {
+            NULL => Value::Null,
+            INTEGER => Value::Integer(i64::from_be_bytes(take(&mut rest, 8)?.try_into().unwrap())),
+            REAL => Value::Real(f64::from_be_bytes(take(&mut rest, 8)?.try_into().unwrap())),
+            BOOL => Value::Bool(take(&mut rest, 1)?[0] != 0),
+            TEXT | BLOB => {
+                let length = u32::from_be_bytes(take(&mut rest, 4)?.try_into().unwrap());
+                let data = take(&mut rest, length as usize)?.to_vec();
+                match class {
+                    TEXT => Value::Text(String::from_utf8(data).map_err(|_| malformed())?),
+                    _ => Value::Blob(data),
+                }
+            }
+            _ => return Err(malformed()),
+        };
+        values.push(value);
+    }
+    Ok(values)
+}
+
+/// Takes the first `count` bytes of `rest`.
+fn take<'a>(rest: &mut &'a [u8], count: usize) -> Result<&'a [u8]> {
+    if rest.len() < count {
+        return Err(malformed());
+    }
+    let (taken, tail) = rest.split_at(count);
+    *rest = tail;
+    Ok(taken)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn record_test() {
+        let values = vec![
+            Value::Integer(-7),
+            Value::Null,
+            Value::Real(2.5),
+            Value::Text("héllo".to_string()),
+            Value::Bool(true),
+            Value::Blob(vec![0, 255]),
+        ];
+        let bytes = encode_record(&values);
+        assert_eq!(decode_record(&bytes).unwrap(), values);
+        assert_eq!(decode_record(&[]).unwrap(), vec![]);
+        assert!(decode_record(&bytes[..bytes.len() - 1]).is_err());
+        assert!(decode_record(&[9]).is_err());
+    }
+}
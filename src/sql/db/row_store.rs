@@ -0,0 +1,137 @@
+//! The rows of a table: a B+tree keyed by ROWID in the pages of a pager of its own, each
+//! row a record of its values in the order of the columns of the table, see `record`.
+//! Statements insert, look up, scan and delete the rows of a table through its tree.
+//!
+//! The pages of the tree are kept in memory by its pager. The rows of a table of a database
+//! file are read from the tree of the file into the tree of the table when the database is
+//! opened, and those that changed are written back on a save, see `file`. Since the pages
+//! are only ever written by the tree, reading or writing them can only fail on a bug, which
+//! panics instead of failing the statement.
+use std::cell::RefCell;
+use std::fmt;
+use std::ops::RangeBounds;
+
+use crate::error::Result;
+use crate::sql::db::btree::BTree;
+use crate::sql::db::pager::Pager;
+use crate::sql::db::record::{decode_record, encode_record};
+use crate::sql::value::Value;
+
+/// The rows of a table, in a B+tree keyed by ROWID
+pub struct RowStore {
+    /// The pages of the tree, behind a `RefCell` since reading a page caches it
+    pager: RefCell<Pager>,
+    tree: BTree,
+}
+
+impl RowStore {
+    /// An empty tree of rows.
+    pub fn new() -> Self {
+        let mut pager = Pager::memory();
+        let tree = intact(BTree::create(&mut pager));
+        RowStore {
+            pager: RefCell::new(pager),
+            tree,
+        }
+    }
+
+    /// Stores `values` as the row `rowid`, replacing the row it was.
+    pub fn insert(&mut self, rowid: i64, values: &[Value]) {
+        let record = encode_record(values);
+        intact(self.tree.insert(self.pager.get_mut(), rowid, &record));
+    }
+
+    /// The values of the row `rowid`, `None` if there is no such row.
+    pub fn get(&self, rowid: i64) -> Option<Vec<Value>> {
+        let record = intact(self.tree.get(&mut self.pager.borrow_mut(), rowid));
+        record.map(|record| intact(decode_record(&record)))
+    }
+
+    /// The rows whose ROWID is in `range`, in ROWID order, read leaf after leaf.
+    pub fn scan(&self, range: impl RangeBounds<i64>) -> Vec<(i64, Vec<Value>)> {
+        intact(self.tree.scan(&mut self.pager.borrow_mut(), range))
+            .into_iter()
+            .map(|(rowid, record)| (rowid, intact(decode_record(&record))))
+            .collect()
+    }
+
+    /// The ROWID of every row, in order.
+    pub fn rowids(&self) -> Vec<i64> {
+        intact(self.tree.rowids(&mut self.pager.borrow_mut()))
+    }
+
+    /// Removes the row `rowid`, returning true if there was one.
+    pub fn delete(&mut self, rowid: i64) -> bool {
+        intact(self.tree.delete(self.pager.get_mut(), rowid))
+    }
+}
+
+impl Default for RowStore {
+    fn default() -> Self {
+        RowStore::new()
+    }
+}
+
+impl PartialEq for RowStore {
+    fn eq(&self, other: &Self) -> bool {
+        self.scan(..) == other.scan(..)
+    }
+}
+
+impl fmt::Debug for RowStore {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_map().entries(self.scan(..)).finish()
+    }
+}
+
+/// The result of reading or writing the pages of the rows, which the tree alone writes.
+fn intact<T>(result: Result<T>) -> T {
+    result.unwrap_or_else(|err| panic!("the B+tree of the rows of a table is corrupt: {}", err))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn row_store_test() {
+        let mut rows = RowStore::new();
+        // In no order, some too big for a leaf
+        for rowid in (1..=1000).rev() {
+            let text = match rowid % 100 {
+                0 => "x".repeat(5000),
+                _ => format!("row {}", rowid),
+            };
+            rows.insert(
+                rowid,
+                &[Value::Integer(rowid), Value::Text(text), Value::Null],
+            );
+        }
+        assert_eq!(
+            rows.get(42),
+            Some(vec![
+                Value::Integer(42),
+                Value::Text("row 42".to_string()),
+                Value::Null
+            ])
+        );
+        assert_eq!(rows.get(1001), None);
+        assert_eq!(rows.rowids(), (1..=1000).collect::<Vec<i64>>());
+        let range = rows.scan(10..13);
+        assert_eq!(
+            range.iter().map(|(rowid, _)| *rowid).collect::<Vec<i64>>(),
+            vec![10, 11, 12]
+        );
+        assert_eq!(range[2].1[0], Value::Integer(12));
+        assert_eq!(rows.get(300).unwrap()[1], Value::Text("x".repeat(5000)));
+
+        // A row replaced or deleted isn't read any more
+        rows.insert(42, &[Value::Null]);
+        assert_eq!(rows.get(42), Some(vec![Value::Null]));
+        assert!(rows.delete(42));
+        assert!(!rows.delete(42));
+        assert_eq!(rows.get(42), None);
+        assert_eq!(rows.rowids().len(), 999);
+        assert_ne!(rows, RowStore::new());
+    }
+}
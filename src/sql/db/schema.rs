@@ -0,0 +1,151 @@
+use serde::{Deserialize, Serialize};
+
+use crate::sql::db::audit::AUDIT_TABLE_NAME;
+use crate::sql::db::database::TEMP_SCHEMA;
+use crate::sql::db::stats::STAT_TABLE_NAME;
+use crate::sql::executor::profile::PROFILE_TABLE_NAME;
+
+/// Names the schema catalog can be queried under, like `SELECT * FROM sqlite_master`
+pub const SCHEMA_TABLE_NAMES: [&str; 3] = ["sqlite_master", "sqlite_schema", "sqlrite_schema"];
+
+/// Names the schema catalog of the temporary tables can be queried under
+pub const TEMP_SCHEMA_TABLE_NAMES: [&str; 2] = ["sqlite_temp_master", "sqlite_temp_schema"];
+
+/// The kinds of objects listed in the schema catalog
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone, Copy)]
+pub enum SchemaObjectType {
+    Table,
+    Index,
+    Trigger,
+}
+
+impl SchemaObjectType {
+    /// Name of the type, as in the `type` column of `sqlite_master`
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SchemaObjectType::Table => "table",
+            SchemaObjectType::Index => "index",
+            SchemaObjectType::Trigger => "trigger",
+        }
+    }
+}
+
+/// An entry of the schema catalog, a row of `sqlite_master`. Objects of attached databases
+/// are named `schema.name`, like their tables.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub struct SchemaObject {
+    pub object_type: SchemaObjectType,
+    pub name: String,
+    /// Name of the table the object belongs to, its own name for tables
+    pub tbl_name: String,
+    /// SQL text the object was created with, `None` for automatically created indexes
+    pub sql: Option<String>,
+}
+
+impl SchemaObject {
+    pub fn new(
+        object_type: SchemaObjectType,
+        name: &str,
+        tbl_name: &str,
+        sql: Option<&str>,
+    ) -> Self {
+        SchemaObject {
+            object_type,
+            name: name.to_string(),
+            tbl_name: tbl_name.to_string(),
+            // Kept the way SQLite does, without the final semicolon
+            sql: sql.map(|sql| sql.trim().trim_end_matches(';').trim_end().to_string()),
+        }
+    }
+}
+
+/// Returns true if `name` is reserved for the objects of the database itself.
+pub fn is_reserved_name(name: &str) -> bool {
+    let name = name.split_once('.').map_or(name, |(_, name)| name);
+    name.to_lowercase().starts_with("sqlite_")
+        || SCHEMA_TABLE_NAMES.contains(&name.to_lowercase().as_str())
+        || name.eq_ignore_ascii_case(AUDIT_TABLE_NAME)
+        || name.eq_ignore_ascii_case(STAT_TABLE_NAME)
+        || name.eq_ignore_ascii_case(PROFILE_TABLE_NAME)
+}
+
+/// Returns the schema name `table_name` queries the catalog of, `None` for the main
+/// database, if it names the schema catalog.
+pub fn schema_table(table_name: &str) -> Option<Option<&str>> {
+    let (schema, name) = match table_name.split_once('.') {
+        Some((schema, name)) => (Some(schema), name),
+        None => (None, table_name),
+    };
+    if TEMP_SCHEMA_TABLE_NAMES
+        .iter()
+        .any(|table| table.eq_ignore_ascii_case(name))
+    {
+        Some(Some(TEMP_SCHEMA))
+    } else if SCHEMA_TABLE_NAMES
+        .iter()
+        .any(|table| table.eq_ignore_ascii_case(name))
+    {
+        Some(schema)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sql::db::database::Database;
+    use crate::sql::value::Value;
+    use crate::sql::{process_command, process_query};
+
+    #[test]
+    fn schema_catalog_test() {
+        let mut db = Database::new("tempdb".to_string());
+        process_command(
+            "CREATE TABLE users (id INTEGER PRIMARY KEY, email TEXT UNIQUE);",
+            &mut db,
+        )
+        .unwrap();
+        process_command(
+            "CREATE TRIGGER t AFTER INSERT ON users BEGIN DELETE FROM users; END;",
+            &mut db,
+        )
+        .unwrap();
+
+        let result =
+            process_query("SELECT type, name, tbl_name, sql FROM sqlite_master;", &db).unwrap();
+        let text = |s: &str| Value::Text(s.to_string());
+        assert_eq!(
+            result.rows,
+            vec![
+                vec![
+                    text("table"),
+                    text("users"),
+                    text("users"),
+                    text("CREATE TABLE users (id INTEGER PRIMARY KEY, email TEXT UNIQUE)"),
+                ],
+                vec![
+                    text("index"),
+                    text("sqlite_autoindex_users_1"),
+                    text("users"),
+                    Value::Null,
+                ],
+                vec![
+                    text("trigger"),
+                    text("t"),
+                    text("users"),
+                    text("CREATE TRIGGER t AFTER INSERT ON users BEGIN DELETE FROM users; END"),
+                ],
+            ]
+        );
+
+        process_command("DROP TRIGGER t;", &mut db).unwrap();
+        let result = process_query(
+            "SELECT name FROM sqlrite_schema WHERE type = 'trigger';",
+            &db,
+        )
+        .unwrap();
+        assert!(result.rows.is_empty());
+
+        assert!(process_command("CREATE TABLE sqlite_master (id INTEGER);", &mut db).is_err());
+    }
+}
@@ -0,0 +1,145 @@
+//! Binary snapshots of a database, written by `.save --snapshot`: the `Database` and its
+//! `Table`s serialized with bincode as they are held in memory, so that a small database
+//! is written and read back in one go, much faster than a JSON copy and without the pages
+//! and B+trees of a database file, to snapshot and restore it for tests and backups.
+//!
+//! The file starts with a magic string, followed by the version of SQLRite that wrote it
+//! and the database. Bincode only reads back the very structures it wrote, so a snapshot
+//! is only read by the version of SQLRite that wrote it, and refused by any other: a
+//! database to keep across versions is saved to a database file.
+use std::fs::{File, OpenOptions};
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+use crate::error::{Result, SQLRiteError};
+use crate::sql::db::database::Database;
+
+/// First bytes of every snapshot file
+const MAGIC: &[u8; 16] = b"SQLRite snapshot";
+/// Version of SQLRite, the only one to read the snapshots it writes
+const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// Tells whether the file `path` starts like a snapshot.
+pub fn is_snapshot_file(path: &Path) -> bool {
+    let mut magic = [0; MAGIC.len()];
+    File::open(path)
+        .and_then(|mut file| file.read_exact(&mut magic))
+        .is_ok()
+        && &magic == MAGIC
+}
+
+/// Writes the snapshot of the main database `db` to the new file `path`, synced to the
+/// disk. Fails if `path` already exists.
+pub fn write_snapshot_file(db: &Database, path: &Path) -> Result<()> {
+    let file_error = |err: std::io::Error| {
+        SQLRiteError::General(format!("cannot write {}: {}", path.display(), err))
+    };
+    let file = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(path)
+        .map_err(file_error)?;
+    let mut writer = BufWriter::new(file);
+    writer.write_all(MAGIC).map_err(file_error)?;
+    bincode::serialize_into(&mut writer, &(VERSION, db))
+        .map_err(|err| SQLRiteError::Internal(format!("{}", err)))?;
+    let file = writer
+        .into_inner()
+        .map_err(|err| file_error(err.into_error()))?;
+    file.sync_all().map_err(file_error)
+}
+
+/// Reads the database in the snapshot file `path`, which must have been written by this
+/// version of SQLRite.
+pub fn read_snapshot_file(path: &Path) -> Result<Database> {
+    let file = File::open(path)
+        .map_err(|err| SQLRiteError::General(format!("cannot read {}: {}", path.display(), err)))?;
+    let mut reader = BufReader::new(file);
+    let mut magic = [0; MAGIC.len()];
+    let malformed = |err: &dyn std::fmt::Display| {
+        SQLRiteError::General(format!(
+            "{} is a malformed snapshot: {}",
+            path.display(),
+            err
+        ))
+    };
+    reader
+        .read_exact(&mut magic)
+        .map_err(|err| malformed(&err))?;
+    if &magic != MAGIC {
+        return Err(SQLRiteError::General(format!(
+            "{} is not a snapshot",
+            path.display()
+        )));
+    }
+    let version: String = bincode::deserialize_from(&mut reader).map_err(|err| malformed(&err))?;
+    if version != VERSION {
+        return Err(SQLRiteError::General(format!(
+            "{} is a snapshot written by version {} of SQLRite, which only that version reads, not version {}",
+            path.display(),
+            version,
+            VERSION
+        )));
+    }
+    bincode::deserialize_from(&mut reader).map_err(|err| malformed(&err))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sql::db::format::{file_format, FileFormat};
+    use crate::sql::process_command;
+    use crate::sql::value::Value;
+
+    #[test]
+    fn snapshot_file_test() {
+        let path =
+            std::env::temp_dir().join(format!("sqlrite-snapshot-{}.bin", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let mut db = Database::new("tempdb".to_string());
+        for command in [
+            "CREATE TABLE users (id INTEGER PRIMARY KEY, name TEXT UNIQUE, score REAL);",
+            "INSERT INTO users (name, score) VALUES ('josh', 1.5);",
+            "INSERT INTO users (name, score) VALUES ('ana', 2.0);",
+            "PRAGMA user_version = 7;",
+        ] {
+            process_command(command, &mut db).unwrap();
+        }
+        db.save_snapshot(&path).unwrap();
+        assert!(is_snapshot_file(&path));
+        assert_eq!(file_format(&path).unwrap(), FileFormat::Snapshot);
+
+        let mut opened = Database::read_file(&path).unwrap();
+        assert_eq!(opened.user_version, 7);
+        assert_eq!(
+            opened.tables["users"].get_value("name", 2).unwrap(),
+            Value::Text("ana".to_string())
+        );
+        // Its indexes are read back with it
+        assert!(process_command("INSERT INTO users (name) VALUES ('josh');", &mut opened).is_err());
+
+        // A snapshot opened is saved back as a snapshot
+        opened.open_file(&path).unwrap();
+        process_command("INSERT INTO users (name) VALUES ('maria');", &mut opened).unwrap();
+        opened.save().unwrap();
+        assert!(is_snapshot_file(&path));
+        assert_eq!(
+            Database::read_file(&path).unwrap().tables["users"]
+                .get_value("name", 3)
+                .unwrap(),
+            Value::Text("maria".to_string())
+        );
+
+        // A snapshot of another version of SQLRite isn't misread
+        let mut bytes = std::fs::read(&path).unwrap();
+        let at = MAGIC.len() + 8;
+        bytes[at..at + VERSION.len()].copy_from_slice(&vec![b'9'; VERSION.len()]);
+        std::fs::write(&path, &bytes).unwrap();
+        let err = Database::read_file(&path).unwrap_err();
+        assert!(err.to_string().contains("which only that version reads"));
+        std::fs::write(&path, &bytes[..MAGIC.len() + 4]).unwrap();
+        let err = Database::read_file(&path).unwrap_err();
+        assert!(err.to_string().contains("is a malformed snapshot"));
+        std::fs::remove_file(&path).unwrap();
+    }
+}
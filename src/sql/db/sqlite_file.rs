@@ -0,0 +1,791 @@
+//! Database files in the format of SQLite 3, so that a database saved by SQLRite can be
+//! opened with `sqlite3`, and a database created by SQLite can be opened by SQLRite.
+//!
+//! The file starts with the 100 bytes header of SQLite, followed by the b-tree pages of
+//! `sqlite_master`, rooted at page 1, which lists every table, index and trigger with its
+//! root page and the SQL it was created with. The rows of each table are in a table
+//! b-tree keyed by ROWID, each row a record of its values, and each automatic index of a
+//! UNIQUE or PRIMARY KEY column has an index b-tree of its values and ROWIDs, in the order
+//! of the collation of the column. Records, varints, cells spilling into overflow pages
+//! and the layout of the pages follow the file format of SQLite.
+//!
+//! Only a subset of what a SQLite file can hold is read: tables whose CREATE TABLE SQLRite
+//! can parse and whose values fit the types of their columns, and triggers. Indexes created
+//! with CREATE INDEX aren't kept, their rows being in the tables anyway, and virtual tables
+//! can't be read. Files are written whole, on saving a database, with their pages packed.
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+use crate::error::{Result, SQLRiteError};
+use crate::sql::collation::CollationRegistry;
+use crate::sql::db::database::{write_new_file, Database};
+use crate::sql::db::memory::PAGE_SIZE;
+use crate::sql::db::pager::malformed;
+use crate::sql::db::schema::SchemaObjectType;
+use crate::sql::db::table::{DataType, Table};
+use crate::sql::parser::create::CreateQuery;
+use crate::sql::value::Value;
+use crate::sql::{parse_statement, process_command};
+
+/// First bytes of every SQLite 3 database file
+const MAGIC: &[u8; 16] = b"SQLite format 3\0";
+/// Bytes of the header of the file, at the start of page 1
+const HEADER_SIZE: usize = 100;
+/// Version of SQLite whose file format the files written follow
+const SQLITE_VERSION_NUMBER: u32 = 3_040_001;
+
+const INDEX_INTERIOR: u8 = 0x02;
+const TABLE_INTERIOR: u8 = 0x05;
+const INDEX_LEAF: u8 = 0x0A;
+const TABLE_LEAF: u8 = 0x0D;
+
+/// Returns true if the file `path` starts like a SQLite 3 database file.
+pub fn is_sqlite_file(path: &Path) -> bool {
+    let mut magic = [0; MAGIC.len()];
+    File::open(path)
+        .and_then(|mut file| file.read_exact(&mut magic))
+        .is_ok()
+        && &magic == MAGIC
+}
+
+/// Writes the main database `db` to the new SQLite database file `path`, sorting the
+/// automatic indexes with the collations of `collations`. Fails if `path` already exists.
+pub fn write_sqlite_file(db: &Database, collations: &CollationRegistry, path: &Path) -> Result<()> {
+    let mut writer = Writer {
+        pages: vec![vec![0; PAGE_SIZE]],
+    };
+    let mut master = vec![];
+    for object in &db.schema {
+        // Objects of attached databases and virtual tables have no table of their own
+        let table = match db.tables.get(&object.tbl_name) {
+            Some(table) => table,
+            None => continue,
+        };
+        let text = |text: &str| Value::Text(text.to_string());
+        let root = match object.object_type {
+            SchemaObjectType::Table => writer.table_tree(table_rows(table), None)?,
+            SchemaObjectType::Index => {
+                let column = table
+                    .autoindexes()
+                    .into_iter()
+                    .find(|(name, _)| *name == object.name)
+                    .map(|(_, column)| column.column_name.to_string());
+                match column {
+                    Some(column) => {
+                        writer.index_tree(index_entries(table, &column, collations)?)?
+                    }
+                    None => continue,
+                }
+            }
+            SchemaObjectType::Trigger => 0,
+        };
+        let sql = object.sql.as_deref().map_or(Value::Null, text);
+        master.push(encode_record(&[
+            text(object.object_type.as_str()),
+            text(&object.name),
+            text(&object.tbl_name),
+            Value::Integer(i64::from(root)),
+            sql,
+        ]));
+    }
+    let master = master
+        .into_iter()
+        .enumerate()
+        .map(|(i, record)| (i as i64 + 1, record))
+        .collect();
+    writer.table_tree(master, Some(1))?;
+
+    let page_count = writer.pages.len() as u32;
+    let header = &mut writer.pages[0][..HEADER_SIZE];
+    header[..MAGIC.len()].copy_from_slice(MAGIC);
+    header[16..18].copy_from_slice(&(PAGE_SIZE as u16).to_be_bytes());
+    // File format versions, unused bytes at the end of pages, and payload fractions
+    header[18..24].copy_from_slice(&[1, 1, 0, 64, 32, 32]);
+    header[24..28].copy_from_slice(&1u32.to_be_bytes());
+    header[28..32].copy_from_slice(&page_count.to_be_bytes());
+    header[40..44].copy_from_slice(&(db.schema_version as u32).to_be_bytes());
+    header[44..48].copy_from_slice(&4u32.to_be_bytes());
+    // UTF-8
+    header[56..60].copy_from_slice(&1u32.to_be_bytes());
+    header[60..64].copy_from_slice(&(db.user_version as u32).to_be_bytes());
+    header[92..96].copy_from_slice(&1u32.to_be_bytes());
+    header[96..100].copy_from_slice(&SQLITE_VERSION_NUMBER.to_be_bytes());
+    write_new_file(path, &writer.pages.concat())
+}
+
+/// Reads the database in the SQLite database file `path`.
+pub fn read_sqlite_file(path: &Path) -> Result<Database> {
+    let bytes = std::fs::read(path)
+        .map_err(|err| SQLRiteError::General(format!("cannot read {}: {}", path.display(), err)))?;
+    if bytes.len() < HEADER_SIZE || &bytes[..MAGIC.len()] != MAGIC {
+        return Err(SQLRiteError::General("file is not a database".to_string()));
+    }
+    let page_size = match u16::from_be_bytes([bytes[16], bytes[17]]) {
+        1 => 65536,
+        size => size as usize,
+    };
+    let encoding = read_u32(&bytes, 56);
+    if encoding > 1 {
+        return Err(SQLRiteError::NotImplemented(
+            "only UTF-8 SQLite databases can be read".to_string(),
+        ));
+    }
+    let file = Reader {
+        bytes: &bytes,
+        page_size,
+        usable: page_size - bytes[20] as usize,
+    };
+
+    let name = path.file_stem().map_or("main".to_string(), |stem| {
+        stem.to_string_lossy().to_string()
+    });
+    let mut db = Database::new(name);
+    let mut triggers = vec![];
+    for (_, record) in file.table_rows(1)? {
+        let values = decode_record(&record)?;
+        let field = |i: usize| values.get(i).cloned().unwrap_or(Value::Null);
+        let (kind, name, root, sql) = (field(0), field(1), field(3), field(4));
+        let name = name.to_text().unwrap_or_default();
+        let sql = sql.to_text().unwrap_or_default();
+        match kind.to_text().unwrap_or_default().as_str() {
+            "table" if name.to_lowercase().starts_with("sqlite_") => {}
+            "table" => match root.to_integer() {
+                Some(root) if root > 0 => read_table(&file, &mut db, &sql, root as u32)?,
+                _ => {
+                    return Err(SQLRiteError::NotImplemented(format!(
+                        "virtual table {} can't be read from a SQLite database",
+                        name
+                    )))
+                }
+            },
+            "trigger" => triggers.push(sql),
+            // Indexes are built from the rows of their table
+            _ => {}
+        }
+    }
+    for sql in triggers {
+        process_command(&sql, &mut db)?;
+    }
+    db.schema_version = i64::from(read_u32(&bytes, 40));
+    db.user_version = i64::from(read_u32(&bytes, 60) as i32);
+    Ok(db)
+}
+
+/// Creates the table of the CREATE TABLE statement `sql` in `db`, and loads its rows from
+/// the table b-tree rooted at `root`.
+fn read_table(file: &Reader, db: &mut Database, sql: &str, root: u32) -> Result<()> {
+    let mut table = Table::new(CreateQuery::new(&parse_statement(sql)?)?);
+    let columns = table
+        .columns
+        .iter()
+        .map(|column| column.column_name.to_string())
+        .collect::<Vec<String>>();
+    let alias = rowid_alias(&table);
+    table.begin_bulk_load();
+    for (rowid, record) in file.table_rows(root)? {
+        let mut values = decode_record(&record)?;
+        // Columns added by ALTER TABLE after the row was written are missing from it
+        values.resize(columns.len(), Value::Null);
+        if let Some(alias) = alias {
+            values[alias] = Value::Integer(rowid);
+        }
+        table.write_row(rowid, &columns, &values)?;
+    }
+    table.end_bulk_load(&db.collations)?;
+    db.add_table(table, sql);
+    Ok(())
+}
+
+/// The position of the INTEGER PRIMARY KEY column of `table`, which is the ROWID of its
+/// rows and stored as NULL in their records.
+fn rowid_alias(table: &Table) -> Option<usize> {
+    table
+        .columns
+        .iter()
+        .position(|column| column.is_pk && column.datatype == DataType::Integer)
+}
+
+/// The rows of `table` as records, by ROWID.
+fn table_rows(table: &Table) -> Vec<(i64, Vec<u8>)> {
+    let alias = rowid_alias(table);
+    table
+        .rowids()
+        .into_iter()
+        .map(|rowid| {
+            let mut values = table.row_values(rowid);
+            if let Some(alias) = alias {
+                values[alias] = Value::Null;
+            }
+            (rowid, encode_record(&values))
+        })
+        .collect()
+}
+
+/// The entries of the automatic index of `column` of `table` as records of the value and
+/// the ROWID of each row, in the order of the collation of the column.
+fn index_entries(
+    table: &Table,
+    column: &str,
+    collations: &CollationRegistry,
+) -> Result<Vec<Vec<u8>>> {
+    let collation = table
+        .columns
+        .iter()
+        .find(|other| other.column_name == column)
+        .and_then(|column| column.collation.as_deref())
+        .unwrap_or("binary");
+    let cmp = collations.get(collation)?;
+    let mut entries = table
+        .rowids()
+        .into_iter()
+        .map(|rowid| Ok((table.get_value(column, rowid)?, rowid)))
+        .collect::<Result<Vec<(Value, i64)>>>()?;
+    entries.sort_by(|(a, a_rowid), (b, b_rowid)| {
+        a.compare(b, cmp.as_ref()).then(a_rowid.cmp(b_rowid))
+    });
+    Ok(entries
+        .into_iter()
+        .map(|(value, rowid)| encode_record(&[value, Value::Integer(rowid)]))
+        .collect())
+}
+
+/// The pages of a file being written, page 1 first
+struct Writer {
+    pages: Vec<Vec<u8>>,
+}
+
+impl Writer {
+    fn allocate(&mut self) -> u32 {
+        self.pages.push(vec![0; PAGE_SIZE]);
+        self.pages.len() as u32
+    }
+
+    /// Writes a table b-tree of `rows`, in ROWID order, into new pages, its root into
+    /// `root` if given. Returns the root page.
+    fn table_tree(&mut self, rows: Vec<(i64, Vec<u8>)>, root: Option<u32>) -> Result<u32> {
+        let mut cells = vec![];
+        let mut keys = vec![];
+        for (rowid, record) in rows {
+            let mut prefix = varint(record.len() as u64);
+            prefix.extend(varint(rowid as u64));
+            cells.push(self.payload_cell(prefix, &record, PAGE_SIZE - 35));
+            keys.push(rowid);
+        }
+        if fits(&cells, root_capacity(root, TABLE_LEAF)) {
+            return Ok(self.write_node(root, TABLE_LEAF, &cells, None));
+        }
+        // Each leaf is followed by the greatest ROWID it holds
+        let mut children = vec![];
+        let mut separators = vec![];
+        let mut start = 0;
+        for group in pack(&cells, capacity(TABLE_LEAF)) {
+            let end = start + group.len();
+            children.push(self.write_node(None, TABLE_LEAF, group, None));
+            separators.push(varint(keys[end - 1] as u64));
+            start = end;
+        }
+        separators.pop();
+        Ok(self.interior_tree(TABLE_INTERIOR, children, separators, root))
+    }
+
+    /// Writes an index b-tree of the records `entries`, in order, into new pages. Returns
+    /// the root page.
+    fn index_tree(&mut self, entries: Vec<Vec<u8>>) -> Result<u32> {
+        let max_local = (PAGE_SIZE - 12) * 64 / 255 - 23;
+        let cells = entries
+            .iter()
+            .map(|record| self.payload_cell(varint(record.len() as u64), record, max_local))
+            .collect::<Vec<Vec<u8>>>();
+        if fits(&cells, capacity(INDEX_LEAF)) {
+            return Ok(self.write_node(None, INDEX_LEAF, &cells, None));
+        }
+        // The entry after each leaf but the last is moved up to separate it from the next
+        let (groups, separators) = split_groups(cells, capacity(INDEX_LEAF));
+        let children = groups
+            .iter()
+            .map(|group| self.write_node(None, INDEX_LEAF, group, None))
+            .collect();
+        Ok(self.interior_tree(INDEX_INTERIOR, children, separators, None))
+    }
+
+    /// Writes the interior pages over the pages `children`, each separated from the next
+    /// by one of `separators`, the cell of its key without the child. Returns the root.
+    fn interior_tree(
+        &mut self,
+        kind: u8,
+        mut children: Vec<u32>,
+        mut separators: Vec<Vec<u8>>,
+        root: Option<u32>,
+    ) -> u32 {
+        loop {
+            let right = children.pop().unwrap_or_default();
+            let cells = children
+                .iter()
+                .zip(&separators)
+                .map(|(child, separator)| [&child.to_be_bytes()[..], separator].concat())
+                .collect::<Vec<Vec<u8>>>();
+            if fits(&cells, root_capacity(root, kind)) {
+                return self.write_node(root, kind, &cells, Some(right));
+            }
+            // Each page takes cells while they fit, the child of the next cell being its
+            // last child, and the key of that cell separating it from the next page
+            let mut cells = cells;
+            cells.push(right.to_be_bytes().to_vec());
+            let (groups, promoted) = split_groups(cells, capacity(kind));
+            let mut pages = vec![];
+            for (i, mut group) in groups.into_iter().enumerate() {
+                let last = match i < promoted.len() {
+                    true => read_u32(&promoted[i], 0),
+                    false => read_u32(&group.pop().unwrap_or_default(), 0),
+                };
+                pages.push(self.write_node(None, kind, &group, Some(last)));
+            }
+            children = pages;
+            separators = promoted
+                .into_iter()
+                .map(|cell| cell[4..].to_vec())
+                .collect();
+        }
+    }
+
+    /// Writes a b-tree page of `cells` into the page `page`, or a new one, with its last
+    /// child `right` for interior pages. Returns the page written.
+    fn write_node(
+        &mut self,
+        page: Option<u32>,
+        kind: u8,
+        cells: &[Vec<u8>],
+        right: Option<u32>,
+    ) -> u32 {
+        let page = page.unwrap_or_else(|| self.allocate());
+        let offset = if page == 1 { HEADER_SIZE } else { 0 };
+        let data = &mut self.pages[page as usize - 1];
+        data[offset] = kind;
+        data[offset + 3..offset + 5].copy_from_slice(&(cells.len() as u16).to_be_bytes());
+        let mut header = offset + 8;
+        if let Some(right) = right {
+            data[offset + 8..offset + 12].copy_from_slice(&right.to_be_bytes());
+            header += 4;
+        }
+        let mut content = PAGE_SIZE;
+        for (i, cell) in cells.iter().enumerate() {
+            content -= cell.len();
+            data[content..content + cell.len()].copy_from_slice(cell);
+            data[header + 2 * i..header + 2 * i + 2]
+                .copy_from_slice(&(content as u16).to_be_bytes());
+        }
+        data[offset + 5..offset + 7].copy_from_slice(&(content as u16).to_be_bytes());
+        page
+    }
+
+    /// The cell of `payload` after `prefix`, its first `max_local` bytes at most kept in
+    /// the cell and the rest in new overflow pages.
+    fn payload_cell(&mut self, prefix: Vec<u8>, payload: &[u8], max_local: usize) -> Vec<u8> {
+        let local = local_size(payload.len(), max_local);
+        let mut cell = prefix;
+        cell.extend_from_slice(&payload[..local]);
+        if local < payload.len() {
+            let chunks = payload[local..]
+                .chunks(PAGE_SIZE - 4)
+                .collect::<Vec<&[u8]>>();
+            let first = self.pages.len() as u32 + 1;
+            for (i, chunk) in chunks.iter().enumerate() {
+                let page = self.allocate();
+                let next = if i + 1 < chunks.len() { page + 1 } else { 0 };
+                let data = &mut self.pages[page as usize - 1];
+                data[..4].copy_from_slice(&next.to_be_bytes());
+                data[4..4 + chunk.len()].copy_from_slice(chunk);
+            }
+            cell.extend_from_slice(&first.to_be_bytes());
+        }
+        cell
+    }
+}
+
+/// Bytes of the cells of a page of type `kind` that isn't page 1.
+fn capacity(kind: u8) -> usize {
+    match kind {
+        TABLE_INTERIOR | INDEX_INTERIOR => PAGE_SIZE - 12,
+        _ => PAGE_SIZE - 8,
+    }
+}
+
+/// Bytes of the cells of the root page `root`, page 1 having the header of the file.
+fn root_capacity(root: Option<u32>, kind: u8) -> usize {
+    match root {
+        Some(1) => capacity(kind) - HEADER_SIZE,
+        _ => capacity(kind),
+    }
+}
+
+/// Returns true if `cells` and their pointers fit in `capacity` bytes.
+fn fits(cells: &[Vec<u8>], capacity: usize) -> bool {
+    cells.iter().map(|cell| cell.len() + 2).sum::<usize>() <= capacity
+}
+
+/// Packs `cells` into groups that fit in `capacity` bytes each.
+fn pack(cells: &[Vec<u8>], capacity: usize) -> Vec<&[Vec<u8>]> {
+    let mut groups = vec![];
+    let (mut start, mut used) = (0, 0);
+    for (i, cell) in cells.iter().enumerate() {
+        if used + cell.len() + 2 > capacity && i > start {
+            groups.push(&cells[start..i]);
+            start = i;
+            used = 0;
+        }
+        used += cell.len() + 2;
+    }
+    groups.push(&cells[start..]);
+    groups
+}
+
+/// Packs `cells` into groups that fit in `capacity` bytes each, the cell after each group
+/// but the last taken out to separate it from the next one. The last group has at least
+/// two cells when there are enough.
+fn split_groups(cells: Vec<Vec<u8>>, capacity: usize) -> (Vec<Vec<Vec<u8>>>, Vec<Vec<u8>>) {
+    let mut groups = vec![];
+    let mut separators = vec![];
+    let mut group = vec![];
+    let mut used = 0;
+    for cell in cells {
+        if used + cell.len() + 2 > capacity && !group.is_empty() {
+            groups.push(std::mem::take(&mut group));
+            separators.push(cell);
+            used = 0;
+            continue;
+        }
+        used += cell.len() + 2;
+        group.push(cell);
+    }
+    // The last group takes cells from the one before it, for its page not to be empty
+    while group.len() < 2 {
+        match groups.last_mut() {
+            Some(previous) if previous.len() > 2 => {
+                group.insert(0, separators.pop().unwrap_or_default());
+                separators.push(previous.pop().unwrap_or_default());
+            }
+            _ => break,
+        }
+    }
+    groups.push(group);
+    (groups, separators)
+}
+
+/// The bytes of a payload of `size` bytes kept in its cell, the rest of it going to
+/// overflow pages, when at most `max_local` bytes can be.
+fn local_size(size: usize, max_local: usize) -> usize {
+    if size <= max_local {
+        return size;
+    }
+    let min_local = (PAGE_SIZE - 12) * 32 / 255 - 23;
+    let local = min_local + (size - min_local) % (PAGE_SIZE - 4);
+    if local <= max_local {
+        local
+    } else {
+        min_local
+    }
+}
+
+/// The pages of a file being read
+struct Reader<'a> {
+    bytes: &'a [u8],
+    page_size: usize,
+    /// Bytes of a page that can be used, the page size less the bytes reserved at its end
+    usable: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn page(&self, page: u32) -> Result<&'a [u8]> {
+        let start = (page as usize).checked_sub(1).ok_or_else(malformed)? * self.page_size;
+        self.bytes
+            .get(start..start + self.page_size)
+            .ok_or_else(malformed)
+    }
+
+    /// The rows of the table b-tree rooted at `root`, in ROWID order.
+    fn table_rows(&self, root: u32) -> Result<Vec<(i64, Vec<u8>)>> {
+        let mut rows = vec![];
+        self.visit(root, &mut rows, 0)?;
+        Ok(rows)
+    }
+
+    fn visit(&self, page: u32, rows: &mut Vec<(i64, Vec<u8>)>, depth: usize) -> Result<()> {
+        // A tree deeper than this has a cycle
+        if depth > 64 {
+            return Err(malformed());
+        }
+        let data = self.page(page)?;
+        let offset = if page == 1 { HEADER_SIZE } else { 0 };
+        let kind = data[offset];
+        let count = u16::from_be_bytes([data[offset + 3], data[offset + 4]]) as usize;
+        let header = match kind {
+            TABLE_INTERIOR => offset + 12,
+            TABLE_LEAF => offset + 8,
+            _ => return Err(malformed()),
+        };
+        for i in 0..count {
+            let pointer = data
+                .get(header + 2 * i..header + 2 * i + 2)
+                .ok_or_else(malformed)?;
+            let cell = u16::from_be_bytes([pointer[0], pointer[1]]) as usize;
+            if kind == TABLE_INTERIOR {
+                self.visit(read_u32(data, cell), rows, depth + 1)?;
+                continue;
+            }
+            let (size, n) = read_varint(data, cell)?;
+            let (rowid, m) = read_varint(data, cell + n)?;
+            let start = cell + n + m;
+            let size = size as usize;
+            let local = local_size_of(size, self.usable);
+            let mut payload = data
+                .get(start..start + local)
+                .ok_or_else(malformed)?
+                .to_vec();
+            if local < size {
+                let mut next = read_u32(data, start + local);
+                while payload.len() < size {
+                    if next == 0 {
+                        return Err(malformed());
+                    }
+                    let overflow = self.page(next)?;
+                    let chunk = (size - payload.len()).min(self.usable - 4);
+                    payload.extend_from_slice(&overflow[4..4 + chunk]);
+                    next = read_u32(overflow, 0);
+                }
+            }
+            rows.push((rowid as i64, payload));
+        }
+        if kind == TABLE_INTERIOR {
+            self.visit(read_u32(data, offset + 8), rows, depth + 1)?;
+        }
+        Ok(())
+    }
+}
+
+/// The bytes of a row of `size` bytes kept in its cell, in a file whose pages have `usable`
+/// bytes.
+fn local_size_of(size: usize, usable: usize) -> usize {
+    let max_local = usable - 35;
+    if size <= max_local {
+        return size;
+    }
+    let min_local = (usable - 12) * 32 / 255 - 23;
+    let local = min_local + (size - min_local) % (usable - 4);
+    if local <= max_local {
+        local
+    } else {
+        min_local
+    }
+}
+
+/// Encodes `values` as a SQLite record: a header of the serial type of each value, then
+/// the values.
+pub fn encode_record(values: &[Value]) -> Vec<u8> {
+    let mut types = vec![];
+    let mut body = vec![];
+    for value in values {
+        let serial_type = match value {
+            Value::Null => 0,
+            Value::Integer(0) | Value::Bool(false) => 8,
+            Value::Integer(1) | Value::Bool(true) => 9,
+            Value::Integer(n) => {
+                let (serial_type, size) = match n {
+                    -0x80..=0x7f => (1, 1),
+                    -0x8000..=0x7fff => (2, 2),
+                    -0x80_0000..=0x7f_ffff => (3, 3),
+                    -0x8000_0000..=0x7fff_ffff => (4, 4),
+                    -0x8000_0000_0000..=0x7fff_ffff_ffff => (5, 6),
+                    _ => (6, 8),
+                };
+                body.extend_from_slice(&n.to_be_bytes()[8 - size..]);
+                serial_type
+            }
+            Value::Real(r) => {
+                body.extend_from_slice(&r.to_be_bytes());
+                7
+            }
+            Value::Text(text) => {
+                body.extend_from_slice(text.as_bytes());
+                text.len() as u64 * 2 + 13
+            }
+            Value::Blob(blob) => {
+                body.extend_from_slice(blob);
+                blob.len() as u64 * 2 + 12
+            }
+        };
+        types.extend(varint(serial_type));
+    }
+    // The size of the header counts the varint of the size itself
+    let mut header_size = types.len() + 1;
+    while types.len() + varint(header_size as u64).len() != header_size {
+        header_size = types.len() + varint(header_size as u64).len();
+    }
+    let mut record = varint(header_size as u64);
+    record.extend(types);
+    record.extend(body);
+    record
+}
+
+/// Decodes the values of a SQLite record.
+pub fn decode_record(record: &[u8]) -> Result<Vec<Value>> {
+    let (header_size, mut position) = read_varint(record, 0)?;
+    let mut body = header_size as usize;
+    let mut values = vec![];
+    while position < header_size as usize {
+        let (serial_type, n) = read_varint(record, position)?;
+        position += n;
+        let size = match serial_type {
+            0 | 8 | 9 => 0,
+            1..=4 => serial_type as usize,
+            5 => 6,
+            6 | 7 => 8,
+            10 | 11 => return Err(malformed()),
+            _ => (serial_type as usize - 12) / 2,
+        };
+        let data = record.get(body..body + size).ok_or_else(malformed)?;
+        body += size;
+        values.push(match serial_type {
+            0 => Value::Null,
+            8 => Value::Integer(0),
+            9 => Value::Integer(1),
+            1..=6 => {
+                // Sign-extended from the bytes stored
+                let fill = if data[0] & 0x80 != 0 { 0xff } else { 0 };
+                let mut bytes = [fill; 8];
+                bytes[8 - size..].copy_from_slice(data);
+                Value::Integer(i64::from_be_bytes(bytes))
+            }
+            7 => {
+                let mut bytes = [0; 8];
+                bytes.copy_from_slice(data);
+                Value::Real(f64::from_be_bytes(bytes))
+            }
+            _ if serial_type % 2 == 0 => Value::Blob(data.to_vec()),
+            _ => Value::Text(String::from_utf8(data.to_vec()).map_err(|_| malformed())?),
+        });
+    }
+    Ok(values)
+}
+
+/// Encodes `value` as a SQLite varint: 7 bits in each byte, the high bit set on all but
+/// the last, and 8 bits in the ninth.
+fn varint(value: u64) -> Vec<u8> {
+    if value >> 56 != 0 {
+        let mut bytes = vec![0; 9];
+        bytes[8] = value as u8;
+        let mut rest = value >> 8;
+        for byte in bytes[..8].iter_mut().rev() {
+            *byte = (rest & 0x7f) as u8 | 0x80;
+            rest >>= 7;
+        }
+        return bytes;
+    }
+    let mut bytes = vec![];
+    let mut rest = value;
+    loop {
+        bytes.push((rest & 0x7f) as u8 | 0x80);
+        rest >>= 7;
+        if rest == 0 {
+            break;
+        }
+    }
+    bytes[0] &= 0x7f;
+    bytes.reverse();
+    bytes
+}
+
+/// Reads the varint at `offset` of `bytes`, returning it with its number of bytes.
+fn read_varint(bytes: &[u8], offset: usize) -> Result<(u64, usize)> {
+    let mut value = 0u64;
+    for i in 0..9 {
+        let byte = *bytes.get(offset + i).ok_or_else(malformed)?;
+        if i == 8 {
+            return Ok(((value << 8) | u64::from(byte), 9));
+        }
+        value = (value << 7) | u64::from(byte & 0x7f);
+        if byte & 0x80 == 0 {
+            return Ok((value, i + 1));
+        }
+    }
+    Err(malformed())
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> u32 {
+    bytes
+        .get(offset..offset + 4)
+        .map_or(0, |be| u32::from_be_bytes([be[0], be[1], be[2], be[3]]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn record_test() {
+        for value in [0, 1, 127, 128, 16383, 16384, 1 << 56, u64::MAX] {
+            let bytes = varint(value);
+            assert_eq!(read_varint(&bytes, 0).unwrap(), (value, bytes.len()));
+        }
+        assert_eq!(varint(u64::MAX).len(), 9);
+        let values = vec![
+            Value::Null,
+            Value::Integer(1),
+            Value::Integer(-300),
+            Value::Integer(i64::MIN),
+            Value::Real(2.5),
+            Value::Text("x".repeat(200)),
+            Value::Blob(vec![0, 1, 2]),
+        ];
+        assert_eq!(decode_record(&encode_record(&values)).unwrap(), values);
+    }
+
+    #[test]
+    fn sqlite_file_test() {
+        let path = std::env::temp_dir().join(format!("sqlrite-{}.sqlite", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let mut db = Database::new("tempdb".to_string());
+        for sql in [
+            "CREATE TABLE users (id INTEGER PRIMARY KEY, name TEXT UNIQUE, score REAL);",
+            "CREATE TABLE log (msg TEXT);",
+            "CREATE TRIGGER logged AFTER INSERT ON users BEGIN INSERT INTO log (msg) VALUES (NEW.name); END;",
+        ] {
+            process_command(sql, &mut db).unwrap();
+        }
+        for i in 0..500 {
+            let name = match i {
+                42 => "x".repeat(10000),
+                _ => format!("user {}", i),
+            };
+            let insert = format!(
+                "INSERT INTO users (name, score) VALUES ('{}', {}.5);",
+                name, i
+            );
+            process_command(&insert, &mut db).unwrap();
+        }
+        write_sqlite_file(&db, &db.collations, &path).unwrap();
+        assert!(is_sqlite_file(&path));
+        assert!(write_sqlite_file(&db, &db.collations, &path).is_err());
+
+        let mut read = read_sqlite_file(&path).unwrap();
+        let users = &read.tables["users"];
+        assert_eq!(users.rowids().len(), 500);
+        assert_eq!(
+            users.row_values(43),
+            vec![
+                Value::Integer(43),
+                Value::Text("x".repeat(10000)),
+                Value::Real(42.5)
+            ]
+        );
+        assert_eq!(read.tables["log"].rowids().len(), 500);
+        assert_eq!(read.schema_version, db.schema_version);
+        assert!(users.check_integrity().is_empty());
+
+        // The unique index and the trigger are read back
+        assert!(process_command("INSERT INTO users (name) VALUES ('user 7');", &mut read).is_err());
+        process_command("INSERT INTO users (name) VALUES ('josh');", &mut read).unwrap();
+        assert_eq!(read.tables["log"].rowids().len(), 501);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
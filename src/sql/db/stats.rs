@@ -0,0 +1,333 @@
+//! Statistics of the values of the columns of tables, gathered by ANALYZE and read back
+//! from the `sqlrite_stat` table. For each column they hold the most common values with
+//! their number of rows, and an equi-depth histogram of the other values: buckets holding
+//! about the same number of rows each, so that the number of rows a range of values covers
+//! is estimated from the buckets it overlaps, however skewed the values are.
+//!
+//! The planner estimates the share of the rows of a table a `column op value` constraint
+//! keeps with them, and falls back to fixed guesses for tables that were never analyzed.
+//! Statistics aren't updated as rows change, ANALYZE has to be run again.
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::Result;
+use crate::sql::collation::{CollationFn, CollationRegistry};
+use crate::sql::db::table::Table;
+use crate::sql::value::Value;
+use crate::sql::vtab::ConstraintOp;
+
+/// Name the statistics are queried under
+pub const STAT_TABLE_NAME: &str = "sqlrite_stat";
+
+/// Number of most common values kept per column
+pub const MOST_COMMON_VALUES: usize = 8;
+
+/// Number of buckets of the histogram of each column
+pub const HISTOGRAM_BUCKETS: usize = 16;
+
+/// Share of the rows an equality keeps, when there are no statistics
+pub const DEFAULT_EQ_SELECTIVITY: f64 = 0.1;
+
+/// Share of the rows a range comparison keeps, when there are no statistics
+pub const DEFAULT_RANGE_SELECTIVITY: f64 = 0.25;
+
+/// Statistics of a table
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone, Default)]
+pub struct TableStats {
+    /// Number of rows of the table when it was analyzed
+    pub rows: usize,
+    /// Statistics of each column, by name
+    pub columns: BTreeMap<String, ColumnStats>,
+}
+
+impl TableStats {
+    /// Estimated share of the rows `column op value` keeps, a fixed guess if the column
+    /// has no statistics.
+    pub fn selectivity(
+        &self,
+        column: &str,
+        op: ConstraintOp,
+        value: &Value,
+        collation: &CollationFn,
+    ) -> f64 {
+        match self.columns.get(column) {
+            Some(stats) if self.rows > 0 => stats.selectivity(op, value, collation),
+            Some(_) => 0.0,
+            None => default_selectivity(op),
+        }
+    }
+}
+
+/// Gathers the statistics of every column of `table`.
+pub fn analyze_table(table: &Table, collations: &CollationRegistry) -> Result<TableStats> {
+    let rowids = table.rowids();
+    let mut columns = BTreeMap::new();
+    for column in &table.columns {
+        let collation = collations.get(column.collation.as_deref().unwrap_or("binary"))?;
+        let values = rowids
+            .iter()
+            .map(|rowid| table.get_value(&column.column_name, *rowid))
+            .collect::<Result<Vec<Value>>>()?;
+        columns.insert(
+            column.column_name.to_string(),
+            ColumnStats::build(values, collation.as_ref()),
+        );
+    }
+    Ok(TableStats {
+        rows: rowids.len(),
+        columns,
+    })
+}
+
+/// The fixed guess of the share of the rows a constraint keeps.
+pub fn default_selectivity(op: ConstraintOp) -> f64 {
+    match op {
+        ConstraintOp::Eq => DEFAULT_EQ_SELECTIVITY,
+        ConstraintOp::Gt | ConstraintOp::Ge | ConstraintOp::Lt | ConstraintOp::Le => {
+            DEFAULT_RANGE_SELECTIVITY
+        }
+        ConstraintOp::Match => 1.0,
+    }
+}
+
+/// Statistics of the values of a column
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub struct ColumnStats {
+    /// Number of rows
+    pub rows: usize,
+    /// Number of rows where the column is NULL
+    pub nulls: usize,
+    /// Number of distinct values, NULL aside
+    pub distinct: usize,
+    /// Smallest and largest values, NULL if every row is NULL
+    pub min: Value,
+    pub max: Value,
+    /// The most common values with their number of rows, most common first. Only values
+    /// found in more than one row are kept.
+    pub most_common: Vec<(Value, usize)>,
+    /// The histogram of the values that aren't among the most common, in order
+    pub histogram: Vec<Bucket>,
+}
+
+/// A bucket of a histogram: the values from `lower` to `upper`, both included
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub struct Bucket {
+    pub lower: Value,
+    pub upper: Value,
+    /// Number of rows having one of the values
+    pub rows: usize,
+    /// Number of distinct values
+    pub distinct: usize,
+}
+
+impl ColumnStats {
+    /// Gathers the statistics of `values`, the values of a column in every row, ordering
+    /// them with `collation`.
+    pub fn build(mut values: Vec<Value>, collation: &CollationFn) -> Self {
+        let rows = values.len();
+        values.retain(|value| !value.is_null());
+        let nulls = rows - values.len();
+        values.sort_by(|a, b| a.compare(b, collation));
+
+        // Runs of equal values, as (value, rows)
+        let mut runs: Vec<(Value, usize)> = vec![];
+        for value in values {
+            match runs.last_mut() {
+                Some((last, count)) if last.compare(&value, collation) == Ordering::Equal => {
+                    *count += 1
+                }
+                _ => runs.push((value, 1)),
+            }
+        }
+        let distinct = runs.len();
+        let min = runs.first().map_or(Value::Null, |(value, _)| value.clone());
+        let max = runs.last().map_or(Value::Null, |(value, _)| value.clone());
+
+        let mut by_count = (0..runs.len())
+            .filter(|i| runs[*i].1 > 1)
+            .collect::<Vec<usize>>();
+        by_count.sort_by(|a, b| runs[*b].1.cmp(&runs[*a].1).then(a.cmp(b)));
+        by_count.truncate(MOST_COMMON_VALUES);
+        let most_common = by_count
+            .iter()
+            .map(|i| runs[*i].clone())
+            .collect::<Vec<(Value, usize)>>();
+
+        // The other values are split in buckets of about the same number of rows, a value
+        // is never split across two buckets
+        let others = runs
+            .into_iter()
+            .enumerate()
+            .filter(|(i, _)| !by_count.contains(i))
+            .map(|(_, run)| run)
+            .collect::<Vec<(Value, usize)>>();
+        let total = others.iter().map(|(_, count)| count).sum::<usize>();
+        let depth = total.div_ceil(HISTOGRAM_BUCKETS).max(1);
+        let mut histogram: Vec<Bucket> = vec![];
+        for (value, count) in others {
+            match histogram.last_mut() {
+                Some(bucket) if bucket.rows < depth => {
+                    bucket.upper = value;
+                    bucket.rows += count;
+                    bucket.distinct += 1;
+                }
+                _ => histogram.push(Bucket {
+                    lower: value.clone(),
+                    upper: value,
+                    rows: count,
+                    distinct: 1,
+                }),
+            }
+        }
+
+        ColumnStats {
+            rows,
+            nulls,
+            distinct,
+            min,
+            max,
+            most_common,
+            histogram,
+        }
+    }
+
+    /// Estimated share of the rows `column op value` keeps.
+    pub fn selectivity(&self, op: ConstraintOp, value: &Value, collation: &CollationFn) -> f64 {
+        if self.rows == 0 {
+            return 0.0;
+        }
+        // Comparisons with NULL never hold
+        if value.is_null() {
+            return if op == ConstraintOp::Match { 1.0 } else { 0.0 };
+        }
+        let non_null = (self.rows - self.nulls) as f64;
+        let rows = match op {
+            ConstraintOp::Eq => self.rows_equal(value, collation),
+            ConstraintOp::Lt => self.rows_below(value, false, collation),
+            ConstraintOp::Le => self.rows_below(value, true, collation),
+            ConstraintOp::Gt => non_null - self.rows_below(value, true, collation),
+            ConstraintOp::Ge => non_null - self.rows_below(value, false, collation),
+            ConstraintOp::Match => return 1.0,
+        };
+        (rows / self.rows as f64).clamp(0.0, 1.0)
+    }
+
+    /// Estimated number of rows equal to `value`.
+    fn rows_equal(&self, value: &Value, collation: &CollationFn) -> f64 {
+        if let Some((_, count)) = self
+            .most_common
+            .iter()
+            .find(|(common, _)| common.compare(value, collation) == Ordering::Equal)
+        {
+            return *count as f64;
+        }
+        // Values of a bucket are taken as equally common
+        self.histogram
+            .iter()
+            .find(|bucket| {
+                bucket.lower.compare(value, collation) != Ordering::Greater
+                    && bucket.upper.compare(value, collation) != Ordering::Less
+            })
+            .map_or(0.0, |bucket| bucket.rows as f64 / bucket.distinct as f64)
+    }
+
+    /// Estimated number of rows below `value`, or equal to it if `inclusive`.
+    fn rows_below(&self, value: &Value, inclusive: bool, collation: &CollationFn) -> f64 {
+        let below = |other: &Value| match other.compare(value, collation) {
+            Ordering::Less => true,
+            Ordering::Equal => inclusive,
+            Ordering::Greater => false,
+        };
+        let common = self
+            .most_common
+            .iter()
+            .filter(|(common, _)| below(common))
+            .map(|(_, count)| *count as f64)
+            .sum::<f64>();
+        let histogram = self
+            .histogram
+            .iter()
+            .map(|bucket| {
+                if below(&bucket.upper) {
+                    bucket.rows as f64
+                } else if !below(&bucket.lower) {
+                    0.0
+                } else {
+                    bucket.rows as f64 * bucket_fraction(bucket, value)
+                }
+            })
+            .sum::<f64>();
+        common + histogram
+    }
+}
+
+/// Share of the rows of `bucket` below `value`, which falls inside it: interpolated
+/// between its bounds for numbers, half of them for other values.
+fn bucket_fraction(bucket: &Bucket, value: &Value) -> f64 {
+    let number = |value: &Value| match value {
+        Value::Integer(v) => Some(*v as f64),
+        Value::Real(v) => Some(*v),
+        _ => None,
+    };
+    match (number(&bucket.lower), number(&bucket.upper), number(value)) {
+        (Some(lower), Some(upper), Some(value)) if upper > lower => {
+            ((value - lower) / (upper - lower)).clamp(0.0, 1.0)
+        }
+        _ => 0.5,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn binary(a: &str, b: &str) -> Ordering {
+        a.cmp(b)
+    }
+
+    #[test]
+    fn column_stats_test() {
+        // 1..=100 once each, 7 forty times, 3 NULLs
+        let mut values = (1..=100).map(Value::Integer).collect::<Vec<Value>>();
+        values.extend(std::iter::repeat_n(Value::Integer(7), 40));
+        values.extend(std::iter::repeat_n(Value::Null, 3));
+        let stats = ColumnStats::build(values, &binary);
+        assert_eq!(stats.rows, 143);
+        assert_eq!(stats.nulls, 3);
+        assert_eq!(stats.distinct, 100);
+        assert_eq!(
+            (&stats.min, &stats.max),
+            (&Value::Integer(1), &Value::Integer(100))
+        );
+        assert_eq!(stats.most_common, vec![(Value::Integer(7), 41)]);
+        assert_eq!(stats.histogram.iter().map(|b| b.rows).sum::<usize>(), 99);
+        assert!(stats.histogram.len() <= HISTOGRAM_BUCKETS);
+
+        let estimate =
+            |op: ConstraintOp, value: i64| stats.selectivity(op, &Value::Integer(value), &binary);
+        assert!((estimate(ConstraintOp::Eq, 7) - 41.0 / 143.0).abs() < 1e-9);
+        assert!((estimate(ConstraintOp::Eq, 50) - 1.0 / 143.0).abs() < 1e-9);
+        assert_eq!(estimate(ConstraintOp::Eq, 500), 0.0);
+        // 50 rows below 51, besides the 41 sevens
+        let lt = estimate(ConstraintOp::Lt, 51) * 143.0;
+        assert!((lt - 90.0).abs() < 3.0, "{}", lt);
+        let ge = estimate(ConstraintOp::Ge, 51) * 143.0;
+        assert!((ge - 50.0).abs() < 3.0, "{}", ge);
+        assert_eq!(estimate(ConstraintOp::Gt, 100), 0.0);
+        assert_eq!(
+            stats.selectivity(ConstraintOp::Eq, &Value::Null, &binary),
+            0.0
+        );
+
+        let table = TableStats {
+            rows: 143,
+            columns: BTreeMap::from([("n".to_string(), stats)]),
+        };
+        assert_eq!(
+            table.selectivity("other", ConstraintOp::Lt, &Value::Integer(1), &binary),
+            DEFAULT_RANGE_SELECTIVITY
+        );
+    }
+}
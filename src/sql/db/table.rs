@@ -1,17 +1,23 @@
 use crate::error::{Result, SQLRiteError};
+use crate::sql::collation::CollationRegistry;
+use crate::sql::db::bloom::BloomFilter;
+use crate::sql::db::cache::Generation;
+use crate::sql::db::row_store::RowStore;
 use crate::sql::parser::create::CreateQuery;
-use serde::{Deserialize, Serialize};
-use std::cell::RefCell;
-use std::collections::{BTreeMap, HashMap};
+use crate::sql::value::Value;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::convert::TryFrom;
 use std::fmt;
-use std::rc::Rc;
+use std::ops::RangeBounds;
 
 use prettytable::{Cell as PrintCell, Row as PrintRow, Table as PrintTable};
 
 /// SQLRite data types
 /// Mapped after SQLite Data Type Storage Classes and SQLite Affinity Type
 /// (Datatypes In SQLite Version 3)[https://www.sqlite.org/datatype3.html]
-#[derive(Serialize, Deserialize, PartialEq, Debug)]
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
 pub enum DataType {
     Integer,
     Text,
@@ -52,20 +58,31 @@ impl fmt::Display for DataType {
 
 /// The schema for each SQL Table is represented in memory by
 /// following structure
-#[derive(Serialize, Deserialize, PartialEq, Debug)]
+///
+/// It is serialized as a `TableImage`, its rows by column.
+#[derive(PartialEq, Debug)]
 pub struct Table {
     /// Name of the table
     pub tb_name: String,
     /// HashMap with information about each column
     pub columns: Vec<Column>,
-    /// HashMap with information about each row
-    pub rows: Rc<RefCell<HashMap<String, Row>>>,
+    /// The rows of the table, in a B+tree keyed by ROWID that statements read and write
+    /// them through. A row may have a value in no column.
+    rows: RowStore,
+    /// The dictionary of each dictionary encoded TEXT column, by name. The rows hold the
+    /// code of their value in the column.
+    dictionaries: HashMap<String, Dictionary>,
     /// HashMap of SQL indexes on this table.
     pub indexes: HashMap<String, String>,
     /// ROWID of most recent insert
     pub last_rowid: i64,
     /// PRIMARY KEY Column name, if table does not have PRIMARY KEY this would be -1
     pub primary_key: String,
+    /// ROWIDs of the rows written during a bulk load, indexed when it ends
+    deferred_rowids: Option<Vec<i64>>,
+    /// Stamp of the rows of the table, a new one each time they are written. A table read
+    /// back from a snapshot gets a new one too.
+    generation: Generation,
 }
 
 impl Table {
@@ -75,7 +92,6 @@ impl Table {
         let columns = create_query.columns;
 
         let mut table_cols: Vec<Column> = vec![];
-        let table_rows: Rc<RefCell<HashMap<String, Row>>> = Rc::new(RefCell::new(HashMap::new()));
         for col in &columns {
             let col_name = &col.name;
             if col.is_pk {
@@ -87,52 +103,448 @@ impl Table {
                 col.is_pk,
                 col.not_null,
                 col.is_unique,
+                col.collation.clone(),
             ));
-
-            match DataType::new(col.datatype.to_string()) {
-                DataType::Integer => table_rows
-                    .clone()
-                    .borrow_mut()
-                    .insert(col.name.to_string(), Row::Integer(BTreeMap::new())),
-                DataType::Real => table_rows
-                    .clone()
-                    .borrow_mut()
-                    .insert(col.name.to_string(), Row::Real(BTreeMap::new())),
-                DataType::Text => table_rows
-                    .clone()
-                    .borrow_mut()
-                    .insert(col.name.to_string(), Row::Text(BTreeMap::new())),
-                DataType::Bool => table_rows
-                    .clone()
-                    .borrow_mut()
-                    .insert(col.name.to_string(), Row::Bool(BTreeMap::new())),
-                DataType::Invalid => table_rows
-                    .clone()
-                    .borrow_mut()
-                    .insert(col.name.to_string(), Row::None),
-                DataType::None => table_rows
-                    .clone()
-                    .borrow_mut()
-                    .insert(col.name.to_string(), Row::None),
-            };
         }
 
         Table {
             tb_name: table_name,
             columns: table_cols,
-            rows: table_rows,
+            rows: RowStore::new(),
+            dictionaries: HashMap::new(),
             indexes: HashMap::new(),
             last_rowid: 0,
             primary_key: primary_key,
+            deferred_rowids: None,
+            generation: Generation::next(),
         }
     }
 
+    /// Returns the indexes created automatically for the table, with their names: one for
+    /// each UNIQUE column and for a PRIMARY KEY that isn't the INTEGER rowid, like in SQLite.
+    ///
+    pub fn autoindexes(&self) -> Vec<(String, &Column)> {
+        let name = self
+            .tb_name
+            .split_once('.')
+            .map_or(self.tb_name.as_str(), |(_, name)| name);
+        self.columns
+            .iter()
+            .filter(|column| {
+                (column.is_unique || column.is_pk)
+                    && !(column.is_pk && column.datatype == DataType::Integer)
+            })
+            .enumerate()
+            .map(|(i, column)| (format!("sqlite_autoindex_{}_{}", name, i + 1), column))
+            .collect()
+    }
+
     /// Returns a `bool` informing if a `Column` with a specific name exists or not
     ///
     pub fn contains_column(&self, column: String) -> bool {
         self.columns.iter().any(|col| col.column_name == column)
     }
 
+    /// Returns every ROWID stored in the table, in ascending order, those of rows without
+    /// a value in any column included.
+    ///
+    pub fn rowids(&self) -> Vec<i64> {
+        self.rows.rowids()
+    }
+
+    /// Returns the rows whose ROWID is in `range`, in ascending order, with their values
+    /// in the order of the columns, read from the leaves of the B+tree one after the other.
+    ///
+    pub fn scan_rows(&self, range: impl RangeBounds<i64>) -> Vec<(i64, Vec<Value>)> {
+        self.rows
+            .scan(range)
+            .into_iter()
+            .map(|(rowid, values)| (rowid, self.decoded(values)))
+            .collect()
+    }
+
+    /// Bytes of memory taken by the caches of the table, the bits of its bloom filters.
+    pub fn cache_memory(&self) -> usize {
+        self.columns
+            .iter()
+            .filter_map(|column| column.bloom_filter.as_ref())
+            .map(BloomFilter::memory)
+            .sum()
+    }
+
+    /// Stamp of the rows of the table, which changes each time a row is written or deleted.
+    pub fn generation(&self) -> Generation {
+        self.generation
+    }
+
+    /// Releases the caches of the table, each bloom filter is rebuilt from its index the
+    /// next time a value is inserted.
+    pub fn release_caches(&mut self) {
+        for column in &mut self.columns {
+            if let Some(filter) = &mut column.bloom_filter {
+                filter.release();
+            }
+        }
+    }
+
+    /// Adds a bloom filter with the given false positive rate to the index of the UNIQUE
+    /// `column`, or removes its filter when the rate is `None`. The filter spares looking
+    /// up in the index the values that aren't in the column yet.
+    ///
+    pub fn set_bloom_filter(
+        &mut self,
+        column: &str,
+        false_positive_rate: Option<f64>,
+    ) -> Result<()> {
+        let column = self
+            .columns
+            .iter_mut()
+            .find(|col| col.column_name == column)
+            .ok_or_else(|| SQLRiteError::General(format!("No such column: {}", column)))?;
+        let false_positive_rate = match false_positive_rate {
+            Some(rate) => rate,
+            None => {
+                column.bloom_filter = None;
+                return Ok(());
+            }
+        };
+        if !column.is_unique || matches!(column.index, Index::None) {
+            return Err(SQLRiteError::General(format!(
+                "column {} has no UNIQUE index for a bloom filter",
+                column.column_name
+            )));
+        }
+        if column.collation.is_some() {
+            return Err(SQLRiteError::General(format!(
+                "column {} is compared with a collation, it can't have a bloom filter",
+                column.column_name
+            )));
+        }
+        if !(false_positive_rate > 0.0 && false_positive_rate < 1.0) {
+            return Err(SQLRiteError::General(
+                "the false positive rate of a bloom filter is between 0 and 1".to_string(),
+            ));
+        }
+        column.bloom_filter = Some(BloomFilter::new(false_positive_rate));
+        column.rebuild_bloom_filter();
+        Ok(())
+    }
+
+    /// Turns dictionary encoding of the TEXT `column` on or off. An encoded column stores
+    /// each distinct value once in a dictionary, and a small integer code per row, which
+    /// saves memory when few values repeat across many rows, like statuses or country
+    /// codes. Values are decoded when they are read, queries see no difference.
+    ///
+    pub fn set_dictionary_encoding(&mut self, column: &str, enabled: bool) -> Result<()> {
+        let position = self
+            .column_position(column)
+            .ok_or_else(|| SQLRiteError::General(format!("No such column: {}", column)))?;
+        if self.columns[position].datatype != DataType::Text {
+            return Err(SQLRiteError::General(format!(
+                "column {} isn't TEXT, it can't be dictionary encoded",
+                column
+            )));
+        }
+        if enabled == self.dictionaries.contains_key(column) {
+            return Ok(());
+        }
+        // Each row is rewritten with the code of its value, or the value of its code
+        let mut dictionary = self.dictionaries.remove(column).unwrap_or_default();
+        for (rowid, mut values) in self.rows.scan(..) {
+            values[position] = match std::mem::replace(&mut values[position], Value::Null) {
+                Value::Text(text) if enabled => Value::Integer(dictionary.encode(text)),
+                Value::Integer(code) if !enabled => dictionary.decode(code),
+                value => value,
+            };
+            self.rows.insert(rowid, &values);
+        }
+        if enabled {
+            self.dictionaries.insert(column.to_string(), dictionary);
+        }
+        Ok(())
+    }
+
+    /// Returns the number of distinct values in the dictionary of `column`, `None` if the
+    /// column isn't dictionary encoded.
+    ///
+    pub fn dictionary_size(&self, column: &str) -> Option<usize> {
+        self.dictionaries.get(column).map(Dictionary::len)
+    }
+
+    /// Returns true if the table has a row `rowid`, with a value that isn't NULL.
+    ///
+    pub fn contains_rowid(&self, rowid: i64) -> bool {
+        self.rows
+            .get(rowid)
+            .is_some_and(|values| values.iter().any(|value| !value.is_null()))
+    }
+
+    /// Removes the row `rowid` from every column and their indexes, returning true if
+    /// there was one.
+    ///
+    pub fn delete_row(&mut self, rowid: i64) -> bool {
+        self.generation = Generation::next();
+        self.unindex_row(rowid);
+        self.release_codes(rowid);
+        self.rows.delete(rowid)
+    }
+
+    /// Indexes the values of the row `rowid` in the indexes of the columns. Every change
+    /// to the rows goes through `index_row` once the row is written and `unindex_row`
+    /// before it is changed or deleted, which keeps the indexes in step with the rows.
+    /// During a bulk load the row is only noted, to be indexed when the load ends.
+    fn index_row(&mut self, rowid: i64) {
+        if let Some(rowids) = &mut self.deferred_rowids {
+            rowids.push(rowid);
+            return;
+        }
+        let values = self.row_values(rowid);
+        for (column, value) in self.columns.iter_mut().zip(&values) {
+            column.index_value(value, rowid);
+        }
+    }
+
+    /// Removes the row `rowid` from the indexes of the columns. A value other rows also
+    /// have stays indexed for them.
+    fn unindex_row(&mut self, rowid: i64) {
+        let values = self.row_values(rowid);
+        for (column, value) in self.columns.iter_mut().zip(&values) {
+            column.index.remove(value, rowid);
+        }
+    }
+
+    /// Starts a bulk load: the rows written until `end_bulk_load` aren't indexed one by
+    /// one, which makes loading many rows into a table with indexes much cheaper. Their
+    /// values are still checked against the values indexed before the load, but two of
+    /// the loaded rows breaking a UNIQUE constraint are only caught when the load ends.
+    ///
+    pub fn begin_bulk_load(&mut self) {
+        if self.deferred_rowids.is_none() {
+            self.deferred_rowids = Some(vec![]);
+        }
+    }
+
+    /// Ends a bulk load, building each index in one sorted pass over its values and those
+    /// of the rows loaded. Text values of UNIQUE columns declared with a COLLATE clause are
+    /// compared using that collation.
+    ///
+    /// Fails without changing the indexes if two rows have the same value in a UNIQUE
+    /// column, leaving the rows loaded unindexed: deleting them brings the table back to
+    /// where it was before the load.
+    ///
+    pub fn end_bulk_load(&mut self, collations: &CollationRegistry) -> Result<()> {
+        let rowids = match self.deferred_rowids.take() {
+            Some(rowids) => rowids,
+            None => return Ok(()),
+        };
+        if rowids.is_empty() {
+            return Ok(());
+        }
+
+        let mut indexes = vec![];
+        {
+            let loaded = rowids
+                .iter()
+                .map(|rowid| (*rowid, self.row_values(*rowid)))
+                .collect::<Vec<(i64, Vec<Value>)>>();
+            for (position, column) in self.columns.iter().enumerate() {
+                if let Index::None = column.index {
+                    continue;
+                }
+                let mut entries = column.index.entries();
+                entries.extend(
+                    loaded
+                        .iter()
+                        .map(|(rowid, values)| (values[position].clone(), *rowid))
+                        .filter(|(value, _)| !value.is_null()),
+                );
+                let mut index = match column.index {
+                    Index::Integer(_) => Index::Integer(BTreeMap::new()),
+                    _ => Index::Text(BTreeMap::new()),
+                };
+                let mut duplicate = index.build(&entries);
+                if let (true, Some(collation)) = (column.is_unique, &column.collation) {
+                    let cmp = collations.get(collation)?;
+                    let mut texts = entries
+                        .iter()
+                        .filter_map(|(value, _)| value.to_text())
+                        .collect::<Vec<String>>();
+                    texts.sort_by(|a, b| cmp(a, b));
+                    duplicate = texts
+                        .windows(2)
+                        .find(|pair| cmp(&pair[0], &pair[1]) == Ordering::Equal)
+                        .map(|pair| Value::Text(pair[1].to_string()));
+                }
+                if let (true, Some(value)) = (column.is_unique, duplicate) {
+                    return Err(SQLRiteError::General(format!(
+                        "Error: unique constraint violation for column {}. Value {} already exists for column {}",
+                        column.column_name, value, column.column_name
+                    )));
+                }
+                indexes.push((position, index));
+            }
+        }
+
+        for (position, index) in indexes {
+            let column = &mut self.columns[position];
+            column.index = index;
+            if column.bloom_filter.is_some() {
+                column.rebuild_bloom_filter();
+            }
+        }
+        Ok(())
+    }
+
+    /// Rebuilds the indexes of the table from its rows, each in one sorted pass.
+    ///
+    pub fn rebuild_indexes(&mut self, collations: &CollationRegistry) -> Result<()> {
+        for column in &mut self.columns {
+            column.index = match column.index {
+                Index::None => continue,
+                Index::Integer(_) => Index::Integer(BTreeMap::new()),
+                Index::Text(_) => Index::Text(BTreeMap::new()),
+            };
+        }
+        self.deferred_rowids = Some(self.rowids());
+        self.end_bulk_load(collations)
+    }
+
+    /// Checks that the indexes of the columns are in step with the rows: every value of a
+    /// column is indexed for the row that has it, every row indexed has the value, and the
+    /// values of UNIQUE columns are unique. Returns a description of each problem found.
+    ///
+    pub fn check_integrity(&self) -> Vec<String> {
+        let rows = self
+            .scan_rows(..)
+            .into_iter()
+            .collect::<BTreeMap<i64, Vec<Value>>>();
+        let mut problems = vec![];
+        for (position, column) in self.columns.iter().enumerate() {
+            if let Index::None = column.index {
+                continue;
+            }
+            let value_of = |rowid: i64| rows.get(&rowid).map(|values| &values[position]);
+            for (value, rowid) in column.index.entries() {
+                if value_of(rowid) != Some(&value) {
+                    problems.push(format!(
+                        "index of {}.{} points to row {} for {}, which the row doesn't have",
+                        self.tb_name, column.column_name, rowid, value
+                    ));
+                }
+            }
+            for (rowid, values) in &rows {
+                let (rowid, value) = (*rowid, &values[position]);
+                if value.is_null() {
+                    continue;
+                }
+                if !column.index.contains(value, rowid) {
+                    problems.push(format!(
+                        "row {} is missing from the index of {}.{}",
+                        rowid, self.tb_name, column.column_name
+                    ));
+                    continue;
+                }
+                // Each pair of rows sharing a value is reported once, from its second row
+                let first = column.index.get(value);
+                if let (true, Some(other)) = (column.is_unique, first.filter(|o| *o != rowid)) {
+                    problems.push(format!(
+                        "rows {} and {} both have {} in UNIQUE column {}.{}",
+                        other, rowid, value, self.tb_name, column.column_name
+                    ));
+                }
+            }
+        }
+        problems
+    }
+
+    /// Stores `values` as the row `rowid` of the table, replacing the row there was. The
+    /// columns missing from `columns` are NULL. Fails without changing anything if a value
+    /// doesn't have the type of its column, or breaks a UNIQUE constraint.
+    ///
+    pub fn write_row(&mut self, rowid: i64, columns: &[String], values: &[Value]) -> Result<()> {
+        self.generation = Generation::next();
+        for (name, value) in columns.iter().zip(values) {
+            let column = self
+                .columns
+                .iter()
+                .find(|column| &column.column_name == name)
+                .ok_or_else(|| SQLRiteError::General(format!("No such column: {}", name)))?;
+            if value.is_null() {
+                continue;
+            }
+            let valid = match column.datatype {
+                DataType::Integer => value.to_integer().is_some(),
+                DataType::Real => value.to_real().is_some(),
+                DataType::Bool => value.to_bool().is_some(),
+                DataType::Text => value.to_text().is_some(),
+                DataType::None | DataType::Invalid => false,
+            };
+            if !valid {
+                return Err(SQLRiteError::General(format!(
+                    "datatype mismatch: {} can't be stored in column {}",
+                    value, name
+                )));
+            }
+            let owner = if column.may_have(value) {
+                column.index.get(value)
+            } else {
+                None
+            };
+            if column.is_unique && owner.is_some_and(|owner| owner != rowid) {
+                return Err(SQLRiteError::General(format!(
+                    "Error: unique constraint violation for column {}. Value {} already exists for column {}",
+                    name, value, name
+                )));
+            }
+        }
+
+        self.delete_row(rowid);
+        self.store_row(rowid, columns, values);
+        self.index_row(rowid);
+        self.last_rowid = self.last_rowid.max(rowid);
+        Ok(())
+    }
+
+    /// Returns the `Value` stored for `column` at `rowid`, or `Value::Null` if there is none.
+    ///
+    pub fn get_value(&self, column: &str, rowid: i64) -> Result<Value> {
+        match self.column_position(column) {
+            Some(position) => Ok(self.row_values(rowid).swap_remove(position)),
+            None => Err(SQLRiteError::General(format!("No such column: {}", column))),
+        }
+    }
+
+    /// Returns the values of the row `rowid`, in the order of the columns of the table.
+    ///
+    pub fn row_values(&self, rowid: i64) -> Vec<Value> {
+        match self.rows.get(rowid) {
+            Some(values) => self.decoded(values),
+            None => vec![Value::Null; self.columns.len()],
+        }
+    }
+
+    /// Removes every row of the table, keeping its columns and their indexes as they are,
+    /// for the rows to be stored elsewhere and loaded back with `load_row`.
+    ///
+    pub fn clear_rows(&mut self) {
+        self.generation = Generation::next();
+        self.rows = RowStore::new();
+        for dictionary in self.dictionaries.values_mut() {
+            *dictionary = Dictionary::default();
+        }
+    }
+
+    /// Stores `values`, in the order of the columns, as the row `rowid` without checking
+    /// or indexing them: the row of a table whose indexes already have it, read back
+    /// after `clear_rows`.
+    ///
+    pub fn load_row(&mut self, rowid: i64, values: &[Value]) {
+        let mut values = values.to_vec();
+        values.resize(self.columns.len(), Value::Null);
+        self.put_row(rowid, &values);
+        self.last_rowid = self.last_rowid.max(rowid);
+    }
+
     /// Returns an immutable reference of `sql::db::table::Column` if the table contains a
     /// column with the specified key as a column name.
     ///
@@ -164,11 +576,13 @@ impl Table {
 
     /// Validates if columns and values being inserted violate the UNIQUE constraint
     /// As a reminder the PRIMARY KEY column automatically also is a UNIQUE column.
+    /// Text columns declared with a COLLATE clause compare values using that collation.
     ///
     pub fn validate_unique_constraint(
         &mut self,
-        cols: &Vec<String>,
-        values: &Vec<String>,
+        cols: &[String],
+        values: &[Value],
+        collations: &CollationRegistry,
     ) -> Result<()> {
         for (idx, name) in cols.iter().enumerate() {
             let column = self.get_column_mut(name.to_string()).unwrap();
@@ -182,7 +596,12 @@ impl Table {
                     let val = &values[idx];
                     match col_idx {
                         Index::Integer(index) => {
-                            if index.contains_key(&val.parse::<i32>().unwrap()) {
+                            // NULLs are never equal to another value
+                            let key = match val.to_integer() {
+                                Some(key) => key,
+                                None => continue,
+                            };
+                            if column.may_have(&Value::Integer(key)) && index.contains_key(&key) {
                                 return Err(SQLRiteError::General(format!(
                                     "Error: unique constraint violation for column {}.
                         Value {} already exists for column {}",
@@ -191,7 +610,21 @@ impl Table {
                             }
                         }
                         Index::Text(index) => {
-                            if index.contains_key(val) {
+                            let val = match val.to_text() {
+                                Some(val) => val,
+                                None => continue,
+                            };
+                            let exists = match &column.collation {
+                                Some(collation) => {
+                                    let cmp = collations.get(collation)?;
+                                    index.keys().any(|key| cmp(key, &val) == Ordering::Equal)
+                                }
+                                None => {
+                                    column.may_have(&Value::Text(val.to_string()))
+                                        && index.contains_key(&val)
+                                }
+                            };
+                            if exists {
                                 return Err(SQLRiteError::General(format!(
                                     "Error: unique constraint violation for column {}.
                         Value {} already exists for column {}",
@@ -212,6 +645,38 @@ impl Table {
         return Ok(());
     }
 
+    /// Returns the rows an INSERT of `values`, the values of every column in the order of
+    /// the columns, conflicts with: those holding one of its values in a UNIQUE or PRIMARY
+    /// KEY column, with the name of the column. Text columns declared with a COLLATE clause
+    /// compare values using that collation.
+    ///
+    pub fn conflicting_rows(
+        &self,
+        values: &[Value],
+        collations: &CollationRegistry,
+    ) -> Result<Vec<(String, i64)>> {
+        let mut conflicts = vec![];
+        for (column, value) in self.columns.iter().zip(values) {
+            if !column.is_unique || value.is_null() || !column.may_have(value) {
+                continue;
+            }
+            let owner = match (&column.index, &column.collation, value.to_text()) {
+                (Index::Text(index), Some(collation), Some(value)) => {
+                    let cmp = collations.get(collation)?;
+                    index
+                        .iter()
+                        .find(|(key, _)| cmp(key, &value) == Ordering::Equal)
+                        .and_then(|(_, rowids)| rowids.iter().next().copied())
+                }
+                (index, _, _) => index.get(value),
+            };
+            if let Some(rowid) = owner {
+                conflicts.push((column.column_name.to_string(), rowid));
+            }
+        }
+        Ok(conflicts)
+    }
+
     /// Inserts all VALUES in its approprieta COLUMNS, using the ROWID an embedded INDEX on all ROWS
     /// Every `Table` keeps track of the `last_rowid` in order to facilitate what the next one would be.
     /// One limitation of this data structure is that we can only have one write transaction at a time, otherwise
@@ -220,136 +685,161 @@ impl Table {
     /// Since we are loosely modeling after SQLite, this is also a limitation of SQLite (allowing only one write transcation at a time),
     /// So we are good. :)
     ///
-    pub fn insert_row(&mut self, cols: &Vec<String>, values: &Vec<String>) {
-        let mut next_rowid = self.last_rowid + i64::from(1);
+    /// Returns the rowid of the row, which is only the `last_rowid` if no row with a higher
+    /// rowid was inserted before it. Once a row has the largest rowid there can be, the rows
+    /// inserted without one fail like in SQLite, with a "database or disk is full" error.
+    pub fn insert_row(&mut self, cols: &[String], values: &[Value]) -> Result<i64> {
+        self.generation = Generation::next();
+        let mut next_rowid = self.last_rowid.checked_add(1);
+        let value_of = |column: &str| {
+            cols.iter()
+                .position(|col| col == column)
+                .map(|position| &values[position])
+                .filter(|value| !value.is_null())
+        };
 
         // Checks if table has a PRIMARY KEY
+        let mut assigned = None;
         if self.primary_key != "-1" {
-            // Checking if primary key is in INSERT QUERY columns
-            // If it is not, assign the next_rowid to it
-            if !cols.iter().any(|col| col == &self.primary_key) {
-                let rows_clone = Rc::clone(&self.rows);
-                let mut row_data = rows_clone.as_ref().borrow_mut();
-                let mut table_col_data = row_data.get_mut(&self.primary_key).unwrap();
-
-                // Getting the header based on the column name
-                let column_headers = self.get_column_mut(self.primary_key.to_string()).unwrap();
-
-                // Getting index for column, if it exist
-                let col_index = column_headers.get_mut_index();
-
-                // We only AUTO ASSIGN in case the ROW is a PRIMARY KEY and INTEGER type
-                match &mut table_col_data {
-                    Row::Integer(tree) => {
-                        let val = next_rowid as i32;
-                        tree.insert(next_rowid.clone(), val);
-                        if let Index::Integer(index) = col_index {
-                            index.insert(val, next_rowid.clone());
+            let datatype = self
+                .columns
+                .iter()
+                .find(|col| col.column_name == self.primary_key)
+                .map(|col| &col.datatype);
+
+            // We only AUTO ASSIGN in case the ROW is a PRIMARY KEY and INTEGER type
+            if datatype == Some(&DataType::Integer) {
+                match value_of(&self.primary_key) {
+                    // If PRIMARY KEY Column is in the Column list from INSERT Query,
+                    // the value assigned to it is the rowid, so every value is indexed
+                    // by same rowid. Also, next ROWID should keep AUTO INCREMENTING from
+                    // last ROWID
+                    Some(val) => {
+                        if let Some(val) = val.to_integer() {
+                            next_rowid = Some(val);
                         }
                     }
-                    _ => (),
-                }
-            } else {
-                // If PRIMARY KEY Column is in the Column list from INSERT Query,
-                // We get the value assigned to it in the VALUES part of the query
-                // and assign it to next_rowid, so every value if indexed by same rowid
-                // Also, next ROWID should keep AUTO INCREMENTING from last ROWID
-                let rows_clone = Rc::clone(&self.rows);
-                let mut row_data = rows_clone.as_ref().borrow_mut();
-                let mut table_col_data = row_data.get_mut(&self.primary_key).unwrap();
-
-                // Again, this is only valid for PRIMARY KEYs of INTEGER type
-                match &mut table_col_data {
-                    Row::Integer(_) => {
-                        for i in 0..cols.len() {
-                            // Getting column name
-                            let key = &cols[i];
-                            if key == &self.primary_key {
-                                let val = &values[i];
-                                next_rowid = val.parse::<i64>().unwrap();
-                            }
-                        }
+                    // Otherwise, or when it is NULL, assign the next_rowid to it
+                    None => {
+                        let rowid = next_rowid.ok_or_else(database_full)?;
+                        assigned = Some(Value::Integer(rowid));
                     }
-                    _ => (),
                 }
             }
         }
 
-        // This block checks if there are any columns from table missing
-        // from INSERT statement. If there are, we add "Null" to the column.
-        // We do this because otherwise the ROWID reference for each value would be wrong
-        // Since rows not always have the same length.
-        let column_names = self
+        let next_rowid = next_rowid.ok_or_else(database_full)?;
+
+        // Columns missing from the INSERT statement are NULL. Values are stored by the
+        // position of their column in the INSERT statement, in any order.
+        let row = self
             .columns
             .iter()
-            .map(|col| col.column_name.to_string())
-            .collect::<Vec<String>>();
-        let mut j: usize = 0;
-        // For every column in the INSERT statement
-        for i in 0..column_names.len() {
-            let mut val = String::from("Null");
-            let key = &column_names[i];
-
-            if let Some(key) = &cols.get(j) {
-                if &key.to_string() == &column_names[i] {
-                    // Getting column name
-                    val = values[j].to_string();
-                    j += 1;
-                } else {
-                    if &self.primary_key == &column_names[i] {
-                        continue;
-                    }
-                }
-            } else {
-                if &self.primary_key == &column_names[i] {
-                    continue;
+            .map(|col| match value_of(&col.column_name) {
+                Some(value) => value.clone(),
+                None if col.column_name == self.primary_key => {
+                    assigned.clone().unwrap_or(Value::Null)
                 }
-            }
+                None => Value::Null,
+            })
+            .collect::<Vec<Value>>();
+        self.put_row(next_rowid, &row);
+        self.index_row(next_rowid);
+        self.last_rowid = self.last_rowid.max(next_rowid);
+        Ok(next_rowid)
+    }
 
-            // Getting the rows from the column name
-            let rows_clone = Rc::clone(&self.rows);
-            let mut row_data = rows_clone.as_ref().borrow_mut();
-            let mut table_col_data = row_data.get_mut(key).unwrap();
+    /// Stores the `values` of the row `rowid` in their `columns`, NULL in the others,
+    /// replacing the row it was. The row is kept even if it has no value in any column.
+    fn store_row(&mut self, rowid: i64, columns: &[String], values: &[Value]) {
+        let row = self
+            .columns
+            .iter()
+            .map(|column| {
+                columns
+                    .iter()
+                    .position(|name| *name == column.column_name)
+                    .map_or(Value::Null, |position| values[position].clone())
+            })
+            .collect::<Vec<Value>>();
+        self.put_row(rowid, &row);
+    }
 
-            // Getting the header based on the column name
-            let column_headers = self.get_column_mut(key.to_string()).unwrap();
+    /// Stores `values`, one for each column, as the row `rowid` in the B+tree, replacing
+    /// the row it was. A value is stored with the type of its column, or as NULL if it
+    /// doesn't have it, and by its code in a dictionary encoded column.
+    fn put_row(&mut self, rowid: i64, values: &[Value]) {
+        self.release_codes(rowid);
+        let dictionaries = &mut self.dictionaries;
+        let row = self
+            .columns
+            .iter()
+            .zip(values)
+            .map(|(column, value)| {
+                let stored = match column.datatype {
+                    DataType::Integer => value.to_integer().map(Value::Integer),
+                    DataType::Real => value.to_real().map(Value::Real),
+                    DataType::Bool => value.to_bool().map(Value::Bool),
+                    DataType::Text => value.to_text().map(|text| {
+                        match dictionaries.get_mut(&column.column_name) {
+                            Some(dictionary) => Value::Integer(dictionary.encode(text)),
+                            None => Value::Text(text),
+                        }
+                    }),
+                    DataType::None | DataType::Invalid => None,
+                };
+                stored.unwrap_or(Value::Null)
+            })
+            .collect::<Vec<Value>>();
+        self.rows.insert(rowid, &row);
+    }
 
-            // Getting index for column, if it exist
-            let col_index = column_headers.get_mut_index();
+    /// Gives back the codes the row `rowid` holds in the dictionaries of the columns,
+    /// before the row is replaced or deleted.
+    fn release_codes(&mut self, rowid: i64) {
+        if self.dictionaries.is_empty() {
+            return;
+        }
+        let values = match self.rows.get(rowid) {
+            Some(values) => values,
+            None => return,
+        };
+        for (column, value) in self.columns.iter().zip(values) {
+            if let (Some(dictionary), Value::Integer(code)) =
+                (self.dictionaries.get_mut(&column.column_name), value)
+            {
+                dictionary.release(code);
+            }
+        }
+    }
 
-            match &mut table_col_data {
-                Row::Integer(tree) => {
-                    let val = val.parse::<i32>().unwrap();
-                    tree.insert(next_rowid.clone(), val);
-                    if let Index::Integer(index) = col_index {
-                        index.insert(val, next_rowid.clone());
-                    }
-                }
-                Row::Text(tree) => {
-                    tree.insert(next_rowid.clone(), val.to_string());
-                    if let Index::Text(index) = col_index {
-                        index.insert(val.to_string(), next_rowid.clone());
-                    }
-                }
-                Row::Real(tree) => {
-                    let val = val.parse::<f32>().unwrap();
-                    tree.insert(next_rowid.clone(), val);
-                }
-                Row::Bool(tree) => {
-                    let val = val.parse::<bool>().unwrap();
-                    tree.insert(next_rowid.clone(), val);
-                }
-                Row::None => panic!("None data Found"),
+    /// The values of a row as the B+tree holds them, with the codes of the dictionary
+    /// encoded columns decoded.
+    fn decoded(&self, mut values: Vec<Value>) -> Vec<Value> {
+        values.resize(self.columns.len(), Value::Null);
+        if self.dictionaries.is_empty() {
+            return values;
+        }
+        for (column, value) in self.columns.iter().zip(values.iter_mut()) {
+            if let (Some(dictionary), Value::Integer(code)) =
+                (self.dictionaries.get(&column.column_name), &value)
+            {
+                *value = dictionary.decode(*code);
             }
         }
-        self.last_rowid = next_rowid;
+        values
+    }
+
+    /// Position of the column `name` among the columns of the table.
+    fn column_position(&self, name: &str) -> Option<usize> {
+        self.columns.iter().position(|col| col.column_name == name)
     }
 
     /// Print the table schema to standard output in a pretty formatted way
     ///
     /// # Example
     ///
-    /// ```
+    /// ```text
     /// let table = Table::new(payload);
     /// table.print_table_schema();
     ///
@@ -393,7 +883,7 @@ impl Table {
     ///
     /// # Example
     ///
-    /// ```
+    /// ```text
     /// let db_table = db.get_table_mut(table_name.to_string()).unwrap();
     /// db_table.print_table_data();
     ///
@@ -425,28 +915,21 @@ impl Table {
                 .collect::<Vec<PrintCell>>(),
         );
 
-        let rows_clone = Rc::clone(&self.rows);
-        let row_data = rows_clone.as_ref().borrow();
-        let first_col_data = row_data
-            .get(&self.columns.first().unwrap().column_name)
-            .unwrap();
-        let num_rows = first_col_data.count();
-        let mut print_table_rows: Vec<PrintRow> = vec![PrintRow::new(vec![]); num_rows];
-
-        for col_name in &column_names {
-            let col_val = row_data
-                .get(col_name)
-                .expect("Can't find any rows with the given column");
-            let columns: Vec<String> = col_val.get_serialized_col_data();
-
-            for i in 0..num_rows {
-                if let Some(cell) = &columns.get(i) {
-                    print_table_rows[i].add_cell(PrintCell::new(cell));
-                } else {
-                    print_table_rows[i].add_cell(PrintCell::new(""));
-                }
-            }
-        }
+        // A row per ROWID, with an empty cell for each NULL
+        let print_table_rows = self
+            .scan_rows(..)
+            .into_iter()
+            .map(|(_, values)| {
+                let cells = values
+                    .iter()
+                    .map(|value| match value {
+                        Value::Null => PrintCell::new(""),
+                        value => PrintCell::new(&value.to_string()),
+                    })
+                    .collect::<Vec<PrintCell>>();
+                PrintRow::new(cells)
+            })
+            .collect::<Vec<PrintRow>>();
 
         print_table.add_row(header_row);
         for row in print_table_rows {
@@ -473,9 +956,16 @@ pub struct Column {
     pub is_unique: bool,
     /// Value representing if column is Indexed or not
     pub is_indexed: bool,
+    /// Name of the collating sequence used to compare the column's text values,
+    /// `None` for the default BINARY collation
+    #[serde(default)]
+    pub collation: Option<String>,
     /// BtreeMap mapping the index to a payload value on the corresponding Row
     /// Mapped using a ROWID
     pub index: Index,
+    /// Bloom filter over the values of the index, set with `Table::set_bloom_filter`
+    #[serde(default)]
+    pub bloom_filter: Option<BloomFilter>,
 }
 
 impl Column {
@@ -485,6 +975,7 @@ impl Column {
         is_pk: bool,
         not_null: bool,
         is_unique: bool,
+        collation: Option<String>,
     ) -> Self {
         let dt = DataType::new(datatype);
         let index = match dt {
@@ -503,58 +994,564 @@ impl Column {
             not_null,
             is_unique,
             is_indexed: if is_pk { true } else { false },
+            collation,
             index,
+            bloom_filter: None,
         }
     }
 
     pub fn get_mut_index(&mut self) -> &mut Index {
         return &mut self.index;
     }
+
+    /// Returns false if no row has `value` in the column, which the bloom filter of the
+    /// column tells without looking the value up in the index. True if one may have it.
+    pub fn may_have(&self, value: &Value) -> bool {
+        match (&self.bloom_filter, self.bloom_key(value)) {
+            (Some(filter), Some(key)) => filter.may_contain(&key),
+            _ => true,
+        }
+    }
+
+    /// Indexes `value` as the value of the row `rowid`, adding it to the bloom filter.
+    fn index_value(&mut self, value: &Value, rowid: i64) {
+        self.index.insert(value, rowid);
+        let filter = match &self.bloom_filter {
+            Some(filter) => filter,
+            None => return,
+        };
+        if filter.needs_rebuild() {
+            self.rebuild_bloom_filter();
+        } else if let Some(key) = self.bloom_key(value) {
+            if let Some(filter) = &mut self.bloom_filter {
+                filter.insert(&key);
+            }
+        }
+    }
+
+    /// Refills the bloom filter with the values of the index, sized for their number.
+    fn rebuild_bloom_filter(&mut self) {
+        let keys = match &self.index {
+            Index::Integer(index) => index
+                .keys()
+                .map(|key| key.to_le_bytes().to_vec())
+                .collect::<Vec<Vec<u8>>>(),
+            Index::Text(index) => index.keys().map(|key| key.as_bytes().to_vec()).collect(),
+            Index::None => vec![],
+        };
+        if let Some(filter) = &mut self.bloom_filter {
+            filter.reset(keys.len());
+            for key in &keys {
+                filter.insert(key);
+            }
+        }
+    }
+
+    /// The bytes `value` is hashed as in the bloom filter: its key in the index.
+    fn bloom_key(&self, value: &Value) -> Option<Vec<u8>> {
+        match &self.index {
+            Index::Integer(_) => value.to_integer().map(|value| value.to_le_bytes().to_vec()),
+            Index::Text(_) => value.to_text().map(String::into_bytes),
+            Index::None => None,
+        }
+    }
 }
 
 /// The schema for each SQL column index in every table is represented in memory
 /// by following structure
+///
+/// Each value maps to the ROWIDs of the rows having it, a single one in a UNIQUE column.
 #[derive(Serialize, Deserialize, PartialEq, Debug)]
 pub enum Index {
-    Integer(BTreeMap<i32, i64>),
-    Text(BTreeMap<String, i64>),
+    Integer(#[serde(deserialize_with = "deserialize_index")] BTreeMap<i64, BTreeSet<i64>>),
+    Text(#[serde(deserialize_with = "deserialize_index")] BTreeMap<String, BTreeSet<i64>>),
     None,
 }
 
-/// The schema for each SQL row in every table is represented in memory
-/// by following structure
+impl Index {
+    /// Indexes `value` as the value of the row `rowid`. NULLs aren't indexed.
+    fn insert(&mut self, value: &Value, rowid: i64) {
+        match self {
+            Index::Integer(index) => {
+                if let Some(value) = value.to_integer() {
+                    index.entry(value).or_default().insert(rowid);
+                }
+            }
+            Index::Text(index) => {
+                if let Some(value) = value.to_text() {
+                    index.entry(value).or_default().insert(rowid);
+                }
+            }
+            Index::None => {}
+        }
+    }
+
+    /// Removes the row `rowid` from the entry of `value`, and the entry once no row has
+    /// the value any more.
+    fn remove(&mut self, value: &Value, rowid: i64) {
+        fn remove_rowid<K: Ord>(index: &mut BTreeMap<K, BTreeSet<i64>>, key: K, rowid: i64) {
+            if let Some(rowids) = index.get_mut(&key) {
+                rowids.remove(&rowid);
+                if rowids.is_empty() {
+                    index.remove(&key);
+                }
+            }
+        }
+        match self {
+            Index::Integer(index) => {
+                if let Some(value) = value.to_integer() {
+                    remove_rowid(index, value, rowid);
+                }
+            }
+            Index::Text(index) => {
+                if let Some(value) = value.to_text() {
+                    remove_rowid(index, value, rowid);
+                }
+            }
+            Index::None => {}
+        }
+    }
+
+    /// Fills the empty index with `entries` in one pass, sorting them first. Returns one
+    /// of the values more than one row has, if any.
+    fn build(&mut self, entries: &[(Value, i64)]) -> Option<Value> {
+        match self {
+            Index::Integer(index) => {
+                let mut entries = entries
+                    .iter()
+                    .filter_map(|(value, rowid)| value.to_integer().map(|value| (value, *rowid)))
+                    .collect::<Vec<(i64, i64)>>();
+                entries.sort_unstable();
+                let (entries, duplicate) = group_rowids(entries);
+                *index = entries.into_iter().collect();
+                duplicate.map(Value::Integer)
+            }
+            Index::Text(index) => {
+                let mut entries = entries
+                    .iter()
+                    .filter_map(|(value, rowid)| value.to_text().map(|value| (value, *rowid)))
+                    .collect::<Vec<(String, i64)>>();
+                entries.sort_unstable();
+                let (entries, duplicate) = group_rowids(entries);
+                *index = entries.into_iter().collect();
+                duplicate.map(Value::Text)
+            }
+            Index::None => None,
+        }
+    }
+
+    /// Returns the rowid of the row indexed with `value`, the first of them if several
+    /// rows have it.
+    pub fn get(&self, value: &Value) -> Option<i64> {
+        self.rowids(value).into_iter().next()
+    }
+
+    /// Returns the rowids of the rows indexed with `value`, in order.
+    pub fn rowids(&self, value: &Value) -> Vec<i64> {
+        let rowids = match self {
+            Index::Integer(index) => value.to_integer().and_then(|value| index.get(&value)),
+            Index::Text(index) => value.to_text().and_then(|value| index.get(&value)),
+            Index::None => None,
+        };
+        rowids.map_or(vec![], |rowids| rowids.iter().copied().collect())
+    }
+
+    /// Returns true if the row `rowid` is indexed with `value`.
+    fn contains(&self, value: &Value, rowid: i64) -> bool {
+        let rowids = match self {
+            Index::Integer(index) => value.to_integer().and_then(|value| index.get(&value)),
+            Index::Text(index) => value.to_text().and_then(|value| index.get(&value)),
+            Index::None => None,
+        };
+        rowids.is_some_and(|rowids| rowids.contains(&rowid))
+    }
+
+    /// The values indexed, with the rowid of each row having them.
+    fn entries(&self) -> Vec<(Value, i64)> {
+        match self {
+            Index::Integer(index) => index
+                .iter()
+                .flat_map(|(value, rowids)| {
+                    rowids
+                        .iter()
+                        .map(move |rowid| (Value::Integer(*value), *rowid))
+                })
+                .collect(),
+            Index::Text(index) => index
+                .iter()
+                .flat_map(|(value, rowids)| {
+                    rowids
+                        .iter()
+                        .map(move |rowid| (Value::Text(value.to_string()), *rowid))
+                })
+                .collect(),
+            Index::None => vec![],
+        }
+    }
+}
+
+/// Serializes `map` in the order of its keys, for a database to be written to the same bytes
+/// whatever order its maps hold their entries in.
+pub(crate) fn serialize_sorted<S, K, V>(
+    map: &HashMap<K, V>,
+    serializer: S,
+) -> std::result::Result<S::Ok, S::Error>
+where
+    S: Serializer,
+    K: Serialize + Ord,
+    V: Serialize,
+{
+    map.iter()
+        .collect::<BTreeMap<&K, &V>>()
+        .serialize(serializer)
+}
+
+/// A table as it is serialized, in snapshots and copies of databases and in the catalog of
+/// a database file: its rows by column, each column a map of the values of the rows by
+/// ROWID, the way tables held their rows before they were kept in B+trees.
+#[derive(Serialize)]
+struct TableImage<'a> {
+    tb_name: &'a str,
+    columns: &'a [Column],
+    rows: BTreeMap<&'a str, Row>,
+    indexes: BTreeMap<&'a String, &'a String>,
+    last_rowid: i64,
+    primary_key: &'a str,
+    row_ids: BTreeSet<i64>,
+}
+
+/// A `TableImage` read back
+#[derive(Deserialize)]
+struct StoredTable {
+    tb_name: String,
+    columns: Vec<Column>,
+    rows: HashMap<String, Row>,
+    indexes: HashMap<String, String>,
+    last_rowid: i64,
+    primary_key: String,
+    /// ROWID of every row, those without a value in any column included. Tables saved
+    /// before it was kept read back without it, their rows being those with a value in
+    /// some column.
+    #[serde(default)]
+    row_ids: BTreeSet<i64>,
+}
+
+impl Serialize for Table {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        let mut columns = self
+            .columns
+            .iter()
+            .map(|column| match self.dictionaries.get(&column.column_name) {
+                Some(dictionary) => Row::Dictionary(dictionary.column()),
+                None => Row::empty(&column.datatype),
+            })
+            .collect::<Vec<Row>>();
+        let mut row_ids = BTreeSet::new();
+        for (rowid, values) in self.rows.scan(..) {
+            for (column, value) in columns.iter_mut().zip(values) {
+                column.insert(rowid, value);
+            }
+            row_ids.insert(rowid);
+        }
+        TableImage {
+            tb_name: &self.tb_name,
+            columns: &self.columns,
+            rows: self
+                .columns
+                .iter()
+                .map(|column| column.column_name.as_str())
+                .zip(columns)
+                .collect(),
+            indexes: self.indexes.iter().collect(),
+            last_rowid: self.last_rowid,
+            primary_key: &self.primary_key,
+            row_ids,
+        }
+        .serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Table {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        let stored = StoredTable::deserialize(deserializer)?;
+        let mut data = stored.rows;
+        let columns = stored
+            .columns
+            .iter()
+            .map(|column| data.remove(&column.column_name).unwrap_or(Row::None))
+            .collect::<Vec<Row>>();
+        let mut rowids = stored.row_ids;
+        for column in &columns {
+            rowids.extend(column.rowids());
+        }
+        let mut rows = RowStore::new();
+        for rowid in rowids {
+            let values = columns
+                .iter()
+                .map(|column| column.get_value(rowid))
+                .collect::<Vec<Value>>();
+            rows.insert(rowid, &values);
+        }
+        let dictionaries = stored
+            .columns
+            .iter()
+            .zip(columns)
+            .filter_map(|(column, data)| match data {
+                Row::Dictionary(data) => Some((column.column_name.to_string(), data.dictionary())),
+                _ => None,
+            })
+            .collect();
+        Ok(Table {
+            tb_name: stored.tb_name,
+            columns: stored.columns,
+            rows,
+            dictionaries,
+            indexes: stored.indexes,
+            last_rowid: stored.last_rowid,
+            primary_key: stored.primary_key,
+            deferred_rowids: None,
+            generation: Generation::next(),
+        })
+    }
+}
+
+/// Error of a row inserted without a rowid once the largest rowid is taken
+pub(crate) fn database_full() -> SQLRiteError {
+    SQLRiteError::General(format!(
+        "database or disk is full: no rowid is left after {}",
+        i64::MAX
+    ))
+}
+
+/// Groups the rowids of the sorted `entries` by key, returning them with one of the keys
+/// there was more than one entry for, if any.
+fn group_rowids<K: PartialEq + Clone>(
+    entries: Vec<(K, i64)>,
+) -> (Vec<(K, BTreeSet<i64>)>, Option<K>) {
+    let mut grouped: Vec<(K, BTreeSet<i64>)> = Vec::with_capacity(entries.len());
+    let mut duplicate = None;
+    for (key, rowid) in entries {
+        match grouped.last_mut() {
+            Some(last) if last.0 == key => {
+                last.1.insert(rowid);
+                duplicate = Some(key);
+            }
+            _ => grouped.push((key, BTreeSet::from([rowid]))),
+        }
+    }
+    (grouped, duplicate)
+}
+
+/// Reads the entries of an index. Indexes saved as JSON before they kept every row of a
+/// value have a single rowid for each value instead of a list, which is read as a list of
+/// one: the rows they left out are indexed again when the indexes are rebuilt.
+fn deserialize_index<'de, D, K>(
+    deserializer: D,
+) -> std::result::Result<BTreeMap<K, BTreeSet<i64>>, D::Error>
+where
+    D: Deserializer<'de>,
+    K: Deserialize<'de> + Ord,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Rowids {
+        One(i64),
+        Many(BTreeSet<i64>),
+    }
+    // Bincode snapshots are only read by the version that wrote them, and can't tell
+    // one from the other
+    if !deserializer.is_human_readable() {
+        return BTreeMap::deserialize(deserializer);
+    }
+    let entries = BTreeMap::<K, Rowids>::deserialize(deserializer)?;
+    Ok(entries
+        .into_iter()
+        .map(|(key, rowids)| match rowids {
+            Rowids::One(rowid) => (key, BTreeSet::from([rowid])),
+            Rowids::Many(rowids) => (key, rowids),
+        })
+        .collect())
+}
+
+/// The values of a column of the rows of a table in a `TableImage`
 ///
 /// This is an enum representing each of the available types organized in a BTreeMap
 /// data structure, using the ROWID and key and each corresponding type as value
 #[derive(Serialize, Deserialize, PartialEq, Debug)]
 pub enum Row {
-    Integer(BTreeMap<i64, i32>),
+    Integer(BTreeMap<i64, i64>),
     Text(BTreeMap<i64, String>),
-    Real(BTreeMap<i64, f32>),
+    Real(BTreeMap<i64, f64>),
     Bool(BTreeMap<i64, bool>),
+    /// A TEXT column stored with dictionary encoding
+    Dictionary(DictionaryColumn),
     None,
 }
 
+/// A dictionary encoded TEXT column in a `TableImage`: its dictionary, and the code of the
+/// value of each row
+#[derive(Serialize, Deserialize, PartialEq, Debug, Default)]
+pub struct DictionaryColumn {
+    /// Code of the value of each row
+    codes: BTreeMap<i64, u32>,
+    /// The values, by code
+    values: Vec<String>,
+    /// Number of rows with each code
+    counts: Vec<usize>,
+    /// Code of each value
+    #[serde(serialize_with = "serialize_sorted")]
+    lookup: HashMap<String, u32>,
+}
+
+impl DictionaryColumn {
+    fn dictionary(self) -> Dictionary {
+        Dictionary {
+            values: self.values,
+            counts: self.counts,
+            lookup: self.lookup,
+        }
+    }
+}
+
+/// The dictionary of a dictionary encoded TEXT column: every distinct value is stored
+/// once, rows store the code of their value
+#[derive(PartialEq, Debug, Default)]
+struct Dictionary {
+    /// The values, by code
+    values: Vec<String>,
+    /// Number of rows with each code, a code no row has any more is reused
+    counts: Vec<usize>,
+    /// Code of each value
+    lookup: HashMap<String, u32>,
+}
+
+impl Dictionary {
+    /// Number of distinct values rows have.
+    fn len(&self) -> usize {
+        self.lookup.len()
+    }
+
+    /// The value of `code`, NULL for a code that has none.
+    fn decode(&self, code: i64) -> Value {
+        match usize::try_from(code)
+            .ok()
+            .and_then(|code| self.values.get(code))
+        {
+            Some(value) => Value::Text(value.to_string()),
+            None => Value::Null,
+        }
+    }
+
+    /// The code of `value` for a row having it: the code of the value if another row has
+    /// it, otherwise a code no row has any more, or a new one.
+    fn encode(&mut self, value: String) -> i64 {
+        let code = match self.lookup.get(&value) {
+            Some(code) => *code,
+            None => {
+                let code = match self.counts.iter().position(|count| *count == 0) {
+                    Some(code) => {
+                        self.values[code] = value.to_string();
+                        code as u32
+                    }
+                    None => {
+                        self.values.push(value.to_string());
+                        self.counts.push(0);
+                        (self.values.len() - 1) as u32
+                    }
+                };
+                self.lookup.insert(value, code);
+                code
+            }
+        };
+        self.counts[code as usize] += 1;
+        i64::from(code)
+    }
+
+    /// Counts one row less having the value of `code`.
+    fn release(&mut self, code: i64) {
+        let code = match usize::try_from(code) {
+            Ok(code) if code < self.counts.len() => code,
+            _ => return,
+        };
+        self.counts[code] = self.counts[code].saturating_sub(1);
+        if self.counts[code] == 0 {
+            self.lookup.remove(&self.values[code]);
+            self.values[code].clear();
+        }
+    }
+
+    /// The dictionary in a `TableImage`, for rows to be given their codes.
+    fn column(&self) -> DictionaryColumn {
+        DictionaryColumn {
+            codes: BTreeMap::new(),
+            values: self.values.clone(),
+            counts: self.counts.clone(),
+            lookup: self.lookup.clone(),
+        }
+    }
+}
+
 impl Row {
-    fn get_serialized_col_data(&self) -> Vec<String> {
-        match self {
-            Row::Integer(cd) => cd.iter().map(|(_i, v)| v.to_string()).collect(),
-            Row::Real(cd) => cd.iter().map(|(_i, v)| v.to_string()).collect(),
-            Row::Text(cd) => cd.iter().map(|(_i, v)| v.to_string()).collect(),
-            Row::Bool(cd) => cd.iter().map(|(_i, v)| v.to_string()).collect(),
-            Row::None => panic!("Found None in columns"),
+    /// The values of a column of type `datatype`, of no row yet.
+    fn empty(datatype: &DataType) -> Row {
+        match datatype {
+            DataType::Integer => Row::Integer(BTreeMap::new()),
+            DataType::Real => Row::Real(BTreeMap::new()),
+            DataType::Text => Row::Text(BTreeMap::new()),
+            DataType::Bool => Row::Bool(BTreeMap::new()),
+            DataType::Invalid | DataType::None => Row::None,
         }
     }
 
-    fn count(&self) -> usize {
+    fn rowids(&self) -> Vec<i64> {
         match self {
-            Row::Integer(cd) => cd.len(),
-            Row::Real(cd) => cd.len(),
-            Row::Text(cd) => cd.len(),
-            Row::Bool(cd) => cd.len(),
-            Row::None => panic!("Found None in columns"),
+            Row::Integer(cd) => cd.keys().copied().collect(),
+            Row::Real(cd) => cd.keys().copied().collect(),
+            Row::Text(cd) => cd.keys().copied().collect(),
+            Row::Bool(cd) => cd.keys().copied().collect(),
+            Row::Dictionary(cd) => cd.codes.keys().copied().collect(),
+            Row::None => vec![],
+        }
+    }
+
+    /// Adds `value`, as the B+tree of the table stores it, as the value of the row
+    /// `rowid`. NULLs aren't kept.
+    fn insert(&mut self, rowid: i64, value: Value) {
+        match (self, value) {
+            (Row::Integer(cd), Value::Integer(value)) => {
+                cd.insert(rowid, value);
+            }
+            (Row::Real(cd), Value::Real(value)) => {
+                cd.insert(rowid, value);
+            }
+            (Row::Text(cd), Value::Text(value)) => {
+                cd.insert(rowid, value);
+            }
+            (Row::Bool(cd), Value::Bool(value)) => {
+                cd.insert(rowid, value);
+            }
+            (Row::Dictionary(cd), Value::Integer(code)) => {
+                if let Ok(code) = u32::try_from(code) {
+                    cd.codes.insert(rowid, code);
+                }
+            }
+            _ => {}
         }
     }
+
+    /// The value of the row `rowid`, the code of its value in a dictionary encoded
+    /// column, as the B+tree of the table stores it.
+    fn get_value(&self, rowid: i64) -> Value {
+        let value = match self {
+            Row::Integer(cd) => cd.get(&rowid).map(|v| Value::Integer(*v)),
+            Row::Real(cd) => cd.get(&rowid).map(|v| Value::Real(*v)),
+            Row::Text(cd) => cd.get(&rowid).map(|v| Value::Text(v.to_string())),
+            Row::Bool(cd) => cd.get(&rowid).map(|v| Value::Bool(*v)),
+            Row::Dictionary(cd) => cd.codes.get(&rowid).map(|v| Value::Integer(i64::from(*v))),
+            Row::None => None,
+        };
+        value.unwrap_or(Value::Null)
+    }
 }
 
 #[cfg(test)]
@@ -639,4 +1636,217 @@ mod tests {
         let lines_printed = table.print_table_schema();
         assert_eq!(lines_printed, Ok(9));
     }
+
+    #[test]
+    fn index_maintenance_test() {
+        let dialect = SQLiteDialect {};
+        let mut ast = Parser::parse_sql(
+            &dialect,
+            "CREATE TABLE users (id INTEGER PRIMARY KEY, email TEXT UNIQUE, team TEXT);",
+        )
+        .unwrap();
+        let mut table = Table::new(CreateQuery::new(&ast.pop().unwrap()).unwrap());
+        let columns = ["email".to_string(), "team".to_string()];
+        for (email, team) in [("a@x", "red"), ("b@x", "red"), ("c@x", "blue")] {
+            let values = [
+                Value::Text(email.to_string()),
+                Value::Text(team.to_string()),
+            ];
+            table.insert_row(&columns, &values).unwrap();
+        }
+        assert!(table.check_integrity().is_empty());
+        let team = &table.columns[2].index;
+        assert_eq!(team.rowids(&Value::Text("red".to_string())), vec![1, 2]);
+
+        // A deleted row is no longer indexed, the values others share stay indexed
+        assert!(table.delete_row(2));
+        assert!(!table.delete_row(2));
+        let email = &table.columns[1].index;
+        assert_eq!(email.get(&Value::Text("b@x".to_string())), None);
+        let team = &table.columns[2].index;
+        assert_eq!(team.rowids(&Value::Text("red".to_string())), vec![1]);
+        assert!(table.check_integrity().is_empty());
+
+        // Rewriting a row replaces its index entries
+        let values = [
+            Value::Text("d@x".to_string()),
+            Value::Text("blue".to_string()),
+        ];
+        table.write_row(1, &columns, &values).unwrap();
+        let email = &table.columns[1].index;
+        assert_eq!(email.get(&Value::Text("a@x".to_string())), None);
+        assert_eq!(email.get(&Value::Text("d@x".to_string())), Some(1));
+        let values = [Value::Text("c@x".to_string()), Value::Null];
+        assert!(table.write_row(1, &columns, &values).is_err());
+        assert!(table.check_integrity().is_empty());
+
+        // With a bloom filter, values that aren't in the column yet skip the index
+        assert!(table.set_bloom_filter("team", Some(0.01)).is_err());
+        table.set_bloom_filter("email", Some(0.01)).unwrap();
+        let email = &table.columns[1];
+        assert!(email.may_have(&Value::Text("c@x".to_string())));
+        let values = [Value::Text("e@x".to_string()), Value::Null];
+        table.write_row(5, &columns, &values).unwrap();
+        assert!(table.columns[1].may_have(&Value::Text("e@x".to_string())));
+        let values = [Value::Text("c@x".to_string()), Value::Null];
+        assert!(table.write_row(6, &columns, &values).is_err());
+        table.set_bloom_filter("email", None).unwrap();
+        assert!(table.check_integrity().is_empty());
+
+        // A stale entry is reported
+        if let Index::Text(index) = &mut table.columns[1].index {
+            index.insert("b@x".to_string(), BTreeSet::from([2]));
+        }
+        assert_eq!(table.check_integrity().len(), 1);
+
+        // Indexes saved with a single rowid for each value read back as lists of one
+        let index: Index = serde_json::from_str(r#"{"Text": {"red": 1}}"#).unwrap();
+        assert_eq!(index.rowids(&Value::Text("red".to_string())), vec![1]);
+    }
+
+    #[test]
+    fn insert_row_rowid_test() {
+        let dialect = SQLiteDialect {};
+        let mut ast = Parser::parse_sql(
+            &dialect,
+            "CREATE TABLE users (id INTEGER PRIMARY KEY, name TEXT);",
+        )
+        .unwrap();
+        let mut table = Table::new(CreateQuery::new(&ast.pop().unwrap()).unwrap());
+        let columns = ["id".to_string(), "name".to_string()];
+        table
+            .insert_row(
+                &columns,
+                &[Value::Integer(10), Value::Text("a".to_string())],
+            )
+            .unwrap();
+        table
+            .insert_row(&columns, &[Value::Integer(5), Value::Text("b".to_string())])
+            .unwrap();
+
+        // A smaller explicit rowid doesn't lower the next one assigned
+        let columns = ["name".to_string()];
+        table
+            .insert_row(&columns, &[Value::Text("c".to_string())])
+            .unwrap();
+        assert_eq!(table.rowids(), vec![5, 10, 11]);
+        assert_eq!(
+            table.get_value("name", 10).unwrap(),
+            Value::Text("a".to_string())
+        );
+        assert_eq!(table.get_value("id", 11).unwrap(), Value::Integer(11));
+        assert!(table.check_integrity().is_empty());
+    }
+
+    #[test]
+    fn bulk_load_test() {
+        let dialect = SQLiteDialect {};
+        let mut ast = Parser::parse_sql(
+            &dialect,
+            "CREATE TABLE users (id INTEGER PRIMARY KEY, email TEXT UNIQUE, \
+             handle TEXT COLLATE NOCASE UNIQUE, team TEXT);",
+        )
+        .unwrap();
+        let mut table = Table::new(CreateQuery::new(&ast.pop().unwrap()).unwrap());
+        let collations = CollationRegistry::new();
+        let columns = [
+            "email".to_string(),
+            "handle".to_string(),
+            "team".to_string(),
+        ];
+        let row = |email: &str, handle: &str, team: &str| {
+            [email, handle, team].map(|value| Value::Text(value.to_string()))
+        };
+        table.insert_row(&columns, &row("a@x", "a", "red")).unwrap();
+        table.set_bloom_filter("email", Some(0.01)).unwrap();
+
+        // Rows loaded are only indexed once the load ends
+        table.begin_bulk_load();
+        for (email, handle, team) in [
+            ("b@x", "b", "red"),
+            ("c@x", "c", "blue"),
+            ("d@x", "d", "red"),
+        ] {
+            table
+                .insert_row(&columns, &row(email, handle, team))
+                .unwrap();
+        }
+        let email = &table.columns[1].index;
+        assert_eq!(email.get(&Value::Text("c@x".to_string())), None);
+        table.end_bulk_load(&collations).unwrap();
+        let email = &table.columns[1];
+        assert_eq!(email.index.get(&Value::Text("c@x".to_string())), Some(3));
+        assert!(email.may_have(&Value::Text("d@x".to_string())));
+        let team = &table.columns[3].index;
+        assert_eq!(team.rowids(&Value::Text("red".to_string())), vec![1, 2, 4]);
+        assert!(table.check_integrity().is_empty());
+
+        // Two rows loaded with the same value leave the indexes as they were
+        for (first, second) in [
+            (row("e@x", "e", "red"), row("e@x", "f", "red")),
+            (row("e@x", "e", "red"), row("f@x", "E", "red")),
+        ] {
+            table.begin_bulk_load();
+            table.insert_row(&columns, &first).unwrap();
+            table.insert_row(&columns, &second).unwrap();
+            assert!(table.end_bulk_load(&collations).is_err());
+            let email = &table.columns[1].index;
+            assert_eq!(email.get(&Value::Text("e@x".to_string())), None);
+            let last_rowid = table.last_rowid;
+            assert!(table.delete_row(last_rowid - 1));
+            assert!(table.delete_row(last_rowid));
+            assert!(table.check_integrity().is_empty());
+        }
+        assert!(table.end_bulk_load(&collations).is_ok());
+    }
+
+    #[test]
+    fn dictionary_encoding_test() {
+        let dialect = SQLiteDialect {};
+        let mut ast = Parser::parse_sql(
+            &dialect,
+            "CREATE TABLE orders (id INTEGER PRIMARY KEY, status TEXT, total REAL);",
+        )
+        .unwrap();
+        let mut table = Table::new(CreateQuery::new(&ast.pop().unwrap()).unwrap());
+        let columns = ["status".to_string(), "total".to_string()];
+        for status in ["paid", "shipped", "paid"] {
+            table
+                .insert_row(
+                    &columns,
+                    &[Value::Text(status.to_string()), Value::Real(1.5)],
+                )
+                .unwrap();
+        }
+        assert!(table.set_dictionary_encoding("total", true).is_err());
+        assert!(table.set_dictionary_encoding("missing", true).is_err());
+        table.set_dictionary_encoding("status", true).unwrap();
+        assert_eq!(table.dictionary_size("status"), Some(2));
+        assert_eq!(
+            table.get_value("status", 3).unwrap(),
+            Value::Text("paid".to_string())
+        );
+
+        // Rows keep being encoded as they change, codes no row has are reused
+        table
+            .insert_row(
+                &columns,
+                &[Value::Text("paid".to_string()), Value::Real(2.0)],
+            )
+            .unwrap();
+        table.delete_row(2);
+        assert_eq!(table.dictionary_size("status"), Some(1));
+        let values = [Value::Text("refunded".to_string()), Value::Null];
+        table.write_row(1, &columns, &values).unwrap();
+        assert_eq!(table.dictionary_size("status"), Some(2));
+        assert_eq!(table.rowids(), vec![1, 3, 4]);
+        assert!(table.check_integrity().is_empty());
+
+        table.set_dictionary_encoding("status", false).unwrap();
+        assert_eq!(table.dictionary_size("status"), None);
+        assert_eq!(
+            table.get_value("status", 1).unwrap(),
+            Value::Text("refunded".to_string())
+        );
+    }
 }
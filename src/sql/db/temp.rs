@@ -0,0 +1,447 @@
+//! Temporary storage of the statements of a connection. Operators that need more room than
+//! they should keep in memory, sorts, hash tables spilling and materialized subqueries,
+//! write their data to temporary files created with the `TempStore` of the database.
+//!
+//! Where temporary files live is set with PRAGMAs, like in SQLite. `PRAGMA temp_store` is
+//! 0 (DEFAULT), 1 (FILE) or 2 (MEMORY). Databases only live in memory for now, so DEFAULT
+//! keeps temporary files in memory too. With FILE they are written to
+//! `PRAGMA temp_store_directory`, the temporary directory of the system when it isn't set.
+//!
+//! A temporary file is removed when it is dropped, and the files a statement left behind
+//! are removed when it ends. Files left in a directory by a process that crashed are
+//! removed the next time a connection starts using that directory.
+use std::collections::BTreeMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Mutex, MutexGuard};
+use std::time::{Duration, SystemTime};
+
+use crate::error::{Result, SQLRiteError};
+use crate::sql::value::Value;
+
+/// Prefix of the names of temporary files, followed by the id of the process creating them
+pub const TEMP_FILE_PREFIX: &str = "sqlrite-temp-";
+
+/// Age past which a temporary file is left behind by a process that crashed, on systems
+/// where the processes running can't be listed
+const ORPHAN_AGE: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Id of the next temporary file, unique in the process so that the connections using the
+/// same directory don't name their files alike
+static NEXT_FILE_ID: AtomicUsize = AtomicUsize::new(0);
+
+/// Where temporary files are kept, set with `PRAGMA temp_store`
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum TempStoreMode {
+    /// The default of the build: in memory, like the databases
+    #[default]
+    Default,
+    /// In files of the temporary directory
+    File,
+    /// In memory
+    Memory,
+}
+
+impl TempStoreMode {
+    /// Reads the value of `PRAGMA temp_store`: 0, 1 or 2, or the name of the mode.
+    pub fn from_pragma(value: &Value) -> Result<TempStoreMode> {
+        let mode = match value {
+            Value::Integer(0) => Some(TempStoreMode::Default),
+            Value::Integer(1) => Some(TempStoreMode::File),
+            Value::Integer(2) => Some(TempStoreMode::Memory),
+            Value::Text(text) => match text.to_lowercase().as_str() {
+                "0" | "default" => Some(TempStoreMode::Default),
+                "1" | "file" => Some(TempStoreMode::File),
+                "2" | "memory" => Some(TempStoreMode::Memory),
+                _ => None,
+            },
+            _ => None,
+        };
+        mode.ok_or_else(|| SQLRiteError::General(format!("unknown temp_store: {}", value)))
+    }
+
+    /// The value `PRAGMA temp_store` returns for the mode.
+    pub fn to_pragma(self) -> i64 {
+        match self {
+            TempStoreMode::Default => 0,
+            TempStoreMode::File => 1,
+            TempStoreMode::Memory => 2,
+        }
+    }
+}
+
+/// The temporary files of the statements of a connection, and where they are kept
+#[derive(Debug, Default)]
+pub struct TempStore {
+    mode: TempStoreMode,
+    /// Directory set with `PRAGMA temp_store_directory`
+    directory: Option<PathBuf>,
+    /// Path of each temporary file there is, `None` for the ones in memory, by file id
+    files: Mutex<BTreeMap<usize, Option<PathBuf>>>,
+    /// Bytes written to the temporary files there are
+    used: AtomicUsize,
+    /// Most bytes in temporary files at once since the database was opened
+    peak: AtomicUsize,
+    /// Number of temporary files created since the database was opened
+    created: AtomicUsize,
+}
+
+impl TempStore {
+    pub fn mode(&self) -> TempStoreMode {
+        self.mode
+    }
+
+    pub fn directory(&self) -> Option<&Path> {
+        self.directory.as_deref()
+    }
+
+    pub fn used(&self) -> usize {
+        self.used.load(Ordering::Relaxed)
+    }
+
+    pub fn peak(&self) -> usize {
+        self.peak.load(Ordering::Relaxed)
+    }
+
+    pub fn created(&self) -> usize {
+        self.created.load(Ordering::Relaxed)
+    }
+
+    /// Number of temporary files there are at the moment.
+    pub fn open_files(&self) -> usize {
+        self.lock_files().len()
+    }
+
+    /// Returns true if the temporary files are kept in memory.
+    pub fn in_memory(&self) -> bool {
+        self.mode != TempStoreMode::File
+    }
+
+    /// Directory temporary files are written to when they aren't kept in memory.
+    pub fn location(&self) -> PathBuf {
+        self.directory.clone().unwrap_or_else(std::env::temp_dir)
+    }
+
+    /// Sets where temporary files are kept, for the files created from now on. Files left
+    /// in the directory by processes that crashed are removed when it starts being used.
+    pub fn set_mode(&mut self, mode: TempStoreMode) -> Result<()> {
+        if mode == TempStoreMode::File {
+            self.start_using(&self.location())?;
+        }
+        self.mode = mode;
+        Ok(())
+    }
+
+    /// Sets the directory temporary files are written to, the temporary directory of the
+    /// system when `None`.
+    pub fn set_directory(&mut self, directory: Option<PathBuf>) -> Result<()> {
+        if let Some(directory) = &directory {
+            if !directory.is_dir() {
+                return Err(SQLRiteError::General(format!(
+                    "not a writable directory: {}",
+                    directory.display()
+                )));
+            }
+        }
+        let location = directory.clone().unwrap_or_else(std::env::temp_dir);
+        if !self.in_memory() {
+            self.start_using(&location)?;
+        }
+        self.directory = directory;
+        Ok(())
+    }
+
+    /// Creates an empty temporary file, removed when it is dropped.
+    pub fn create(&self) -> Result<TempFile<'_>> {
+        let id = NEXT_FILE_ID.fetch_add(1, Ordering::Relaxed);
+        let data = if self.in_memory() {
+            TempData::Memory(vec![])
+        } else {
+            let path =
+                self.location()
+                    .join(format!("{}{}-{}", TEMP_FILE_PREFIX, std::process::id(), id));
+            let file = OpenOptions::new()
+                .read(true)
+                .write(true)
+                .create_new(true)
+                .open(&path)
+                .map_err(|err| {
+                    SQLRiteError::General(format!(
+                        "cannot create temporary file {}: {}",
+                        path.display(),
+                        err
+                    ))
+                })?;
+            TempData::File { file, path }
+        };
+        let path = match &data {
+            TempData::File { path, .. } => Some(path.clone()),
+            TempData::Memory(_) => None,
+        };
+        self.lock_files().insert(id, path);
+        self.created.fetch_add(1, Ordering::Relaxed);
+        Ok(TempFile {
+            store: self,
+            id,
+            data,
+            bytes: 0,
+        })
+    }
+
+    /// Removes the temporary files the statement that just ended left behind. No file
+    /// outlives the statement creating it, so none is left in use.
+    pub fn end_statement(&self) {
+        let files = std::mem::take(&mut *self.lock_files());
+        for path in files.into_values().flatten() {
+            let _ = fs::remove_file(path);
+        }
+        self.used.store(0, Ordering::Relaxed);
+    }
+
+    /// Removes the temporary files left in `directory` by processes that crashed, and
+    /// checks that files can be created there. Returns the number of files removed.
+    fn start_using(&self, directory: &Path) -> Result<usize> {
+        if cfg!(target_arch = "wasm32") {
+            return Err(SQLRiteError::NotImplemented(
+                "temporary files are only kept in memory on this platform".to_string(),
+            ));
+        }
+        let entries = fs::read_dir(directory).map_err(|err| {
+            SQLRiteError::General(format!(
+                "cannot use {} for temporary files: {}",
+                directory.display(),
+                err
+            ))
+        })?;
+        let mut removed = 0;
+        for entry in entries.flatten() {
+            let name = entry.file_name().to_string_lossy().to_string();
+            let pid = match name
+                .strip_prefix(TEMP_FILE_PREFIX)
+                .and_then(|rest| rest.split('-').next())
+                .and_then(|pid| pid.parse::<u32>().ok())
+            {
+                Some(pid) => pid,
+                None => continue,
+            };
+            if is_orphan(&entry.path(), pid) && fs::remove_file(entry.path()).is_ok() {
+                removed += 1;
+            }
+        }
+        Ok(removed)
+    }
+
+    fn lock_files(&self) -> MutexGuard<'_, BTreeMap<usize, Option<PathBuf>>> {
+        self.files.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    fn grow(&self, bytes: usize) {
+        let used = self.used.fetch_add(bytes, Ordering::Relaxed) + bytes;
+        self.peak.fetch_max(used, Ordering::Relaxed);
+    }
+}
+
+/// Stores are equal if they keep temporary files in the same place, the files there are
+/// depend on the statements running.
+impl PartialEq for TempStore {
+    fn eq(&self, other: &Self) -> bool {
+        self.mode == other.mode && self.directory == other.directory
+    }
+}
+
+/// Returns true if the temporary file at `path`, created by the process `pid`, was left
+/// behind: that process is no longer running. Where the processes running can't be
+/// listed, files older than `ORPHAN_AGE` are.
+fn is_orphan(path: &Path, pid: u32) -> bool {
+    let proc = Path::new("/proc");
+    if proc.is_dir() {
+        return pid != std::process::id() && !proc.join(pid.to_string()).exists();
+    }
+    fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+        .and_then(|modified| SystemTime::now().duration_since(modified).ok())
+        .is_some_and(|age| age > ORPHAN_AGE)
+}
+
+#[derive(Debug)]
+enum TempData {
+    Memory(Vec<u8>),
+    File { file: File, path: PathBuf },
+}
+
+/// A temporary file of a statement, data is appended to it and read back from its start
+#[derive(Debug)]
+pub struct TempFile<'a> {
+    store: &'a TempStore,
+    id: usize,
+    data: TempData,
+    bytes: usize,
+}
+
+impl TempFile<'_> {
+    /// Bytes written to the file.
+    pub fn len(&self) -> usize {
+        self.bytes
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bytes == 0
+    }
+
+    /// Appends `bytes` to the file.
+    pub fn write(&mut self, bytes: &[u8]) -> Result<()> {
+        match &mut self.data {
+            TempData::Memory(data) => data.extend_from_slice(bytes),
+            TempData::File { file, path } => file.write_all(bytes).map_err(|err| {
+                SQLRiteError::General(format!(
+                    "cannot write temporary file {}: {}",
+                    path.display(),
+                    err
+                ))
+            })?,
+        }
+        self.bytes += bytes.len();
+        self.store.grow(bytes.len());
+        Ok(())
+    }
+
+    /// Reads everything written to the file.
+    pub fn read(&mut self) -> Result<Vec<u8>> {
+        match &mut self.data {
+            TempData::Memory(data) => Ok(data.clone()),
+            TempData::File { file, path } => {
+                let mut data = Vec::with_capacity(self.bytes);
+                file.seek(SeekFrom::Start(0))
+                    .and_then(|_| file.read_to_end(&mut data))
+                    .and_then(|_| file.seek(SeekFrom::End(0)))
+                    .map_err(|err| {
+                        SQLRiteError::General(format!(
+                            "cannot read temporary file {}: {}",
+                            path.display(),
+                            err
+                        ))
+                    })?;
+                Ok(data)
+            }
+        }
+    }
+
+    /// Appends `rows` to the file, one JSON line per row.
+    pub fn write_rows(&mut self, rows: &[Vec<Value>]) -> Result<()> {
+        let mut lines = String::new();
+        for row in rows {
+            let line = serde_json::to_string(row)
+                .map_err(|err| SQLRiteError::Internal(format!("cannot spill a row: {}", err)))?;
+            lines.push_str(&line);
+            lines.push('\n');
+        }
+        self.write(lines.as_bytes())
+    }
+
+    /// Reads the rows written to the file with `write_rows`.
+    pub fn read_rows(&mut self) -> Result<Vec<Vec<Value>>> {
+        let data = self.read()?;
+        String::from_utf8_lossy(&data)
+            .lines()
+            .map(|line| {
+                serde_json::from_str(line).map_err(|err| {
+                    SQLRiteError::Internal(format!("cannot read a spilled row: {}", err))
+                })
+            })
+            .collect()
+    }
+}
+
+impl Drop for TempFile<'_> {
+    fn drop(&mut self) {
+        self.store.used.fetch_sub(self.bytes, Ordering::Relaxed);
+        self.store.lock_files().remove(&self.id);
+        if let TempData::File { path, .. } = &self.data {
+            let _ = fs::remove_file(path);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn temp_store_test() {
+        let rows = vec![
+            vec![Value::Integer(1), Value::Text("a\nb".to_string())],
+            vec![Value::Null, Value::Real(1.5)],
+        ];
+
+        // In memory by default
+        let store = TempStore::default();
+        assert!(store.in_memory());
+        {
+            let mut file = store.create().unwrap();
+            file.write_rows(&rows).unwrap();
+            assert_eq!(file.read_rows().unwrap(), rows);
+            assert_eq!(store.used(), file.len());
+            assert_eq!(store.open_files(), 1);
+        }
+        assert_eq!(store.used(), 0);
+        assert!(store.peak() > 0);
+        assert_eq!(store.open_files(), 0);
+
+        // In files of the directory, removed when dropped or when the statement ends
+        let directory = std::env::temp_dir().join("sqlrite_temp_store_test");
+        let _ = fs::remove_dir_all(&directory);
+        fs::create_dir(&directory).unwrap();
+        let files = || fs::read_dir(&directory).unwrap().count();
+        let mut store = TempStore::default();
+        assert!(store
+            .set_directory(Some(directory.join("missing")))
+            .is_err());
+        store.set_directory(Some(directory.clone())).unwrap();
+        store.set_mode(TempStoreMode::File).unwrap();
+        {
+            let mut file = store.create().unwrap();
+            file.write(b"spilled").unwrap();
+            file.write_rows(&rows).unwrap();
+            let mut second = store.create().unwrap();
+            second.write_rows(&rows).unwrap();
+            assert_eq!(second.read_rows().unwrap(), rows);
+            assert_eq!(files(), 2);
+            assert_eq!(store.used(), file.len() + second.len());
+            std::mem::forget(second);
+            assert!(file.read().unwrap().starts_with(b"spilled"));
+        }
+        assert_eq!(files(), 1);
+        store.end_statement();
+        assert_eq!(files(), 0);
+        assert_eq!(store.used(), 0);
+        assert_eq!(store.created(), 2);
+
+        // Files of processes that are gone are removed when the directory starts being used
+        let orphan = directory.join(format!("{}{}-0", TEMP_FILE_PREFIX, u32::MAX));
+        let ours = directory.join(format!("{}{}-0", TEMP_FILE_PREFIX, std::process::id()));
+        let other = directory.join("notes.txt");
+        for path in [&orphan, &ours, &other] {
+            fs::write(path, "").unwrap();
+        }
+        let removed = store.start_using(&directory).unwrap();
+        if Path::new("/proc").is_dir() {
+            assert_eq!(removed, 1);
+            assert!(!orphan.exists());
+        }
+        assert!(ours.exists() && other.exists());
+        fs::remove_dir_all(&directory).unwrap();
+
+        assert_eq!(
+            TempStoreMode::from_pragma(&Value::Text("MEMORY".to_string())).unwrap(),
+            TempStoreMode::Memory
+        );
+        assert_eq!(
+            TempStoreMode::from_pragma(&Value::Integer(1)).unwrap(),
+            TempStoreMode::File
+        );
+        assert!(TempStoreMode::from_pragma(&Value::Integer(3)).is_err());
+    }
+}
@@ -0,0 +1,188 @@
+//! The transaction a connection has open, begun with `BEGIN` or with the first `SAVEPOINT`,
+//! and its savepoints. Each savepoint keeps a snapshot of the database as it was when it
+//! was set, like the server keeps for the transactions of its sessions, which `ROLLBACK TO`
+//! restores and `ROLLBACK` restores from the first one. `RELEASE` forgets the savepoints
+//! from the one named on, committing the transaction if it began with that savepoint.
+//!
+//! While a transaction is open the database isn't saved to its file, by autosave or
+//! otherwise, so that the file only ever holds the changes of the transactions committed.
+use crate::error::{Result, SQLRiteError};
+use crate::sql::db::database::Database;
+
+/// A savepoint of the open transaction
+#[derive(Debug)]
+struct Savepoint {
+    /// Name of the savepoint, `None` for the start of a transaction begun with `BEGIN`
+    name: Option<String>,
+    /// The database as it was when the savepoint was set
+    snapshot: Database,
+}
+
+/// The transaction open on a connection, if any, as the stack of its savepoints
+#[derive(Debug, Default)]
+pub struct Transaction {
+    savepoints: Vec<Savepoint>,
+}
+
+impl Transaction {
+    /// Whether a transaction is open.
+    pub fn is_open(&self) -> bool {
+        !self.savepoints.is_empty()
+    }
+
+    /// Names of the savepoints set, from the first one.
+    pub fn savepoints(&self) -> Vec<&str> {
+        self.savepoints
+            .iter()
+            .filter_map(|savepoint| savepoint.name.as_deref())
+            .collect()
+    }
+
+    /// Sets the savepoint `name`, or the start of the transaction if `None`, at the
+    /// database `snapshot`.
+    pub fn push(&mut self, name: Option<String>, snapshot: Database) {
+        self.savepoints.push(Savepoint { name, snapshot });
+    }
+
+    /// Ends the transaction, returning the database as it was when it began.
+    pub fn end(&mut self) -> Option<Database> {
+        let mut savepoints = std::mem::take(&mut self.savepoints);
+        savepoints.truncate(1);
+        savepoints.pop().map(|savepoint| savepoint.snapshot)
+    }
+
+    /// A copy of the database as it was when the transaction began, `None` if none is open.
+    pub fn committed(&self) -> Option<Result<Database>> {
+        self.savepoints
+            .first()
+            .map(|savepoint| savepoint.snapshot.snapshot())
+    }
+
+    /// Forgets the savepoint `name` and the savepoints set after it, returning whether
+    /// that ended the transaction.
+    pub fn release(&mut self, name: &str) -> Result<bool> {
+        let index = self.find(name)?;
+        self.savepoints.truncate(index);
+        Ok(!self.is_open())
+    }
+
+    /// Forgets the savepoints set after the savepoint `name`, returning a copy of the
+    /// database as it was when it was set. The savepoint stays set.
+    pub fn rollback_to(&mut self, name: &str) -> Result<Database> {
+        let index = self.find(name)?;
+        self.savepoints.truncate(index + 1);
+        self.savepoints[index].snapshot.snapshot()
+    }
+
+    /// Position of the last savepoint named `name`, ignoring case like SQLite.
+    fn find(&self, name: &str) -> Result<usize> {
+        self.savepoints
+            .iter()
+            .rposition(|savepoint| {
+                savepoint
+                    .name
+                    .as_deref()
+                    .is_some_and(|saved| saved.eq_ignore_ascii_case(name))
+            })
+            .ok_or_else(|| SQLRiteError::General(format!("no such savepoint: {}", name)))
+    }
+}
+
+/// Transactions are always equal, they belong to the connection, not to the database.
+impl PartialEq for Transaction {
+    fn eq(&self, _: &Self) -> bool {
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sql::db::autosave::AutosaveInterval;
+    use crate::sql::process_command;
+    use crate::sql::value::Value;
+
+    #[test]
+    fn transaction_test() {
+        let mut db = Database::new("tempdb".to_string());
+        let run = |db: &mut Database, sql: &str| process_command(sql, db).map(|r| r.message);
+        run(
+            &mut db,
+            "CREATE TABLE users (id INTEGER PRIMARY KEY, name TEXT);",
+        )
+        .unwrap();
+        run(&mut db, "INSERT INTO users (name) VALUES ('josh');").unwrap();
+
+        // ROLLBACK undoes the whole transaction, COMMIT keeps it
+        assert_eq!(run(&mut db, "BEGIN;").unwrap(), "BEGIN Statement executed.");
+        assert!(run(&mut db, "BEGIN;").is_err());
+        run(&mut db, "INSERT INTO users (name) VALUES ('ana');").unwrap();
+        run(&mut db, "CREATE TABLE logs (id INTEGER PRIMARY KEY);").unwrap();
+        run(&mut db, "ROLLBACK;").unwrap();
+        assert_eq!(db.tables["users"].rowids(), vec![1]);
+        assert!(!db.tables.contains_key("logs"));
+        assert!(run(&mut db, "ROLLBACK;").is_err());
+        assert!(run(&mut db, "COMMIT;").is_err());
+        run(&mut db, "BEGIN TRANSACTION;").unwrap();
+        run(&mut db, "INSERT INTO users (name) VALUES ('ana');").unwrap();
+        run(&mut db, "END;").unwrap();
+        assert!(!db.transaction.is_open());
+        assert_eq!(db.tables["users"].rowids(), vec![1, 2]);
+
+        // ROLLBACK TO undoes the work done since the savepoint, which stays set
+        run(&mut db, "BEGIN;").unwrap();
+        run(&mut db, "DELETE FROM users WHERE id = 1;").unwrap();
+        run(&mut db, "SAVEPOINT a;").unwrap();
+        run(&mut db, "DELETE FROM users WHERE id = 2;").unwrap();
+        run(&mut db, "SAVEPOINT b;").unwrap();
+        run(&mut db, "INSERT INTO users (name) VALUES ('maria');").unwrap();
+        assert_eq!(db.transaction.savepoints(), vec!["a", "b"]);
+        run(&mut db, "ROLLBACK TO a;").unwrap();
+        assert_eq!(db.transaction.savepoints(), vec!["a"]);
+        assert_eq!(db.tables["users"].rowids(), vec![2]);
+        run(&mut db, "DELETE FROM users WHERE id = 2;").unwrap();
+        run(&mut db, "ROLLBACK TRANSACTION TO SAVEPOINT A;").unwrap();
+        assert_eq!(db.tables["users"].rowids(), vec![2]);
+        assert!(run(&mut db, "ROLLBACK TO b;").is_err());
+
+        // RELEASE keeps the work done since the savepoint, within the transaction
+        run(&mut db, "INSERT INTO users (name) VALUES ('maria');").unwrap();
+        run(&mut db, "RELEASE a;").unwrap();
+        assert!(db.transaction.is_open());
+        assert_eq!(db.tables["users"].rowids(), vec![2, 3]);
+        run(&mut db, "ROLLBACK;").unwrap();
+        assert_eq!(db.tables["users"].rowids(), vec![1, 2]);
+        assert_eq!(
+            db.tables["users"].get_value("name", 1).unwrap(),
+            Value::Text("josh".to_string())
+        );
+
+        // A transaction begun by a savepoint is committed by releasing it
+        run(&mut db, "SAVEPOINT outer_work;").unwrap();
+        run(&mut db, "INSERT INTO users (name) VALUES ('maria');").unwrap();
+        assert!(run(&mut db, "ATTACH DATABASE ':memory:' AS aux;").is_err());
+        assert!(db.save().is_err());
+        run(&mut db, "RELEASE SAVEPOINT outer_work;").unwrap();
+        assert!(!db.transaction.is_open());
+        assert_eq!(db.tables["users"].rowids(), vec![1, 2, 3]);
+        assert!(run(&mut db, "RELEASE outer_work;").is_err());
+
+        // Autosave waits for the transaction to be committed
+        let path = std::env::temp_dir().join(format!("sqlrite-txn-{}.db", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        db.open_file(&path).unwrap();
+        db.autosave.set_interval(AutosaveInterval::Statements(1));
+        run(&mut db, "CREATE TABLE logs (id INTEGER PRIMARY KEY);").unwrap();
+        run(&mut db, "BEGIN;").unwrap();
+        run(&mut db, "INSERT INTO logs (id) VALUES (1);").unwrap();
+        assert!(Database::read_file(&path).unwrap().tables["logs"]
+            .rowids()
+            .is_empty());
+        run(&mut db, "COMMIT;").unwrap();
+        assert_eq!(
+            Database::read_file(&path).unwrap().tables["logs"].rowids(),
+            vec![1]
+        );
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,50 @@
+use serde::{Deserialize, Serialize};
+
+/// When a trigger runs relative to the change of the row that fired it
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone, Copy)]
+pub enum TriggerTiming {
+    Before,
+    After,
+}
+
+/// The kind of statement firing a trigger
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub enum TriggerEvent {
+    Insert,
+    /// UPDATE, optionally only of some columns with `UPDATE OF col, ...`
+    Update(Vec<String>),
+    Delete,
+}
+
+/// A trigger created with `CREATE TRIGGER`, the statements it runs for each row changed
+/// by the statements of its kind on its table. The row is available to the WHEN clause and
+/// to the statements as `NEW` for INSERT and UPDATE and `OLD` for UPDATE and DELETE.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub struct Trigger {
+    pub name: String,
+    pub table_name: String,
+    pub timing: TriggerTiming,
+    pub event: TriggerEvent,
+    /// SQL text of the WHEN condition, if any
+    pub when: Option<String>,
+    /// SQL text of each statement between BEGIN and END
+    pub body: Vec<String>,
+}
+
+impl Trigger {
+    /// Returns true if the trigger fires at `timing` for `event`. An UPDATE trigger
+    /// restricted to some columns only fires when one of them is in `event`.
+    pub fn fires_on(&self, timing: TriggerTiming, event: &TriggerEvent) -> bool {
+        if self.timing != timing {
+            return false;
+        }
+        match (&self.event, event) {
+            (TriggerEvent::Insert, TriggerEvent::Insert)
+            | (TriggerEvent::Delete, TriggerEvent::Delete) => true,
+            (TriggerEvent::Update(columns), TriggerEvent::Update(changed)) => {
+                columns.is_empty() || columns.iter().any(|col| changed.contains(col))
+            }
+            _ => false,
+        }
+    }
+}
@@ -0,0 +1,411 @@
+//! Aggregate functions, computing a value from the rows of a query rather than from one
+//! row: `count`, `sum`, `total`, `avg`, and `min` and `max` of one argument, `min` and
+//! `max` of several being the scalar functions. A query calling one returns a single row,
+//! and can't have other columns of the rows in its SELECT list until there is GROUP BY,
+//! which would pick their values from an arbitrary row. Like in
+//! SQL, NULLs are left out of every aggregate but `count(*)`: `count(x)` counts the rows
+//! where `x` isn't NULL, and `sum`, `avg`, `min` and `max` of no values but NULLs are NULL.
+use std::cmp::Ordering;
+
+use sqlparser::ast::{Expr, Function, FunctionArg, FunctionArgExpr, Ident};
+
+use crate::error::{Result, SQLRiteError};
+use crate::sql::db::database::Database;
+use crate::sql::executor::expr::{eval_expr, RowContext};
+use crate::sql::executor::source::SourceColumn;
+use crate::sql::value::Value;
+
+/// Returns true if the call `function` is that of an aggregate function.
+pub fn is_aggregate(function: &Function) -> bool {
+    let name = function.name.to_string().to_lowercase();
+    match name.as_str() {
+        "count" | "sum" | "total" | "avg" => true,
+        "min" | "max" => function.args.len() == 1,
+        _ => false,
+    }
+}
+
+/// The aggregate function calls of a query, accumulating their values over its rows
+#[derive(Debug)]
+pub struct Aggregates {
+    calls: Vec<(Function, Accumulator)>,
+}
+
+impl Aggregates {
+    /// Collects the aggregate calls of `exprs`, replacing each of them by a reference to
+    /// the column its value is given as by `row`. `None` if there are none.
+    pub fn extract<'a>(exprs: impl Iterator<Item = &'a mut Expr>) -> Result<Option<Aggregates>> {
+        let mut aggregates = Aggregates { calls: vec![] };
+        for expr in exprs {
+            aggregates.replace_calls(expr)?;
+        }
+        Ok(match aggregates.calls.is_empty() {
+            true => None,
+            false => Some(aggregates),
+        })
+    }
+
+    fn replace_calls(&mut self, expr: &mut Expr) -> Result<()> {
+        match expr {
+            Expr::Function(function) if is_aggregate(function) => {
+                if function.args.iter().any(|arg| match arg {
+                    FunctionArg::Unnamed(FunctionArgExpr::Expr(expr)) => contains_aggregate(expr),
+                    _ => false,
+                }) {
+                    return Err(misuse(function));
+                }
+                let name = function.to_string();
+                if !self.calls.iter().any(|(call, _)| call.to_string() == name) {
+                    let accumulator = Accumulator::new(function)?;
+                    self.calls.push((function.clone(), accumulator));
+                }
+                *expr = Expr::Identifier(Ident::new(name));
+            }
+            Expr::Function(function) => {
+                for arg in &mut function.args {
+                    if let FunctionArg::Unnamed(FunctionArgExpr::Expr(expr)) = arg {
+                        self.replace_calls(expr)?;
+                    }
+                }
+            }
+            Expr::BinaryOp { left, right, .. } => {
+                self.replace_calls(left)?;
+                self.replace_calls(right)?;
+            }
+            Expr::UnaryOp { expr, .. }
+            | Expr::Nested(expr)
+            | Expr::Collate { expr, .. }
+            | Expr::IsNull(expr)
+            | Expr::IsNotNull(expr)
+            | Expr::Cast { expr, .. } => self.replace_calls(expr)?,
+            Expr::Trim { expr, trim_where } => {
+                self.replace_calls(expr)?;
+                if let Some((_, chars)) = trim_where {
+                    self.replace_calls(chars)?;
+                }
+            }
+            Expr::Substring {
+                expr,
+                substring_from,
+                substring_for,
+            } => {
+                self.replace_calls(expr)?;
+                for expr in substring_from.iter_mut().chain(substring_for.iter_mut()) {
+                    self.replace_calls(expr)?;
+                }
+            }
+            Expr::Position { expr, r#in } => {
+                self.replace_calls(expr)?;
+                self.replace_calls(r#in)?;
+            }
+            Expr::Between {
+                expr, low, high, ..
+            } => {
+                self.replace_calls(expr)?;
+                self.replace_calls(low)?;
+                self.replace_calls(high)?;
+            }
+            Expr::InList { expr, list, .. } => {
+                self.replace_calls(expr)?;
+                for item in list {
+                    self.replace_calls(item)?;
+                }
+            }
+            Expr::Tuple(exprs) => {
+                for expr in exprs {
+                    self.replace_calls(expr)?;
+                }
+            }
+            Expr::Case {
+                operand,
+                conditions,
+                results,
+                else_result,
+            } => {
+                for expr in operand.iter_mut().chain(else_result.iter_mut()) {
+                    self.replace_calls(expr)?;
+                }
+                for expr in conditions.iter_mut().chain(results.iter_mut()) {
+                    self.replace_calls(expr)?;
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Adds the values of the arguments of each call for `row`.
+    pub fn accumulate(&mut self, db: &Database, row: &RowContext) -> Result<()> {
+        for (function, accumulator) in &mut self.calls {
+            let value = match function.args.first() {
+                Some(FunctionArg::Unnamed(FunctionArgExpr::Expr(expr))) => {
+                    Some(eval_expr(expr, db, row)?)
+                }
+                _ => None,
+            };
+            accumulator.add(value, function.distinct, db)?;
+        }
+        Ok(())
+    }
+
+    /// The columns the calls were replaced by, and their values.
+    pub fn finish(self) -> Result<(Vec<SourceColumn>, Vec<Value>)> {
+        let mut columns = vec![];
+        let mut values = vec![];
+        for (function, accumulator) in self.calls {
+            columns.push(SourceColumn {
+                name: function.to_string(),
+                datatype: None,
+                collation: None,
+                hidden: true,
+                table: None,
+            });
+            values.push(accumulator.value()?);
+        }
+        Ok((columns, values))
+    }
+}
+
+/// Returns true if `expr` calls an aggregate function.
+pub fn contains_aggregate(expr: &Expr) -> bool {
+    let mut expr = expr.clone();
+    let mut aggregates = Aggregates { calls: vec![] };
+    aggregates.replace_calls(&mut expr).is_err() || !aggregates.calls.is_empty()
+}
+
+/// The first column `expr` refers to outside of the arguments of aggregate calls, if
+/// any. Subqueries are left out, their columns are their own.
+pub fn bare_column(expr: &Expr) -> Option<&Expr> {
+    match expr {
+        Expr::Identifier(_) | Expr::CompoundIdentifier(_) => Some(expr),
+        Expr::Function(function) if is_aggregate(function) => None,
+        Expr::Function(function) => function.args.iter().find_map(|arg| match arg {
+            FunctionArg::Unnamed(FunctionArgExpr::Expr(expr)) => bare_column(expr),
+            _ => None,
+        }),
+        Expr::BinaryOp { left, right, .. } => bare_column(left).or_else(|| bare_column(right)),
+        Expr::UnaryOp { expr, .. }
+        | Expr::Nested(expr)
+        | Expr::Collate { expr, .. }
+        | Expr::IsNull(expr)
+        | Expr::IsNotNull(expr)
+        | Expr::Cast { expr, .. }
+        | Expr::InSubquery { expr, .. } => bare_column(expr),
+        Expr::Trim { expr, trim_where } => bare_column(expr).or_else(|| {
+            trim_where
+                .as_ref()
+                .and_then(|(_, chars)| bare_column(chars))
+        }),
+        Expr::Substring {
+            expr,
+            substring_from,
+            substring_for,
+        } => bare_column(expr)
+            .or_else(|| substring_from.as_deref().and_then(bare_column))
+            .or_else(|| substring_for.as_deref().and_then(bare_column)),
+        Expr::Position { expr, r#in } => bare_column(expr).or_else(|| bare_column(r#in)),
+        Expr::Between {
+            expr, low, high, ..
+        } => bare_column(expr)
+            .or_else(|| bare_column(low))
+            .or_else(|| bare_column(high)),
+        Expr::InList { expr, list, .. } => {
+            bare_column(expr).or_else(|| list.iter().find_map(bare_column))
+        }
+        Expr::Tuple(exprs) => exprs.iter().find_map(bare_column),
+        Expr::Case {
+            operand,
+            conditions,
+            results,
+            else_result,
+        } => operand
+            .iter()
+            .chain(else_result.iter())
+            .map(|expr| expr.as_ref())
+            .chain(conditions.iter().chain(results.iter()))
+            .find_map(bare_column),
+        _ => None,
+    }
+}
+
+/// The error of an aggregate call where none can be, like in a WHERE clause or in the
+/// argument of another aggregate.
+pub fn misuse(function: &Function) -> SQLRiteError {
+    SQLRiteError::General(format!("misuse of aggregate function {}()", function.name))
+}
+
+/// The state of an aggregate call over the rows read so far
+#[derive(Debug)]
+enum Accumulator {
+    /// `count(*)` counts rows, `count(x)` the values that aren't NULL
+    Count {
+        rows: bool,
+        count: i64,
+        seen: Vec<Value>,
+    },
+    /// `sum`, `total` and `avg` add the values that aren't NULL, read as numbers
+    Sum {
+        kind: SumKind,
+        count: i64,
+        /// The sum while every value is an integer, `None` once it overflowed
+        integer: Option<i64>,
+        integers: bool,
+        real: f64,
+        seen: Vec<Value>,
+    },
+    /// `min` and `max` keep the least or the greatest value that isn't NULL
+    Extremum {
+        keep: Ordering,
+        value: Option<Value>,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum SumKind {
+    Sum,
+    Total,
+    Avg,
+}
+
+impl Accumulator {
+    fn new(function: &Function) -> Result<Accumulator> {
+        let name = function.name.to_string().to_lowercase();
+        let star = match function.args.as_slice() {
+            [] => true,
+            [FunctionArg::Unnamed(FunctionArgExpr::Wildcard)] => true,
+            [FunctionArg::Unnamed(FunctionArgExpr::Expr(_))] => false,
+            _ => {
+                return Err(SQLRiteError::General(format!(
+                    "wrong number of arguments to function {}()",
+                    function.name
+                )))
+            }
+        };
+        if star && (name != "count" || function.distinct) {
+            return Err(SQLRiteError::General(format!(
+                "wrong number of arguments to function {}()",
+                function.name
+            )));
+        }
+        let sum = |kind| Accumulator::Sum {
+            kind,
+            count: 0,
+            integer: Some(0),
+            integers: true,
+            real: 0.0,
+            seen: vec![],
+        };
+        Ok(match name.as_str() {
+            "count" => Accumulator::Count {
+                rows: star,
+                count: 0,
+                seen: vec![],
+            },
+            "sum" => sum(SumKind::Sum),
+            "total" => sum(SumKind::Total),
+            "avg" => sum(SumKind::Avg),
+            "min" => Accumulator::Extremum {
+                keep: Ordering::Less,
+                value: None,
+            },
+            _ => Accumulator::Extremum {
+                keep: Ordering::Greater,
+                value: None,
+            },
+        })
+    }
+
+    /// Adds the `value` of the argument for a row, `None` for `count(*)`. With `distinct`,
+    /// a value equal to one added before is left out.
+    fn add(&mut self, value: Option<Value>, distinct: bool, db: &Database) -> Result<()> {
+        let binary = db.collations.get("binary")?;
+        let repeated = |seen: &mut Vec<Value>, value: &Value| {
+            if !distinct {
+                return false;
+            }
+            if seen
+                .iter()
+                .any(|other| other.compare(value, binary.as_ref()) == Ordering::Equal)
+            {
+                return true;
+            }
+            seen.push(value.clone());
+            false
+        };
+        match (self, value) {
+            (
+                Accumulator::Count {
+                    rows: true, count, ..
+                },
+                _,
+            ) => *count += 1,
+            (_, None) | (_, Some(Value::Null)) => {}
+            (Accumulator::Count { count, seen, .. }, Some(value)) => {
+                if !repeated(seen, &value) {
+                    *count += 1;
+                }
+            }
+            (
+                Accumulator::Sum {
+                    count,
+                    integer,
+                    integers,
+                    real,
+                    seen,
+                    ..
+                },
+                Some(value),
+            ) => {
+                if repeated(seen, &value) {
+                    return Ok(());
+                }
+                *count += 1;
+                match value.to_numeric() {
+                    Some(Value::Integer(n)) => {
+                        *integer = integer.and_then(|sum| sum.checked_add(n));
+                        *real += n as f64;
+                    }
+                    number => {
+                        *integers = false;
+                        *real += number.and_then(|number| number.to_real()).unwrap_or(0.0);
+                    }
+                }
+            }
+            (Accumulator::Extremum { keep, value: kept }, Some(value)) => {
+                let replace = match kept {
+                    Some(kept) => value.compare(kept, binary.as_ref()) == *keep,
+                    None => true,
+                };
+                if replace {
+                    *kept = Some(value);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// The value of the aggregate over the rows added.
+    fn value(self) -> Result<Value> {
+        Ok(match self {
+            Accumulator::Count { count, .. } => Value::Integer(count),
+            Accumulator::Sum {
+                kind,
+                count,
+                integer,
+                integers,
+                real,
+                ..
+            } => match kind {
+                SumKind::Total => Value::Real(real),
+                _ if count == 0 => Value::Null,
+                SumKind::Avg => Value::Real(real / count as f64),
+                SumKind::Sum if integers => match integer {
+                    Some(sum) => Value::Integer(sum),
+                    None => return Err(SQLRiteError::General("integer overflow".to_string())),
+                },
+                SumKind::Sum => Value::Real(real),
+            },
+            Accumulator::Extremum { value, .. } => value.unwrap_or(Value::Null),
+        })
+    }
+}
@@ -0,0 +1,124 @@
+//! Storage of the intermediate tuples of a statement. Evaluating a query allocates one
+//! tuple per row read, along with its ORDER BY keys, and most of them are thrown away or
+//! moved around while sorting. A `TupleArena` keeps every tuple of a statement back to
+//! back in one buffer that grows by doubling, so a scan of a million rows makes a few
+//! dozen allocations instead of millions, and sorting permutes indexes instead of moving
+//! tuples. The arena is owned by the statement and released all at once when it finishes.
+//!
+//! The values themselves are still `Value`s: TEXT values keep owning their string.
+use crate::error::Result;
+use crate::sql::db::memory::value_size;
+use crate::sql::value::Value;
+
+/// Tuples of a fixed number of values, stored contiguously
+#[derive(Debug, Default)]
+pub struct TupleArena {
+    /// The values of every tuple, one tuple after the other
+    values: Vec<Value>,
+    /// Number of values in each tuple
+    width: usize,
+    /// Number of tuples
+    len: usize,
+    /// Bytes the values of the tuples own outside of the buffer, like their strings
+    owned: usize,
+}
+
+impl TupleArena {
+    /// An empty arena for tuples of `width` values, with room for `capacity` of them.
+    pub fn with_capacity(width: usize, capacity: usize) -> Self {
+        TupleArena {
+            values: Vec::with_capacity(width * capacity),
+            width,
+            len: 0,
+            owned: 0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Estimated bytes of memory taken by the arena.
+    pub fn bytes(&self) -> usize {
+        self.values.capacity() * std::mem::size_of::<Value>() + self.owned
+    }
+
+    /// Appends the tuple made of `values`, which must have `width` of them, and returns
+    /// its index. If a value fails to evaluate, nothing is appended and its error returned.
+    pub fn push(&mut self, values: impl IntoIterator<Item = Result<Value>>) -> Result<usize> {
+        let start = self.values.len();
+        for value in values {
+            match value {
+                Ok(value) => self.values.push(value),
+                Err(err) => {
+                    self.values.truncate(start);
+                    return Err(err);
+                }
+            }
+        }
+        debug_assert_eq!(self.values.len() - start, self.width);
+        self.owned += self.values[start..]
+            .iter()
+            .map(|value| value_size(value) - std::mem::size_of::<Value>())
+            .sum::<usize>();
+        self.len += 1;
+        Ok(self.len - 1)
+    }
+
+    /// The values of the tuple `index`.
+    pub fn get(&self, index: usize) -> &[Value] {
+        &self.values[index * self.width..(index + 1) * self.width]
+    }
+
+    /// Moves the first `columns` values of the tuples out of the arena, in the order of
+    /// `order`, releasing the arena.
+    pub fn into_rows(mut self, order: &[usize], columns: usize) -> Vec<Vec<Value>> {
+        order
+            .iter()
+            .map(|index| {
+                let start = index * self.width;
+                self.values[start..start + columns]
+                    .iter_mut()
+                    .map(|value| std::mem::replace(value, Value::Null))
+                    .collect()
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::SQLRiteError;
+
+    #[test]
+    fn tuple_arena_test() {
+        let mut arena = TupleArena::with_capacity(2, 1);
+        assert!(arena.is_empty());
+        for i in 0..3 {
+            let index = arena
+                .push(vec![Ok(Value::Integer(i)), Ok(Value::Text(i.to_string()))])
+                .unwrap();
+            assert_eq!(index, i as usize);
+        }
+        let failed = arena.push(vec![
+            Ok(Value::Integer(3)),
+            Err(SQLRiteError::General("no such column".to_string())),
+        ]);
+        assert!(failed.is_err());
+        assert_eq!(arena.len(), 3);
+        assert!(arena.bytes() >= 6 * std::mem::size_of::<Value>() + 3);
+        assert_eq!(
+            arena.get(1),
+            &[Value::Integer(1), Value::Text("1".to_string())]
+        );
+        assert_eq!(
+            arena.into_rows(&[2, 0], 1),
+            vec![vec![Value::Integer(2)], vec![Value::Integer(0)]]
+        );
+    }
+}
@@ -0,0 +1,289 @@
+//! Compound SELECTs: `SELECT ... UNION [ALL] SELECT ...`, `INTERSECT` and `EXCEPT`. Each
+//! SELECT runs on its own, and their rows are combined from left to right, like in SQLite:
+//! UNION ALL keeps every row, UNION the rows of either side, INTERSECT those of both and
+//! EXCEPT those of the left side only, without duplicates. Rows are compared value by
+//! value, NULLs being equal to each other like for DISTINCT. The columns are named after
+//! those of the first SELECT, and the ORDER BY of the statement sorts the combined rows.
+use std::cmp::Ordering;
+use std::sync::Arc;
+
+use sqlparser::ast::{Expr, SetOperator, Value as AstValue};
+
+use crate::error::{Result, SQLRiteError};
+use crate::sql::collation::CollationFn;
+use crate::sql::db::database::Database;
+use crate::sql::executor::profile::QueryProfile;
+use crate::sql::executor::{compare_keys, ordinal, ResultSet};
+use crate::sql::parser::select::{Projection, SelectQuery};
+use crate::sql::value::Value;
+
+/// The first SELECT of the compound `query`, without the ORDER BY of the statement.
+fn first_select(query: &SelectQuery) -> SelectQuery {
+    SelectQuery {
+        order_by: vec![],
+        compound: vec![],
+        ..query.clone()
+    }
+}
+
+/// Runs the compound SELECT `query` with `select`, which runs a single SELECT, and returns
+/// its rows and the plan it ran with: the plans of its SELECTs, each combined with the
+/// rows before it, then the sort of its ORDER BY.
+pub fn select_compound<F>(
+    query: &SelectQuery,
+    db: &Database,
+    timed: bool,
+    mut select: F,
+) -> Result<(ResultSet, QueryProfile)>
+where
+    F: FnMut(&SelectQuery) -> Result<(ResultSet, QueryProfile)>,
+{
+    let mut profile = QueryProfile {
+        timed,
+        ..QueryProfile::default()
+    };
+    let (mut result, first) = select(&first_select(query))?;
+    let mut last = profile.append(first);
+    let mut estimated = profile.operators[last - 1].estimated_rows;
+    for compound in &query.compound {
+        let (right, plan) = select(&compound.query)?;
+        if right.columns.len() != result.columns.len() {
+            return Err(mismatched_columns(&compound.operator, compound.all));
+        }
+        let end = profile.append(plan);
+        estimated = combined_estimate(&compound.operator, estimated, &profile, end);
+        let id = profile.add(detail(&compound.operator, compound.all), estimated);
+        profile.operators[last - 1].parent = id;
+        profile.begin(id);
+        let started = profile.now();
+        db.progress.step()?;
+        result.rows = combine(&compound.operator, compound.all, result.rows, right.rows);
+        profile.record(id, started, result.rows.len());
+        last = id;
+    }
+    if !query.order_by.is_empty() {
+        let id = profile.add(sort_detail(query), estimated);
+        profile.begin(id);
+        let started = profile.now();
+        sort(query, db, &mut result)?;
+        profile.record(id, started, result.rows.len());
+    }
+    Ok((result, profile))
+}
+
+/// The plan of the compound SELECT `query`, from the plans `plan` gives for its SELECTs.
+pub fn plan_compound<F>(query: &SelectQuery, mut plan: F) -> Result<QueryProfile>
+where
+    F: FnMut(&SelectQuery) -> Result<QueryProfile>,
+{
+    let mut profile = QueryProfile::default();
+    let mut last = profile.append(plan(&first_select(query))?);
+    let mut estimated = profile.operators[last - 1].estimated_rows;
+    for compound in &query.compound {
+        let end = profile.append(plan(&compound.query)?);
+        estimated = combined_estimate(&compound.operator, estimated, &profile, end);
+        let id = profile.add(detail(&compound.operator, compound.all), estimated);
+        profile.operators[last - 1].parent = id;
+        last = id;
+    }
+    if !query.order_by.is_empty() {
+        profile.add(sort_detail(query), estimated);
+    }
+    Ok(profile)
+}
+
+fn mismatched_columns(operator: &SetOperator, all: bool) -> SQLRiteError {
+    SQLRiteError::General(format!(
+        "SELECTs to the left and right of {} do not have the same number of result columns",
+        detail(operator, all)
+    ))
+}
+
+/// How the operator combining the rows is shown by EXPLAIN QUERY PLAN.
+fn detail(operator: &SetOperator, all: bool) -> String {
+    match all {
+        true => format!("{} ALL", operator),
+        false => operator.to_string(),
+    }
+}
+
+fn sort_detail(query: &SelectQuery) -> String {
+    let terms = query
+        .order_by
+        .iter()
+        .map(|term| match term.asc {
+            true => term.expr.to_string(),
+            false => format!("{} DESC", term.expr),
+        })
+        .collect::<Vec<String>>();
+    format!("SORT BY {}", terms.join(", "))
+}
+
+/// The rows estimated to be left once the rows estimated before are combined with those of
+/// the plan ending with the operator `end`: both for UNION, the fewest for INTERSECT, and
+/// those on the left for EXCEPT.
+fn combined_estimate(
+    operator: &SetOperator,
+    estimated: Option<f64>,
+    profile: &QueryProfile,
+    end: usize,
+) -> Option<f64> {
+    let right = profile.operators[end - 1].estimated_rows;
+    match operator {
+        SetOperator::Union => Some(estimated? + right?),
+        SetOperator::Intersect => Some(estimated?.min(right?)),
+        SetOperator::Except => estimated,
+    }
+}
+
+/// Combines the rows `left` with the rows `right` by `operator`.
+fn combine(
+    operator: &SetOperator,
+    all: bool,
+    left: Vec<Vec<Value>>,
+    right: Vec<Vec<Value>>,
+) -> Vec<Vec<Value>> {
+    if all {
+        let mut rows = left;
+        rows.extend(right);
+        return rows;
+    }
+    let mut kept = RowSet::default();
+    let mut rows = vec![];
+    match operator {
+        SetOperator::Union => {
+            for row in left.into_iter().chain(right) {
+                if kept.insert(&row) {
+                    rows.push(row);
+                }
+            }
+        }
+        SetOperator::Intersect | SetOperator::Except => {
+            let mut other = RowSet::default();
+            for row in &right {
+                other.insert(row);
+            }
+            let wanted = *operator == SetOperator::Intersect;
+            for row in left {
+                if other.contains(&row) == wanted && kept.insert(&row) {
+                    rows.push(row);
+                }
+            }
+        }
+    }
+    rows
+}
+
+/// A set of rows, kept sorted to be searched
+#[derive(Default)]
+struct RowSet {
+    rows: Vec<Vec<Value>>,
+}
+
+impl RowSet {
+    fn search(&self, row: &[Value]) -> std::result::Result<usize, usize> {
+        self.rows.binary_search_by(|other| compare_rows(other, row))
+    }
+
+    fn contains(&self, row: &[Value]) -> bool {
+        self.search(row).is_ok()
+    }
+
+    /// Adds `row`, returning false if it was in the set already.
+    fn insert(&mut self, row: &[Value]) -> bool {
+        match self.search(row) {
+            Ok(_) => false,
+            Err(position) => {
+                self.rows.insert(position, row.to_vec());
+                true
+            }
+        }
+    }
+}
+
+/// Compares two rows value by value, text with the binary collating sequence.
+fn compare_rows(a: &[Value], b: &[Value]) -> Ordering {
+    let binary = |a: &str, b: &str| a.cmp(b);
+    a.iter()
+        .zip(b)
+        .map(|(a, b)| a.compare(b, &binary))
+        .find(|ordering| *ordering != Ordering::Equal)
+        .unwrap_or(Ordering::Equal)
+}
+
+/// Sorts the rows of `result` by the ORDER BY of `query`, each term of which has to be a
+/// result column: its position, its name, or the expression or alias of one of the
+/// SELECTs.
+fn sort(query: &SelectQuery, db: &Database, result: &mut ResultSet) -> Result<()> {
+    let columns = query
+        .order_by
+        .iter()
+        .enumerate()
+        .map(|(i, term)| result_column(i, &term.expr, query, result))
+        .collect::<Result<Vec<usize>>>()?;
+    let collations = query
+        .order_by
+        .iter()
+        .map(|term| {
+            db.collations
+                .get(term.collation.as_deref().unwrap_or("binary"))
+        })
+        .collect::<Result<Vec<Arc<CollationFn>>>>()?;
+    let mut keyed = std::mem::take(&mut result.rows)
+        .into_iter()
+        .map(|row| {
+            let keys = columns
+                .iter()
+                .map(|column| row[*column].clone())
+                .collect::<Vec<Value>>();
+            (keys, row)
+        })
+        .collect::<Vec<(Vec<Value>, Vec<Value>)>>();
+    keyed.sort_by(|(a, _), (b, _)| compare_keys(&query.order_by, &collations, a, b));
+    result.rows = keyed.into_iter().map(|(_, row)| row).collect();
+    Ok(())
+}
+
+/// The position of the result column the `i`th ORDER BY term `expr` of `query` sorts by.
+fn result_column(i: usize, expr: &Expr, query: &SelectQuery, result: &ResultSet) -> Result<usize> {
+    if let Expr::Value(AstValue::Number(n, _)) = expr {
+        return match n.parse::<usize>() {
+            Ok(n) if n >= 1 && n <= result.columns.len() => Ok(n - 1),
+            _ => Err(SQLRiteError::General(format!(
+                "{} ORDER BY term out of range - should be between 1 and {}",
+                ordinal(i + 1),
+                result.columns.len()
+            ))),
+        };
+    }
+    let text = expr.to_string();
+    if let Some(position) = result
+        .columns
+        .iter()
+        .position(|column| column.name.eq_ignore_ascii_case(&text))
+    {
+        return Ok(position);
+    }
+    // The other SELECTs name their columns too
+    let selects = query.compound.iter().map(|compound| &compound.query);
+    for select in selects {
+        let wildcard = |item: &Projection| !matches!(item, Projection::Expr { .. });
+        if select.projection.iter().any(wildcard) {
+            continue;
+        }
+        let position = select.projection.iter().position(|item| match item {
+            Projection::Expr { expr, alias } => {
+                expr.to_string().eq_ignore_ascii_case(&text)
+                    || matches!(alias, Some(alias) if alias.eq_ignore_ascii_case(&text))
+            }
+            Projection::Wildcard | Projection::TableWildcard(_) => false,
+        });
+        if let Some(position) = position {
+            return Ok(position);
+        }
+    }
+    Err(SQLRiteError::General(format!(
+        "{} ORDER BY term does not match any column in the result set",
+        ordinal(i + 1)
+    )))
+}
@@ -0,0 +1,757 @@
+use std::cmp::Ordering;
+use std::sync::Arc;
+
+use sqlparser::ast::{
+    BinaryOperator, Expr, Function, FunctionArg, FunctionArgExpr, Query, Statement, TrimWhereField,
+    UnaryOperator, Value as AstValue,
+};
+
+use crate::error::{Result, SQLRiteError};
+use crate::sql::collation::CollationFn;
+use crate::sql::db::database::Database;
+use crate::sql::executor::aggregate::{is_aggregate, misuse};
+use crate::sql::executor::scope::Scope;
+use crate::sql::executor::source::SourceColumn;
+use crate::sql::executor::{execute_correlated, ResultSet};
+use crate::sql::function::connection;
+use crate::sql::parser::select::SelectQuery;
+use crate::sql::value::{Affinity, Value};
+
+/// The row an expression is evaluated against: the columns in scope and their values
+#[derive(Debug, Clone, Copy)]
+pub struct RowContext<'a> {
+    pub columns: &'a [SourceColumn],
+    pub values: &'a [Value],
+    /// The query the row is read by, whose subqueries can refer to its columns
+    pub scope: Option<&'a Scope<'a>>,
+}
+
+impl<'a> RowContext<'a> {
+    pub fn new(columns: &'a [SourceColumn], values: &'a [Value]) -> Self {
+        RowContext {
+            columns,
+            values,
+            scope: None,
+        }
+    }
+
+    /// The row read by the query of `scope`.
+    pub fn with_scope(self, scope: &'a Scope<'a>) -> Self {
+        RowContext {
+            scope: Some(scope),
+            ..self
+        }
+    }
+
+    /// Returns the value of the column named `name` in this row.
+    pub fn get(&self, name: &str) -> Result<Value> {
+        match self.columns.iter().position(|col| col.name == name) {
+            Some(index) => Ok(self.values.get(index).cloned().unwrap_or(Value::Null)),
+            None => Err(SQLRiteError::General(format!("No such column: {}", name))),
+        }
+    }
+}
+
+/// Evaluates `expr` against `row`.
+/// Function calls are resolved against the functions registered in `db`.
+pub fn eval_expr(expr: &Expr, db: &Database, row: &RowContext) -> Result<Value> {
+    match expr {
+        Expr::Identifier(ident) => row.get(&ident.value),
+        Expr::Value(value) => literal_to_value(value),
+        Expr::Nested(expr) => eval_expr(expr, db, row),
+        // The collation only matters when comparing values, not to the value itself, see
+        // `comparison_collation`
+        Expr::Collate { expr, .. } => eval_expr(expr, db, row),
+        Expr::Function(function) => eval_function(function, db, row),
+        Expr::UnaryOp {
+            op: UnaryOperator::Plus,
+            expr,
+        } => eval_expr(expr, db, row),
+        Expr::UnaryOp {
+            op: UnaryOperator::Minus,
+            expr,
+        } => match expr.as_ref() {
+            // The smallest integer, although its digits alone don't fit one
+            Expr::Value(AstValue::Number(n, _)) if n == "9223372036854775808" => {
+                Ok(Value::Integer(i64::MIN))
+            }
+            // Unary minus binds tighter than these in SQLite, the parser applies it to the
+            // whole product: -a % b is (-a) % b, not -(a % b)
+            Expr::BinaryOp {
+                left,
+                op:
+                    op @ (BinaryOperator::Multiply | BinaryOperator::Divide | BinaryOperator::Modulo),
+                right,
+            } => {
+                let left = Expr::UnaryOp {
+                    op: UnaryOperator::Minus,
+                    expr: left.clone(),
+                };
+                eval_binary_op(&left, op, right, db, row)
+            }
+            expr => Ok(negate(eval_expr(expr, db, row)?)),
+        },
+        Expr::UnaryOp {
+            op: UnaryOperator::Not,
+            expr,
+        } => Ok(truth_value(eval_expr(expr, db, row)?.to_bool().map(|v| !v))),
+        Expr::BinaryOp { left, op, right } => eval_binary_op(left, op, right, db, row),
+        Expr::Cast { expr, data_type } => {
+            let affinity = Affinity::from_type_name(&data_type.to_string());
+            Ok(eval_expr(expr, db, row)?.cast(affinity))
+        }
+        // Unlike a comparison with NULL, never NULL itself
+        Expr::IsNull(expr) => Ok(truth_value(Some(eval_expr(expr, db, row)?.is_null()))),
+        Expr::IsNotNull(expr) => Ok(truth_value(Some(!eval_expr(expr, db, row)?.is_null()))),
+        // A row value is only a value in a comparison or an IN
+        Expr::Tuple(_) => Err(row_value_misused()),
+        Expr::InList {
+            expr,
+            list,
+            negated,
+        } => {
+            let left = eval_row(expr, db, row)?;
+            let list = list
+                .iter()
+                .map(|item| {
+                    let values = eval_row(item, db, row)?;
+                    Ok(match (left.as_slice(), values.as_slice()) {
+                        ([left], [value]) => vec![compared(expr, left, item, value, row).1],
+                        _ => values,
+                    })
+                })
+                .collect::<Result<Vec<Vec<Value>>>>()?;
+            let collation = operand_collation(expr, db, row)?;
+            let found = is_in(&left, &list, collation.as_ref())?;
+            Ok(truth_value(found.map(|found| found != *negated)))
+        }
+        // `x BETWEEN low AND high` is `x >= low AND x <= high`, with its NULLs
+        Expr::Between {
+            expr,
+            negated,
+            low,
+            high,
+        } => {
+            let value = eval_expr(expr, db, row)?;
+            let compare = |bound: &Expr, op: BinaryOperator| -> Result<Option<bool>> {
+                let collation = comparison_collation(expr, bound, db, row)?;
+                let bound_value = eval_expr(bound, db, row)?;
+                if value.is_null() || bound_value.is_null() {
+                    return Ok(None);
+                }
+                let (value, bound) = compared(expr, &value, bound, &bound_value, row);
+                compare_result(&op, value.compare(&bound, collation.as_ref())).map(Some)
+            };
+            let between = match (
+                compare(low, BinaryOperator::GtEq)?,
+                compare(high, BinaryOperator::LtEq)?,
+            ) {
+                (Some(false), _) | (_, Some(false)) => Some(false),
+                (Some(true), Some(true)) => Some(true),
+                _ => None,
+            };
+            Ok(truth_value(between.map(|between| between != *negated)))
+        }
+        // The result of the first WHEN that is true, or that equals the operand when there is
+        // one, else the ELSE result or NULL. Only the result chosen is evaluated.
+        Expr::Case {
+            operand,
+            conditions,
+            results,
+            else_result,
+        } => {
+            let operand = match operand {
+                Some(operand) => Some((operand, eval_expr(operand, db, row)?)),
+                None => None,
+            };
+            for (condition, result) in conditions.iter().zip(results) {
+                let condition_value = eval_expr(condition, db, row)?;
+                let matched = match &operand {
+                    Some((_, value)) if value.is_null() || condition_value.is_null() => false,
+                    Some((operand, value)) => {
+                        let collation = comparison_collation(operand, condition, db, row)?;
+                        let (value, condition) =
+                            compared(operand, value, condition, &condition_value, row);
+                        value.compare(&condition, collation.as_ref()) == Ordering::Equal
+                    }
+                    None => condition_value.to_bool() == Some(true),
+                };
+                if matched {
+                    return eval_expr(result, db, row);
+                }
+            }
+            match else_result {
+                Some(else_result) => eval_expr(else_result, db, row),
+                None => Ok(Value::Null),
+            }
+        }
+        Expr::InSubquery {
+            expr,
+            subquery,
+            negated,
+        } => {
+            let left = eval_row(expr, db, row)?;
+            let result = execute_subquery(subquery, db, row)?;
+            if result.columns.len() != left.len() {
+                return Err(SQLRiteError::General(format!(
+                    "sub-select returns {} columns - expected {}",
+                    result.columns.len(),
+                    left.len()
+                )));
+            }
+            let collation = operand_collation(expr, db, row)?;
+            let found = is_in(&left, &result.rows, collation.as_ref())?;
+            Ok(truth_value(found.map(|found| found != *negated)))
+        }
+        // A scalar subquery is the first value of the rows it returns, NULL without any
+        Expr::Subquery(subquery) => {
+            let result = execute_subquery(subquery, db, row)?;
+            if result.columns.len() != 1 {
+                return Err(SQLRiteError::General(format!(
+                    "sub-select returns {} columns - expected 1",
+                    result.columns.len()
+                )));
+            }
+            let first = result.rows.into_iter().next();
+            Ok(first
+                .and_then(|values| values.into_iter().next())
+                .unwrap_or(Value::Null))
+        }
+        // TRIM, SUBSTRING and POSITION have their own syntax, they are evaluated by the
+        // functions of the same name, `instr` for POSITION
+        Expr::Trim { expr, trim_where } => {
+            let (name, mut args) = match trim_where {
+                None => ("trim", vec![]),
+                Some((field, chars)) => {
+                    let name = match field {
+                        TrimWhereField::Both => "trim",
+                        TrimWhereField::Leading => "ltrim",
+                        TrimWhereField::Trailing => "rtrim",
+                    };
+                    (name, vec![eval_expr(chars, db, row)?])
+                }
+            };
+            args.insert(0, eval_expr(expr, db, row)?);
+            db.functions.call(name, &args)
+        }
+        Expr::Substring {
+            expr,
+            substring_from,
+            substring_for,
+        } => {
+            let mut args = vec![eval_expr(expr, db, row)?];
+            args.push(match substring_from {
+                Some(from) => eval_expr(from, db, row)?,
+                None => Value::Integer(1),
+            });
+            if let Some(count) = substring_for {
+                args.push(eval_expr(count, db, row)?);
+            }
+            db.functions.call("substring", &args)
+        }
+        Expr::Position { expr, r#in } => {
+            let args = [eval_expr(r#in, db, row)?, eval_expr(expr, db, row)?];
+            db.functions.call("instr", &args)
+        }
+        _ => Err(SQLRiteError::NotImplemented(format!(
+            "Expression not supported yet: {}",
+            expr
+        ))),
+    }
+}
+
+/// Evaluates the arguments of a function call and calls the function registered under its name.
+fn eval_function(function: &Function, db: &Database, row: &RowContext) -> Result<Value> {
+    // Aggregate calls are replaced by their values where they are allowed
+    if is_aggregate(function) {
+        return Err(misuse(function));
+    }
+    let name = function.name.to_string();
+    if let "coalesce" | "ifnull" = name.to_lowercase().as_str() {
+        return eval_coalesce(function, db, row);
+    }
+    let mut args: Vec<Value> = vec![];
+    for arg in &function.args {
+        match arg {
+            FunctionArg::Unnamed(FunctionArgExpr::Expr(expr)) => {
+                args.push(eval_expr(expr, db, row)?)
+            }
+            _ => {
+                return Err(SQLRiteError::NotImplemented(format!(
+                    "Function argument not supported yet: {}",
+                    arg
+                )))
+            }
+        }
+    }
+    let value = match connection::call(db, &name, &args) {
+        Some(result) => result?,
+        None => db.functions.call(&name, &args)?,
+    };
+    db.limits.check_length(&value)?;
+    Ok(value)
+}
+
+/// Evaluates `coalesce(X, Y, ...)` and `ifnull(X, Y)` like SQLite does, up to their first
+/// argument that isn't NULL, which is their value: the arguments after it aren't evaluated.
+fn eval_coalesce(function: &Function, db: &Database, row: &RowContext) -> Result<Value> {
+    let count = function.args.len();
+    let ifnull = function.name.to_string().eq_ignore_ascii_case("ifnull");
+    if count < 2 || (ifnull && count != 2) {
+        return Err(SQLRiteError::General(format!(
+            "wrong number of arguments to function {}()",
+            function.name
+        )));
+    }
+    for arg in &function.args {
+        let value = match arg {
+            FunctionArg::Unnamed(FunctionArgExpr::Expr(expr)) => eval_expr(expr, db, row)?,
+            _ => {
+                return Err(SQLRiteError::NotImplemented(format!(
+                    "Function argument not supported yet: {}",
+                    arg
+                )))
+            }
+        };
+        if !value.is_null() {
+            return Ok(value);
+        }
+    }
+    Ok(Value::Null)
+}
+
+/// Evaluates the logical, comparison, arithmetic and concatenation operators. Like in
+/// SQLite, they are NULL when an operand is NULL, except when AND and OR are decided by
+/// their other operand.
+fn eval_binary_op(
+    left: &Expr,
+    op: &BinaryOperator,
+    right: &Expr,
+    db: &Database,
+    row: &RowContext,
+) -> Result<Value> {
+    if matches!(left, Expr::Tuple(_)) || matches!(right, Expr::Tuple(_)) {
+        let (left, right) = (eval_row(left, db, row)?, eval_row(right, db, row)?);
+        let binary = db.collations.get("binary")?;
+        return Ok(truth_value(compare_rows(
+            &left,
+            op,
+            &right,
+            binary.as_ref(),
+        )?));
+    }
+    let (left_expr, right_expr) = (left, right);
+    let left = eval_expr(left, db, row)?;
+    let right = eval_expr(right, db, row)?;
+    let result = match op {
+        BinaryOperator::And => match (left.to_bool(), right.to_bool()) {
+            (Some(false), _) | (_, Some(false)) => Some(false),
+            (Some(true), Some(true)) => Some(true),
+            _ => None,
+        },
+        BinaryOperator::Or => match (left.to_bool(), right.to_bool()) {
+            (Some(true), _) | (_, Some(true)) => Some(true),
+            (Some(false), Some(false)) => Some(false),
+            _ => None,
+        },
+        BinaryOperator::Plus
+        | BinaryOperator::Minus
+        | BinaryOperator::Multiply
+        | BinaryOperator::Divide
+        | BinaryOperator::Modulo
+        | BinaryOperator::BitwiseAnd
+        | BinaryOperator::BitwiseOr => return arithmetic(op, &left, &right),
+        BinaryOperator::StringConcat => {
+            return Ok(match (left.to_text(), right.to_text()) {
+                (Some(left), Some(right)) => Value::Text(left + &right),
+                _ => Value::Null,
+            })
+        }
+        op => {
+            if left.is_null() || right.is_null() {
+                return Ok(Value::Null);
+            }
+            let collation = comparison_collation(left_expr, right_expr, db, row)?;
+            let (left, right) = compared(left_expr, &left, right_expr, &right, row);
+            let ordering = left.compare(&right, collation.as_ref());
+            Some(match op {
+                BinaryOperator::Eq => ordering == Ordering::Equal,
+                BinaryOperator::NotEq => ordering != Ordering::Equal,
+                BinaryOperator::Lt => ordering == Ordering::Less,
+                BinaryOperator::LtEq => ordering != Ordering::Greater,
+                BinaryOperator::Gt => ordering == Ordering::Greater,
+                BinaryOperator::GtEq => ordering != Ordering::Less,
+                _ => {
+                    return Err(SQLRiteError::NotImplemented(format!(
+                        "Operator not supported yet: {}",
+                        op
+                    )))
+                }
+            })
+        }
+    };
+    Ok(truth_value(result))
+}
+
+/// Applies the arithmetic or bitwise operator `op` to two values the way SQLite does: the
+/// operands are read as numbers, integers staying integers unless the result overflows,
+/// when it is computed on reals. Dividing by zero is NULL, and `%` and the bitwise
+/// operators work on the integer part of reals, the remainder of the smallest integer
+/// by -1 being an integer overflow.
+fn arithmetic(op: &BinaryOperator, left: &Value, right: &Value) -> Result<Value> {
+    let (left, right) = match (left.to_numeric(), right.to_numeric()) {
+        (Some(left), Some(right)) => (left, right),
+        _ => return Ok(Value::Null),
+    };
+    if let (Value::Integer(a), Value::Integer(b)) = (&left, &right) {
+        let (a, b) = (*a, *b);
+        let result = match op {
+            BinaryOperator::Plus => a.checked_add(b),
+            BinaryOperator::Minus => a.checked_sub(b),
+            BinaryOperator::Multiply => a.checked_mul(b),
+            BinaryOperator::Divide if b == 0 => return Ok(Value::Null),
+            BinaryOperator::Divide => a.checked_div(b),
+            BinaryOperator::Modulo if b == 0 => return Ok(Value::Null),
+            BinaryOperator::Modulo => Some(a.checked_rem(b).ok_or_else(integer_overflow)?),
+            BinaryOperator::BitwiseAnd => Some(a & b),
+            _ => Some(a | b),
+        };
+        if let Some(result) = result {
+            return Ok(Value::Integer(result));
+        }
+    }
+    let (a, b) = (
+        left.to_real().unwrap_or(0.0),
+        right.to_real().unwrap_or(0.0),
+    );
+    Ok(match op {
+        BinaryOperator::Plus => Value::Real(a + b),
+        BinaryOperator::Minus => Value::Real(a - b),
+        BinaryOperator::Multiply => Value::Real(a * b),
+        BinaryOperator::Divide if b == 0.0 => Value::Null,
+        BinaryOperator::Divide => Value::Real(a / b),
+        BinaryOperator::Modulo if b as i64 == 0 => Value::Null,
+        BinaryOperator::Modulo => Value::Real(
+            (a as i64)
+                .checked_rem(b as i64)
+                .ok_or_else(integer_overflow)? as f64,
+        ),
+        BinaryOperator::BitwiseAnd => Value::Integer(a as i64 & b as i64),
+        _ => Value::Integer(a as i64 | b as i64),
+    })
+}
+
+fn integer_overflow() -> SQLRiteError {
+    SQLRiteError::General("integer overflow".to_string())
+}
+
+/// Evaluates `expr` as a row value: the values of a `(a, b, ...)` row value constructor,
+/// or the value of any other expression as a row value of one column.
+fn eval_row(expr: &Expr, db: &Database, row: &RowContext) -> Result<Vec<Value>> {
+    match expr {
+        Expr::Tuple(exprs) => exprs.iter().map(|expr| eval_expr(expr, db, row)).collect(),
+        expr => Ok(vec![eval_expr(expr, db, row)?]),
+    }
+}
+
+fn row_value_misused() -> SQLRiteError {
+    SQLRiteError::General("row value misused".to_string())
+}
+
+/// Compares the row values `left` and `right` with `op`, like SQLite: column by column,
+/// the first columns that aren't equal deciding the result. A NULL column before them
+/// makes it unknown, `None`, except for `=` and `<>`, which a later column can still decide.
+fn compare_rows(
+    left: &[Value],
+    op: &BinaryOperator,
+    right: &[Value],
+    collation: &CollationFn,
+) -> Result<Option<bool>> {
+    if left.len() != right.len() {
+        return Err(row_value_misused());
+    }
+    let equality = matches!(op, BinaryOperator::Eq | BinaryOperator::NotEq);
+    let mut unknown = false;
+    for (a, b) in left.iter().zip(right) {
+        if a.is_null() || b.is_null() {
+            if !equality {
+                return Ok(None);
+            }
+            unknown = true;
+            continue;
+        }
+        let ordering = a.compare(b, collation);
+        if ordering != Ordering::Equal {
+            return Ok(Some(compare_result(op, ordering)?));
+        }
+    }
+    if unknown {
+        return Ok(None);
+    }
+    Ok(Some(compare_result(op, Ordering::Equal)?))
+}
+
+/// Runs `subquery` for `row`, whose columns, and those of the rows it is evaluated for in
+/// turn, the subquery can refer to.
+fn execute_subquery(subquery: &Query, db: &Database, row: &RowContext) -> Result<ResultSet> {
+    let statement = Statement::Query(Box::new(subquery.clone()));
+    let outer = match row.scope {
+        Some(scope) => scope.enclosing(row),
+        None => vec![],
+    };
+    execute_correlated(&SelectQuery::new(&statement)?, db, &outer)
+}
+
+/// The affinity of `expr`: that of the column it names, or of the type it is CAST to.
+/// Other expressions have none.
+fn affinity(expr: &Expr, row: &RowContext) -> Option<Affinity> {
+    match expr {
+        Expr::Identifier(ident) => row
+            .columns
+            .iter()
+            .find(|column| column.name == ident.value)
+            .and_then(|column| column.datatype.as_ref())
+            .and_then(Affinity::of_column),
+        Expr::Cast { data_type, .. } => Some(Affinity::from_type_name(&data_type.to_string())),
+        Expr::Nested(expr) | Expr::Collate { expr, .. } => affinity(expr, row),
+        _ => None,
+    }
+}
+
+/// The values of the expressions `left` and `right` converted the way SQLite converts the
+/// operands of a comparison: when one of them has a numeric affinity and the other doesn't,
+/// the other is converted to that affinity, and when one has TEXT affinity and the other
+/// none, the other is converted to text. `id = '5'` is true for the INTEGER 5 that way.
+fn compared(
+    left: &Expr,
+    left_value: &Value,
+    right: &Expr,
+    right_value: &Value,
+    row: &RowContext,
+) -> (Value, Value) {
+    let numeric =
+        |affinity: Option<Affinity>| matches!(affinity, Some(affinity) if affinity.is_numeric());
+    match (affinity(left, row), affinity(right, row)) {
+        (Some(left_affinity), right_affinity)
+            if left_affinity.is_numeric() && !numeric(right_affinity) =>
+        {
+            (
+                left_value.clone(),
+                right_value.apply_affinity(left_affinity),
+            )
+        }
+        (left_affinity, Some(right_affinity))
+            if right_affinity.is_numeric() && !numeric(left_affinity) =>
+        {
+            (
+                left_value.apply_affinity(right_affinity),
+                right_value.clone(),
+            )
+        }
+        (Some(Affinity::Text), None) => (
+            left_value.clone(),
+            right_value.apply_affinity(Affinity::Text),
+        ),
+        (None, Some(Affinity::Text)) => (
+            left_value.apply_affinity(Affinity::Text),
+            right_value.clone(),
+        ),
+        _ => (left_value.clone(), right_value.clone()),
+    }
+}
+
+/// The collating sequence comparing the values of `left` and `right`, picked like SQLite
+/// does: that of a COLLATE clause on the left operand, else on the right one, else that of
+/// the column the left operand names, else of the column the right one names, else BINARY.
+fn comparison_collation(
+    left: &Expr,
+    right: &Expr,
+    db: &Database,
+    row: &RowContext,
+) -> Result<Arc<CollationFn>> {
+    let name = explicit_collation(left)
+        .or_else(|| explicit_collation(right))
+        .or_else(|| column_collation(left, row))
+        .or_else(|| column_collation(right, row));
+    db.collations.get(name.as_deref().unwrap_or("binary"))
+}
+
+/// The collating sequence the left operand `expr` of IN compares with, the one it would
+/// compare with in `=`: that of its COLLATE clause, else of the column it names, else BINARY.
+fn operand_collation(expr: &Expr, db: &Database, row: &RowContext) -> Result<Arc<CollationFn>> {
+    comparison_collation(expr, expr, db, row)
+}
+
+/// The collation named by the COLLATE clause `expr` ends with, if any.
+fn explicit_collation(expr: &Expr) -> Option<String> {
+    match expr {
+        Expr::Collate { collation, .. } => Some(collation.to_string()),
+        Expr::Nested(expr) => explicit_collation(expr),
+        _ => None,
+    }
+}
+
+/// The collation declared for the column `expr` names, if it names one that has one.
+fn column_collation(expr: &Expr, row: &RowContext) -> Option<String> {
+    match expr {
+        Expr::Identifier(ident) => row
+            .columns
+            .iter()
+            .find(|column| column.name == ident.value)
+            .and_then(|column| column.collation.clone()),
+        Expr::Nested(expr) => column_collation(expr, row),
+        _ => None,
+    }
+}
+
+/// Checks that every collation named by a COLLATE clause of `expr` is registered, so that
+/// an unknown name is reported even when no row is compared.
+pub fn check_collations(expr: &Expr, db: &Database) -> Result<()> {
+    if let Expr::Collate { collation, .. } = expr {
+        db.collations.get(&collation.to_string())?;
+    }
+    for child in children(expr) {
+        check_collations(child, db)?;
+    }
+    Ok(())
+}
+
+/// The expressions `expr` is made of, subqueries left out.
+fn children(expr: &Expr) -> Vec<&Expr> {
+    match expr {
+        Expr::Function(function) => function
+            .args
+            .iter()
+            .filter_map(|arg| match arg {
+                FunctionArg::Unnamed(FunctionArgExpr::Expr(expr)) => Some(expr),
+                _ => None,
+            })
+            .collect(),
+        Expr::BinaryOp { left, right, .. } => vec![left, right],
+        Expr::UnaryOp { expr, .. }
+        | Expr::Nested(expr)
+        | Expr::Collate { expr, .. }
+        | Expr::IsNull(expr)
+        | Expr::IsNotNull(expr)
+        | Expr::Cast { expr, .. }
+        | Expr::InSubquery { expr, .. } => vec![expr],
+        Expr::Trim { expr, trim_where } => {
+            let mut exprs = vec![expr.as_ref()];
+            exprs.extend(trim_where.as_ref().map(|(_, chars)| chars.as_ref()));
+            exprs
+        }
+        Expr::Substring {
+            expr,
+            substring_from,
+            substring_for,
+        } => {
+            let mut exprs = vec![expr.as_ref()];
+            exprs.extend(substring_from.as_deref());
+            exprs.extend(substring_for.as_deref());
+            exprs
+        }
+        Expr::Position { expr, r#in } => vec![expr, r#in],
+        Expr::Between {
+            expr, low, high, ..
+        } => vec![expr, low, high],
+        Expr::InList { expr, list, .. } => {
+            let mut exprs = vec![expr.as_ref()];
+            exprs.extend(list);
+            exprs
+        }
+        Expr::Tuple(exprs) => exprs.iter().collect(),
+        Expr::Case {
+            operand,
+            conditions,
+            results,
+            else_result,
+        } => operand
+            .iter()
+            .chain(else_result.iter())
+            .map(|expr| expr.as_ref())
+            .chain(conditions.iter().chain(results.iter()))
+            .collect(),
+        _ => vec![],
+    }
+}
+
+/// Whether two values comparing as `ordering` satisfy the comparison `op`.
+fn compare_result(op: &BinaryOperator, ordering: Ordering) -> Result<bool> {
+    Ok(match op {
+        BinaryOperator::Eq => ordering == Ordering::Equal,
+        BinaryOperator::NotEq => ordering != Ordering::Equal,
+        BinaryOperator::Lt => ordering == Ordering::Less,
+        BinaryOperator::LtEq => ordering != Ordering::Greater,
+        BinaryOperator::Gt => ordering == Ordering::Greater,
+        BinaryOperator::GtEq => ordering != Ordering::Less,
+        _ => return Err(row_value_misused()),
+    })
+}
+
+/// Whether the row value `left` is one of `rows`, compared under `collation`, `None` when it
+/// is unknown because of NULLs, the way IN tells it.
+fn is_in(left: &[Value], rows: &[Vec<Value>], collation: &CollationFn) -> Result<Option<bool>> {
+    let mut unknown = false;
+    for candidate in rows {
+        match compare_rows(left, &BinaryOperator::Eq, candidate, collation)? {
+            Some(true) => return Ok(Some(true)),
+            Some(false) => {}
+            None => unknown = true,
+        }
+    }
+    Ok(if unknown { None } else { Some(false) })
+}
+
+/// The value of a condition: 1 if true, 0 if false, NULL if unknown.
+fn truth_value(value: Option<bool>) -> Value {
+    value.map_or(Value::Null, |v| Value::Integer(i64::from(v)))
+}
+
+/// Negates a value the way SQLite does: text is read as a number first, and negating the
+/// smallest integer overflows into a real.
+fn negate(value: Value) -> Value {
+    match value {
+        Value::Null => Value::Null,
+        Value::Integer(v) => v
+            .checked_neg()
+            .map_or(Value::Real(-(v as f64)), Value::Integer),
+        Value::Real(v) => Value::Real(-v),
+        value => match (value.to_integer(), value.to_real()) {
+            (Some(i), Some(r)) if i as f64 == r => negate(Value::Integer(i)),
+            (_, r) => Value::Real(-r.unwrap_or(0.0)),
+        },
+    }
+}
+
+/// Converts a literal from the sqlparser AST into a `Value`.
+pub fn literal_to_value(value: &AstValue) -> Result<Value> {
+    match value {
+        AstValue::Number(n, _) => match n.parse::<i64>() {
+            Ok(i) => Ok(Value::Integer(i)),
+            Err(_) => n
+                .parse::<f64>()
+                .map(Value::Real)
+                .map_err(|_| SQLRiteError::General(format!("Invalid number: {}", n))),
+        },
+        AstValue::SingleQuotedString(s) => Ok(Value::Text(s.to_string())),
+        AstValue::HexStringLiteral(s) => decode_hex(s).map(Value::Blob),
+        AstValue::Boolean(b) => Ok(Value::Bool(*b)),
+        AstValue::Null => Ok(Value::Null),
+        _ => Err(SQLRiteError::NotImplemented(format!(
+            "Literal not supported yet: {}",
+            value
+        ))),
+    }
+}
+
+/// Decodes the digits of a `X'...'` blob literal.
+fn decode_hex(digits: &str) -> Result<Vec<u8>> {
+    let invalid = || SQLRiteError::General(format!("Invalid blob literal: X'{}'", digits));
+    if digits.len() % 2 == 1 || !digits.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return Err(invalid());
+    }
+    (0..digits.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&digits[i..i + 2], 16).map_err(|_| invalid()))
+        .collect()
+}
@@ -0,0 +1,214 @@
+use sqlparser::ast::{BinaryOperator, Expr, FunctionArg, FunctionArgExpr};
+
+use crate::error::Result;
+use crate::sql::db::database::Database;
+use crate::sql::executor::expr::{eval_expr, RowContext};
+use crate::sql::value::Value;
+use crate::sql::vtab::ConstraintOp;
+
+/// A `column op value` term of a WHERE clause, checked while the table is scanned.
+/// Virtual tables are offered these terms so they only produce the rows satisfying them.
+#[derive(Debug, PartialEq, Clone)]
+pub struct ScanConstraint {
+    pub column: String,
+    pub op: ConstraintOp,
+    pub value: Value,
+}
+
+/// A `column IN (constant, ...)` term of a WHERE clause, whose rows can be looked up in the
+/// index of the column. Unlike a `ScanConstraint`, the term is still evaluated on each row.
+#[derive(Debug, PartialEq, Clone)]
+pub struct ScanList {
+    pub column: String,
+    pub values: Vec<Value>,
+}
+
+/// Splits a WHERE clause into its top-level AND terms comparing a column with a constant,
+/// returned as `ScanConstraint`s, and the other terms, which have to be evaluated on each
+/// row. `column MATCH value` is parsed as the function call `match(column, value)`, and
+/// `column BETWEEN low AND high` is the two constraints `column >= low` and
+/// `column <= high`.
+pub fn split_selection(
+    selection: &Expr,
+    db: &Database,
+) -> Result<(Vec<ScanConstraint>, Vec<Expr>)> {
+    let mut constraints: Vec<ScanConstraint> = vec![];
+    let mut residual: Vec<Expr> = vec![];
+    let mut terms = vec![selection];
+    while let Some(term) = terms.pop() {
+        match term {
+            Expr::BinaryOp {
+                left,
+                op: BinaryOperator::And,
+                right,
+            } => {
+                terms.push(right);
+                terms.push(left);
+            }
+            Expr::Nested(expr) => terms.push(expr),
+            Expr::Between {
+                expr,
+                negated: false,
+                low,
+                high,
+            } if matches!(expr.as_ref(), Expr::Identifier(_))
+                && is_constant(low)
+                && is_constant(high) =>
+            {
+                let column = expr.to_string();
+                for (op, bound) in [(ConstraintOp::Ge, low), (ConstraintOp::Le, high)] {
+                    constraints.push(ScanConstraint {
+                        column: column.to_string(),
+                        op,
+                        value: eval_expr(bound, db, &RowContext::new(&[], &[]))?,
+                    });
+                }
+            }
+            term => match as_constraint(term) {
+                Some((column, op, value)) => constraints.push(ScanConstraint {
+                    column,
+                    op,
+                    value: eval_expr(value, db, &RowContext::new(&[], &[]))?,
+                }),
+                None => residual.push(term.clone()),
+            },
+        }
+    }
+    Ok((constraints, residual))
+}
+
+/// The `column IN (constant, ...)` terms among `terms`, the terms of a WHERE clause
+/// `split_selection` didn't make constraints.
+pub fn scan_lists(terms: &[Expr], db: &Database) -> Result<Vec<ScanList>> {
+    let mut lists = vec![];
+    for term in terms {
+        if let Expr::InList {
+            expr,
+            list,
+            negated: false,
+        } = term
+        {
+            if let Expr::Identifier(ident) = expr.as_ref() {
+                if list.iter().all(is_constant) {
+                    let values = list
+                        .iter()
+                        .map(|value| eval_expr(value, db, &RowContext::new(&[], &[])))
+                        .collect::<Result<Vec<Value>>>()?;
+                    lists.push(ScanList {
+                        column: ident.value.to_string(),
+                        values,
+                    });
+                }
+            }
+        }
+    }
+    Ok(lists)
+}
+
+/// Returns true if every one of `terms` is true for `row`.
+pub fn satisfies(terms: &[Expr], db: &Database, row: &RowContext) -> Result<bool> {
+    for term in terms {
+        if eval_expr(term, db, row)?.to_bool() != Some(true) {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+/// Breaks `column op constant`, `constant op column` and `match(column, constant)` down
+/// into their column, operator and constant.
+fn as_constraint(term: &Expr) -> Option<(String, ConstraintOp, &Expr)> {
+    match term {
+        Expr::BinaryOp { left, op, right } => {
+            let op = match op {
+                BinaryOperator::Eq => ConstraintOp::Eq,
+                BinaryOperator::Gt => ConstraintOp::Gt,
+                BinaryOperator::GtEq => ConstraintOp::Ge,
+                BinaryOperator::Lt => ConstraintOp::Lt,
+                BinaryOperator::LtEq => ConstraintOp::Le,
+                _ => return None,
+            };
+            match (left.as_ref(), right.as_ref()) {
+                (Expr::Identifier(ident), value) if is_constant(value) => {
+                    Some((ident.value.to_string(), op, value))
+                }
+                (value, Expr::Identifier(ident)) if is_constant(value) => {
+                    let op = match op {
+                        ConstraintOp::Gt => ConstraintOp::Lt,
+                        ConstraintOp::Ge => ConstraintOp::Le,
+                        ConstraintOp::Lt => ConstraintOp::Gt,
+                        ConstraintOp::Le => ConstraintOp::Ge,
+                        op => op,
+                    };
+                    Some((ident.value.to_string(), op, value))
+                }
+                _ => None,
+            }
+        }
+        Expr::Function(function) if function.name.to_string().eq_ignore_ascii_case("match") => {
+            match function.args.as_slice() {
+                [FunctionArg::Unnamed(FunctionArgExpr::Expr(Expr::Identifier(ident))), FunctionArg::Unnamed(FunctionArgExpr::Expr(value))]
+                    if is_constant(value) =>
+                {
+                    Some((ident.value.to_string(), ConstraintOp::Match, value))
+                }
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+/// Returns true if `expr` doesn't refer to any column.
+fn is_constant(expr: &Expr) -> bool {
+    match expr {
+        Expr::Value(_) => true,
+        Expr::Nested(expr) | Expr::UnaryOp { expr, .. } => is_constant(expr),
+        Expr::Function(function) => function.args.iter().all(|arg| match arg {
+            FunctionArg::Unnamed(FunctionArgExpr::Expr(expr)) => is_constant(expr),
+            _ => false,
+        }),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sql::parser::select::SelectQuery;
+
+    #[test]
+    fn split_selection_test() {
+        let db = Database::new("tempdb".to_string());
+        let statement = crate::sql::parse_statement(
+            "SELECT * FROM docs WHERE docs MATCH 'rust' AND (2 < id AND name = lower('JOSH')) AND age;",
+        )
+        .unwrap();
+        let query = SelectQuery::new(&statement).unwrap();
+        let (constraints, residual) =
+            split_selection(query.selection.as_ref().unwrap(), &db).unwrap();
+
+        assert_eq!(
+            constraints,
+            vec![
+                ScanConstraint {
+                    column: "docs".to_string(),
+                    op: ConstraintOp::Match,
+                    value: Value::Text("rust".to_string()),
+                },
+                ScanConstraint {
+                    column: "id".to_string(),
+                    op: ConstraintOp::Gt,
+                    value: Value::Integer(2),
+                },
+                ScanConstraint {
+                    column: "name".to_string(),
+                    op: ConstraintOp::Eq,
+                    value: Value::Text("josh".to_string()),
+                },
+            ]
+        );
+        assert_eq!(residual.len(), 1);
+        assert_eq!(residual[0].to_string(), "age");
+    }
+}
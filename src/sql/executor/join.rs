@@ -0,0 +1,320 @@
+//! Joins: SELECTs from several tables, listed in the FROM clause separated by commas or
+//! joined with `JOIN ... ON`, `LEFT JOIN ... ON` and `CROSS JOIN`. The tables are joined from
+//! left to right, each row of the tables before a table paired with every row of the table
+//! satisfying the ON condition of its join, or every row without one. A LEFT JOIN keeps the
+//! rows before it that no row of its table matches, with NULL for the columns of the table.
+//! The tables of attached databases are joined like those of the main one.
+//!
+//! A table joined ON an equality between one of its UNIQUE columns and a column of the
+//! tables before it, `o.id = u.order_id`, isn't read whole: the rows matching each row
+//! before it are looked up in the index of the column, and a value the bloom filter of the
+//! index doesn't have is known to match no row without looking it up.
+//!
+//! The columns of a join are named by the table they are in, `u.name` for the column `name`
+//! of the table called `u`, its alias or else its name. Like in SQLite, a column can be
+//! named without its table when no other table has a column with that name, and is an
+//! `ambiguous column name` otherwise.
+use sqlparser::ast::{BinaryOperator, Expr};
+
+use crate::error::{Result, SQLRiteError};
+use crate::sql::db::database::Database;
+use crate::sql::db::privilege::Privilege;
+use crate::sql::db::stats::DEFAULT_RANGE_SELECTIVITY;
+use crate::sql::db::table::{Column, Table};
+use crate::sql::executor::expr::{eval_expr, RowContext};
+use crate::sql::executor::filter::satisfies;
+use crate::sql::executor::scope::Scope;
+use crate::sql::executor::source::{comparable, indexed_column, Source, SourceColumn};
+use crate::sql::parser::select::{names_table, SelectQuery};
+use crate::sql::value::Value;
+
+/// A table of the FROM clause of a SELECT
+#[derive(Debug, Clone, Copy)]
+pub struct FromTable<'a> {
+    pub name: &'a str,
+    pub alias: Option<&'a str>,
+    /// Arguments given when the table is a table-valued function
+    pub args: &'a [Expr],
+}
+
+impl FromTable<'_> {
+    /// The name the columns of the table are qualified by in the rows of a join: its alias,
+    /// or its name without one.
+    pub fn qualifier(&self) -> &str {
+        self.alias.unwrap_or(self.name)
+    }
+
+    /// Returns true if `qualifier` names the table.
+    pub fn is_named(&self, qualifier: &str) -> bool {
+        names_table(qualifier, self.name, self.alias)
+    }
+}
+
+/// The tables of the FROM clause of `query`, from left to right.
+pub fn from_tables(query: &SelectQuery) -> Vec<FromTable<'_>> {
+    let first = query.table_name.as_deref().map(|name| FromTable {
+        name,
+        alias: query.table_alias.as_deref(),
+        args: &query.table_args,
+    });
+    let joined = query.joins.iter().map(|join| FromTable {
+        name: &join.table_name,
+        alias: join.table_alias.as_deref(),
+        args: &join.table_args,
+    });
+    first.into_iter().chain(joined).collect()
+}
+
+/// The columns of the rows the tables of `query` are joined into. Their rows aren't read.
+pub fn joined_columns(query: &SelectQuery, db: &Database) -> Result<Vec<SourceColumn>> {
+    let mut columns = vec![];
+    for table in from_tables(query) {
+        columns.extend(table_columns(&table, db)?);
+    }
+    Ok(columns)
+}
+
+/// Reads the rows of the tables of `query` and joins them.
+pub fn join(query: &SelectQuery, db: &Database) -> Result<Source> {
+    // Names of enclosing queries were replaced by their values, subqueries of the ON
+    // conditions only refer to the columns of the join
+    let scope = Scope::new(query, &[]);
+    let mut joined = Source::single_row();
+    for (i, table) in from_tables(query).iter().enumerate() {
+        let (outer, constraint) = match i.checked_sub(1).map(|i| &query.joins[i]) {
+            Some(join) => (join.outer, join.constraint.as_ref()),
+            None => (false, None),
+        };
+        let probe = match constraint {
+            Some(constraint) => index_probe(db, table, constraint, &joined.columns)?,
+            None => None,
+        };
+        // The rows of a table looked up in an index are only read when a value before it
+        // can't be looked up
+        let source = match &probe {
+            Some(probe) => {
+                db.check_privilege(Privilege::Select, Some(&probe.table_name))?;
+                Source::from_rowids(probe.table, std::iter::empty())
+            }
+            None => Source::scan(db, table.name, &table_args(table.args, db)?, &[])?,
+        };
+        let mut read = None;
+        let width = source.columns.len();
+        joined
+            .columns
+            .extend(qualified(source.columns, table.qualifier()));
+        let mut rows = vec![];
+        for left in &joined.rows {
+            let looked_up;
+            let right_rows = match &probe {
+                Some(probe) => match probe.rowids(&left[probe.left]) {
+                    Some(rowids) => {
+                        looked_up = Source::from_rowids(probe.table, rowids.into_iter()).rows;
+                        &looked_up
+                    }
+                    None => read.get_or_insert_with(|| Source::from_table(probe.table).rows),
+                },
+                None => &source.rows,
+            };
+            let mut matched = false;
+            for right in right_rows {
+                db.progress.step()?;
+                let values = left.iter().chain(right).cloned().collect::<Vec<Value>>();
+                if let Some(constraint) = constraint {
+                    let context = RowContext::new(&joined.columns, &values).with_scope(&scope);
+                    if !satisfies(std::slice::from_ref(constraint), db, &context)? {
+                        continue;
+                    }
+                }
+                matched = true;
+                rows.push(values);
+            }
+            if outer && !matched {
+                let nulls = vec![Value::Null; width];
+                rows.push(left.iter().cloned().chain(nulls).collect());
+            }
+        }
+        joined.rows = rows;
+    }
+    Ok(joined)
+}
+
+/// The lookup, for each row of the tables before it, of the rows of a table joined ON an
+/// equality between one of its UNIQUE columns and a column before it
+struct IndexProbe<'a> {
+    table_name: String,
+    table: &'a Table,
+    /// The UNIQUE column looked up
+    column: &'a Column,
+    /// Position among the columns before the table of the column whose values are looked up
+    left: usize,
+}
+
+impl IndexProbe<'_> {
+    /// The rowids of the rows whose column equals `value`, none for NULL. `None` if `value`
+    /// can't be looked up in the index, and has to be compared with every row.
+    fn rowids(&self, value: &Value) -> Option<Vec<i64>> {
+        if value.is_null() {
+            Some(vec![])
+        } else if !comparable(&self.column.index, value) {
+            None
+        } else if !self.column.may_have(value) {
+            Some(vec![])
+        } else {
+            Some(self.column.index.rowids(value))
+        }
+    }
+}
+
+/// The index lookup the rows of `table` can be joined with, ON `constraint`, to the rows of
+/// the columns `before` it: when a term of the condition equals a UNIQUE column of the table
+/// with one of the columns before it, both compared without a collation.
+fn index_probe<'a>(
+    db: &'a Database,
+    table: &FromTable,
+    constraint: &Expr,
+    before: &[SourceColumn],
+) -> Result<Option<IndexProbe<'a>>> {
+    let table_name = db.resolve_table_name(table.name)?;
+    let db_table = match db.tables.get(&table_name) {
+        Some(db_table) if table.args.is_empty() => db_table,
+        _ => return Ok(None),
+    };
+    let prefix = format!("{}.", table.qualifier());
+    let binary = |column: &SourceColumn| {
+        column
+            .collation
+            .as_ref()
+            .is_none_or(|collation| collation.eq_ignore_ascii_case("binary"))
+    };
+    let mut terms = vec![constraint];
+    while let Some(term) = terms.pop() {
+        let (left, right) = match term {
+            Expr::BinaryOp {
+                left,
+                op: BinaryOperator::And,
+                right,
+            } => {
+                terms.push(right);
+                terms.push(left);
+                continue;
+            }
+            Expr::Nested(expr) => {
+                terms.push(expr);
+                continue;
+            }
+            Expr::BinaryOp {
+                left,
+                op: BinaryOperator::Eq,
+                right,
+            } => (left.as_ref(), right.as_ref()),
+            _ => continue,
+        };
+        for (own, other) in [(left, right), (right, left)] {
+            if let (Expr::Identifier(own), Expr::Identifier(other)) = (own, other) {
+                let column = own
+                    .value
+                    .strip_prefix(&prefix)
+                    .and_then(|name| indexed_column(db_table, name));
+                let left = before
+                    .iter()
+                    .position(|column| column.name == other.value && binary(column));
+                if let (Some(column), Some(left)) = (column, left) {
+                    return Ok(Some(IndexProbe {
+                        table_name,
+                        table: db_table,
+                        column,
+                        left,
+                    }));
+                }
+            }
+        }
+    }
+    Ok(None)
+}
+
+/// How the plan of `query` shows its join, like `SCAN users AS u JOIN orders AS o ON
+/// o.user_id = u.id`, with the index a table is looked up in.
+pub fn detail(query: &SelectQuery, db: &Database) -> Result<String> {
+    let named = |table: &FromTable| match table.alias {
+        Some(alias) => format!("{} AS {}", table.name, alias),
+        None => table.name.to_string(),
+    };
+    let tables = from_tables(query);
+    let mut detail = format!("SCAN {}", named(&tables[0]));
+    let mut before = table_columns(&tables[0], db)?;
+    for (join, table) in query.joins.iter().zip(&tables[1..]) {
+        let operator = match (join.outer, &join.constraint) {
+            (true, _) => "LEFT JOIN",
+            (false, Some(_)) => "JOIN",
+            (false, None) => "CROSS JOIN",
+        };
+        detail.push_str(&format!(" {} {}", operator, named(table)));
+        if let Some(constraint) = &join.constraint {
+            detail.push_str(&format!(" ON {}", constraint));
+            if let Some(probe) = index_probe(db, table, constraint, &before)? {
+                let column = &probe.column.column_name;
+                detail.push_str(&format!(" USING INDEX ({}=?)", column));
+            }
+        }
+        before.extend(table_columns(table, db)?);
+    }
+    Ok(detail)
+}
+
+/// The number of rows the planner estimates the join of `query` produces: those of its
+/// tables multiplied, each ON condition guessed to keep as many as a range comparison, and
+/// a LEFT JOIN keeping at least the rows before it. `None` when a table is virtual.
+pub fn estimated_rows(query: &SelectQuery, db: &Database) -> Result<Option<f64>> {
+    let mut estimated = Some(1.0);
+    for (i, table) in from_tables(query).iter().enumerate() {
+        let table_name = db.resolve_table_name(table.name)?;
+        let rows = match (db.stats.get(&table_name), db.tables.get(&table_name)) {
+            (Some(stats), _) => Some(stats.rows as f64),
+            (None, Some(table)) => Some(table.rowids().len() as f64),
+            (None, None) => None,
+        };
+        let before = estimated;
+        estimated = estimated.zip(rows).map(|(left, right)| left * right);
+        if let Some(join) = i.checked_sub(1).map(|i| &query.joins[i]) {
+            if join.constraint.is_some() {
+                estimated = estimated.map(|rows| rows * DEFAULT_RANGE_SELECTIVITY);
+            }
+            if join.outer {
+                estimated = estimated.zip(before).map(|(rows, left)| rows.max(left));
+            }
+        }
+    }
+    Ok(estimated)
+}
+
+/// The error of a column name that more than one table of a join has.
+pub fn ambiguous(name: &str) -> SQLRiteError {
+    SQLRiteError::General(format!("ambiguous column name: {}", name))
+}
+
+/// The columns of `table`, named after it. Its rows aren't read.
+fn table_columns(table: &FromTable, db: &Database) -> Result<Vec<SourceColumn>> {
+    let args = table_args(table.args, db)?;
+    let columns = Source::columns_of(db, table.name, &args)?;
+    Ok(qualified(columns, table.qualifier()))
+}
+
+/// `columns` named after the table called `qualifier` they are in.
+fn qualified(columns: Vec<SourceColumn>, qualifier: &str) -> Vec<SourceColumn> {
+    columns
+        .into_iter()
+        .map(|column| SourceColumn {
+            name: format!("{}.{}", qualifier, column.name),
+            table: Some(qualifier.to_string()),
+            ..column
+        })
+        .collect()
+}
+
+/// The values of the arguments of a table-valued function, which can't refer to columns.
+fn table_args(args: &[Expr], db: &Database) -> Result<Vec<Value>> {
+    args.iter()
+        .map(|arg| eval_expr(arg, db, &RowContext::new(&[], &[])))
+        .collect()
+}
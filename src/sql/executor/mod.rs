@@ -0,0 +1,2310 @@
+pub mod aggregate;
+pub mod arena;
+pub mod compound;
+pub mod expr;
+pub mod filter;
+pub mod join;
+pub mod modify;
+pub mod pragma;
+pub mod profile;
+pub mod render;
+pub mod scope;
+pub mod source;
+pub mod trigger;
+
+use std::cmp::Ordering;
+use std::fmt;
+use std::sync::Arc;
+
+use prettytable::{Cell as PrintCell, Row as PrintRow, Table as PrintTable};
+use sqlparser::ast::{Expr, Value as AstValue};
+
+use crate::error::{Result, SQLRiteError};
+use crate::sql::collation::CollationFn;
+use crate::sql::db::database::Database;
+use crate::sql::db::memory::rows_size;
+use crate::sql::db::stats::DEFAULT_RANGE_SELECTIVITY;
+use crate::sql::db::table::DataType;
+use crate::sql::params::quote_literal;
+use crate::sql::parser::select::{names_table, OrderBy, Projection, SelectQuery};
+use crate::sql::value::{Affinity, Value};
+
+use aggregate::{bare_column, contains_aggregate, Aggregates};
+use arena::TupleArena;
+use compound::{plan_compound, select_compound};
+use expr::{check_collations, eval_expr, RowContext};
+use filter::{satisfies, scan_lists, split_selection, ScanConstraint};
+use join::{ambiguous, join};
+use profile::QueryProfile;
+use scope::{resolve, OuterRow, Scope};
+use source::{index_scan, selectivity, with_affinity, Source, SourceColumn};
+
+/// Metadata of each column of a `ResultSet`
+#[derive(Debug, PartialEq, Clone)]
+pub struct ResultColumn {
+    /// Name of the column, the `AS` alias if one was given
+    pub name: String,
+    /// Declared type of the table column it comes from, `None` for computed expressions
+    pub datatype: Option<DataType>,
+}
+
+/// The rows produced by executing a query, together with the metadata of their columns
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct ResultSet {
+    pub columns: Vec<ResultColumn>,
+    pub rows: Vec<Vec<Value>>,
+}
+
+/// Receives the rows of a query as the executor produces them
+pub trait RowSink {
+    /// Receives the columns of the result, before any row.
+    fn columns(&mut self, columns: &[ResultColumn]) -> Result<()>;
+    /// Receives a row of the result.
+    fn row(&mut self, values: Vec<Value>) -> Result<()>;
+}
+
+impl RowSink for ResultSet {
+    fn columns(&mut self, columns: &[ResultColumn]) -> Result<()> {
+        self.columns = columns.to_vec();
+        Ok(())
+    }
+
+    fn row(&mut self, values: Vec<Value>) -> Result<()> {
+        self.rows.push(values);
+        Ok(())
+    }
+}
+
+impl ResultSet {
+    /// Prints the result set as a table to standard output
+    pub fn print(&self) {
+        self.to_print_table().printstd();
+    }
+
+    fn to_print_table(&self) -> PrintTable {
+        let mut print_table = PrintTable::new();
+        print_table.add_row(PrintRow::new(
+            self.columns
+                .iter()
+                .map(|col| PrintCell::new(&col.name))
+                .collect::<Vec<PrintCell>>(),
+        ));
+        for row in &self.rows {
+            print_table.add_row(PrintRow::new(
+                row.iter()
+                    .map(|value| PrintCell::new(&value.to_string()))
+                    .collect::<Vec<PrintCell>>(),
+            ));
+        }
+        print_table
+    }
+}
+
+/// Formats the result set as the table `print` shows
+impl fmt::Display for ResultSet {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.to_print_table())
+    }
+}
+
+/// Executes a parsed SELECT query against the database and returns its result set.
+/// Rows are produced in ORDER BY order when there is one, in ROWID order otherwise,
+/// or in the order a virtual table returns them.
+pub fn execute_select(query: &SelectQuery, db: &Database) -> Result<ResultSet> {
+    Ok(select(query, db, false, &[])?.0)
+}
+
+/// Executes the query like `execute_select`, handing its rows to `sink` as they are
+/// produced instead of returning them.
+pub fn execute_select_into(
+    query: &SelectQuery,
+    db: &Database,
+    sink: &mut dyn RowSink,
+) -> Result<()> {
+    select_into(query, db, false, &[], sink)?;
+    Ok(())
+}
+
+/// Executes the subquery `query` for the rows `outer` of the queries enclosing it, whose
+/// columns it can refer to.
+pub fn execute_correlated(
+    query: &SelectQuery,
+    db: &Database,
+    outer: &[OuterRow],
+) -> Result<ResultSet> {
+    Ok(select(query, db, false, outer)?.0)
+}
+
+/// Executes the query like `execute_select`, and returns along with its result set the
+/// plan it ran with the rows each operator produced and the time spent in it.
+pub fn execute_select_profiled(
+    query: &SelectQuery,
+    db: &Database,
+) -> Result<(ResultSet, QueryProfile)> {
+    select(query, db, true, &[])
+}
+
+/// Returns the plan the query would run with, without running it.
+pub fn plan_select(query: &SelectQuery, db: &Database) -> Result<QueryProfile> {
+    if !query.compound.is_empty() {
+        return plan_compound(query, |query| plan_select(query, db));
+    }
+    let query = &resolve(query, db, &[])?;
+    let (source, constraints, residual) = open(query, db)?;
+    let mut profile = QueryProfile::default();
+    plan(query, db, &source, &constraints, &residual, &mut profile)?;
+    Ok(profile)
+}
+
+/// Reads the rows of the table of the query, returning them with the constraints of the
+/// WHERE clause they still have to be filtered with and its other terms.
+fn open(query: &SelectQuery, db: &Database) -> Result<(Source, Vec<ScanConstraint>, Vec<Expr>)> {
+    // Arguments of table-valued functions can't refer to columns
+    let args = query
+        .table_args
+        .iter()
+        .map(|arg| eval_expr(arg, db, &RowContext::new(&[], &[])))
+        .collect::<Result<Vec<Value>>>()?;
+    let (constraints, residual) = match &query.selection {
+        Some(selection) => split_selection(selection, db)?,
+        None => (vec![], vec![]),
+    };
+    let lists = scan_lists(&residual, db)?;
+    let (source, constraints) = match &query.table_name {
+        // The values of the constraints on a join take the affinity of their column, like
+        // those the rows of a table are read with
+        Some(_) if !query.joins.is_empty() => {
+            let source = join(query, db)?;
+            let constraints = constraints
+                .into_iter()
+                .map(|constraint| {
+                    let index = source.column_index(&constraint.column)?;
+                    let affinity = source.columns[index]
+                        .datatype
+                        .as_ref()
+                        .and_then(Affinity::of_column);
+                    Ok(ScanConstraint {
+                        value: match affinity {
+                            Some(affinity) => constraint.value.apply_affinity(affinity),
+                            None => constraint.value.clone(),
+                        },
+                        ..constraint
+                    })
+                })
+                .collect::<Result<Vec<ScanConstraint>>>()?;
+            (source, constraints)
+        }
+        Some(table_name) => Source::open(db, table_name, &args, &constraints, &lists)?,
+        None => (Source::single_row(), constraints),
+    };
+    Ok((source, constraints, residual))
+}
+
+/// Adds to `profile` the operators of the query, in the order rows flow through them: the
+/// scan of its table, one filter per term of its WHERE clause, the projection of its result
+/// columns and the sort of its ORDER BY. Filters of terms that aren't `column op value` are
+/// guessed to keep as many rows as a range comparison.
+fn plan(
+    query: &SelectQuery,
+    db: &Database,
+    source: &Source,
+    constraints: &[ScanConstraint],
+    residual: &[Expr],
+    profile: &mut QueryProfile,
+) -> Result<()> {
+    // The operator reading the rows of a join reads those of each of its tables
+    let joined = !query.joins.is_empty();
+    let table_name = match &query.table_name {
+        Some(table_name) if !joined => db.resolve_table_name(table_name)?,
+        _ => String::new(),
+    };
+    let mut estimated = match (db.stats.get(&table_name), db.tables.get(&table_name)) {
+        _ if joined => join::estimated_rows(query, db)?,
+        _ if query.table_name.is_none() => Some(1.0),
+        (Some(stats), _) => Some(stats.rows as f64),
+        (None, Some(_)) => Some(source.rows.len() as f64),
+        (None, None) => None,
+    };
+    let virtual_table = !db.tables.contains_key(&table_name)
+        && (db.vtabs.contains_table(&table_name) || db.vtabs.contains_module(&table_name));
+    let lists = scan_lists(residual, db)?;
+    let converted = db
+        .tables
+        .get(&table_name)
+        .map(|table| (table, with_affinity(table, constraints, &lists)));
+    let indexed = converted
+        .as_ref()
+        .and_then(|(table, (constraints, lists))| index_scan(table, constraints, lists));
+    let detail = match indexed {
+        _ if joined => join::detail(query, db)?,
+        _ if query.table_name.is_none() => "SCAN CONSTANT ROW".to_string(),
+        _ if virtual_table => format!("SCAN VIRTUAL TABLE {}", table_name),
+        // A lookup in a unique index reads a row per value at most
+        Some(scan) => {
+            if let Some(max_rows) = scan.max_rows() {
+                estimated = estimated.map(|rows| rows.min(max_rows));
+            }
+            scan.detail(&table_name)
+        }
+        None => format!("SCAN {}", table_name),
+    };
+    profile.add(detail, estimated);
+    for constraint in constraints {
+        let selectivity = selectivity(db, &table_name, &source.columns, constraint)?;
+        estimated = estimated.map(|rows| rows * selectivity);
+        profile.add(
+            format!(
+                "FILTER {} {} {}",
+                constraint.column,
+                constraint.op,
+                quote_literal(&constraint.value)
+            ),
+            estimated,
+        );
+    }
+    for term in residual {
+        estimated = estimated.map(|rows| rows * DEFAULT_RANGE_SELECTIVITY);
+        profile.add(format!("FILTER {}", term), estimated);
+    }
+    let outputs = query
+        .projection
+        .iter()
+        .map(|item| match item {
+            Projection::Wildcard => "*".to_string(),
+            Projection::TableWildcard(qualifier) => format!("{}.*", qualifier),
+            Projection::Expr { expr, .. } => expr.to_string(),
+        })
+        .collect::<Vec<String>>();
+    // Aggregate functions reduce the rows to one
+    if query.projection.iter().any(|item| match item {
+        Projection::Expr { expr, .. } => contains_aggregate(expr),
+        Projection::Wildcard | Projection::TableWildcard(_) => false,
+    }) {
+        estimated = estimated.map(|rows| rows.min(1.0));
+    }
+    profile.add(format!("PROJECT {}", outputs.join(", ")), estimated);
+    if !query.order_by.is_empty() {
+        let terms = query
+            .order_by
+            .iter()
+            .map(|term| {
+                let mut term_text = term.expr.to_string();
+                if let Some(collation) = &term.collation {
+                    term_text.push_str(&format!(" COLLATE {}", collation));
+                }
+                if !term.asc {
+                    term_text.push_str(" DESC");
+                }
+                match term.nulls_first {
+                    Some(true) => term_text.push_str(" NULLS FIRST"),
+                    Some(false) => term_text.push_str(" NULLS LAST"),
+                    None => {}
+                }
+                term_text
+            })
+            .collect::<Vec<String>>();
+        profile.add(format!("SORT BY {}", terms.join(", ")), estimated);
+    }
+    Ok(())
+}
+
+/// Runs the query, recording what each operator of its plan did, and timing them if `timed`.
+fn select(
+    query: &SelectQuery,
+    db: &Database,
+    timed: bool,
+    outer: &[OuterRow],
+) -> Result<(ResultSet, QueryProfile)> {
+    let mut result = ResultSet::default();
+    let profile = select_into(query, db, timed, outer, &mut result)?;
+    Ok((result, profile))
+}
+
+/// Executes the query, handing its rows to `sink` as they are produced. Those of a query
+/// without ORDER BY nor aggregate functions are handed over one at a time while the rows
+/// of the table are read; the others once every row is read.
+fn select_into(
+    query: &SelectQuery,
+    db: &Database,
+    timed: bool,
+    outer: &[OuterRow],
+    sink: &mut dyn RowSink,
+) -> Result<QueryProfile> {
+    if !query.compound.is_empty() {
+        // Compound operators need the rows of each side before any row of the result
+        let (result, profile) =
+            select_compound(query, db, timed, |query| select(query, db, timed, outer))?;
+        sink.columns(&result.columns)?;
+        for values in result.rows {
+            sink.row(values)?;
+        }
+        return Ok(profile);
+    }
+    let query = &resolve(query, db, outer)?;
+    // An unknown collation is reported even when no row is compared with it
+    let projected = query.projection.iter().filter_map(|item| match item {
+        Projection::Expr { expr, .. } => Some(expr),
+        Projection::Wildcard | Projection::TableWildcard(_) => None,
+    });
+    for expr in query.selection.iter().chain(projected) {
+        check_collations(expr, db)?;
+    }
+    let scope = Scope::new(query, outer);
+    let mut profile = QueryProfile {
+        timed,
+        ..QueryProfile::default()
+    };
+    let started = profile.now();
+    let (mut source, constraints, residual) = open(query, db)?;
+    plan(query, db, &source, &constraints, &residual, &mut profile)?;
+    let mut id = 1;
+    profile.begin(id);
+    profile.record(id, started, source.rows.len());
+
+    // Constraints the source didn't check are checked one at a time, so that each filter
+    // is measured
+    for constraint in &constraints {
+        id += 1;
+        profile.begin(id);
+        let started = profile.now();
+        source.filter(db, std::slice::from_ref(constraint))?;
+        profile.record(id, started, source.rows.len());
+    }
+    let first_term = id + 1;
+    let project = first_term + residual.len();
+    for term in first_term..=project {
+        profile.begin(term);
+    }
+
+    // The rows read, their tuples and the result are reserved until the result is returned
+    let source_bytes = rows_size(&source.rows);
+    let mut memory = db.memory.reserve(source_bytes)?;
+    profile.record_memory(1, source_bytes);
+
+    // Expanding the SELECT list into one output expression per result column
+    let mut columns: Vec<ResultColumn> = vec![];
+    let mut outputs: Vec<Expr> = vec![];
+    for item in &query.projection {
+        match item {
+            Projection::Wildcard if query.table_name.is_none() => {
+                return Err(SQLRiteError::General("no tables specified".to_string()))
+            }
+            Projection::Wildcard | Projection::TableWildcard(_) => {
+                let expanded = source.columns.iter().filter(|col| match item {
+                    Projection::TableWildcard(qualifier) => col
+                        .table
+                        .as_ref()
+                        .is_some_and(|table| names_table(qualifier, table, None)),
+                    _ => true,
+                });
+                for col in expanded.filter(|col| !col.hidden) {
+                    // Tables called by the same name in a join have columns with the same name
+                    if source
+                        .columns
+                        .iter()
+                        .filter(|other| other.name == col.name)
+                        .count()
+                        > 1
+                    {
+                        return Err(ambiguous(&col.name));
+                    }
+                    columns.push(ResultColumn {
+                        name: col.unqualified_name().to_string(),
+                        datatype: col.datatype.clone(),
+                    });
+                    outputs.push(Expr::Identifier(col.name.as_str().into()));
+                }
+            }
+            Projection::Expr { expr, alias } => {
+                // A column of a join is named without its table
+                let (name, datatype) = match expr {
+                    Expr::Identifier(ident) => {
+                        let column = &source.columns[source.column_index(&ident.value)?];
+                        let name = column.table.as_ref().map(|_| column.unqualified_name());
+                        (name.map(str::to_string), column.datatype.clone())
+                    }
+                    _ => (None, None),
+                };
+                columns.push(ResultColumn {
+                    name: alias.clone().or(name).unwrap_or_else(|| expr.to_string()),
+                    datatype,
+                });
+                outputs.push(expr.clone());
+            }
+        }
+    }
+
+    let mut sort_keys = query
+        .order_by
+        .iter()
+        .enumerate()
+        .map(|(i, term)| sort_key(i, &term.expr, query, &outputs))
+        .collect::<Result<Vec<Expr>>>()?;
+    // Without GROUP BY, the other columns of a query calling aggregate functions would take
+    // the values of an arbitrary row
+    if outputs.iter().any(contains_aggregate) {
+        if let Some(column) = outputs.iter().find_map(bare_column) {
+            return Err(SQLRiteError::NotImplemented(format!(
+                "column {} must be used in an aggregate function, GROUP BY isn't supported yet.",
+                column
+            )));
+        }
+    }
+    // A query calling aggregate functions returns one row, evaluated once every row is read
+    let mut aggregates = Aggregates::extract(outputs.iter_mut().chain(sort_keys.iter_mut()))?;
+    let collations = query
+        .order_by
+        .iter()
+        .zip(&sort_keys)
+        .map(|(term, key)| order_by_collation(term.collation.as_ref(), key, db, &source.columns))
+        .collect::<Result<Vec<Arc<CollationFn>>>>()?;
+    sink.columns(&columns)?;
+    let streamed = aggregates.is_none() && query.order_by.is_empty();
+
+    // Each row is kept along with its ORDER BY keys until it is sorted, as one tuple of
+    // the statement's arena
+    let width = outputs.len() + query.order_by.len();
+    let mut tuples = TupleArena::with_capacity(width, source.rows.len());
+    let mut last_row = None;
+    'rows: for values in &source.rows {
+        db.progress.step()?;
+        let context = RowContext::new(&source.columns, values).with_scope(&scope);
+        for (i, term) in residual.iter().enumerate() {
+            let started = profile.now();
+            let kept = satisfies(std::slice::from_ref(term), db, &context)?;
+            profile.record(first_term + i, started, kept as usize);
+            if !kept {
+                continue 'rows;
+            }
+        }
+        let started = profile.now();
+        if let Some(aggregates) = &mut aggregates {
+            aggregates.accumulate(db, &context)?;
+            last_row = Some(values);
+            profile.record(project, started, 0);
+            continue;
+        }
+        if streamed {
+            let values = outputs
+                .iter()
+                .map(|expr| eval_expr(expr, db, &context))
+                .collect::<Result<Vec<Value>>>()?;
+            profile.record(project, started, 1);
+            sink.row(values)?;
+            continue;
+        }
+        tuples.push(
+            outputs
+                .iter()
+                .chain(&sort_keys)
+                .map(|expr| eval_expr(expr, db, &context)),
+        )?;
+        profile.record(project, started, 1);
+        memory.resize(source_bytes + tuples.bytes())?;
+    }
+    if let Some(aggregates) = aggregates {
+        // Columns that aren't aggregated are those of the last row, NULL without rows
+        let started = profile.now();
+        let (aggregate_columns, aggregate_values) = aggregates.finish()?;
+        let mut columns = source.columns.clone();
+        columns.extend(aggregate_columns);
+        let mut values = match last_row {
+            Some(values) => values.clone(),
+            None => vec![Value::Null; source.columns.len()],
+        };
+        values.extend(aggregate_values);
+        let context = RowContext::new(&columns, &values).with_scope(&scope);
+        tuples.push(
+            outputs
+                .iter()
+                .chain(&sort_keys)
+                .map(|expr| eval_expr(expr, db, &context)),
+        )?;
+        profile.record(project, started, 1);
+    }
+    profile.record_memory(project, tuples.bytes());
+
+    memory.resize(source_bytes + tuples.bytes() + tuples.len() * std::mem::size_of::<usize>())?;
+    let mut order: Vec<usize> = (0..tuples.len()).collect();
+    if !query.order_by.is_empty() {
+        let sort = project + 1;
+        profile.begin(sort);
+        let started = profile.now();
+        let keys = |index: usize| &tuples.get(index)[outputs.len()..];
+        order.sort_by(|a, b| compare_keys(&query.order_by, &collations, keys(*a), keys(*b)));
+        profile.record(sort, started, order.len());
+        profile.record_memory(sort, order.len() * std::mem::size_of::<usize>());
+    }
+
+    for values in tuples.into_rows(&order, outputs.len()) {
+        sink.row(values)?;
+    }
+    Ok(profile)
+}
+
+/// The expression the rows are sorted by for the `i`th ORDER BY term `expr` of `query`,
+/// whose result columns are `outputs`. Like in SQLite, an integer N stands for the Nth
+/// result column, and a name for the result column it is the alias of before the column
+/// of the table.
+fn sort_key(i: usize, expr: &Expr, query: &SelectQuery, outputs: &[Expr]) -> Result<Expr> {
+    match expr {
+        Expr::Value(AstValue::Number(n, _)) => match n.parse::<usize>() {
+            Ok(n) if n >= 1 && n <= outputs.len() => Ok(outputs[n - 1].clone()),
+            _ => Err(SQLRiteError::General(format!(
+                "{} ORDER BY term out of range - should be between 1 and {}",
+                ordinal(i + 1),
+                outputs.len()
+            ))),
+        },
+        Expr::Identifier(ident) => {
+            let aliased = query.projection.iter().find_map(|item| match item {
+                Projection::Expr {
+                    expr,
+                    alias: Some(alias),
+                } if alias.eq_ignore_ascii_case(&ident.value) => Some(expr),
+                _ => None,
+            });
+            Ok(aliased.unwrap_or(expr).clone())
+        }
+        expr => Ok(expr.clone()),
+    }
+}
+
+/// `n` written as an English ordinal, like `2nd`, as SQLite numbers the terms in errors.
+fn ordinal(n: usize) -> String {
+    let suffix = match (n % 10, n % 100) {
+        (_, 11..=13) => "th",
+        (1, _) => "st",
+        (2, _) => "nd",
+        (3, _) => "rd",
+        _ => "th",
+    };
+    format!("{}{}", n, suffix)
+}
+
+/// Compares the keys `a` and `b` of two rows, a value per term of `order_by`, sorted with
+/// the collating sequences `collations`.
+fn compare_keys(
+    order_by: &[OrderBy],
+    collations: &[Arc<CollationFn>],
+    a: &[Value],
+    b: &[Value],
+) -> Ordering {
+    for (i, term) in order_by.iter().enumerate() {
+        // NULLS FIRST and NULLS LAST place NULLs whatever the direction
+        let ordering = match (a[i].is_null(), b[i].is_null(), term.nulls_first) {
+            (true, false, Some(first)) | (false, true, Some(first)) => {
+                if first == a[i].is_null() {
+                    Ordering::Less
+                } else {
+                    Ordering::Greater
+                }
+            }
+            _ => {
+                let ordering = a[i].compare(&b[i], collations[i].as_ref());
+                if term.asc {
+                    ordering
+                } else {
+                    ordering.reverse()
+                }
+            }
+        };
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+    }
+    Ordering::Equal
+}
+
+/// Resolves the collating sequence an ORDER BY term is sorted with: the one given in its
+/// COLLATE clause, otherwise the one declared for the column it refers to, otherwise BINARY.
+fn order_by_collation(
+    collation: Option<&String>,
+    expr: &Expr,
+    db: &Database,
+    columns: &[SourceColumn],
+) -> Result<Arc<CollationFn>> {
+    if let Some(collation) = collation {
+        return db.collations.get(collation);
+    }
+    if let Expr::Identifier(ident) = expr {
+        if let Some(collation) = columns
+            .iter()
+            .find(|col| col.name == ident.value)
+            .and_then(|col| col.collation.as_ref())
+        {
+            return db.collations.get(collation);
+        }
+    }
+    db.collations.get("binary")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sql::process_command;
+
+    fn select(db: &Database, sql: &str) -> Result<ResultSet> {
+        let statement = crate::sql::parse_statement(sql)?;
+        execute_select(&SelectQuery::new(&statement)?, db)
+    }
+
+    #[test]
+    fn execute_select_test() {
+        let mut db = Database::new("tempdb".to_string());
+        process_command(
+            "CREATE TABLE users (id INTEGER PRIMARY KEY, name TEXT);",
+            &mut db,
+        )
+        .unwrap();
+        process_command("INSERT INTO users (name) VALUES ('josh');", &mut db).unwrap();
+        process_command("INSERT INTO users (name) VALUES ('mary');", &mut db).unwrap();
+
+        let result = select(&db, "SELECT * FROM users;").unwrap();
+        assert_eq!(result.columns.len(), 2);
+        assert_eq!(result.columns[0].name, "id");
+        assert_eq!(result.columns[0].datatype, Some(DataType::Integer));
+        assert_eq!(
+            result.rows,
+            vec![
+                vec![Value::Integer(1), Value::Text("josh".to_string())],
+                vec![Value::Integer(2), Value::Text("mary".to_string())],
+            ]
+        );
+
+        let result = select(&db, "SELECT name AS username, id FROM users;").unwrap();
+        assert_eq!(result.columns[0].name, "username");
+        assert_eq!(result.columns[0].datatype, Some(DataType::Text));
+        assert_eq!(
+            result.rows[1],
+            vec![Value::Text("mary".to_string()), Value::Integer(2)]
+        );
+    }
+
+    #[test]
+    fn execute_select_builtin_function_test() {
+        let mut db = Database::new("tempdb".to_string());
+        process_command(
+            "CREATE TABLE users (id INTEGER PRIMARY KEY, name TEXT);",
+            &mut db,
+        )
+        .unwrap();
+        process_command("INSERT INTO users (name) VALUES ('  josh ');", &mut db).unwrap();
+
+        let result = select(
+            &db,
+            "SELECT upper(trim(name)), length(name), substr(trim(name), 2, 2), substring(name FROM 3 FOR 2), trim(LEADING ' ' FROM name) FROM users;",
+        )
+        .unwrap();
+        assert_eq!(
+            result.rows[0],
+            vec![
+                Value::Text("JOSH".to_string()),
+                Value::Integer(7),
+                Value::Text("os".to_string()),
+                Value::Text("jo".to_string()),
+                Value::Text("josh ".to_string()),
+            ]
+        );
+
+        let result = select(
+            &db,
+            "SELECT length(randomblob(8)), typeof(random()), X'0aFF' FROM users;",
+        )
+        .unwrap();
+        assert_eq!(
+            result.rows[0],
+            vec![
+                Value::Integer(8),
+                Value::Text("integer".to_string()),
+                Value::Blob(vec![0x0a, 0xff]),
+            ]
+        );
+        assert!(select(&db, "SELECT X'0aF' FROM users;").is_err());
+    }
+
+    #[test]
+    fn execute_select_expression_test() {
+        let mut db = Database::new("tempdb".to_string());
+        let result = select(&db, "SELECT 1 + 1;").unwrap();
+        assert_eq!(result.columns[0].name, "1 + 1");
+        assert_eq!(result.rows, vec![vec![Value::Integer(2)]]);
+
+        let result = select(
+            &db,
+            "SELECT 2 + 3 * 4, (2 + 3) * 4, 7 / 2, 7.0 / 2, 7 % 3, -7 % 3, 5.5 % 2, 1 / 0, 1 % 0, \
+             9223372036854775807 + 1, -(3 - 5), 6 & 3, 6 | 3, NULL + 1, '3' + 1, '1.5' * 2;",
+        )
+        .unwrap();
+        assert_eq!(
+            result.rows[0],
+            vec![
+                Value::Integer(14),
+                Value::Integer(20),
+                Value::Integer(3),
+                Value::Real(3.5),
+                Value::Integer(1),
+                Value::Integer(-1),
+                Value::Real(1.0),
+                Value::Null,
+                Value::Null,
+                Value::Real(9223372036854775808.0),
+                Value::Integer(2),
+                Value::Integer(2),
+                Value::Integer(7),
+                Value::Null,
+                Value::Integer(4),
+                Value::Real(3.0),
+            ]
+        );
+        let result = select(
+            &db,
+            "SELECT -9223372036854775808, -9223372036854775807 - 1, \
+             (-9223372036854775807 - 1) % 7, abs(-9223372036854775807);",
+        )
+        .unwrap();
+        assert_eq!(
+            result.rows[0],
+            vec![
+                Value::Integer(i64::MIN),
+                Value::Integer(i64::MIN),
+                Value::Integer(-1),
+                Value::Integer(i64::MAX),
+            ]
+        );
+        for sql in [
+            "SELECT -9223372036854775808 % -1;",
+            "SELECT -9223372036854775808.0 % -1;",
+            "SELECT abs(-9223372036854775808);",
+        ] {
+            assert_eq!(
+                select(&db, sql).unwrap_err().to_string(),
+                "General error: integer overflow"
+            );
+        }
+        let result = select(
+            &db,
+            "SELECT 'a' || 'b' || 1, 'a' || NULL, 1 + 1 = 2 AND NOT 2 * 2 > 5;",
+        )
+        .unwrap();
+        assert_eq!(
+            result.rows[0],
+            vec![
+                Value::Text("ab1".to_string()),
+                Value::Null,
+                Value::Integer(1)
+            ]
+        );
+        assert!(select(&db, "SELECT *;").is_err());
+
+        process_command(
+            "CREATE TABLE users (id INTEGER PRIMARY KEY, name TEXT, age INTEGER);",
+            &mut db,
+        )
+        .unwrap();
+        process_command(
+            "INSERT INTO users (name, age) VALUES ('josh', 30), ('mary', 20);",
+            &mut db,
+        )
+        .unwrap();
+        let result = select(
+            &db,
+            "SELECT name || ' (' || (age + 1) || ')' AS label FROM users WHERE age * 2 > 50;",
+        )
+        .unwrap();
+        assert_eq!(
+            result.rows,
+            vec![vec![Value::Text("josh (31)".to_string())]]
+        );
+    }
+
+    #[test]
+    fn execute_select_null_test() {
+        let mut db = Database::new("tempdb".to_string());
+        process_command(
+            "CREATE TABLE users (id INTEGER PRIMARY KEY, name TEXT, nick TEXT NOT NULL, age INTEGER);",
+            &mut db,
+        )
+        .unwrap();
+        process_command(
+            "INSERT INTO users (nick, name, age) VALUES ('jo', 'josh', 30), ('Null', NULL, NULL);",
+            &mut db,
+        )
+        .unwrap();
+        process_command("INSERT INTO users (nick) VALUES ('ma');", &mut db).unwrap();
+        let ids = |sql: &str| {
+            select(&db, sql)
+                .unwrap()
+                .rows
+                .into_iter()
+                .map(|row| row[0].clone())
+                .collect::<Vec<Value>>()
+        };
+        // NULL is stored as NULL, in TEXT columns too, and isn't the text 'Null'
+        assert_eq!(
+            ids("SELECT id FROM users WHERE name IS NULL;"),
+            vec![Value::Integer(2), Value::Integer(3)]
+        );
+        assert_eq!(
+            ids("SELECT id FROM users WHERE name IS NOT NULL;"),
+            vec![Value::Integer(1)]
+        );
+        assert_eq!(
+            ids("SELECT name FROM users WHERE nick = 'Null';"),
+            vec![Value::Null]
+        );
+        assert!(ids("SELECT id FROM users WHERE name = NULL OR NOT name = 'josh';").is_empty());
+        assert_eq!(
+            select(
+                &db,
+                "SELECT NULL = NULL, NOT NULL, NULL OR 1, NULL AND 0, NULL IS NULL, 1 IS NOT NULL;"
+            )
+            .unwrap()
+            .rows[0],
+            vec![
+                Value::Null,
+                Value::Null,
+                Value::Integer(1),
+                Value::Integer(0),
+                Value::Integer(1),
+                Value::Integer(1),
+            ]
+        );
+
+        // NOT NULL columns refuse NULL, given or missing
+        assert!(process_command("INSERT INTO users (name) VALUES ('mary');", &mut db).is_err());
+        assert!(process_command("INSERT INTO users (nick) VALUES (NULL);", &mut db).is_err());
+
+        // A row with no value but NULLs is still a row
+        process_command("CREATE TABLE notes (body TEXT, stars INTEGER);", &mut db).unwrap();
+        process_command(
+            "INSERT INTO notes (body, stars) VALUES (NULL, NULL), ('hi', 2);",
+            &mut db,
+        )
+        .unwrap();
+        assert_eq!(
+            select(&db, "SELECT body IS NULL, stars FROM notes;")
+                .unwrap()
+                .rows,
+            vec![
+                vec![Value::Integer(1), Value::Null],
+                vec![Value::Integer(0), Value::Integer(2)],
+            ]
+        );
+    }
+
+    #[test]
+    fn execute_select_aggregate_test() {
+        let mut db = Database::new("tempdb".to_string());
+        process_command(
+            "CREATE TABLE scores (id INTEGER PRIMARY KEY, player TEXT, score INTEGER);",
+            &mut db,
+        )
+        .unwrap();
+        let result = select(
+            &db,
+            "SELECT count(*), count(score), sum(score), total(score), avg(score), max(score) FROM scores;",
+        )
+        .unwrap();
+        assert_eq!(
+            result.rows,
+            vec![vec![
+                Value::Integer(0),
+                Value::Integer(0),
+                Value::Null,
+                Value::Real(0.0),
+                Value::Null,
+                Value::Null,
+            ]]
+        );
+
+        process_command(
+            "INSERT INTO scores (player, score) VALUES ('ann', 10), ('bob', NULL), ('cid', 20), ('ann', 10);",
+            &mut db,
+        )
+        .unwrap();
+        // NULLs are left out of every aggregate but count(*)
+        let result = select(
+            &db,
+            "SELECT count(*), count(score), COUNT(DISTINCT player), sum(score), avg(score), \
+             min(score), max(player), max(score) - min(score) AS spread FROM scores;",
+        )
+        .unwrap();
+        assert_eq!(result.columns[0].name, "count(*)");
+        assert_eq!(result.columns[7].name, "spread");
+        assert_eq!(
+            result.rows,
+            vec![vec![
+                Value::Integer(4),
+                Value::Integer(3),
+                Value::Integer(3),
+                Value::Integer(40),
+                Value::Real(40.0 / 3.0),
+                Value::Integer(10),
+                Value::Text("cid".to_string()),
+                Value::Integer(10),
+            ]]
+        );
+        let result = select(
+            &db,
+            "SELECT count(*), sum(score) FROM scores WHERE score IS NULL;",
+        )
+        .unwrap();
+        assert_eq!(result.rows, vec![vec![Value::Integer(1), Value::Null]]);
+        // Columns that aren't aggregated would come from an arbitrary row without GROUP BY
+        for sql in [
+            "SELECT player, count(*) FROM scores WHERE score > 5;",
+            "SELECT *, count(*) FROM scores;",
+            "SELECT upper(player) || count(*) FROM scores;",
+            "SELECT player, count(*) FROM scores GROUP BY player HAVING count(*) > 1;",
+        ] {
+            match select(&db, sql) {
+                Err(SQLRiteError::NotImplemented(_)) => {}
+                result => panic!("{} wasn't refused: {:?}", sql, result),
+            }
+        }
+        let result = select(&db, "SELECT count(*) + max(score) FROM scores;").unwrap();
+        assert_eq!(result.rows, vec![vec![Value::Integer(24)]]);
+        // min and max of several values are the scalar functions
+        let result = select(&db, "SELECT min(score, 15) FROM scores WHERE id < 3;").unwrap();
+        assert_eq!(
+            result.rows,
+            vec![vec![Value::Integer(10)], vec![Value::Null]]
+        );
+        let result = select(&db, "SELECT count(*);").unwrap();
+        assert_eq!(result.rows, vec![vec![Value::Integer(1)]]);
+
+        assert!(select(&db, "SELECT id FROM scores WHERE count(*) > 1;").is_err());
+        assert!(select(&db, "SELECT sum(count(*)) FROM scores;").is_err());
+        assert!(select(&db, "SELECT sum(*) FROM scores;").is_err());
+        process_command(
+            "INSERT INTO scores (player, score) VALUES ('dan', 2147483647), ('eve', 2147483647);",
+            &mut db,
+        )
+        .unwrap();
+        let result = select(&db, "SELECT sum(score) FROM scores;").unwrap();
+        assert_eq!(result.rows, vec![vec![Value::Integer(4294967334)]]);
+    }
+
+    #[test]
+    fn execute_select_string_functions_test() {
+        let mut db = Database::new("tempdb".to_string());
+        process_command(
+            "CREATE TABLE users (id INTEGER PRIMARY KEY, name TEXT, email TEXT);",
+            &mut db,
+        )
+        .unwrap();
+        process_command(
+            "INSERT INTO users (name, email) VALUES ('  Josh ', 'josh@example.com'), ('mary', NULL);",
+            &mut db,
+        )
+        .unwrap();
+        let result = select(
+            &db,
+            "SELECT UPPER(TRIM(name)), length(name), substr(email, instr(email, '@') + 1), \
+             replace(lower(email), 'example', 'test'), POSITION('@' IN email) FROM users ORDER BY id;",
+        )
+        .unwrap();
+        assert_eq!(
+            result.rows,
+            vec![
+                vec![
+                    Value::Text("JOSH".to_string()),
+                    Value::Integer(7),
+                    Value::Text("example.com".to_string()),
+                    Value::Text("josh@test.com".to_string()),
+                    Value::Integer(5),
+                ],
+                vec![
+                    Value::Text("MARY".to_string()),
+                    Value::Integer(4),
+                    Value::Null,
+                    Value::Null,
+                    Value::Null,
+                ],
+            ]
+        );
+
+        let result = select(
+            &db,
+            "SELECT id FROM users WHERE lower(trim(name)) = 'josh' AND SUBSTRING(email FROM 1 FOR 4) = 'josh';",
+        )
+        .unwrap();
+        assert_eq!(result.rows, vec![vec![Value::Integer(1)]]);
+        let result = select(&db, "SELECT TRIM(LEADING ' ' FROM max(name)) FROM users;").unwrap();
+        assert_eq!(result.rows, vec![vec![Value::Text("mary".to_string())]]);
+        assert!(select(&db, "SELECT substr(name) FROM users;").is_err());
+        assert!(select(&db, "SELECT upper(name, email) FROM users;").is_err());
+    }
+
+    #[test]
+    fn execute_select_cast_test() {
+        let mut db = Database::new("tempdb".to_string());
+        process_command(
+            "CREATE TABLE users (id INTEGER PRIMARY KEY, name TEXT, score REAL, active BOOL);",
+            &mut db,
+        )
+        .unwrap();
+        process_command(
+            "INSERT INTO users (name, score, active) VALUES ('5', '1.5', 'true'), (7, 2, false);",
+            &mut db,
+        )
+        .unwrap();
+        let result = select(
+            &db,
+            "SELECT CAST(score AS INTEGER), CAST(id AS TEXT) || name, CAST('3.0' AS NUMERIC), \
+             CAST(active AS TEXT), CAST(NULL AS REAL) FROM users ORDER BY id;",
+        )
+        .unwrap();
+        assert_eq!(
+            result.rows,
+            vec![
+                vec![
+                    Value::Integer(1),
+                    Value::Text("15".to_string()),
+                    Value::Integer(3),
+                    Value::Text("true".to_string()),
+                    Value::Null,
+                ],
+                vec![
+                    Value::Integer(2),
+                    Value::Text("27".to_string()),
+                    Value::Integer(3),
+                    Value::Text("false".to_string()),
+                    Value::Null,
+                ],
+            ]
+        );
+
+        // Constants compared with a column take the type of the column, constants
+        // compared together don't
+        let ids = |sql: &str| {
+            select(&db, sql)
+                .unwrap()
+                .rows
+                .into_iter()
+                .map(|row| row[0].clone())
+                .collect::<Vec<Value>>()
+        };
+        assert_eq!(
+            ids("SELECT id FROM users WHERE id = '2';"),
+            vec![Value::Integer(2)]
+        );
+        assert_eq!(
+            ids("SELECT id FROM users WHERE name = 7;"),
+            vec![Value::Integer(2)]
+        );
+        assert_eq!(
+            ids("SELECT id FROM users WHERE score > '1.8' AND active = 'false';"),
+            vec![Value::Integer(2)]
+        );
+        assert_eq!(
+            ids("SELECT id FROM users WHERE id IN ('1', '2') AND score BETWEEN '1' AND '2';"),
+            vec![Value::Integer(1), Value::Integer(2)]
+        );
+        assert_eq!(
+            ids("SELECT id FROM users WHERE CAST(name AS INTEGER) = 5;"),
+            vec![Value::Integer(1)]
+        );
+        assert_eq!(
+            ids("SELECT 1 = '1', CAST('1' AS INTEGER) = '1';"),
+            vec![Value::Integer(0)]
+        );
+        let plan = crate::sql::process_query(
+            "EXPLAIN QUERY PLAN SELECT * FROM users WHERE id IN ('1', '2');",
+            &db,
+        )
+        .unwrap();
+        assert_eq!(
+            plan.rows[0][3],
+            Value::Text("SEARCH users USING INDEX (id=?)".to_string())
+        );
+
+        // Values that aren't those of the column are still refused
+        assert!(process_command("INSERT INTO users (score) VALUES ('high');", &mut db).is_err());
+        assert!(process_command("INSERT INTO users (active) VALUES (1);", &mut db).is_err());
+    }
+
+    #[test]
+    fn execute_select_null_functions_test() {
+        let mut db = Database::new("tempdb".to_string());
+        process_command(
+            "CREATE TABLE users (id INTEGER PRIMARY KEY, name TEXT, nick TEXT, age INTEGER);",
+            &mut db,
+        )
+        .unwrap();
+        let result = select(
+            &db,
+            "SELECT coalesce(sum(age), 0), IFNULL(max(name), 'none') FROM users;",
+        )
+        .unwrap();
+        assert_eq!(
+            result.rows,
+            vec![vec![Value::Integer(0), Value::Text("none".to_string())]]
+        );
+
+        process_command(
+            "INSERT INTO users (name, nick, age) VALUES ('josh', NULL, 0), (NULL, 'mo', 41), (NULL, NULL, NULL);",
+            &mut db,
+        )
+        .unwrap();
+        let result = select(
+            &db,
+            "SELECT COALESCE(nick, name, 'anonymous'), ifnull(age, -1), NULLIF(age, 0), nullif(name, 'josh') \
+             FROM users ORDER BY id;",
+        )
+        .unwrap();
+        assert_eq!(
+            result.rows,
+            vec![
+                vec![
+                    Value::Text("josh".to_string()),
+                    Value::Integer(0),
+                    Value::Null,
+                    Value::Null,
+                ],
+                vec![
+                    Value::Text("mo".to_string()),
+                    Value::Integer(41),
+                    Value::Integer(41),
+                    Value::Null,
+                ],
+                vec![
+                    Value::Text("anonymous".to_string()),
+                    Value::Integer(-1),
+                    Value::Null,
+                    Value::Null,
+                ],
+            ]
+        );
+        let result = select(&db, "SELECT id FROM users WHERE coalesce(age, 0) < 10;").unwrap();
+        assert_eq!(
+            result.rows,
+            vec![vec![Value::Integer(1)], vec![Value::Integer(3)]]
+        );
+
+        // The arguments after the first that isn't NULL aren't evaluated
+        let overflow = "abs(-9223372036854775807 - 1)";
+        assert!(select(&db, &format!("SELECT {};", overflow)).is_err());
+        let result = select(
+            &db,
+            &format!(
+                "SELECT coalesce(NULL, 1, {}), ifnull(2, {});",
+                overflow, overflow
+            ),
+        )
+        .unwrap();
+        assert_eq!(
+            result.rows,
+            vec![vec![Value::Integer(1), Value::Integer(2)]]
+        );
+        assert!(select(&db, &format!("SELECT coalesce(NULL, {});", overflow)).is_err());
+
+        assert!(select(&db, "SELECT coalesce(name) FROM users;").is_err());
+        assert!(select(&db, "SELECT ifnull(name, nick, 'x') FROM users;").is_err());
+        assert!(select(&db, "SELECT nullif(name) FROM users;").is_err());
+    }
+
+    #[test]
+    fn execute_select_compound_test() {
+        let mut db = Database::new("tempdb".to_string());
+        process_command(
+            "CREATE TABLE users (id INTEGER PRIMARY KEY, name TEXT);",
+            &mut db,
+        )
+        .unwrap();
+        process_command(
+            "CREATE TABLE admins (id INTEGER PRIMARY KEY, name TEXT);",
+            &mut db,
+        )
+        .unwrap();
+        process_command(
+            "INSERT INTO users (name) VALUES ('josh'), ('mary'), ('mary'), (NULL);",
+            &mut db,
+        )
+        .unwrap();
+        process_command(
+            "INSERT INTO admins (name) VALUES ('mary'), ('ana'), (NULL);",
+            &mut db,
+        )
+        .unwrap();
+        let names = |sql: &str| {
+            select(&db, sql)
+                .unwrap()
+                .rows
+                .into_iter()
+                .map(|row| row[0].clone())
+                .collect::<Vec<Value>>()
+        };
+        let text = |name: &str| Value::Text(name.to_string());
+
+        // Without ALL, duplicates are left out, NULLs being equal to each other
+        assert_eq!(
+            names("SELECT name FROM users UNION SELECT name FROM admins ORDER BY name;"),
+            vec![Value::Null, text("ana"), text("josh"), text("mary")]
+        );
+        assert_eq!(
+            names("SELECT name FROM users UNION ALL SELECT name FROM admins;").len(),
+            7
+        );
+        assert_eq!(
+            names("SELECT name FROM users INTERSECT SELECT name FROM admins ORDER BY 1 DESC;"),
+            vec![text("mary"), Value::Null]
+        );
+        assert_eq!(
+            names("SELECT name FROM users EXCEPT SELECT name FROM admins;"),
+            vec![text("josh")]
+        );
+        // Left to right, and named after the first SELECT
+        let result = select(
+            &db,
+            "SELECT name AS who FROM users UNION SELECT 'zoe' EXCEPT SELECT name FROM admins ORDER BY who;",
+        )
+        .unwrap();
+        assert_eq!(result.columns[0].name, "who");
+        assert_eq!(result.rows, vec![vec![text("josh")], vec![text("zoe")]]);
+        assert_eq!(
+            names("SELECT count(*) FROM users UNION ALL SELECT count(*) FROM admins;"),
+            vec![Value::Integer(4), Value::Integer(3)]
+        );
+        assert_eq!(
+            names(
+                "SELECT id FROM users WHERE name IN (SELECT 'josh' UNION SELECT name FROM admins) ORDER BY id;"
+            ),
+            vec![Value::Integer(1), Value::Integer(2), Value::Integer(3)]
+        );
+
+        let plan = crate::sql::process_query(
+            "EXPLAIN QUERY PLAN SELECT name FROM users UNION SELECT name FROM admins ORDER BY 1;",
+            &db,
+        )
+        .unwrap();
+        let details = plan
+            .rows
+            .iter()
+            .map(|row| (row[1].clone(), row[3].to_string()))
+            .collect::<Vec<(Value, String)>>();
+        assert_eq!(details[1], (Value::Integer(5), "PROJECT name".to_string()));
+        assert_eq!(details[4], (Value::Integer(6), "UNION".to_string()));
+        assert_eq!(details[5], (Value::Integer(0), "SORT BY 1".to_string()));
+
+        assert!(select(
+            &db,
+            "SELECT id, name FROM users UNION SELECT name FROM admins;"
+        )
+        .is_err());
+        assert!(select(
+            &db,
+            "SELECT name FROM users UNION SELECT name FROM admins ORDER BY id;"
+        )
+        .is_err());
+        assert!(select(
+            &db,
+            "SELECT name FROM users UNION SELECT name FROM admins ORDER BY 2;"
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn execute_select_subquery_test() {
+        let mut db = Database::new("tempdb".to_string());
+        process_command(
+            "CREATE TABLE users (id INTEGER PRIMARY KEY, name TEXT);",
+            &mut db,
+        )
+        .unwrap();
+        process_command(
+            "CREATE TABLE orders (id INTEGER PRIMARY KEY, user_id INTEGER, total REAL);",
+            &mut db,
+        )
+        .unwrap();
+        process_command(
+            "INSERT INTO users (name) VALUES ('josh'), ('mary'), ('ana');",
+            &mut db,
+        )
+        .unwrap();
+        process_command(
+            "INSERT INTO orders (user_id, total) VALUES (1, 10.5), (1, 2.0), (2, 7.25);",
+            &mut db,
+        )
+        .unwrap();
+        let column = |sql: &str, i: usize| {
+            select(&db, sql)
+                .unwrap()
+                .rows
+                .into_iter()
+                .map(|row| row[i].clone())
+                .collect::<Vec<Value>>()
+        };
+
+        // The subquery is evaluated for each row, with the columns of that row
+        assert_eq!(
+            column(
+                "SELECT name, (SELECT count(*) FROM orders o WHERE o.user_id = u.id) FROM users u;",
+                1
+            ),
+            vec![Value::Integer(2), Value::Integer(1), Value::Integer(0)]
+        );
+        // Without rows it is NULL, and unqualified names are first those of its own table
+        assert_eq!(
+            column(
+                "SELECT (SELECT sum(total) FROM orders WHERE user_id = users.id) AS spent FROM users ORDER BY spent DESC;",
+                0
+            ),
+            vec![Value::Real(12.5), Value::Real(7.25), Value::Null]
+        );
+        assert_eq!(
+            column(
+                "SELECT (SELECT count(*) FROM orders WHERE user_id = id) FROM users;",
+                0
+            ),
+            vec![Value::Integer(1); 3]
+        );
+        assert_eq!(
+            column(
+                "SELECT (SELECT max(total) FROM orders WHERE user_id = u.id AND total < (SELECT max(total) FROM orders WHERE user_id = u.id)) FROM users u;",
+                0
+            ),
+            vec![Value::Real(2.0), Value::Null, Value::Null]
+        );
+        assert_eq!(
+            column(
+                "SELECT name FROM users u WHERE id IN (SELECT user_id FROM orders WHERE total > 5 AND user_id = u.id);",
+                0
+            ),
+            vec![
+                Value::Text("josh".to_string()),
+                Value::Text("mary".to_string())
+            ]
+        );
+
+        assert!(select(&db, "SELECT (SELECT id, name FROM users);").is_err());
+        assert!(select(&db, "SELECT (SELECT x.id FROM orders) FROM users u;").is_err());
+    }
+
+    #[test]
+    fn execute_select_alias_test() {
+        let mut db = Database::new("tempdb".to_string());
+        process_command(
+            "CREATE TABLE users (id INTEGER PRIMARY KEY, name TEXT, age INTEGER);",
+            &mut db,
+        )
+        .unwrap();
+        process_command(
+            "INSERT INTO users (name, age) VALUES ('josh', 30), ('mary', 15), ('ana', 70);",
+            &mut db,
+        )
+        .unwrap();
+        let names = |sql: &str| {
+            select(&db, sql)
+                .unwrap()
+                .rows
+                .into_iter()
+                .map(|row| row[0].clone())
+                .collect::<Vec<Value>>()
+        };
+        let text = |name: &str| Value::Text(name.to_string());
+
+        let result = select(
+            &db,
+            "SELECT u.name AS username FROM users u WHERE u.age > 20 ORDER BY u.age DESC;",
+        )
+        .unwrap();
+        assert_eq!(result.columns[0].name, "username");
+        assert_eq!(result.rows, vec![vec![text("ana")], vec![text("josh")]]);
+        // Without an alias the table is qualified by its name, and the column is named
+        // without it
+        let result = select(&db, "SELECT users.name FROM users WHERE users.id = 2;").unwrap();
+        assert_eq!(result.columns[0].name, "name");
+        assert_eq!(result.rows, vec![vec![text("mary")]]);
+        assert_eq!(
+            select(&db, "SELECT u.* FROM users u WHERE u.id = 1;")
+                .unwrap()
+                .rows,
+            vec![vec![Value::Integer(1), text("josh"), Value::Integer(30)]]
+        );
+
+        // The WHERE clause can use the alias of a result column, unless a column has its name
+        assert_eq!(
+            names("SELECT age * 2 AS doubled FROM users WHERE doubled > 50 ORDER BY doubled;"),
+            vec![Value::Integer(60), Value::Integer(140)]
+        );
+        assert_eq!(
+            names("SELECT name AS age FROM users WHERE age < 20;"),
+            vec![text("mary")]
+        );
+
+        // Aliases tell apart the two sides of a table joined with itself, in the ON
+        // condition too
+        let result = select(
+            &db,
+            "SELECT a.name, b.name AS elder FROM users a JOIN users b ON b.age > a.age * 2 ORDER BY a.name;",
+        )
+        .unwrap();
+        assert_eq!(result.columns[1].name, "elder");
+        assert_eq!(
+            result.rows,
+            vec![
+                vec![text("josh"), text("ana")],
+                vec![text("mary"), text("ana")]
+            ]
+        );
+        assert!(select(
+            &db,
+            "SELECT 1 FROM users u JOIN users v ON users.id = v.id;"
+        )
+        .is_err());
+
+        assert!(select(&db, "SELECT users.name FROM users u;").is_err());
+        assert!(select(&db, "SELECT u.name FROM users WHERE v.id = 1;").is_err());
+        assert!(select(&db, "SELECT x.* FROM users;").is_err());
+        assert!(select(&db, "SELECT count(*) AS n FROM users WHERE n > 1;").is_err());
+    }
+
+    #[test]
+    fn execute_select_join_test() {
+        let mut db = Database::new("tempdb".to_string());
+        for sql in &[
+            "CREATE TABLE users (id INTEGER PRIMARY KEY, name TEXT);",
+            "CREATE TABLE orders (id INTEGER PRIMARY KEY, user_id INTEGER, total REAL);",
+            "INSERT INTO users (name) VALUES ('josh'), ('mary'), ('ana');",
+            "INSERT INTO orders (user_id, total) VALUES (1, 10.0), (1, 2.5), (2, 7.0);",
+        ] {
+            process_command(sql, &mut db).unwrap();
+        }
+        let text = |name: &str| Value::Text(name.to_string());
+
+        let result = select(
+            &db,
+            "SELECT u.name, o.total FROM users u JOIN orders o ON o.user_id = u.id ORDER BY total;",
+        )
+        .unwrap();
+        let names = result
+            .columns
+            .iter()
+            .map(|c| c.name.as_str())
+            .collect::<Vec<&str>>();
+        assert_eq!(names, vec!["name", "total"]);
+        assert_eq!(
+            result.rows,
+            vec![
+                vec![text("josh"), Value::Real(2.5)],
+                vec![text("mary"), Value::Real(7.0)],
+                vec![text("josh"), Value::Real(10.0)],
+            ]
+        );
+        // Tables listed with commas are joined by the WHERE clause, whose constraints take
+        // the affinity of their column
+        let result = select(
+            &db,
+            "SELECT name, orders.id FROM users, orders WHERE user_id = users.id AND user_id = '2';",
+        )
+        .unwrap();
+        assert_eq!(result.rows, vec![vec![text("mary"), Value::Integer(3)]]);
+
+        // A LEFT JOIN keeps the users without orders, with NULLs
+        let result = select(
+            &db,
+            "SELECT u.name, o.total FROM users u LEFT JOIN orders o ON o.user_id = u.id AND o.total > 5;",
+        )
+        .unwrap();
+        assert_eq!(
+            result.rows,
+            vec![
+                vec![text("josh"), Value::Real(10.0)],
+                vec![text("mary"), Value::Real(7.0)],
+                vec![text("ana"), Value::Null],
+            ]
+        );
+        let result = select(
+            &db,
+            "SELECT u.name FROM users u LEFT JOIN orders o ON o.user_id = u.id WHERE o.id IS NULL;",
+        )
+        .unwrap();
+        assert_eq!(result.rows, vec![vec![text("ana")]]);
+        let result = select(&db, "SELECT * FROM users CROSS JOIN orders;").unwrap();
+        assert_eq!(result.rows.len(), 9);
+        assert_eq!(result.columns.len(), 5);
+        assert_eq!(result.columns[3].name, "user_id");
+        let result = select(
+            &db,
+            "SELECT o.* FROM users u, orders o WHERE u.name = 'mary' AND o.user_id = u.id;",
+        )
+        .unwrap();
+        assert_eq!(
+            result.rows,
+            vec![vec![Value::Integer(3), Value::Integer(2), Value::Real(7.0)]]
+        );
+
+        // The columns of a join can be used by its subqueries
+        let result = select(
+            &db,
+            "SELECT u.name FROM users u JOIN orders o ON o.user_id = u.id \
+             WHERE o.total = (SELECT max(total) FROM orders WHERE user_id = u.id) ORDER BY u.name;",
+        )
+        .unwrap();
+        assert_eq!(result.rows, vec![vec![text("josh")], vec![text("mary")]]);
+        // ...and an alias of a result column by the ORDER BY, before a column of a table
+        let result = select(
+            &db,
+            "SELECT u.name AS id, o.id AS name FROM users u, orders o WHERE o.user_id = u.id ORDER BY name DESC;",
+        )
+        .unwrap();
+        assert_eq!(result.rows[0], vec![text("mary"), Value::Integer(3)]);
+
+        let plan = crate::sql::process_query(
+            "EXPLAIN QUERY PLAN SELECT u.name FROM users u JOIN orders o ON o.user_id = u.id WHERE o.total > 5;",
+            &db,
+        )
+        .unwrap();
+        let details = plan
+            .rows
+            .iter()
+            .map(|row| row[3].clone())
+            .collect::<Vec<Value>>();
+        assert_eq!(
+            details,
+            vec![
+                text("SCAN users AS u JOIN orders AS o ON o.user_id = u.id"),
+                text("FILTER o.total > 5.0"),
+                text("PROJECT u.name"),
+            ]
+        );
+
+        for (sql, error) in [
+            ("SELECT id FROM users, orders;", "ambiguous column name: id"),
+            (
+                "SELECT * FROM users, users;",
+                "ambiguous column name: users.id",
+            ),
+            (
+                "SELECT users.id FROM users, users;",
+                "ambiguous column name: users.id",
+            ),
+            (
+                "SELECT o.name FROM users u, orders o;",
+                "no such column: o.name",
+            ),
+            (
+                "SELECT 1 FROM users JOIN orders ON nope = 1;",
+                "No such column: nope",
+            ),
+        ] {
+            assert_eq!(
+                select(&db, sql).unwrap_err().to_string(),
+                format!("General error: {}", error),
+                "{}",
+                sql
+            );
+        }
+    }
+
+    #[test]
+    fn execute_select_join_index_test() {
+        use std::sync::atomic::{AtomicU64, Ordering};
+        use std::sync::Arc;
+
+        let mut db = Database::new("tempdb".to_string());
+        for sql in &[
+            "CREATE TABLE users (id INTEGER PRIMARY KEY, email TEXT UNIQUE);",
+            "CREATE TABLE orders (id INTEGER PRIMARY KEY, email TEXT, total REAL);",
+            "INSERT INTO orders (email, total) VALUES ('user7@example.com', 10.0), \
+             ('nobody@example.com', 2.5), ('user42@example.com', 7.0);",
+        ] {
+            process_command(sql, &mut db).unwrap();
+        }
+        for n in 0..100 {
+            let sql = format!(
+                "INSERT INTO users (email) VALUES ('user{}@example.com');",
+                n
+            );
+            process_command(&sql, &mut db).unwrap();
+        }
+        db.tables
+            .get_mut("users")
+            .unwrap()
+            .set_bloom_filter("email", Some(0.01))
+            .unwrap();
+        let steps = Arc::new(AtomicU64::new(0));
+        let counted = steps.clone();
+        db.progress.set(
+            1,
+            Some(Box::new(move || {
+                counted.fetch_add(1, Ordering::Relaxed);
+                false
+            })),
+        );
+
+        // The users matching each order are looked up in the index of their email, instead
+        // of reading the 100 users for each of the 3 orders
+        let sql = "SELECT o.id, u.id FROM orders o LEFT JOIN users u ON u.email = o.email;";
+        let result = select(&db, sql).unwrap();
+        assert_eq!(
+            result.rows,
+            vec![
+                vec![Value::Integer(1), Value::Integer(8)],
+                vec![Value::Integer(2), Value::Null],
+                vec![Value::Integer(3), Value::Integer(43)],
+            ]
+        );
+        assert!(steps.load(Ordering::Relaxed) < 100);
+        // ...and so are those of a primary key, the rest of the condition still checked
+        let result = select(
+            &db,
+            "SELECT u.email FROM orders o JOIN users u ON o.id = u.id AND o.total > 5;",
+        )
+        .unwrap();
+        assert_eq!(
+            result.rows,
+            vec![
+                vec![Value::Text("user0@example.com".to_string())],
+                vec![Value::Text("user2@example.com".to_string())],
+            ]
+        );
+
+        let plan = crate::sql::process_query(&format!("EXPLAIN QUERY PLAN {}", sql), &db).unwrap();
+        assert_eq!(
+            plan.rows[0][3],
+            Value::Text(
+                "SCAN orders AS o LEFT JOIN users AS u ON u.email = o.email USING INDEX (email=?)"
+                    .to_string()
+            )
+        );
+        // A join on a column that isn't UNIQUE reads the whole table
+        let plan = crate::sql::process_query(
+            "EXPLAIN QUERY PLAN SELECT 1 FROM users u JOIN orders o ON o.email = u.email;",
+            &db,
+        )
+        .unwrap();
+        assert_eq!(
+            plan.rows[0][3],
+            Value::Text("SCAN users AS u JOIN orders AS o ON o.email = u.email".to_string())
+        );
+    }
+
+    #[test]
+    fn execute_select_case_test() {
+        let mut db = Database::new("tempdb".to_string());
+        process_command(
+            "CREATE TABLE users (id INTEGER PRIMARY KEY, name TEXT, age INTEGER);",
+            &mut db,
+        )
+        .unwrap();
+        process_command(
+            "INSERT INTO users (name, age) VALUES ('josh', 30), ('mary', 15), ('ana', 70);",
+            &mut db,
+        )
+        .unwrap();
+        let result = select(
+            &db,
+            "SELECT name, CASE WHEN age < 18 THEN 'minor' WHEN age < 65 THEN 'adult' ELSE 'senior' END AS bracket \
+             FROM users ORDER BY id;",
+        )
+        .unwrap();
+        assert_eq!(result.columns[1].name, "bracket");
+        assert_eq!(
+            result
+                .rows
+                .iter()
+                .map(|row| row[1].clone())
+                .collect::<Vec<Value>>(),
+            vec![
+                Value::Text("adult".to_string()),
+                Value::Text("minor".to_string()),
+                Value::Text("senior".to_string()),
+            ]
+        );
+
+        let result = select(
+            &db,
+            "SELECT id FROM users WHERE CASE name WHEN 'josh' THEN 1 WHEN 'ana' THEN age > 60 END \
+             ORDER BY CASE WHEN id = 1 THEN 10 ELSE id END;",
+        )
+        .unwrap();
+        assert_eq!(
+            result.rows,
+            vec![vec![Value::Integer(3)], vec![Value::Integer(1)]]
+        );
+
+        // Without a match nor an ELSE, the result is NULL, and NULL equals no WHEN
+        let result = select(
+            &db,
+            "SELECT CASE 2 WHEN 1 THEN 'one' END, CASE NULL WHEN NULL THEN 1 ELSE 0 END, \
+             CASE WHEN NULL THEN 1 ELSE 0 END, CASE 1 WHEN 1 THEN 'one' ELSE no_such_function() END;",
+        )
+        .unwrap();
+        assert_eq!(
+            result.rows[0],
+            vec![
+                Value::Null,
+                Value::Integer(0),
+                Value::Integer(0),
+                Value::Text("one".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn execute_select_table_function_test() {
+        let db = Database::new("tempdb".to_string());
+
+        let result = select(&db, "SELECT * FROM generate_series(1, 7, 3);").unwrap();
+        assert_eq!(result.columns.len(), 1);
+        assert_eq!(result.columns[0].name, "value");
+        assert_eq!(
+            result.rows,
+            vec![
+                vec![Value::Integer(1)],
+                vec![Value::Integer(4)],
+                vec![Value::Integer(7)],
+            ]
+        );
+
+        let result = select(
+            &db,
+            "SELECT value, step FROM generate_series(1, 3) ORDER BY value DESC;",
+        )
+        .unwrap();
+        assert_eq!(result.rows[0], vec![Value::Integer(3), Value::Integer(1)]);
+
+        assert!(select(&db, "SELECT * FROM generate_series(1, 2, 3, 4);").is_err());
+    }
+
+    #[test]
+    fn execute_select_where_test() {
+        let mut db = Database::new("tempdb".to_string());
+        process_command(
+            "CREATE TABLE users (id INTEGER PRIMARY KEY, name TEXT COLLATE nocase);",
+            &mut db,
+        )
+        .unwrap();
+        process_command("INSERT INTO users (name) VALUES ('josh');", &mut db).unwrap();
+        process_command("INSERT INTO users (name) VALUES ('mary');", &mut db).unwrap();
+        process_command("INSERT INTO users (name) VALUES ('JOSH');", &mut db).unwrap();
+
+        let result = select(&db, "SELECT id FROM users WHERE name = 'Josh' AND 1 < id;").unwrap();
+        assert_eq!(result.rows, vec![vec![Value::Integer(3)]]);
+        assert!(select(&db, "SELECT id FROM users WHERE name MATCH 'josh';").is_err());
+    }
+
+    #[test]
+    fn execute_select_where_index_test() {
+        let mut db = Database::new("tempdb".to_string());
+        process_command(
+            "CREATE TABLE users (id INTEGER PRIMARY KEY, email TEXT UNIQUE, name TEXT);",
+            &mut db,
+        )
+        .unwrap();
+        for (email, name) in [("a@x", "ann"), ("b@x", "bob"), ("c@x", "bob")] {
+            let sql = format!(
+                "INSERT INTO users (email, name) VALUES ('{}', '{}');",
+                email, name
+            );
+            process_command(&sql, &mut db).unwrap();
+        }
+        let ids = |sql: &str| {
+            select(&db, sql)
+                .unwrap()
+                .rows
+                .into_iter()
+                .map(|row| row[0].clone())
+                .collect::<Vec<Value>>()
+        };
+        assert_eq!(
+            ids("SELECT id FROM users WHERE email = 'b@x' AND name = 'bob';"),
+            vec![Value::Integer(2)]
+        );
+        assert!(ids("SELECT id FROM users WHERE email = 'b@x' AND name = 'ann';").is_empty());
+        assert!(ids("SELECT id FROM users WHERE email = 'd@x';").is_empty());
+        assert_eq!(
+            ids("SELECT id FROM users WHERE id = 3 OR NOT (name != 'ann');"),
+            vec![Value::Integer(1), Value::Integer(3)]
+        );
+        assert_eq!(
+            ids("SELECT id FROM users WHERE id >= 2 AND id <= 3 AND name = 'bob';"),
+            vec![Value::Integer(2), Value::Integer(3)]
+        );
+
+        // Equalities on unique columns are looked up in their index
+        let plan = crate::sql::process_query(
+            "EXPLAIN QUERY PLAN SELECT id FROM users WHERE name = 'bob' AND email = 'c@x';",
+            &db,
+        )
+        .unwrap();
+        assert_eq!(
+            plan.rows[0][3],
+            Value::Text("SEARCH users USING INDEX (email=?)".to_string())
+        );
+        let plan = crate::sql::process_query(
+            "EXPLAIN QUERY PLAN SELECT id FROM users WHERE name = 'bob';",
+            &db,
+        )
+        .unwrap();
+        assert_eq!(plan.rows[0][3], Value::Text("SCAN users".to_string()));
+    }
+
+    #[test]
+    fn execute_select_between_in_test() {
+        let mut db = Database::new("tempdb".to_string());
+        process_command(
+            "CREATE TABLE scores (id INTEGER PRIMARY KEY, player TEXT UNIQUE, score INTEGER);",
+            &mut db,
+        )
+        .unwrap();
+        for (player, score) in [("ann", 15), ("bob", 8), ("cid", 20), ("dan", 25)] {
+            let sql = format!(
+                "INSERT INTO scores (player, score) VALUES ('{}', {});",
+                player, score
+            );
+            process_command(&sql, &mut db).unwrap();
+        }
+        let ids = |sql: &str| {
+            select(&db, sql)
+                .unwrap()
+                .rows
+                .into_iter()
+                .map(|row| row[0].clone())
+                .collect::<Vec<Value>>()
+        };
+        let integers = |ids: &[i64]| ids.iter().map(|id| Value::Integer(*id)).collect::<Vec<_>>();
+        assert_eq!(
+            ids("SELECT id FROM scores WHERE score BETWEEN 10 AND 20;"),
+            integers(&[1, 3])
+        );
+        assert_eq!(
+            ids("SELECT id FROM scores WHERE score NOT BETWEEN 10 AND 20;"),
+            integers(&[2, 4])
+        );
+        assert_eq!(
+            ids("SELECT id FROM scores WHERE id BETWEEN 2 AND 3;"),
+            integers(&[2, 3])
+        );
+        assert!(ids("SELECT id FROM scores WHERE id BETWEEN 3 AND 2;").is_empty());
+        assert_eq!(
+            ids("SELECT id FROM scores WHERE player BETWEEN 'b' AND 'd' AND score > 10;"),
+            integers(&[3])
+        );
+        assert_eq!(
+            select(&db, "SELECT NULL BETWEEN 1 AND 2, 1 BETWEEN NULL AND 0;")
+                .unwrap()
+                .rows,
+            vec![vec![Value::Null, Value::Integer(0)]]
+        );
+
+        // Rows looked up by a list of values come in ROWID order, once each
+        assert_eq!(
+            ids("SELECT id FROM scores WHERE id IN (3, 1, 3, 9, NULL);"),
+            integers(&[1, 3])
+        );
+        assert_eq!(
+            ids("SELECT id FROM scores WHERE player IN ('dan', 'bob') AND score < 10;"),
+            integers(&[2])
+        );
+        assert_eq!(
+            ids("SELECT id FROM scores WHERE id IN (2, 10000000000);"),
+            integers(&[2])
+        );
+        assert_eq!(
+            ids("SELECT id FROM scores WHERE id NOT IN (1, 2);"),
+            integers(&[3, 4])
+        );
+
+        let detail = |sql: &str| {
+            let plan =
+                crate::sql::process_query(&format!("EXPLAIN QUERY PLAN {}", sql), &db).unwrap();
+            plan.rows[0][3].clone()
+        };
+        assert_eq!(
+            detail("SELECT id FROM scores WHERE id BETWEEN 2 AND 3;"),
+            Value::Text("SEARCH scores USING INDEX (id>? AND id<?)".to_string())
+        );
+        assert_eq!(
+            detail("SELECT id FROM scores WHERE player IN ('ann', 'bob');"),
+            Value::Text("SEARCH scores USING INDEX (player=?)".to_string())
+        );
+        assert_eq!(
+            detail("SELECT id FROM scores WHERE score BETWEEN 10 AND 20;"),
+            Value::Text("SCAN scores".to_string())
+        );
+        assert_eq!(
+            detail("SELECT id FROM scores WHERE id > 2;"),
+            Value::Text("SCAN scores".to_string())
+        );
+    }
+
+    #[test]
+    fn execute_select_order_by_test() {
+        let mut db = Database::new("tempdb".to_string());
+        process_command(
+            "CREATE TABLE users (id INTEGER PRIMARY KEY, name TEXT COLLATE nocase, age INTEGER);",
+            &mut db,
+        )
+        .unwrap();
+        process_command(
+            "INSERT INTO users (name, age) VALUES ('bob', 30), ('Alice', 25), ('alice', 40);",
+            &mut db,
+        )
+        .unwrap();
+        process_command("INSERT INTO users (name) VALUES ('Carol');", &mut db).unwrap();
+        let ids = |sql: &str| {
+            select(&db, sql)
+                .unwrap()
+                .rows
+                .into_iter()
+                .map(|row| row[0].clone())
+                .collect::<Vec<Value>>()
+        };
+
+        // The column's collation, unless the term has its own
+        assert_eq!(
+            ids("SELECT id FROM users ORDER BY name, id DESC;"),
+            [3, 2, 1, 4].map(Value::Integer)
+        );
+        assert_eq!(
+            ids("SELECT id FROM users ORDER BY name COLLATE binary;"),
+            [2, 4, 3, 1].map(Value::Integer)
+        );
+
+        // NULLs first in ascending order and last in descending order, unless placed
+        assert_eq!(
+            ids("SELECT id FROM users ORDER BY age;"),
+            [4, 2, 1, 3].map(Value::Integer)
+        );
+        assert_eq!(
+            ids("SELECT id FROM users ORDER BY age DESC;"),
+            [3, 1, 2, 4].map(Value::Integer)
+        );
+        assert_eq!(
+            ids("SELECT id FROM users ORDER BY age NULLS LAST;"),
+            [2, 1, 3, 4].map(Value::Integer)
+        );
+        assert_eq!(
+            ids("SELECT id FROM users ORDER BY age DESC NULLS FIRST;"),
+            [4, 3, 1, 2].map(Value::Integer)
+        );
+
+        // Result columns by position and by alias
+        assert_eq!(
+            ids("SELECT id, age FROM users ORDER BY 2 DESC, 1;"),
+            [3, 1, 2, 4].map(Value::Integer)
+        );
+        assert_eq!(
+            ids("SELECT id, -age AS rank FROM users ORDER BY rank;"),
+            [4, 3, 1, 2].map(Value::Integer)
+        );
+        assert_eq!(
+            ids("SELECT id, name AS n FROM users ORDER BY n, 1 DESC;"),
+            [3, 2, 1, 4].map(Value::Integer)
+        );
+        let err = select(&db, "SELECT id FROM users ORDER BY id, 2;").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "General error: 2nd ORDER BY term out of range - should be between 1 and 1"
+        );
+    }
+
+    #[test]
+    fn execute_select_collate_where_test() {
+        let mut db = Database::new("tempdb".to_string());
+        process_command(
+            "CREATE TABLE users (id INTEGER PRIMARY KEY, name TEXT COLLATE nocase, tag TEXT);",
+            &mut db,
+        )
+        .unwrap();
+        process_command(
+            "INSERT INTO users (name, tag) VALUES ('bob', 'B'), ('Alice', 'a'), ('alice', 'A');",
+            &mut db,
+        )
+        .unwrap();
+        let ids = |sql: &str| {
+            select(&db, sql)
+                .unwrap()
+                .rows
+                .into_iter()
+                .map(|row| row[0].clone())
+                .collect::<Vec<Value>>()
+        };
+
+        // The column's collation, unless either side has its own
+        assert_eq!(
+            ids("SELECT id FROM users WHERE name = 'ALICE';"),
+            [2, 3].map(Value::Integer)
+        );
+        assert_eq!(
+            ids("SELECT id FROM users WHERE 'ALICE' = name;"),
+            [2, 3].map(Value::Integer)
+        );
+        assert_eq!(
+            ids("SELECT id FROM users WHERE name = 'alice' COLLATE binary;"),
+            [3].map(Value::Integer)
+        );
+        assert_eq!(
+            ids("SELECT id FROM users WHERE tag = 'a';"),
+            [2].map(Value::Integer)
+        );
+        assert_eq!(
+            ids("SELECT id FROM users WHERE tag = 'a' COLLATE nocase;"),
+            [2, 3].map(Value::Integer)
+        );
+        assert_eq!(
+            ids("SELECT id FROM users WHERE tag < 'b' COLLATE nocase;"),
+            [2, 3].map(Value::Integer)
+        );
+
+        // IN takes the collation of its left operand
+        assert_eq!(
+            ids("SELECT id FROM users WHERE name IN ('ALICE', 'BOB');"),
+            [1, 2, 3].map(Value::Integer)
+        );
+        assert_eq!(
+            ids("SELECT id FROM users WHERE tag COLLATE nocase IN ('b');"),
+            [1].map(Value::Integer)
+        );
+        assert_eq!(
+            ids("SELECT id FROM users WHERE tag IN ('b');"),
+            Vec::<Value>::new()
+        );
+
+        // BETWEEN compares each bound on its own
+        assert_eq!(
+            ids("SELECT id FROM users WHERE name BETWEEN 'A' AND 'ALICE';"),
+            [2, 3].map(Value::Integer)
+        );
+        assert_eq!(
+            ids("SELECT id FROM users WHERE tag COLLATE nocase BETWEEN 'a' AND 'a';"),
+            [2, 3].map(Value::Integer)
+        );
+
+        // Unknown collations are reported even when no row is compared
+        process_command("DELETE FROM users;", &mut db).unwrap();
+        let err = select(&db, "SELECT id FROM users WHERE name = 'x' COLLATE bogus;").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "General error: no such collation sequence: bogus"
+        );
+        assert!(select(&db, "SELECT name COLLATE bogus FROM users;").is_err());
+    }
+
+    #[test]
+    fn execute_select_row_values_test() {
+        let mut db = Database::new("tempdb".to_string());
+        process_command(
+            "CREATE TABLE events (id INTEGER PRIMARY KEY, day INTEGER, seq INTEGER);",
+            &mut db,
+        )
+        .unwrap();
+        process_command(
+            "INSERT INTO events (day, seq) VALUES (1, 1), (1, 2), (2, 1), (2, 2), (3, 1);",
+            &mut db,
+        )
+        .unwrap();
+        process_command("INSERT INTO events (day) VALUES (2);", &mut db).unwrap();
+        process_command(
+            "CREATE TABLE holidays (id INTEGER PRIMARY KEY, day INTEGER, seq INTEGER);",
+            &mut db,
+        )
+        .unwrap();
+        process_command(
+            "INSERT INTO holidays (day, seq) VALUES (1, 2), (3, 1);",
+            &mut db,
+        )
+        .unwrap();
+        let ids = |sql: &str| {
+            select(&db, sql)
+                .unwrap()
+                .rows
+                .into_iter()
+                .map(|row| row[0].clone())
+                .collect::<Vec<Value>>()
+        };
+
+        assert_eq!(
+            ids("SELECT id FROM events WHERE (day, seq) = (2, 1);"),
+            [Value::Integer(3)]
+        );
+        // Keyset pagination: the rows after (1, 2) in (day, seq) order
+        assert_eq!(
+            ids("SELECT id FROM events WHERE (day, seq) > (1, 2) ORDER BY day, seq;"),
+            [6, 3, 4, 5].map(Value::Integer)
+        );
+        assert_eq!(
+            ids("SELECT id FROM events WHERE (day, seq) <= (1, 2);"),
+            [1, 2].map(Value::Integer)
+        );
+        // The first columns that differ decide, whatever the NULLs after them
+        assert_eq!(
+            ids("SELECT id FROM events WHERE (day, seq) < (3, 0) AND (day, seq) <> (1, 2);"),
+            [1, 3, 4, 6].map(Value::Integer)
+        );
+        assert_eq!(
+            ids("SELECT id FROM events WHERE (day, seq) = (2, 5) OR (seq, day) < (1, 2);"),
+            [1].map(Value::Integer)
+        );
+        assert_eq!(
+            ids("SELECT id FROM events WHERE (day, seq) IN ((1, 1), (3, 1));"),
+            [1, 5].map(Value::Integer)
+        );
+        assert_eq!(
+            ids("SELECT id FROM events WHERE (day, seq) IN (SELECT day, seq FROM holidays);"),
+            [2, 5].map(Value::Integer)
+        );
+        assert_eq!(
+            ids("SELECT id FROM events WHERE day NOT IN (SELECT day FROM holidays);"),
+            [3, 4, 6].map(Value::Integer)
+        );
+
+        assert!(select(&db, "SELECT id FROM events WHERE (day, seq) = (1, 2, 3);").is_err());
+        assert!(select(&db, "SELECT id FROM events WHERE (day, seq) = 1;").is_err());
+        assert!(select(&db, "SELECT (day, seq) FROM events;").is_err());
+        assert!(select(
+            &db,
+            "SELECT id FROM events WHERE (day, seq) IN (SELECT day FROM holidays);"
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn delete_limit_test() {
+        let mut db = Database::new("tempdb".to_string());
+        process_command("CREATE VIRTUAL TABLE docs USING fts(title, body);", &mut db).unwrap();
+        process_command(
+            "INSERT INTO docs (title, body) VALUES ('b', 'x'), ('c', 'x'), ('a', 'x'), ('d', 'y');",
+            &mut db,
+        )
+        .unwrap();
+        let result = process_command(
+            "DELETE FROM docs WHERE body = 'x' ORDER BY title DESC LIMIT 1 OFFSET 1;",
+            &mut db,
+        )
+        .unwrap();
+        assert_eq!(result.rows_affected, Some(1));
+        let titles = select(&db, "SELECT title FROM docs;").unwrap().rows;
+        assert!(!titles.contains(&vec![Value::Text("b".to_string())]));
+        assert_eq!(titles.len(), 3);
+
+        process_command("DELETE FROM docs LIMIT -1 OFFSET 1;", &mut db).unwrap();
+        assert_eq!(select(&db, "SELECT * FROM docs;").unwrap().rows.len(), 1);
+        assert!(process_command("DELETE FROM docs ORDER BY title;", &mut db).is_err());
+    }
+
+    #[test]
+    fn execute_select_match_test() {
+        let mut db = Database::new("tempdb".to_string());
+        process_command("CREATE VIRTUAL TABLE docs USING fts(title, body);", &mut db).unwrap();
+        process_command(
+            "INSERT INTO docs (title, body) VALUES ('SQLRite', 'A simple SQLite clone written in Rust'), ('Rust book', 'Learning Rust');",
+            &mut db,
+        )
+        .unwrap();
+        process_command(
+            "INSERT INTO docs VALUES ('SQLite internals', 'How the SQLite B-tree works');",
+            &mut db,
+        )
+        .unwrap();
+        assert_eq!(db.counters.last_insert_rowid, 3);
+
+        let result = select(
+            &db,
+            "SELECT title, rowid FROM docs WHERE docs MATCH 'rust AND sqlite';",
+        )
+        .unwrap();
+        assert_eq!(
+            result.rows,
+            vec![vec![Value::Text("SQLRite".to_string()), Value::Integer(1)]]
+        );
+        let result = select(
+            &db,
+            "SELECT rowid FROM docs WHERE title MATCH 'sqlite OR rust' AND rowid > 2;",
+        )
+        .unwrap();
+        assert_eq!(result.rows, vec![vec![Value::Integer(3)]]);
+        assert_eq!(select(&db, "SELECT * FROM docs;").unwrap().rows.len(), 3);
+        assert!(select(&db, "SELECT * FROM docs WHERE docs MATCH 'rust AND';").is_err());
+
+        process_command("DELETE FROM docs WHERE docs MATCH 'clone';", &mut db).unwrap();
+        assert_eq!(db.counters.changes, 1);
+        let result = select(&db, "SELECT rowid FROM docs WHERE docs MATCH 'rust';").unwrap();
+        assert_eq!(result.rows, vec![vec![Value::Integer(2)]]);
+        assert!(process_command("INSERT INTO generate_series VALUES (1);", &mut db).is_err());
+    }
+
+    #[test]
+    fn execute_select_unknown_column_test() {
+        let mut db = Database::new("tempdb".to_string());
+        process_command(
+            "CREATE TABLE users (id INTEGER PRIMARY KEY, name TEXT);",
+            &mut db,
+        )
+        .unwrap();
+
+        assert!(select(&db, "SELECT email FROM users;").is_err());
+        assert!(select(&db, "SELECT * FROM orders;").is_err());
+    }
+
+    #[test]
+    fn explain_select_test() {
+        let mut db = Database::new("tempdb".to_string());
+        process_command(
+            "CREATE TABLE users (id INTEGER PRIMARY KEY, name TEXT, age INTEGER);",
+            &mut db,
+        )
+        .unwrap();
+        for age in 1..=10 {
+            let sql = format!(
+                "INSERT INTO users (name, age) VALUES ('user{}', {});",
+                age, age
+            );
+            process_command(&sql, &mut db).unwrap();
+        }
+        let sql = "SELECT name FROM users WHERE age > 7 AND name != 'user9' ORDER BY name DESC;";
+        let column = |result: &ResultSet, name: &str| {
+            let index = result.columns.iter().position(|c| c.name == name).unwrap();
+            result
+                .rows
+                .iter()
+                .map(|row| row[index].clone())
+                .collect::<Vec<Value>>()
+        };
+        let integers = |values: &[i64]| {
+            values
+                .iter()
+                .map(|v| Value::Integer(*v))
+                .collect::<Vec<Value>>()
+        };
+
+        let plan = crate::sql::process_query(&format!("EXPLAIN QUERY PLAN {}", sql), &db).unwrap();
+        assert_eq!(
+            column(&plan, "detail"),
+            vec![
+                Value::Text("SCAN users".to_string()),
+                Value::Text("FILTER age > 7".to_string()),
+                Value::Text("FILTER name <> 'user9'".to_string()),
+                Value::Text("PROJECT name".to_string()),
+                Value::Text("SORT BY name DESC".to_string()),
+            ]
+        );
+        assert_eq!(column(&plan, "parent"), integers(&[2, 3, 4, 5, 0]));
+
+        let analysis = crate::sql::process_query(&format!("EXPLAIN ANALYZE {}", sql), &db).unwrap();
+        assert_eq!(column(&analysis, "rows"), integers(&[10, 3, 2, 2, 2]));
+        assert_eq!(column(&analysis, "loops"), integers(&[1, 1, 1, 1, 1]));
+        // Without statistics a range comparison is guessed to keep a quarter of the rows
+        assert_eq!(column(&analysis, "estimated_rows")[1], Value::Integer(3));
+
+        process_command("INSERT INTO users (name, age) VALUES ('old', 90);", &mut db).unwrap();
+        process_command("ANALYZE users;", &mut db).unwrap();
+        let (result, profile) = execute_select_profiled(
+            &SelectQuery::new(
+                &crate::sql::parse_statement("SELECT * FROM users WHERE age >= 9;").unwrap(),
+            )
+            .unwrap(),
+            &db,
+        )
+        .unwrap();
+        assert_eq!(result.rows.len(), 3);
+        let filter = &profile.operators[1];
+        assert_eq!(filter.rows, 3);
+        assert!((filter.estimated_rows.unwrap() - 3.0).abs() < 1.0);
+        assert!(crate::sql::process_query("EXPLAIN DELETE FROM users;", &db).is_err());
+    }
+}
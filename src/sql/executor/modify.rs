@@ -0,0 +1,832 @@
+//! Execution of the INSERT, UPDATE and DELETE statements changing tables and virtual tables.
+use sqlparser::ast::{Assignment, Expr, Ident, Query, SetExpr, Statement, Values};
+use std::convert::TryFrom;
+use std::sync::Arc;
+
+use crate::error::{Result, SQLRiteError};
+use crate::sql::collation::CollationFn;
+use crate::sql::db::audit::AuditOperation;
+use crate::sql::db::database::Database;
+use crate::sql::db::privilege::Privilege;
+use crate::sql::db::table::DataType;
+use crate::sql::db::trigger::{TriggerEvent, TriggerTiming};
+use crate::sql::executor::expr::{eval_expr, RowContext};
+use crate::sql::executor::filter::{satisfies, split_selection};
+use crate::sql::executor::source::{Source, SourceColumn};
+use crate::sql::executor::trigger::{bind_rows, fire_triggers};
+use crate::sql::executor::{compare_keys, order_by_collation};
+use crate::sql::parse_statement;
+use crate::sql::parser::limit::ModifyLimit;
+use crate::sql::parser::upsert::OnConflict;
+use crate::sql::value::{Affinity, Value};
+
+/// Number of rows from which an INSERT into a table without triggers is a bulk load,
+/// building the indexes of the table once all the rows are in rather than row by row.
+pub const BULK_LOAD_ROWS: usize = 64;
+
+/// Inserts `rows` into the columns `columns` of a table, firing the INSERT triggers on it
+/// before and after each row, and returns the number of rows inserted, or updated by an
+/// upsert. A row conflicting with the rows of the table on a UNIQUE or PRIMARY KEY column
+/// fails the statement, unless `on_conflict` resolves the conflict. If a row can't be
+/// inserted, the rows inserted before it are removed and none of them are, the rows
+/// `on_conflict` replaced or updated are only restored by a transaction around the call.
+pub fn insert_into_table(
+    db: &mut Database,
+    table_name: &str,
+    columns: &[String],
+    rows: &[Vec<Value>],
+    on_conflict: Option<&OnConflict>,
+) -> Result<i64> {
+    let last_rowid = db.tables.get(table_name).map(|table| table.last_rowid);
+    let counters = db.counters;
+    let mut rowids = vec![];
+    // Triggers could look the loaded rows up before they are indexed, and conflicts are
+    // found in the indexes
+    let bulk = rows.len() >= BULK_LOAD_ROWS
+        && on_conflict.is_none()
+        && !db.triggers.iter().any(|t| t.table_name == table_name);
+    if let (true, Some(table)) = (bulk, db.tables.get_mut(table_name)) {
+        table.begin_bulk_load();
+    }
+    let mut result = insert_rows(db, table_name, columns, rows, on_conflict, &mut rowids);
+    if let (true, Some(table)) = (bulk, db.tables.get_mut(table_name)) {
+        if let Err(err) = table.end_bulk_load(&db.collations) {
+            result = result.and(Err(SQLRiteError::Internal(format!(
+                "Unique key constaint violation: {}",
+                err
+            ))));
+        }
+    }
+    if result.is_err() {
+        if let (Some(table), Some(last_rowid)) = (db.tables.get_mut(table_name), last_rowid) {
+            for rowid in rowids {
+                table.delete_row(rowid);
+            }
+            table.last_rowid = last_rowid;
+        }
+        db.counters = counters;
+    }
+    result
+}
+
+/// Inserts `rows` like `insert_into_table`, pushing the rowid of each row to `rowids` as
+/// soon as it is inserted.
+fn insert_rows(
+    db: &mut Database,
+    table_name: &str,
+    columns: &[String],
+    rows: &[Vec<Value>],
+    on_conflict: Option<&OnConflict>,
+    rowids: &mut Vec<i64>,
+) -> Result<i64> {
+    db.check_privilege(Privilege::Insert, Some(table_name))?;
+    let table = match db.tables.get(table_name) {
+        Some(table) => table,
+        None => return Err(SQLRiteError::Internal("Table doesn't exist".to_string())),
+    };
+    // Checking if columns on INSERT query exist on Table
+    if !columns
+        .iter()
+        .all(|column| table.contains_column(column.to_string()))
+    {
+        return Err(SQLRiteError::Internal(
+            "Cannot insert, some of the columns do not exist".to_string(),
+        ));
+    }
+    if let Some(column) = columns
+        .iter()
+        .enumerate()
+        .find(|(i, column)| columns[..*i].contains(column))
+        .map(|(_, column)| column)
+    {
+        return Err(SQLRiteError::Internal(format!(
+            "Cannot insert, column {} is given more than once",
+            column
+        )));
+    }
+    let table_columns = table
+        .columns
+        .iter()
+        .map(|col| (col.column_name.to_string(), col.datatype.clone()))
+        .collect::<Vec<(String, DataType)>>();
+    // The INTEGER PRIMARY KEY is the rowid, a row inserted without one is assigned one
+    let not_null = table
+        .columns
+        .iter()
+        .filter(|col| col.not_null && !(col.is_pk && col.datatype == DataType::Integer))
+        .map(|col| col.column_name.to_string())
+        .collect::<Vec<String>>();
+    let names = table_columns
+        .iter()
+        .map(|(name, _)| name.to_string())
+        .collect::<Vec<String>>();
+
+    let mut inserted: i64 = 0;
+    for row in rows {
+        db.progress.step()?;
+        // Checking if number of columns in query are the same as number of values
+        if columns.len() != row.len() {
+            return Err(SQLRiteError::Internal(format!(
+                "{} values for {} columns",
+                row.len(),
+                columns.len()
+            )));
+        }
+
+        // The row as it will be inserted, columns missing from the INSERT are NULL
+        let new = table_columns
+            .iter()
+            .map(
+                |(name, datatype)| match columns.iter().position(|col| col == name) {
+                    Some(position) => column_value(&row[position], datatype),
+                    None => Value::Null,
+                },
+            )
+            .collect::<Vec<Value>>();
+        for ((name, datatype), value) in table_columns.iter().zip(&new) {
+            check_column_value(name, datatype, value)?;
+            if value.is_null() && not_null.contains(name) {
+                return Err(SQLRiteError::General(format!(
+                    "NOT NULL constraint failed: {}.{}",
+                    table_name, name
+                )));
+            }
+        }
+        fire_triggers(
+            db,
+            table_name,
+            TriggerTiming::Before,
+            &TriggerEvent::Insert,
+            None,
+            Some(&new),
+        )?;
+
+        db.faults.check("INSERT")?;
+        if let Some(on_conflict) = on_conflict {
+            match resolve_conflicts(db, table_name, &names, &new, on_conflict)? {
+                Resolution::Insert => {}
+                Resolution::Ignored => continue,
+                Resolution::Updated => {
+                    inserted += 1;
+                    continue;
+                }
+            }
+        }
+        let collations = &db.collations;
+        let db_table = db.tables.get_mut(table_name).unwrap();
+        if let Err(err) = db_table.validate_unique_constraint(&names, &new, collations) {
+            return Err(SQLRiteError::Internal(format!(
+                "Unique key constaint violation: {}",
+                err
+            )));
+        }
+        // No unique constraint violation, moving forward with inserting row
+        let rowid = db_table.insert_row(&names, &new)?;
+        rowids.push(rowid);
+        db.counters.last_insert_rowid = rowid;
+        inserted += 1;
+
+        // Read back, so AFTER triggers see the values assigned to the row, like its rowid
+        let new = table_columns
+            .iter()
+            .map(|(name, _)| db_table.get_value(name, rowid))
+            .collect::<Result<Vec<Value>>>()?;
+        if db.records_changes() {
+            db.record_change(
+                AuditOperation::Insert,
+                table_name,
+                rowid,
+                &names,
+                None,
+                Some(&new),
+            );
+        }
+        fire_triggers(
+            db,
+            table_name,
+            TriggerTiming::After,
+            &TriggerEvent::Insert,
+            None,
+            Some(&new),
+        )?;
+        db.counters.last_insert_rowid = rowid;
+    }
+    Ok(inserted)
+}
+
+/// What becomes of a row to insert once its conflicts with the rows of the table are resolved
+#[derive(Debug, PartialEq)]
+enum Resolution {
+    /// The row is inserted: no row conflicts with it anymore, or only on columns the
+    /// conflict resolution doesn't cover, which fails the INSERT
+    Insert,
+    /// The row isn't inserted, nor any row changed
+    Ignored,
+    /// The row it conflicts with was updated instead
+    Updated,
+}
+
+/// Resolves the conflicts of the row `new`, the values of the columns `columns` of a table,
+/// with the rows of the table, as `on_conflict` says.
+fn resolve_conflicts(
+    db: &mut Database,
+    table_name: &str,
+    columns: &[String],
+    new: &[Value],
+    on_conflict: &OnConflict,
+) -> Result<Resolution> {
+    let table = db.tables.get(table_name).unwrap();
+    let target = match on_conflict {
+        OnConflict::Ignore { target } | OnConflict::Update { target, .. } => target.as_slice(),
+        OnConflict::Replace => &[],
+    };
+    // Without constraints on several columns, a target can only be one UNIQUE column
+    let unique = |name: &String| {
+        table
+            .columns
+            .iter()
+            .any(|col| col.column_name == *name && col.is_unique)
+    };
+    if !target.is_empty() && (target.len() > 1 || !target.iter().all(unique)) {
+        return Err(SQLRiteError::General(
+            "ON CONFLICT clause does not match any PRIMARY KEY or UNIQUE constraint".to_string(),
+        ));
+    }
+    let conflicts = table.conflicting_rows(new, &db.collations)?;
+    let on_target = conflicts
+        .iter()
+        .find(|(name, _)| target.contains(name))
+        .map(|(_, rowid)| *rowid);
+
+    match on_conflict {
+        OnConflict::Replace => {
+            let mut replaced = conflicts
+                .iter()
+                .map(|(_, rowid)| *rowid)
+                .collect::<Vec<i64>>();
+            replaced.sort_unstable();
+            replaced.dedup();
+            if !replaced.is_empty() {
+                db.check_privilege(Privilege::Delete, Some(table_name))?;
+            }
+            for rowid in replaced {
+                let table = db.tables.get_mut(table_name).unwrap();
+                let old = columns
+                    .iter()
+                    .map(|name| table.get_value(name, rowid))
+                    .collect::<Result<Vec<Value>>>()?;
+                table.delete_row(rowid);
+                if db.records_changes() {
+                    db.record_change(
+                        AuditOperation::Delete,
+                        table_name,
+                        rowid,
+                        columns,
+                        Some(&old),
+                        None,
+                    );
+                }
+            }
+            Ok(Resolution::Insert)
+        }
+        OnConflict::Ignore { target } if target.is_empty() && !conflicts.is_empty() => {
+            Ok(Resolution::Ignored)
+        }
+        OnConflict::Ignore { .. } => match on_target {
+            Some(_) => Ok(Resolution::Ignored),
+            None => Ok(Resolution::Insert),
+        },
+        OnConflict::Update { clauses, .. } => match on_target {
+            Some(rowid) => match update_row(db, table_name, columns, rowid, new, clauses)? {
+                true => Ok(Resolution::Updated),
+                false => Ok(Resolution::Ignored),
+            },
+            None => Ok(Resolution::Insert),
+        },
+    }
+}
+
+/// Updates the row `rowid` of a table an upsert conflicts with, with the SET and WHERE
+/// `clauses` of its DO UPDATE, firing the UPDATE triggers on the table. `new` is the row
+/// the upsert would have inserted, `excluded` in the clauses. Returns false if the row
+/// doesn't satisfy the WHERE clause, and is left alone.
+fn update_row(
+    db: &mut Database,
+    table_name: &str,
+    columns: &[String],
+    rowid: i64,
+    new: &[Value],
+    clauses: &str,
+) -> Result<bool> {
+    let table = db.tables.get(table_name).unwrap();
+    let source = Source::from_rowids(table, std::iter::once(rowid));
+    let old = source.rows[0].clone();
+    // The row is also named after its table, the table of an attached database included
+    let name = table_name.rsplit('.').next().unwrap_or(table_name);
+    let clauses = bind_rows(
+        clauses,
+        columns,
+        &[("excluded", Some(new)), (name, Some(&old))],
+    )?;
+    let (assignments, selection) = match parse_statement(&format!("UPDATE t {}", clauses))? {
+        Statement::Update {
+            assignments,
+            selection,
+            ..
+        } => (assignments, selection),
+        _ => {
+            return Err(SQLRiteError::Internal(
+                "Error parsing DO UPDATE clause".to_string(),
+            ))
+        }
+    };
+    let context = RowContext::new(&source.columns, &old);
+    if !satisfies(selection.as_slice(), db, &context)? {
+        return Ok(false);
+    }
+    db.check_privilege(Privilege::Update, Some(table_name))?;
+    let (updated, changed) = assign(db, table_name, columns, &assignments, &context, &old)?;
+
+    write_update(db, table_name, columns, rowid, &old, &updated, changed)
+}
+
+/// The row `old` of a table once the `assignments` of an UPDATE, evaluated against
+/// `context`, are applied to it, with the names of the columns they set. `columns` are
+/// the names of the columns of the table, in their order.
+fn assign(
+    db: &Database,
+    table_name: &str,
+    columns: &[String],
+    assignments: &[Assignment],
+    context: &RowContext,
+    old: &[Value],
+) -> Result<(Vec<Value>, Vec<String>)> {
+    let table = db.tables.get(table_name).unwrap();
+    let mut updated = old.to_vec();
+    let mut changed = vec![];
+    for assignment in assignments {
+        let name = assignment
+            .id
+            .last()
+            .map(|ident| ident.value.to_string())
+            .unwrap_or_default();
+        let position = columns
+            .iter()
+            .position(|col| *col == name)
+            .ok_or_else(|| SQLRiteError::General(format!("No such column: {}", name)))?;
+        let column = &table.columns[position];
+        let value = column_value(
+            &eval_expr(&assignment.value, db, context)?,
+            &column.datatype,
+        );
+        if name == table.primary_key
+            && column.datatype == DataType::Integer
+            && value != old[position]
+        {
+            return Err(SQLRiteError::NotImplemented(
+                "Changing the INTEGER PRIMARY KEY of a row is not supported yet.".to_string(),
+            ));
+        }
+        check_column_value(&name, &column.datatype, &value)?;
+        if value.is_null() && column.not_null {
+            return Err(SQLRiteError::General(format!(
+                "NOT NULL constraint failed: {}.{}",
+                table_name, name
+            )));
+        }
+        updated[position] = value;
+        changed.push(name);
+    }
+    Ok((updated, changed))
+}
+
+/// Writes `updated` over the row `rowid` of a table, which was `old`, firing the UPDATE
+/// triggers on the columns `changed` before and after it. Returns false if a BEFORE
+/// trigger deleted the row, which is left deleted.
+fn write_update(
+    db: &mut Database,
+    table_name: &str,
+    columns: &[String],
+    rowid: i64,
+    old: &[Value],
+    updated: &[Value],
+    changed: Vec<String>,
+) -> Result<bool> {
+    let event = TriggerEvent::Update(changed);
+    fire_triggers(
+        db,
+        table_name,
+        TriggerTiming::Before,
+        &event,
+        Some(old),
+        Some(updated),
+    )?;
+    db.faults.check("UPDATE")?;
+    let table = db.tables.get_mut(table_name).unwrap();
+    if !table.contains_rowid(rowid) {
+        return Ok(false);
+    }
+    table.write_row(rowid, columns, updated)?;
+    // Read back, so AFTER triggers see the values as they are stored
+    let updated = columns
+        .iter()
+        .map(|name| table.get_value(name, rowid))
+        .collect::<Result<Vec<Value>>>()?;
+    if db.records_changes() {
+        db.record_change(
+            AuditOperation::Update,
+            table_name,
+            rowid,
+            columns,
+            Some(old),
+            Some(&updated),
+        );
+    }
+    fire_triggers(
+        db,
+        table_name,
+        TriggerTiming::After,
+        &event,
+        Some(old),
+        Some(&updated),
+    )?;
+    Ok(true)
+}
+
+/// Converts a value of an INSERT statement into the value it is stored as in a column of
+/// type `datatype`, by the affinity of the column: text is read as the type of the column
+/// when it is one of its values, and values are stored as the text of TEXT columns. Other
+/// values are kept, for `check_column_value` to reject.
+fn column_value(value: &Value, datatype: &DataType) -> Value {
+    match Affinity::of_column(datatype) {
+        Some(affinity) => value.apply_affinity(affinity),
+        None => value.clone(),
+    }
+}
+
+/// Returns an error if `value` can't be stored in the column `name` of type `datatype`:
+/// INTEGER, REAL and BOOL columns only store their own type.
+fn check_column_value(name: &str, datatype: &DataType, value: &Value) -> Result<()> {
+    let valid = matches!(
+        (datatype, value),
+        (_, Value::Null)
+            | (DataType::Integer, Value::Integer(_))
+            | (DataType::Real, Value::Real(_))
+            | (DataType::Bool, Value::Bool(_))
+            | (DataType::Text, Value::Text(_))
+    );
+    if valid {
+        Ok(())
+    } else {
+        Err(SQLRiteError::General(format!(
+            "datatype mismatch: {} can't be stored in column {}",
+            value, name
+        )))
+    }
+}
+
+/// Inserts the rows of `INSERT INTO table_name (columns) VALUES ...` into a virtual table,
+/// returning the number of rows inserted. Without a column list, values are given for
+/// every column that isn't hidden.
+pub fn insert_into_virtual_table(
+    db: &mut Database,
+    table_name: &str,
+    columns: &[Ident],
+    source: &Query,
+) -> Result<i64> {
+    db.check_privilege(Privilege::Insert, Some(table_name))?;
+    let table = match db.vtabs.get_table(table_name) {
+        Some(table) => table.clone(),
+        None => return Err(SQLRiteError::Internal("Table doesn't exist".to_string())),
+    };
+    let rows = match &source.body {
+        SetExpr::Values(Values(rows)) => rows,
+        _ => {
+            return Err(SQLRiteError::NotImplemented(
+                "Only INSERT ... VALUES is supported for virtual tables.".to_string(),
+            ))
+        }
+    };
+
+    let table_columns = table.columns();
+    let positions = if columns.is_empty() {
+        (0..table_columns.len())
+            .filter(|i| !table_columns[*i].hidden)
+            .collect::<Vec<usize>>()
+    } else {
+        columns
+            .iter()
+            .map(|column| {
+                table_columns
+                    .iter()
+                    .position(|col| col.name == column.value)
+                    .ok_or_else(|| {
+                        SQLRiteError::General(format!(
+                            "table {} has no column named {}",
+                            table_name, column.value
+                        ))
+                    })
+            })
+            .collect::<Result<Vec<usize>>>()?
+    };
+
+    let mut inserted: i64 = 0;
+    for row in rows {
+        db.progress.step()?;
+        if row.len() != positions.len() {
+            return Err(SQLRiteError::Internal(format!(
+                "{} values for {} columns",
+                row.len(),
+                positions.len()
+            )));
+        }
+        let mut values = vec![Value::Null; table_columns.len()];
+        for (position, expr) in positions.iter().zip(row) {
+            values[*position] = eval_expr(expr, db, &RowContext::new(&[], &[]))?;
+        }
+        db.counters.last_insert_rowid = table.insert(&values)?;
+        inserted += 1;
+    }
+    db.counters.record(inserted);
+    Ok(inserted)
+}
+
+/// Sets the columns of `assignments` in the rows of a table satisfying `selection`, every
+/// row without one, or those of them `limit` bounds the statement to, firing the UPDATE
+/// triggers on it before and after each row, and returns the number of rows updated. If a
+/// row can't be updated, the rows updated before it are written back and none of them are.
+pub fn update_table(
+    db: &mut Database,
+    table_name: &str,
+    assignments: &[Assignment],
+    selection: Option<&Expr>,
+    limit: Option<&ModifyLimit>,
+) -> Result<i64> {
+    let counters = db.counters;
+    let mut updated = vec![];
+    let result = update_rows(db, table_name, assignments, selection, limit, &mut updated);
+    if result.is_err() {
+        restore_rows(db, table_name, &updated);
+        db.counters = counters;
+    }
+    result
+}
+
+/// Writes `rows`, the rowid and the values of each row a failed statement changed, back
+/// into the table, the last one first. Every row is written back even if one of them can't
+/// be, which is logged, so the statement fails with its own error.
+fn restore_rows(db: &mut Database, table_name: &str, rows: &[(i64, Vec<Value>)]) {
+    let table = match db.tables.get_mut(table_name) {
+        Some(table) => table,
+        None => return,
+    };
+    let columns = table
+        .columns
+        .iter()
+        .map(|col| col.column_name.to_string())
+        .collect::<Vec<String>>();
+    for (rowid, row) in rows.iter().rev() {
+        if let Err(err) = table.write_row(*rowid, &columns, row) {
+            log::warn!(
+                "Could not restore row {} of {} after a failed statement: {}",
+                rowid,
+                table_name,
+                err
+            );
+        }
+    }
+}
+
+/// Updates rows like `update_table`, pushing the rowid and the values of each row before
+/// the update to `updated` as soon as it is updated.
+fn update_rows(
+    db: &mut Database,
+    table_name: &str,
+    assignments: &[Assignment],
+    selection: Option<&Expr>,
+    limit: Option<&ModifyLimit>,
+    updated: &mut Vec<(i64, Vec<Value>)>,
+) -> Result<i64> {
+    db.check_privilege(Privilege::Update, Some(table_name))?;
+    let table = match db.tables.get(table_name) {
+        Some(table) => table,
+        None => return Err(SQLRiteError::Internal("Table doesn't exist".to_string())),
+    };
+    let columns = table
+        .columns
+        .iter()
+        .map(|col| col.column_name.to_string())
+        .collect::<Vec<String>>();
+    // Rows are collected first, the table can't be changed while it is being read
+    let rowids = table.rowids();
+    let source = Source::from_table(table);
+    let terms = selection.map(std::slice::from_ref).unwrap_or(&[]);
+    let mut selected: Vec<(&Vec<Value>, i64)> = vec![];
+    for (row, &rowid) in source.rows.iter().zip(&rowids) {
+        db.progress.step()?;
+        if satisfies(terms, db, &RowContext::new(&source.columns, row))? {
+            selected.push((row, rowid));
+        }
+    }
+    if let Some(limit) = limit {
+        selected = limit_rows(db, &source.columns, selected, limit)?;
+    }
+
+    let mut count: i64 = 0;
+    for (_, rowid) in selected {
+        // The triggers of the rows updated before may have changed or deleted the row
+        let table = db.tables.get(table_name).unwrap();
+        if !table.contains_rowid(rowid) {
+            continue;
+        }
+        let row = Source::from_rowids(table, std::iter::once(rowid));
+        let old = &row.rows[0];
+        let context = RowContext::new(&row.columns, old);
+        let (new, changed) = assign(db, table_name, &columns, assignments, &context, old)?;
+        updated.push((rowid, old.clone()));
+        if write_update(db, table_name, &columns, rowid, old, &new, changed)? {
+            count += 1;
+        }
+    }
+    db.counters.record(count);
+    Ok(count)
+}
+
+/// Deletes the rows of a table satisfying `selection`, every row without one, or those of
+/// them `limit` bounds the statement to, firing the DELETE triggers on it before and after
+/// each row, and returns the number of rows deleted. Each row is removed from every column
+/// and from their indexes. If a row can't be deleted, the rows deleted before it are
+/// written back and none of them are.
+pub fn delete_from_table(
+    db: &mut Database,
+    table_name: &str,
+    selection: Option<&Expr>,
+    limit: Option<&ModifyLimit>,
+) -> Result<i64> {
+    let counters = db.counters;
+    let mut deleted = vec![];
+    let result = delete_rows(db, table_name, selection, limit, &mut deleted);
+    if result.is_err() {
+        restore_rows(db, table_name, &deleted);
+        db.counters = counters;
+    }
+    result
+}
+
+/// Deletes rows like `delete_from_table`, pushing the rowid and the values of each row to
+/// `deleted` as soon as it is deleted.
+fn delete_rows(
+    db: &mut Database,
+    table_name: &str,
+    selection: Option<&Expr>,
+    limit: Option<&ModifyLimit>,
+    deleted: &mut Vec<(i64, Vec<Value>)>,
+) -> Result<i64> {
+    db.check_privilege(Privilege::Delete, Some(table_name))?;
+    let table = match db.tables.get(table_name) {
+        Some(table) => table,
+        None => return Err(SQLRiteError::Internal("Table doesn't exist".to_string())),
+    };
+    let columns = table
+        .columns
+        .iter()
+        .map(|col| col.column_name.to_string())
+        .collect::<Vec<String>>();
+    // Rows are collected first, the table can't be changed while it is being read
+    let rowids = table.rowids();
+    let source = Source::from_table(table);
+    let terms = selection.map(std::slice::from_ref).unwrap_or(&[]);
+    let mut selected: Vec<(&Vec<Value>, i64)> = vec![];
+    for (row, &rowid) in source.rows.iter().zip(&rowids) {
+        db.progress.step()?;
+        if satisfies(terms, db, &RowContext::new(&source.columns, row))? {
+            selected.push((row, rowid));
+        }
+    }
+    if let Some(limit) = limit {
+        selected = limit_rows(db, &source.columns, selected, limit)?;
+    }
+
+    let mut count: i64 = 0;
+    for (old, rowid) in selected {
+        fire_triggers(
+            db,
+            table_name,
+            TriggerTiming::Before,
+            &TriggerEvent::Delete,
+            Some(old),
+            None,
+        )?;
+        db.faults.check("DELETE")?;
+        // A BEFORE trigger may have deleted the row already
+        if !db.tables.get_mut(table_name).unwrap().delete_row(rowid) {
+            continue;
+        }
+        deleted.push((rowid, old.clone()));
+        count += 1;
+        if db.records_changes() {
+            db.record_change(
+                AuditOperation::Delete,
+                table_name,
+                rowid,
+                &columns,
+                Some(old),
+                None,
+            );
+        }
+        fire_triggers(
+            db,
+            table_name,
+            TriggerTiming::After,
+            &TriggerEvent::Delete,
+            Some(old),
+            None,
+        )?;
+    }
+    db.counters.record(count);
+    Ok(count)
+}
+
+/// Deletes the rows of a virtual table satisfying `selection`, every row without one, or
+/// those of them `limit` bounds the statement to, returning the number of rows deleted.
+pub fn delete_from_virtual_table(
+    db: &mut Database,
+    table_name: &str,
+    selection: Option<&Expr>,
+    limit: Option<&ModifyLimit>,
+) -> Result<i64> {
+    db.check_privilege(Privilege::Delete, Some(table_name))?;
+    let table = match db.vtabs.get_table(table_name) {
+        Some(table) => table.clone(),
+        None => return Err(SQLRiteError::Internal("Table doesn't exist".to_string())),
+    };
+    let (constraints, residual) = match selection {
+        Some(selection) => split_selection(selection, db)?,
+        None => (vec![], vec![]),
+    };
+    let source = Source::from_virtual_table(table.as_ref(), table_name, &[], &constraints)?;
+
+    // Rows are collected first, the table can't be changed while it is being read
+    let mut deleted: Vec<(&Vec<Value>, ())> = vec![];
+    for row in &source.rows {
+        db.progress.step()?;
+        if satisfies(&residual, db, &RowContext::new(&source.columns, row))? {
+            deleted.push((row, ()));
+        }
+    }
+    if let Some(limit) = limit {
+        deleted = limit_rows(db, &source.columns, deleted, limit)?;
+    }
+    for (row, _) in &deleted {
+        table.delete(row)?;
+    }
+    db.counters.record(deleted.len() as i64);
+    Ok(deleted.len() as i64)
+}
+
+/// The rows, of columns `columns`, a statement ending with the ORDER BY and LIMIT clauses
+/// `limit` changes out of `rows`, those it changes without the clauses, each row with what
+/// identifies it. Rows sorting the same keep their order.
+fn limit_rows<'a, T>(
+    db: &Database,
+    columns: &[SourceColumn],
+    rows: Vec<(&'a Vec<Value>, T)>,
+    limit: &ModifyLimit,
+) -> Result<Vec<(&'a Vec<Value>, T)>> {
+    let collations = limit
+        .order_by
+        .iter()
+        .map(|term| order_by_collation(term.collation.as_ref(), &term.expr, db, columns))
+        .collect::<Result<Vec<Arc<CollationFn>>>>()?;
+    let mut keyed = rows
+        .into_iter()
+        .map(|row| {
+            let context = RowContext::new(columns, row.0);
+            let keys = limit
+                .order_by
+                .iter()
+                .map(|term| eval_expr(&term.expr, db, &context))
+                .collect::<Result<Vec<Value>>>()?;
+            Ok((keys, row))
+        })
+        .collect::<Result<Vec<(Vec<Value>, (&Vec<Value>, T))>>>()?;
+    keyed.sort_by(|(a, _), (b, _)| compare_keys(&limit.order_by, &collations, a, b));
+
+    let count = |expr: &Expr| {
+        eval_expr(expr, db, &RowContext::new(&[], &[]))?
+            .to_integer()
+            .ok_or_else(|| SQLRiteError::General(format!("{} is not an integer", expr)))
+    };
+    // A negative LIMIT doesn't bound the rows, like in SQLite
+    let most = usize::try_from(count(&limit.limit)?).unwrap_or(usize::MAX);
+    let offset = match &limit.offset {
+        Some(offset) => usize::try_from(count(offset)?).unwrap_or(0),
+        None => 0,
+    };
+    Ok(keyed
+        .into_iter()
+        .skip(offset)
+        .take(most)
+        .map(|(_, row)| row)
+        .collect())
+}
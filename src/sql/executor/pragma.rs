@@ -0,0 +1,632 @@
+//! Execution of PRAGMA statements, which read and change the settings of the connection
+//! and describe the schema, returning their results as rows like a query.
+use crate::error::{Result, SQLRiteError};
+use crate::sql::db::audit::AuditMode;
+use crate::sql::db::database::{Database, MAIN_SCHEMA, TEMP_SCHEMA};
+use crate::sql::db::file::free_page_count;
+use crate::sql::db::pager::MAX_MMAP_SIZE;
+use crate::sql::db::privilege::Privilege;
+use crate::sql::db::table::DataType;
+use crate::sql::db::temp::TempStoreMode;
+use crate::sql::executor::{ResultColumn, ResultSet};
+use crate::sql::parser::pragma::PragmaStatement;
+use crate::sql::value::Value;
+use std::path::PathBuf;
+
+/// The journal modes accepted by PRAGMA journal_mode
+const JOURNAL_MODES: [&str; 6] = ["delete", "truncate", "persist", "memory", "wal", "off"];
+
+/// Runs a PRAGMA reading a setting or describing the schema. Like in SQLite, unknown
+/// pragmas are ignored and return no rows.
+pub fn query_pragma(db: &Database, pragma: &PragmaStatement) -> Result<ResultSet> {
+    if let Some(schema_name) = &pragma.schema_name {
+        if !db.contains_schema(schema_name) {
+            return Err(SQLRiteError::General(format!(
+                "unknown database {}",
+                schema_name
+            )));
+        }
+    }
+    let name = pragma.name.to_lowercase();
+    if pragma.value.is_some() && is_pragma_setting(&name) {
+        return Err(SQLRiteError::General(format!(
+            "PRAGMA {} changes the database, it has to be executed",
+            pragma.name
+        )));
+    }
+    match name.as_str() {
+        "table_info" => table_info(db, pragma),
+        "index_list" => index_list(db, pragma),
+        "bloom_filter_list" => bloom_filter_list(db, pragma),
+        "dictionary_list" => dictionary_list(db, pragma),
+        "database_list" => Ok(database_list(db)),
+        "user_version" => Ok(single_value(&name, Value::Integer(db.user_version))),
+        "schema_version" => Ok(single_value(&name, Value::Integer(db.schema_version))),
+        "foreign_keys" => Ok(single_value(
+            &name,
+            Value::Integer(i64::from(db.settings.foreign_keys)),
+        )),
+        "cache_size" => Ok(single_value(&name, Value::Integer(db.settings.cache_size))),
+        "result_cache" => Ok(single_value(
+            &name,
+            Value::Integer(db.result_cache.capacity() as i64),
+        )),
+        "profile" => Ok(single_value(
+            &name,
+            Value::Integer(i64::from(db.settings.profile)),
+        )),
+        "soft_heap_limit" => Ok(single_value(
+            &name,
+            Value::Integer(db.memory.soft_limit() as i64),
+        )),
+        "hard_heap_limit" => Ok(single_value(
+            &name,
+            Value::Integer(db.memory.hard_limit() as i64),
+        )),
+        "journal_mode" => Ok(single_value(
+            &name,
+            Value::Text(db.settings.journal_mode.to_string()),
+        )),
+        "mmap_size" => Ok(single_value(
+            &name,
+            Value::Integer(db.settings.mmap_size as i64),
+        )),
+        "freelist_count" => {
+            let count = db.path.as_deref().map_or(Ok(0), free_page_count)?;
+            Ok(single_value(&name, Value::Integer(count as i64)))
+        }
+        "temp_store" => Ok(single_value(
+            &name,
+            Value::Integer(db.temp.mode().to_pragma()),
+        )),
+        "temp_store_directory" => Ok(single_value(
+            &name,
+            db.temp.directory().map_or(Value::Null, |directory| {
+                Value::Text(directory.display().to_string())
+            }),
+        )),
+        "audit" => Ok(single_value(&name, Value::Text(db.audit.mode.to_pragma()))),
+        "integrity_check" => Ok(integrity_check(db)),
+        _ => Ok(ResultSet::default()),
+    }
+}
+
+/// `PRAGMA integrity_check`: one row per problem found, or a single `ok` row.
+fn integrity_check(db: &Database) -> ResultSet {
+    let mut problems = db.check_integrity();
+    if problems.is_empty() {
+        problems.push("ok".to_string());
+    }
+    ResultSet {
+        columns: result_columns(&["integrity_check"]),
+        rows: problems
+            .into_iter()
+            .map(|problem| vec![Value::Text(problem)])
+            .collect(),
+    }
+}
+
+/// Runs a PRAGMA, changing the setting it names first when it is given a value.
+pub fn execute_pragma(db: &mut Database, pragma: &PragmaStatement) -> Result<ResultSet> {
+    let name = pragma.name.to_lowercase();
+    let value = match &pragma.value {
+        Some(value) if is_pragma_setting(&name) => value,
+        _ => return query_pragma(db, pragma),
+    };
+    // Settings are shared by everyone using the database
+    db.check_privilege(Privilege::Ddl, None)?;
+    match name.as_str() {
+        "user_version" => db.user_version = value.to_integer().unwrap_or(0),
+        "schema_version" => db.schema_version = value.to_integer().unwrap_or(0),
+        "cache_size" => db.settings.cache_size = value.to_integer().unwrap_or(0),
+        "result_cache" => db
+            .result_cache
+            .set_capacity(value.to_integer().unwrap_or(0).max(0) as usize),
+        // Negative limits leave the limit unchanged, like in SQLite
+        "soft_heap_limit" | "hard_heap_limit" => {
+            if let Some(limit) = value.to_integer().filter(|limit| *limit >= 0) {
+                if name == "soft_heap_limit" {
+                    db.memory.set_soft_limit(limit as usize);
+                } else {
+                    db.memory.set_hard_limit(limit as usize);
+                }
+            }
+        }
+        // Negative sizes go back to the default, and sizes past the greatest are capped to
+        // it, none without the `mmap` feature, like in SQLite
+        "mmap_size" => {
+            if let Some(size) = value.to_integer() {
+                db.settings.mmap_size = (size.max(0) as u64).min(MAX_MMAP_SIZE);
+            }
+        }
+        "audit" => db.audit.mode = AuditMode::from_pragma(value)?,
+        "temp_store" => db.temp.set_mode(TempStoreMode::from_pragma(value)?)?,
+        // An empty directory goes back to the temporary directory of the system
+        "temp_store_directory" => {
+            let directory = value.to_text().filter(|directory| !directory.is_empty());
+            db.temp.set_directory(directory.map(PathBuf::from))?
+        }
+        "foreign_keys" => {
+            // Values that aren't booleans leave the setting unchanged
+            if let Some(enabled) = to_flag(value) {
+                db.settings.foreign_keys = enabled;
+            }
+            return Ok(ResultSet::default());
+        }
+        "profile" => {
+            if let Some(enabled) = to_flag(value) {
+                db.set_profile(enabled);
+            }
+            return Ok(ResultSet::default());
+        }
+        "journal_mode" => {
+            let mode = value.to_text().unwrap_or_default().to_lowercase();
+            if !JOURNAL_MODES.contains(&mode.as_str()) {
+                return Err(SQLRiteError::General(format!(
+                    "unknown journal mode: {}",
+                    mode
+                )));
+            }
+            // An in-memory database has no journal file, and the pager no write-ahead log,
+            // the modes they would need are ignored
+            let supported = match db.path {
+                Some(_) => mode != "wal",
+                None => mode == "memory" || mode == "off",
+            };
+            if supported {
+                db.settings.journal_mode = mode;
+            }
+        }
+        _ => {}
+    }
+    let pragma = PragmaStatement {
+        value: None,
+        ..pragma.clone()
+    };
+    match name.as_str() {
+        // Like in SQLite, setting the journal mode, the mapped size or a heap limit returns
+        // the resulting one
+        "journal_mode" | "mmap_size" | "soft_heap_limit" | "hard_heap_limit" => {
+            query_pragma(db, &pragma)
+        }
+        _ => Ok(ResultSet::default()),
+    }
+}
+
+/// Returns true if the pragma names a setting changed by giving it a value.
+pub fn is_pragma_setting(name: &str) -> bool {
+    matches!(
+        name.to_lowercase().as_str(),
+        "user_version"
+            | "schema_version"
+            | "foreign_keys"
+            | "cache_size"
+            | "result_cache"
+            | "soft_heap_limit"
+            | "hard_heap_limit"
+            | "journal_mode"
+            | "mmap_size"
+            | "audit"
+            | "profile"
+            | "temp_store"
+            | "temp_store_directory"
+    )
+}
+
+/// Reads a boolean setting the way SQLite does.
+fn to_flag(value: &Value) -> Option<bool> {
+    match value {
+        Value::Text(text) => match text.to_lowercase().as_str() {
+            "on" | "yes" | "true" => Some(true),
+            "off" | "no" | "false" => Some(false),
+            _ => None,
+        },
+        value => value.to_bool(),
+    }
+}
+
+fn result_columns(names: &[&str]) -> Vec<ResultColumn> {
+    names
+        .iter()
+        .map(|name| ResultColumn {
+            name: name.to_string(),
+            datatype: None,
+        })
+        .collect()
+}
+
+fn single_value(name: &str, value: Value) -> ResultSet {
+    ResultSet {
+        columns: result_columns(&[name]),
+        rows: vec![vec![value]],
+    }
+}
+
+/// Returns the key of the table the pragma's argument names, qualified with its schema.
+fn table_argument(db: &Database, pragma: &PragmaStatement) -> Result<Option<String>> {
+    let table_name = match pragma.value.as_ref().and_then(|value| value.to_text()) {
+        Some(table_name) => table_name,
+        None => return Ok(None),
+    };
+    let key = match &pragma.schema_name {
+        Some(schema_name) => db.table_key(&format!("{}.{}", schema_name, table_name))?,
+        None => db.resolve_table_name(&table_name)?,
+    };
+    Ok(Some(key))
+}
+
+/// `PRAGMA table_info(table)`: one row per column of the table, no rows if there is none.
+fn table_info(db: &Database, pragma: &PragmaStatement) -> Result<ResultSet> {
+    let mut result = ResultSet {
+        columns: result_columns(&["cid", "name", "type", "notnull", "dflt_value", "pk"]),
+        rows: vec![],
+    };
+    let table_name = match table_argument(db, pragma)? {
+        Some(table_name) => table_name,
+        None => return Ok(result),
+    };
+    if let Some(table) = db.tables.get(&table_name) {
+        for (cid, column) in table.columns.iter().enumerate() {
+            result.rows.push(vec![
+                Value::Integer(cid as i64),
+                Value::Text(column.column_name.to_string()),
+                Value::Text(type_name(&column.datatype)),
+                Value::Integer(i64::from(column.not_null)),
+                Value::Null,
+                Value::Integer(i64::from(column.is_pk)),
+            ]);
+        }
+    } else if let Some(table) = db.vtabs.get_table(&table_name) {
+        for (cid, column) in table
+            .columns()
+            .iter()
+            .filter(|column| !column.hidden)
+            .enumerate()
+        {
+            result.rows.push(vec![
+                Value::Integer(cid as i64),
+                Value::Text(column.name.to_string()),
+                Value::Text(type_name(&column.datatype)),
+                Value::Integer(0),
+                Value::Null,
+                Value::Integer(0),
+            ]);
+        }
+    }
+    Ok(result)
+}
+
+/// Declared type of a column, as SQLite reports it.
+fn type_name(datatype: &DataType) -> String {
+    match datatype {
+        DataType::None | DataType::Invalid => String::new(),
+        datatype => datatype.to_string().to_uppercase(),
+    }
+}
+
+/// `PRAGMA index_list(table)`: one row per index of the table. UNIQUE columns and
+/// PRIMARY KEY columns that aren't the INTEGER rowid are indexed automatically.
+fn index_list(db: &Database, pragma: &PragmaStatement) -> Result<ResultSet> {
+    let mut result = ResultSet {
+        columns: result_columns(&["seq", "name", "unique", "origin", "partial"]),
+        rows: vec![],
+    };
+    let table_name = match table_argument(db, pragma)? {
+        Some(table_name) => table_name,
+        None => return Ok(result),
+    };
+    if let Some(table) = db.tables.get(&table_name) {
+        for (seq, (name, column)) in table.autoindexes().into_iter().enumerate() {
+            result.rows.push(vec![
+                Value::Integer(seq as i64),
+                Value::Text(name),
+                Value::Integer(1),
+                Value::Text(if column.is_pk { "pk" } else { "u" }.to_string()),
+                Value::Integer(0),
+            ]);
+        }
+    }
+    Ok(result)
+}
+
+/// `PRAGMA bloom_filter_list(table)`: the indexes of the table that have a bloom filter,
+/// with its false positive rate.
+fn bloom_filter_list(db: &Database, pragma: &PragmaStatement) -> Result<ResultSet> {
+    let mut result = ResultSet {
+        columns: result_columns(&["name", "column", "false_positive_rate"]),
+        rows: vec![],
+    };
+    let table_name = match table_argument(db, pragma)? {
+        Some(table_name) => table_name,
+        None => return Ok(result),
+    };
+    if let Some(table) = db.tables.get(&table_name) {
+        for (name, column) in table.autoindexes() {
+            if let Some(filter) = &column.bloom_filter {
+                result.rows.push(vec![
+                    Value::Text(name),
+                    Value::Text(column.column_name.to_string()),
+                    Value::Real(filter.false_positive_rate),
+                ]);
+            }
+        }
+    }
+    Ok(result)
+}
+
+/// `PRAGMA dictionary_list(table)`: the dictionary encoded columns of the table, with
+/// the number of distinct values in their dictionary.
+fn dictionary_list(db: &Database, pragma: &PragmaStatement) -> Result<ResultSet> {
+    let mut result = ResultSet {
+        columns: result_columns(&["column", "values"]),
+        rows: vec![],
+    };
+    let table_name = match table_argument(db, pragma)? {
+        Some(table_name) => table_name,
+        None => return Ok(result),
+    };
+    if let Some(table) = db.tables.get(&table_name) {
+        for column in &table.columns {
+            if let Some(size) = table.dictionary_size(&column.column_name) {
+                result.rows.push(vec![
+                    Value::Text(column.column_name.to_string()),
+                    Value::Integer(size as i64),
+                ]);
+            }
+        }
+    }
+    Ok(result)
+}
+
+/// `PRAGMA database_list`: the main database, the temp schema once it has tables, and the
+/// attached databases, with the files they are saved to, empty for in-memory databases.
+/// Attached databases are numbered from 2, 1 is the temp schema like in SQLite.
+fn database_list(db: &Database) -> ResultSet {
+    let file = |path: Option<&std::path::PathBuf>| {
+        Value::Text(path.map_or(String::new(), |path| path.display().to_string()))
+    };
+    let mut rows = vec![vec![
+        Value::Integer(0),
+        Value::Text(MAIN_SCHEMA.to_string()),
+        file(db.path.as_ref()),
+    ]];
+    let temp_prefix = format!("{}.", TEMP_SCHEMA);
+    if db.tables.keys().any(|name| name.starts_with(&temp_prefix)) {
+        rows.push(vec![
+            Value::Integer(1),
+            Value::Text(TEMP_SCHEMA.to_string()),
+            file(None),
+        ]);
+    }
+    for (i, schema_name) in db.attached.iter().enumerate() {
+        rows.push(vec![
+            Value::Integer(i as i64 + 2),
+            Value::Text(schema_name.to_string()),
+            file(db.attached_files.get(schema_name).map(|file| &file.path)),
+        ]);
+    }
+    ResultSet {
+        columns: result_columns(&["seq", "name", "file"]),
+        rows,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sql::{process_command, process_query};
+
+    #[test]
+    fn table_info_and_index_list_test() {
+        let mut db = Database::new("tempdb".to_string());
+        process_command(
+            "CREATE TABLE users (id INTEGER PRIMARY KEY, name TEXT NOT NULL, email TEXT UNIQUE);",
+            &mut db,
+        )
+        .unwrap();
+
+        let result = process_query("PRAGMA table_info(users);", &db).unwrap();
+        assert_eq!(
+            result
+                .columns
+                .iter()
+                .map(|col| col.name.as_str())
+                .collect::<Vec<&str>>(),
+            vec!["cid", "name", "type", "notnull", "dflt_value", "pk"]
+        );
+        assert_eq!(
+            result.rows[1],
+            vec![
+                Value::Integer(1),
+                Value::Text("name".to_string()),
+                Value::Text("TEXT".to_string()),
+                Value::Integer(1),
+                Value::Null,
+                Value::Integer(0),
+            ]
+        );
+        assert_eq!(result.rows[0][5], Value::Integer(1));
+        assert!(process_query("PRAGMA table_info(missing);", &db)
+            .unwrap()
+            .rows
+            .is_empty());
+
+        let result = process_query("PRAGMA index_list(users);", &db).unwrap();
+        assert_eq!(
+            result.rows,
+            vec![vec![
+                Value::Integer(0),
+                Value::Text("sqlite_autoindex_users_1".to_string()),
+                Value::Integer(1),
+                Value::Text("u".to_string()),
+                Value::Integer(0),
+            ]]
+        );
+
+        db.get_table_mut("users".to_string())
+            .unwrap()
+            .set_bloom_filter("email", Some(0.05))
+            .unwrap();
+        let result = process_query("PRAGMA bloom_filter_list(users);", &db).unwrap();
+        assert_eq!(
+            result.rows,
+            vec![vec![
+                Value::Text("sqlite_autoindex_users_1".to_string()),
+                Value::Text("email".to_string()),
+                Value::Real(0.05),
+            ]]
+        );
+
+        db.get_table_mut("users".to_string())
+            .unwrap()
+            .set_dictionary_encoding("name", true)
+            .unwrap();
+        let result = process_query("PRAGMA dictionary_list(users);", &db).unwrap();
+        assert_eq!(
+            result.rows,
+            vec![vec![Value::Text("name".to_string()), Value::Integer(0)]]
+        );
+
+        let result = process_query("PRAGMA integrity_check;", &db).unwrap();
+        assert_eq!(result.rows, vec![vec![Value::Text("ok".to_string())]]);
+    }
+
+    #[test]
+    fn settings_test() {
+        let mut db = Database::new("tempdb".to_string());
+        let value = |db: &Database, sql: &str| process_query(sql, db).unwrap().rows[0][0].clone();
+
+        assert_eq!(value(&db, "PRAGMA user_version;"), Value::Integer(0));
+        process_command("PRAGMA user_version = 7;", &mut db).unwrap();
+        assert_eq!(value(&db, "PRAGMA user_version;"), Value::Integer(7));
+        assert!(process_query("PRAGMA user_version = 8;", &db).is_err());
+
+        process_command("CREATE TABLE users (id INTEGER PRIMARY KEY);", &mut db).unwrap();
+        assert_eq!(value(&db, "PRAGMA schema_version;"), Value::Integer(1));
+
+        process_command("PRAGMA foreign_keys = ON;", &mut db).unwrap();
+        assert_eq!(value(&db, "PRAGMA foreign_keys;"), Value::Integer(1));
+        process_command("PRAGMA cache_size = -4000;", &mut db).unwrap();
+        assert_eq!(value(&db, "PRAGMA cache_size;"), Value::Integer(-4000));
+
+        assert_eq!(value(&db, "PRAGMA temp_store;"), Value::Integer(0));
+        process_command("PRAGMA temp_store = FILE;", &mut db).unwrap();
+        assert_eq!(value(&db, "PRAGMA temp_store;"), Value::Integer(1));
+        assert!(process_command("PRAGMA temp_store = disk;", &mut db).is_err());
+        let directory = std::env::temp_dir().display().to_string();
+        let sql = format!("PRAGMA temp_store_directory = '{}';", directory);
+        process_command(&sql, &mut db).unwrap();
+        assert_eq!(
+            value(&db, "PRAGMA temp_store_directory;"),
+            Value::Text(directory)
+        );
+        assert!(process_command("PRAGMA temp_store_directory = '/no/such/dir';", &mut db).is_err());
+        // Temporary files a statement leaves behind are removed when it ends
+        std::mem::forget(db.temp.create().unwrap());
+        assert_eq!(db.temp.open_files(), 1);
+        process_command("PRAGMA temp_store_directory = '';", &mut db).unwrap();
+        assert_eq!(db.temp.open_files(), 0);
+        assert_eq!(value(&db, "PRAGMA temp_store_directory;"), Value::Null);
+        process_command("PRAGMA temp_store = 2;", &mut db).unwrap();
+
+        let result = execute_pragma(
+            &mut db,
+            &crate::sql::parser::pragma::parse_pragma("PRAGMA journal_mode = WAL;")
+                .unwrap()
+                .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(result.rows, vec![vec![Value::Text("memory".to_string())]]);
+        assert!(process_command("PRAGMA journal_mode = fast;", &mut db).is_err());
+        // A database file has the journal modes of a file
+        db.path = Some(std::env::temp_dir().join("sqlrite-pragma.db"));
+        process_command("PRAGMA journal_mode = persist;", &mut db).unwrap();
+        process_command("PRAGMA journal_mode = wal;", &mut db).unwrap();
+        assert_eq!(
+            value(&db, "PRAGMA journal_mode;"),
+            Value::Text("persist".to_string())
+        );
+        db.path = None;
+
+        assert_eq!(value(&db, "PRAGMA mmap_size;"), Value::Integer(0));
+        process_command("PRAGMA mmap_size = 1099511627776;", &mut db).unwrap();
+        assert_eq!(
+            value(&db, "PRAGMA mmap_size;"),
+            Value::Integer(MAX_MMAP_SIZE as i64)
+        );
+        process_command("PRAGMA mmap_size = -1;", &mut db).unwrap();
+        assert_eq!(value(&db, "PRAGMA mmap_size;"), Value::Integer(0));
+        // An in-memory database has no file, nor free pages
+        assert_eq!(value(&db, "PRAGMA freelist_count;"), Value::Integer(0));
+
+        process_command("ATTACH ':memory:' AS aux;", &mut db).unwrap();
+        let result = process_query("PRAGMA database_list;", &db).unwrap();
+        assert_eq!(result.rows.len(), 2);
+        assert_eq!(result.rows[1][1], Value::Text("aux".to_string()));
+
+        // Unknown pragmas are ignored
+        assert!(process_query("PRAGMA no_such_pragma;", &db)
+            .unwrap()
+            .rows
+            .is_empty());
+    }
+
+    #[test]
+    fn heap_limit_test() {
+        let mut db = Database::new("tempdb".to_string());
+        process_command(
+            "CREATE TABLE users (id INTEGER PRIMARY KEY, email TEXT UNIQUE);",
+            &mut db,
+        )
+        .unwrap();
+        for i in 0..50 {
+            let sql = format!(
+                "INSERT INTO users (id, email) VALUES ({}, 'user{}@example.com');",
+                i, i
+            );
+            process_command(&sql, &mut db).unwrap();
+        }
+
+        process_command("PRAGMA hard_heap_limit = 2000;", &mut db).unwrap();
+        let limit = process_query("PRAGMA hard_heap_limit;", &db).unwrap();
+        assert_eq!(limit.rows, vec![vec![Value::Integer(2000)]]);
+        let err = process_query("SELECT * FROM users ORDER BY email;", &db).unwrap_err();
+        assert!(matches!(err, SQLRiteError::OutOfMemory(_)));
+        assert_eq!(db.memory.used(), 0);
+        // Negative limits leave it unchanged, 0 removes it
+        process_command("PRAGMA hard_heap_limit = -1;", &mut db).unwrap();
+        assert_eq!(db.memory.hard_limit(), 2000);
+        process_command("PRAGMA hard_heap_limit = 0;", &mut db).unwrap();
+        let result = process_query("SELECT * FROM users ORDER BY email;", &db).unwrap();
+        assert_eq!(result.rows.len(), 50);
+
+        // Caches are released once a statement went past the soft limit
+        process_command("PRAGMA soft_heap_limit = 100;", &mut db).unwrap();
+        db.tables
+            .get_mut("users")
+            .unwrap()
+            .set_bloom_filter("email", Some(0.01))
+            .unwrap();
+        process_command("PRAGMA user_version = 1;", &mut db).unwrap();
+        assert!(db.cache_memory() > 0);
+        process_query("SELECT * FROM users;", &db).unwrap();
+        process_command("PRAGMA user_version = 1;", &mut db).unwrap();
+        assert_eq!(db.cache_memory(), 0);
+
+        // and when they grow past the cache size
+        process_command("PRAGMA soft_heap_limit = 0;", &mut db).unwrap();
+        process_command(
+            "INSERT INTO users (id, email) VALUES (50, 'new@example.com');",
+            &mut db,
+        )
+        .unwrap();
+        assert!(db.cache_memory() > 0);
+        process_command("PRAGMA cache_size = -1;", &mut db).unwrap();
+        assert_eq!(db.cache_memory(), 0);
+        assert!(process_command(
+            "INSERT INTO users (id, email) VALUES (51, 'new@example.com');",
+            &mut db
+        )
+        .is_err());
+    }
+}
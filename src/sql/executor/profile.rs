@@ -0,0 +1,340 @@
+//! The plan of a query as the operators it runs, one after the other: the scan of its
+//! table, the filters of its WHERE clause, the sort of its ORDER BY and the projection of
+//! its result columns. `EXPLAIN QUERY PLAN` shows the operators with the number of rows
+//! the planner estimates each one produces. `EXPLAIN ANALYZE` runs the query and shows
+//! next to the estimates the rows each operator actually produced, how many times it ran
+//! and the time spent in it, so estimates far from the actual rows stand out.
+//!
+//! Operators are only timed for `EXPLAIN ANALYZE` and while `PRAGMA profile` is on, and
+//! never on wasm32, which has no clock. While it is on, the `Profiler` of the database keeps
+//! what each operator of the last statements did in the `sqlrite_profile` table, so that a
+//! statement run again and again can be measured without running it under EXPLAIN ANALYZE.
+use std::collections::VecDeque;
+use std::sync::{Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+use crate::sql::executor::{ResultColumn, ResultSet};
+use crate::sql::value::Value;
+
+/// Name the profiles of the last statements are queried under
+pub const PROFILE_TABLE_NAME: &str = "sqlrite_profile";
+
+/// Number of statements the profiler keeps the profile of
+pub const PROFILED_STATEMENTS: usize = 1000;
+
+/// An operator of the plan of a query, with what it did once the query ran
+#[derive(Debug, PartialEq, Clone)]
+pub struct Operator {
+    /// Position of the operator in the plan, from 1
+    pub id: usize,
+    /// Id of the operator consuming the rows this one produces, 0 for the last one
+    pub parent: usize,
+    /// What the operator does, like `SCAN users`
+    pub detail: String,
+    /// Rows the planner estimates the operator produces, if it can tell
+    pub estimated_rows: Option<f64>,
+    /// Rows produced
+    pub rows: usize,
+    /// Number of times the operator ran
+    pub loops: usize,
+    /// Time spent in the operator
+    pub time: Duration,
+    /// Most bytes of memory the operator held at once
+    pub memory: usize,
+}
+
+/// The operators of a query, in the order rows flow through them
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct QueryProfile {
+    pub operators: Vec<Operator>,
+    /// Whether the time spent in each operator is measured
+    pub timed: bool,
+}
+
+impl QueryProfile {
+    /// Adds an operator consuming the rows of the last one added, and returns its id.
+    pub fn add(&mut self, detail: String, estimated_rows: Option<f64>) -> usize {
+        let id = self.operators.len() + 1;
+        if let Some(last) = self.operators.last_mut() {
+            last.parent = id;
+        }
+        self.operators.push(Operator {
+            id,
+            parent: 0,
+            detail,
+            estimated_rows,
+            rows: 0,
+            loops: 0,
+            time: Duration::ZERO,
+            memory: 0,
+        });
+        id
+    }
+
+    /// Adds the operators of `other`, the plan of a query whose rows an operator added next
+    /// combines with other rows, like a SELECT of a compound SELECT. Returns the id its last
+    /// operator has now.
+    pub fn append(&mut self, other: QueryProfile) -> usize {
+        let offset = self.operators.len();
+        for mut operator in other.operators {
+            operator.id += offset;
+            if operator.parent != 0 {
+                operator.parent += offset;
+            }
+            self.operators.push(operator);
+        }
+        self.operators.len()
+    }
+
+    /// Records that the operator `id` ran once more.
+    pub fn begin(&mut self, id: usize) {
+        self.operators[id - 1].loops += 1;
+    }
+
+    /// The time an operator starts working at, `None` when operators aren't timed.
+    pub fn now(&self) -> Option<Instant> {
+        if self.timed && !cfg!(target_arch = "wasm32") {
+            Some(Instant::now())
+        } else {
+            None
+        }
+    }
+
+    /// Adds to the operator `id` the `rows` it produced and the time spent since `started`.
+    pub fn record(&mut self, id: usize, started: Option<Instant>, rows: usize) {
+        let operator = &mut self.operators[id - 1];
+        operator.rows += rows;
+        if let Some(started) = started {
+            operator.time += started.elapsed();
+        }
+    }
+
+    /// Records that the operator `id` holds `bytes` of memory.
+    pub fn record_memory(&mut self, id: usize, bytes: usize) {
+        let operator = &mut self.operators[id - 1];
+        operator.memory = operator.memory.max(bytes);
+    }
+
+    /// The plan as `EXPLAIN QUERY PLAN` shows it, with the columns SQLite has.
+    pub fn plan(&self) -> ResultSet {
+        ResultSet {
+            columns: result_columns(&["id", "parent", "notused", "detail"]),
+            rows: self
+                .operators
+                .iter()
+                .map(|operator| {
+                    vec![
+                        Value::Integer(operator.id as i64),
+                        Value::Integer(operator.parent as i64),
+                        Value::Integer(0),
+                        Value::Text(operator.detail.to_string()),
+                    ]
+                })
+                .collect(),
+        }
+    }
+
+    /// The plan with what each operator did, as `EXPLAIN ANALYZE` shows it. Times are in
+    /// milliseconds.
+    pub fn analysis(&self) -> ResultSet {
+        ResultSet {
+            columns: result_columns(&[
+                "id",
+                "parent",
+                "detail",
+                "estimated_rows",
+                "rows",
+                "loops",
+                "time_ms",
+                "memory",
+            ]),
+            rows: self
+                .operators
+                .iter()
+                .map(|operator| {
+                    vec![
+                        Value::Integer(operator.id as i64),
+                        Value::Integer(operator.parent as i64),
+                        Value::Text(operator.detail.to_string()),
+                        operator
+                            .estimated_rows
+                            .map_or(Value::Null, |rows| Value::Integer(rows.round() as i64)),
+                        Value::Integer(operator.rows as i64),
+                        Value::Integer(operator.loops as i64),
+                        Value::Real(operator.time.as_secs_f64() * 1000.0),
+                        Value::Integer(operator.memory as i64),
+                    ]
+                })
+                .collect(),
+        }
+    }
+}
+
+/// The profiles of the last statements run while profiling was on
+#[derive(Debug, Default)]
+pub struct Profiler {
+    log: Mutex<ProfileLog>,
+}
+
+#[derive(Debug, Default)]
+struct ProfileLog {
+    /// Number of statements profiled since the profiler was last cleared
+    statements: i64,
+    /// The profiles of the last statements with their number and SQL, oldest first
+    profiles: VecDeque<(i64, String, QueryProfile)>,
+}
+
+impl Profiler {
+    /// Keeps the profile of the statement `sql`, forgetting the oldest one kept if there are
+    /// too many.
+    pub fn record(&self, sql: &str, profile: QueryProfile) {
+        let mut log = self.log();
+        log.statements += 1;
+        let statement = log.statements;
+        log.profiles
+            .push_back((statement, sql.trim().to_string(), profile));
+        if log.profiles.len() > PROFILED_STATEMENTS {
+            log.profiles.pop_front();
+        }
+    }
+
+    /// Forgets the profiles kept.
+    pub fn clear(&self) {
+        *self.log() = ProfileLog::default();
+    }
+
+    /// Number of statements profiled since the profiler was last cleared.
+    pub fn statements(&self) -> i64 {
+        self.log().statements
+    }
+
+    /// The columns of the `sqlrite_profile` table.
+    pub fn columns() -> Vec<ResultColumn> {
+        result_columns(&[
+            "stmt",
+            "sql",
+            "id",
+            "parent",
+            "detail",
+            "estimated_rows",
+            "rows",
+            "loops",
+            "time_ms",
+            "memory",
+        ])
+    }
+
+    /// The rows of the `sqlrite_profile` table: one per operator of each statement kept, its
+    /// number and SQL followed by the columns of `EXPLAIN ANALYZE`.
+    pub fn rows(&self) -> Vec<Vec<Value>> {
+        let log = self.log();
+        let mut rows = vec![];
+        for (statement, sql, profile) in &log.profiles {
+            for row in profile.analysis().rows {
+                let mut values = vec![Value::Integer(*statement), Value::Text(sql.to_string())];
+                values.extend(row);
+                rows.push(values);
+            }
+        }
+        rows
+    }
+
+    fn log(&self) -> MutexGuard<'_, ProfileLog> {
+        // A statement panicking while recording leaves the log usable
+        self.log.lock().unwrap_or_else(|err| err.into_inner())
+    }
+}
+
+/// Profilers are always equal, the statements they profiled aren't part of the database.
+impl PartialEq for Profiler {
+    fn eq(&self, _: &Self) -> bool {
+        true
+    }
+}
+
+fn result_columns(names: &[&str]) -> Vec<ResultColumn> {
+    names
+        .iter()
+        .map(|name| ResultColumn {
+            name: name.to_string(),
+            datatype: None,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sql::db::database::Database;
+    use crate::sql::{process_command, process_query};
+
+    #[test]
+    fn profiler_test() {
+        let mut db = Database::new("tempdb".to_string());
+        process_command(
+            "CREATE TABLE users (id INTEGER PRIMARY KEY, age INTEGER);",
+            &mut db,
+        )
+        .unwrap();
+        process_command("PRAGMA profile = on;", &mut db).unwrap();
+        process_command("INSERT INTO users (age) VALUES (20), (30), (40);", &mut db).unwrap();
+        process_query("SELECT id FROM users WHERE age > 25 ORDER BY id;", &db).unwrap();
+        process_query("SELECT id FROM users WHERE age > 25 ORDER BY id;", &db).unwrap();
+        assert_eq!(db.profiler.statements(), 3);
+
+        let result = process_query(
+            "SELECT stmt, id, detail, rows FROM sqlrite_profile WHERE stmt >= 2;",
+            &db,
+        )
+        .unwrap();
+        let row = |stmt: i64, id: i64, detail: &str, rows: i64| {
+            vec![
+                Value::Integer(stmt),
+                Value::Integer(id),
+                Value::Text(detail.to_string()),
+                Value::Integer(rows),
+            ]
+        };
+        assert_eq!(
+            result.rows[..4],
+            [
+                row(2, 1, "SCAN users", 3),
+                row(2, 2, "FILTER age > 25", 2),
+                row(2, 3, "PROJECT id", 2),
+                row(2, 4, "SORT BY id", 2),
+            ]
+        );
+        assert_eq!(result.rows.len(), 8);
+        let result = process_query(
+            "SELECT sql, detail, rows, memory FROM sqlrite_profile WHERE stmt = 1;",
+            &db,
+        )
+        .unwrap();
+        assert_eq!(
+            result.rows,
+            vec![vec![
+                Value::Text("INSERT INTO users (age) VALUES (20), (30), (40);".to_string()),
+                Value::Text("INSERT".to_string()),
+                Value::Integer(3),
+                Value::Integer(0),
+            ]]
+        );
+        let result = process_query(
+            "SELECT memory FROM sqlrite_profile WHERE stmt = 2 AND id = 1;",
+            &db,
+        )
+        .unwrap();
+        assert!(result.rows[0][0].to_integer().unwrap() > 0);
+
+        // Statements aren't profiled while it is off, and turning it on starts over
+        process_command("PRAGMA profile = off;", &mut db).unwrap();
+        process_query("SELECT * FROM users;", &db).unwrap();
+        // Queries of the profile are profiled too
+        assert_eq!(db.profiler.statements(), 6);
+        let profile = process_query("PRAGMA profile;", &db).unwrap();
+        assert_eq!(profile.rows, vec![vec![Value::Integer(0)]]);
+        process_command("PRAGMA profile = on;", &mut db).unwrap();
+        assert_eq!(db.profiler.statements(), 0);
+        assert!(process_command("CREATE TABLE sqlrite_profile (id INTEGER);", &mut db).is_err());
+    }
+}
@@ -0,0 +1,273 @@
+//! Rendering of result sets for a terminal of a given width. A table wider than the
+//! terminal would be wrapped by the terminal itself, each of its lines broken wherever the
+//! screen ends, so instead its widest columns are narrowed, with their TEXT cells wrapped
+//! on several lines, and when even narrowed columns don't fit, the rows are rendered in
+//! line mode, like `.mode line` of the sqlite3 shell: a `column = value` line per column.
+use prettytable::{Cell as PrintCell, Row as PrintRow, Table as PrintTable};
+
+use crate::sql::executor::ResultSet;
+use crate::sql::value::Value;
+
+/// Lines a wrapped cell takes at most, the rest of its text is cut
+const MAX_CELL_LINES: usize = 4;
+
+/// Width a column is never narrowed below, unless its values are all narrower
+const MIN_COLUMN_WIDTH: usize = 8;
+
+/// Ends a value cut to fit its column
+const ELLIPSIS: char = '…';
+
+impl ResultSet {
+    /// Renders the result set for a terminal `width` characters wide: as the table `print`
+    /// shows when it fits, with its widest columns narrowed otherwise, and in line mode when
+    /// it doesn't fit even so. A TEXT value too wide for its column is wrapped, and cut with
+    /// an ellipsis past a few lines, other values are cut. Without a width, the table is
+    /// rendered whole.
+    pub fn render(&self, width: Option<usize>) -> String {
+        let width = match width {
+            Some(width) => width,
+            None => return self.to_string(),
+        };
+        let natural = self.natural_widths();
+        if table_width(&natural) <= width {
+            return self.to_string();
+        }
+        let minimum = self
+            .columns
+            .iter()
+            .zip(&natural)
+            .map(|(column, &natural)| {
+                let header = text_width(&column.name);
+                natural.min(header.max(MIN_COLUMN_WIDTH))
+            })
+            .collect::<Vec<usize>>();
+        if table_width(&minimum) > width {
+            return self.render_lines();
+        }
+        let borders = table_width(&vec![0; natural.len()]);
+        let widths = share_width(&natural, minimum, width - borders);
+
+        let mut print_table = PrintTable::new();
+        print_table.add_row(PrintRow::new(
+            self.columns
+                .iter()
+                .map(|col| PrintCell::new(&col.name))
+                .collect::<Vec<PrintCell>>(),
+        ));
+        for row in &self.rows {
+            print_table.add_row(PrintRow::new(
+                row.iter()
+                    .zip(&widths)
+                    .map(|(value, &width)| PrintCell::new(&fit_cell(value, width)))
+                    .collect::<Vec<PrintCell>>(),
+            ));
+        }
+        print_table.to_string()
+    }
+
+    /// Renders the result set in line mode: a line per column of each row, the name of the
+    /// column aligned right, with a blank line between rows.
+    pub fn render_lines(&self) -> String {
+        let name_width = self
+            .columns
+            .iter()
+            .map(|column| text_width(&column.name))
+            .max()
+            .unwrap_or(0);
+        let mut text = String::new();
+        for (i, row) in self.rows.iter().enumerate() {
+            if i > 0 {
+                text.push('\n');
+            }
+            for (column, value) in self.columns.iter().zip(row) {
+                let padding = " ".repeat(name_width - text_width(&column.name));
+                text.push_str(&format!("{}{} = {}\n", padding, column.name, value));
+            }
+        }
+        text
+    }
+
+    /// Width each column takes when no value is cut: that of its widest line.
+    fn natural_widths(&self) -> Vec<usize> {
+        let mut widths = self
+            .columns
+            .iter()
+            .map(|column| text_width(&column.name))
+            .collect::<Vec<usize>>();
+        for row in &self.rows {
+            for (width, value) in widths.iter_mut().zip(row) {
+                *width = (*width).max(text_width(&value.to_string()));
+            }
+        }
+        widths
+    }
+}
+
+/// Width of the widest line of `text`, counted in characters.
+fn text_width(text: &str) -> usize {
+    text.lines()
+        .map(|line| line.chars().count())
+        .max()
+        .unwrap_or(0)
+}
+
+/// Width of a table whose columns are `widths` wide, with the borders and the padding of
+/// the default format of prettytable: `| a | b |`.
+fn table_width(widths: &[usize]) -> usize {
+    1 + widths.iter().map(|width| width + 3).sum::<usize>()
+}
+
+/// Widens the columns from their `minimum` width towards their `natural` one until the
+/// table takes `available` characters of values, sharing them evenly between the columns
+/// still narrower than their values.
+fn share_width(natural: &[usize], minimum: Vec<usize>, available: usize) -> Vec<usize> {
+    let mut widths = minimum;
+    let mut spare = available.saturating_sub(widths.iter().sum());
+    loop {
+        let growing = (0..widths.len())
+            .filter(|&i| widths[i] < natural[i])
+            .collect::<Vec<usize>>();
+        if growing.is_empty() || spare == 0 {
+            return widths;
+        }
+        let share = (spare / growing.len()).max(1);
+        for i in growing {
+            let grow = share.min(natural[i] - widths[i]).min(spare);
+            widths[i] += grow;
+            spare -= grow;
+        }
+    }
+}
+
+/// The text of `value` fitted in a column `width` characters wide: TEXT wrapped on at most
+/// `MAX_CELL_LINES` lines, other values on one, cut with an ellipsis when they don't fit.
+fn fit_cell(value: &Value, width: usize) -> String {
+    let text = value.to_string();
+    if text_width(&text) <= width {
+        return text;
+    }
+    let mut lines = match value {
+        Value::Text(_) => wrap(&text, width),
+        _ => text.lines().map(|line| line.to_string()).collect(),
+    };
+    let max_lines = match value {
+        Value::Text(_) => MAX_CELL_LINES,
+        _ => 1,
+    };
+    if lines.len() > max_lines {
+        lines.truncate(max_lines);
+        let last = lines.last_mut().unwrap();
+        *last = cut(last, width.saturating_sub(1));
+        last.push(ELLIPSIS);
+    }
+    for line in &mut lines {
+        if line.chars().count() > width {
+            *line = cut(line, width.saturating_sub(1));
+            line.push(ELLIPSIS);
+        }
+    }
+    lines.join("\n")
+}
+
+/// Breaks each line of `text` into lines of at most `width` characters, after the last
+/// space that fits when there is one.
+fn wrap(text: &str, width: usize) -> Vec<String> {
+    let width = width.max(1);
+    let mut lines = vec![];
+    for line in text.lines() {
+        let mut rest = line.chars().collect::<Vec<char>>();
+        while rest.len() > width {
+            let (end, skip) = match rest[..=width].iter().rposition(|c| c.is_whitespace()) {
+                Some(space) if space > 0 => (space, 1),
+                _ => (width, 0),
+            };
+            lines.push(rest[..end].iter().collect());
+            rest.drain(..end + skip);
+        }
+        lines.push(rest.into_iter().collect());
+    }
+    lines
+}
+
+/// The first `width` characters of `text`.
+fn cut(text: &str, width: usize) -> String {
+    text.chars().take(width).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sql::executor::ResultColumn;
+
+    fn result_set(columns: &[&str], rows: Vec<Vec<Value>>) -> ResultSet {
+        ResultSet {
+            columns: columns
+                .iter()
+                .map(|name| ResultColumn {
+                    name: name.to_string(),
+                    datatype: None,
+                })
+                .collect(),
+            rows,
+        }
+    }
+
+    #[test]
+    fn render_test() {
+        let result = result_set(
+            &["id", "bio"],
+            vec![vec![
+                Value::Integer(1),
+                Value::Text("likes long walks on the beach at sunset".to_string()),
+            ]],
+        );
+        // A table that fits is rendered whole
+        assert_eq!(result.render(Some(80)), result.to_string());
+        assert_eq!(result.render(None), result.to_string());
+
+        // Long TEXT is wrapped to fit the terminal
+        let rendered = result.render(Some(30));
+        assert!(rendered.lines().all(|line| line.chars().count() <= 30));
+        assert_eq!(
+            rendered,
+            [
+                "+----+---------------------+",
+                "| id | bio                 |",
+                "+----+---------------------+",
+                "| 1  | likes long walks on |",
+                "|    | the beach at sunset |",
+                "+----+---------------------+",
+                "",
+            ]
+            .join("\n")
+        );
+
+        // Past a few lines, it is cut
+        let rendered = result.render(Some(18));
+        assert!(rendered.lines().all(|line| line.chars().count() <= 18));
+        assert!(rendered.contains("| the beac… |"));
+
+        // Columns that don't fit even narrowed fall back to line mode
+        let wide = result_set(
+            &["id", "first_name", "last_name", "email"],
+            vec![vec![
+                Value::Integer(1),
+                Value::Text("Ana".to_string()),
+                Value::Text("Lima".to_string()),
+                Value::Text("ana.lima@example.com".to_string()),
+            ]],
+        );
+        assert_eq!(
+            wide.render(Some(40)),
+            "        id = 1\nfirst_name = Ana\n last_name = Lima\n     email = ana.lima@example.com\n"
+        );
+    }
+
+    #[test]
+    fn wrap_test() {
+        assert_eq!(wrap("a bc def", 4), vec!["a bc", "def"]);
+        assert_eq!(wrap("abcdefgh", 3), vec!["abc", "def", "gh"]);
+        assert_eq!(wrap("ab\ncd", 5), vec!["ab", "cd"]);
+        assert_eq!(fit_cell(&Value::Real(1234.56789012), 8), "1234.56…");
+    }
+}
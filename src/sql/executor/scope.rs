@@ -0,0 +1,395 @@
+//! Name resolution: what the names used by a SELECT refer to. Its table can be called by
+//! its name, or by the alias the FROM clause gives it, like `u` in `FROM users u`, and the
+//! names of its columns qualified with either, `u.name`. Like in SQLite, the WHERE clause
+//! can also use the alias of a result column when no column of the table has that name,
+//! as in `SELECT price * 2 AS doubled FROM items WHERE doubled > 10`. A subquery can refer
+//! to the columns of the queries it is evaluated for a row of, when none of its own has
+//! the name: in `SELECT name, (SELECT count(*) FROM orders o WHERE o.user_id = u.id) FROM
+//! users u`, `u.id` is the id of the user the orders are counted for. Before a SELECT runs,
+//! `resolve` rewrites its qualified names into the names of the columns its rows are read
+//! by, the aliases of its WHERE clause into the expressions they stand for, and the
+//! columns of enclosing queries into their values for the row the SELECT runs for. The
+//! columns of a join are resolved into the names the join qualifies them with, see `join`.
+use sqlparser::ast::{Expr, FunctionArg, FunctionArgExpr, Ident, Value as AstValue};
+
+use crate::error::{Result, SQLRiteError};
+use crate::sql::db::database::Database;
+use crate::sql::executor::expr::{eval_expr, RowContext};
+use crate::sql::executor::join::{ambiguous, from_tables, joined_columns};
+use crate::sql::executor::source::{Source, SourceColumn};
+use crate::sql::params::quote_literal;
+use crate::sql::parser::select::{names_table, Projection, SelectQuery};
+use crate::sql::value::Value;
+
+/// A row of a query enclosing a subquery, whose columns the subquery can refer to
+#[derive(Debug, Clone)]
+pub struct OuterRow {
+    table_name: Option<String>,
+    table_alias: Option<String>,
+    columns: Vec<SourceColumn>,
+    values: Vec<Value>,
+}
+
+impl OuterRow {
+    /// Returns true if `qualifier` names the table of this row.
+    fn is_named(&self, qualifier: &str) -> bool {
+        match &self.table_name {
+            Some(table_name) => names_table(qualifier, table_name, self.table_alias.as_deref()),
+            None => false,
+        }
+    }
+
+    /// The value of the column named `name`, if the row has one. The columns of a join
+    /// are also named without their table.
+    fn get(&self, name: &str) -> Option<&Value> {
+        let position = self.columns.iter().position(|column| {
+            column.name.eq_ignore_ascii_case(name)
+                || column.unqualified_name().eq_ignore_ascii_case(name)
+        })?;
+        self.values.get(position)
+    }
+
+    /// The value of the column `name` of the table called `qualifier`, if the row has one.
+    fn get_qualified(&self, qualifier: &str, name: &str) -> Option<&Value> {
+        if self.is_named(qualifier) {
+            return self.get(name);
+        }
+        let position = self.columns.iter().position(|column| {
+            column
+                .table
+                .as_ref()
+                .is_some_and(|table| names_table(qualifier, table, None))
+                && column.unqualified_name().eq_ignore_ascii_case(name)
+        })?;
+        self.values.get(position)
+    }
+}
+
+/// What the rows of a SELECT are read in: its table, and the rows of the queries enclosing
+/// it, innermost last, when it is a subquery
+#[derive(Debug, Default)]
+pub struct Scope<'a> {
+    pub table_name: Option<&'a str>,
+    pub table_alias: Option<&'a str>,
+    pub outer: &'a [OuterRow],
+}
+
+impl<'a> Scope<'a> {
+    /// The scope of the rows of `query`, a subquery of the rows `outer` when it has any.
+    /// The rows of a join have no table name, their columns are named after their tables.
+    pub fn new(query: &'a SelectQuery, outer: &'a [OuterRow]) -> Scope<'a> {
+        let joined = !query.joins.is_empty();
+        Scope {
+            table_name: query.table_name.as_deref().filter(|_| !joined),
+            table_alias: query.table_alias.as_deref().filter(|_| !joined),
+            outer,
+        }
+    }
+
+    /// The rows enclosing a subquery evaluated for `row`, one read in this scope.
+    pub fn enclosing(&self, row: &RowContext) -> Vec<OuterRow> {
+        let mut rows = self.outer.to_vec();
+        rows.push(OuterRow {
+            table_name: self.table_name.map(str::to_string),
+            table_alias: self.table_alias.map(str::to_string),
+            columns: row.columns.to_vec(),
+            values: row.values.to_vec(),
+        });
+        rows
+    }
+}
+
+/// The SELECT `query` with the names of its SELECT list, WHERE clause and ORDER BY
+/// resolved against its table, its result columns, and the rows `outer` of the queries
+/// enclosing it.
+pub fn resolve(query: &SelectQuery, db: &Database, outer: &[OuterRow]) -> Result<SelectQuery> {
+    let mut resolver = Resolver {
+        query,
+        db,
+        outer,
+        columns: None,
+    };
+    let mut resolved = query.clone();
+    for item in &mut resolved.projection {
+        if let Projection::Expr { expr, .. } = item {
+            resolver.resolve(expr, &[])?;
+        }
+    }
+    for term in &mut resolved.order_by {
+        // The alias of a result column is resolved into its expression when sorting, before
+        // the column of a join it could be the name of
+        let aliased = match &term.expr {
+            Expr::Identifier(ident) => is_alias(&query.projection, &ident.value),
+            _ => false,
+        };
+        if !aliased {
+            resolver.resolve(&mut term.expr, &[])?;
+        }
+    }
+    for join in &mut resolved.joins {
+        if let Some(constraint) = &mut join.constraint {
+            resolver.resolve(constraint, &[])?;
+        }
+    }
+    if let Some(selection) = &mut resolved.selection {
+        resolver.resolve(selection, &resolved.projection)?;
+    }
+    Ok(resolved)
+}
+
+/// Returns true if `name` is the alias of a result column of `projection`.
+fn is_alias(projection: &[Projection], name: &str) -> bool {
+    projection.iter().any(|item| match item {
+        Projection::Expr {
+            alias: Some(alias), ..
+        } => alias.eq_ignore_ascii_case(name),
+        _ => false,
+    })
+}
+
+struct Resolver<'a> {
+    query: &'a SelectQuery,
+    db: &'a Database,
+    outer: &'a [OuterRow],
+    /// The columns of the table or of the join, read when first needed
+    columns: Option<Vec<SourceColumn>>,
+}
+
+impl<'a> Resolver<'a> {
+    /// Rewrites the names of `expr`, where the aliases of the result columns `projection`
+    /// can be used.
+    fn resolve(&mut self, expr: &mut Expr, projection: &[Projection]) -> Result<()> {
+        rewrite(expr, &mut |expr| {
+            let resolved = match expr {
+                Expr::CompoundIdentifier(idents) => Some(self.qualified(idents)?),
+                Expr::Identifier(ident) => self.unqualified(ident, projection)?,
+                _ => None,
+            };
+            Ok(match resolved {
+                Some(resolved) => {
+                    *expr = resolved;
+                    true
+                }
+                None => false,
+            })
+        })
+    }
+
+    /// What the qualified column name `idents` refers to: a column of the table, or the
+    /// value of a column of an enclosing row.
+    fn qualified(&mut self, idents: &[Ident]) -> Result<Expr> {
+        let (column, qualifier) = match idents.split_last() {
+            Some(split) => split,
+            None => {
+                return Err(SQLRiteError::Internal(
+                    "Error resolving a column name".to_string(),
+                ))
+            }
+        };
+        let qualifier = qualifier
+            .iter()
+            .map(|ident| ident.value.as_str())
+            .collect::<Vec<&str>>()
+            .join(".");
+        let no_such_column =
+            || SQLRiteError::General(format!("no such column: {}.{}", qualifier, column.value));
+        if !self.query.joins.is_empty() {
+            let tables = from_tables(self.query);
+            let mut named = tables.iter().filter(|table| table.is_named(&qualifier));
+            if let Some(table) = named.next() {
+                if named.next().is_some() {
+                    return Err(ambiguous(&format!("{}.{}", qualifier, column.value)));
+                }
+                let name = format!("{}.{}", table.qualifier(), column.value);
+                return match self
+                    .find_column(|candidate| candidate.name.eq_ignore_ascii_case(&name))?
+                {
+                    Some(name) => Ok(Expr::Identifier(Ident::new(name))),
+                    None => Err(no_such_column()),
+                };
+            }
+        } else if let Some(table_name) = &self.query.table_name {
+            if names_table(&qualifier, table_name, self.query.table_alias.as_deref()) {
+                return Ok(Expr::Identifier(column.clone()));
+            }
+        }
+        let value = self
+            .outer
+            .iter()
+            .rev()
+            .find_map(|row| row.get_qualified(&qualifier, &column.value));
+        match value {
+            Some(value) => Ok(literal(value)),
+            None => Err(no_such_column()),
+        }
+    }
+
+    /// What the unqualified name `ident` refers to when it isn't a column of the table: the
+    /// expression of the result column of `projection` it is the alias of, or else the
+    /// value of the column of an enclosing row. `None` if it is a column of the table.
+    fn unqualified(&mut self, ident: &Ident, projection: &[Projection]) -> Result<Option<Expr>> {
+        let name = ident.value.as_str();
+        // The column of a join is named after its table, the only one with the column
+        if !self.query.joins.is_empty() {
+            let named =
+                |column: &SourceColumn| column.unqualified_name().eq_ignore_ascii_case(name);
+            if let Some(column) = self.find_column(named)? {
+                return Ok(Some(Expr::Identifier(Ident::new(column))));
+            }
+        }
+        let aliased = projection.iter().find_map(|item| match item {
+            Projection::Expr {
+                expr,
+                alias: Some(alias),
+            } if alias.eq_ignore_ascii_case(name) => Some(expr),
+            _ => None,
+        });
+        let outer = self.outer.iter().rev().find_map(|row| row.get(name));
+        // The columns of the table are only read when the name might be something else
+        if (aliased.is_none() && outer.is_none()) || self.is_column(name)? {
+            return Ok(None);
+        }
+        match (aliased, outer) {
+            (Some(aliased), _) => Ok(Some(Expr::Nested(Box::new(aliased.clone())))),
+            (None, Some(value)) => Ok(Some(literal(value))),
+            (None, None) => Ok(None),
+        }
+    }
+
+    /// Returns true if the table has a column named `name`.
+    fn is_column(&mut self, name: &str) -> Result<bool> {
+        let named = |column: &SourceColumn| column.name.eq_ignore_ascii_case(name);
+        Ok(self.find_column(named)?.is_some())
+    }
+
+    /// The name of the column `matches` is true for, if there is one. Fails if several
+    /// columns of a join are.
+    fn find_column<F>(&mut self, matches: F) -> Result<Option<String>>
+    where
+        F: Fn(&SourceColumn) -> bool,
+    {
+        let columns = match &mut self.columns {
+            Some(columns) => columns,
+            None => self.columns.insert(columns_in_scope(self.query, self.db)?),
+        };
+        let mut found = columns.iter().filter(|column| matches(column));
+        match (found.next(), found.next()) {
+            (Some(column), Some(_)) => Err(ambiguous(column.unqualified_name())),
+            (column, _) => Ok(column.map(|column| column.name.clone())),
+        }
+    }
+}
+
+/// The literal of `value`, which the column of an enclosing row is replaced by.
+fn literal(value: &Value) -> Expr {
+    Expr::Value(match value {
+        Value::Null => AstValue::Null,
+        Value::Integer(_) | Value::Real(_) => AstValue::Number(quote_literal(value), false),
+        Value::Text(text) => AstValue::SingleQuotedString(text.clone()),
+        Value::Bool(value) => AstValue::Boolean(*value),
+        Value::Blob(_) => {
+            let quoted = quote_literal(value);
+            AstValue::HexStringLiteral(quoted[2..quoted.len() - 1].to_string())
+        }
+    })
+}
+
+/// The columns of the table of `query`, or of its join, none without one.
+fn columns_in_scope(query: &SelectQuery, db: &Database) -> Result<Vec<SourceColumn>> {
+    let table_name = match &query.table_name {
+        Some(_) if !query.joins.is_empty() => return joined_columns(query, db),
+        Some(table_name) => table_name,
+        None => return Ok(vec![]),
+    };
+    // Arguments of table-valued functions can't refer to columns
+    let args = query
+        .table_args
+        .iter()
+        .map(|arg| eval_expr(arg, db, &RowContext::new(&[], &[])))
+        .collect::<Result<Vec<Value>>>()?;
+    Source::columns_of(db, table_name, &args)
+}
+
+/// Calls `replace` on `expr` and, unless it replaced it, on each expression within it, down
+/// to those of subqueries, which resolve their own names.
+fn rewrite<F>(expr: &mut Expr, replace: &mut F) -> Result<()>
+where
+    F: FnMut(&mut Expr) -> Result<bool>,
+{
+    if replace(expr)? {
+        return Ok(());
+    }
+    match expr {
+        Expr::Function(function) => {
+            for arg in &mut function.args {
+                if let FunctionArg::Unnamed(FunctionArgExpr::Expr(expr)) = arg {
+                    rewrite(expr, replace)?;
+                }
+            }
+        }
+        Expr::BinaryOp { left, right, .. } => {
+            rewrite(left, replace)?;
+            rewrite(right, replace)?;
+        }
+        Expr::UnaryOp { expr, .. }
+        | Expr::Nested(expr)
+        | Expr::Collate { expr, .. }
+        | Expr::IsNull(expr)
+        | Expr::IsNotNull(expr)
+        | Expr::Cast { expr, .. }
+        | Expr::InSubquery { expr, .. } => rewrite(expr, replace)?,
+        Expr::Trim { expr, trim_where } => {
+            rewrite(expr, replace)?;
+            if let Some((_, chars)) = trim_where {
+                rewrite(chars, replace)?;
+            }
+        }
+        Expr::Substring {
+            expr,
+            substring_from,
+            substring_for,
+        } => {
+            rewrite(expr, replace)?;
+            for expr in substring_from.iter_mut().chain(substring_for.iter_mut()) {
+                rewrite(expr, replace)?;
+            }
+        }
+        Expr::Position { expr, r#in } => {
+            rewrite(expr, replace)?;
+            rewrite(r#in, replace)?;
+        }
+        Expr::Between {
+            expr, low, high, ..
+        } => {
+            rewrite(expr, replace)?;
+            rewrite(low, replace)?;
+            rewrite(high, replace)?;
+        }
+        Expr::InList { expr, list, .. } => {
+            rewrite(expr, replace)?;
+            for item in list {
+                rewrite(item, replace)?;
+            }
+        }
+        Expr::Tuple(exprs) => {
+            for expr in exprs {
+                rewrite(expr, replace)?;
+            }
+        }
+        Expr::Case {
+            operand,
+            conditions,
+            results,
+            else_result,
+        } => {
+            for expr in operand.iter_mut().chain(else_result.iter_mut()) {
+                rewrite(expr, replace)?;
+            }
+            for expr in conditions.iter_mut().chain(results.iter_mut()) {
+                rewrite(expr, replace)?;
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
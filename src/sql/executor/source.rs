@@ -0,0 +1,650 @@
+use crate::error::{Result, SQLRiteError};
+use crate::sql::db::audit::AUDIT_TABLE_NAME;
+use crate::sql::db::database::Database;
+use crate::sql::db::privilege::Privilege;
+use crate::sql::db::schema::schema_table;
+use crate::sql::db::stats::{default_selectivity, STAT_TABLE_NAME};
+use crate::sql::db::table::{Column, DataType, Index, Table};
+use crate::sql::executor::filter::{ScanConstraint, ScanList};
+use crate::sql::executor::profile::{Profiler, PROFILE_TABLE_NAME};
+use crate::sql::value::{Affinity, Value};
+use crate::sql::vtab::{self, ConstraintOp, IndexConstraint, VirtualTable};
+
+/// Metadata of each column of the table a query reads from
+#[derive(Debug, PartialEq, Clone)]
+pub struct SourceColumn {
+    pub name: String,
+    /// Declared type of the column
+    pub datatype: Option<DataType>,
+    /// Collating sequence declared for the column, if any
+    pub collation: Option<String>,
+    /// Hidden columns of virtual tables are left out of `SELECT *`
+    pub hidden: bool,
+    /// In a join, the name or alias the table of the column is called by, which qualifies
+    /// the name of the column, `table.column`
+    pub table: Option<String>,
+}
+
+impl SourceColumn {
+    /// The name of the column, without the table qualifying it in a join.
+    pub fn unqualified_name(&self) -> &str {
+        match &self.table {
+            Some(table) => &self.name[table.len() + 1..],
+            None => &self.name,
+        }
+    }
+}
+
+/// The rows of the table in the FROM clause of a query, read into memory
+/// together with the metadata of their columns.
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct Source {
+    pub columns: Vec<SourceColumn>,
+    pub rows: Vec<Vec<Value>>,
+}
+
+impl Source {
+    /// The single row without columns a SELECT without a FROM clause reads, to evaluate its
+    /// SELECT list once.
+    pub fn single_row() -> Source {
+        Source {
+            columns: vec![],
+            rows: vec![vec![]],
+        }
+    }
+
+    /// Reads the rows satisfying `constraints` of the table or virtual table named
+    /// `table_name`, or of the rows returned by the table-valued function `table_name(args...)`.
+    pub fn scan(
+        db: &Database,
+        table_name: &str,
+        args: &[Value],
+        constraints: &[ScanConstraint],
+    ) -> Result<Source> {
+        let (mut source, constraints) = Source::open(db, table_name, args, constraints, &[])?;
+        source.filter(db, &constraints)?;
+        Ok(source)
+    }
+
+    /// The columns of the table named `table_name`, or of the rows returned by the
+    /// table-valued function `table_name(args...)`. The rows of a table aren't read.
+    pub fn columns_of(
+        db: &Database,
+        table_name: &str,
+        args: &[Value],
+    ) -> Result<Vec<SourceColumn>> {
+        match db.tables.get(&db.resolve_table_name(table_name)?) {
+            Some(table) => Ok(Source::from_rowids(table, std::iter::empty()).columns),
+            None => Ok(Source::open(db, table_name, args, &[], &[])?.0.columns),
+        }
+    }
+
+    /// Reads the rows of the table like `scan`, returning them with the constraints they
+    /// still have to be filtered with, in the order they are best checked. Virtual tables
+    /// are given the constraints, none are left for them. The rows of a table can be looked
+    /// up by `lists`, which are left to the caller to check.
+    pub fn open(
+        db: &Database,
+        table_name: &str,
+        args: &[Value],
+        constraints: &[ScanConstraint],
+        lists: &[ScanList],
+    ) -> Result<(Source, Vec<ScanConstraint>)> {
+        let table_name = db.resolve_table_name(table_name)?;
+        let table_name = table_name.as_str();
+        // Every user can read the schema catalog and call table-valued functions, reading
+        // a table needs the SELECT privilege
+        let function = db.vtabs.contains_module(table_name) && !db.contains_table_name(table_name);
+        if schema_table(table_name).is_none() && !function {
+            db.check_privilege(Privilege::Select, Some(table_name))?;
+        }
+        if let Some(schema_name) = schema_table(table_name) {
+            Ok((Source::from_schema(db, schema_name), constraints.to_vec()))
+        } else if table_name.eq_ignore_ascii_case(AUDIT_TABLE_NAME) {
+            Ok((Source::from_audit_log(db), constraints.to_vec()))
+        } else if table_name.eq_ignore_ascii_case(STAT_TABLE_NAME) {
+            Ok((Source::from_stats(db), constraints.to_vec()))
+        } else if table_name.eq_ignore_ascii_case(PROFILE_TABLE_NAME) {
+            Ok((Source::from_profiler(db), constraints.to_vec()))
+        } else if let Some(table) = db.tables.get(table_name) {
+            if !args.is_empty() {
+                return Err(SQLRiteError::General(format!(
+                    "{} may not be used as a table-valued function",
+                    table_name
+                )));
+            }
+            let (constraints, lists) = with_affinity(table, constraints, lists);
+            // Rows that can be looked up in the index of a unique column are, instead of
+            // reading every row
+            let source = match index_scan(table, &constraints, &lists) {
+                Some(scan) => Source::from_rowids(table, scan.rowids(table).into_iter()),
+                None => Source::from_table(table),
+            };
+            let constraints = by_selectivity(db, table_name, &source.columns, &constraints)?;
+            Ok((source, constraints))
+        } else if let Some(table) = db.vtabs.get_table(table_name) {
+            let source = Source::from_virtual_table(table.as_ref(), table_name, args, constraints)?;
+            Ok((source, vec![]))
+        } else if let Some(module) = db.vtabs.get_module(table_name) {
+            // Modules can be used directly as table-valued functions
+            let table = module.create(&[])?;
+            let source = Source::from_virtual_table(table.as_ref(), table_name, args, constraints)?;
+            Ok((source, vec![]))
+        } else {
+            Err(SQLRiteError::General(String::from("Table not found.")))
+        }
+    }
+
+    /// Reads the rows of a table in ROWID order.
+    pub fn from_table(table: &Table) -> Source {
+        let rows = table.scan_rows(..);
+        Source {
+            columns: Source::from_rowids(table, std::iter::empty()).columns,
+            rows: rows.into_iter().map(|(_, values)| values).collect(),
+        }
+    }
+
+    /// Reads the rows `rowids` of a table, in that order.
+    pub fn from_rowids(table: &Table, rowids: impl Iterator<Item = i64>) -> Source {
+        let columns = table
+            .columns
+            .iter()
+            .map(|col| SourceColumn {
+                name: col.column_name.to_string(),
+                datatype: Some(col.datatype.clone()),
+                collation: col.collation.clone(),
+                hidden: false,
+                table: None,
+            })
+            .collect::<Vec<SourceColumn>>();
+
+        let rows = rowids
+            .map(|rowid| table.row_values(rowid))
+            .collect::<Vec<Vec<Value>>>();
+
+        Source { columns, rows }
+    }
+
+    /// Reads the schema catalog of the main database, or of the database attached as
+    /// `schema_name`, with the columns of SQLite's `sqlite_master`. There are no pages,
+    /// `rootpage` is always 0.
+    pub fn from_schema(db: &Database, schema_name: Option<&str>) -> Source {
+        let column = |name: &str, datatype: DataType| SourceColumn {
+            name: name.to_string(),
+            datatype: Some(datatype),
+            collation: None,
+            hidden: false,
+            table: None,
+        };
+        let columns = vec![
+            column("type", DataType::Text),
+            column("name", DataType::Text),
+            column("tbl_name", DataType::Text),
+            column("rootpage", DataType::Integer),
+            column("sql", DataType::Text),
+        ];
+
+        // Objects of attached databases are stored as `schema.name`
+        let unqualified = |name: &str| -> Option<String> {
+            match (schema_name, name.split_once('.')) {
+                (None, None) => Some(name.to_string()),
+                (Some(schema_name), Some((schema, name))) if schema == schema_name => {
+                    Some(name.to_string())
+                }
+                _ => None,
+            }
+        };
+        let mut rows: Vec<Vec<Value>> = vec![];
+        for object in &db.schema {
+            if let Some(tbl_name) = unqualified(&object.tbl_name) {
+                let name = unqualified(&object.name).unwrap_or_else(|| object.name.to_string());
+                rows.push(vec![
+                    Value::Text(object.object_type.as_str().to_string()),
+                    Value::Text(name),
+                    Value::Text(tbl_name),
+                    Value::Integer(0),
+                    object
+                        .sql
+                        .as_ref()
+                        .map_or(Value::Null, |sql| Value::Text(sql.to_string())),
+                ]);
+            }
+        }
+        Source { columns, rows }
+    }
+
+    /// Reads the changes recorded in the `sqlrite_audit` table of the database.
+    pub fn from_audit_log(db: &Database) -> Source {
+        let column = |name: &str, datatype: DataType| SourceColumn {
+            name: name.to_string(),
+            datatype: Some(datatype),
+            collation: None,
+            hidden: false,
+            table: None,
+        };
+        let columns = vec![
+            column("id", DataType::Integer),
+            column("timestamp", DataType::Text),
+            column("user", DataType::Text),
+            column("operation", DataType::Text),
+            column("table_name", DataType::Text),
+            column("rowid", DataType::Integer),
+            column("old_values", DataType::Text),
+            column("new_values", DataType::Text),
+        ];
+        let text = |value: &Option<String>| value.clone().map_or(Value::Null, Value::Text);
+        let rows = db
+            .audit
+            .records
+            .iter()
+            .map(|record| {
+                vec![
+                    Value::Integer(record.id),
+                    Value::Text(record.timestamp.to_string()),
+                    text(&record.user),
+                    Value::Text(record.operation.as_str().to_string()),
+                    Value::Text(record.table_name.to_string()),
+                    Value::Integer(record.rowid),
+                    text(&record.old),
+                    text(&record.new),
+                ]
+            })
+            .collect();
+        Source { columns, rows }
+    }
+
+    /// Reads the statistics gathered by ANALYZE, as rows of the `sqlrite_stat` table. Each
+    /// analyzed column has a `column` row with its number of rows, the smallest and largest
+    /// of its values and their number of distinct values, then a `mcv` row per most common
+    /// value and a `histogram` row per bucket of the histogram of the other values.
+    pub fn from_stats(db: &Database) -> Source {
+        let column = |name: &str, datatype: Option<DataType>| SourceColumn {
+            name: name.to_string(),
+            datatype,
+            collation: None,
+            hidden: false,
+            table: None,
+        };
+        let columns = vec![
+            column("tbl", Some(DataType::Text)),
+            column("col", Some(DataType::Text)),
+            column("kind", Some(DataType::Text)),
+            column("lower", None),
+            column("upper", None),
+            column("rows", Some(DataType::Integer)),
+            column("distinct", Some(DataType::Integer)),
+        ];
+        let mut rows: Vec<Vec<Value>> = vec![];
+        for (table_name, table) in &db.stats {
+            for (column_name, stats) in &table.columns {
+                let row = |kind: &str, lower: &Value, upper: &Value, count: usize, distinct| {
+                    vec![
+                        Value::Text(table_name.to_string()),
+                        Value::Text(column_name.to_string()),
+                        Value::Text(kind.to_string()),
+                        lower.clone(),
+                        upper.clone(),
+                        Value::Integer(count as i64),
+                        Value::Integer(distinct as i64),
+                    ]
+                };
+                rows.push(row(
+                    "column",
+                    &stats.min,
+                    &stats.max,
+                    stats.rows - stats.nulls,
+                    stats.distinct,
+                ));
+                for (value, count) in &stats.most_common {
+                    rows.push(row("mcv", value, value, *count, 1));
+                }
+                for bucket in &stats.histogram {
+                    rows.push(row(
+                        "histogram",
+                        &bucket.lower,
+                        &bucket.upper,
+                        bucket.rows,
+                        bucket.distinct,
+                    ));
+                }
+            }
+        }
+        Source { columns, rows }
+    }
+
+    /// Reads the profiles of the last statements run while profiling was on, one row per
+    /// operator of each.
+    pub fn from_profiler(db: &Database) -> Source {
+        let columns = Profiler::columns()
+            .into_iter()
+            .map(|column| SourceColumn {
+                name: column.name,
+                datatype: None,
+                collation: None,
+                hidden: false,
+                table: None,
+            })
+            .collect();
+        Source {
+            columns,
+            rows: db.profiler.rows(),
+        }
+    }
+
+    /// Reads the rows produced by a virtual table's cursor, constraining its hidden columns
+    /// with `args` when it is used as the table-valued function `name(args...)`.
+    pub fn from_virtual_table(
+        table: &dyn VirtualTable,
+        name: &str,
+        args: &[Value],
+        constraints: &[ScanConstraint],
+    ) -> Result<Source> {
+        let virtual_columns = table.columns();
+        let mut index_constraints = vtab::arg_constraints(&virtual_columns, name, args)?;
+        let columns = virtual_columns
+            .into_iter()
+            .map(|col| SourceColumn {
+                name: col.name,
+                datatype: Some(col.datatype),
+                collation: None,
+                hidden: col.hidden,
+                table: None,
+            })
+            .collect();
+        let mut source = Source {
+            columns,
+            rows: vec![],
+        };
+        for constraint in constraints {
+            let index_constraint = IndexConstraint {
+                column: source.column_index(&constraint.column)?,
+                op: constraint.op,
+            };
+            index_constraints.push((index_constraint, constraint.value.clone()));
+        }
+        source.rows = vtab::scan_with_constraints(table, &index_constraints)?;
+
+        Ok(source)
+    }
+
+    /// Keeps only the rows satisfying every constraint, comparing text with the collating
+    /// sequence of the column. Only virtual tables can answer MATCH constraints.
+    pub fn filter(&mut self, db: &Database, constraints: &[ScanConstraint]) -> Result<()> {
+        for constraint in constraints {
+            if constraint.op == ConstraintOp::Match {
+                return Err(SQLRiteError::General(
+                    "unable to use function MATCH in the requested context".to_string(),
+                ));
+            }
+            let index = self.column_index(&constraint.column)?;
+            let collation = db
+                .collations
+                .get(self.columns[index].collation.as_deref().unwrap_or("binary"))?;
+            let index_constraint = IndexConstraint {
+                column: index,
+                op: constraint.op,
+            };
+            self.rows.retain(|row| {
+                index_constraint.test(&row[index], &constraint.value, collation.as_ref())
+            });
+        }
+        Ok(())
+    }
+
+    /// Returns the position of the column named `name`.
+    pub fn column_index(&self, name: &str) -> Result<usize> {
+        match self.columns.iter().position(|col| col.name == name) {
+            Some(index) => Ok(index),
+            None => Err(SQLRiteError::General(format!("No such column: {}", name))),
+        }
+    }
+}
+
+/// How the rows of a table satisfying a WHERE clause are looked up in the index of one of
+/// its columns instead of reading every row. Only the indexes of UNIQUE and PRIMARY KEY
+/// columns are used, they hold a row per value. The rows looked up are still filtered with
+/// the WHERE clause.
+#[derive(Debug, PartialEq)]
+pub enum IndexScan<'a> {
+    /// An equality on the column, matching a row at most
+    Eq(&'a ScanConstraint),
+    /// `column IN (...)`, matching a row per value at most
+    List(&'a ScanList),
+    /// A lower and an upper bound on the column, `column BETWEEN lower AND upper` among
+    /// others, reading the rows of the values in between, bounds included
+    Range {
+        column: &'a str,
+        lower: &'a Value,
+        upper: &'a Value,
+    },
+}
+
+/// `constraints` and `lists` with their values converted to the affinity of their column of
+/// `table`, like the operands of a comparison with the column, so that `id = '5'` looks up
+/// the INTEGER 5 in the index of `id`.
+pub fn with_affinity(
+    table: &Table,
+    constraints: &[ScanConstraint],
+    lists: &[ScanList],
+) -> (Vec<ScanConstraint>, Vec<ScanList>) {
+    let convert = |column: &str, value: &Value| {
+        let affinity = table
+            .columns
+            .iter()
+            .find(|other| other.column_name == column)
+            .and_then(|column| Affinity::of_column(&column.datatype));
+        match affinity {
+            Some(affinity) => value.apply_affinity(affinity),
+            None => value.clone(),
+        }
+    };
+    let constraints = constraints
+        .iter()
+        .map(|constraint| ScanConstraint {
+            value: convert(&constraint.column, &constraint.value),
+            ..constraint.clone()
+        })
+        .collect();
+    let lists = lists
+        .iter()
+        .map(|list| ScanList {
+            column: list.column.clone(),
+            values: list
+                .values
+                .iter()
+                .map(|value| convert(&list.column, value))
+                .collect(),
+        })
+        .collect();
+    (constraints, lists)
+}
+
+/// How the rows of `table` satisfying `constraints` and `lists` can be looked up in the
+/// index of a column, if they can: by an equality first, then by a list of values, then
+/// by a range.
+pub fn index_scan<'a>(
+    table: &Table,
+    constraints: &'a [ScanConstraint],
+    lists: &'a [ScanList],
+) -> Option<IndexScan<'a>> {
+    let indexed = |name: &str, value: &Value| match indexed_column(table, name) {
+        Some(column) => comparable(&column.index, value),
+        None => false,
+    };
+    if let Some(constraint) = constraints.iter().find(|constraint| {
+        constraint.op == ConstraintOp::Eq && indexed(&constraint.column, &constraint.value)
+    }) {
+        return Some(IndexScan::Eq(constraint));
+    }
+    // NULLs are in no list
+    if let Some(list) = lists.iter().find(|list| {
+        list.values
+            .iter()
+            .all(|value| *value == Value::Null || indexed(&list.column, value))
+    }) {
+        return Some(IndexScan::List(list));
+    }
+    let bound = |ops: &[ConstraintOp], column: &str| {
+        constraints
+            .iter()
+            .find(|constraint| {
+                ops.contains(&constraint.op)
+                    && constraint.column == column
+                    && indexed(column, &constraint.value)
+            })
+            .map(|constraint| &constraint.value)
+    };
+    constraints.iter().find_map(|constraint| {
+        let column = constraint.column.as_str();
+        let lower = bound(&[ConstraintOp::Gt, ConstraintOp::Ge], column)?;
+        let upper = bound(&[ConstraintOp::Lt, ConstraintOp::Le], column)?;
+        Some(IndexScan::Range {
+            column,
+            lower,
+            upper,
+        })
+    })
+}
+
+impl<'a> IndexScan<'a> {
+    pub fn column(&self) -> &str {
+        match self {
+            IndexScan::Eq(constraint) => &constraint.column,
+            IndexScan::List(list) => &list.column,
+            IndexScan::Range { column, .. } => column,
+        }
+    }
+
+    /// Looks up the rows in the index of the column, returning their ROWIDs in order. Bounds
+    /// of a range are included, whether the constraints do or not.
+    pub fn rowids(&self, table: &Table) -> Vec<i64> {
+        let column = match indexed_column(table, self.column()) {
+            Some(column) => column,
+            None => return vec![],
+        };
+        let lookup = |value: &Value| match column.may_have(value) {
+            true => column.index.get(value),
+            false => None,
+        };
+        let mut rowids = match self {
+            IndexScan::Eq(constraint) => lookup(&constraint.value).into_iter().collect(),
+            IndexScan::List(list) => list.values.iter().filter_map(lookup).collect(),
+            IndexScan::Range { lower, upper, .. } => match (&column.index, lower, upper) {
+                (Index::Integer(index), Value::Integer(lower), Value::Integer(upper))
+                    if lower <= upper =>
+                {
+                    index
+                        .range(*lower..=*upper)
+                        .flat_map(|(_, rowids)| rowids.iter().copied())
+                        .collect()
+                }
+                (Index::Text(index), Value::Text(lower), Value::Text(upper)) if lower <= upper => {
+                    index
+                        .range::<String, _>(lower..=upper)
+                        .flat_map(|(_, rowids)| rowids.iter().copied())
+                        .collect()
+                }
+                _ => vec![],
+            },
+        };
+        rowids.sort_unstable();
+        rowids.dedup();
+        rowids
+    }
+
+    /// The rows the lookup reads at most, if known.
+    pub fn max_rows(&self) -> Option<f64> {
+        match self {
+            IndexScan::Eq(_) => Some(1.0),
+            IndexScan::List(list) => Some(list.values.len() as f64),
+            IndexScan::Range { .. } => None,
+        }
+    }
+
+    /// How the lookup is shown by EXPLAIN QUERY PLAN.
+    pub fn detail(&self, table_name: &str) -> String {
+        match self {
+            IndexScan::Eq(_) | IndexScan::List(_) => {
+                format!("SEARCH {} USING INDEX ({}=?)", table_name, self.column())
+            }
+            IndexScan::Range { column, .. } => format!(
+                "SEARCH {} USING INDEX ({}>? AND {}<?)",
+                table_name, column, column
+            ),
+        }
+    }
+}
+
+/// The column `name` of `table` if its index can be looked up: a UNIQUE or PRIMARY KEY
+/// column, whose values the index compares exactly, like the binary collating sequence.
+pub fn indexed_column<'t>(table: &'t Table, name: &str) -> Option<&'t Column> {
+    let column = table
+        .columns
+        .iter()
+        .find(|column| column.column_name == name)?;
+    let binary = !matches!(
+        column.collation.as_deref(),
+        Some(collation) if !collation.eq_ignore_ascii_case("binary")
+    );
+    match (column.is_unique || column.is_pk) && binary {
+        true => Some(column),
+        false => None,
+    }
+}
+
+/// Returns true if `value` can be looked up in `index`.
+pub fn comparable(index: &Index, value: &Value) -> bool {
+    matches!(
+        (index, value),
+        (Index::Integer(_), Value::Integer(_)) | (Index::Text(_), Value::Text(_))
+    )
+}
+
+/// Orders the constraints on the table `table_name` so that the ones estimated to keep the
+/// fewest rows are checked first, from the statistics of the table if it was analyzed.
+pub fn by_selectivity(
+    db: &Database,
+    table_name: &str,
+    columns: &[SourceColumn],
+    constraints: &[ScanConstraint],
+) -> Result<Vec<ScanConstraint>> {
+    let mut estimated = constraints
+        .iter()
+        .map(|constraint| {
+            let selectivity = selectivity(db, table_name, columns, constraint)?;
+            Ok((selectivity, constraint.clone()))
+        })
+        .collect::<Result<Vec<(f64, ScanConstraint)>>>()?;
+    estimated.sort_by(|a, b| a.0.total_cmp(&b.0));
+    Ok(estimated
+        .into_iter()
+        .map(|(_, constraint)| constraint)
+        .collect())
+}
+
+/// Estimated share of the rows of the table `table_name` `constraint` keeps, from the
+/// statistics of the table if it was analyzed.
+pub fn selectivity(
+    db: &Database,
+    table_name: &str,
+    columns: &[SourceColumn],
+    constraint: &ScanConstraint,
+) -> Result<f64> {
+    match db.stats.get(table_name) {
+        Some(stats) => {
+            let collation = columns
+                .iter()
+                .find(|column| column.name == constraint.column)
+                .and_then(|column| column.collation.as_deref())
+                .unwrap_or("binary");
+            let collation = db.collations.get(collation)?;
+            Ok(stats.selectivity(
+                &constraint.column,
+                constraint.op,
+                &constraint.value,
+                collation.as_ref(),
+            ))
+        }
+        None => Ok(default_selectivity(constraint.op)),
+    }
+}
@@ -0,0 +1,445 @@
+//! Creation of triggers and execution of the triggers fired by changes to a table.
+use sqlparser::tokenizer::Token;
+
+use crate::error::{Result, SQLRiteError};
+use crate::sql::db::database::{ChangeCounters, Database};
+use crate::sql::db::privilege::Privilege;
+use crate::sql::db::schema::{SchemaObject, SchemaObjectType};
+use crate::sql::db::table::Table;
+use crate::sql::db::trigger::{Trigger, TriggerEvent, TriggerTiming};
+use crate::sql::executor::expr::{eval_expr, RowContext};
+use crate::sql::params::quote_literal;
+use crate::sql::parser::trigger::parse_expression;
+use crate::sql::process_command;
+use crate::sql::tokenize;
+use crate::sql::value::Value;
+use std::collections::HashMap;
+
+/// Adds `trigger`, created by the statement `sql`, to the database, failing if its table
+/// doesn't exist or if there is already a trigger with the same name, unless
+/// `if_not_exists` is set.
+pub fn create_trigger(
+    db: &mut Database,
+    mut trigger: Trigger,
+    if_not_exists: bool,
+    sql: &str,
+) -> Result<String> {
+    trigger.table_name = db.resolve_table_name(&trigger.table_name)?;
+    db.check_privilege(Privilege::Ddl, Some(&trigger.table_name))?;
+    if db.vtabs.contains_table(&trigger.table_name) {
+        return Err(SQLRiteError::General(
+            "cannot create triggers on virtual tables".to_string(),
+        ));
+    }
+    if !db.contains_table(trigger.table_name.to_string()) {
+        return Err(SQLRiteError::General(format!(
+            "no such table: {}",
+            trigger.table_name
+        )));
+    }
+    if db.triggers.iter().any(|t| t.name == trigger.name) {
+        if if_not_exists {
+            return Ok(String::from("CREATE TRIGGER Statement executed."));
+        }
+        return Err(SQLRiteError::General(format!(
+            "trigger {} already exists",
+            trigger.name
+        )));
+    }
+    if let TriggerEvent::Update(columns) = &trigger.event {
+        let table = db.get_table(trigger.table_name.to_string())?;
+        if let Some(column) = columns
+            .iter()
+            .find(|column| !table.contains_column(column.to_string()))
+        {
+            return Err(SQLRiteError::General(format!("no such column: {}", column)));
+        }
+    }
+    db.schema.push(SchemaObject::new(
+        SchemaObjectType::Trigger,
+        &trigger.name,
+        &trigger.table_name,
+        Some(sql),
+    ));
+    db.triggers.push(trigger);
+    db.schema_version += 1;
+    Ok(String::from("CREATE TRIGGER Statement executed."))
+}
+
+/// Removes the trigger named `name`, failing if there is none, unless `if_exists` is set.
+pub fn drop_trigger(db: &mut Database, name: &str, if_exists: bool) -> Result<String> {
+    match db.triggers.iter().position(|t| t.name == name) {
+        Some(position) => {
+            db.check_privilege(Privilege::Ddl, Some(&db.triggers[position].table_name))?;
+            db.triggers.remove(position);
+            db.schema.retain(|object| {
+                object.object_type != SchemaObjectType::Trigger || object.name != name
+            });
+            db.schema_version += 1;
+        }
+        None if if_exists => {}
+        None => return Err(SQLRiteError::General(format!("no such trigger: {}", name))),
+    }
+    Ok(String::from("DROP TRIGGER Statement executed."))
+}
+
+/// Returns true if any trigger is defined on `table_name`.
+pub fn has_triggers(db: &Database, table_name: &str) -> bool {
+    db.triggers.iter().any(|t| t.table_name == table_name)
+}
+
+/// Runs the triggers on `table_name` firing at `timing` for `event`, in the order they
+/// were created. `old` and `new` are the values of every column of the row being changed,
+/// in the order of the table's columns, bound to `OLD.column` and `NEW.column`.
+/// A trigger doesn't fire while it is already running, so triggers never recurse.
+pub fn fire_triggers(
+    db: &mut Database,
+    table_name: &str,
+    timing: TriggerTiming,
+    event: &TriggerEvent,
+    old: Option<&[Value]>,
+    new: Option<&[Value]>,
+) -> Result<()> {
+    let triggers = db
+        .triggers
+        .iter()
+        .filter(|t| t.table_name == table_name && t.fires_on(timing, event))
+        .filter(|t| !db.running_triggers.contains(&t.name))
+        .cloned()
+        .collect::<Vec<Trigger>>();
+    if triggers.is_empty() {
+        return Ok(());
+    }
+
+    let table = db.get_table(table_name.to_string())?;
+    let columns = table
+        .columns
+        .iter()
+        .map(|col| col.column_name.to_string())
+        .collect::<Vec<String>>();
+
+    for trigger in triggers {
+        db.running_triggers.push(trigger.name.to_string());
+        let result = run_trigger(db, &trigger, &columns, old, new);
+        db.running_triggers.pop();
+        result?;
+    }
+    Ok(())
+}
+
+fn run_trigger(
+    db: &mut Database,
+    trigger: &Trigger,
+    columns: &[String],
+    old: Option<&[Value]>,
+    new: Option<&[Value]>,
+) -> Result<()> {
+    if let Some(when) = &trigger.when {
+        let condition = parse_expression(&bind_row(when, columns, old, new)?)?;
+        if eval_expr(&condition, db, &RowContext::new(&[], &[]))?.to_bool() != Some(true) {
+            return Ok(());
+        }
+    }
+    // Like in SQLite, the statements of a trigger don't change last_insert_rowid()
+    let last_insert_rowid = db.counters.last_insert_rowid;
+    for statement in &trigger.body {
+        let statement = bind_row(statement, columns, old, new)?;
+        if let Err(err) = process_command(&statement, db) {
+            db.counters.last_insert_rowid = last_insert_rowid;
+            return Err(err);
+        }
+    }
+    db.counters.last_insert_rowid = last_insert_rowid;
+    Ok(())
+}
+
+/// Replaces every `NEW.column` and `OLD.column` of `sql` with the literal of the value of
+/// the column in the row.
+fn bind_row(
+    sql: &str,
+    columns: &[String],
+    old: Option<&[Value]>,
+    new: Option<&[Value]>,
+) -> Result<String> {
+    bind_rows(sql, columns, &[("NEW", new), ("OLD", old)])
+}
+
+/// Replaces every `name.column` of `sql`, for each of the `rows` named `name`, with the
+/// literal of the value of the column in the row, the rows having the columns `columns`.
+/// Names are matched ignoring case, a row that isn't there can't be referred to.
+pub fn bind_rows(
+    sql: &str,
+    columns: &[String],
+    rows: &[(&str, Option<&[Value]>)],
+) -> Result<String> {
+    let tokens = tokenize(sql)?;
+    let mut bound = String::with_capacity(sql.len());
+    let mut i = 0;
+    while i < tokens.len() {
+        if let (Token::Word(row), Some(Token::Period), Some(Token::Word(column))) =
+            (&tokens[i], tokens.get(i + 1), tokens.get(i + 2))
+        {
+            let values = rows
+                .iter()
+                .find(|(name, _)| row.value.eq_ignore_ascii_case(name));
+            if let Some((name, values)) = values {
+                let values = values.ok_or_else(|| {
+                    SQLRiteError::General(format!("no such column: {}.{}", name, column.value))
+                })?;
+                let value = columns
+                    .iter()
+                    .position(|col| *col == column.value)
+                    .and_then(|position| values.get(position))
+                    .ok_or_else(|| {
+                        SQLRiteError::General(format!("no such column: {}.{}", name, column.value))
+                    })?;
+                bound.push_str(&quote_literal(value));
+                i += 3;
+                continue;
+            }
+        }
+        match &tokens[i] {
+            // The tokenizer unescapes string literals, they have to be quoted back
+            Token::SingleQuotedString(s) => bound.push_str(&quote_literal(&Value::Text(s.clone()))),
+            token => bound.push_str(&token.to_string()),
+        }
+        i += 1;
+    }
+    Ok(bound)
+}
+
+/// Runs `f` so that either all of its changes to the tables are kept or, if it fails,
+/// none of them are, which makes a statement and the triggers it fires atomic.
+pub fn in_transaction<T>(
+    db: &mut Database,
+    f: impl FnOnce(&mut Database) -> Result<T>,
+) -> Result<T> {
+    let snapshot = serde_json::to_string(&db.tables)
+        .map_err(|err| SQLRiteError::Internal(format!("{}", err)))?;
+    let counters: ChangeCounters = db.counters;
+    let result = f(db);
+    if result.is_err() {
+        db.tables = serde_json::from_str::<HashMap<String, Table>>(&snapshot)
+            .map_err(|err| SQLRiteError::Internal(format!("{}", err)))?;
+        db.counters = counters;
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sql::process_query;
+
+    fn execute(db: &mut Database, sql: &str) -> String {
+        process_command(sql, db).unwrap().message
+    }
+
+    #[test]
+    fn insert_trigger_test() {
+        let mut db = Database::new("tempdb".to_string());
+        execute(
+            &mut db,
+            "CREATE TABLE posts (id INTEGER PRIMARY KEY, title TEXT, published INTEGER);",
+        );
+        execute(
+            &mut db,
+            "CREATE TABLE audit (id INTEGER PRIMARY KEY, action TEXT, post INTEGER);",
+        );
+        assert_eq!(
+            execute(
+                &mut db,
+                "CREATE TRIGGER log_posts AFTER INSERT ON posts WHEN NEW.published = 1
+                 BEGIN
+                     INSERT INTO audit (action, post) VALUES ('it''s published', NEW.id);
+                 END;",
+            ),
+            "CREATE TRIGGER Statement executed."
+        );
+        assert!(process_command(
+            "CREATE TRIGGER log_posts AFTER INSERT ON posts BEGIN DELETE FROM audit; END;",
+            &mut db
+        )
+        .is_err());
+        assert!(process_command(
+            "CREATE TRIGGER t AFTER INSERT ON missing BEGIN DELETE FROM audit; END;",
+            &mut db
+        )
+        .is_err());
+
+        execute(
+            &mut db,
+            "INSERT INTO posts (title, published) VALUES ('draft', 0), ('hello', 1);",
+        );
+        let result = process_query("SELECT action, post FROM audit;", &db).unwrap();
+        assert_eq!(
+            result.rows,
+            vec![vec![
+                Value::Text("it's published".to_string()),
+                Value::Integer(2)
+            ]]
+        );
+        // The trigger's INSERT doesn't change last_insert_rowid()
+        assert_eq!(db.counters.last_insert_rowid, 2);
+        assert_eq!(db.counters.changes, 2);
+
+        execute(&mut db, "DROP TRIGGER log_posts;");
+        assert!(db.triggers.is_empty());
+        assert!(process_command("DROP TRIGGER log_posts;", &mut db).is_err());
+        execute(&mut db, "DROP TRIGGER IF EXISTS log_posts;");
+    }
+
+    #[test]
+    fn failing_trigger_rolls_back_test() {
+        let mut db = Database::new("tempdb".to_string());
+        execute(
+            &mut db,
+            "CREATE TABLE users (id INTEGER PRIMARY KEY, email TEXT UNIQUE);",
+        );
+        execute(
+            &mut db,
+            "CREATE TABLE emails (id INTEGER PRIMARY KEY, email TEXT UNIQUE);",
+        );
+        execute(
+            &mut db,
+            "CREATE TRIGGER copy_email BEFORE INSERT ON users
+             BEGIN
+                 INSERT INTO emails (email) VALUES (NEW.email);
+             END;",
+        );
+
+        execute(&mut db, "INSERT INTO emails (email) VALUES ('b@x.com');");
+        assert!(process_command(
+            "INSERT INTO users (email) VALUES ('a@x.com'), ('b@x.com');",
+            &mut db
+        )
+        .is_err());
+
+        // Neither the first row nor the row its trigger inserted are kept
+        let users = process_query("SELECT email FROM users;", &db).unwrap();
+        assert!(users.rows.is_empty());
+        let emails = process_query("SELECT email FROM emails;", &db).unwrap();
+        assert_eq!(emails.rows, vec![vec![Value::Text("b@x.com".to_string())]]);
+    }
+
+    #[test]
+    fn delete_trigger_test() {
+        let mut db = Database::new("tempdb".to_string());
+        execute(
+            &mut db,
+            "CREATE TABLE posts (id INTEGER PRIMARY KEY, title TEXT, published INTEGER);",
+        );
+        execute(
+            &mut db,
+            "CREATE TABLE audit (id INTEGER PRIMARY KEY, action TEXT, post INTEGER);",
+        );
+        execute(
+            &mut db,
+            "CREATE TRIGGER log_deletes AFTER DELETE ON posts
+             BEGIN
+                 INSERT INTO audit (action, post) VALUES ('deleted', OLD.id);
+             END;",
+        );
+        execute(
+            &mut db,
+            "INSERT INTO posts (title, published) VALUES ('a', 0), ('b', 1), ('c', 0);",
+        );
+
+        execute(&mut db, "DELETE FROM posts WHERE published = 0;");
+        let result = process_query("SELECT action, post FROM audit;", &db).unwrap();
+        assert_eq!(
+            result.rows,
+            vec![
+                vec![Value::Text("deleted".to_string()), Value::Integer(1)],
+                vec![Value::Text("deleted".to_string()), Value::Integer(3)],
+            ]
+        );
+        assert_eq!(db.counters.changes, 2);
+
+        // A failing trigger keeps the rows
+        execute(
+            &mut db,
+            "CREATE TABLE log (id INTEGER PRIMARY KEY, post INTEGER UNIQUE);",
+        );
+        execute(&mut db, "INSERT INTO log (post) VALUES (2);");
+        execute(
+            &mut db,
+            "CREATE TRIGGER log_post BEFORE DELETE ON posts
+             BEGIN
+                 INSERT INTO log (post) VALUES (OLD.id);
+             END;",
+        );
+        assert!(process_command("DELETE FROM posts;", &mut db).is_err());
+        let posts = process_query("SELECT title FROM posts;", &db).unwrap();
+        assert_eq!(posts.rows, vec![vec![Value::Text("b".to_string())]]);
+    }
+
+    #[test]
+    fn update_trigger_test() {
+        let mut db = Database::new("tempdb".to_string());
+        execute(
+            &mut db,
+            "CREATE TABLE posts (id INTEGER PRIMARY KEY, title TEXT, views INTEGER);",
+        );
+        execute(
+            &mut db,
+            "CREATE TABLE audit (id INTEGER PRIMARY KEY, post INTEGER, before INTEGER, after INTEGER);",
+        );
+        execute(
+            &mut db,
+            "CREATE TRIGGER log_views AFTER UPDATE OF views ON posts
+             BEGIN
+                 INSERT INTO audit (post, before, after) VALUES (OLD.id, OLD.views, NEW.views);
+             END;",
+        );
+        execute(
+            &mut db,
+            "INSERT INTO posts (title, views) VALUES ('a', 1), ('b', 5), ('c', 2);",
+        );
+
+        execute(
+            &mut db,
+            "UPDATE posts SET views = views * 10 WHERE views < 5;",
+        );
+        execute(&mut db, "UPDATE posts SET title = 'd' WHERE id = 2;");
+        let result = process_query("SELECT post, before, after FROM audit;", &db).unwrap();
+        assert_eq!(
+            result.rows,
+            vec![
+                [1, 1, 10].map(Value::Integer).to_vec(),
+                [3, 2, 20].map(Value::Integer).to_vec(),
+            ]
+        );
+
+        // A failing trigger keeps the rows as they were
+        execute(
+            &mut db,
+            "CREATE TABLE log (id INTEGER PRIMARY KEY, post INTEGER UNIQUE);",
+        );
+        execute(&mut db, "INSERT INTO log (post) VALUES (3);");
+        execute(
+            &mut db,
+            "CREATE TRIGGER log_post BEFORE UPDATE ON posts
+             BEGIN
+                 INSERT INTO log (post) VALUES (OLD.id);
+             END;",
+        );
+        assert!(process_command("UPDATE posts SET views = 0;", &mut db).is_err());
+        let views = process_query("SELECT views FROM posts;", &db).unwrap();
+        assert_eq!(
+            views.rows,
+            vec![
+                vec![Value::Integer(10)],
+                vec![Value::Integer(5)],
+                vec![Value::Integer(20)],
+            ]
+        );
+        assert_eq!(
+            process_query("SELECT post FROM audit;", &db)
+                .unwrap()
+                .rows
+                .len(),
+            2
+        );
+    }
+}
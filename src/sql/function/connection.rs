@@ -0,0 +1,67 @@
+//! Built-in functions reading the counters of the connection. Unlike the functions in the
+//! registry they need the database they run against, so they are resolved before it.
+use crate::error::Result;
+use crate::sql::db::database::Database;
+use crate::sql::function::check_arg_count;
+use crate::sql::value::Value;
+
+/// Calls the connection function named `name`, `None` if there is no such function.
+pub fn call(db: &Database, name: &str, args: &[Value]) -> Option<Result<Value>> {
+    let value = match name.to_lowercase().as_str() {
+        "last_insert_rowid" => db.counters.last_insert_rowid,
+        "changes" => db.counters.changes,
+        "total_changes" => db.counters.total_changes,
+        _ => return None,
+    };
+    Some(check_arg_count(name, args, 0, 0).map(|_| Value::Integer(value)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sql::{process_command, process_query};
+
+    /// The counters as read by SQL, there is one result row per row of `users`
+    fn counters(db: &Database) -> Vec<Value> {
+        process_query(
+            "SELECT last_insert_rowid(), changes(), total_changes() FROM users;",
+            db,
+        )
+        .unwrap()
+        .rows
+        .remove(0)
+    }
+
+    #[test]
+    fn connection_functions_test() {
+        let mut db = Database::new("tempdb".to_string());
+        assert_eq!(
+            call(&db, "LAST_INSERT_ROWID", &[]),
+            Some(Ok(Value::Integer(0)))
+        );
+
+        process_command(
+            "CREATE TABLE users (id INTEGER PRIMARY KEY, name TEXT);",
+            &mut db,
+        )
+        .unwrap();
+        process_command(
+            "INSERT INTO users (name) VALUES ('josh'), ('mary');",
+            &mut db,
+        )
+        .unwrap();
+        assert_eq!(
+            counters(&db),
+            vec![Value::Integer(2), Value::Integer(2), Value::Integer(2)]
+        );
+
+        process_command("INSERT INTO users (id, name) VALUES (10, 'bob');", &mut db).unwrap();
+        assert_eq!(
+            counters(&db),
+            vec![Value::Integer(10), Value::Integer(1), Value::Integer(3)]
+        );
+
+        assert!(call(&db, "changes", &[Value::Integer(1)]).unwrap().is_err());
+        assert!(call(&db, "random", &[]).is_none());
+    }
+}
@@ -0,0 +1,363 @@
+//! JSON functions, after SQLite's JSON1 extension, operating on TEXT values holding JSON.
+//! Elements are addressed with paths like `$.tags[0]` or `$.items[#-1].name`.
+//!
+//! SQLite tags the results of JSON functions so they are embedded as JSON rather than as
+//! strings when passed to `json_array()` or `json_object()`. SQLRite values carry no such
+//! tag, instead text arguments holding a valid JSON array or object are embedded as JSON.
+use serde_json::{Map, Number, Value as Json};
+
+use crate::error::{Result, SQLRiteError};
+use crate::sql::function::{check_arg_count, FunctionRegistry};
+use crate::sql::value::Value;
+
+pub fn register(functions: &mut FunctionRegistry) {
+    functions.register("json", 1, true, |args| {
+        Ok(match args[0].to_text() {
+            Some(text) => Value::Text(parse_json(&text)?.to_string()),
+            None => Value::Null,
+        })
+    });
+    functions.register("json_valid", 1, true, |args| {
+        Ok(match args[0].to_text() {
+            Some(text) => Value::Integer(i64::from(serde_json::from_str::<Json>(&text).is_ok())),
+            None => Value::Null,
+        })
+    });
+    functions.register("json_quote", 1, true, |args| {
+        let json = match &args[0] {
+            Value::Text(text) => Json::String(text.clone()),
+            value => to_json(value)?,
+        };
+        Ok(Value::Text(json.to_string()))
+    });
+    functions.register("json_array", -1, true, |args| {
+        let array = args.iter().map(to_json).collect::<Result<Vec<Json>>>()?;
+        Ok(Value::Text(Json::Array(array).to_string()))
+    });
+    functions.register("json_object", -1, true, json_object);
+    functions.register("json_extract", -1, true, json_extract);
+    functions.register("json_type", -1, true, |args| {
+        check_arg_count("json_type", args, 1, 2)?;
+        Ok(match lookup_arg(args)? {
+            Some(json) => Value::Text(type_name(&json).to_string()),
+            None => Value::Null,
+        })
+    });
+    functions.register("json_array_length", -1, true, |args| {
+        check_arg_count("json_array_length", args, 1, 2)?;
+        Ok(match lookup_arg(args)? {
+            Some(Json::Array(array)) => Value::Integer(array.len() as i64),
+            Some(_) => Value::Integer(0),
+            None => Value::Null,
+        })
+    });
+}
+
+/// A step of a JSON path
+#[derive(Debug, PartialEq, Clone)]
+pub enum PathStep {
+    /// `.key` or `."key"`: member of an object
+    Key(String),
+    /// `[N]`: element of an array, from its start
+    Index(usize),
+    /// `[#-N]`: element of an array, from its end. `[#]` is one past the last element.
+    FromEnd(usize),
+}
+
+/// Parses `text` as JSON.
+pub fn parse_json(text: &str) -> Result<Json> {
+    serde_json::from_str(text).map_err(|_| SQLRiteError::General("malformed JSON".to_string()))
+}
+
+/// Parses a JSON path: `$` followed by any number of `.key` and `[index]` steps.
+pub fn parse_path(path: &str) -> Result<Vec<PathStep>> {
+    let error = || SQLRiteError::General(format!("JSON path error near '{}'", path));
+    let mut rest = path.strip_prefix('$').ok_or_else(error)?;
+    let mut steps = vec![];
+    while !rest.is_empty() {
+        if let Some(after) = rest.strip_prefix('.') {
+            let (key, after) = if let Some(quoted) = after.strip_prefix('"') {
+                let end = quoted.find('"').ok_or_else(error)?;
+                (&quoted[..end], &quoted[end + 1..])
+            } else {
+                let end = after.find(['.', '[']).unwrap_or(after.len());
+                (&after[..end], &after[end..])
+            };
+            if key.is_empty() {
+                return Err(error());
+            }
+            steps.push(PathStep::Key(key.to_string()));
+            rest = after;
+        } else if let Some(after) = rest.strip_prefix('[') {
+            let end = after.find(']').ok_or_else(error)?;
+            let index = after[..end].trim();
+            steps.push(match index.strip_prefix('#') {
+                Some("") => PathStep::FromEnd(0),
+                Some(from_end) => match from_end.trim_start().strip_prefix('-') {
+                    Some(n) => PathStep::FromEnd(n.trim().parse().map_err(|_| error())?),
+                    None => return Err(error()),
+                },
+                None => PathStep::Index(index.parse().map_err(|_| error())?),
+            });
+            rest = &after[end + 1..];
+        } else {
+            return Err(error());
+        }
+    }
+    Ok(steps)
+}
+
+/// Returns the element of `json` at `path`, if there is one.
+pub fn lookup<'a>(json: &'a Json, path: &[PathStep]) -> Option<&'a Json> {
+    path.iter().try_fold(json, |json, step| match (step, json) {
+        (PathStep::Key(key), Json::Object(object)) => object.get(key),
+        (PathStep::Index(index), Json::Array(array)) => array.get(*index),
+        (PathStep::FromEnd(n), Json::Array(array)) => array
+            .len()
+            .checked_sub(*n)
+            .and_then(|index| array.get(index)),
+        _ => None,
+    })
+}
+
+/// Appends the path step to the member `key` of an object to `path`, quoting the key
+/// when it isn't a plain identifier.
+pub fn push_key(path: &mut String, key: &str) {
+    if !key.is_empty() && key.chars().all(|c| c.is_alphanumeric() || c == '_') {
+        path.push('.');
+        path.push_str(key);
+    } else {
+        path.push_str(".\"");
+        path.push_str(key);
+        path.push('"');
+    }
+}
+
+/// Name of the JSON type of `json`, as returned by `json_type()`
+pub fn type_name(json: &Json) -> &'static str {
+    match json {
+        Json::Null => "null",
+        Json::Bool(true) => "true",
+        Json::Bool(false) => "false",
+        Json::Number(n) if n.is_i64() => "integer",
+        Json::Number(_) => "real",
+        Json::String(_) => "text",
+        Json::Array(_) => "array",
+        Json::Object(_) => "object",
+    }
+}
+
+/// Converts a JSON element to a SQL value. Booleans become 1 and 0, arrays and objects
+/// are returned as JSON text.
+pub fn to_value(json: &Json) -> Value {
+    match json {
+        Json::Null => Value::Null,
+        Json::Bool(b) => Value::Integer(i64::from(*b)),
+        Json::Number(n) => match n.as_i64() {
+            Some(i) => Value::Integer(i),
+            None => n.as_f64().map_or(Value::Null, Value::Real),
+        },
+        Json::String(s) => Value::Text(s.clone()),
+        json => Value::Text(json.to_string()),
+    }
+}
+
+/// Converts a SQL value to JSON, text holding a JSON array or object is embedded as is.
+fn to_json(value: &Value) -> Result<Json> {
+    Ok(match value {
+        Value::Null => Json::Null,
+        Value::Integer(v) => Json::Number(Number::from(*v)),
+        // JSON has no representation for infinities and NaN
+        Value::Real(v) => Number::from_f64(*v).map_or(Json::Null, Json::Number),
+        Value::Bool(v) => Json::Bool(*v),
+        Value::Text(text) if text.trim_start().starts_with(['[', '{']) => {
+            serde_json::from_str(text).unwrap_or_else(|_| Json::String(text.clone()))
+        }
+        Value::Text(text) => Json::String(text.clone()),
+        Value::Blob(_) => {
+            return Err(SQLRiteError::General(
+                "JSON cannot hold BLOB values".to_string(),
+            ))
+        }
+    })
+}
+
+/// `json_object(LABEL1, VALUE1, ...)`: a JSON object made of the label/value pairs given
+fn json_object(args: &[Value]) -> Result<Value> {
+    if args.len() % 2 == 1 {
+        return Err(SQLRiteError::General(
+            "json_object() requires an even number of arguments".to_string(),
+        ));
+    }
+    let mut object = Map::new();
+    for pair in args.chunks(2) {
+        let label = match &pair[0] {
+            Value::Text(label) => label.clone(),
+            _ => {
+                return Err(SQLRiteError::General(
+                    "json_object() labels must be TEXT".to_string(),
+                ))
+            }
+        };
+        object.insert(label, to_json(&pair[1])?);
+    }
+    Ok(Value::Text(Json::Object(object).to_string()))
+}
+
+/// `json_extract(X, P1, P2, ...)`: the element of X at path P1 as a SQL value, NULL if
+/// there is none. With several paths, a JSON array of the elements at each of them.
+fn json_extract(args: &[Value]) -> Result<Value> {
+    check_arg_count("json_extract", args, 2, usize::MAX)?;
+    let json = match args[0].to_text() {
+        Some(text) => parse_json(&text)?,
+        None => return Ok(Value::Null),
+    };
+    let mut found = vec![];
+    for path in &args[1..] {
+        let path = match path.to_text() {
+            Some(path) => parse_path(&path)?,
+            None => return Ok(Value::Null),
+        };
+        found.push(lookup(&json, &path));
+    }
+    if found.len() == 1 {
+        return Ok(found[0].map_or(Value::Null, to_value));
+    }
+    let array = found
+        .into_iter()
+        .map(|json| json.cloned().unwrap_or(Json::Null))
+        .collect();
+    Ok(Value::Text(Json::Array(array).to_string()))
+}
+
+/// Reads the JSON in the first argument, and returns its element at the path in the
+/// second one if there is one. `None` for NULL arguments and missing elements.
+fn lookup_arg(args: &[Value]) -> Result<Option<Json>> {
+    let json = match args[0].to_text() {
+        Some(text) => parse_json(&text)?,
+        None => return Ok(None),
+    };
+    let path = match args.get(1) {
+        Some(path) => match path.to_text() {
+            Some(path) => parse_path(&path)?,
+            None => return Ok(None),
+        },
+        None => vec![],
+    };
+    Ok(lookup(&json, &path).cloned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text(s: &str) -> Value {
+        Value::Text(s.to_string())
+    }
+
+    fn call(name: &str, args: &[Value]) -> Result<Value> {
+        FunctionRegistry::new().call(name, args)
+    }
+
+    #[test]
+    fn json_path_test() {
+        assert_eq!(parse_path("$").unwrap(), vec![]);
+        assert_eq!(
+            parse_path("$.a[2].\"b c\"[#-1][#]").unwrap(),
+            vec![
+                PathStep::Key("a".to_string()),
+                PathStep::Index(2),
+                PathStep::Key("b c".to_string()),
+                PathStep::FromEnd(1),
+                PathStep::FromEnd(0),
+            ]
+        );
+        assert!(parse_path("a.b").is_err());
+        assert!(parse_path("$.").is_err());
+        assert!(parse_path("$[x]").is_err());
+        assert!(parse_path("$[1").is_err());
+    }
+
+    #[test]
+    fn json_extract_test() {
+        let doc = text(
+            r#"{"name": "josh", "age": 30, "score": 9.5, "admin": true, "tags": ["a", "b"], "address": {"city": "Lisbon"}}"#,
+        );
+        let extract = |path: &str| call("json_extract", &[doc.clone(), text(path)]).unwrap();
+        assert_eq!(extract("$.name"), text("josh"));
+        assert_eq!(extract("$.age"), Value::Integer(30));
+        assert_eq!(extract("$.score"), Value::Real(9.5));
+        assert_eq!(extract("$.admin"), Value::Integer(1));
+        assert_eq!(extract("$.tags[1]"), text("b"));
+        assert_eq!(extract("$.tags[#-1]"), text("b"));
+        assert_eq!(extract("$.tags"), text(r#"["a","b"]"#));
+        assert_eq!(extract("$.address.city"), text("Lisbon"));
+        assert_eq!(extract("$.missing"), Value::Null);
+        assert_eq!(
+            call("json_extract", &[doc.clone(), text("$.age"), text("$.x")]),
+            Ok(text("[30,null]"))
+        );
+        assert_eq!(
+            call("json_extract", &[Value::Null, text("$")]),
+            Ok(Value::Null)
+        );
+        assert!(call("json_extract", &[text("{oops"), text("$")]).is_err());
+    }
+
+    #[test]
+    fn json_build_test() {
+        assert_eq!(
+            call(
+                "json_array",
+                &[Value::Integer(1), Value::Real(2.5), text("x"), Value::Null]
+            ),
+            Ok(text(r#"[1,2.5,"x",null]"#))
+        );
+        assert_eq!(
+            call(
+                "json_object",
+                &[
+                    text("name"),
+                    text("josh"),
+                    text("tags"),
+                    text(r#"["a"]"#),
+                    text("id"),
+                    Value::Integer(1)
+                ]
+            ),
+            Ok(text(r#"{"name":"josh","tags":["a"],"id":1}"#))
+        );
+        assert!(call("json_object", &[text("a")]).is_err());
+        assert!(call("json_object", &[Value::Integer(1), Value::Integer(2)]).is_err());
+        assert!(call("json_array", &[Value::Blob(vec![1])]).is_err());
+        assert_eq!(call("json_quote", &[text("[1]")]), Ok(text(r#""[1]""#)));
+        assert_eq!(
+            call("json", &[text(" { \"a\" : [1, 2] } ")]),
+            Ok(text(r#"{"a":[1,2]}"#))
+        );
+    }
+
+    #[test]
+    fn json_inspect_test() {
+        let doc = text(r#"{"a": [1, 2.5, null, false], "b": "x"}"#);
+        let json_type = |path: &str| call("json_type", &[doc.clone(), text(path)]).unwrap();
+        assert_eq!(json_type("$"), text("object"));
+        assert_eq!(json_type("$.a"), text("array"));
+        assert_eq!(json_type("$.a[0]"), text("integer"));
+        assert_eq!(json_type("$.a[1]"), text("real"));
+        assert_eq!(json_type("$.a[2]"), text("null"));
+        assert_eq!(json_type("$.a[3]"), text("false"));
+        assert_eq!(json_type("$.b"), text("text"));
+        assert_eq!(json_type("$.c"), Value::Null);
+        assert_eq!(
+            call("json_array_length", &[doc.clone(), text("$.a")]),
+            Ok(Value::Integer(4))
+        );
+        assert_eq!(
+            call("json_array_length", std::slice::from_ref(&doc)),
+            Ok(Value::Integer(0))
+        );
+        assert_eq!(call("json_valid", &[doc]), Ok(Value::Integer(1)));
+        assert_eq!(call("json_valid", &[text("{")]), Ok(Value::Integer(0)));
+    }
+}
@@ -0,0 +1,240 @@
+//! Built-in math functions. Any NULL argument makes the result NULL. Functions that can
+//! keep their result exact return an integer when all their arguments are integers and a
+//! real otherwise, text arguments are read as numbers like SQLite does.
+use std::cmp::Ordering;
+
+use crate::error::{Result, SQLRiteError};
+use crate::sql::function::{check_arg_count, FunctionRegistry};
+use crate::sql::value::Value;
+
+pub fn register(functions: &mut FunctionRegistry) {
+    functions.register("abs", 1, true, abs);
+    functions.register("round", -1, true, round);
+    functions.register("ceil", 1, true, |args| map_real(&args[0], f64::ceil));
+    functions.register("ceiling", 1, true, |args| map_real(&args[0], f64::ceil));
+    functions.register("floor", 1, true, |args| map_real(&args[0], f64::floor));
+    functions.register("pow", 2, true, pow);
+    functions.register("power", 2, true, pow);
+    functions.register("sqrt", 1, true, sqrt);
+    functions.register("mod", 2, true, modulo);
+    functions.register("sign", 1, true, sign);
+    functions.register("min", -1, true, |args| {
+        extremum("min", args, Ordering::Less)
+    });
+    functions.register("max", -1, true, |args| {
+        extremum("max", args, Ordering::Greater)
+    });
+}
+
+/// A non-NULL argument read as a number
+enum Number {
+    Integer(i64),
+    Real(f64),
+}
+
+impl Number {
+    fn from_value(value: &Value) -> Option<Number> {
+        match value {
+            Value::Null => None,
+            Value::Integer(v) => Some(Number::Integer(*v)),
+            Value::Bool(v) => Some(Number::Integer(i64::from(*v))),
+            Value::Real(v) => Some(Number::Real(*v)),
+            Value::Text(_) | Value::Blob(_) => {
+                let real = value.to_real()?;
+                let integer = value.to_integer()?;
+                if integer as f64 == real {
+                    Some(Number::Integer(integer))
+                } else {
+                    Some(Number::Real(real))
+                }
+            }
+        }
+    }
+
+    fn as_real(&self) -> f64 {
+        match self {
+            Number::Integer(v) => *v as f64,
+            Number::Real(v) => *v,
+        }
+    }
+}
+
+fn integer_overflow() -> SQLRiteError {
+    SQLRiteError::General("integer overflow".to_string())
+}
+
+/// `abs(X)`: absolute value of X
+fn abs(args: &[Value]) -> Result<Value> {
+    Ok(match Number::from_value(&args[0]) {
+        Some(Number::Integer(v)) => Value::Integer(v.checked_abs().ok_or_else(integer_overflow)?),
+        Some(Number::Real(v)) => Value::Real(v.abs()),
+        None => Value::Null,
+    })
+}
+
+/// `round(X[, Y])`: X rounded to Y digits after the decimal point, 0 by default.
+/// Always returns a real, halves are rounded away from zero.
+fn round(args: &[Value]) -> Result<Value> {
+    check_arg_count("round", args, 1, 2)?;
+    let x = match args[0].to_real() {
+        Some(x) => x,
+        None => return Ok(Value::Null),
+    };
+    let digits = match args.get(1) {
+        Some(digits) => match digits.to_integer() {
+            Some(digits) => digits.clamp(0, 30) as i32,
+            None => return Ok(Value::Null),
+        },
+        None => 0,
+    };
+    let factor = 10f64.powi(digits);
+    let rounded = (x * factor).round() / factor;
+    // Large values can't hold more digits, multiplying them may overflow
+    Ok(Value::Real(if rounded.is_finite() { rounded } else { x }))
+}
+
+/// Applies `f` to a real argument, integers are returned unchanged.
+fn map_real(value: &Value, f: fn(f64) -> f64) -> Result<Value> {
+    Ok(match Number::from_value(value) {
+        Some(Number::Integer(v)) => Value::Integer(v),
+        Some(Number::Real(v)) => Value::Real(f(v)),
+        None => Value::Null,
+    })
+}
+
+/// `pow(X, Y)`: X raised to the power Y, as a real
+fn pow(args: &[Value]) -> Result<Value> {
+    Ok(match (args[0].to_real(), args[1].to_real()) {
+        (Some(x), Some(y)) => Value::Real(x.powf(y)),
+        _ => Value::Null,
+    })
+}
+
+/// `sqrt(X)`: square root of X, as a real, NULL for negative values
+fn sqrt(args: &[Value]) -> Result<Value> {
+    Ok(match args[0].to_real() {
+        Some(x) if x >= 0.0 => Value::Real(x.sqrt()),
+        _ => Value::Null,
+    })
+}
+
+/// `mod(X, Y)`: remainder of X divided by Y, with the sign of X. NULL if Y is 0.
+fn modulo(args: &[Value]) -> Result<Value> {
+    Ok(
+        match (Number::from_value(&args[0]), Number::from_value(&args[1])) {
+            (Some(Number::Integer(_)), Some(Number::Integer(0))) => Value::Null,
+            // i64::MIN % -1 overflows, but the remainder is 0
+            (Some(Number::Integer(x)), Some(Number::Integer(y))) => {
+                Value::Integer(x.checked_rem(y).unwrap_or(0))
+            }
+            (Some(x), Some(y)) => {
+                let y = y.as_real();
+                if y == 0.0 {
+                    Value::Null
+                } else {
+                    Value::Real(x.as_real() % y)
+                }
+            }
+            _ => Value::Null,
+        },
+    )
+}
+
+/// `sign(X)`: -1, 0 or 1 for negative, zero and positive values of X
+fn sign(args: &[Value]) -> Result<Value> {
+    let sign = match &args[0] {
+        // Unlike other math functions, sign() returns NULL for text that isn't a number
+        Value::Text(text) if text.trim().parse::<f64>().is_err() => return Ok(Value::Null),
+        value => match Number::from_value(value) {
+            Some(number) => number.as_real(),
+            None => return Ok(Value::Null),
+        },
+    };
+    Ok(Value::Integer(if sign > 0.0 {
+        1
+    } else if sign < 0.0 {
+        -1
+    } else {
+        0
+    }))
+}
+
+/// Scalar `min(X, Y, ...)` and `max(X, Y, ...)`: the argument that sorts first or last
+fn extremum(name: &str, args: &[Value], wanted: Ordering) -> Result<Value> {
+    check_arg_count(name, args, 2, usize::MAX)?;
+    if args.iter().any(Value::is_null) {
+        return Ok(Value::Null);
+    }
+    let binary = |a: &str, b: &str| a.cmp(b);
+    let mut result = &args[0];
+    for arg in &args[1..] {
+        if arg.compare(result, &binary) == wanted {
+            result = arg;
+        }
+    }
+    Ok(result.clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn call(name: &str, args: &[Value]) -> Value {
+        FunctionRegistry::new().call(name, args).unwrap()
+    }
+
+    #[test]
+    fn math_functions_test() {
+        assert_eq!(call("abs", &[Value::Integer(-3)]), Value::Integer(3));
+        assert_eq!(call("abs", &[Value::Real(-1.5)]), Value::Real(1.5));
+        assert!(FunctionRegistry::new()
+            .call("abs", &[Value::Integer(i64::MIN)])
+            .is_err());
+        assert_eq!(call("round", &[Value::Real(2.5)]), Value::Real(3.0));
+        assert_eq!(call("round", &[Value::Real(-2.5)]), Value::Real(-3.0));
+        assert_eq!(
+            call("round", &[Value::Real(1.23456), Value::Integer(2)]),
+            Value::Real(1.23)
+        );
+        assert_eq!(call("round", &[Value::Integer(7)]), Value::Real(7.0));
+        assert_eq!(call("ceil", &[Value::Real(1.2)]), Value::Real(2.0));
+        assert_eq!(call("floor", &[Value::Real(-1.2)]), Value::Real(-2.0));
+        assert_eq!(call("floor", &[Value::Integer(5)]), Value::Integer(5));
+        assert_eq!(
+            call("pow", &[Value::Integer(2), Value::Integer(10)]),
+            Value::Real(1024.0)
+        );
+        assert_eq!(call("sqrt", &[Value::Integer(16)]), Value::Real(4.0));
+        assert_eq!(call("sqrt", &[Value::Integer(-1)]), Value::Null);
+        assert_eq!(
+            call("mod", &[Value::Integer(-7), Value::Integer(3)]),
+            Value::Integer(-1)
+        );
+        assert_eq!(
+            call("mod", &[Value::Real(7.5), Value::Integer(2)]),
+            Value::Real(1.5)
+        );
+        assert_eq!(
+            call("mod", &[Value::Integer(7), Value::Integer(0)]),
+            Value::Null
+        );
+        assert_eq!(call("sign", &[Value::Real(-0.5)]), Value::Integer(-1));
+        assert_eq!(call("sign", &[Value::Text("abc".to_string())]), Value::Null);
+        assert_eq!(call("ceil", &[Value::Null]), Value::Null);
+    }
+
+    #[test]
+    fn scalar_min_max_test() {
+        let args = [Value::Integer(3), Value::Real(1.5), Value::Integer(2)];
+        assert_eq!(call("min", &args), Value::Real(1.5));
+        assert_eq!(call("max", &args), Value::Integer(3));
+        assert_eq!(
+            call("max", &[Value::Integer(3), Value::Text("a".to_string())]),
+            Value::Text("a".to_string())
+        );
+        assert_eq!(call("min", &[Value::Integer(3), Value::Null]), Value::Null);
+        assert!(FunctionRegistry::new()
+            .call("min", &[Value::Integer(1)])
+            .is_err());
+    }
+}
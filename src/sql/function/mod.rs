@@ -0,0 +1,174 @@
+pub mod connection;
+pub mod json;
+pub mod math;
+pub mod null;
+pub mod printf;
+pub mod random;
+pub mod string;
+pub mod uuid;
+
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
+
+use crate::error::{Result, SQLRiteError};
+use crate::sql::value::Value;
+
+/// Signature of the Rust closures that can be called from SQL expressions.
+pub type ScalarFn = dyn Fn(&[Value]) -> Result<Value> + Send + Sync + 'static;
+
+/// A scalar function registered under a name, callable from any SQL expression.
+#[derive(Clone)]
+pub struct ScalarFunction {
+    /// Number of arguments the function takes, -1 for any number of arguments
+    pub n_args: i32,
+    /// Whether the function always returns the same result for the same arguments.
+    /// Only deterministic functions can be used where results are stored, like indexes.
+    pub deterministic: bool,
+    func: Arc<ScalarFn>,
+}
+
+impl ScalarFunction {
+    /// Calls the function with `args`, after checking the number of arguments.
+    pub fn call(&self, name: &str, args: &[Value]) -> Result<Value> {
+        if self.n_args >= 0 && args.len() != self.n_args as usize {
+            return Err(SQLRiteError::General(format!(
+                "wrong number of arguments to function {}()",
+                name
+            )));
+        }
+        (self.func)(args)
+    }
+}
+
+/// Checks that a function taking a variable number of arguments got between `min` and `max`.
+pub(crate) fn check_arg_count(name: &str, args: &[Value], min: usize, max: usize) -> Result<()> {
+    if args.len() < min || args.len() > max {
+        return Err(SQLRiteError::General(format!(
+            "wrong number of arguments to function {}()",
+            name
+        )));
+    }
+    Ok(())
+}
+
+/// The registry of scalar functions available to the SQL statements run against a `Database`.
+/// Function names are case insensitive, like in SQLite.
+#[derive(Clone)]
+pub struct FunctionRegistry {
+    functions: HashMap<String, ScalarFunction>,
+}
+
+impl FunctionRegistry {
+    /// Creates a registry with the built-in functions.
+    pub fn new() -> Self {
+        let mut registry = FunctionRegistry {
+            functions: HashMap::new(),
+        };
+        json::register(&mut registry);
+        math::register(&mut registry);
+        null::register(&mut registry);
+        printf::register(&mut registry);
+        random::register(&mut registry);
+        string::register(&mut registry);
+        uuid::register(&mut registry);
+        // `X MATCH Y` is parsed as `match(X, Y)`, which only virtual tables can answer
+        registry.register("match", 2, true, |_| {
+            Err(SQLRiteError::General(
+                "unable to use function MATCH in the requested context".to_string(),
+            ))
+        });
+        registry
+    }
+
+    /// Registers `func` under `name`, replacing any function previously registered with it.
+    pub fn register<F>(&mut self, name: &str, n_args: i32, deterministic: bool, func: F)
+    where
+        F: Fn(&[Value]) -> Result<Value> + Send + Sync + 'static,
+    {
+        self.functions.insert(
+            name.to_lowercase(),
+            ScalarFunction {
+                n_args,
+                deterministic,
+                func: Arc::new(func),
+            },
+        );
+    }
+
+    /// Returns the function registered under `name`, if any.
+    pub fn get(&self, name: &str) -> Option<&ScalarFunction> {
+        self.functions.get(&name.to_lowercase())
+    }
+
+    /// Calls the function registered under `name` with `args`.
+    pub fn call(&self, name: &str, args: &[Value]) -> Result<Value> {
+        match self.get(name) {
+            Some(function) => function.call(name, args),
+            None => Err(SQLRiteError::General(format!("no such function: {}", name))),
+        }
+    }
+}
+
+impl Default for FunctionRegistry {
+    fn default() -> Self {
+        FunctionRegistry::new()
+    }
+}
+
+impl fmt::Debug for FunctionRegistry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut names: Vec<&String> = self.functions.keys().collect();
+        names.sort();
+        f.debug_struct("FunctionRegistry")
+            .field("functions", &names)
+            .finish()
+    }
+}
+
+impl PartialEq for FunctionRegistry {
+    // Closures can't be compared, two registries are equal when they expose the same functions
+    fn eq(&self, other: &Self) -> bool {
+        self.functions.len() == other.functions.len()
+            && self.functions.iter().all(|(name, function)| {
+                matches!(other.functions.get(name), Some(other)
+                    if function.n_args == other.n_args && function.deterministic == other.deterministic)
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn function_registry_call_test() {
+        let mut functions = FunctionRegistry::new();
+        functions.register("double", 1, true, |args| match &args[0] {
+            Value::Integer(v) => Ok(Value::Integer(v * 2)),
+            _ => Ok(Value::Null),
+        });
+
+        assert_eq!(
+            functions.call("DOUBLE", &[Value::Integer(21)]),
+            Ok(Value::Integer(42))
+        );
+        assert!(functions.get("double").unwrap().deterministic);
+        assert!(functions.call("double", &[]).is_err());
+        assert!(functions.call("triple", &[Value::Integer(1)]).is_err());
+    }
+
+    #[test]
+    fn function_registry_variadic_test() {
+        let mut functions = FunctionRegistry::new();
+        functions.register("count_args", -1, false, |args| {
+            Ok(Value::Integer(args.len() as i64))
+        });
+
+        assert_eq!(functions.call("count_args", &[]), Ok(Value::Integer(0)));
+        assert_eq!(
+            functions.call("count_args", &[Value::Null, Value::Null]),
+            Ok(Value::Integer(2))
+        );
+    }
+}
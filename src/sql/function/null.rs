@@ -0,0 +1,97 @@
+//! Built-in functions handling NULLs and inspecting the storage class of values.
+use std::cmp::Ordering;
+
+use crate::error::Result;
+use crate::sql::function::{check_arg_count, FunctionRegistry};
+use crate::sql::value::Value;
+
+pub fn register(functions: &mut FunctionRegistry) {
+    functions.register("coalesce", -1, true, |args| {
+        check_arg_count("coalesce", args, 2, usize::MAX)?;
+        Ok(first_not_null(args))
+    });
+    functions.register("ifnull", 2, true, |args| Ok(first_not_null(args)));
+    functions.register("nullif", 2, true, nullif);
+    functions.register("typeof", 1, true, |args| {
+        Ok(Value::Text(args[0].type_name().to_string()))
+    });
+}
+
+/// `coalesce(X, Y, ...)` and `ifnull(X, Y)`: the first argument that isn't NULL, if any.
+/// SQL expressions evaluate their arguments up to that one only, see `eval_coalesce`.
+fn first_not_null(args: &[Value]) -> Value {
+    args.iter()
+        .find(|arg| !arg.is_null())
+        .cloned()
+        .unwrap_or(Value::Null)
+}
+
+/// `nullif(X, Y)`: NULL if X and Y are equal, X otherwise
+fn nullif(args: &[Value]) -> Result<Value> {
+    let binary = |a: &str, b: &str| a.cmp(b);
+    if !args[0].is_null() && args[0].compare(&args[1], &binary) == Ordering::Equal {
+        Ok(Value::Null)
+    } else {
+        Ok(args[0].clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn call(name: &str, args: &[Value]) -> Result<Value> {
+        FunctionRegistry::new().call(name, args)
+    }
+
+    #[test]
+    fn null_functions_test() {
+        assert_eq!(
+            call(
+                "coalesce",
+                &[Value::Null, Value::Integer(2), Value::Integer(3)]
+            ),
+            Ok(Value::Integer(2))
+        );
+        assert_eq!(
+            call("coalesce", &[Value::Null, Value::Null]),
+            Ok(Value::Null)
+        );
+        assert!(call("coalesce", &[Value::Null]).is_err());
+        assert_eq!(
+            call("ifnull", &[Value::Null, Value::Text("none".to_string())]),
+            Ok(Value::Text("none".to_string()))
+        );
+        assert_eq!(
+            call("nullif", &[Value::Integer(1), Value::Real(1.0)]),
+            Ok(Value::Null)
+        );
+        assert_eq!(
+            call("nullif", &[Value::Integer(1), Value::Integer(2)]),
+            Ok(Value::Integer(1))
+        );
+    }
+
+    #[test]
+    fn typeof_test() {
+        let types = [
+            Value::Null,
+            Value::Integer(1),
+            Value::Real(1.5),
+            Value::Text("a".to_string()),
+            Value::Bool(true),
+            Value::Blob(vec![1]),
+        ]
+        .iter()
+        .map(|value| {
+            call("typeof", std::slice::from_ref(value))
+                .unwrap()
+                .to_string()
+        })
+        .collect::<Vec<String>>();
+        assert_eq!(
+            types,
+            vec!["null", "integer", "real", "text", "bool", "blob"]
+        );
+    }
+}
@@ -0,0 +1,407 @@
+//! `printf(FORMAT, ...)` and its alias `format(FORMAT, ...)`, formatting their arguments the
+//! way SQLite's printf does. Conversions follow C's printf, plus SQLite's own `%q`, `%Q`, `%w`
+//! and `%z`. Missing arguments are read as NULL, a NULL format string gives NULL.
+use std::iter::Peekable;
+use std::slice::Iter;
+use std::str::Chars;
+
+use crate::error::{Result, SQLRiteError};
+use crate::sql::function::{check_arg_count, FunctionRegistry};
+use crate::sql::value::Value;
+
+/// Largest width or precision accepted, larger ones are clamped to it
+const MAX_WIDTH: usize = 100_000;
+
+pub fn register(functions: &mut FunctionRegistry) {
+    functions.register("printf", -1, true, |args| printf("printf", args));
+    functions.register("format", -1, true, |args| printf("format", args));
+}
+
+/// Flags, width and precision of a conversion
+#[derive(Debug, Default)]
+struct Spec {
+    left: bool,
+    plus: bool,
+    space: bool,
+    zero: bool,
+    alternate: bool,
+    thousands: bool,
+    width: usize,
+    precision: Option<usize>,
+}
+
+fn printf(name: &str, args: &[Value]) -> Result<Value> {
+    check_arg_count(name, args, 1, usize::MAX)?;
+    let format = match args[0].to_text() {
+        Some(format) => format,
+        None => return Ok(Value::Null),
+    };
+    let mut args = args[1..].iter();
+    let mut chars = format.chars().peekable();
+    let mut out = String::with_capacity(format.len());
+
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            out.push(c);
+            continue;
+        }
+        let mut spec = Spec::default();
+        while let Some(&flag) = chars.peek() {
+            match flag {
+                '-' => spec.left = true,
+                '+' => spec.plus = true,
+                ' ' => spec.space = true,
+                '0' => spec.zero = true,
+                '#' => spec.alternate = true,
+                ',' => spec.thousands = true,
+                // Alternate form for SQLite's floating point output, not supported
+                '!' => (),
+                _ => break,
+            }
+            chars.next();
+        }
+        if chars.peek() == Some(&'*') {
+            chars.next();
+            let width = next_arg(&mut args).to_integer().unwrap_or(0);
+            spec.left |= width < 0;
+            spec.width = (width.unsigned_abs() as usize).min(MAX_WIDTH);
+        } else {
+            spec.width = read_number(&mut chars);
+        }
+        if chars.peek() == Some(&'.') {
+            chars.next();
+            spec.precision = Some(if chars.peek() == Some(&'*') {
+                chars.next();
+                let precision = next_arg(&mut args).to_integer().unwrap_or(0);
+                (precision.max(0) as usize).min(MAX_WIDTH)
+            } else {
+                read_number(&mut chars)
+            });
+        }
+        // Length modifiers are accepted and ignored, all integers are 64 bits
+        while chars.peek() == Some(&'l') {
+            chars.next();
+        }
+
+        let conversion = match chars.next() {
+            Some(conversion) => conversion,
+            // A trailing '%' is dropped, like SQLite does
+            None => break,
+        };
+        let formatted = match conversion {
+            '%' => "%".to_string(),
+            'd' | 'i' => format_integer(next_arg(&mut args).to_integer().unwrap_or(0), &spec),
+            'u' | 'x' | 'X' | 'o' => format_unsigned(
+                next_arg(&mut args).to_integer().unwrap_or(0) as u64,
+                conversion,
+                &spec,
+            ),
+            'f' | 'e' | 'E' | 'g' | 'G' => format_real(
+                next_arg(&mut args).to_real().unwrap_or(0.0),
+                conversion,
+                &spec,
+            ),
+            's' | 'z' => {
+                let text = next_arg(&mut args).to_text().unwrap_or_default();
+                pad("", &truncate(&text, spec.precision), &spec, false)
+            }
+            'q' | 'Q' | 'w' => {
+                let quoted = match next_arg(&mut args).to_text() {
+                    None if conversion == 'Q' => "NULL".to_string(),
+                    None => "(NULL)".to_string(),
+                    Some(text) => {
+                        let text = truncate(&text, spec.precision);
+                        match conversion {
+                            'q' => text.replace('\'', "''"),
+                            'Q' => format!("'{}'", text.replace('\'', "''")),
+                            _ => text.replace('"', "\"\""),
+                        }
+                    }
+                };
+                pad("", &quoted, &spec, false)
+            }
+            'c' => {
+                let text = next_arg(&mut args).to_text().unwrap_or_default();
+                pad("", &truncate(&text, Some(1)), &spec, false)
+            }
+            _ => {
+                return Err(SQLRiteError::General(format!(
+                    "unknown format conversion in {}(): %{}",
+                    name, conversion
+                )))
+            }
+        };
+        out.push_str(&formatted);
+    }
+    Ok(Value::Text(out))
+}
+
+fn next_arg(args: &mut Iter<Value>) -> Value {
+    args.next().cloned().unwrap_or(Value::Null)
+}
+
+fn read_number(chars: &mut Peekable<Chars>) -> usize {
+    let mut number: usize = 0;
+    while let Some(digit) = chars.peek().and_then(|c| c.to_digit(10)) {
+        number = (number * 10 + digit as usize).min(MAX_WIDTH);
+        chars.next();
+    }
+    number
+}
+
+/// The first `precision` characters of `text`, all of them without a precision
+fn truncate(text: &str, precision: Option<usize>) -> String {
+    match precision {
+        Some(precision) => text.chars().take(precision).collect(),
+        None => text.to_string(),
+    }
+}
+
+/// Pads `prefix` followed by `body` to the width of `spec`. Numbers padded with zeros get
+/// them between their prefix and their digits.
+fn pad(prefix: &str, body: &str, spec: &Spec, numeric: bool) -> String {
+    let len = prefix.chars().count() + body.chars().count();
+    if len >= spec.width {
+        return format!("{}{}", prefix, body);
+    }
+    let fill = spec.width - len;
+    if spec.left {
+        format!("{}{}{}", prefix, body, " ".repeat(fill))
+    } else if spec.zero && numeric {
+        format!("{}{}{}", prefix, "0".repeat(fill), body)
+    } else {
+        format!("{}{}{}", " ".repeat(fill), prefix, body)
+    }
+}
+
+fn sign_prefix(negative: bool, spec: &Spec) -> &'static str {
+    if negative {
+        "-"
+    } else if spec.plus {
+        "+"
+    } else if spec.space {
+        " "
+    } else {
+        ""
+    }
+}
+
+/// Zero-pads `digits` to the minimum number of digits given by the precision
+fn min_digits(digits: String, spec: &Spec) -> String {
+    match spec.precision {
+        Some(precision) if digits.len() < precision => {
+            format!("{}{}", "0".repeat(precision - digits.len()), digits)
+        }
+        _ => digits,
+    }
+}
+
+/// Inserts a comma between every group of three digits
+fn group_thousands(digits: &str) -> String {
+    let mut grouped = String::with_capacity(digits.len() + digits.len() / 3);
+    // Digits are ASCII, a group starts wherever the number of digits left is a multiple of 3
+    let offset = digits.len() % 3;
+    for (i, digit) in digits.chars().enumerate() {
+        if i > 0 && i % 3 == offset {
+            grouped.push(',');
+        }
+        grouped.push(digit);
+    }
+    grouped
+}
+
+/// `%d` and `%i`
+fn format_integer(value: i64, spec: &Spec) -> String {
+    let mut digits = min_digits(value.unsigned_abs().to_string(), spec);
+    if spec.thousands {
+        digits = group_thousands(&digits);
+    }
+    // Like in C, a precision disables padding with zeros
+    let numeric = spec.precision.is_none();
+    pad(sign_prefix(value < 0, spec), &digits, spec, numeric)
+}
+
+/// `%u`, `%x`, `%X` and `%o`, negative values are read as their two's complement
+fn format_unsigned(value: u64, conversion: char, spec: &Spec) -> String {
+    let digits = match conversion {
+        'x' => format!("{:x}", value),
+        'X' => format!("{:X}", value),
+        'o' => format!("{:o}", value),
+        _ => value.to_string(),
+    };
+    let mut digits = min_digits(digits, spec);
+    let prefix = match conversion {
+        'x' if spec.alternate && value != 0 => "0x",
+        'X' if spec.alternate && value != 0 => "0X",
+        'o' if spec.alternate && !digits.starts_with('0') => "0",
+        _ => "",
+    };
+    if spec.thousands && conversion == 'u' {
+        digits = group_thousands(&digits);
+    }
+    pad(prefix, &digits, spec, spec.precision.is_none())
+}
+
+/// `%f`, `%e`, `%E`, `%g` and `%G`, with 6 digits of precision by default
+fn format_real(value: f64, conversion: char, spec: &Spec) -> String {
+    let prefix = sign_prefix(value < 0.0, spec);
+    if value.is_nan() {
+        return pad("", "NaN", spec, false);
+    }
+    if value.is_infinite() {
+        return pad(prefix, "Inf", spec, false);
+    }
+    let value = value.abs();
+    let precision = spec.precision.unwrap_or(6);
+    let body = match conversion {
+        'f' => format!("{:.*}", precision, value),
+        'e' | 'E' => format_exponent(value, precision, conversion == 'E'),
+        _ => {
+            // %g picks between %e and %f depending on the exponent, and drops trailing zeros
+            let precision = precision.max(1);
+            let exponent = decimal_exponent(value, precision - 1);
+            let body = if exponent < -4 || exponent >= precision as i32 {
+                format_exponent(value, precision - 1, conversion == 'G')
+            } else {
+                format!("{:.*}", (precision as i32 - 1 - exponent) as usize, value)
+            };
+            if spec.alternate {
+                body
+            } else {
+                strip_trailing_zeros(&body)
+            }
+        }
+    };
+    pad(prefix, &body, spec, true)
+}
+
+/// Exponent of `value` once rounded to `precision` digits after the first one
+fn decimal_exponent(value: f64, precision: usize) -> i32 {
+    let formatted = format!("{:.*e}", precision, value);
+    formatted
+        .rsplit('e')
+        .next()
+        .and_then(|exponent| exponent.parse::<i32>().ok())
+        .unwrap_or(0)
+}
+
+/// `value` in scientific notation, with a signed exponent of at least two digits like in C
+fn format_exponent(value: f64, precision: usize, upper: bool) -> String {
+    let formatted = format!("{:.*e}", precision, value);
+    let (mantissa, exponent) = formatted.split_at(formatted.find('e').unwrap_or(formatted.len()));
+    let exponent = exponent.trim_start_matches('e').parse::<i32>().unwrap_or(0);
+    format!(
+        "{}{}{}{:02}",
+        mantissa,
+        if upper { 'E' } else { 'e' },
+        if exponent < 0 { '-' } else { '+' },
+        exponent.abs()
+    )
+}
+
+/// Removes the zeros at the end of the decimals of a number, and the decimal point if
+/// nothing is left after it
+fn strip_trailing_zeros(number: &str) -> String {
+    let (mantissa, exponent) = match number.find(['e', 'E']) {
+        Some(index) => number.split_at(index),
+        None => (number, ""),
+    };
+    if !mantissa.contains('.') {
+        return number.to_string();
+    }
+    let mantissa = mantissa.trim_end_matches('0').trim_end_matches('.');
+    format!("{}{}", mantissa, exponent)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn printf(args: &[Value]) -> Value {
+        FunctionRegistry::new().call("printf", args).unwrap()
+    }
+
+    fn format_one(format: &str, arg: Value) -> String {
+        printf(&[Value::Text(format.to_string()), arg]).to_string()
+    }
+
+    #[test]
+    fn printf_integer_test() {
+        assert_eq!(format_one("%d", Value::Integer(42)), "42");
+        assert_eq!(format_one("%5d|", Value::Integer(-42)), "  -42|");
+        assert_eq!(format_one("%-5d|", Value::Integer(42)), "42   |");
+        assert_eq!(format_one("%05d", Value::Integer(-42)), "-0042");
+        assert_eq!(format_one("%+.3d", Value::Integer(7)), "+007");
+        assert_eq!(format_one("%,d", Value::Integer(1234567)), "1,234,567");
+        assert_eq!(format_one("%x", Value::Integer(255)), "ff");
+        assert_eq!(format_one("%#X", Value::Integer(255)), "0XFF");
+        assert_eq!(format_one("%o", Value::Integer(8)), "10");
+        assert_eq!(format_one("%d", Value::Text("12abc".to_string())), "12");
+        assert_eq!(format_one("%d", Value::Null), "0");
+    }
+
+    #[test]
+    fn printf_real_test() {
+        assert_eq!(format_one("%f", Value::Real(1.5)), "1.500000");
+        assert_eq!(format_one("%.2f", Value::Real(2.345)), "2.35");
+        assert_eq!(format_one("%8.1f|", Value::Real(-2.25)), "    -2.2|");
+        assert_eq!(format_one("%e", Value::Real(12345.678)), "1.234568e+04");
+        assert_eq!(format_one("%.1E", Value::Real(0.00012)), "1.2E-04");
+        assert_eq!(format_one("%g", Value::Real(0.0001)), "0.0001");
+        assert_eq!(format_one("%g", Value::Real(1234567.0)), "1.23457e+06");
+        assert_eq!(format_one("%g", Value::Integer(100)), "100");
+        assert_eq!(format_one("%g", Value::Real(2.5)), "2.5");
+        assert_eq!(format_one("%f", Value::Real(f64::INFINITY)), "Inf");
+    }
+
+    #[test]
+    fn printf_text_test() {
+        assert_eq!(format_one("%s!", Value::Text("josh".to_string())), "josh!");
+        assert_eq!(
+            format_one("[%6s]", Value::Text("josh".to_string())),
+            "[  josh]"
+        );
+        assert_eq!(
+            format_one("[%-6s]", Value::Text("josh".to_string())),
+            "[josh  ]"
+        );
+        assert_eq!(format_one("%.2s", Value::Text("josh".to_string())), "jo");
+        assert_eq!(format_one("%s", Value::Null), "");
+        assert_eq!(format_one("%q", Value::Text("it's".to_string())), "it''s");
+        assert_eq!(format_one("%Q", Value::Text("it's".to_string())), "'it''s'");
+        assert_eq!(format_one("%Q", Value::Null), "NULL");
+        assert_eq!(format_one("%w", Value::Text("a\"b".to_string())), "a\"\"b");
+        assert_eq!(format_one("%c", Value::Text("josh".to_string())), "j");
+        assert_eq!(format_one("100%%", Value::Null), "100%");
+    }
+
+    #[test]
+    fn printf_args_test() {
+        assert_eq!(
+            printf(&[
+                Value::Text("%s is %d years old".to_string()),
+                Value::Text("josh".to_string()),
+                Value::Integer(30)
+            ]),
+            Value::Text("josh is 30 years old".to_string())
+        );
+        assert_eq!(
+            printf(&[
+                Value::Text("%*d|%-*d|".to_string()),
+                Value::Integer(4),
+                Value::Integer(1),
+                Value::Integer(3),
+                Value::Integer(2)
+            ]),
+            Value::Text("   1|2  |".to_string())
+        );
+        assert_eq!(printf(&[Value::Null]), Value::Null);
+        assert_eq!(
+            FunctionRegistry::new().call("format", &[Value::Text("%d-%s".to_string())]),
+            Ok(Value::Text("0-".to_string()))
+        );
+        assert!(FunctionRegistry::new()
+            .call("printf", &[Value::Text("%k".to_string())])
+            .is_err());
+    }
+}
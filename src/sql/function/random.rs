@@ -0,0 +1,90 @@
+//! Built-in functions returning random values, for generating test data and synthetic keys.
+//! They aren't deterministic and can't be used where results are stored.
+use std::cell::Cell;
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+
+use crate::error::{Result, SQLRiteError};
+use crate::sql::db::limits::Limit;
+use crate::sql::function::FunctionRegistry;
+use crate::sql::value::Value;
+
+pub fn register(functions: &mut FunctionRegistry) {
+    functions.register("random", 0, false, |_| {
+        Ok(Value::Integer(next_u64() as i64))
+    });
+    functions.register("randomblob", 1, false, randomblob);
+}
+
+thread_local! {
+    // Seeded from the random keys std uses for hash maps, so every thread gets its own sequence
+    static STATE: Cell<u64> = Cell::new(RandomState::new().build_hasher().finish());
+}
+
+/// Next number of the splitmix64 sequence of the current thread.
+/// Good enough for test data, not for anything that needs to be unpredictable.
+pub(crate) fn next_u64() -> u64 {
+    STATE.with(|state| {
+        let next = state.get().wrapping_add(0x9E37_79B9_7F4A_7C15);
+        state.set(next);
+        let mut z = next;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    })
+}
+
+/// `randomblob(N)`: a blob of N random bytes, at least 1 like SQLite. N past the longest
+/// blob a connection can be allowed fails before the bytes are allocated, the connection's
+/// own limit is checked once the blob is returned.
+fn randomblob(args: &[Value]) -> Result<Value> {
+    let len = match args[0].to_integer() {
+        Some(len) => len.max(1) as usize,
+        None => 1,
+    };
+    if len > Limit::Length.maximum() {
+        return Err(SQLRiteError::General("string or blob too big".to_string()));
+    }
+    Ok(Value::Blob(random_bytes(len)))
+}
+
+/// `len` random bytes
+pub(crate) fn random_bytes(len: usize) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(len + 8);
+    while bytes.len() < len {
+        bytes.extend_from_slice(&next_u64().to_le_bytes());
+    }
+    bytes.truncate(len);
+    bytes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn random_test() {
+        let functions = FunctionRegistry::new();
+        assert!(!functions.get("random").unwrap().deterministic);
+        let first = functions.call("random", &[]).unwrap();
+        assert!(matches!(first, Value::Integer(_)));
+        assert_ne!(functions.call("random", &[]).unwrap(), first);
+        assert!(functions.call("random", &[Value::Integer(1)]).is_err());
+    }
+
+    #[test]
+    fn randomblob_test() {
+        let functions = FunctionRegistry::new();
+        let blob_len = |arg: Value| match functions.call("randomblob", &[arg]).unwrap() {
+            Value::Blob(bytes) => bytes.len(),
+            other => panic!("Expected a blob, got {:?}", other),
+        };
+        assert_eq!(blob_len(Value::Integer(16)), 16);
+        assert_eq!(blob_len(Value::Integer(3)), 3);
+        assert_eq!(blob_len(Value::Integer(0)), 1);
+        assert_eq!(blob_len(Value::Null), 1);
+        assert!(functions
+            .call("randomblob", &[Value::Integer(i64::MAX)])
+            .is_err());
+    }
+}
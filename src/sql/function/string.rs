@@ -0,0 +1,248 @@
+//! Built-in string functions, following SQLite's semantics: any NULL argument makes the
+//! result NULL, numbers are converted to text, and positions count characters from 1.
+use crate::error::Result;
+use crate::sql::function::{check_arg_count, FunctionRegistry};
+use crate::sql::params::quote_literal;
+use crate::sql::value::Value;
+
+pub fn register(functions: &mut FunctionRegistry) {
+    functions.register("length", 1, true, length);
+    functions.register("upper", 1, true, |args| {
+        map_text(&args[0], |s| s.to_ascii_uppercase())
+    });
+    functions.register("lower", 1, true, |args| {
+        map_text(&args[0], |s| s.to_ascii_lowercase())
+    });
+    functions.register("substr", -1, true, |args| substr("substr", args));
+    functions.register("substring", -1, true, |args| substr("substring", args));
+    functions.register("trim", -1, true, |args| trim("trim", args, true, true));
+    functions.register("ltrim", -1, true, |args| trim("ltrim", args, true, false));
+    functions.register("rtrim", -1, true, |args| trim("rtrim", args, false, true));
+    functions.register("replace", 3, true, replace);
+    functions.register("instr", 2, true, instr);
+    functions.register("hex", 1, true, hex);
+    functions.register("quote", 1, true, |args| {
+        Ok(Value::Text(quote_literal(&args[0])))
+    });
+}
+
+fn map_text<F>(value: &Value, f: F) -> Result<Value>
+where
+    F: Fn(&str) -> String,
+{
+    Ok(match value.to_text() {
+        Some(text) => Value::Text(f(&text)),
+        None => Value::Null,
+    })
+}
+
+/// `length(X)`: number of characters of X, or of bytes for a blob
+fn length(args: &[Value]) -> Result<Value> {
+    if let Value::Blob(bytes) = &args[0] {
+        return Ok(Value::Integer(bytes.len() as i64));
+    }
+    Ok(match args[0].to_text() {
+        Some(text) => Value::Integer(text.chars().count() as i64),
+        None => Value::Null,
+    })
+}
+
+/// `substr(X, Y[, Z])`: the Z characters of X starting at the Y-th one. A negative Y counts
+/// from the end of X, and a negative Z returns the characters before position Y instead.
+fn substr(name: &str, args: &[Value]) -> Result<Value> {
+    check_arg_count(name, args, 2, 3)?;
+    let (text, start) = match (args[0].to_text(), args[1].to_integer()) {
+        (Some(text), Some(start)) => (text, start),
+        _ => return Ok(Value::Null),
+    };
+    let count = match args.get(2) {
+        Some(count) => match count.to_integer() {
+            Some(count) => Some(count),
+            None => return Ok(Value::Null),
+        },
+        None => None,
+    };
+
+    let chars: Vec<char> = text.chars().collect();
+    let len = chars.len() as i64;
+    // Same arithmetic as SQLite's substrFunc, within bounds that can't overflow
+    let limit = i64::from(i32::MAX);
+    let mut p1 = start.clamp(-limit, limit);
+    // Without Z, everything up to the end of X, as far as the start is within X
+    let mut p2 = count.unwrap_or(limit).clamp(-limit, limit);
+    let negative = p2 < 0;
+    if negative {
+        p2 = -p2;
+    }
+    if p1 < 0 {
+        p1 += len;
+        if p1 < 0 {
+            p2 += p1;
+            if p2 < 0 {
+                p2 = 0;
+            }
+            p1 = 0;
+        }
+    } else if p1 > 0 {
+        p1 -= 1;
+    } else if p2 > 0 && count.is_some() {
+        p2 -= 1;
+    }
+    if negative {
+        p1 -= p2;
+        if p1 < 0 {
+            p2 += p1;
+            p1 = 0;
+        }
+    }
+    if p1 + p2 > len {
+        p2 = (len - p1).max(0);
+    }
+
+    let p1 = p1.min(len) as usize;
+    let p2 = p2 as usize;
+    Ok(Value::Text(chars[p1..p1 + p2].iter().collect()))
+}
+
+/// `trim(X[, Y])`: X without the characters in Y, spaces by default, at its start and/or end
+fn trim(name: &str, args: &[Value], start: bool, end: bool) -> Result<Value> {
+    check_arg_count(name, args, 1, 2)?;
+    let text = match args[0].to_text() {
+        Some(text) => text,
+        None => return Ok(Value::Null),
+    };
+    let set: Vec<char> = match args.get(1) {
+        Some(set) => match set.to_text() {
+            Some(set) => set.chars().collect(),
+            None => return Ok(Value::Null),
+        },
+        None => vec![' '],
+    };
+
+    let mut trimmed = text.as_str();
+    if start {
+        trimmed = trimmed.trim_start_matches(|c| set.contains(&c));
+    }
+    if end {
+        trimmed = trimmed.trim_end_matches(|c| set.contains(&c));
+    }
+    Ok(Value::Text(trimmed.to_string()))
+}
+
+/// `replace(X, Y, Z)`: X with every occurrence of Y replaced by Z
+fn replace(args: &[Value]) -> Result<Value> {
+    match (args[0].to_text(), args[1].to_text(), args[2].to_text()) {
+        (Some(text), Some(from), _) if from.is_empty() => Ok(Value::Text(text)),
+        (Some(text), Some(from), Some(to)) => Ok(Value::Text(text.replace(&from, &to))),
+        _ => Ok(Value::Null),
+    }
+}
+
+/// `instr(X, Y)`: position of the first occurrence of Y in X, 0 if there is none
+fn instr(args: &[Value]) -> Result<Value> {
+    match (args[0].to_text(), args[1].to_text()) {
+        (Some(text), Some(needle)) => Ok(Value::Integer(match text.find(&needle) {
+            Some(index) => text[..index].chars().count() as i64 + 1,
+            None => 0,
+        })),
+        _ => Ok(Value::Null),
+    }
+}
+
+/// `hex(X)`: the bytes of a blob, or of X as UTF-8 text, as upper-case hexadecimal.
+/// An empty string for NULL.
+fn hex(args: &[Value]) -> Result<Value> {
+    let bytes = match &args[0] {
+        Value::Blob(bytes) => bytes.clone(),
+        value => value.to_text().unwrap_or_default().into_bytes(),
+    };
+    Ok(Value::Text(
+        bytes.iter().map(|b| format!("{:02X}", b)).collect(),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text(s: &str) -> Value {
+        Value::Text(s.to_string())
+    }
+
+    fn call(name: &str, args: &[Value]) -> Value {
+        FunctionRegistry::new().call(name, args).unwrap()
+    }
+
+    #[test]
+    fn string_functions_test() {
+        assert_eq!(call("length", &[text("héllo")]), Value::Integer(5));
+        assert_eq!(call("length", &[Value::Real(1.5)]), Value::Integer(3));
+        assert_eq!(call("upper", &[text("Josh")]), text("JOSH"));
+        assert_eq!(call("lower", &[text("Josh")]), text("josh"));
+        assert_eq!(call("trim", &[text("  josh ")]), text("josh"));
+        assert_eq!(call("ltrim", &[text("xxjoshx"), text("x")]), text("joshx"));
+        assert_eq!(call("rtrim", &[text("xxjoshx"), text("x")]), text("xxjosh"));
+        assert_eq!(
+            call("replace", &[text("a-b-c"), text("-"), text("+")]),
+            text("a+b+c")
+        );
+        assert_eq!(
+            call("instr", &[text("héllo"), text("llo")]),
+            Value::Integer(3)
+        );
+        assert_eq!(
+            call("instr", &[text("hello"), text("x")]),
+            Value::Integer(0)
+        );
+        assert_eq!(call("upper", &[Value::Null]), Value::Null);
+        assert_eq!(
+            call("replace", &[text("a"), Value::Null, text("b")]),
+            Value::Null
+        );
+    }
+
+    #[test]
+    fn hex_quote_test() {
+        assert_eq!(
+            call("hex", &[Value::Blob(vec![0, 0xab, 0x10])]),
+            text("00AB10")
+        );
+        assert_eq!(call("hex", &[text("é1")]), text("C3A931"));
+        assert_eq!(call("hex", &[Value::Integer(12)]), text("3132"));
+        assert_eq!(call("hex", &[Value::Null]), text(""));
+        assert_eq!(call("quote", &[text("it's")]), text("'it''s'"));
+        assert_eq!(call("quote", &[Value::Real(2.0)]), text("2.0"));
+        assert_eq!(
+            call("quote", &[Value::Blob(vec![0xca, 0xfe])]),
+            text("X'CAFE'")
+        );
+        assert_eq!(call("quote", &[Value::Null]), text("NULL"));
+    }
+
+    #[test]
+    fn substr_test() {
+        let substr = |args: &[Value]| call("substr", args);
+        assert_eq!(substr(&[text("sqlrite"), Value::Integer(4)]), text("rite"));
+        assert_eq!(
+            substr(&[text("sqlrite"), Value::Integer(1), Value::Integer(3)]),
+            text("sql")
+        );
+        assert_eq!(
+            substr(&[text("sqlrite"), Value::Integer(-4), Value::Integer(2)]),
+            text("ri")
+        );
+        assert_eq!(
+            substr(&[text("sqlrite"), Value::Integer(4), Value::Integer(-2)]),
+            text("ql")
+        );
+        assert_eq!(
+            substr(&[text("sqlrite"), Value::Integer(0), Value::Integer(2)]),
+            text("s")
+        );
+        assert_eq!(substr(&[text("sqlrite"), Value::Integer(10)]), text(""));
+        assert_eq!(substr(&[text("abc"), Value::Integer(-5)]), text("abc"));
+        assert!(FunctionRegistry::new()
+            .call("substr", &[text("sqlrite")])
+            .is_err());
+    }
+}
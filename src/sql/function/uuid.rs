@@ -0,0 +1,147 @@
+//! Functions generating and converting UUIDs, as text like
+//! `a0eebc99-9c0b-4ef8-bb6d-6bb9bd380a11` or as 16-byte blobs.
+#[cfg(not(all(target_arch = "wasm32", feature = "wasm")))]
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::sql::function::random::random_bytes;
+use crate::sql::function::FunctionRegistry;
+use crate::sql::value::Value;
+
+pub fn register(functions: &mut FunctionRegistry) {
+    functions.register("uuid", 0, false, |_| Ok(Value::Text(format_uuid(&uuid4()))));
+    functions.register("uuid4", 0, false, |_| {
+        Ok(Value::Text(format_uuid(&uuid4())))
+    });
+    functions.register("uuid7", 0, false, |_| {
+        Ok(Value::Text(format_uuid(&uuid7())))
+    });
+    functions.register("uuid_str", 1, true, |args| {
+        Ok(parse_uuid(&args[0]).map_or(Value::Null, |uuid| Value::Text(format_uuid(&uuid))))
+    });
+    functions.register("uuid_blob", 1, true, |args| {
+        Ok(parse_uuid(&args[0]).map_or(Value::Null, |uuid| Value::Blob(uuid.to_vec())))
+    });
+}
+
+/// Random UUID, version 4
+fn uuid4() -> [u8; 16] {
+    let mut uuid = [0; 16];
+    uuid.copy_from_slice(&random_bytes(16));
+    set_version(&mut uuid, 4);
+    uuid
+}
+
+/// Version 7 UUID: the Unix time in milliseconds followed by random bits, so UUIDs
+/// generated one after the other sort in the order they were generated in, to the
+/// millisecond.
+fn uuid7() -> [u8; 16] {
+    let millis = unix_millis();
+    let mut uuid = uuid4();
+    uuid[..6].copy_from_slice(&millis.to_be_bytes()[2..]);
+    set_version(&mut uuid, 7);
+    uuid
+}
+
+#[cfg(not(all(target_arch = "wasm32", feature = "wasm")))]
+pub(crate) fn unix_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_millis() as u64)
+}
+
+// The system clock isn't available on wasm32-unknown-unknown, JavaScript's is used instead
+#[cfg(all(target_arch = "wasm32", feature = "wasm"))]
+pub(crate) fn unix_millis() -> u64 {
+    js_sys::Date::now() as u64
+}
+
+/// Sets the version and the RFC 4122 variant bits of `uuid`.
+fn set_version(uuid: &mut [u8; 16], version: u8) {
+    uuid[6] = (uuid[6] & 0x0f) | (version << 4);
+    uuid[8] = (uuid[8] & 0x3f) | 0x80;
+}
+
+/// Formats `uuid` as lower-case text, in groups of 8-4-4-4-12 hexadecimal digits.
+fn format_uuid(uuid: &[u8; 16]) -> String {
+    let mut text = String::with_capacity(36);
+    for (i, byte) in uuid.iter().enumerate() {
+        if i == 4 || i == 6 || i == 8 || i == 10 {
+            text.push('-');
+        }
+        text.push_str(&format!("{:02x}", byte));
+    }
+    text
+}
+
+/// Reads a UUID from a 16-byte blob, or from text holding 32 hexadecimal digits, optionally
+/// separated by dashes and surrounded by braces. `None` if the value isn't a UUID.
+fn parse_uuid(value: &Value) -> Option<[u8; 16]> {
+    let mut uuid = [0; 16];
+    match value {
+        Value::Blob(bytes) if bytes.len() == 16 => uuid.copy_from_slice(bytes),
+        Value::Text(text) => {
+            let text = match text.strip_prefix('{') {
+                Some(braced) => braced.strip_suffix('}')?,
+                None => text,
+            };
+            let digits = text.replace('-', "");
+            if digits.len() != 32 || !digits.bytes().all(|b| b.is_ascii_hexdigit()) {
+                return None;
+            }
+            for (i, byte) in uuid.iter_mut().enumerate() {
+                *byte = u8::from_str_radix(&digits[2 * i..2 * i + 2], 16).ok()?;
+            }
+        }
+        _ => return None,
+    }
+    Some(uuid)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn call(name: &str, args: &[Value]) -> Value {
+        FunctionRegistry::new().call(name, args).unwrap()
+    }
+
+    fn text(s: &str) -> Value {
+        Value::Text(s.to_string())
+    }
+
+    #[test]
+    fn uuid_generate_test() {
+        let first = call("uuid4", &[]).to_string();
+        assert_eq!(first.len(), 36);
+        assert_eq!(&first[14..15], "4");
+        assert!(matches!(&first[19..20], "8" | "9" | "a" | "b"));
+        assert_ne!(call("uuid4", &[]).to_string(), first);
+
+        let v7 = call("uuid7", &[]).to_string();
+        assert_eq!(&v7[14..15], "7");
+        // The first 48 bits hold the timestamp
+        assert!(v7[..13] <= call("uuid7", &[]).to_string()[..13]);
+        assert!(!FunctionRegistry::new().get("uuid7").unwrap().deterministic);
+    }
+
+    #[test]
+    fn uuid_convert_test() {
+        let uuid = "a0eebc99-9c0b-4ef8-bb6d-6bb9bd380a11";
+        let blob = call("uuid_blob", &[text(uuid)]);
+        assert_eq!(
+            blob,
+            Value::Blob(vec![
+                0xa0, 0xee, 0xbc, 0x99, 0x9c, 0x0b, 0x4e, 0xf8, 0xbb, 0x6d, 0x6b, 0xb9, 0xbd, 0x38,
+                0x0a, 0x11
+            ])
+        );
+        assert_eq!(call("uuid_str", &[blob]), text(uuid));
+        assert_eq!(
+            call("uuid_str", &[text("{A0EEBC999C0B4EF8BB6D6BB9BD380A11}")]),
+            text(uuid)
+        );
+        assert_eq!(call("uuid_str", &[text("a0eebc99")]), Value::Null);
+        assert_eq!(call("uuid_blob", &[Value::Blob(vec![1, 2])]), Value::Null);
+        assert_eq!(call("uuid_str", &[Value::Null]), Value::Null);
+    }
+}
@@ -1,17 +1,53 @@
 pub mod parser;
 // pub mod tokenizer;
+pub mod collation;
 pub mod db;
+pub mod executor;
+pub mod function;
+pub mod params;
+pub mod value;
+pub mod vtab;
 
+use executor::modify::{
+    delete_from_table, delete_from_virtual_table, insert_into_table, insert_into_virtual_table,
+    update_table,
+};
+use executor::pragma::{execute_pragma, is_pragma_setting, query_pragma};
+use executor::profile::QueryProfile;
+use executor::trigger::{create_trigger, drop_trigger, has_triggers, in_transaction};
+use executor::{
+    execute_select, execute_select_into, execute_select_profiled, plan_select, ResultSet, RowSink,
+};
+use parser::analyze::parse_analyze;
+use parser::attach::{parse_attach_statement, AttachStatement};
 use parser::create::CreateQuery;
+use parser::explain::{parse_explain, ExplainMode};
 use parser::insert::InsertQuery;
+use parser::limit::parse_modify_limit;
+use parser::match_operator::rewrite_match_operator;
+use parser::pragma::parse_pragma;
+use parser::privilege::{parse_access_statement, AccessStatement};
+use parser::select::SelectQuery;
+use parser::transaction::{parse_transaction_statement, TransactionStatement};
+use parser::trigger::{parse_trigger_statement, TriggerStatement};
+use parser::upsert::{parse_upsert, OnConflict};
+use parser::virtual_table::parse_create_virtual_table;
 
-use sqlparser::ast::Statement;
+use sqlparser::ast::{Statement, TableFactor};
 use sqlparser::dialect::SQLiteDialect;
 use sqlparser::parser::{Parser, ParserError};
+use sqlparser::tokenizer::{Token, Tokenizer};
+
+use std::fmt;
 
 use crate::error::{Result, SQLRiteError};
-use crate::sql::db::database::Database;
+use crate::sql::db::cache::{cache_key, cached_tables};
+use crate::sql::db::database::{Database, TEMP_SCHEMA};
+use crate::sql::db::privilege::Privilege;
+use crate::sql::db::schema::{is_reserved_name, SchemaObject, SchemaObjectType};
 use crate::sql::db::table::Table;
+use crate::sql::params::bind_parameters;
+use crate::sql::value::Value;
 
 #[derive(Debug, PartialEq)]
 pub enum SQLCommand {
@@ -25,8 +61,10 @@ pub enum SQLCommand {
 
 impl SQLCommand {
     pub fn new(command: String) -> SQLCommand {
-        let v = command.split(" ").collect::<Vec<&str>>();
-        match v[0] {
+        // The statement type is told by its first word, comments before it left out
+        let stripped = strip_comments(&command);
+        let first = stripped.split_whitespace().next().unwrap_or_default();
+        match first {
             "insert" => SQLCommand::Insert(command),
             "update" => SQLCommand::Update(command),
             "delete" => SQLCommand::Delete(command),
@@ -37,39 +75,406 @@ impl SQLCommand {
     }
 }
 
-/// Performs initial parsing of SQL Statement using sqlparser-rs
-pub fn process_command(query: &str, db: &mut Database) -> Result<String> {
+/// What a statement returning no rows did, as `process_command` returns it
+#[derive(Debug, PartialEq, Clone)]
+pub struct QueryResult {
+    /// Message of the engine, like `INSERT Statement executed.`
+    pub message: String,
+    /// Number of rows the statement inserted, updated or deleted, `None` for statements that
+    /// don't change rows
+    pub rows_affected: Option<i64>,
+    /// Rowid of the last row inserted through the connection, as `last_insert_rowid()`
+    /// returns it after the statement
+    pub last_insert_rowid: i64,
+}
+
+impl QueryResult {
+    fn new(message: &str) -> Self {
+        QueryResult::from(message.to_string())
+    }
+}
+
+impl From<String> for QueryResult {
+    fn from(message: String) -> Self {
+        QueryResult {
+            message,
+            rows_affected: None,
+            last_insert_rowid: 0,
+        }
+    }
+}
+
+/// Formats the message, followed for statements changing rows by the number of rows they
+/// changed and the last rowid inserted, the way the REPL shows it
+impl fmt::Display for QueryResult {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.rows_affected {
+            Some(rows) => write!(
+                f,
+                "{} {} row{} affected, last insert rowid {}.",
+                self.message,
+                rows,
+                if rows == 1 { "" } else { "s" },
+                self.last_insert_rowid
+            ),
+            None => write!(f, "{}", self.message),
+        }
+    }
+}
+
+/// Tokenizes `sql` with sqlparser-rs' tokenizer, mapping tokenizer errors into `SQLRiteError`.
+pub fn tokenize(sql: &str) -> Result<Vec<Token>> {
     let dialect = SQLiteDialect {};
-    let message: String;
-    let mut ast = Parser::parse_sql(&dialect, &query).map_err(SQLRiteError::from)?;
+    Tokenizer::new(&dialect, sql)
+        .tokenize()
+        .map_err(|err| SQLRiteError::General(format!("{:?}", err)))
+}
+
+/// Removes the `-- line comments` and `/* block comments */` of `sql` outside of strings and
+/// quoted identifiers, each replaced by a space. A line comment leaves the end of its line,
+/// and a block comment left open runs to the end of `sql`.
+pub fn strip_comments(sql: &str) -> String {
+    let mut stripped = String::with_capacity(sql.len());
+    let mut chars = sql.chars().peekable();
+    // The character closing the string or the quoted identifier read
+    let mut quote: Option<char> = None;
+    while let Some(c) = chars.next() {
+        match (quote, c, chars.peek()) {
+            (Some(q), c, _) => {
+                if c == q {
+                    quote = None;
+                }
+                stripped.push(c);
+            }
+            (None, '-', Some('-')) => {
+                while chars.peek().is_some_and(|&c| c != '\n') {
+                    chars.next();
+                }
+                stripped.push(' ');
+            }
+            (None, '/', Some('*')) => {
+                chars.next();
+                let mut previous = None;
+                for c in chars.by_ref() {
+                    if previous == Some('*') && c == '/' {
+                        break;
+                    }
+                    previous = Some(c);
+                }
+                stripped.push(' ');
+            }
+            (None, '\'', _) | (None, '"', _) | (None, '`', _) => {
+                quote = Some(c);
+                stripped.push(c);
+            }
+            (None, '[', _) => {
+                quote = Some(']');
+                stripped.push(c);
+            }
+            (None, c, _) => stripped.push(c),
+        }
+    }
+    stripped
+}
 
-    if ast.len() > 1 {
+/// Parses a single SQL Statement using sqlparser-rs
+pub fn parse_statement(query: &str) -> Result<Statement> {
+    if let Some(statement) = parse_create_virtual_table(query)? {
+        return Ok(statement);
+    }
+
+    let dialect = SQLiteDialect {};
+    let mut ast = match rewrite_match_operator(query)? {
+        Some(query) => Parser::parse_sql(&dialect, &query),
+        None => Parser::parse_sql(&dialect, query),
+    }
+    .map_err(SQLRiteError::from)?;
+
+    if ast.len() != 1 {
         return Err(SQLRiteError::SqlError(ParserError::ParserError(format!(
             "Expected a single query statement, but there are {}",
             ast.len()
         ))));
     }
 
-    let query = ast.pop().unwrap();
+    Ok(ast.pop().unwrap())
+}
+
+/// Returns true if `sql` is a statement returning rows, which has to be run with
+/// `process_query`: a SELECT, an EXPLAIN, or a PRAGMA that isn't changing a setting.
+pub fn returns_rows(sql: &str) -> Result<bool> {
+    if let Some(pragma) = parse_pragma(sql)? {
+        return Ok(pragma.value.is_none() || !is_pragma_setting(&pragma.name));
+    }
+    if parse_explain(sql)?.is_some() {
+        return Ok(true);
+    }
+    if parse_trigger_statement(sql)?.is_some()
+        || parse_attach_statement(sql)?.is_some()
+        || parse_analyze(sql)?.is_some()
+        || parse_access_statement(sql)?.is_some()
+        || parse_transaction_statement(sql)?.is_some()
+    {
+        return Ok(false);
+    }
+    // sqlparser doesn't take `?NNN` placeholders, they are bound to NULL to be parsed
+    let bound = bind_parameters(sql, &[])?;
+    Ok(matches!(parse_statement(&bound)?, Statement::Query(_)))
+}
+
+/// Parses and executes a SELECT, EXPLAIN or PRAGMA statement, returning its result set
+pub fn process_query(query: &str, db: &Database) -> Result<ResultSet> {
+    process_query_with_params(query, &[], db)
+}
+
+/// Same as `process_query`, for a statement whose positional `?` parameters are bound to
+/// `params`. While the result cache is on, the result of a SELECT is served from it if the
+/// same statement ran with the same parameters before and its tables haven't changed since.
+pub fn process_query_with_params(
+    query: &str,
+    params: &[Value],
+    db: &Database,
+) -> Result<ResultSet> {
+    let bound;
+    let sql = if params.is_empty() {
+        query
+    } else {
+        bound = bind_parameters(query, params)?;
+        bound.as_str()
+    };
+    // Profiled statements, and those whose privileges are checked, are always executed
+    if db.result_cache.capacity() == 0 || db.settings.profile || db.current_user.is_some() {
+        return execute_query(sql, db);
+    }
+    let tokens = tokenize(query)?;
+    let tables = match cached_tables(&tokens, db) {
+        Some(tables) => tables,
+        None => return execute_query(sql, db),
+    };
+    let key = cache_key(&tokens, params);
+    if let Some(result) = db.result_cache.get(&key, db) {
+        return Ok(result);
+    }
+    let result = execute_query(sql, db)?;
+    db.result_cache.insert(key, tables, result.clone(), db);
+    Ok(result)
+}
+
+/// Same as `process_query`, handing the rows of the result to `sink` as the executor
+/// produces them instead of returning them, for results too large to be held in memory.
+/// The result cache isn't used.
+pub fn process_query_into(query: &str, db: &Database, sink: &mut dyn RowSink) -> Result<()> {
+    db.limits.check_sql(query, &tokenize(query)?)?;
+    // Pragmas, plans and profiled statements are executed whole, their results are small
+    if db.settings.profile || parse_pragma(query)?.is_some() || parse_explain(query)?.is_some() {
+        let result = execute_query(query, db)?;
+        sink.columns(&result.columns)?;
+        for values in result.rows {
+            sink.row(values)?;
+        }
+        return Ok(());
+    }
+    let statement = parse_statement(query)?;
+    db.limits.check_statement(&statement)?;
+    if !matches!(statement, Statement::Query(_)) {
+        return Err(SQLRiteError::General(
+            "Expected a SELECT statement.".to_string(),
+        ));
+    }
+    execute_select_into(&SelectQuery::new(&statement)?, db, sink)
+}
+
+fn execute_query(query: &str, db: &Database) -> Result<ResultSet> {
+    db.limits.check_sql(query, &tokenize(query)?)?;
+    if let Some(pragma) = parse_pragma(query)? {
+        return query_pragma(db, &pragma);
+    }
+    if let Some(explain) = parse_explain(query)? {
+        let statement = parse_statement(&explain.statement)?;
+        db.limits.check_statement(&statement)?;
+        if !matches!(statement, Statement::Query(_)) {
+            return Err(SQLRiteError::NotImplemented(
+                "Only SELECT statements can be explained.".to_string(),
+            ));
+        }
+        let select_query = SelectQuery::new(&statement)?;
+        return match explain.mode {
+            ExplainMode::QueryPlan => Ok(plan_select(&select_query, db)?.plan()),
+            ExplainMode::Analyze => Ok(execute_select_profiled(&select_query, db)?.1.analysis()),
+        };
+    }
+    let statement = parse_statement(query)?;
+    db.limits.check_statement(&statement)?;
+    match statement {
+        Statement::Query(_) => {
+            let select_query = SelectQuery::new(&statement)?;
+            if db.settings.profile {
+                let (result, profile) = execute_select_profiled(&select_query, db)?;
+                db.profiler.record(query, profile);
+                Ok(result)
+            } else {
+                execute_select(&select_query, db)
+            }
+        }
+        _ => Err(SQLRiteError::General(
+            "Expected a SELECT statement.".to_string(),
+        )),
+    }
+}
+
+/// Performs initial parsing of SQL Statement using sqlparser-rs, returning what the
+/// statement did
+pub fn process_command(query: &str, db: &mut Database) -> Result<QueryResult> {
+    // Statements other than queries are profiled as a single operator, the statements of
+    // triggers with the statement firing them
+    let profiled = db.settings.profile && db.running_triggers.is_empty();
+    let mut profile = QueryProfile {
+        timed: profiled,
+        ..QueryProfile::default()
+    };
+    let started = profile.now();
+    let changes = db.counters.total_changes;
+    // With autocommit off, a statement outside of a transaction begins one, which the
+    // statements that follow run in until it is committed or rolled back
+    if !db.settings.autocommit
+        && db.running_triggers.is_empty()
+        && !db.transaction.is_open()
+        && parse_transaction_statement(query)?.is_none()
+    {
+        db.begin()?;
+    }
+
+    let result = execute_command(query, db).map(|result| QueryResult {
+        last_insert_rowid: db.counters.last_insert_rowid,
+        ..result
+    });
+    // The changes made by the statements of triggers are recorded with the statement
+    // firing them, once all of them succeeded
+    if db.running_triggers.is_empty() {
+        db.end_statement(result.is_ok())?;
+        db.autosave();
+    }
+
+    // Queries record the profile of their operators themselves
+    if profiled && db.settings.profile && result.is_ok() && !returns_rows(query)? {
+        let stripped = strip_comments(query);
+        let kind = stripped.split_whitespace().next().unwrap_or_default();
+        let id = profile.add(kind.to_uppercase(), None);
+        profile.begin(id);
+        profile.record(id, started, (db.counters.total_changes - changes) as usize);
+        db.profiler.record(query, profile);
+    }
+    result
+}
+
+fn execute_command(query: &str, db: &mut Database) -> Result<QueryResult> {
+    db.limits.check_sql(query, &tokenize(query)?)?;
+    let message: String;
+    let mut rows_affected: Option<i64> = None;
+    match parse_trigger_statement(query)? {
+        Some(TriggerStatement::Create {
+            trigger,
+            if_not_exists,
+        }) => return create_trigger(db, trigger, if_not_exists, query).map(QueryResult::from),
+        Some(TriggerStatement::Drop { name, if_exists }) => {
+            return drop_trigger(db, &name, if_exists).map(QueryResult::from)
+        }
+        None => {}
+    }
+    if let Some(pragma) = parse_pragma(query)? {
+        execute_pragma(db, &pragma)?;
+        return Ok(QueryResult::new("PRAGMA Statement executed."));
+    }
+    if let Some(statement) = parse_access_statement(query)? {
+        return execute_access_statement(db, statement).map(QueryResult::from);
+    }
+    match parse_attach_statement(query)? {
+        Some(AttachStatement::Attach {
+            filename,
+            schema_name,
+        }) => {
+            db.check_privilege(Privilege::Ddl, None)?;
+            db.attach(&filename, &schema_name)?;
+            return Ok(QueryResult::new("ATTACH Statement executed."));
+        }
+        Some(AttachStatement::Detach { schema_name }) => {
+            db.check_privilege(Privilege::Ddl, None)?;
+            db.detach(&schema_name)?;
+            return Ok(QueryResult::new("DETACH Statement executed."));
+        }
+        None => {}
+    }
+    if let Some(statement) = parse_transaction_statement(query)? {
+        let message = match statement {
+            TransactionStatement::Begin => db.begin().map(|_| "BEGIN"),
+            TransactionStatement::Commit => db.commit().map(|_| "COMMIT"),
+            TransactionStatement::Rollback => db.rollback().map(|_| "ROLLBACK"),
+            TransactionStatement::Savepoint { name } => db.savepoint(&name).map(|_| "SAVEPOINT"),
+            TransactionStatement::Release { name } => db.release(&name).map(|_| "RELEASE"),
+            TransactionStatement::RollbackTo { name } => db.rollback_to(&name).map(|_| "ROLLBACK"),
+        }?;
+        return Ok(QueryResult::from(format!(
+            "{} Statement executed.",
+            message
+        )));
+    }
+    if let Some(statement) = parse_analyze(query)? {
+        db.analyze(statement.target.as_deref())?;
+        return Ok(QueryResult::new("ANALYZE Statement executed."));
+    }
+    if parse_explain(query)?.is_some() {
+        return Ok(QueryResult::new("EXPLAIN Statement executed."));
+    }
+    let sql = query;
+    // UPDATE and DELETE can end with ORDER BY and LIMIT clauses, which are split off
+    let (query, limit) = match parse_modify_limit(sql)? {
+        Some((statement, limit)) => (statement, Some(limit)),
+        None => (sql.to_string(), None),
+    };
+    // As can INSERT with the ON CONFLICT clause of an upsert
+    let (query, upsert) = match parse_upsert(&query)? {
+        Some((statement, on_conflict)) => (statement, Some(on_conflict)),
+        None => (query, None),
+    };
+    let query = parse_statement(&query)?;
+    db.limits.check_statement(&query)?;
 
     // Initialy only implementing some basic SQL Statements
     match query {
-        Statement::CreateTable { .. } => {
+        Statement::CreateTable { temporary, .. } => {
+            db.check_privilege(Privilege::Ddl, None)?;
             let create_query = CreateQuery::new(&query);
             match create_query {
-                Ok(payload) => {
+                Ok(mut payload) => {
+                    // Temporary tables are in the temp schema, whatever the name says
+                    if temporary {
+                        payload.table_name = temp_table_name(&payload.table_name)?;
+                    }
+                    // Tables of attached databases are stored as `schema.table`
+                    payload.table_name = db.table_key(&payload.table_name)?;
                     let table_name = payload.table_name.clone();
+                    if is_reserved_name(&table_name) {
+                        return Err(SQLRiteError::General(format!(
+                            "object name reserved for internal use: {}",
+                            table_name
+                        )));
+                    }
                     // Checking if table already exists, after parsing CREATE TABLE query
-                    match db.contains_table(table_name.to_string()) {
+                    match db.contains_table_name(&table_name) {
                         true => {
                             return Err(SQLRiteError::Internal(
                                 "Cannot create, table already exists.".to_string(),
                             ));
                         }
                         false => {
+                            // Every COLLATE clause must name a registered collating sequence
+                            for column in &payload.columns {
+                                if let Some(collation) = &column.collation {
+                                    db.collations.get(collation)?;
+                                }
+                            }
                             let table = Table::new(payload);
-                            let _ = table.print_table_schema();
-                            db.tables.insert(table_name.to_string(), table);
+                            db.add_table(table, sql);
                             // Iterate over everything.
                             // for (table_name, _) in &db.tables {
                             //     println!("{}" , table_name);
@@ -81,70 +486,159 @@ pub fn process_command(query: &str, db: &mut Database) -> Result<String> {
                 Err(err) => return Err(err),
             }
         }
-        Statement::Insert { .. } => {
-            let insert_query = InsertQuery::new(&query);
-            match insert_query {
-                Ok(payload) => {
-                    let table_name = payload.table_name;
-                    let columns = payload.columns;
-                    let values = payload.rows;
-
-                    // println!("table_name = {:?}\n cols = {:?}\n vals = {:?}", table_name, columns, values);
-                    // Checking if Table exists in Database
-                    match db.contains_table(table_name.to_string()) {
-                        true => {
-                            let db_table = db.get_table_mut(table_name.to_string()).unwrap();
-                            // Checking if columns on INSERT query exist on Table
-                            match columns
-                                .iter()
-                                .all(|column| db_table.contains_column(column.to_string()))
-                            {
-                                true => {
-                                    for value in &values {
-                                        // Checking if number of columns in query are the same as number of values
-                                        if columns.len() != value.len() {
-                                            return Err(SQLRiteError::Internal(format!(
-                                                "{} values for {} columns",
-                                                value.len(),
-                                                columns.len()
-                                            )));
-                                        }
-                                        match db_table.validate_unique_constraint(&columns, value) {
-                                            Ok(()) => {
-                                                // No unique constraint violation, moving forward with inserting row
-                                                db_table.insert_row(&columns, &value);
-                                            }
-                                            Err(err) => {
-                                                return Err(SQLRiteError::Internal(format!(
-                                                    "Unique key constaint violation: {}",
-                                                    err
-                                                )))
-                                            }
-                                        }
-                                    }
-                                }
-                                false => {
-                                    return Err(SQLRiteError::Internal(
-                                        "Cannot insert, some of the columns do not exist"
-                                            .to_string(),
-                                    ));
-                                }
-                            }
-                            db_table.print_table_data();
-                        }
-                        false => {
-                            return Err(SQLRiteError::Internal("Table doesn't exist".to_string()))
-                        }
-                    }
+        Statement::CreateVirtualTable {
+            name,
+            if_not_exists,
+            module_name,
+            module_args,
+        } => {
+            db.check_privilege(Privilege::Ddl, None)?;
+            let table_name = db.table_key(&name.to_string())?;
+            if is_reserved_name(&table_name) {
+                return Err(SQLRiteError::General(format!(
+                    "object name reserved for internal use: {}",
+                    table_name
+                )));
+            }
+            if db.contains_table_name(&table_name) {
+                if !if_not_exists {
+                    return Err(SQLRiteError::Internal(
+                        "Cannot create, table already exists.".to_string(),
+                    ));
                 }
-                Err(err) => return Err(err),
+            } else {
+                let args = module_args
+                    .iter()
+                    .map(|arg| arg.value.to_string())
+                    .collect::<Vec<String>>();
+                db.vtabs
+                    .create_table(&table_name, &module_name.value, &args)?;
+                db.schema.push(SchemaObject::new(
+                    SchemaObjectType::Table,
+                    &table_name,
+                    &table_name,
+                    Some(sql),
+                ));
+                db.schema_version += 1;
+            }
+            message = String::from("CREATE VIRTUAL TABLE Statement executed.");
+        }
+        Statement::Insert {
+            ref table_name,
+            ref columns,
+            ref source,
+            ..
+        } if db
+            .vtabs
+            .contains_table(&db.resolve_table_name(&table_name.to_string())?) =>
+        {
+            let table_name = db.resolve_table_name(&table_name.to_string())?;
+            if upsert.is_some() {
+                return Err(SQLRiteError::NotImplemented(
+                    "ON CONFLICT on a virtual table is not supported yet.".to_string(),
+                ));
+            }
+            insert_into_virtual_table(db, &table_name, columns, source)?;
+            rows_affected = Some(db.counters.changes);
+            message = String::from("INSERT Statement executed.")
+        }
+        Statement::Insert { ref or, .. } => {
+            let payload = InsertQuery::new(&query, db)?;
+            let table_name = db.resolve_table_name(&payload.table_name)?;
+            let columns = payload.columns;
+            let values = payload.rows;
+
+            // Checking if Table exists in Database
+            if !db.contains_table(table_name.to_string()) {
+                return Err(SQLRiteError::Internal("Table doesn't exist".to_string()));
             }
+            // The ON CONFLICT clause of an upsert wins over the OR of `INSERT OR ...`
+            let on_conflict = upsert.or_else(|| OnConflict::from_or(or.clone()));
+            // The rows inserted by the triggers a statement fires are only kept if all the
+            // rows are inserted, as are the changes recorded of a statement and the rows
+            // replaced or updated by a conflict resolution
+            let on_conflict = on_conflict.as_ref();
+            let inserted =
+                if has_triggers(db, &table_name) || db.records_changes() || on_conflict.is_some() {
+                    in_transaction(db, |db| {
+                        insert_into_table(db, &table_name, &columns, &values, on_conflict)
+                    })?
+                } else {
+                    insert_into_table(db, &table_name, &columns, &values, None)?
+                };
+            db.counters.record(inserted);
+            rows_affected = Some(inserted);
 
             message = String::from("INSERT Statement executed.")
         }
-        Statement::Query(_query) => message = String::from("SELECT Statement executed."),
+        // The rows of a query are returned as the table the REPL shows
+        Statement::Query(_) => message = process_query(sql, db)?.to_string().trim_end().to_string(),
         // Statement::Insert { .. } => message = String::from("INSERT Statement executed."),
-        Statement::Delete { .. } => message = String::from("DELETE Statement executed."),
+        Statement::Update {
+            table,
+            assignments,
+            from,
+            selection,
+        } => {
+            let table_name = match (&table.relation, table.joins.is_empty(), from) {
+                (TableFactor::Table { name, alias, .. }, true, None) => {
+                    if alias.is_some() {
+                        return Err(SQLRiteError::NotImplemented(
+                            "Table aliases in UPDATE are not supported yet.".to_string(),
+                        ));
+                    }
+                    db.resolve_table_name(&name.to_string())?
+                }
+                _ => {
+                    return Err(SQLRiteError::NotImplemented(
+                        "UPDATE of several tables is not supported yet.".to_string(),
+                    ))
+                }
+            };
+            if db.vtabs.contains_table(&table_name) {
+                return Err(SQLRiteError::NotImplemented(
+                    "UPDATE on a virtual table is not supported yet.".to_string(),
+                ));
+            } else if db.contains_table(table_name.to_string()) {
+                // Like for INSERT, the changes of the triggers are only kept with the rows
+                let selection = selection.as_ref();
+                let updated = if has_triggers(db, &table_name) || db.records_changes() {
+                    in_transaction(db, |db| {
+                        update_table(db, &table_name, &assignments, selection, limit.as_ref())
+                    })?
+                } else {
+                    update_table(db, &table_name, &assignments, selection, limit.as_ref())?
+                };
+                rows_affected = Some(updated);
+            } else {
+                return Err(SQLRiteError::Internal("Table doesn't exist".to_string()));
+            }
+            message = String::from("UPDATE Statement executed.")
+        }
+        Statement::Delete {
+            table_name,
+            selection,
+        } => {
+            let table_name = db.resolve_table_name(&table_name.to_string())?;
+            if db.vtabs.contains_table(&table_name) {
+                delete_from_virtual_table(db, &table_name, selection.as_ref(), limit.as_ref())?;
+                rows_affected = Some(db.counters.changes);
+            } else if db.contains_table(table_name.to_string()) {
+                // Like for INSERT, the changes of the triggers are only kept with the rows
+                let selection = selection.as_ref();
+                let deleted = if has_triggers(db, &table_name) || db.records_changes() {
+                    in_transaction(db, |db| {
+                        delete_from_table(db, &table_name, selection, limit.as_ref())
+                    })?
+                } else {
+                    delete_from_table(db, &table_name, selection, limit.as_ref())?
+                };
+                rows_affected = Some(deleted);
+            } else {
+                return Err(SQLRiteError::Internal("Table doesn't exist".to_string()));
+            }
+            message = String::from("DELETE Statement executed.")
+        }
         _ => {
             return Err(SQLRiteError::NotImplemented(
                 "SQL Statement not supported yet.".to_string(),
@@ -152,7 +646,75 @@ pub fn process_command(query: &str, db: &mut Database) -> Result<String> {
         }
     };
 
-    Ok(message)
+    Ok(QueryResult {
+        message,
+        rows_affected,
+        last_insert_rowid: 0,
+    })
+}
+
+/// Runs a statement managing users and their privileges, which only the owner of the
+/// database can do.
+fn execute_access_statement(db: &mut Database, statement: AccessStatement) -> Result<String> {
+    db.check_owner()?;
+    match statement {
+        AccessStatement::CreateUser { name, password } => {
+            db.access.create_user(&name, &password)?;
+            Ok(String::from("CREATE USER Statement executed."))
+        }
+        AccessStatement::DropUser { name, if_exists } => {
+            db.access.drop_user(&name, if_exists)?;
+            Ok(String::from("DROP USER Statement executed."))
+        }
+        AccessStatement::Grant {
+            privileges,
+            table,
+            user,
+        } => {
+            let table = table
+                .map(|table| resolve_granted_table(db, &table))
+                .transpose()?;
+            db.access.grant(&privileges, table.as_deref(), &user)?;
+            Ok(String::from("GRANT Statement executed."))
+        }
+        AccessStatement::Revoke {
+            privileges,
+            table,
+            user,
+        } => {
+            let table = table
+                .map(|table| db.resolve_table_name(&table))
+                .transpose()?;
+            db.access.revoke(&privileges, table.as_deref(), &user)?;
+            Ok(String::from("REVOKE Statement executed."))
+        }
+    }
+}
+
+/// The key of the table a privilege is granted on, which has to exist.
+fn resolve_granted_table(db: &Database, table_name: &str) -> Result<String> {
+    let table_name = db.resolve_table_name(table_name)?;
+    if db.contains_table_name(&table_name) || is_reserved_name(&table_name) {
+        Ok(table_name)
+    } else {
+        Err(SQLRiteError::General(format!(
+            "no such table: {}",
+            table_name
+        )))
+    }
+}
+
+/// The name a temporary table named `table_name` is created under, in the temp schema.
+/// Like in SQLite, the name can only be qualified with `temp`.
+fn temp_table_name(table_name: &str) -> Result<String> {
+    match table_name.split_once('.') {
+        None => Ok(format!("{}.{}", TEMP_SCHEMA, table_name)),
+        Some((schema, _)) if schema.eq_ignore_ascii_case(TEMP_SCHEMA) => Ok(table_name.to_string()),
+        Some(_) => Err(SQLRiteError::General(format!(
+            "temporary table name must be unqualified: {}",
+            table_name
+        ))),
+    }
 }
 
 #[cfg(test)]
@@ -163,14 +725,29 @@ mod tests {
     fn process_command_select_test() {
         let inputed_query = String::from("SELECT * from users;");
         let mut db = Database::new("tempdb".to_string());
+        process_command(
+            "CREATE TABLE users (id INTEGER PRIMARY KEY, name TEXT);",
+            &mut db,
+        )
+        .unwrap();
+        process_command("INSERT INTO users (name) VALUES ('josh');", &mut db).unwrap();
 
         let _ = match process_command(&inputed_query, &mut db) {
-            Ok(response) => assert_eq!(response, "SELECT Statement executed."),
+            Ok(response) => assert_eq!(
+                response.message,
+                "+----+------+\n\
+                 | id | name |\n\
+                 +----+------+\n\
+                 | 1  | josh |\n\
+                 +----+------+"
+            ),
             Err(err) => {
                 eprintln!("Error: {}", err);
                 assert!(false)
             }
         };
+        let result = process_command("SELECT name FROM missing;", &mut db);
+        assert!(result.is_err());
     }
 
     #[test]
@@ -200,7 +777,7 @@ mod tests {
         // Inserting data into table
         let insert_query = String::from("INSERT INTO users (name) Values ('josh');");
         let _ = match process_command(&insert_query, &mut db) {
-            Ok(response) => assert_eq!(response, "INSERT Statement executed."),
+            Ok(response) => assert_eq!(response.message, "INSERT Statement executed."),
             Err(err) => {
                 eprintln!("Error: {}", err);
                 assert!(false)
@@ -208,6 +785,150 @@ mod tests {
         };
     }
 
+    #[test]
+    fn process_command_query_result_test() {
+        let mut db = Database::new("tempdb".to_string());
+        let result = process_command(
+            "CREATE TABLE users (id INTEGER PRIMARY KEY, name TEXT);",
+            &mut db,
+        )
+        .unwrap();
+        assert_eq!(result.rows_affected, None);
+        assert_eq!(result.to_string(), "CREATE TABLE Statement executed.");
+
+        let result = process_command(
+            "INSERT INTO users (name) VALUES ('josh'), ('mary');",
+            &mut db,
+        )
+        .unwrap();
+        assert_eq!(result.rows_affected, Some(2));
+        assert_eq!(result.last_insert_rowid, 2);
+        assert_eq!(
+            result.to_string(),
+            "INSERT Statement executed. 2 rows affected, last insert rowid 2."
+        );
+        // Statements that don't change rows don't report the rows of the last one that did
+        let result = process_command("PRAGMA user_version = 1;", &mut db).unwrap();
+        assert_eq!(result.rows_affected, None);
+        assert_eq!(result.last_insert_rowid, 2);
+
+        process_command("CREATE VIRTUAL TABLE docs USING fts(body);", &mut db).unwrap();
+        let result = process_command(
+            "INSERT INTO docs (body) VALUES ('rust'), ('sqlite'), ('rust and sqlite');",
+            &mut db,
+        )
+        .unwrap();
+        assert_eq!(result.rows_affected, Some(3));
+        let result = process_command("DELETE FROM docs WHERE docs MATCH 'rust';", &mut db).unwrap();
+        assert_eq!(result.rows_affected, Some(2));
+        assert_eq!(result.last_insert_rowid, 3);
+    }
+
+    #[test]
+    fn process_command_bulk_insert_test() {
+        let mut db = Database::new("tempdb".to_string());
+        process_command(
+            "CREATE TABLE users (id INTEGER PRIMARY KEY, email TEXT UNIQUE);",
+            &mut db,
+        )
+        .unwrap();
+        let values = |emails: &[i64]| {
+            emails
+                .iter()
+                .map(|i| format!("('{}@x')", i))
+                .collect::<Vec<String>>()
+                .join(", ")
+        };
+        let emails = (1..=100).collect::<Vec<i64>>();
+        let insert = format!("INSERT INTO users (email) VALUES {};", values(&emails));
+        let result = process_command(&insert, &mut db).unwrap();
+        assert_eq!(result.rows_affected, Some(100));
+
+        // A duplicate within the rows loaded fails the whole INSERT
+        let mut emails = (101..=200).collect::<Vec<i64>>();
+        emails.push(150);
+        let insert = format!("INSERT INTO users (email) VALUES {};", values(&emails));
+        assert!(process_command(&insert, &mut db).is_err());
+        let table = db.get_table("users".to_string()).unwrap();
+        assert_eq!(table.rowids().len(), 100);
+        assert_eq!(table.last_rowid, 100);
+        assert!(table.check_integrity().is_empty());
+        assert!(process_command("INSERT INTO users (email) VALUES ('1@x');", &mut db).is_err());
+        process_command("INSERT INTO users (email) VALUES ('150@x');", &mut db).unwrap();
+    }
+
+    #[test]
+    fn process_command_insert_select_test() {
+        let mut db = Database::new("tempdb".to_string());
+        process_command(
+            "CREATE TABLE users (id INTEGER PRIMARY KEY, name TEXT, email TEXT UNIQUE);",
+            &mut db,
+        )
+        .unwrap();
+        let values = (1..=80)
+            .map(|i| format!("('user{}', '{}@x')", i, i))
+            .collect::<Vec<String>>()
+            .join(", ");
+        let insert = format!("INSERT INTO users (name, email) VALUES {};", values);
+        process_command(&insert, &mut db).unwrap();
+
+        // The rows are read before any is inserted into the table they are read from, and
+        // loaded in bulk like those of a VALUES clause
+        let result = process_command("INSERT INTO users (name) SELECT name FROM users;", &mut db);
+        assert_eq!(result.unwrap().rows_affected, Some(80));
+        let result = process_command(
+            "INSERT INTO users (name) SELECT upper(name) FROM users WHERE id <= 10;",
+            &mut db,
+        );
+        assert_eq!(result.unwrap().rows_affected, Some(10));
+        let table = db.get_table("users".to_string()).unwrap();
+        assert_eq!(table.rowids().len(), 170);
+        assert_eq!(
+            table.get_value("name", 170).unwrap(),
+            Value::Text("USER10".to_string())
+        );
+
+        // The rows of a bulk load are checked against the index like any others
+        let result = process_command(
+            "INSERT INTO users (name, email) SELECT name, email FROM users WHERE id <= 80;",
+            &mut db,
+        );
+        assert!(result.is_err());
+        let table = db.get_table("users".to_string()).unwrap();
+        assert_eq!(table.rowids().len(), 170);
+        assert!(table.check_integrity().is_empty());
+    }
+
+    #[test]
+    fn process_command_insert_below_last_rowid_test() {
+        let mut db = Database::new("tempdb".to_string());
+        process_command(
+            "CREATE TABLE t (id INTEGER PRIMARY KEY, name TEXT);",
+            &mut db,
+        )
+        .unwrap();
+        process_command(
+            "INSERT INTO t (id, name) VALUES (10, 'ten'), (11, 'eleven');",
+            &mut db,
+        )
+        .unwrap();
+
+        // The failing row only removes the row the statement inserted, not the last one
+        let result = process_command(
+            "INSERT INTO t (id, name) VALUES (5, 'five'), (11, 'dup');",
+            &mut db,
+        );
+        assert!(result.is_err());
+        let table = db.get_table("t".to_string()).unwrap();
+        assert_eq!(table.rowids(), vec![10, 11]);
+        assert!(table.check_integrity().is_empty());
+
+        process_command("INSERT INTO t (id, name) VALUES (7, 'seven');", &mut db).unwrap();
+        assert_eq!(db.counters.last_insert_rowid, 7);
+        let table = db.get_table("t".to_string()).unwrap();
+        assert_eq!(table.last_rowid, 11);
+    }
+
     #[test]
     fn process_command_insert_no_pk_test() {
         // Creating temporary database
@@ -234,7 +955,7 @@ mod tests {
         // Inserting data into table
         let insert_query = String::from("INSERT INTO users (name) Values ('josh');");
         let _ = match process_command(&insert_query, &mut db) {
-            Ok(response) => assert_eq!(response, "INSERT Statement executed."),
+            Ok(response) => assert_eq!(response.message, "INSERT Statement executed."),
             Err(err) => {
                 eprintln!("Error: {}", err);
                 assert!(false)
@@ -242,23 +963,299 @@ mod tests {
         };
     }
 
+    #[test]
+    fn process_command_insert_invalid_values_test() {
+        use crate::sql::value::Value;
+
+        let mut db = Database::new("tempdb".to_string());
+        process_command(
+            "CREATE TABLE users (id INTEGER PRIMARY KEY, name TEXT, age INTEGER UNIQUE, score REAL, active BOOL);",
+            &mut db,
+        )
+        .unwrap();
+
+        // Values that can't be stored in their column are errors, not panics
+        for insert_query in [
+            "INSERT INTO users (name, age) VALUES ('josh', 'old');",
+            "INSERT INTO users (name, age) VALUES ('josh', 99999999999999999999);",
+            "INSERT INTO users (id, name) VALUES ('x', 'josh');",
+            "INSERT INTO users (name, score) VALUES ('josh', 'high');",
+            "INSERT INTO users (name, name) VALUES ('josh', 'mary');",
+        ] {
+            assert!(process_command(insert_query, &mut db).is_err());
+        }
+        assert!(db.tables["users"].rowids().is_empty());
+
+        // Columns are given in any order, the missing ones and NULL PRIMARY KEYs are NULL
+        process_command(
+            "INSERT INTO users (score, age, name) VALUES (1.5, 30, 'josh');",
+            &mut db,
+        )
+        .unwrap();
+        process_command(
+            "INSERT INTO users (id, name) VALUES (NULL, 'mary');",
+            &mut db,
+        )
+        .unwrap();
+        process_command("INSERT INTO users (active) VALUES (true);", &mut db).unwrap();
+        let users = &db.tables["users"];
+        assert_eq!(users.rowids(), vec![1, 2, 3]);
+        assert_eq!(users.get_value("age", 1).unwrap(), Value::Integer(30));
+        assert_eq!(users.get_value("age", 2).unwrap(), Value::Null);
+        assert_eq!(users.get_value("active", 3).unwrap(), Value::Bool(true));
+
+        // INTEGER columns and their indexes hold any 64-bit integer
+        process_command(
+            "INSERT INTO users (name, age) VALUES ('ann', 99999999999), ('bob', random());",
+            &mut db,
+        )
+        .unwrap();
+        assert!(process_command(
+            "INSERT INTO users (name, age) VALUES ('eve', 99999999999);",
+            &mut db
+        )
+        .is_err());
+        let result = process_query("SELECT name FROM users WHERE age = 99999999999;", &db).unwrap();
+        assert_eq!(result.rows, vec![vec![Value::Text("ann".to_string())]]);
+        assert!(db.tables["users"].check_integrity().is_empty());
+    }
+
+    #[test]
+    fn process_command_insert_max_rowid_test() {
+        let mut db = Database::new("tempdb".to_string());
+        process_command(
+            "CREATE TABLE t (id INTEGER PRIMARY KEY, name TEXT);",
+            &mut db,
+        )
+        .unwrap();
+        process_command(
+            "INSERT INTO t (id, name) VALUES (9223372036854775807, 'max');",
+            &mut db,
+        )
+        .unwrap();
+
+        // No rowid is left to assign, but rows can still be given one
+        let err = process_command("INSERT INTO t (name) VALUES ('next');", &mut db).unwrap_err();
+        assert!(err.to_string().contains("database or disk is full"));
+        process_command("INSERT INTO t (id, name) VALUES (1, 'one');", &mut db).unwrap();
+        let table = db.get_table("t".to_string()).unwrap();
+        assert_eq!(table.rowids(), vec![1, i64::MAX]);
+        assert!(table.check_integrity().is_empty());
+    }
+
     #[test]
     fn process_command_delete_test() {
-        let inputed_query = String::from("DELETE FROM users WHERE id=1;");
         let mut db = Database::new("tempdb".to_string());
+        process_command(
+            "CREATE TABLE users (id INTEGER PRIMARY KEY, email TEXT UNIQUE, age INTEGER);",
+            &mut db,
+        )
+        .unwrap();
+        process_command(
+            "INSERT INTO users (email, age) VALUES ('a@x', 30), ('b@x', 17), ('c@x', 45), ('d@x', 12);",
+            &mut db,
+        )
+        .unwrap();
 
-        let _ = match process_command(&inputed_query, &mut db) {
-            Ok(response) => assert_eq!(response, "DELETE Statement executed."),
-            Err(err) => {
-                eprintln!("Error: {}", err);
-                assert!(false)
-            }
+        let response = process_command("DELETE FROM users WHERE id=1;", &mut db).unwrap();
+        assert_eq!(response.message, "DELETE Statement executed.");
+        assert_eq!(response.rows_affected, Some(1));
+        let response = process_command("DELETE FROM users WHERE age < 18;", &mut db).unwrap();
+        assert_eq!(response.rows_affected, Some(2));
+        assert_eq!(db.counters.changes, 2);
+        let users = &db.tables["users"];
+        assert_eq!(users.rowids(), vec![3]);
+        // The deleted values left the indexes, they can be inserted again
+        process_command(
+            "INSERT INTO users (email) VALUES ('a@x'), ('b@x');",
+            &mut db,
+        )
+        .unwrap();
+        let emails = process_query("SELECT email FROM users WHERE email = 'b@x';", &db).unwrap();
+        assert_eq!(emails.rows, vec![vec![Value::Text("b@x".to_string())]]);
+
+        let response = process_command("DELETE FROM users WHERE age > 100;", &mut db).unwrap();
+        assert_eq!(response.rows_affected, Some(0));
+        let response =
+            process_command("DELETE FROM users ORDER BY id DESC LIMIT 2;", &mut db).unwrap();
+        assert_eq!(response.rows_affected, Some(2));
+        assert_eq!(db.tables["users"].rowids(), vec![3]);
+        let response = process_command("DELETE FROM users;", &mut db).unwrap();
+        assert_eq!(response.rows_affected, Some(1));
+        assert!(db.tables["users"].rowids().is_empty());
+
+        assert!(process_command("DELETE FROM missing WHERE id=1;", &mut db).is_err());
+    }
+
+    #[test]
+    fn process_command_update_test() {
+        let mut db = Database::new("tempdb".to_string());
+        process_command(
+            "CREATE TABLE users (id INTEGER PRIMARY KEY, email TEXT UNIQUE, age INTEGER NOT NULL);",
+            &mut db,
+        )
+        .unwrap();
+        process_command(
+            "INSERT INTO users (email, age) VALUES ('a@x', 30), ('b@x', 17), ('c@x', 45), ('d@x', 12);",
+            &mut db,
+        )
+        .unwrap();
+        let ages = |db: &Database| {
+            process_query("SELECT age FROM users;", db)
+                .unwrap()
+                .rows
+                .into_iter()
+                .map(|row| row[0].clone())
+                .collect::<Vec<Value>>()
+        };
+
+        let response =
+            process_command("UPDATE users SET age = age + 1 WHERE id = 1;", &mut db).unwrap();
+        assert_eq!(response.message, "UPDATE Statement executed.");
+        assert_eq!(response.rows_affected, Some(1));
+        // Values are stored with the affinity of their column, like on INSERT
+        let response =
+            process_command("UPDATE users SET age = '18' WHERE age < 18;", &mut db).unwrap();
+        assert_eq!(response.rows_affected, Some(2));
+        assert_eq!(db.counters.changes, 2);
+        assert_eq!(ages(&db), [31, 18, 45, 18].map(Value::Integer));
+
+        // The indexes follow the values
+        process_command("UPDATE users SET email = 'e@x' WHERE id = 1;", &mut db).unwrap();
+        let ids = process_query("SELECT id FROM users WHERE email = 'e@x';", &db).unwrap();
+        assert_eq!(ids.rows, vec![vec![Value::Integer(1)]]);
+        process_command(
+            "INSERT INTO users (email, age) VALUES ('a@x', 50);",
+            &mut db,
+        )
+        .unwrap();
+
+        // ORDER BY and LIMIT bound the rows updated
+        let response = process_command(
+            "UPDATE users SET age = 0 WHERE id < 5 ORDER BY age DESC, id LIMIT 2;",
+            &mut db,
+        )
+        .unwrap();
+        assert_eq!(response.rows_affected, Some(2));
+        assert_eq!(ages(&db), [0, 18, 0, 18, 50].map(Value::Integer));
+        let response =
+            process_command("UPDATE users SET age = 1 LIMIT -1 OFFSET 3;", &mut db).unwrap();
+        assert_eq!(response.rows_affected, Some(2));
+        assert!(process_command("UPDATE users SET age = 1 ORDER BY id;", &mut db).is_err());
+
+        // A failing row leaves the rows updated before it as they were
+        assert!(process_command("UPDATE users SET email = 'z@x';", &mut db).is_err());
+        assert!(process_command("UPDATE users SET age = NULL WHERE id > 2;", &mut db).is_err());
+        assert!(process_command("UPDATE users SET age = 'old';", &mut db).is_err());
+        assert_eq!(ages(&db), [0, 18, 0, 1, 1].map(Value::Integer));
+        let emails = process_query("SELECT email FROM users WHERE email = 'z@x';", &db).unwrap();
+        assert!(emails.rows.is_empty());
+
+        assert!(process_command("UPDATE users SET id = 9 WHERE id = 1;", &mut db).is_err());
+        assert!(process_command("UPDATE users SET missing = 1;", &mut db).is_err());
+        assert!(process_command("UPDATE missing SET age = 1;", &mut db).is_err());
+    }
+
+    #[test]
+    fn process_command_upsert_test() {
+        let mut db = Database::new("tempdb".to_string());
+        process_command(
+            "CREATE TABLE counts (id INTEGER PRIMARY KEY, word TEXT UNIQUE, n INTEGER);",
+            &mut db,
+        )
+        .unwrap();
+        let count = |db: &Database, word: &str| {
+            let sql = format!("SELECT id, n FROM counts WHERE word = '{}';", word);
+            process_query(&sql, db).unwrap().rows
         };
+        let upsert = "INSERT INTO counts (word, n) VALUES ('on', 2), ('at', 1) \
+                      ON CONFLICT (word) DO UPDATE SET n = excluded.n WHERE counts.n < excluded.n;";
+        process_command("INSERT INTO counts (word, n) VALUES ('on', 1);", &mut db).unwrap();
+        let response = process_command(upsert, &mut db).unwrap();
+        assert_eq!(response.rows_affected, Some(2));
+        assert_eq!(
+            count(&db, "on"),
+            vec![vec![Value::Integer(1), Value::Integer(2)]]
+        );
+        assert_eq!(
+            count(&db, "at"),
+            vec![vec![Value::Integer(2), Value::Integer(1)]]
+        );
+
+        // Rows not satisfying the WHERE clause of DO UPDATE are left alone
+        let response = process_command(
+            "INSERT INTO counts (word, n) VALUES ('on', 1) \
+             ON CONFLICT (word) DO UPDATE SET n = excluded.n WHERE n > 5;",
+            &mut db,
+        )
+        .unwrap();
+        assert_eq!(response.rows_affected, Some(0));
+        assert_eq!(
+            count(&db, "on"),
+            vec![vec![Value::Integer(1), Value::Integer(2)]]
+        );
+        let response = process_command(
+            "INSERT INTO counts (word, n) VALUES ('on', 9) ON CONFLICT DO NOTHING;",
+            &mut db,
+        )
+        .unwrap();
+        assert_eq!(response.rows_affected, Some(0));
+        let response = process_command(
+            "INSERT OR IGNORE INTO counts (word) VALUES ('at');",
+            &mut db,
+        )
+        .unwrap();
+        assert_eq!(response.rows_affected, Some(0));
+        assert_eq!(
+            count(&db, "at"),
+            vec![vec![Value::Integer(2), Value::Integer(1)]]
+        );
+
+        // INSERT OR REPLACE deletes the rows the new one conflicts with, on any column
+        process_command(
+            "INSERT OR REPLACE INTO counts (id, word, n) VALUES (2, 'on', 7);",
+            &mut db,
+        )
+        .unwrap();
+        assert_eq!(
+            count(&db, "on"),
+            vec![vec![Value::Integer(2), Value::Integer(7)]]
+        );
+        assert!(count(&db, "at").is_empty());
+        assert_eq!(db.tables["counts"].rowids(), vec![2]);
+
+        // Conflicts on other columns than the target still fail, with no row changed
+        assert!(process_command(
+            "INSERT INTO counts (id, word) VALUES (2, 'up') ON CONFLICT (word) DO NOTHING;",
+            &mut db
+        )
+        .is_err());
+        assert!(process_command(
+            "INSERT INTO counts (word) VALUES ('on') ON CONFLICT (n) DO NOTHING;",
+            &mut db
+        )
+        .is_err());
+        assert!(process_command("INSERT INTO counts (word) VALUES ('on');", &mut db).is_err());
+        assert_eq!(
+            count(&db, "on"),
+            vec![vec![Value::Integer(2), Value::Integer(7)]]
+        );
+
+        process_command(
+            "INSERT INTO counts (word, n) VALUES ('on', 3) \
+             ON CONFLICT (word) DO UPDATE SET n = n + excluded.n;",
+            &mut db,
+        )
+        .unwrap();
+        assert_eq!(
+            count(&db, "on"),
+            vec![vec![Value::Integer(2), Value::Integer(10)]]
+        );
     }
 
     #[test]
     fn process_command_not_implemented_test() {
-        let inputed_query = String::from("UPDATE users SET name='josh' where id=1;");
+        let inputed_query = String::from("DROP TABLE users;");
         let mut db = Database::new("tempdb".to_string());
         let expected = Err(SQLRiteError::NotImplemented(
             "SQL Statement not supported yet.".to_string(),
@@ -267,4 +1264,21 @@ mod tests {
         let result = process_command(&inputed_query, &mut db).map_err(|e| e);
         assert_eq!(result, expected);
     }
+
+    #[test]
+    fn strip_comments_test() {
+        assert_eq!(
+            strip_comments("SELECT '--a', \"/*b*/\" -- c\nFROM t /* d; */;"),
+            "SELECT '--a', \"/*b*/\"  \nFROM t  ;"
+        );
+        assert_eq!(strip_comments("SELECT 1; /* open"), "SELECT 1;  ");
+        assert_eq!(
+            SQLCommand::new("/* header */ -- note\ninsert into t values (1);".to_string()),
+            SQLCommand::Insert("/* header */ -- note\ninsert into t values (1);".to_string())
+        );
+
+        let mut db = Database::new("tempdb".to_string());
+        let sql = "-- the users\nCREATE TABLE users (id INTEGER PRIMARY KEY /* rowid */); -- done";
+        assert!(process_command(sql, &mut db).is_ok());
+    }
 }
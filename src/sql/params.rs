@@ -0,0 +1,152 @@
+use sqlparser::tokenizer::Token;
+
+use crate::error::{Result, SQLRiteError};
+use crate::sql::tokenize;
+use crate::sql::value::Value;
+
+/// A placeholder of a statement
+struct Placeholder {
+    /// Position of its first token
+    at: usize,
+    /// Number of tokens it spans, the `?` and the digits of a `?NNN`
+    len: usize,
+    /// Position, starting at 1, of the parameter it binds to
+    index: usize,
+}
+
+/// Returns the placeholders of `tokens`, in the order they appear. Both `?` and `?NNN`
+/// placeholders are supported, a `?` binds to the parameter after the largest one bound
+/// so far, like in SQLite. The tokenizer takes the digits of a `?NNN` as a number of its
+/// own, right after the `?`.
+fn placeholders(tokens: &[Token]) -> Result<Vec<Placeholder>> {
+    let mut placeholders: Vec<Placeholder> = vec![];
+    let mut largest: usize = 0;
+    let mut at = 0;
+    while at < tokens.len() {
+        if let Token::Placeholder(placeholder) = &tokens[at] {
+            let (index, len) = match (placeholder.as_str(), tokens.get(at + 1)) {
+                ("?", Some(Token::Number(number, long))) => match number.parse::<usize>() {
+                    Ok(index) if index > 0 && !long => (index, 2),
+                    _ => {
+                        return Err(SQLRiteError::General(format!(
+                            "Invalid parameter: ?{}",
+                            number
+                        )))
+                    }
+                },
+                ("?", _) => (largest + 1, 1),
+                _ => {
+                    return Err(SQLRiteError::NotImplemented(format!(
+                        "Only positional parameters are supported: {}",
+                        placeholder
+                    )))
+                }
+            };
+            largest = largest.max(index);
+            placeholders.push(Placeholder { at, len, index });
+            at += len;
+        } else {
+            at += 1;
+        }
+    }
+    Ok(placeholders)
+}
+
+/// Returns the number of parameters `sql` expects, the largest parameter index it uses.
+pub fn parameter_count(sql: &str) -> Result<usize> {
+    let placeholders = placeholders(&tokenize(sql)?)?;
+    Ok(placeholders
+        .iter()
+        .map(|placeholder| placeholder.index)
+        .max()
+        .unwrap_or(0))
+}
+
+/// Quotes `value` as a SQL literal.
+pub fn quote_literal(value: &Value) -> String {
+    match value {
+        Value::Null => "NULL".to_string(),
+        Value::Integer(v) => v.to_string(),
+        // Debug keeps the decimal point on integral values, so they are parsed back as reals
+        Value::Real(v) => format!("{:?}", v),
+        Value::Text(v) => format!("'{}'", v.replace('\'', "''")),
+        Value::Bool(v) => v.to_string(),
+        Value::Blob(v) => format!(
+            "X'{}'",
+            v.iter().map(|b| format!("{:02X}", b)).collect::<String>()
+        ),
+    }
+}
+
+/// Replaces every placeholder in `sql` with the literal of the parameter it binds to.
+/// Parameters missing from `params` are bound to NULL.
+pub fn bind_parameters(sql: &str, params: &[Value]) -> Result<String> {
+    let tokens = tokenize(sql)?;
+    let mut placeholders = placeholders(&tokens)?.into_iter().peekable();
+
+    let mut bound = String::with_capacity(sql.len());
+    let mut at = 0;
+    while at < tokens.len() {
+        if let Some(placeholder) = placeholders.next_if(|placeholder| placeholder.at == at) {
+            let value = params.get(placeholder.index - 1).unwrap_or(&Value::Null);
+            bound.push_str(&quote_literal(value));
+            at += placeholder.len;
+            continue;
+        }
+        match &tokens[at] {
+            // The tokenizer unescapes string literals, they have to be quoted back
+            Token::SingleQuotedString(s) => bound.push_str(&quote_literal(&Value::Text(s.clone()))),
+            token => bound.push_str(&token.to_string()),
+        }
+        at += 1;
+    }
+    Ok(bound)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parameter_count_test() {
+        assert_eq!(parameter_count("SELECT * FROM users;").unwrap(), 0);
+        assert_eq!(
+            parameter_count("INSERT INTO users (id, name) VALUES (?, ?);").unwrap(),
+            2
+        );
+        assert_eq!(
+            parameter_count("INSERT INTO users (id, name) VALUES (?2, ?1);").unwrap(),
+            2
+        );
+        assert_eq!(
+            parameter_count("SELECT * FROM users WHERE id = ?12 OR id = ?;").unwrap(),
+            13
+        );
+        assert!(parameter_count("SELECT * FROM users WHERE id = ?0;").is_err());
+        assert!(parameter_count("SELECT * FROM users WHERE id = ?1.5;").is_err());
+    }
+
+    #[test]
+    fn bind_parameters_test() {
+        let sql = bind_parameters(
+            "INSERT INTO users (id, name, score) VALUES (?, ?, ?);",
+            &[
+                Value::Integer(1),
+                Value::Text("o'neil".to_string()),
+                Value::Real(2.0),
+            ],
+        )
+        .unwrap();
+        assert_eq!(
+            sql,
+            "INSERT INTO users (id, name, score) VALUES (1, 'o''neil', 2.0);"
+        );
+
+        let sql = bind_parameters("SELECT ?2, ?1, ?, 'it''s';", &[Value::Integer(1)]).unwrap();
+        assert_eq!(sql, "SELECT NULL, 1, NULL, 'it''s';");
+
+        // A `?` apart from a number is a placeholder of its own
+        let sql = bind_parameters("SELECT ? 5, ?1+2;", &[Value::Integer(1)]).unwrap();
+        assert_eq!(sql, "SELECT 1 5, 1+2;");
+    }
+}
@@ -0,0 +1,73 @@
+use sqlparser::tokenizer::Token;
+
+use crate::error::Result;
+use crate::sql::parser::virtual_table::{
+    expect_identifier, is_keyword, next_token, parser_error, peek_token,
+};
+use crate::sql::tokenize;
+
+/// An ANALYZE statement, which sqlparser-rs doesn't parse
+#[derive(Debug, PartialEq)]
+pub struct AnalyzeStatement {
+    /// The schema or table given, `schema.table` when qualified. `None` analyzes every
+    /// table of every database.
+    pub target: Option<String>,
+}
+
+/// Parses `ANALYZE [schema_name | table_name | schema_name.table_name]`, returning `None`
+/// for any other statement.
+pub fn parse_analyze(sql: &str) -> Result<Option<AnalyzeStatement>> {
+    let tokens = tokenize(sql)?;
+    let mut tokens = tokens.iter().peekable();
+
+    if !is_keyword(peek_token(&mut tokens), "ANALYZE") {
+        return Ok(None);
+    }
+    next_token(&mut tokens);
+
+    let mut target: Option<String> = None;
+    if let Some(Token::Word(_)) = peek_token(&mut tokens) {
+        let mut name = expect_identifier(next_token(&mut tokens))?.value;
+        if let Some(Token::Period) = peek_token(&mut tokens) {
+            next_token(&mut tokens);
+            name = format!(
+                "{}.{}",
+                name,
+                expect_identifier(next_token(&mut tokens))?.value
+            );
+        }
+        target = Some(name);
+    }
+
+    if let Some(Token::SemiColon) = peek_token(&mut tokens) {
+        next_token(&mut tokens);
+    }
+    if let Some(token) = next_token(&mut tokens).filter(|token| **token != Token::EOF) {
+        return Err(parser_error(&format!(
+            "Expected end of statement, found: {}",
+            token
+        )));
+    }
+    Ok(Some(AnalyzeStatement { target }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_analyze_test() {
+        assert_eq!(
+            parse_analyze("ANALYZE;").unwrap(),
+            Some(AnalyzeStatement { target: None })
+        );
+        assert_eq!(
+            parse_analyze("analyze aux.users").unwrap(),
+            Some(AnalyzeStatement {
+                target: Some("aux.users".to_string())
+            })
+        );
+        assert_eq!(parse_analyze("SELECT * FROM users;").unwrap(), None);
+        assert!(parse_analyze("ANALYZE users orders;").is_err());
+    }
+}
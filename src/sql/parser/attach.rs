@@ -0,0 +1,101 @@
+use sqlparser::tokenizer::Token;
+
+use crate::error::Result;
+use crate::sql::parser::virtual_table::{
+    expect_identifier, expect_keyword, is_keyword, next_token, parser_error, peek_token,
+};
+use crate::sql::tokenize;
+
+/// The statements attaching and detaching databases, which sqlparser-rs doesn't parse
+#[derive(Debug, PartialEq)]
+pub enum AttachStatement {
+    /// `ATTACH [DATABASE] 'filename' AS schema_name`
+    Attach {
+        filename: String,
+        schema_name: String,
+    },
+    /// `DETACH [DATABASE] schema_name`
+    Detach { schema_name: String },
+}
+
+/// Parses `ATTACH` and `DETACH`, returning `None` for any other statement.
+pub fn parse_attach_statement(sql: &str) -> Result<Option<AttachStatement>> {
+    let tokens = tokenize(sql)?;
+    let mut tokens = tokens.iter().peekable();
+
+    let statement = if is_keyword(peek_token(&mut tokens), "ATTACH") {
+        next_token(&mut tokens);
+        if is_keyword(peek_token(&mut tokens), "DATABASE") {
+            next_token(&mut tokens);
+        }
+        let filename = match next_token(&mut tokens) {
+            Some(Token::SingleQuotedString(filename)) => filename.to_string(),
+            Some(Token::Word(word)) => word.value.to_string(),
+            token => {
+                return Err(parser_error(&format!(
+                    "Expected a filename, found: {}",
+                    token.map_or("EOF".to_string(), |t| t.to_string())
+                )))
+            }
+        };
+        expect_keyword(next_token(&mut tokens), "AS")?;
+        let schema_name = expect_identifier(next_token(&mut tokens))?.value;
+        AttachStatement::Attach {
+            filename,
+            schema_name,
+        }
+    } else if is_keyword(peek_token(&mut tokens), "DETACH") {
+        next_token(&mut tokens);
+        if is_keyword(peek_token(&mut tokens), "DATABASE") {
+            next_token(&mut tokens);
+        }
+        let schema_name = expect_identifier(next_token(&mut tokens))?.value;
+        AttachStatement::Detach { schema_name }
+    } else {
+        return Ok(None);
+    };
+
+    if let Some(Token::SemiColon) = peek_token(&mut tokens) {
+        next_token(&mut tokens);
+    }
+    if let Some(token) = next_token(&mut tokens).filter(|token| **token != Token::EOF) {
+        return Err(parser_error(&format!(
+            "Expected end of statement, found: {}",
+            token
+        )));
+    }
+    Ok(Some(statement))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_attach_statement_test() {
+        assert_eq!(
+            parse_attach_statement("ATTACH DATABASE ':memory:' AS aux;").unwrap(),
+            Some(AttachStatement::Attach {
+                filename: ":memory:".to_string(),
+                schema_name: "aux".to_string(),
+            })
+        );
+        assert_eq!(
+            parse_attach_statement("attach 'other.db' as other").unwrap(),
+            Some(AttachStatement::Attach {
+                filename: "other.db".to_string(),
+                schema_name: "other".to_string(),
+            })
+        );
+        assert_eq!(
+            parse_attach_statement("DETACH aux;").unwrap(),
+            Some(AttachStatement::Detach {
+                schema_name: "aux".to_string(),
+            })
+        );
+        assert!(parse_attach_statement("ATTACH ':memory:';").is_err());
+        assert!(parse_attach_statement("SELECT * FROM users;")
+            .unwrap()
+            .is_none());
+    }
+}
@@ -16,6 +16,8 @@ pub struct ParsedColumn {
     pub not_null: bool,
     /// Value representing if column was declared with the UNIQUE Constraint
     pub is_unique: bool,
+    /// Name of the collating sequence declared with COLLATE, if any
+    pub collation: Option<String>,
 }
 
 /// The following structure represents a CREATE TABLE query already parsed
@@ -72,6 +74,11 @@ impl CreateQuery {
                         DataType::Float(_precision) => "Real",
                         DataType::Double => "Real",
                         DataType::Decimal(_precision1, _precision2) => "Real",
+                        custom @ DataType::Custom(..)
+                            if custom.to_string().eq_ignore_ascii_case("bool") =>
+                        {
+                            "Bool"
+                        }
                         _ => {
                             eprintln!("not matched on custom type");
                             "Invalid"
@@ -117,6 +124,7 @@ impl CreateQuery {
                         is_pk,
                         not_null,
                         is_unique,
+                        collation: col.collation.as_ref().map(|name| name.to_string()),
                     });
                 }
                 // TODO: Handle constraints,
@@ -176,4 +184,20 @@ mod tests {
             _ => (),
         };
     }
+
+    #[test]
+    fn create_table_collation_test() {
+        let sql_input =
+            String::from("CREATE TABLE users (id INTEGER PRIMARY KEY, name TEXT COLLATE nocase);");
+        let dialect = SQLiteDialect {};
+        let mut ast = Parser::parse_sql(&dialect, &sql_input).unwrap();
+        let query = ast.pop().unwrap();
+
+        let create_query = CreateQuery::new(&query).unwrap();
+        assert_eq!(create_query.columns[0].collation, None);
+        assert_eq!(
+            create_query.columns[1].collation,
+            Some("nocase".to_string())
+        );
+    }
 }
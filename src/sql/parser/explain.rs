@@ -0,0 +1,82 @@
+use crate::error::Result;
+use crate::sql::parser::virtual_table::{
+    expect_keyword, is_keyword, next_token, parser_error, peek_token, token_text,
+};
+use crate::sql::tokenize;
+
+/// What an EXPLAIN statement shows of the statement it explains
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum ExplainMode {
+    /// `EXPLAIN` and `EXPLAIN QUERY PLAN`: the plan, with estimated rows
+    QueryPlan,
+    /// `EXPLAIN ANALYZE`: the plan, with what each operator did once the statement ran
+    Analyze,
+}
+
+/// An EXPLAIN statement, which sqlparser-rs doesn't parse the way SQLite writes it
+#[derive(Debug, PartialEq)]
+pub struct ExplainStatement {
+    pub mode: ExplainMode,
+    /// The SQL text of the statement explained
+    pub statement: String,
+}
+
+/// Parses `EXPLAIN [QUERY PLAN | ANALYZE] statement`, returning `None` for any other
+/// statement.
+pub fn parse_explain(sql: &str) -> Result<Option<ExplainStatement>> {
+    let tokens = tokenize(sql)?;
+    let mut tokens = tokens.iter().peekable();
+
+    if !is_keyword(peek_token(&mut tokens), "EXPLAIN") {
+        return Ok(None);
+    }
+    next_token(&mut tokens);
+
+    let mut mode = ExplainMode::QueryPlan;
+    if is_keyword(peek_token(&mut tokens), "QUERY") {
+        next_token(&mut tokens);
+        expect_keyword(next_token(&mut tokens), "PLAN")?;
+    } else if is_keyword(peek_token(&mut tokens), "ANALYZE") {
+        next_token(&mut tokens);
+        mode = ExplainMode::Analyze;
+    }
+
+    peek_token(&mut tokens);
+    let statement = tokens.map(token_text).collect::<String>();
+    if statement.trim().trim_end_matches(';').trim().is_empty() {
+        return Err(parser_error("Expected a statement to explain"));
+    }
+    Ok(Some(ExplainStatement { mode, statement }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_explain_test() {
+        assert_eq!(
+            parse_explain("explain query plan SELECT * FROM users WHERE name = 'o''hara';")
+                .unwrap(),
+            Some(ExplainStatement {
+                mode: ExplainMode::QueryPlan,
+                statement: "SELECT * FROM users WHERE name = 'o''hara';".to_string(),
+            })
+        );
+        assert_eq!(
+            parse_explain("EXPLAIN ANALYZE SELECT id FROM users")
+                .unwrap()
+                .map(|explain| explain.mode),
+            Some(ExplainMode::Analyze)
+        );
+        assert_eq!(
+            parse_explain("EXPLAIN SELECT 1")
+                .unwrap()
+                .map(|explain| explain.mode),
+            Some(ExplainMode::QueryPlan)
+        );
+        assert_eq!(parse_explain("SELECT * FROM users;").unwrap(), None);
+        assert!(parse_explain("EXPLAIN QUERY SELECT 1;").is_err());
+        assert!(parse_explain("EXPLAIN ANALYZE;").is_err());
+    }
+}
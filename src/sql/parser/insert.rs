@@ -1,23 +1,31 @@
-use sqlparser::ast::{Expr, Query, SetExpr, Statement, Value, Values};
+use sqlparser::ast::{Expr, Query, SetExpr, Statement, Value as AstValue, Values};
 
 use crate::error::{Result, SQLRiteError};
+use crate::sql::db::database::Database;
+use crate::sql::executor::execute_select;
+use crate::sql::executor::expr::{eval_expr, RowContext};
+use crate::sql::parser::select::SelectQuery;
+use crate::sql::value::Value;
 
 /// The following structure represents a INSERT query already parsed
 /// and broken down into `table_name` a `Vec<String>` representing the `Columns`
-/// and `Vec<Vec<String>>` representing the list of `Rows` to be inserted
+/// and `Vec<Vec<Value>>` representing the list of `Rows` to be inserted
 #[derive(Debug)]
 pub struct InsertQuery {
     pub table_name: String,
     pub columns: Vec<String>,
-    pub rows: Vec<Vec<String>>,
+    pub rows: Vec<Vec<Value>>,
 }
 
 impl InsertQuery {
-    pub fn new(statement: &Statement) -> Result<InsertQuery> {
+    /// Breaks down an INSERT statement, evaluating the expressions of its VALUES clause
+    /// against `db`, or running its SELECT.
+    pub fn new(statement: &Statement, db: &Database) -> Result<InsertQuery> {
         #[allow(unused_assignments)]
         let mut tname: Option<String> = None;
         let mut columns: Vec<String> = vec![];
-        let mut all_values: Vec<Vec<String>> = vec![];
+        let mut all_values: Vec<Vec<Value>> = vec![];
+        let row = RowContext::new(&[], &[]);
 
         match statement {
             Statement::Insert {
@@ -44,34 +52,40 @@ impl InsertQuery {
                             #[allow(irrefutable_let_patterns)]
                             if let Values(expressions) = values {
                                 for i in expressions {
-                                    let mut value_set: Vec<String> = vec![];
+                                    let mut value_set: Vec<Value> = vec![];
                                     for e in i {
                                         match e {
                                             Expr::Value(v) => match v {
-                                                Value::Number(n, _) => {
-                                                    value_set.push(n.to_string());
+                                                AstValue::Number(n, _) => {
+                                                    value_set.push(number(n));
                                                 }
-                                                Value::Boolean(b) => match *b {
-                                                    true => value_set.push("true".to_string()),
-                                                    false => value_set.push("false".to_string()),
-                                                },
-                                                Value::SingleQuotedString(sqs) => {
-                                                    value_set.push(sqs.to_string());
+                                                AstValue::Boolean(b) => {
+                                                    value_set.push(Value::Bool(*b));
                                                 }
-                                                Value::Null => {
-                                                    value_set.push("Null".to_string());
+                                                AstValue::SingleQuotedString(sqs) => {
+                                                    value_set.push(Value::Text(sqs.to_string()));
                                                 }
-                                                _ => {}
+                                                AstValue::Null => {
+                                                    value_set.push(Value::Null);
+                                                }
+                                                _ => value_set.push(eval_expr(e, db, &row)?),
                                             },
                                             Expr::Identifier(i) => {
-                                                value_set.push(i.to_string());
+                                                value_set.push(Value::Text(i.to_string()));
                                             }
-                                            _ => {}
+                                            // Any other expression is evaluated, with no row
+                                            // to read columns from
+                                            _ => value_set.push(eval_expr(e, db, &row)?),
                                         }
                                     }
                                     all_values.push(value_set);
                                 }
                             }
+                        } else {
+                            // INSERT ... SELECT inserts the rows of the query, all read
+                            // before any is inserted, even into the table they are read from
+                            let select = SelectQuery::new(&Statement::Query(source.clone()))?;
+                            all_values = execute_select(&select, db)?.rows;
                         }
                     }
                 }
@@ -95,3 +109,40 @@ impl InsertQuery {
         }
     }
 }
+
+/// The value of the numeric literal `n`: an integer if it is one that fits in 64 bits,
+/// a real otherwise.
+fn number(n: &str) -> Value {
+    match (n.parse::<i64>(), n.parse::<f64>()) {
+        (Ok(n), _) => Value::Integer(n),
+        (_, Ok(n)) => Value::Real(n),
+        _ => Value::Text(n.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sql::parse_statement;
+
+    #[test]
+    fn insert_query_test() {
+        let db = Database::new("tempdb".to_string());
+        let statement = parse_statement(
+            "INSERT INTO t (a, b, c, d, e) VALUES (-5, 1 + 1, upper('c'), uuid4(), 'x' || 2);",
+        )
+        .unwrap();
+        let query = InsertQuery::new(&statement, &db).unwrap();
+        assert_eq!(query.columns, vec!["a", "b", "c", "d", "e"]);
+        let row = &query.rows[0];
+        assert_eq!(row.len(), 5);
+        assert_eq!(row[0], Value::Integer(-5));
+        assert_eq!(row[1], Value::Integer(2));
+        assert_eq!(row[2], Value::Text("C".to_string()));
+        assert!(matches!(&row[3], Value::Text(uuid) if uuid.len() == 36));
+        assert_eq!(row[4], Value::Text("x2".to_string()));
+
+        let statement = parse_statement("INSERT INTO t (a) VALUES (no_such_function());").unwrap();
+        assert!(InsertQuery::new(&statement, &db).is_err());
+    }
+}
@@ -0,0 +1,191 @@
+use sqlparser::ast::{Expr, Statement};
+use sqlparser::tokenizer::Token;
+
+use crate::error::Result;
+use crate::sql::parser::select::{order_by_terms, OrderBy};
+use crate::sql::parser::trigger::parse_expression;
+use crate::sql::parser::virtual_table::{is_keyword, parser_error, peek_token, token_text};
+use crate::sql::{parse_statement, tokenize};
+
+/// The ORDER BY and LIMIT clauses ending an UPDATE or a DELETE, like SQLite's
+/// `DELETE FROM events ORDER BY ts LIMIT 10000`, which sqlparser-rs doesn't parse. They
+/// bound the rows the statement changes: the rows it would change are sorted, OFFSET of
+/// them are left alone, and at most LIMIT of the others are changed.
+#[derive(Debug, PartialEq)]
+pub struct ModifyLimit {
+    /// Terms the rows are sorted by, in the order they are changed in
+    pub order_by: Vec<OrderBy>,
+    /// Expression of the maximum number of rows changed, none when it is negative
+    pub limit: Expr,
+    /// Expression of the number of rows left alone before the first one changed
+    pub offset: Option<Expr>,
+}
+
+/// Splits `UPDATE | DELETE ... [ORDER BY ...] LIMIT n [OFFSET m]` into the SQL text of the
+/// statement without its ORDER BY and LIMIT clauses, and the clauses. Returns `None` for
+/// other statements, and for those without a LIMIT clause.
+///
+/// The clauses are parsed here rather than as those of a SELECT, which only takes a
+/// literal number after LIMIT and OFFSET, so that `LIMIT -1` and `LIMIT ?` are taken,
+/// and `LIMIT m, n` skips m rows like in SQLite.
+pub fn parse_modify_limit(sql: &str) -> Result<Option<(String, ModifyLimit)>> {
+    let tokens = tokenize(sql)?;
+    let mut iter = tokens.iter().peekable();
+    let keyword = match peek_token(&mut iter) {
+        token if is_keyword(token, "UPDATE") => "UPDATE",
+        token if is_keyword(token, "DELETE") => "DELETE",
+        _ => return Ok(None),
+    };
+
+    // The clauses start at the first ORDER BY or LIMIT outside of parentheses
+    let mut start = None;
+    let mut limit_at = None;
+    for (i, token) in outside_parentheses(&tokens) {
+        if is_keyword(Some(token), "LIMIT") {
+            start = start.or(Some(i));
+            limit_at = Some(i);
+            break;
+        }
+        if start.is_none() && is_keyword(Some(token), "ORDER") {
+            let next = tokens[i + 1..]
+                .iter()
+                .find(|token| !matches!(token, Token::Whitespace(_)));
+            if is_keyword(next, "BY") {
+                start = Some(i);
+            }
+        }
+    }
+    let (start, limit_at) = match (start, limit_at) {
+        (None, _) => return Ok(None),
+        (Some(_), None) => {
+            return Err(parser_error(&format!(
+                "ORDER BY without LIMIT on {}",
+                keyword
+            )))
+        }
+        (Some(start), Some(limit_at)) => (start, limit_at),
+    };
+    let text = |tokens: &[Token]| tokens.iter().map(token_text).collect::<String>();
+
+    // The ORDER BY clause is parsed as that of a SELECT
+    let order_by = if start < limit_at {
+        match parse_statement(&format!(
+            "SELECT * FROM t {}",
+            text(&tokens[start..limit_at])
+        ))? {
+            Statement::Query(query) if query.limit.is_none() && query.offset.is_none() => {
+                order_by_terms(&query.order_by)
+            }
+            _ => return Err(parser_error("Expected ORDER BY")),
+        }
+    } else {
+        vec![]
+    };
+
+    // LIMIT n OFFSET m, or LIMIT m, n
+    let clause = &tokens[limit_at + 1..];
+    let split = outside_parentheses(clause)
+        .find(|(_, token)| **token == Token::Comma || is_keyword(Some(token), "OFFSET"));
+    let (limit, offset) = match split {
+        Some((i, Token::Comma)) => (
+            parse_expression(&text(&clause[i + 1..]))?,
+            Some(parse_expression(&text(&clause[..i]))?),
+        ),
+        Some((i, _)) => (
+            parse_expression(&text(&clause[..i]))?,
+            Some(parse_expression(&text(&clause[i + 1..]))?),
+        ),
+        None => (parse_expression(&text(clause))?, None),
+    };
+    Ok(Some((
+        text(&tokens[..start]),
+        ModifyLimit {
+            order_by,
+            limit,
+            offset,
+        },
+    )))
+}
+
+/// The tokens of `tokens` outside of parentheses, with their position.
+fn outside_parentheses(tokens: &[Token]) -> impl Iterator<Item = (usize, &Token)> {
+    let mut depth = 0;
+    tokens.iter().enumerate().filter(move |(_, token)| {
+        match token {
+            Token::LParen => depth += 1,
+            Token::RParen => depth -= 1,
+            _ => return depth == 0,
+        }
+        false
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_modify_limit_test() {
+        let (statement, limit) = parse_modify_limit(
+            "DELETE FROM events WHERE kind IN (SELECT kind FROM kinds ORDER BY kind LIMIT 2) ORDER BY ts DESC, id LIMIT 100 OFFSET 10;",
+        )
+        .unwrap()
+        .unwrap();
+        assert_eq!(
+            statement,
+            "DELETE FROM events WHERE kind IN (SELECT kind FROM kinds ORDER BY kind LIMIT 2) "
+        );
+        assert_eq!(limit.order_by.len(), 2);
+        assert_eq!(limit.order_by[0].expr.to_string(), "ts");
+        assert!(!limit.order_by[0].asc);
+        assert_eq!(limit.limit.to_string(), "100");
+        assert_eq!(
+            limit.offset.map(|offset| offset.to_string()),
+            Some("10".to_string())
+        );
+
+        let (statement, limit) = parse_modify_limit("delete from t where a = 'x''y' limit -1")
+            .unwrap()
+            .unwrap();
+        assert_eq!(statement, "delete from t where a = 'x''y' ");
+        assert!(limit.order_by.is_empty());
+        assert_eq!(limit.limit.to_string(), "- 1");
+        assert_eq!(limit.offset, None);
+
+        // LIMIT m, n skips m rows
+        let (_, limit) = parse_modify_limit("DELETE FROM t LIMIT 2 + 1, 5;")
+            .unwrap()
+            .unwrap();
+        assert_eq!(limit.limit.to_string(), "5");
+        assert_eq!(
+            limit.offset.map(|offset| offset.to_string()),
+            Some("2 + 1".to_string())
+        );
+
+        let (statement, limit) = parse_modify_limit(
+            "UPDATE t SET a = (SELECT max(a) FROM t LIMIT 1) ORDER BY a LIMIT 1;",
+        )
+        .unwrap()
+        .unwrap();
+        assert_eq!(
+            statement,
+            "UPDATE t SET a = (SELECT max(a) FROM t LIMIT 1) "
+        );
+        assert_eq!(limit.order_by[0].expr.to_string(), "a");
+        assert_eq!(limit.limit.to_string(), "1");
+        assert_eq!(
+            parse_modify_limit("UPDATE t SET a = 1 WHERE a = 2;").unwrap(),
+            None
+        );
+        assert_eq!(
+            parse_modify_limit("DELETE FROM t WHERE a = 1;").unwrap(),
+            None
+        );
+        assert_eq!(
+            parse_modify_limit("SELECT * FROM t LIMIT 1;").unwrap(),
+            None
+        );
+        assert!(parse_modify_limit("DELETE FROM t ORDER BY a;").is_err());
+        assert!(parse_modify_limit("UPDATE t SET a = 1 ORDER BY a;").is_err());
+    }
+}
@@ -0,0 +1,83 @@
+use sqlparser::tokenizer::Token;
+
+use crate::error::Result;
+use crate::sql::parser::virtual_table::{is_keyword, token_text};
+use crate::sql::tokenize;
+
+// Keywords that can't be the operands of MATCH, like in `SELECT match FROM games`
+const RESERVED: [&str; 20] = [
+    "ALL", "AND", "AS", "BY", "CASE", "DISTINCT", "ELSE", "END", "FROM", "HAVING", "LIMIT",
+    "MATCH", "NOT", "ON", "OR", "SELECT", "SET", "THEN", "WHEN", "WHERE",
+];
+
+/// Rewrites every `column MATCH value` in `sql` into the function call `match(column, value)`,
+/// returning `None` when there is nothing to rewrite.
+///
+/// sqlparser-rs doesn't know the MATCH operator. Like in SQLite, `X MATCH Y` is equivalent
+/// to `match(X, Y)`, which only virtual tables can answer. Only a column name is supported
+/// on the left side, and a literal, a parameter or a column name on the right side.
+pub fn rewrite_match_operator(sql: &str) -> Result<Option<String>> {
+    let tokens = tokenize(sql)?;
+    let significant = (0..tokens.len())
+        .filter(|i| !matches!(tokens[*i], Token::Whitespace(_)))
+        .collect::<Vec<usize>>();
+
+    // Text inserted before and after each token
+    let mut before = vec![""; tokens.len()];
+    let mut after = vec![""; tokens.len()];
+    let mut separators: Vec<usize> = vec![];
+    for window in significant.windows(3) {
+        let (left, operator, right) = (window[0], window[1], window[2]);
+        let is_column = |token: &Token| {
+            matches!(token, Token::Word(_))
+                && !RESERVED
+                    .iter()
+                    .any(|keyword| is_keyword(Some(token), keyword))
+        };
+        let is_operand = |token: &Token| match token {
+            Token::SingleQuotedString(_) | Token::Number(_, _) | Token::Placeholder(_) => true,
+            token => is_column(token),
+        };
+        if is_keyword(Some(&tokens[operator]), "MATCH")
+            && is_column(&tokens[left])
+            && is_operand(&tokens[right])
+        {
+            before[left] = "match(";
+            separators.push(operator);
+            after[right] = ")";
+        }
+    }
+    if separators.is_empty() {
+        return Ok(None);
+    }
+
+    let mut rewritten = String::new();
+    for (i, token) in tokens.iter().enumerate() {
+        rewritten.push_str(before[i]);
+        if separators.contains(&i) {
+            rewritten.push(',');
+        } else {
+            rewritten.push_str(&token_text(token));
+        }
+        rewritten.push_str(after[i]);
+    }
+    Ok(Some(rewritten))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rewrite_match_operator_test() {
+        assert_eq!(
+            rewrite_match_operator("SELECT * FROM docs WHERE docs MATCH 'it''s' AND id = ?1;")
+                .unwrap(),
+            Some("SELECT * FROM docs WHERE match(docs , 'it''s') AND id = ?1;".to_string())
+        );
+        assert_eq!(
+            rewrite_match_operator("SELECT match FROM games;").unwrap(),
+            None
+        );
+    }
+}
@@ -1,2 +1,14 @@
+pub mod analyze;
+pub mod attach;
 pub mod create;
+pub mod explain;
 pub mod insert;
+pub mod limit;
+pub mod match_operator;
+pub mod pragma;
+pub mod privilege;
+pub mod select;
+pub mod transaction;
+pub mod trigger;
+pub mod upsert;
+pub mod virtual_table;
@@ -0,0 +1,140 @@
+use std::iter::Peekable;
+use std::slice::Iter;
+
+use sqlparser::tokenizer::Token;
+
+use crate::error::Result;
+use crate::sql::parser::virtual_table::{
+    expect_identifier, is_keyword, next_token, parser_error, peek_token,
+};
+use crate::sql::tokenize;
+use crate::sql::value::Value;
+
+/// A PRAGMA statement, which sqlparser-rs doesn't parse
+#[derive(Debug, PartialEq, Clone)]
+pub struct PragmaStatement {
+    /// Schema name the pragma is qualified with, if any
+    pub schema_name: Option<String>,
+    pub name: String,
+    /// The value given with `= value` or `(value)`: the new value of a setting, or the
+    /// argument of pragmas like `table_info(table)`. Names and keywords are kept as text.
+    pub value: Option<Value>,
+}
+
+/// Parses `PRAGMA [schema.]name [= value | (value)]`, returning `None` for any other statement.
+pub fn parse_pragma(sql: &str) -> Result<Option<PragmaStatement>> {
+    let tokens = tokenize(sql)?;
+    let mut tokens = tokens.iter().peekable();
+
+    if !is_keyword(peek_token(&mut tokens), "PRAGMA") {
+        return Ok(None);
+    }
+    next_token(&mut tokens);
+
+    let mut schema_name: Option<String> = None;
+    let mut name = expect_identifier(next_token(&mut tokens))?.value;
+    if let Some(Token::Period) = peek_token(&mut tokens) {
+        next_token(&mut tokens);
+        schema_name = Some(name);
+        name = expect_identifier(next_token(&mut tokens))?.value;
+    }
+
+    let value = match peek_token(&mut tokens) {
+        Some(Token::Eq) => {
+            next_token(&mut tokens);
+            Some(parse_value(&mut tokens)?)
+        }
+        Some(Token::LParen) => {
+            next_token(&mut tokens);
+            let value = parse_value(&mut tokens)?;
+            match next_token(&mut tokens) {
+                Some(Token::RParen) => Some(value),
+                token => {
+                    return Err(parser_error(&format!(
+                        "Expected ), found: {}",
+                        token.map_or("EOF".to_string(), |t| t.to_string())
+                    )))
+                }
+            }
+        }
+        _ => None,
+    };
+
+    if let Some(Token::SemiColon) = peek_token(&mut tokens) {
+        next_token(&mut tokens);
+    }
+    if let Some(token) = next_token(&mut tokens).filter(|token| **token != Token::EOF) {
+        return Err(parser_error(&format!(
+            "Expected end of statement, found: {}",
+            token
+        )));
+    }
+    Ok(Some(PragmaStatement {
+        schema_name,
+        name,
+        value,
+    }))
+}
+
+/// Parses the value of a pragma: a name, a string or a number, which may be signed.
+fn parse_value(tokens: &mut Peekable<Iter<'_, Token>>) -> Result<Value> {
+    let (sign, token) = match next_token(tokens) {
+        Some(Token::Minus) => ("-", next_token(tokens)),
+        Some(Token::Plus) => ("", next_token(tokens)),
+        token => ("", token),
+    };
+    let value = match token {
+        Some(Token::Number(number, _)) => {
+            let number = format!("{}{}", sign, number);
+            match number.parse::<i64>() {
+                Ok(v) => Value::Integer(v),
+                Err(_) => Value::Real(
+                    number
+                        .parse::<f64>()
+                        .map_err(|_| parser_error(&format!("Invalid number: {}", number)))?,
+                ),
+            }
+        }
+        Some(Token::SingleQuotedString(s)) if sign.is_empty() => Value::Text(s.to_string()),
+        Some(Token::Word(word)) if sign.is_empty() => Value::Text(word.value.to_string()),
+        token => {
+            return Err(parser_error(&format!(
+                "Expected a pragma value, found: {}",
+                token.map_or("EOF".to_string(), |t| t.to_string())
+            )))
+        }
+    };
+    Ok(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_pragma_test() {
+        assert_eq!(
+            parse_pragma("PRAGMA aux.table_info(users);").unwrap(),
+            Some(PragmaStatement {
+                schema_name: Some("aux".to_string()),
+                name: "table_info".to_string(),
+                value: Some(Value::Text("users".to_string())),
+            })
+        );
+        assert_eq!(
+            parse_pragma("pragma cache_size = -4000").unwrap(),
+            Some(PragmaStatement {
+                schema_name: None,
+                name: "cache_size".to_string(),
+                value: Some(Value::Integer(-4000)),
+            })
+        );
+        assert_eq!(
+            parse_pragma("PRAGMA user_version;").unwrap().unwrap().value,
+            None
+        );
+        assert!(parse_pragma("PRAGMA table_info(users").is_err());
+        assert!(parse_pragma("PRAGMA journal_mode = -wal;").is_err());
+        assert!(parse_pragma("SELECT 1;").unwrap().is_none());
+    }
+}
@@ -0,0 +1,188 @@
+use sqlparser::tokenizer::Token;
+
+use crate::error::Result;
+use crate::sql::db::privilege::Privilege;
+use crate::sql::parser::virtual_table::{
+    expect_identifier, expect_keyword, is_keyword, next_token, parser_error, peek_token,
+};
+use crate::sql::tokenize;
+
+/// The statements managing users and their privileges, which sqlparser-rs doesn't parse
+#[derive(Debug, PartialEq)]
+pub enum AccessStatement {
+    /// `CREATE USER name [WITH] PASSWORD 'password'`
+    CreateUser { name: String, password: String },
+    /// `DROP USER [IF EXISTS] name`
+    DropUser { name: String, if_exists: bool },
+    /// `GRANT privileges ON [TABLE] table_name TO user`, or `ON DATABASE` for every table
+    Grant {
+        privileges: Vec<Privilege>,
+        table: Option<String>,
+        user: String,
+    },
+    /// `REVOKE privileges ON [TABLE] table_name FROM user`, or `ON DATABASE`
+    Revoke {
+        privileges: Vec<Privilege>,
+        table: Option<String>,
+        user: String,
+    },
+}
+
+/// Parses `CREATE USER`, `DROP USER`, `GRANT` and `REVOKE`, returning `None` for any other
+/// statement.
+pub fn parse_access_statement(sql: &str) -> Result<Option<AccessStatement>> {
+    let tokens = tokenize(sql)?;
+    let mut tokens = tokens.iter().peekable();
+
+    let statement = match next_token(&mut tokens) {
+        token if is_keyword(token, "CREATE") && is_keyword(peek_token(&mut tokens), "USER") => {
+            next_token(&mut tokens);
+            let name = expect_identifier(next_token(&mut tokens))?.value;
+            if is_keyword(peek_token(&mut tokens), "WITH") {
+                next_token(&mut tokens);
+            }
+            expect_keyword(next_token(&mut tokens), "PASSWORD")?;
+            let password = match next_token(&mut tokens) {
+                Some(Token::SingleQuotedString(password)) => password.to_string(),
+                token => {
+                    return Err(parser_error(&format!(
+                        "Expected a password, found: {}",
+                        token.map_or("EOF".to_string(), |t| t.to_string())
+                    )))
+                }
+            };
+            AccessStatement::CreateUser { name, password }
+        }
+        token if is_keyword(token, "DROP") && is_keyword(peek_token(&mut tokens), "USER") => {
+            next_token(&mut tokens);
+            let if_exists = is_keyword(peek_token(&mut tokens), "IF");
+            if if_exists {
+                next_token(&mut tokens);
+                expect_keyword(next_token(&mut tokens), "EXISTS")?;
+            }
+            let name = expect_identifier(next_token(&mut tokens))?.value;
+            AccessStatement::DropUser { name, if_exists }
+        }
+        token if is_keyword(token, "GRANT") || is_keyword(token, "REVOKE") => {
+            let grant = is_keyword(token, "GRANT");
+            let privileges = parse_privileges(&mut tokens)?;
+            expect_keyword(next_token(&mut tokens), "ON")?;
+            let table = if is_keyword(peek_token(&mut tokens), "DATABASE") {
+                next_token(&mut tokens);
+                None
+            } else {
+                if is_keyword(peek_token(&mut tokens), "TABLE") {
+                    next_token(&mut tokens);
+                }
+                Some(parse_table_name(&mut tokens)?)
+            };
+            expect_keyword(next_token(&mut tokens), if grant { "TO" } else { "FROM" })?;
+            let user = expect_identifier(next_token(&mut tokens))?.value;
+            if grant {
+                AccessStatement::Grant {
+                    privileges,
+                    table,
+                    user,
+                }
+            } else {
+                AccessStatement::Revoke {
+                    privileges,
+                    table,
+                    user,
+                }
+            }
+        }
+        _ => return Ok(None),
+    };
+
+    if let Some(Token::SemiColon) = peek_token(&mut tokens) {
+        next_token(&mut tokens);
+    }
+    if let Some(token) = next_token(&mut tokens).filter(|token| **token != Token::EOF) {
+        return Err(parser_error(&format!(
+            "Expected end of statement, found: {}",
+            token
+        )));
+    }
+    Ok(Some(statement))
+}
+
+/// Parses `ALL [PRIVILEGES]` or a list of privileges separated by commas.
+fn parse_privileges(
+    tokens: &mut std::iter::Peekable<std::slice::Iter<Token>>,
+) -> Result<Vec<Privilege>> {
+    if is_keyword(peek_token(tokens), "ALL") {
+        next_token(tokens);
+        if is_keyword(peek_token(tokens), "PRIVILEGES") {
+            next_token(tokens);
+        }
+        return Ok(Privilege::ALL.to_vec());
+    }
+    let mut privileges = vec![];
+    loop {
+        let name = expect_identifier(next_token(tokens))?.value;
+        let privilege = Privilege::from_name(&name)
+            .ok_or_else(|| parser_error(&format!("Unknown privilege: {}", name)))?;
+        privileges.push(privilege);
+        if let Some(Token::Comma) = peek_token(tokens) {
+            next_token(tokens);
+        } else {
+            return Ok(privileges);
+        }
+    }
+}
+
+/// Parses a table name, qualified by the schema name of an attached database or not.
+fn parse_table_name(tokens: &mut std::iter::Peekable<std::slice::Iter<Token>>) -> Result<String> {
+    let mut name = expect_identifier(next_token(tokens))?.value;
+    if let Some(Token::Period) = peek_token(tokens) {
+        next_token(tokens);
+        name = format!("{}.{}", name, expect_identifier(next_token(tokens))?.value);
+    }
+    Ok(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_access_statement_test() {
+        assert_eq!(
+            parse_access_statement("CREATE USER reporting WITH PASSWORD 'it''s';").unwrap(),
+            Some(AccessStatement::CreateUser {
+                name: "reporting".to_string(),
+                password: "it's".to_string(),
+            })
+        );
+        assert_eq!(
+            parse_access_statement("drop user if exists reporting").unwrap(),
+            Some(AccessStatement::DropUser {
+                name: "reporting".to_string(),
+                if_exists: true,
+            })
+        );
+        assert_eq!(
+            parse_access_statement("GRANT SELECT, insert ON TABLE aux.users TO reporting;")
+                .unwrap(),
+            Some(AccessStatement::Grant {
+                privileges: vec![Privilege::Select, Privilege::Insert],
+                table: Some("aux.users".to_string()),
+                user: "reporting".to_string(),
+            })
+        );
+        assert_eq!(
+            parse_access_statement("REVOKE ALL PRIVILEGES ON DATABASE FROM reporting;").unwrap(),
+            Some(AccessStatement::Revoke {
+                privileges: Privilege::ALL.to_vec(),
+                table: None,
+                user: "reporting".to_string(),
+            })
+        );
+        assert!(parse_access_statement("GRANT TRUNCATE ON users TO reporting;").is_err());
+        assert!(parse_access_statement("REVOKE SELECT ON users TO reporting;").is_err());
+        assert!(parse_access_statement("CREATE TABLE users (id INTEGER);")
+            .unwrap()
+            .is_none());
+    }
+}
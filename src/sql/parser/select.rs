@@ -0,0 +1,573 @@
+use sqlparser::ast::{
+    Expr, FunctionArg, FunctionArgExpr, JoinConstraint, JoinOperator, OrderByExpr, Select,
+    SelectItem, SetExpr, SetOperator, Statement, TableFactor,
+};
+
+use crate::error::{Result, SQLRiteError};
+
+/// Each item of the SELECT list is represented by the following enum
+/// after the query is parsed
+#[derive(Debug, PartialEq, Clone)]
+pub enum Projection {
+    /// `*`, expands to every column of the table, of every table in a join
+    Wildcard,
+    /// `u.*` in a SELECT from several tables, expands to every column of the table called `u`
+    TableWildcard(String),
+    /// Expression to be evaluated for every row, with its optional `AS` alias
+    Expr { expr: Expr, alias: Option<String> },
+}
+
+/// Each term of the ORDER BY clause is represented by the following structure
+#[derive(Debug, PartialEq, Clone)]
+pub struct OrderBy {
+    /// Expression the rows are sorted by
+    pub expr: Expr,
+    /// Sort direction, `false` for DESC
+    pub asc: bool,
+    /// Placement of NULLs given with NULLS FIRST or NULLS LAST, if any. By default they
+    /// sort before other values, first in ascending order and last in descending order.
+    pub nulls_first: Option<bool>,
+    /// Collating sequence given with a COLLATE clause, if any
+    pub collation: Option<String>,
+}
+
+/// The following structure represents a SELECT query already parsed
+/// and broken down into `table_name` and a `Vec<Projection>` representing
+/// the SELECT list
+#[derive(Debug, Clone)]
+pub struct SelectQuery {
+    /// Name of the table in the FROM clause, none without one, when the SELECT list is
+    /// evaluated once, like `SELECT 1 + 1`
+    pub table_name: Option<String>,
+    /// Alias given to the table in the FROM clause, like `u` in `FROM users u`, by which
+    /// the names of its columns are qualified instead of by the name of the table
+    pub table_alias: Option<String>,
+    /// Arguments given when the FROM clause calls a table-valued function
+    pub table_args: Vec<Expr>,
+    /// The tables joined to the rows of the table `table_name`, from left to right, none when
+    /// the FROM clause has one table
+    pub joins: Vec<Join>,
+    /// Vector of `Projection` with every item in the SELECT list
+    pub projection: Vec<Projection>,
+    /// Condition of the WHERE clause, if any
+    pub selection: Option<Expr>,
+    /// Vector of `OrderBy` with every term of the ORDER BY clause, which sorts the rows of
+    /// the whole compound SELECT when there is one
+    pub order_by: Vec<OrderBy>,
+    /// The SELECTs combined, from left to right, with the rows of this one by UNION,
+    /// INTERSECT or EXCEPT, none for a simple SELECT
+    pub compound: Vec<Compound>,
+}
+
+/// A table of the FROM clause joined to the rows of the tables before it, by a comma or a
+/// JOIN operator
+#[derive(Debug, PartialEq, Clone)]
+pub struct Join {
+    pub table_name: String,
+    /// Alias given to the table, by which the names of its columns are qualified
+    pub table_alias: Option<String>,
+    /// Arguments given when the table is a table-valued function
+    pub table_args: Vec<Expr>,
+    /// Whether the rows before that no row of the table matches are kept, with NULL for each
+    /// column of the table, by LEFT JOIN
+    pub outer: bool,
+    /// Condition of the ON clause, none for a cross join
+    pub constraint: Option<Expr>,
+}
+
+/// A SELECT of a compound SELECT, combined with the rows of the SELECTs before it
+#[derive(Debug, Clone)]
+pub struct Compound {
+    pub operator: SetOperator,
+    /// Whether duplicate rows are kept, with UNION ALL
+    pub all: bool,
+    /// The SELECT, without an ORDER BY
+    pub query: SelectQuery,
+}
+
+impl SelectQuery {
+    pub fn new(statement: &Statement) -> Result<SelectQuery> {
+        let query = match statement {
+            Statement::Query(query) => query,
+            _ => {
+                return Err(SQLRiteError::Internal(
+                    "Error parsing select query".to_string(),
+                ))
+            }
+        };
+
+        // Clauses the executor doesn't run are refused rather than ignored
+        let unsupported = [
+            (query.with.is_some(), "WITH"),
+            (query.limit.is_some() || query.fetch.is_some(), "LIMIT"),
+            (query.offset.is_some(), "OFFSET"),
+            (query.lock.is_some(), "FOR UPDATE"),
+        ];
+        refuse_clauses(&unsupported)?;
+
+        let mut selects = vec![];
+        compound_members(&query.body, None, &mut selects)?;
+        let mut selects = selects.into_iter();
+        let mut select_query = match selects.next() {
+            Some((_, select)) => SelectQuery::from_select(select)?,
+            None => {
+                return Err(SQLRiteError::Internal(
+                    "Error parsing select query".to_string(),
+                ))
+            }
+        };
+        for (operator, select) in selects {
+            let (operator, all) = match operator {
+                Some(operator) => operator,
+                None => {
+                    return Err(SQLRiteError::Internal(
+                        "Error parsing compound select".to_string(),
+                    ))
+                }
+            };
+            if all && operator != SetOperator::Union {
+                return Err(SQLRiteError::NotImplemented(format!(
+                    "{} ALL is not supported, only UNION ALL is.",
+                    operator
+                )));
+            }
+            select_query.compound.push(Compound {
+                operator,
+                all,
+                query: SelectQuery::from_select(select)?,
+            });
+        }
+        select_query.order_by = order_by_terms(&query.order_by);
+        Ok(select_query)
+    }
+
+    /// The query of a single SELECT, without its ORDER BY, which belongs to the statement.
+    fn from_select(select: &Select) -> Result<SelectQuery> {
+        let unsupported = [
+            (select.distinct, "DISTINCT"),
+            (select.top.is_some(), "TOP"),
+            (select.into.is_some(), "SELECT INTO"),
+            (!select.lateral_views.is_empty(), "LATERAL VIEW"),
+            (!select.cluster_by.is_empty(), "CLUSTER BY"),
+            (!select.distribute_by.is_empty(), "DISTRIBUTE BY"),
+            (!select.sort_by.is_empty(), "SORT BY"),
+            (!select.group_by.is_empty(), "GROUP BY"),
+            (select.having.is_some(), "HAVING"),
+            (select.qualify.is_some(), "QUALIFY"),
+        ];
+        refuse_clauses(&unsupported)?;
+
+        let (table_name, table_alias, table_args) = match select.from.first() {
+            Some(from) => {
+                let (name, alias, args) = from_table(&from.relation)?;
+                (Some(name), alias, args)
+            }
+            None => (None, None, vec![]),
+        };
+        // Tables after a comma are joined like with CROSS JOIN
+        let mut joins = vec![];
+        for (i, from) in select.from.iter().enumerate() {
+            if i > 0 {
+                let (table_name, table_alias, table_args) = from_table(&from.relation)?;
+                joins.push(Join {
+                    table_name,
+                    table_alias,
+                    table_args,
+                    outer: false,
+                    constraint: None,
+                });
+            }
+            for join in &from.joins {
+                let (outer, constraint) = match &join.join_operator {
+                    JoinOperator::Inner(constraint) => (false, constraint),
+                    JoinOperator::LeftOuter(constraint) => (true, constraint),
+                    JoinOperator::CrossJoin => (false, &JoinConstraint::None),
+                    operator => {
+                        return Err(SQLRiteError::NotImplemented(format!(
+                            "{} is not supported, only INNER, LEFT and CROSS JOINs are.",
+                            join_name(operator)
+                        )))
+                    }
+                };
+                let constraint = match constraint {
+                    JoinConstraint::On(expr) => Some(expr.clone()),
+                    JoinConstraint::None => None,
+                    JoinConstraint::Using(_) | JoinConstraint::Natural => {
+                        return Err(SQLRiteError::NotImplemented(
+                            "USING and NATURAL joins are not supported for now.".to_string(),
+                        ))
+                    }
+                };
+                let (table_name, table_alias, table_args) = from_table(&join.relation)?;
+                joins.push(Join {
+                    table_name,
+                    table_alias,
+                    table_args,
+                    outer,
+                    constraint,
+                });
+            }
+        }
+
+        let mut projection: Vec<Projection> = vec![];
+        for item in &select.projection {
+            match item {
+                SelectItem::Wildcard => projection.push(Projection::Wildcard),
+                SelectItem::UnnamedExpr(expr) => projection.push(Projection::Expr {
+                    expr: expr.clone(),
+                    alias: None,
+                }),
+                SelectItem::ExprWithAlias { expr, alias } => projection.push(Projection::Expr {
+                    expr: expr.clone(),
+                    alias: Some(alias.value.to_string()),
+                }),
+                // `u.*` stands for every column of the table called `u`, all of them when it
+                // is the only one
+                SelectItem::QualifiedWildcard(qualifier) => {
+                    let qualifier = qualifier.to_string();
+                    let named = |name: &str, alias: &Option<String>| {
+                        names_table(&qualifier, name, alias.as_deref())
+                    };
+                    let first = table_name
+                        .as_ref()
+                        .is_some_and(|name| named(name, &table_alias));
+                    if first && joins.is_empty() {
+                        projection.push(Projection::Wildcard)
+                    } else if first
+                        || joins
+                            .iter()
+                            .any(|join| named(&join.table_name, &join.table_alias))
+                    {
+                        projection.push(Projection::TableWildcard(qualifier))
+                    } else {
+                        return Err(SQLRiteError::General(format!(
+                            "no such table: {}",
+                            qualifier
+                        )));
+                    }
+                }
+            }
+        }
+
+        Ok(SelectQuery {
+            table_name,
+            table_alias,
+            table_args,
+            joins,
+            projection,
+            selection: select.selection.clone(),
+            order_by: vec![],
+            compound: vec![],
+        })
+    }
+}
+
+/// The name, alias and arguments of the table `relation` of the FROM clause.
+fn from_table(relation: &TableFactor) -> Result<(String, Option<String>, Vec<Expr>)> {
+    match relation {
+        TableFactor::Table {
+            name, alias, args, ..
+        } => {
+            let args = args
+                .iter()
+                .map(|arg| match arg {
+                    FunctionArg::Unnamed(FunctionArgExpr::Expr(expr)) => Ok(expr.clone()),
+                    _ => Err(SQLRiteError::NotImplemented(format!(
+                        "Unsupported argument to table-valued function {}: {}",
+                        name, arg
+                    ))),
+                })
+                .collect::<Result<Vec<Expr>>>()?;
+            if alias
+                .as_ref()
+                .is_some_and(|alias| !alias.columns.is_empty())
+            {
+                return Err(SQLRiteError::NotImplemented(
+                    "Aliases of the columns of a table are not supported.".to_string(),
+                ));
+            }
+            let alias = alias.as_ref().map(|alias| alias.name.value.to_string());
+            Ok((name.to_string(), alias, args))
+        }
+        _ => Err(SQLRiteError::NotImplemented(
+            "Only table names are supported in the FROM clause for now.".to_string(),
+        )),
+    }
+}
+
+/// The name of the join `operator` in errors.
+fn join_name(operator: &JoinOperator) -> &'static str {
+    match operator {
+        JoinOperator::Inner(_) => "INNER JOIN",
+        JoinOperator::LeftOuter(_) => "LEFT JOIN",
+        JoinOperator::RightOuter(_) => "RIGHT JOIN",
+        JoinOperator::FullOuter(_) => "FULL JOIN",
+        JoinOperator::CrossJoin => "CROSS JOIN",
+        JoinOperator::CrossApply => "CROSS APPLY",
+        JoinOperator::OuterApply => "OUTER APPLY",
+    }
+}
+
+/// Fails with the first of the `clauses` used, each the clause and whether it is used.
+fn refuse_clauses(clauses: &[(bool, &str)]) -> Result<()> {
+    match clauses.iter().find(|(used, _)| *used) {
+        Some((_, clause)) => Err(SQLRiteError::NotImplemented(format!(
+            "{} is not supported in SELECT for now.",
+            clause
+        ))),
+        None => Ok(()),
+    }
+}
+
+/// Returns true if `qualifier` names the table `table_name` given the alias `alias`: like
+/// in SQLite, a table with an alias is called by it only, and one without by its name,
+/// with or without the schema it is in.
+pub fn names_table(qualifier: &str, table_name: &str, alias: Option<&str>) -> bool {
+    match alias {
+        Some(alias) => qualifier.eq_ignore_ascii_case(alias),
+        None => {
+            qualifier.eq_ignore_ascii_case(table_name)
+                || table_name
+                    .rsplit('.')
+                    .next()
+                    .is_some_and(|name| qualifier.eq_ignore_ascii_case(name))
+        }
+    }
+}
+
+/// Collects the SELECTs of `body` from left to right, each with the operator combining it
+/// with the ones before it, `operator` for the first one. sqlparser-rs binds INTERSECT
+/// tighter than UNION and EXCEPT, but in SQLite they all bind from left to right, which
+/// is the order the SELECTs are collected in whatever the tree.
+fn compound_members<'a>(
+    body: &'a SetExpr,
+    operator: Option<(SetOperator, bool)>,
+    selects: &mut Vec<(Option<(SetOperator, bool)>, &'a Select)>,
+) -> Result<()> {
+    match body {
+        SetExpr::Select(select) => selects.push((operator, select)),
+        SetExpr::SetOperation {
+            op,
+            all,
+            left,
+            right,
+        } => {
+            compound_members(left, operator, selects)?;
+            compound_members(right, Some((op.clone(), *all)), selects)?;
+        }
+        _ => {
+            return Err(SQLRiteError::NotImplemented(
+                "Only SELECT statements, simple or compound, are supported for now.".to_string(),
+            ))
+        }
+    }
+    Ok(())
+}
+
+/// The terms of an ORDER BY clause, the COLLATE clause of each split from its expression.
+pub(crate) fn order_by_terms(terms: &[OrderByExpr]) -> Vec<OrderBy> {
+    terms
+        .iter()
+        .map(|term| {
+            let (expr, collation) = match &term.expr {
+                Expr::Collate { expr, collation } => (*expr.clone(), Some(collation.to_string())),
+                expr => (expr.clone(), None),
+            };
+            OrderBy {
+                expr,
+                asc: term.asc.unwrap_or(true),
+                nulls_first: term.nulls_first,
+                collation,
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sql::*;
+
+    #[test]
+    fn select_query_test() {
+        let sql_input = String::from("SELECT id, name AS username FROM users;");
+        let dialect = SQLiteDialect {};
+        let mut ast = Parser::parse_sql(&dialect, &sql_input).unwrap();
+        let query = ast.pop().unwrap();
+
+        let select_query = SelectQuery::new(&query).unwrap();
+        assert_eq!(select_query.table_name, Some("users".to_string()));
+        assert_eq!(select_query.projection.len(), 2);
+        match &select_query.projection[1] {
+            Projection::Expr { alias, .. } => assert_eq!(alias, &Some("username".to_string())),
+            _ => panic!("expected an expression projection"),
+        }
+    }
+
+    #[test]
+    fn select_query_order_by_test() {
+        let sql_input =
+            String::from("SELECT * FROM users ORDER BY name COLLATE nocase DESC NULLS LAST, id;");
+        let dialect = SQLiteDialect {};
+        let mut ast = Parser::parse_sql(&dialect, &sql_input).unwrap();
+        let query = ast.pop().unwrap();
+
+        let select_query = SelectQuery::new(&query).unwrap();
+        assert_eq!(select_query.order_by.len(), 2);
+        assert_eq!(select_query.order_by[0].expr.to_string(), "name");
+        assert!(!select_query.order_by[0].asc);
+        assert_eq!(
+            select_query.order_by[0].collation,
+            Some("nocase".to_string())
+        );
+        assert_eq!(select_query.order_by[0].nulls_first, Some(false));
+        assert!(select_query.order_by[1].asc);
+        assert_eq!(select_query.order_by[1].nulls_first, None);
+        assert_eq!(select_query.order_by[1].collation, None);
+    }
+
+    #[test]
+    fn select_query_table_function_test() {
+        let sql_input = String::from("SELECT value FROM generate_series(1, 10);");
+        let dialect = SQLiteDialect {};
+        let mut ast = Parser::parse_sql(&dialect, &sql_input).unwrap();
+        let query = ast.pop().unwrap();
+
+        let select_query = SelectQuery::new(&query).unwrap();
+        assert_eq!(select_query.table_name, Some("generate_series".to_string()));
+        assert_eq!(select_query.table_args.len(), 2);
+        assert_eq!(select_query.table_args[1].to_string(), "10");
+    }
+
+    #[test]
+    fn select_query_compound_test() {
+        let sql_input = String::from(
+            "SELECT id FROM users UNION SELECT 1 INTERSECT SELECT id FROM admins UNION ALL SELECT 2 ORDER BY 1;",
+        );
+        let dialect = SQLiteDialect {};
+        let mut ast = Parser::parse_sql(&dialect, &sql_input).unwrap();
+        let query = ast.pop().unwrap();
+
+        // The SELECTs are combined from left to right, INTERSECT binding like UNION
+        let select_query = SelectQuery::new(&query).unwrap();
+        assert_eq!(select_query.table_name, Some("users".to_string()));
+        assert_eq!(select_query.order_by.len(), 1);
+        let members = select_query
+            .compound
+            .iter()
+            .map(|compound| {
+                (
+                    compound.operator.clone(),
+                    compound.all,
+                    compound.query.table_name.clone(),
+                )
+            })
+            .collect::<Vec<(SetOperator, bool, Option<String>)>>();
+        assert_eq!(
+            members,
+            vec![
+                (SetOperator::Union, false, None),
+                (SetOperator::Intersect, false, Some("admins".to_string())),
+                (SetOperator::Union, true, None),
+            ]
+        );
+        assert!(select_query.compound[0].query.order_by.is_empty());
+
+        let mut ast = Parser::parse_sql(
+            &dialect,
+            "SELECT id FROM users EXCEPT ALL SELECT id FROM admins;",
+        )
+        .unwrap();
+        assert!(SelectQuery::new(&ast.pop().unwrap()).is_err());
+    }
+
+    #[test]
+    fn select_query_table_alias_test() {
+        let dialect = SQLiteDialect {};
+        let mut ast = Parser::parse_sql(&dialect, "SELECT u.* FROM users AS u;").unwrap();
+        let select_query = SelectQuery::new(&ast.pop().unwrap()).unwrap();
+        assert_eq!(select_query.table_alias, Some("u".to_string()));
+        assert_eq!(select_query.projection, vec![Projection::Wildcard]);
+
+        // A table with an alias is only called by it
+        let mut ast = Parser::parse_sql(&dialect, "SELECT users.* FROM users u;").unwrap();
+        assert!(SelectQuery::new(&ast.pop().unwrap()).is_err());
+        assert!(names_table("USERS", "users", None));
+        assert!(names_table("users", "main.users", None));
+        assert!(!names_table("users", "users", Some("u")));
+    }
+
+    #[test]
+    fn select_query_join_test() {
+        let dialect = SQLiteDialect {};
+        let mut ast = Parser::parse_sql(
+            &dialect,
+            "SELECT o.* FROM users u, aux.orders o LEFT JOIN items ON items.order_id = o.id;",
+        )
+        .unwrap();
+        let select_query = SelectQuery::new(&ast.pop().unwrap()).unwrap();
+        assert_eq!(select_query.table_name, Some("users".to_string()));
+        assert_eq!(
+            select_query.projection,
+            vec![Projection::TableWildcard("o".to_string())]
+        );
+        let joins = select_query
+            .joins
+            .iter()
+            .map(|join| {
+                (
+                    join.table_name.as_str(),
+                    join.table_alias.as_deref(),
+                    join.outer,
+                    join.constraint.as_ref().map(|expr| expr.to_string()),
+                )
+            })
+            .collect::<Vec<(&str, Option<&str>, bool, Option<String>)>>();
+        assert_eq!(
+            joins,
+            vec![
+                ("aux.orders", Some("o"), false, None),
+                (
+                    "items",
+                    None,
+                    true,
+                    Some("items.order_id = o.id".to_string())
+                ),
+            ]
+        );
+
+        for sql in [
+            "SELECT * FROM users RIGHT JOIN orders ON orders.user_id = users.id;",
+            "SELECT * FROM users JOIN orders USING (id);",
+            "SELECT * FROM users NATURAL JOIN orders;",
+        ] {
+            let mut ast = Parser::parse_sql(&dialect, sql).unwrap();
+            match SelectQuery::new(&ast.pop().unwrap()) {
+                Err(SQLRiteError::NotImplemented(_)) => {}
+                result => panic!("{} wasn't refused: {:?}", sql, result),
+            }
+        }
+        let mut ast = Parser::parse_sql(&dialect, "SELECT x.* FROM users, orders;").unwrap();
+        assert!(SelectQuery::new(&ast.pop().unwrap()).is_err());
+    }
+
+    #[test]
+    fn select_query_unsupported_clauses_test() {
+        let dialect = SQLiteDialect {};
+        for sql in [
+            "SELECT name FROM users LIMIT 1;",
+            "SELECT name FROM users ORDER BY name LIMIT 1 OFFSET 2;",
+            "SELECT DISTINCT name FROM users;",
+            "SELECT id FROM users UNION SELECT DISTINCT id FROM admins;",
+            "WITH u AS (SELECT id FROM users) SELECT id FROM u;",
+            "SELECT name, count(*) FROM users GROUP BY name HAVING count(*) > 1;",
+            "SELECT count(*) FROM users HAVING count(*) > 1;",
+        ] {
+            let mut ast = Parser::parse_sql(&dialect, sql).unwrap();
+            match SelectQuery::new(&ast.pop().unwrap()) {
+                Err(SQLRiteError::NotImplemented(_)) => {}
+                result => panic!("{} wasn't refused: {:?}", sql, result),
+            }
+        }
+    }
+}
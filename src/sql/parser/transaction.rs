@@ -0,0 +1,136 @@
+use sqlparser::tokenizer::Token;
+
+use crate::error::Result;
+use crate::sql::parser::virtual_table::{
+    expect_identifier, is_keyword, next_token, parser_error, peek_token,
+};
+use crate::sql::tokenize;
+
+/// The statements of transactions and savepoints, parsed the way SQLite takes them
+#[derive(Debug, PartialEq)]
+pub enum TransactionStatement {
+    /// `BEGIN [DEFERRED|IMMEDIATE|EXCLUSIVE] [TRANSACTION]`
+    Begin,
+    /// `COMMIT [TRANSACTION]` or `END [TRANSACTION]`
+    Commit,
+    /// `ROLLBACK [TRANSACTION]`
+    Rollback,
+    /// `SAVEPOINT name`
+    Savepoint { name: String },
+    /// `RELEASE [SAVEPOINT] name`
+    Release { name: String },
+    /// `ROLLBACK [TRANSACTION] TO [SAVEPOINT] name`
+    RollbackTo { name: String },
+}
+
+/// Parses the statements of transactions and savepoints, returning `None` for any other
+/// statement.
+pub fn parse_transaction_statement(sql: &str) -> Result<Option<TransactionStatement>> {
+    let tokens = tokenize(sql)?;
+    let mut tokens = tokens.iter().peekable();
+    // Skips the optional keyword `keyword`
+    let optional = |tokens: &mut std::iter::Peekable<std::slice::Iter<Token>>, keyword| {
+        if is_keyword(peek_token(tokens), keyword) {
+            next_token(tokens);
+        }
+    };
+
+    let statement = match peek_token(&mut tokens) {
+        token if is_keyword(token, "BEGIN") => {
+            next_token(&mut tokens);
+            for mode in ["DEFERRED", "IMMEDIATE", "EXCLUSIVE"] {
+                optional(&mut tokens, mode);
+            }
+            optional(&mut tokens, "TRANSACTION");
+            TransactionStatement::Begin
+        }
+        token if is_keyword(token, "COMMIT") || is_keyword(token, "END") => {
+            next_token(&mut tokens);
+            optional(&mut tokens, "TRANSACTION");
+            TransactionStatement::Commit
+        }
+        token if is_keyword(token, "ROLLBACK") => {
+            next_token(&mut tokens);
+            optional(&mut tokens, "TRANSACTION");
+            if is_keyword(peek_token(&mut tokens), "TO") {
+                next_token(&mut tokens);
+                optional(&mut tokens, "SAVEPOINT");
+                let name = expect_identifier(next_token(&mut tokens))?.value;
+                TransactionStatement::RollbackTo { name }
+            } else {
+                TransactionStatement::Rollback
+            }
+        }
+        token if is_keyword(token, "SAVEPOINT") => {
+            next_token(&mut tokens);
+            let name = expect_identifier(next_token(&mut tokens))?.value;
+            TransactionStatement::Savepoint { name }
+        }
+        token if is_keyword(token, "RELEASE") => {
+            next_token(&mut tokens);
+            optional(&mut tokens, "SAVEPOINT");
+            let name = expect_identifier(next_token(&mut tokens))?.value;
+            TransactionStatement::Release { name }
+        }
+        _ => return Ok(None),
+    };
+
+    if let Some(Token::SemiColon) = peek_token(&mut tokens) {
+        next_token(&mut tokens);
+    }
+    if let Some(token) = next_token(&mut tokens).filter(|token| **token != Token::EOF) {
+        return Err(parser_error(&format!(
+            "Expected end of statement, found: {}",
+            token
+        )));
+    }
+    Ok(Some(statement))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_transaction_statement_test() {
+        let parse = |sql| parse_transaction_statement(sql).unwrap();
+        assert_eq!(parse("BEGIN;"), Some(TransactionStatement::Begin));
+        assert_eq!(
+            parse("begin immediate transaction"),
+            Some(TransactionStatement::Begin)
+        );
+        assert_eq!(
+            parse("END TRANSACTION;"),
+            Some(TransactionStatement::Commit)
+        );
+        assert_eq!(parse("COMMIT"), Some(TransactionStatement::Commit));
+        assert_eq!(parse("ROLLBACK;"), Some(TransactionStatement::Rollback));
+        assert_eq!(
+            parse("SAVEPOINT before_import;"),
+            Some(TransactionStatement::Savepoint {
+                name: "before_import".to_string()
+            })
+        );
+        assert_eq!(
+            parse("RELEASE SAVEPOINT a;"),
+            Some(TransactionStatement::Release {
+                name: "a".to_string()
+            })
+        );
+        assert_eq!(
+            parse("ROLLBACK TRANSACTION TO SAVEPOINT a;"),
+            Some(TransactionStatement::RollbackTo {
+                name: "a".to_string()
+            })
+        );
+        assert_eq!(
+            parse("rollback to a"),
+            Some(TransactionStatement::RollbackTo {
+                name: "a".to_string()
+            })
+        );
+        assert!(parse_transaction_statement("SAVEPOINT;").is_err());
+        assert!(parse_transaction_statement("COMMIT now;").is_err());
+        assert_eq!(parse("SELECT * FROM users;"), None);
+    }
+}
@@ -0,0 +1,305 @@
+use std::iter::Peekable;
+use std::slice::Iter;
+
+use sqlparser::ast::{Expr, SelectItem, SetExpr, Statement};
+use sqlparser::tokenizer::Token;
+
+use crate::error::Result;
+use crate::sql::db::trigger::{Trigger, TriggerEvent, TriggerTiming};
+use crate::sql::parse_statement;
+use crate::sql::parser::virtual_table::{
+    expect_identifier, expect_keyword, is_keyword, next_token, parser_error, peek_token, token_text,
+};
+use crate::sql::tokenize;
+
+/// The statements managing triggers, which sqlparser-rs doesn't parse
+#[derive(Debug, PartialEq)]
+pub enum TriggerStatement {
+    /// `CREATE [TEMP] TRIGGER [IF NOT EXISTS] name ...`
+    Create {
+        trigger: Trigger,
+        if_not_exists: bool,
+    },
+    /// `DROP TRIGGER [IF EXISTS] name`
+    Drop { name: String, if_exists: bool },
+}
+
+/// Parses `CREATE TRIGGER` and `DROP TRIGGER`, returning `None` for any other statement:
+///
+/// ```sql
+/// CREATE TRIGGER [IF NOT EXISTS] name [BEFORE | AFTER]
+///     {INSERT | UPDATE [OF column, ...] | DELETE} ON table [FOR EACH ROW] [WHEN condition]
+/// BEGIN
+///     statement; ...
+/// END;
+/// ```
+///
+/// The WHEN condition and the statements are checked to be valid SQL and kept as text,
+/// the `NEW` and `OLD` values are bound into them each time the trigger fires.
+pub fn parse_trigger_statement(sql: &str) -> Result<Option<TriggerStatement>> {
+    let tokens = tokenize(sql)?;
+    let mut tokens = tokens.iter().peekable();
+
+    let statement = if is_keyword(peek_token(&mut tokens), "DROP") {
+        next_token(&mut tokens);
+        if !is_keyword(next_token(&mut tokens), "TRIGGER") {
+            return Ok(None);
+        }
+        let mut if_exists = false;
+        if is_keyword(peek_token(&mut tokens), "IF") {
+            next_token(&mut tokens);
+            expect_keyword(next_token(&mut tokens), "EXISTS")?;
+            if_exists = true;
+        }
+        let name = expect_identifier(next_token(&mut tokens))?.value;
+        TriggerStatement::Drop { name, if_exists }
+    } else if is_keyword(peek_token(&mut tokens), "CREATE") {
+        next_token(&mut tokens);
+        // Temporary triggers are created as regular ones, a trigger on a temporary table
+        // is dropped with it
+        if is_keyword(peek_token(&mut tokens), "TEMP")
+            || is_keyword(peek_token(&mut tokens), "TEMPORARY")
+        {
+            next_token(&mut tokens);
+        }
+        if !is_keyword(next_token(&mut tokens), "TRIGGER") {
+            return Ok(None);
+        }
+        parse_create_trigger(&mut tokens)?
+    } else {
+        return Ok(None);
+    };
+
+    if let Some(Token::SemiColon) = peek_token(&mut tokens) {
+        next_token(&mut tokens);
+    }
+    if let Some(token) = next_token(&mut tokens).filter(|token| **token != Token::EOF) {
+        return Err(parser_error(&format!(
+            "Expected end of statement, found: {}",
+            token
+        )));
+    }
+    Ok(Some(statement))
+}
+
+/// Parses what follows `CREATE TRIGGER`.
+fn parse_create_trigger(tokens: &mut Peekable<Iter<'_, Token>>) -> Result<TriggerStatement> {
+    let mut if_not_exists = false;
+    if is_keyword(peek_token(tokens), "IF") {
+        next_token(tokens);
+        expect_keyword(next_token(tokens), "NOT")?;
+        expect_keyword(next_token(tokens), "EXISTS")?;
+        if_not_exists = true;
+    }
+    let name = expect_identifier(next_token(tokens))?.value;
+
+    let timing = if is_keyword(peek_token(tokens), "BEFORE") {
+        next_token(tokens);
+        TriggerTiming::Before
+    } else if is_keyword(peek_token(tokens), "AFTER") {
+        next_token(tokens);
+        TriggerTiming::After
+    } else if is_keyword(peek_token(tokens), "INSTEAD") {
+        return Err(parser_error(
+            "INSTEAD OF triggers are only supported on views",
+        ));
+    } else {
+        TriggerTiming::Before
+    };
+
+    let event = match next_token(tokens) {
+        token if is_keyword(token, "INSERT") => TriggerEvent::Insert,
+        token if is_keyword(token, "DELETE") => TriggerEvent::Delete,
+        token if is_keyword(token, "UPDATE") => {
+            let mut columns: Vec<String> = vec![];
+            if is_keyword(peek_token(tokens), "OF") {
+                next_token(tokens);
+                columns.push(expect_identifier(next_token(tokens))?.value);
+                while let Some(Token::Comma) = peek_token(tokens) {
+                    next_token(tokens);
+                    columns.push(expect_identifier(next_token(tokens))?.value);
+                }
+            }
+            TriggerEvent::Update(columns)
+        }
+        token => {
+            return Err(parser_error(&format!(
+                "Expected INSERT, UPDATE or DELETE, found: {}",
+                token.map_or("EOF".to_string(), |t| t.to_string())
+            )))
+        }
+    };
+    expect_keyword(next_token(tokens), "ON")?;
+    let table_name = expect_identifier(next_token(tokens))?.value;
+
+    if is_keyword(peek_token(tokens), "FOR") {
+        next_token(tokens);
+        expect_keyword(next_token(tokens), "EACH")?;
+        expect_keyword(next_token(tokens), "ROW")?;
+    }
+
+    let mut when: Option<String> = None;
+    if is_keyword(peek_token(tokens), "WHEN") {
+        next_token(tokens);
+        let mut condition = String::new();
+        loop {
+            match tokens.peek() {
+                Some(Token::EOF) | None => return Err(parser_error("Expected BEGIN, found: EOF")),
+                Some(token) if is_keyword(Some(token), "BEGIN") => break,
+                Some(token) => condition.push_str(&token_text(token)),
+            }
+            tokens.next();
+        }
+        parse_expression(condition.trim())?;
+        when = Some(condition.trim().to_string());
+    }
+
+    expect_keyword(next_token(tokens), "BEGIN")?;
+    let mut body: Vec<String> = vec![];
+    let mut statement = String::new();
+    // END also closes CASE expressions
+    let mut case_depth = 0;
+    loop {
+        let token = match tokens.next() {
+            Some(Token::EOF) | None => return Err(parser_error("Expected END, found: EOF")),
+            Some(token) => token,
+        };
+        match token {
+            Token::SemiColon => {
+                if statement.trim().is_empty() {
+                    return Err(parser_error("Expected a statement, found: ;"));
+                }
+                parse_statement(statement.trim())?;
+                body.push(statement.trim().to_string());
+                statement.clear();
+                continue;
+            }
+            token if is_keyword(Some(token), "CASE") => case_depth += 1,
+            token if is_keyword(Some(token), "END") => {
+                if case_depth == 0 {
+                    if !statement.trim().is_empty() {
+                        return Err(parser_error("Expected ;, found: END"));
+                    }
+                    break;
+                }
+                case_depth -= 1;
+            }
+            _ => {}
+        }
+        statement.push_str(&token_text(token));
+    }
+    if body.is_empty() {
+        return Err(parser_error("Expected a statement, found: END"));
+    }
+
+    Ok(TriggerStatement::Create {
+        trigger: Trigger {
+            name,
+            table_name,
+            timing,
+            event,
+            when,
+            body,
+        },
+        if_not_exists,
+    })
+}
+
+/// Parses a standalone expression, like the WHEN condition of a trigger.
+pub fn parse_expression(sql: &str) -> Result<Expr> {
+    let statement = parse_statement(&format!("SELECT {}", sql))?;
+    if let Statement::Query(query) = &statement {
+        if let SetExpr::Select(select) = &query.body {
+            if let [SelectItem::UnnamedExpr(expr)] = select.projection.as_slice() {
+                if select.from.is_empty() {
+                    return Ok(expr.clone());
+                }
+            }
+        }
+    }
+    Err(parser_error(&format!(
+        "Expected an expression, found: {}",
+        sql
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_create_trigger_test() {
+        let statement = parse_trigger_statement(
+            "CREATE TRIGGER IF NOT EXISTS count_posts AFTER INSERT ON posts FOR EACH ROW
+             WHEN NEW.published = 1
+             BEGIN
+                 INSERT INTO audit (action, post) VALUES ('insert', NEW.id);
+                 UPDATE counters SET posts = CASE WHEN posts > 0 THEN posts + 1 ELSE 1 END;
+             END;",
+        )
+        .unwrap()
+        .unwrap();
+        match statement {
+            TriggerStatement::Create {
+                trigger,
+                if_not_exists,
+            } => {
+                assert!(if_not_exists);
+                assert_eq!(trigger.name, "count_posts");
+                assert_eq!(trigger.table_name, "posts");
+                assert_eq!(trigger.timing, TriggerTiming::After);
+                assert_eq!(trigger.event, TriggerEvent::Insert);
+                assert_eq!(trigger.when, Some("NEW.published = 1".to_string()));
+                assert_eq!(trigger.body.len(), 2);
+                assert_eq!(
+                    trigger.body[0],
+                    "INSERT INTO audit (action, post) VALUES ('insert', NEW.id)"
+                );
+            }
+            _ => panic!("expected a CREATE TRIGGER statement"),
+        }
+
+        let statement = parse_trigger_statement(
+            "CREATE TRIGGER t UPDATE OF name, email ON users BEGIN DELETE FROM logs; END",
+        )
+        .unwrap()
+        .unwrap();
+        match statement {
+            TriggerStatement::Create { trigger, .. } => {
+                assert_eq!(trigger.timing, TriggerTiming::Before);
+                assert_eq!(
+                    trigger.event,
+                    TriggerEvent::Update(vec!["name".to_string(), "email".to_string()])
+                );
+            }
+            _ => panic!("expected a CREATE TRIGGER statement"),
+        }
+
+        assert!(parse_trigger_statement("CREATE TRIGGER t INSERT ON users BEGIN END;").is_err());
+        assert!(
+            parse_trigger_statement("CREATE TRIGGER t INSERT ON users BEGIN DELETE FROM logs")
+                .is_err()
+        );
+        assert!(parse_trigger_statement(
+            "CREATE TRIGGER t INSERT ON users BEGIN INSERT INTO; END;"
+        )
+        .is_err());
+        assert!(parse_trigger_statement("CREATE TABLE users (id INTEGER);")
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn parse_drop_trigger_test() {
+        assert_eq!(
+            parse_trigger_statement("DROP TRIGGER IF EXISTS count_posts;").unwrap(),
+            Some(TriggerStatement::Drop {
+                name: "count_posts".to_string(),
+                if_exists: true
+            })
+        );
+        assert!(parse_trigger_statement("DROP TABLE users;")
+            .unwrap()
+            .is_none());
+    }
+}
@@ -0,0 +1,163 @@
+use sqlparser::ast::{SqliteOnConflict, Statement};
+use sqlparser::tokenizer::Token;
+
+use crate::error::Result;
+use crate::sql::parser::virtual_table::{is_keyword, parser_error, token_text};
+use crate::sql::{parse_statement, tokenize};
+
+/// What an INSERT does with a row that would give a UNIQUE or PRIMARY KEY column a value
+/// another row of the table already has, instead of failing
+#[derive(Debug, PartialEq)]
+pub enum OnConflict {
+    /// `INSERT OR REPLACE`: the rows it conflicts with are deleted before it is inserted
+    Replace,
+    /// `INSERT OR IGNORE` and `ON CONFLICT DO NOTHING`: the row isn't inserted. With a
+    /// target, only conflicts on the target columns are ignored.
+    Ignore { target: Vec<String> },
+    /// `ON CONFLICT (target) DO UPDATE SET ... [WHERE ...]`: the row it conflicts with on
+    /// the target columns is updated instead
+    Update {
+        target: Vec<String>,
+        /// SQL text of the SET and WHERE clauses, in which `excluded.column` is the value
+        /// the row would have been inserted with
+        clauses: String,
+    },
+}
+
+impl OnConflict {
+    /// The conflict resolution of `INSERT OR ...`, those aborting the statement like a
+    /// plain INSERT being none.
+    pub fn from_or(or: Option<SqliteOnConflict>) -> Option<OnConflict> {
+        match or {
+            Some(SqliteOnConflict::Replace) => Some(OnConflict::Replace),
+            Some(SqliteOnConflict::Ignore) => Some(OnConflict::Ignore { target: vec![] }),
+            _ => None,
+        }
+    }
+}
+
+/// Splits `INSERT ... ON CONFLICT [(columns)] DO NOTHING | DO UPDATE SET ...`, SQLite's
+/// upsert, which sqlparser-rs doesn't parse, into the SQL text of the INSERT without its
+/// ON CONFLICT clause, and the clause. Returns `None` for other statements, and for
+/// INSERT statements without the clause.
+pub fn parse_upsert(sql: &str) -> Result<Option<(String, OnConflict)>> {
+    let tokens = tokenize(sql)?
+        .into_iter()
+        .filter(|token| *token != Token::SemiColon)
+        .collect::<Vec<Token>>();
+    let words = tokens
+        .iter()
+        .enumerate()
+        .filter(|(_, token)| !matches!(token, Token::Whitespace(_)))
+        .collect::<Vec<(usize, &Token)>>();
+    if !is_keyword(words.first().map(|(_, token)| *token), "INSERT")
+        && !is_keyword(words.first().map(|(_, token)| *token), "REPLACE")
+    {
+        return Ok(None);
+    }
+
+    // The clause starts at the first ON CONFLICT outside of parentheses
+    let mut depth = 0;
+    let mut start = None;
+    for (i, (_, token)) in words.iter().enumerate() {
+        match token {
+            Token::LParen => depth += 1,
+            Token::RParen => depth -= 1,
+            token
+                if depth == 0
+                    && is_keyword(Some(token), "ON")
+                    && is_keyword(words.get(i + 1).map(|(_, token)| *token), "CONFLICT") =>
+            {
+                start = Some(i);
+                break;
+            }
+            _ => {}
+        }
+    }
+    let start = match start {
+        Some(start) => start,
+        None => return Ok(None),
+    };
+    let text = |tokens: &[Token]| tokens.iter().map(token_text).collect::<String>();
+    let statement = text(&tokens[..words[start].0]);
+
+    let mut clause = words[start + 2..].iter().peekable();
+    let mut target = vec![];
+    if let Some((_, Token::LParen)) = clause.peek() {
+        clause.next();
+        loop {
+            match clause.next() {
+                Some((_, Token::Word(word))) => target.push(word.value.to_string()),
+                _ => return Err(parser_error("Expected a column name in ON CONFLICT")),
+            }
+            match clause.next() {
+                Some((_, Token::Comma)) => {}
+                Some((_, Token::RParen)) => break,
+                _ => return Err(parser_error("Expected , or ) in ON CONFLICT")),
+            }
+        }
+    }
+    if !is_keyword(clause.next().map(|(_, token)| *token), "DO") {
+        return Err(parser_error("Expected DO in ON CONFLICT"));
+    }
+    let on_conflict = match clause.next() {
+        Some((_, token)) if is_keyword(Some(token), "NOTHING") => {
+            if clause.next().is_some() {
+                return Err(parser_error("Expected end of statement after DO NOTHING"));
+            }
+            OnConflict::Ignore { target }
+        }
+        Some((i, token)) if is_keyword(Some(token), "UPDATE") => {
+            if target.is_empty() {
+                return Err(parser_error("DO UPDATE without a conflict target"));
+            }
+            let clauses = text(&tokens[i + 1..]).trim().to_string();
+            // The clauses are parsed as those of an UPDATE, to fail on a syntax error now
+            match parse_statement(&format!("UPDATE t {}", clauses))? {
+                Statement::Update { .. } => {}
+                _ => return Err(parser_error("Expected SET after DO UPDATE")),
+            }
+            OnConflict::Update { target, clauses }
+        }
+        _ => return Err(parser_error("Expected NOTHING or UPDATE after DO")),
+    };
+    Ok(Some((statement, on_conflict)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_upsert_test() {
+        let (statement, on_conflict) = parse_upsert(
+            "INSERT INTO counts (word, n) VALUES ('on', 1) ON CONFLICT (word) DO UPDATE SET n = n + excluded.n WHERE n < 10;",
+        )
+        .unwrap()
+        .unwrap();
+        assert_eq!(
+            statement.trim(),
+            "INSERT INTO counts (word, n) VALUES ('on', 1)"
+        );
+        assert_eq!(
+            on_conflict,
+            OnConflict::Update {
+                target: vec!["word".to_string()],
+                clauses: "SET n = n + excluded.n WHERE n < 10".to_string(),
+            }
+        );
+
+        let (_, on_conflict) = parse_upsert("INSERT INTO t (a) VALUES (1) on conflict do nothing")
+            .unwrap()
+            .unwrap();
+        assert_eq!(on_conflict, OnConflict::Ignore { target: vec![] });
+
+        assert_eq!(parse_upsert("INSERT INTO t (a) VALUES (1);").unwrap(), None);
+        assert_eq!(parse_upsert("SELECT 1;").unwrap(), None);
+        assert!(
+            parse_upsert("INSERT INTO t (a) VALUES (1) ON CONFLICT DO UPDATE SET a = 2").is_err()
+        );
+        assert!(parse_upsert("INSERT INTO t (a) VALUES (1) ON CONFLICT (a) DO").is_err());
+        assert!(parse_upsert("INSERT INTO t (a) VALUES (1) ON CONFLICT (a) DO UPDATE a").is_err());
+    }
+}
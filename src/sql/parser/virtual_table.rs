@@ -0,0 +1,186 @@
+use std::iter::Peekable;
+use std::slice::Iter;
+
+use sqlparser::ast::{Ident, ObjectName, Statement};
+use sqlparser::parser::ParserError;
+use sqlparser::tokenizer::{Token, Word};
+
+use crate::error::{Result, SQLRiteError};
+use crate::sql::tokenize;
+
+/// Parses `CREATE VIRTUAL TABLE [IF NOT EXISTS] name USING module[(args...)]`, returning
+/// `None` for any other statement.
+///
+/// sqlparser-rs only accepts identifiers as module arguments, while SQLite hands every
+/// argument to the module as raw text, like `filename='data.csv'`. Each argument of the
+/// returned `Statement::CreateVirtualTable` is an `Ident` holding that raw text.
+pub fn parse_create_virtual_table(sql: &str) -> Result<Option<Statement>> {
+    let tokens = tokenize(sql)?;
+    let mut tokens = tokens.iter().peekable();
+
+    if !is_keyword(next_token(&mut tokens), "CREATE")
+        || !is_keyword(peek_token(&mut tokens), "VIRTUAL")
+    {
+        return Ok(None);
+    }
+    next_token(&mut tokens);
+    expect_keyword(next_token(&mut tokens), "TABLE")?;
+
+    let mut if_not_exists = false;
+    if is_keyword(peek_token(&mut tokens), "IF") {
+        next_token(&mut tokens);
+        expect_keyword(next_token(&mut tokens), "NOT")?;
+        expect_keyword(next_token(&mut tokens), "EXISTS")?;
+        if_not_exists = true;
+    }
+
+    let mut name = vec![expect_identifier(next_token(&mut tokens))?];
+    while let Some(Token::Period) = peek_token(&mut tokens) {
+        next_token(&mut tokens);
+        name.push(expect_identifier(next_token(&mut tokens))?);
+    }
+    expect_keyword(next_token(&mut tokens), "USING")?;
+    let module_name = expect_identifier(next_token(&mut tokens))?;
+
+    let mut module_args: Vec<Ident> = vec![];
+    if let Some(Token::LParen) = peek_token(&mut tokens) {
+        next_token(&mut tokens);
+        // Arguments are kept as written, whitespace included
+        let mut arg = String::new();
+        let mut depth = 0;
+        loop {
+            let token = match tokens.next() {
+                Some(Token::EOF) | None => return Err(parser_error("Expected ), found: EOF")),
+                Some(token) => token,
+            };
+            match token {
+                Token::RParen if depth == 0 => break,
+                Token::Comma if depth == 0 => {
+                    module_args.push(Ident::new(arg.trim()));
+                    arg.clear();
+                    continue;
+                }
+                Token::LParen => depth += 1,
+                Token::RParen => depth -= 1,
+                _ => {}
+            }
+            arg.push_str(&token_text(token));
+        }
+        if !arg.trim().is_empty() || !module_args.is_empty() {
+            module_args.push(Ident::new(arg.trim()));
+        }
+    }
+
+    if let Some(Token::SemiColon) = peek_token(&mut tokens) {
+        next_token(&mut tokens);
+    }
+    if let Some(token) = next_token(&mut tokens).filter(|token| **token != Token::EOF) {
+        return Err(parser_error(&format!(
+            "Expected end of statement, found: {}",
+            token
+        )));
+    }
+
+    Ok(Some(Statement::CreateVirtualTable {
+        name: ObjectName(name),
+        if_not_exists,
+        module_name,
+        module_args,
+    }))
+}
+
+/// Returns the next token that isn't whitespace.
+pub(crate) fn next_token<'a>(tokens: &mut Peekable<Iter<'a, Token>>) -> Option<&'a Token> {
+    peek_token(tokens);
+    tokens.next()
+}
+
+/// Returns the next token that isn't whitespace without consuming it.
+pub(crate) fn peek_token<'a>(tokens: &mut Peekable<Iter<'a, Token>>) -> Option<&'a Token> {
+    while let Some(Token::Whitespace(_)) = tokens.peek() {
+        tokens.next();
+    }
+    tokens.peek().copied()
+}
+
+/// Returns the SQL text of a token of a module argument.
+pub(crate) fn token_text(token: &Token) -> String {
+    match token {
+        // The tokenizer unescapes string literals, they have to be quoted back
+        Token::SingleQuotedString(s) => format!("'{}'", s.replace('\'', "''")),
+        token => token.to_string(),
+    }
+}
+
+pub(crate) fn is_keyword(token: Option<&Token>, keyword: &str) -> bool {
+    matches!(token, Some(Token::Word(Word { value, quote_style: None, .. }))
+        if value.eq_ignore_ascii_case(keyword))
+}
+
+pub(crate) fn expect_keyword(token: Option<&Token>, keyword: &str) -> Result<()> {
+    if is_keyword(token, keyword) {
+        Ok(())
+    } else {
+        Err(parser_error(&format!(
+            "Expected {}, found: {}",
+            keyword,
+            token.map_or("EOF".to_string(), |t| t.to_string())
+        )))
+    }
+}
+
+pub(crate) fn expect_identifier(token: Option<&Token>) -> Result<Ident> {
+    match token {
+        Some(Token::Word(word)) => Ok(Ident {
+            value: word.value.to_string(),
+            quote_style: word.quote_style,
+        }),
+        token => Err(parser_error(&format!(
+            "Expected identifier, found: {}",
+            token.map_or("EOF".to_string(), |t| t.to_string())
+        ))),
+    }
+}
+
+pub(crate) fn parser_error(message: &str) -> SQLRiteError {
+    SQLRiteError::SqlError(ParserError::ParserError(message.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_create_virtual_table_test() {
+        let statement = parse_create_virtual_table(
+            "CREATE VIRTUAL TABLE IF NOT EXISTS logs USING csv(filename='app''s.csv',  header = true);",
+        )
+        .unwrap()
+        .unwrap();
+        match statement {
+            Statement::CreateVirtualTable {
+                name,
+                if_not_exists,
+                module_name,
+                module_args,
+            } => {
+                assert_eq!(name.to_string(), "logs");
+                assert!(if_not_exists);
+                assert_eq!(module_name.value, "csv");
+                let args = module_args
+                    .iter()
+                    .map(|arg| arg.value.as_str())
+                    .collect::<Vec<&str>>();
+                assert_eq!(args, vec!["filename='app''s.csv'", "header = true"]);
+            }
+            _ => panic!("expected a CREATE VIRTUAL TABLE statement"),
+        }
+
+        assert!(
+            parse_create_virtual_table("CREATE TABLE users (id INTEGER);")
+                .unwrap()
+                .is_none()
+        );
+        assert!(parse_create_virtual_table("CREATE VIRTUAL TABLE logs USING csv(a").is_err());
+    }
+}
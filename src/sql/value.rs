@@ -0,0 +1,553 @@
+use crate::error::{Result, SQLRiteError};
+use crate::sql::collation::CollationFn;
+use crate::sql::db::table::DataType;
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::convert::TryFrom;
+use std::fmt;
+
+/// A single SQL value as produced by the query engine.
+/// Mapped after the SQLite storage classes, plus a dedicated `Bool` for SQLRite's
+/// boolean columns.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub enum Value {
+    Null,
+    Integer(i64),
+    Real(f64),
+    Text(String),
+    Bool(bool),
+    Blob(Vec<u8>),
+}
+
+impl Value {
+    /// Returns true if the value is SQL NULL.
+    pub fn is_null(&self) -> bool {
+        matches!(self, Value::Null)
+    }
+
+    /// Name of the storage class of this value, as reported by errors and `typeof()`.
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::Null => "null",
+            Value::Integer(_) => "integer",
+            Value::Real(_) => "real",
+            Value::Text(_) => "text",
+            Value::Bool(_) => "bool",
+            Value::Blob(_) => "blob",
+        }
+    }
+
+    /// Compares two values following SQLite's sort order: NULLs first, then numeric values
+    /// (booleans count as 0 and 1), then text values compared with `collation`, then blobs
+    /// compared byte by byte.
+    pub fn compare(&self, other: &Value, collation: &CollationFn) -> Ordering {
+        match (self, other) {
+            (Value::Text(a), Value::Text(b)) => collation(a, b),
+            (Value::Blob(a), Value::Blob(b)) => a.cmp(b),
+            _ => match (self.as_number(), other.as_number()) {
+                (Some(a), Some(b)) => a.partial_cmp(&b).unwrap_or(Ordering::Equal),
+                _ => self.sort_class().cmp(&other.sort_class()),
+            },
+        }
+    }
+
+    /// Converts the value to text the way SQLite does, `None` for NULL.
+    pub fn to_text(&self) -> Option<String> {
+        match self {
+            Value::Null => None,
+            // Debug keeps the decimal point on integral reals, like SQLite
+            Value::Real(v) => Some(format!("{:?}", v)),
+            Value::Bool(v) => Some(i64::from(*v).to_string()),
+            value => Some(value.to_string()),
+        }
+    }
+
+    /// Converts the value to an integer the way SQLite does: reals are truncated and text
+    /// is read from its longest numeric prefix, 0 if there is none. `None` for NULL.
+    pub fn to_integer(&self) -> Option<i64> {
+        match self {
+            Value::Null => None,
+            Value::Integer(v) => Some(*v),
+            Value::Real(v) => Some(*v as i64),
+            Value::Bool(v) => Some(i64::from(*v)),
+            Value::Text(_) | Value::Blob(_) => {
+                let text = self.to_text().unwrap_or_default();
+                let prefix = numeric_prefix(&text);
+                Some(
+                    prefix
+                        .parse::<i64>()
+                        .unwrap_or_else(|_| prefix.parse::<f64>().unwrap_or(0.0) as i64),
+                )
+            }
+        }
+    }
+
+    /// Converts the value to a real the same way as `to_integer`. `None` for NULL.
+    pub fn to_real(&self) -> Option<f64> {
+        match self {
+            Value::Text(_) | Value::Blob(_) => {
+                let text = self.to_text().unwrap_or_default();
+                Some(numeric_prefix(&text).parse::<f64>().unwrap_or(0.0))
+            }
+            value => value.as_number(),
+        }
+    }
+
+    /// Converts the value to a boolean the way SQLite evaluates conditions: true if its
+    /// numeric value isn't zero. `None` for NULL.
+    pub fn to_bool(&self) -> Option<bool> {
+        self.to_real().map(|v| v != 0.0)
+    }
+
+    /// Converts the value to the number arithmetic operates on, the way SQLite does: an
+    /// integer when it reads as one, a real otherwise, text and blobs being read from their
+    /// longest numeric prefix like by `to_real`, 0 if there is none. `None` for NULL.
+    pub fn to_numeric(&self) -> Option<Value> {
+        match self {
+            Value::Null => None,
+            Value::Integer(v) => Some(Value::Integer(*v)),
+            Value::Real(v) => Some(Value::Real(*v)),
+            Value::Bool(v) => Some(Value::Integer(i64::from(*v))),
+            Value::Text(_) | Value::Blob(_) => {
+                let text = self.to_text().unwrap_or_default();
+                let prefix = numeric_prefix(&text);
+                match prefix.parse::<i64>() {
+                    Ok(v) => Some(Value::Integer(v)),
+                    Err(_) if prefix.is_empty() => Some(Value::Integer(0)),
+                    Err(_) => Some(Value::Real(prefix.parse::<f64>().unwrap_or(0.0))),
+                }
+            }
+        }
+    }
+
+    /// Converts the value to `affinity` the way `CAST(value AS type)` does, which always
+    /// succeeds: text is read as a number from its longest numeric prefix, 0 if there is
+    /// none, numbers become their text, NUMERIC reads text as an integer when it is one
+    /// without losing anything, and BOOL reads `'true'` and `'false'` as well as numbers.
+    /// Booleans become the text `'true'` or `'false'`, the text a BOOL column reads back.
+    /// NULL stays NULL.
+    pub fn cast(&self, affinity: Affinity) -> Value {
+        match (self, affinity) {
+            (Value::Null, _) => Value::Null,
+            (_, Affinity::Integer) => Value::Integer(self.to_integer().unwrap_or(0)),
+            (_, Affinity::Real) => Value::Real(self.to_real().unwrap_or(0.0)),
+            (Value::Text(_), Affinity::Numeric) | (Value::Blob(_), Affinity::Numeric) => {
+                match self.to_numeric() {
+                    Some(Value::Real(v)) => integral(v).unwrap_or(Value::Real(v)),
+                    numeric => numeric.unwrap_or(Value::Null),
+                }
+            }
+            (_, Affinity::Numeric) => self.to_numeric().unwrap_or(Value::Null),
+            (Value::Bool(v), Affinity::Text) => Value::Text(v.to_string()),
+            (_, Affinity::Text) => Value::Text(self.to_text().unwrap_or_default()),
+            (Value::Blob(_), Affinity::Blob) => self.clone(),
+            (_, Affinity::Blob) => Value::Blob(self.to_text().unwrap_or_default().into_bytes()),
+            (_, Affinity::Bool) => match boolean(self) {
+                Some(v) => Value::Bool(v),
+                None => Value::Bool(self.to_bool().unwrap_or(false)),
+            },
+        }
+    }
+
+    /// Converts the value to `affinity` only when nothing is lost, the way SQLite converts
+    /// values stored into a column or compared with one: text that reads entirely as a number
+    /// becomes that number for the numeric affinities, reals with no fractional part become
+    /// integers for INTEGER and NUMERIC, integers become reals for REAL, and numbers become
+    /// text for TEXT. Other values are left as they are.
+    pub fn apply_affinity(&self, affinity: Affinity) -> Value {
+        match (self, affinity) {
+            (Value::Text(_), Affinity::Bool) if boolean(self).is_some() => {
+                Value::Bool(boolean(self).unwrap_or(false))
+            }
+            (Value::Text(text), affinity) if affinity.is_numeric() => match number(text) {
+                Some(number) => number.apply_affinity(affinity),
+                None => self.clone(),
+            },
+            (Value::Real(v), Affinity::Integer) | (Value::Real(v), Affinity::Numeric) => {
+                integral(*v).unwrap_or_else(|| self.clone())
+            }
+            (Value::Integer(v), Affinity::Real) => Value::Real(*v as f64),
+            (Value::Integer(_), Affinity::Text)
+            | (Value::Real(_), Affinity::Text)
+            | (Value::Bool(_), Affinity::Text) => self.cast(Affinity::Text),
+            _ => self.clone(),
+        }
+    }
+
+    fn as_number(&self) -> Option<f64> {
+        match self {
+            Value::Integer(v) => Some(*v as f64),
+            Value::Real(v) => Some(*v),
+            Value::Bool(v) => Some(if *v { 1.0 } else { 0.0 }),
+            _ => None,
+        }
+    }
+
+    fn sort_class(&self) -> u8 {
+        match self {
+            Value::Null => 0,
+            Value::Integer(_) | Value::Real(_) | Value::Bool(_) => 1,
+            Value::Text(_) => 2,
+            Value::Blob(_) => 3,
+        }
+    }
+}
+
+/// The number `text` reads as, if all of it does but for spaces around it: an integer when it
+/// is one that fits in 64 bits, a real otherwise.
+fn number(text: &str) -> Option<Value> {
+    let text = text.trim();
+    if text.is_empty() || numeric_prefix(text).len() != text.len() {
+        return None;
+    }
+    match text.parse::<i64>() {
+        Ok(v) => Some(Value::Integer(v)),
+        Err(_) => text.parse::<f64>().ok().map(Value::Real),
+    }
+}
+
+/// `v` as an integer, if it is one that fits in 64 bits.
+fn integral(v: f64) -> Option<Value> {
+    // i64::MAX isn't exactly a f64, the bound is the power of two above it
+    match v.fract() == 0.0 && (-9.223_372_036_854_776e18..9.223_372_036_854_776e18).contains(&v) {
+        true => Some(Value::Integer(v as i64)),
+        false => None,
+    }
+}
+
+/// The boolean the text `'true'` or `'false'` reads as, in any case.
+fn boolean(value: &Value) -> Option<bool> {
+    match value {
+        Value::Text(text) if text.trim().eq_ignore_ascii_case("true") => Some(true),
+        Value::Text(text) if text.trim().eq_ignore_ascii_case("false") => Some(false),
+        _ => None,
+    }
+}
+
+/// The type a value is converted to by `CAST`, or that a column prefers its values to
+/// have: SQLite's type affinities, plus BOOL for SQLRite's boolean columns. A column
+/// without a type has no affinity, its values are neither converted nor compared as
+/// numbers.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Affinity {
+    Integer,
+    Real,
+    Numeric,
+    Text,
+    Blob,
+    Bool,
+}
+
+impl Affinity {
+    /// The affinity of the type named `name`, by SQLite's rules: a type whose name contains
+    /// INT is INTEGER, CHAR, CLOB or TEXT is TEXT, BLOB or BINARY is BLOB, REAL, FLOA or
+    /// DOUB is REAL, BOOL is BOOL, and any other type is NUMERIC.
+    pub fn from_type_name(name: &str) -> Affinity {
+        let name = name.to_uppercase();
+        let contains = |parts: &[&str]| parts.iter().any(|part| name.contains(part));
+        if contains(&["INT"]) {
+            Affinity::Integer
+        } else if contains(&["CHAR", "CLOB", "TEXT"]) {
+            Affinity::Text
+        } else if contains(&["BLOB", "BINARY"]) {
+            Affinity::Blob
+        } else if contains(&["REAL", "FLOA", "DOUB"]) {
+            Affinity::Real
+        } else if contains(&["BOOL"]) {
+            Affinity::Bool
+        } else {
+            Affinity::Numeric
+        }
+    }
+
+    /// The affinity of a column declared with `datatype`, none for a column without a type.
+    pub fn of_column(datatype: &DataType) -> Option<Affinity> {
+        match datatype {
+            DataType::Integer => Some(Affinity::Integer),
+            DataType::Real => Some(Affinity::Real),
+            DataType::Text => Some(Affinity::Text),
+            DataType::Bool => Some(Affinity::Bool),
+            DataType::None | DataType::Invalid => None,
+        }
+    }
+
+    /// Returns true for the affinities values are compared as numbers with.
+    pub fn is_numeric(self) -> bool {
+        !matches!(self, Affinity::Text | Affinity::Blob)
+    }
+}
+
+/// Returns the longest prefix of `text` that reads as a number, ignoring leading spaces.
+fn numeric_prefix(text: &str) -> &str {
+    let text = text.trim_start();
+    let bytes = text.as_bytes();
+    let digits = |mut i: usize| {
+        while i < bytes.len() && bytes[i].is_ascii_digit() {
+            i += 1;
+        }
+        i
+    };
+
+    let mut end = 0;
+    if end < bytes.len() && (bytes[end] == b'+' || bytes[end] == b'-') {
+        end += 1;
+    }
+    let start = end;
+    end = digits(end);
+    if end < bytes.len() && bytes[end] == b'.' {
+        end = digits(end + 1);
+    }
+    if end == start || &text[start..end] == "." {
+        return "";
+    }
+    if end < bytes.len() && (bytes[end] == b'e' || bytes[end] == b'E') {
+        let mut exp = end + 1;
+        if exp < bytes.len() && (bytes[exp] == b'+' || bytes[exp] == b'-') {
+            exp += 1;
+        }
+        let exp_end = digits(exp);
+        if exp_end > exp {
+            end = exp_end;
+        }
+    }
+    &text[..end]
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Value::Null => f.write_str("NULL"),
+            Value::Integer(v) => write!(f, "{}", v),
+            Value::Real(v) => write!(f, "{}", v),
+            Value::Text(v) => f.write_str(v),
+            Value::Bool(v) => write!(f, "{}", v),
+            // Printed as text, like the sqlite3 shell does
+            Value::Blob(v) => f.write_str(&String::from_utf8_lossy(v)),
+        }
+    }
+}
+
+/// Trait implemented by the Rust types a `Value` can be read into,
+/// used by `Row::get` and `Row::get_by_name`.
+pub trait FromValue: Sized {
+    fn from_value(value: &Value) -> Result<Self>;
+}
+
+fn type_mismatch(value: &Value, expected: &str) -> SQLRiteError {
+    SQLRiteError::General(format!(
+        "Cannot read {} value '{}' as {}",
+        value.type_name(),
+        value,
+        expected
+    ))
+}
+
+impl FromValue for Value {
+    fn from_value(value: &Value) -> Result<Self> {
+        Ok(value.clone())
+    }
+}
+
+impl FromValue for i64 {
+    fn from_value(value: &Value) -> Result<Self> {
+        match value {
+            Value::Integer(v) => Ok(*v),
+            Value::Bool(v) => Ok(i64::from(*v)),
+            _ => Err(type_mismatch(value, "i64")),
+        }
+    }
+}
+
+impl FromValue for i32 {
+    fn from_value(value: &Value) -> Result<Self> {
+        let v = i64::from_value(value)?;
+        i32::try_from(v).map_err(|_| type_mismatch(value, "i32"))
+    }
+}
+
+impl FromValue for f64 {
+    fn from_value(value: &Value) -> Result<Self> {
+        match value {
+            Value::Real(v) => Ok(*v),
+            Value::Integer(v) => Ok(*v as f64),
+            _ => Err(type_mismatch(value, "f64")),
+        }
+    }
+}
+
+impl FromValue for String {
+    fn from_value(value: &Value) -> Result<Self> {
+        match value {
+            Value::Text(v) => Ok(v.clone()),
+            _ => Err(type_mismatch(value, "String")),
+        }
+    }
+}
+
+impl FromValue for Vec<u8> {
+    fn from_value(value: &Value) -> Result<Self> {
+        match value {
+            Value::Blob(v) => Ok(v.clone()),
+            Value::Text(v) => Ok(v.as_bytes().to_vec()),
+            _ => Err(type_mismatch(value, "Vec<u8>")),
+        }
+    }
+}
+
+impl FromValue for bool {
+    fn from_value(value: &Value) -> Result<Self> {
+        match value {
+            Value::Bool(v) => Ok(*v),
+            Value::Integer(v) => Ok(*v != 0),
+            _ => Err(type_mismatch(value, "bool")),
+        }
+    }
+}
+
+impl<T: FromValue> FromValue for Option<T> {
+    fn from_value(value: &Value) -> Result<Self> {
+        match value {
+            Value::Null => Ok(None),
+            _ => T::from_value(value).map(Some),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn value_display_trait_test() {
+        assert_eq!(format!("{}", Value::Null), "NULL");
+        assert_eq!(format!("{}", Value::Integer(10)), "10");
+        assert_eq!(format!("{}", Value::Real(1.5)), "1.5");
+        assert_eq!(format!("{}", Value::Text("josh".to_string())), "josh");
+        assert_eq!(format!("{}", Value::Bool(true)), "true");
+    }
+
+    #[test]
+    fn value_conversion_test() {
+        assert_eq!(Value::Real(2.0).to_text(), Some("2.0".to_string()));
+        assert_eq!(Value::Bool(true).to_text(), Some("1".to_string()));
+        assert_eq!(Value::Null.to_text(), None);
+        assert_eq!(Value::Real(-2.7).to_integer(), Some(-2));
+        assert_eq!(Value::Text(" 12abc".to_string()).to_integer(), Some(12));
+        assert_eq!(Value::Text("1.5e2x".to_string()).to_integer(), Some(150));
+        assert_eq!(Value::Text("abc".to_string()).to_integer(), Some(0));
+        assert_eq!(Value::Text("-.5".to_string()).to_real(), Some(-0.5));
+        assert_eq!(Value::Text("1e".to_string()).to_real(), Some(1.0));
+        assert_eq!(
+            Value::Text(" 12abc".to_string()).to_numeric(),
+            Some(Value::Integer(12))
+        );
+        assert_eq!(
+            Value::Text("2.5".to_string()).to_numeric(),
+            Some(Value::Real(2.5))
+        );
+        assert_eq!(
+            Value::Text("abc".to_string()).to_numeric(),
+            Some(Value::Integer(0))
+        );
+        assert_eq!(Value::Bool(true).to_numeric(), Some(Value::Integer(1)));
+        assert_eq!(Value::Null.to_numeric(), None);
+    }
+
+    #[test]
+    fn value_cast_test() {
+        let text = |s: &str| Value::Text(s.to_string());
+        assert_eq!(text("12abc").cast(Affinity::Integer), Value::Integer(12));
+        assert_eq!(
+            Value::Real(-2.7).cast(Affinity::Integer),
+            Value::Integer(-2)
+        );
+        assert_eq!(text("abc").cast(Affinity::Real), Value::Real(0.0));
+        assert_eq!(text("3.0").cast(Affinity::Numeric), Value::Integer(3));
+        assert_eq!(text("3.5x").cast(Affinity::Numeric), Value::Real(3.5));
+        assert_eq!(Value::Real(2.0).cast(Affinity::Text), text("2.0"));
+        assert_eq!(Value::Bool(true).cast(Affinity::Text), text("true"));
+        assert_eq!(
+            text("ab").cast(Affinity::Blob),
+            Value::Blob(vec![b'a', b'b'])
+        );
+        assert_eq!(text("FALSE").cast(Affinity::Bool), Value::Bool(false));
+        assert_eq!(Value::Integer(2).cast(Affinity::Bool), Value::Bool(true));
+        assert_eq!(Value::Null.cast(Affinity::Text), Value::Null);
+
+        // Only what converts without loss does
+        assert_eq!(
+            text(" 5 ").apply_affinity(Affinity::Integer),
+            Value::Integer(5)
+        );
+        assert_eq!(
+            text("5.0").apply_affinity(Affinity::Integer),
+            Value::Integer(5)
+        );
+        assert_eq!(
+            text("5.5").apply_affinity(Affinity::Integer),
+            Value::Real(5.5)
+        );
+        assert_eq!(text("5x").apply_affinity(Affinity::Integer), text("5x"));
+        assert_eq!(text("2").apply_affinity(Affinity::Real), Value::Real(2.0));
+        assert_eq!(
+            text("true").apply_affinity(Affinity::Bool),
+            Value::Bool(true)
+        );
+        assert_eq!(Value::Integer(5).apply_affinity(Affinity::Text), text("5"));
+        assert_eq!(text("5").apply_affinity(Affinity::Blob), text("5"));
+        assert_eq!(Value::Null.apply_affinity(Affinity::Integer), Value::Null);
+
+        assert_eq!(Affinity::from_type_name("BIGINT"), Affinity::Integer);
+        assert_eq!(Affinity::from_type_name("VARCHAR(10)"), Affinity::Text);
+        assert_eq!(Affinity::from_type_name("DOUBLE"), Affinity::Real);
+        assert_eq!(Affinity::from_type_name("BOOLEAN"), Affinity::Bool);
+        assert_eq!(Affinity::from_type_name("DECIMAL(10,2)"), Affinity::Numeric);
+    }
+
+    #[test]
+    fn value_compare_test() {
+        let binary = |a: &str, b: &str| a.cmp(b);
+        assert_eq!(
+            Value::Null.compare(&Value::Integer(1), &binary),
+            Ordering::Less
+        );
+        assert_eq!(
+            Value::Integer(2).compare(&Value::Real(1.5), &binary),
+            Ordering::Greater
+        );
+        assert_eq!(
+            Value::Bool(true).compare(&Value::Integer(1), &binary),
+            Ordering::Equal
+        );
+        assert_eq!(
+            Value::Integer(10).compare(&Value::Text("1".to_string()), &binary),
+            Ordering::Less
+        );
+        assert_eq!(
+            Value::Text("a".to_string()).compare(&Value::Text("B".to_string()), &|a, b| a
+                .to_lowercase()
+                .cmp(&b.to_lowercase())),
+            Ordering::Less
+        );
+        assert_eq!(
+            Value::Blob(vec![0]).compare(&Value::Text("z".to_string()), &binary),
+            Ordering::Greater
+        );
+    }
+
+    #[test]
+    fn from_value_test() {
+        assert_eq!(i64::from_value(&Value::Integer(7)), Ok(7));
+        assert_eq!(i32::from_value(&Value::Integer(7)), Ok(7));
+        assert_eq!(f64::from_value(&Value::Integer(7)), Ok(7.0));
+        assert_eq!(
+            String::from_value(&Value::Text("josh".to_string())),
+            Ok("josh".to_string())
+        );
+        assert_eq!(bool::from_value(&Value::Bool(false)), Ok(false));
+        assert_eq!(Option::<i64>::from_value(&Value::Null), Ok(None));
+        assert_eq!(Option::<i64>::from_value(&Value::Integer(1)), Ok(Some(1)));
+        assert!(i64::from_value(&Value::Text("josh".to_string())).is_err());
+        assert!(i32::from_value(&Value::Integer(i64::MAX)).is_err());
+    }
+}
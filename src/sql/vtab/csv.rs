@@ -0,0 +1,278 @@
+use std::fs;
+use std::sync::Arc;
+
+use crate::error::{Result, SQLRiteError};
+use crate::sql::db::table::DataType;
+use crate::sql::value::Value;
+use crate::sql::vtab::{VirtualColumn, VirtualCursor, VirtualTable, VirtualTableModule};
+
+/// The `csv` module, exposing a CSV file as a read-only table:
+///
+/// ```sql
+/// CREATE VIRTUAL TABLE logs USING csv(filename='app.log.csv', header=true);
+/// ```
+///
+/// Arguments:
+/// - `filename`: path of the CSV file, read again on every scan.
+/// - `data`: CSV content given inline, instead of `filename`.
+/// - `header`: when true, the first row holds the column names. Otherwise the columns
+///   are named `c0`, `c1`... and the first row is data.
+///
+/// Every value is read as TEXT, rows with fewer fields than columns are padded with NULLs.
+pub struct CsvModule;
+
+impl VirtualTableModule for CsvModule {
+    fn create(&self, args: &[String]) -> Result<Arc<dyn VirtualTable>> {
+        let mut source: Option<CsvSource> = None;
+        let mut header = false;
+        for arg in args {
+            let (key, value) = match arg.find('=') {
+                Some(i) => (arg[..i].trim().to_lowercase(), unquote(arg[i + 1..].trim())),
+                None => {
+                    return Err(SQLRiteError::General(format!(
+                        "csv: invalid argument, expected key=value: {}",
+                        arg
+                    )))
+                }
+            };
+            match key.as_str() {
+                "filename" => source = Some(CsvSource::File(value)),
+                "data" => source = Some(CsvSource::Data(value)),
+                "header" => header = parse_bool(&value)?,
+                _ => {
+                    return Err(SQLRiteError::General(format!(
+                        "csv: unknown argument: {}",
+                        key
+                    )))
+                }
+            }
+        }
+
+        let source = match source {
+            Some(source) => source,
+            None => {
+                return Err(SQLRiteError::General(
+                    "csv: must specify either filename= or data=".to_string(),
+                ))
+            }
+        };
+
+        let records = source.read()?;
+        let columns = match records.first() {
+            Some(first) if header => first.clone(),
+            Some(first) => (0..first.len()).map(|i| format!("c{}", i)).collect(),
+            None => {
+                return Err(SQLRiteError::General(
+                    "csv: cannot read the columns of an empty file".to_string(),
+                ))
+            }
+        };
+
+        Ok(Arc::new(CsvTable {
+            source,
+            header,
+            columns,
+        }))
+    }
+}
+
+enum CsvSource {
+    File(String),
+    Data(String),
+}
+
+impl CsvSource {
+    fn read(&self) -> Result<Vec<Vec<String>>> {
+        match self {
+            CsvSource::File(filename) => {
+                let content = fs::read_to_string(filename).map_err(|err| {
+                    SQLRiteError::General(format!("csv: cannot read {}: {}", filename, err))
+                })?;
+                parse_csv(&content)
+            }
+            CsvSource::Data(data) => parse_csv(data),
+        }
+    }
+}
+
+struct CsvTable {
+    source: CsvSource,
+    header: bool,
+    columns: Vec<String>,
+}
+
+impl VirtualTable for CsvTable {
+    fn columns(&self) -> Vec<VirtualColumn> {
+        self.columns
+            .iter()
+            .map(|name| VirtualColumn::new(name, DataType::Text))
+            .collect()
+    }
+
+    fn open(&self) -> Result<Box<dyn VirtualCursor>> {
+        let mut records = self.source.read()?;
+        if self.header && !records.is_empty() {
+            records.remove(0);
+        }
+        Ok(Box::new(CsvCursor {
+            records,
+            position: 0,
+        }))
+    }
+}
+
+struct CsvCursor {
+    records: Vec<Vec<String>>,
+    position: usize,
+}
+
+impl VirtualCursor for CsvCursor {
+    fn filter(&mut self, _idx_num: i32, _args: &[Value]) -> Result<()> {
+        self.position = 0;
+        Ok(())
+    }
+
+    fn next(&mut self) -> Result<()> {
+        self.position += 1;
+        Ok(())
+    }
+
+    fn eof(&self) -> bool {
+        self.position >= self.records.len()
+    }
+
+    fn column(&self, index: usize) -> Result<Value> {
+        Ok(match self.records[self.position].get(index) {
+            Some(field) => Value::Text(field.to_string()),
+            None => Value::Null,
+        })
+    }
+}
+
+/// Removes the single or double quotes around an argument value.
+fn unquote(value: &str) -> String {
+    for quote in &['\'', '"'] {
+        if value.len() >= 2 && value.starts_with(*quote) && value.ends_with(*quote) {
+            let doubled = format!("{}{}", quote, quote);
+            return value[1..value.len() - 1].replace(&doubled, &quote.to_string());
+        }
+    }
+    value.to_string()
+}
+
+fn parse_bool(value: &str) -> Result<bool> {
+    match value.to_lowercase().as_str() {
+        "true" | "yes" | "on" | "1" => Ok(true),
+        "false" | "no" | "off" | "0" => Ok(false),
+        _ => Err(SQLRiteError::General(format!(
+            "csv: invalid boolean: {}",
+            value
+        ))),
+    }
+}
+
+/// Splits CSV content into records, following RFC 4180: fields are separated by commas,
+/// records by LF or CRLF, and fields in double quotes may contain commas, line breaks
+/// and doubled double quotes.
+fn parse_csv(content: &str) -> Result<Vec<Vec<String>>> {
+    let mut records: Vec<Vec<String>> = vec![];
+    let mut record: Vec<String> = vec![];
+    let mut field = String::new();
+    let mut in_quotes = false;
+    let mut chars = content.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if in_quotes {
+            match c {
+                '"' if chars.peek() == Some(&'"') => {
+                    chars.next();
+                    field.push('"');
+                }
+                '"' => in_quotes = false,
+                c => field.push(c),
+            }
+            continue;
+        }
+        match c {
+            '"' => in_quotes = true,
+            ',' => record.push(std::mem::take(&mut field)),
+            '\r' if chars.peek() == Some(&'\n') => {}
+            '\n' => {
+                record.push(std::mem::take(&mut field));
+                records.push(std::mem::take(&mut record));
+            }
+            c => field.push(c),
+        }
+    }
+
+    if in_quotes {
+        return Err(SQLRiteError::General(
+            "csv: unterminated quoted field".to_string(),
+        ));
+    }
+    // The last record may not end with a line break
+    if !field.is_empty() || !record.is_empty() {
+        record.push(field);
+        records.push(record);
+    }
+    Ok(records)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sql::vtab::scan;
+
+    #[test]
+    fn parse_csv_test() {
+        let records = parse_csv("a,b\r\n\"x, \"\"y\"\"\",\n\"multi\nline\",z").unwrap();
+        assert_eq!(
+            records,
+            vec![
+                vec!["a", "b"],
+                vec!["x, \"y\"", ""],
+                vec!["multi\nline", "z"],
+            ]
+        );
+        assert!(parse_csv("\"open").is_err());
+    }
+
+    #[test]
+    fn csv_table_test() {
+        let path = std::env::temp_dir().join("sqlrite_csv_table_test.csv");
+        fs::write(&path, "level,message\ninfo,started\nerror\n").unwrap();
+
+        let table = CsvModule
+            .create(&[
+                format!("filename='{}'", path.display()),
+                "header=true".to_string(),
+            ])
+            .unwrap();
+        let columns = table
+            .columns()
+            .into_iter()
+            .map(|col| col.name)
+            .collect::<Vec<String>>();
+        assert_eq!(columns, vec!["level", "message"]);
+        assert_eq!(
+            scan(table.as_ref()).unwrap(),
+            vec![
+                vec![
+                    Value::Text("info".to_string()),
+                    Value::Text("started".to_string())
+                ],
+                vec![Value::Text("error".to_string()), Value::Null],
+            ]
+        );
+        fs::remove_file(&path).unwrap();
+
+        let table = CsvModule.create(&["data='1,2'".to_string()]).unwrap();
+        assert_eq!(table.columns()[1].name, "c1");
+        assert_eq!(scan(table.as_ref()).unwrap().len(), 1);
+
+        assert!(CsvModule.create(&["header=true".to_string()]).is_err());
+        assert!(CsvModule
+            .create(&["filename='does/not/exist.csv'".to_string()])
+            .is_err());
+    }
+}
@@ -0,0 +1,749 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::{Arc, Mutex};
+
+use crate::error::{Result, SQLRiteError};
+use crate::sql::db::table::{database_full, DataType};
+use crate::sql::value::Value;
+use crate::sql::vtab::{
+    lock, ConstraintOp, IndexInfo, VirtualColumn, VirtualCursor, VirtualTable, VirtualTableModule,
+};
+
+// Parameters of the BM25 ranking function
+const K1: f64 = 1.2;
+const B: f64 = 0.75;
+
+/// The `fts` module, a full-text index over the text of its columns:
+///
+/// ```sql
+/// CREATE VIRTUAL TABLE docs USING fts(title, body);
+/// INSERT INTO docs (title, body) VALUES ('SQLRite', 'A simple SQLite clone in Rust');
+/// SELECT title FROM docs WHERE docs MATCH 'rust AND sqlite';
+/// ```
+///
+/// Text is split into tokens on every character that isn't a letter or a digit, and
+/// tokens are compared case insensitively. A full-text query is made of:
+/// - terms, like `rust`, and prefix terms, like `sql*`;
+/// - phrases of consecutive terms between double quotes, like `"simple sqlite"`;
+/// - column filters, like `title:rust`;
+/// - the `AND`, `OR` and `NOT` operators, in upper case, and parentheses. Terms written
+///   one after the other are implicitly joined with `AND`.
+///
+/// `table MATCH query` searches every column, `column MATCH query` only that column.
+/// Besides the declared columns, the table has the hidden columns `rank`, the opposite of
+/// the BM25 score of the row in a MATCH query, and `rowid`. Rows matching a query are
+/// returned best match first.
+pub struct FtsModule;
+
+impl VirtualTableModule for FtsModule {
+    fn create(&self, _args: &[String]) -> Result<Arc<dyn VirtualTable>> {
+        Err(SQLRiteError::General(
+            "fts: tables must be created with CREATE VIRTUAL TABLE".to_string(),
+        ))
+    }
+
+    fn create_named(&self, name: &str, args: &[String]) -> Result<Arc<dyn VirtualTable>> {
+        if args.is_empty() {
+            return Err(SQLRiteError::General(
+                "fts: at least one column is required".to_string(),
+            ));
+        }
+        let mut columns: Vec<String> = vec![];
+        for arg in args {
+            let column = arg.trim().trim_matches('"').to_string();
+            if column.is_empty() || column.contains(char::is_whitespace) {
+                return Err(SQLRiteError::General(format!(
+                    "fts: invalid column: {}",
+                    arg
+                )));
+            }
+            let reserved = [name, "rank", "rowid"];
+            if reserved.iter().any(|r| r.eq_ignore_ascii_case(&column))
+                || columns.iter().any(|c| c.eq_ignore_ascii_case(&column))
+            {
+                return Err(SQLRiteError::General(format!(
+                    "fts: reserved or duplicate column name: {}",
+                    column
+                )));
+            }
+            columns.push(column);
+        }
+        Ok(Arc::new(FtsTable {
+            name: name.to_string(),
+            columns,
+            index: Arc::new(Mutex::new(FtsIndex::default())),
+        }))
+    }
+}
+
+/// Splits `text` into lower case tokens made of letters and digits.
+pub fn tokenize(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|token| !token.is_empty())
+        .map(|token| token.to_lowercase())
+        .collect()
+}
+
+struct FtsTable {
+    name: String,
+    columns: Vec<String>,
+    index: Arc<Mutex<FtsIndex>>,
+}
+
+impl FtsTable {
+    // Positions of the hidden columns, after the declared ones, `rank` is in between
+    fn table_column(&self) -> usize {
+        self.columns.len()
+    }
+
+    fn rowid_column(&self) -> usize {
+        self.columns.len() + 2
+    }
+}
+
+impl VirtualTable for FtsTable {
+    fn columns(&self) -> Vec<VirtualColumn> {
+        let mut columns = self
+            .columns
+            .iter()
+            .map(|name| VirtualColumn::new(name, DataType::Text))
+            .collect::<Vec<VirtualColumn>>();
+        columns.push(VirtualColumn::hidden(&self.name, DataType::Text));
+        columns.push(VirtualColumn::hidden("rank", DataType::Real));
+        columns.push(VirtualColumn::hidden("rowid", DataType::Integer));
+        columns
+    }
+
+    // The first MATCH constraint is used, on the table column to search every column or on
+    // one of the declared columns. `idx_num` is 0 for a full scan, the position of the
+    // constrained column plus one otherwise.
+    fn best_index(&self, info: &mut IndexInfo) -> Result<()> {
+        info.estimated_cost = 1_000_000.0;
+        for (i, constraint) in info.constraints.iter().enumerate() {
+            if constraint.op == ConstraintOp::Match && constraint.column <= self.table_column() {
+                info.idx_num = constraint.column as i32 + 1;
+                info.used[i] = true;
+                info.estimated_cost = 1.0;
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    fn open(&self) -> Result<Box<dyn VirtualCursor>> {
+        Ok(Box::new(FtsCursor {
+            columns: self.columns.clone(),
+            index: self.index.clone(),
+            rows: vec![],
+            position: 0,
+        }))
+    }
+
+    fn insert(&self, values: &[Value]) -> Result<i64> {
+        let mut index = lock(&self.index)?;
+        let rowid = match values.get(self.rowid_column()).and_then(Value::to_integer) {
+            Some(rowid) if index.docs.contains_key(&rowid) => {
+                return Err(SQLRiteError::General(format!(
+                    "UNIQUE constraint failed: {}.rowid",
+                    self.name
+                )))
+            }
+            Some(rowid) => rowid,
+            None => index.last_rowid.checked_add(1).ok_or_else(database_full)?,
+        };
+        let doc = (0..self.columns.len())
+            .map(|i| values.get(i).cloned().unwrap_or(Value::Null))
+            .collect();
+        index.insert(rowid, doc);
+        Ok(rowid)
+    }
+
+    fn delete(&self, row: &[Value]) -> Result<()> {
+        let rowid = row
+            .get(self.rowid_column())
+            .and_then(Value::to_integer)
+            .ok_or_else(|| SQLRiteError::General("fts: missing rowid".to_string()))?;
+        lock(&self.index)?.delete(rowid);
+        Ok(())
+    }
+}
+
+/// The documents of an fts table and the inverted index over their tokens
+#[derive(Debug, Default)]
+struct FtsIndex {
+    /// Values of the declared columns of each row, by rowid
+    docs: BTreeMap<i64, Vec<Value>>,
+    /// For each token, the rows it appears in with its (column, position) occurrences
+    postings: BTreeMap<String, BTreeMap<i64, Vec<(usize, usize)>>>,
+    /// Number of tokens of each row
+    lengths: BTreeMap<i64, usize>,
+    total_length: usize,
+    last_rowid: i64,
+}
+
+impl FtsIndex {
+    fn insert(&mut self, rowid: i64, doc: Vec<Value>) {
+        let mut length = 0;
+        for (column, value) in doc.iter().enumerate() {
+            let tokens = tokenize(&value.to_text().unwrap_or_default());
+            length += tokens.len();
+            for (position, token) in tokens.into_iter().enumerate() {
+                self.postings
+                    .entry(token)
+                    .or_default()
+                    .entry(rowid)
+                    .or_default()
+                    .push((column, position));
+            }
+        }
+        self.docs.insert(rowid, doc);
+        self.lengths.insert(rowid, length);
+        self.total_length += length;
+        self.last_rowid = self.last_rowid.max(rowid);
+    }
+
+    fn delete(&mut self, rowid: i64) {
+        let doc = match self.docs.remove(&rowid) {
+            Some(doc) => doc,
+            None => return,
+        };
+        for value in &doc {
+            for token in tokenize(&value.to_text().unwrap_or_default()) {
+                if let Some(rows) = self.postings.get_mut(&token) {
+                    rows.remove(&rowid);
+                    if rows.is_empty() {
+                        self.postings.remove(&token);
+                    }
+                }
+            }
+        }
+        self.total_length -= self.lengths.remove(&rowid).unwrap_or(0);
+    }
+
+    /// The occurrences of `term`, or of every token starting with it if `prefix` is set,
+    /// by rowid.
+    fn occurrences(&self, term: &str, prefix: bool) -> BTreeMap<i64, BTreeSet<(usize, usize)>> {
+        let mut occurrences: BTreeMap<i64, BTreeSet<(usize, usize)>> = BTreeMap::new();
+        let postings = self
+            .postings
+            .range(term.to_string()..)
+            .take_while(|(token, _)| *token == term || (prefix && token.starts_with(term)));
+        for (_, rows) in postings {
+            for (rowid, positions) in rows {
+                occurrences
+                    .entry(*rowid)
+                    .or_default()
+                    .extend(positions.iter().copied());
+            }
+        }
+        occurrences
+    }
+
+    /// Returns the rowids of the rows matching `query`, searching only `column` if set.
+    fn search(&self, query: &Query, column: Option<usize>) -> BTreeSet<i64> {
+        match query {
+            Query::Phrase { terms, prefix } => self.search_phrase(terms, *prefix, column),
+            Query::Column(filter, query) => match column {
+                Some(column) if column != *filter => BTreeSet::new(),
+                _ => self.search(query, Some(*filter)),
+            },
+            Query::And(left, right) => {
+                let left = self.search(left, column);
+                let right = self.search(right, column);
+                left.intersection(&right).copied().collect()
+            }
+            Query::Or(left, right) => {
+                let mut left = self.search(left, column);
+                left.extend(self.search(right, column));
+                left
+            }
+            Query::Not(left, right) => {
+                let left = self.search(left, column);
+                let right = self.search(right, column);
+                left.difference(&right).copied().collect()
+            }
+        }
+    }
+
+    fn search_phrase(
+        &self,
+        terms: &[String],
+        prefix: bool,
+        column: Option<usize>,
+    ) -> BTreeSet<i64> {
+        // An empty phrase matches nothing
+        let occurrences = terms
+            .iter()
+            .enumerate()
+            .map(|(i, term)| self.occurrences(term, prefix && i == terms.len() - 1))
+            .collect::<Vec<BTreeMap<i64, BTreeSet<(usize, usize)>>>>();
+        let first = match occurrences.first() {
+            Some(first) => first,
+            None => return BTreeSet::new(),
+        };
+        first
+            .iter()
+            .filter(|(rowid, positions)| {
+                positions.iter().any(|(col, position)| {
+                    column.is_none_or(|column| column == *col)
+                        && occurrences.iter().enumerate().skip(1).all(|(i, next)| {
+                            next.get(rowid)
+                                .is_some_and(|next| next.contains(&(*col, position + i)))
+                        })
+                })
+            })
+            .map(|(rowid, _)| *rowid)
+            .collect()
+    }
+
+    /// The BM25 score of row `rowid` for `terms`, higher for better matches.
+    /// Uses the `ln(1 + (N - n + 0.5) / (n + 0.5))` IDF, which is never negative.
+    fn bm25(&self, rowid: i64, terms: &[(String, bool)]) -> f64 {
+        let count = self.docs.len() as f64;
+        let average_length = self.total_length as f64 / count.max(1.0);
+        let length = *self.lengths.get(&rowid).unwrap_or(&0) as f64;
+        terms
+            .iter()
+            .map(|(term, prefix)| {
+                let occurrences = self.occurrences(term, *prefix);
+                let frequency = occurrences.get(&rowid).map_or(0, |o| o.len()) as f64;
+                let matching = occurrences.len() as f64;
+                let idf = (1.0 + (count - matching + 0.5) / (matching + 0.5)).ln();
+                let norm = 1.0 - B + B * length / average_length.max(1.0);
+                idf * frequency * (K1 + 1.0) / (frequency + K1 * norm)
+            })
+            .sum()
+    }
+}
+
+/// A parsed full-text query
+#[derive(Debug, PartialEq)]
+enum Query {
+    /// Consecutive terms, the last one being a prefix if `prefix` is set
+    Phrase {
+        terms: Vec<String>,
+        prefix: bool,
+    },
+    /// Restricts the query to the column at this position
+    Column(usize, Box<Query>),
+    And(Box<Query>, Box<Query>),
+    Or(Box<Query>, Box<Query>),
+    /// Rows matching the left side but not the right side
+    Not(Box<Query>, Box<Query>),
+}
+
+impl Query {
+    /// Collects the terms the rows are ranked on, leaving out the ones after NOT.
+    fn terms(&self, terms: &mut Vec<(String, bool)>) {
+        match self {
+            Query::Phrase {
+                terms: phrase,
+                prefix,
+            } => {
+                for (i, term) in phrase.iter().enumerate() {
+                    terms.push((term.to_string(), *prefix && i == phrase.len() - 1));
+                }
+            }
+            Query::Column(_, query) | Query::Not(query, _) => query.terms(terms),
+            Query::And(left, right) | Query::Or(left, right) => {
+                left.terms(terms);
+                right.terms(terms);
+            }
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+enum QueryToken {
+    Word(String),
+    Phrase(String),
+    LParen,
+    RParen,
+    Colon,
+    Star,
+}
+
+/// Parses a full-text query, resolving column filters against `columns`.
+fn parse_query(query: &str, columns: &[String]) -> Result<Query> {
+    let mut parser = QueryParser {
+        tokens: lex_query(query)?,
+        position: 0,
+        columns,
+    };
+    let parsed = parser.parse_or()?;
+    match parser.peek() {
+        None => Ok(parsed),
+        Some(token) => Err(syntax_error(token)),
+    }
+}
+
+fn lex_query(query: &str) -> Result<Vec<QueryToken>> {
+    let mut tokens: Vec<QueryToken> = vec![];
+    let mut chars = query.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => {}
+            '(' => tokens.push(QueryToken::LParen),
+            ')' => tokens.push(QueryToken::RParen),
+            ':' => tokens.push(QueryToken::Colon),
+            '*' => tokens.push(QueryToken::Star),
+            '"' => {
+                // Double quotes are escaped by doubling them
+                let mut phrase = String::new();
+                loop {
+                    match chars.next() {
+                        Some('"') if chars.peek() == Some(&'"') => {
+                            chars.next();
+                            phrase.push('"');
+                        }
+                        Some('"') => break,
+                        Some(c) => phrase.push(c),
+                        None => {
+                            return Err(SQLRiteError::General(
+                                "fts: unterminated string in query".to_string(),
+                            ))
+                        }
+                    }
+                }
+                tokens.push(QueryToken::Phrase(phrase));
+            }
+            c if c.is_alphanumeric() || c == '_' => {
+                let mut word = c.to_string();
+                while let Some(&c) = chars.peek() {
+                    if !c.is_alphanumeric() && c != '_' {
+                        break;
+                    }
+                    word.push(c);
+                    chars.next();
+                }
+                tokens.push(QueryToken::Word(word));
+            }
+            c => {
+                return Err(SQLRiteError::General(format!(
+                    "fts: syntax error near \"{}\"",
+                    c
+                )))
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+fn syntax_error(token: &QueryToken) -> SQLRiteError {
+    let text = match token {
+        QueryToken::Word(word) => word.to_string(),
+        QueryToken::Phrase(phrase) => format!("\"{}\"", phrase),
+        QueryToken::LParen => "(".to_string(),
+        QueryToken::RParen => ")".to_string(),
+        QueryToken::Colon => ":".to_string(),
+        QueryToken::Star => "*".to_string(),
+    };
+    SQLRiteError::General(format!("fts: syntax error near \"{}\"", text))
+}
+
+/// Recursive descent parser for full-text queries, NOT binds tighter than AND, which binds
+/// tighter than OR.
+struct QueryParser<'a> {
+    tokens: Vec<QueryToken>,
+    position: usize,
+    columns: &'a [String],
+}
+
+impl<'a> QueryParser<'a> {
+    fn peek(&self) -> Option<&QueryToken> {
+        self.tokens.get(self.position)
+    }
+
+    fn next(&mut self) -> Option<QueryToken> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+
+    fn peek_operator(&self, operator: &str) -> bool {
+        matches!(self.peek(), Some(QueryToken::Word(word)) if word == operator)
+    }
+
+    fn parse_or(&mut self) -> Result<Query> {
+        let mut query = self.parse_and()?;
+        while self.peek_operator("OR") {
+            self.next();
+            query = Query::Or(Box::new(query), Box::new(self.parse_and()?));
+        }
+        Ok(query)
+    }
+
+    fn parse_and(&mut self) -> Result<Query> {
+        let mut query = self.parse_not()?;
+        loop {
+            if self.peek_operator("AND") {
+                self.next();
+            } else if self.peek_operator("OR")
+                || self.peek_operator("NOT")
+                || !matches!(
+                    self.peek(),
+                    Some(QueryToken::Word(_))
+                        | Some(QueryToken::Phrase(_))
+                        | Some(QueryToken::LParen)
+                )
+            {
+                return Ok(query);
+            }
+            query = Query::And(Box::new(query), Box::new(self.parse_not()?));
+        }
+    }
+
+    fn parse_not(&mut self) -> Result<Query> {
+        let mut query = self.parse_primary()?;
+        while self.peek_operator("NOT") {
+            self.next();
+            query = Query::Not(Box::new(query), Box::new(self.parse_primary()?));
+        }
+        Ok(query)
+    }
+
+    fn parse_primary(&mut self) -> Result<Query> {
+        let text = match self.next() {
+            Some(QueryToken::LParen) => {
+                let query = self.parse_or()?;
+                return match self.next() {
+                    Some(QueryToken::RParen) => Ok(query),
+                    Some(token) => Err(syntax_error(&token)),
+                    None => Err(SQLRiteError::General(
+                        "fts: expected ) in query".to_string(),
+                    )),
+                };
+            }
+            Some(QueryToken::Word(word)) if ["AND", "OR", "NOT"].contains(&word.as_str()) => {
+                return Err(syntax_error(&QueryToken::Word(word)))
+            }
+            Some(QueryToken::Word(word)) => {
+                if self.peek() == Some(&QueryToken::Colon) {
+                    self.next();
+                    let column = self
+                        .columns
+                        .iter()
+                        .position(|column| column.eq_ignore_ascii_case(&word))
+                        .ok_or_else(|| {
+                            SQLRiteError::General(format!("fts: no such column: {}", word))
+                        })?;
+                    return Ok(Query::Column(column, Box::new(self.parse_primary()?)));
+                }
+                word
+            }
+            Some(QueryToken::Phrase(phrase)) => phrase,
+            Some(token) => return Err(syntax_error(&token)),
+            None => return Err(SQLRiteError::General("fts: incomplete query".to_string())),
+        };
+        let prefix = self.peek() == Some(&QueryToken::Star);
+        if prefix {
+            self.next();
+        }
+        Ok(Query::Phrase {
+            terms: tokenize(&text),
+            prefix,
+        })
+    }
+}
+
+struct FtsCursor {
+    columns: Vec<String>,
+    index: Arc<Mutex<FtsIndex>>,
+    rows: Vec<Vec<Value>>,
+    position: usize,
+}
+
+impl VirtualCursor for FtsCursor {
+    fn filter(&mut self, idx_num: i32, args: &[Value]) -> Result<()> {
+        let index = lock(&self.index)?;
+        self.position = 0;
+
+        // Every row in rowid order when there is no MATCH, without a rank
+        let mut ranked: Vec<(i64, Value)> = vec![];
+        if idx_num == 0 {
+            ranked = index
+                .docs
+                .keys()
+                .map(|rowid| (*rowid, Value::Null))
+                .collect();
+        } else if let Some(text) = args.first().and_then(Value::to_text) {
+            let query = parse_query(&text, &self.columns)?;
+            let column = Some(idx_num as usize - 1).filter(|column| *column < self.columns.len());
+            let mut terms: Vec<(String, bool)> = vec![];
+            query.terms(&mut terms);
+            let mut scored = index
+                .search(&query, column)
+                .into_iter()
+                .map(|rowid| (rowid, -index.bm25(rowid, &terms)))
+                .collect::<Vec<(i64, f64)>>();
+            scored.sort_by(|a, b| a.1.total_cmp(&b.1).then(a.0.cmp(&b.0)));
+            ranked = scored
+                .into_iter()
+                .map(|(rowid, rank)| (rowid, Value::Real(rank)))
+                .collect();
+        }
+
+        self.rows = ranked
+            .into_iter()
+            .map(|(rowid, rank)| {
+                let mut row = index.docs[&rowid].clone();
+                row.push(Value::Null);
+                row.push(rank);
+                row.push(Value::Integer(rowid));
+                row
+            })
+            .collect();
+        Ok(())
+    }
+
+    fn next(&mut self) -> Result<()> {
+        self.position += 1;
+        Ok(())
+    }
+
+    fn eof(&self) -> bool {
+        self.position >= self.rows.len()
+    }
+
+    fn column(&self, index: usize) -> Result<Value> {
+        self.rows
+            .get(self.position)
+            .and_then(|row| row.get(index))
+            .cloned()
+            .ok_or_else(|| SQLRiteError::General(format!("No such column: {}", index)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sql::vtab::{scan, scan_with_constraints, IndexConstraint};
+
+    fn docs() -> Arc<dyn VirtualTable> {
+        let table = FtsModule
+            .create_named("docs", &["title".to_string(), "body".to_string()])
+            .unwrap();
+        for (title, body) in [
+            ("SQLRite", "A simple SQLite clone written in Rust"),
+            ("Rust book", "Learning Rust, one chapter at a time"),
+            ("SQLite internals", "How the SQLite B-tree works"),
+        ] {
+            let mut values = vec![Value::Null; 5];
+            values[0] = Value::Text(title.to_string());
+            values[1] = Value::Text(body.to_string());
+            table.insert(&values).unwrap();
+        }
+        table
+    }
+
+    /// The rowids of the rows of `table` matching `query` on the column at `column`
+    fn search(table: &dyn VirtualTable, column: usize, query: &str) -> Result<Vec<i64>> {
+        let constraint = IndexConstraint {
+            column,
+            op: ConstraintOp::Match,
+        };
+        let rows = scan_with_constraints(table, &[(constraint, Value::Text(query.to_string()))])?;
+        Ok(rows
+            .iter()
+            .map(|row| row[4].to_integer().unwrap())
+            .collect())
+    }
+
+    #[test]
+    fn fts_tokenize_test() {
+        assert_eq!(
+            tokenize("Hello, World! it's SQLRite-2"),
+            vec!["hello", "world", "it", "s", "sqlrite", "2"]
+        );
+        assert!(tokenize(" ,; ").is_empty());
+    }
+
+    #[test]
+    fn fts_parse_query_test() {
+        let columns = ["title".to_string(), "body".to_string()];
+        let phrase = |terms: &[&str], prefix| Query::Phrase {
+            terms: terms.iter().map(|t| t.to_string()).collect(),
+            prefix,
+        };
+        assert_eq!(
+            parse_query("rust sql* OR title:\"B-tree\"", &columns).unwrap(),
+            Query::Or(
+                Box::new(Query::And(
+                    Box::new(phrase(&["rust"], false)),
+                    Box::new(phrase(&["sql"], true))
+                )),
+                Box::new(Query::Column(0, Box::new(phrase(&["b", "tree"], false))))
+            )
+        );
+        assert!(parse_query("rust AND", &columns).is_err());
+        assert!(parse_query("(rust", &columns).is_err());
+        assert!(parse_query("author:rust", &columns).is_err());
+        assert!(parse_query("rust - sqlite", &columns).is_err());
+    }
+
+    #[test]
+    fn fts_match_test() {
+        let table = docs();
+        assert_eq!(
+            search(table.as_ref(), 2, "rust AND sqlite").unwrap(),
+            vec![1]
+        );
+        assert_eq!(
+            search(table.as_ref(), 2, "sqlite NOT rust").unwrap(),
+            vec![3]
+        );
+        assert_eq!(
+            search(table.as_ref(), 2, "\"sqlite b tree\"").unwrap(),
+            vec![3]
+        );
+        assert_eq!(
+            search(table.as_ref(), 2, "\"tree sqlite\"").unwrap(),
+            vec![] as Vec<i64>
+        );
+        assert_eq!(search(table.as_ref(), 2, "learn*").unwrap(), vec![2]);
+        assert_eq!(search(table.as_ref(), 2, "title:rust").unwrap(), vec![2]);
+        assert_eq!(search(table.as_ref(), 0, "sqlite").unwrap(), vec![3]);
+        assert_eq!(
+            search(table.as_ref(), 0, "body:clone").unwrap(),
+            vec![] as Vec<i64>
+        );
+
+        // The row mentioning rust the most ranks first
+        assert_eq!(search(table.as_ref(), 2, "rust").unwrap(), vec![2, 1]);
+        let constraint = IndexConstraint {
+            column: 2,
+            op: ConstraintOp::Match,
+        };
+        let rows = scan_with_constraints(
+            table.as_ref(),
+            &[(constraint, Value::Text("rust".to_string()))],
+        )
+        .unwrap();
+        assert!(rows[0][3].to_real().unwrap() < rows[1][3].to_real().unwrap());
+        assert!(rows[1][3].to_real().unwrap() < 0.0);
+    }
+
+    #[test]
+    fn fts_insert_delete_test() {
+        let table = docs();
+        let rows = scan(table.as_ref()).unwrap();
+        assert_eq!(rows.len(), 3);
+        assert_eq!(rows[0][3], Value::Null);
+
+        table.delete(&rows[0]).unwrap();
+        assert_eq!(search(table.as_ref(), 2, "rust").unwrap(), vec![2]);
+        assert!(search(table.as_ref(), 2, "clone").unwrap().is_empty());
+
+        let mut values = vec![Value::Null; 5];
+        values[0] = Value::Text("Rusty".to_string());
+        values[4] = Value::Integer(2);
+        assert!(table.insert(&values).is_err());
+        values[4] = Value::Null;
+        assert_eq!(table.insert(&values).unwrap(), 4);
+        assert_eq!(search(table.as_ref(), 2, "rust*").unwrap(), vec![4, 2]);
+
+        assert!(FtsModule.create_named("docs", &[]).is_err());
+        assert!(FtsModule
+            .create_named("docs", &["rank".to_string()])
+            .is_err());
+    }
+}
@@ -0,0 +1,226 @@
+use std::sync::Arc;
+
+use serde_json::Value as Json;
+
+use crate::error::{Result, SQLRiteError};
+use crate::sql::db::table::DataType;
+use crate::sql::function::json::{lookup, parse_json, parse_path, push_key, to_value, type_name};
+use crate::sql::value::Value;
+use crate::sql::vtab::{
+    ConstraintOp, IndexInfo, VirtualColumn, VirtualCursor, VirtualTable, VirtualTableModule,
+};
+
+// Positions of the hidden columns holding the arguments
+const JSON: usize = 8;
+const ROOT: usize = 9;
+
+/// The `json_each(json[, path])` table-valued function, returning one row for each element
+/// of the array or member of the object found at `path`, `$` by default:
+///
+/// ```sql
+/// SELECT value FROM json_each('{"tags": ["a", "b"]}', '$.tags');
+/// ```
+///
+/// Columns are the same as in SQLite: `key` (array index or object label), `value`, `type`,
+/// `atom` (the value of elements that aren't arrays or objects), `id`, `parent` (always
+/// NULL), `fullkey` (path of the element) and `path` (path of its container).
+/// Unlike SQLite, `id` is the position of the element in its container.
+pub struct JsonEachModule;
+
+impl VirtualTableModule for JsonEachModule {
+    fn create(&self, _args: &[String]) -> Result<Arc<dyn VirtualTable>> {
+        Ok(Arc::new(JsonEachTable))
+    }
+}
+
+struct JsonEachTable;
+
+impl VirtualTable for JsonEachTable {
+    fn columns(&self) -> Vec<VirtualColumn> {
+        vec![
+            VirtualColumn::new("key", DataType::None),
+            VirtualColumn::new("value", DataType::None),
+            VirtualColumn::new("type", DataType::Text),
+            VirtualColumn::new("atom", DataType::None),
+            VirtualColumn::new("id", DataType::Integer),
+            VirtualColumn::new("parent", DataType::Integer),
+            VirtualColumn::new("fullkey", DataType::Text),
+            VirtualColumn::new("path", DataType::Text),
+            VirtualColumn::hidden("json", DataType::Text),
+            VirtualColumn::hidden("root", DataType::Text),
+        ]
+    }
+
+    // Equality constraints on json and root are used, `idx_num` packs the column each
+    // argument of `filter` is for, one bit per argument: 0 for json, 1 for root.
+    fn best_index(&self, info: &mut IndexInfo) -> Result<()> {
+        let mut count = 0;
+        for (i, constraint) in info.constraints.iter().enumerate() {
+            if constraint.op == ConstraintOp::Eq
+                && (constraint.column == JSON || constraint.column == ROOT)
+            {
+                info.idx_num |= ((constraint.column - JSON) as i32) << count;
+                info.used[i] = true;
+                count += 1;
+            }
+        }
+        info.estimated_cost = 1.0;
+        Ok(())
+    }
+
+    fn open(&self) -> Result<Box<dyn VirtualCursor>> {
+        Ok(Box::new(JsonEachCursor::default()))
+    }
+}
+
+#[derive(Default)]
+struct JsonEachCursor {
+    rows: Vec<Vec<Value>>,
+    position: usize,
+}
+
+impl VirtualCursor for JsonEachCursor {
+    fn filter(&mut self, idx_num: i32, args: &[Value]) -> Result<()> {
+        let (mut json, mut root) = (Value::Null, Value::Text("$".to_string()));
+        for (i, arg) in args.iter().enumerate() {
+            if (idx_num >> i) & 1 == 0 {
+                json = arg.clone();
+            } else {
+                root = arg.clone();
+            }
+        }
+        self.rows = vec![];
+        self.position = 0;
+
+        // Like in SQLite, a NULL argument produces no rows
+        let (text, path) = match (json.to_text(), root.to_text()) {
+            (Some(text), Some(path)) => (text, path),
+            _ => return Ok(()),
+        };
+        let document = parse_json(&text)?;
+        let element = match lookup(&document, &parse_path(&path)?) {
+            Some(element) => element,
+            None => return Ok(()),
+        };
+
+        let row = |key: Value, element: &Json, id: usize, fullkey: String| {
+            let atom = match element {
+                Json::Array(_) | Json::Object(_) => Value::Null,
+                element => to_value(element),
+            };
+            vec![
+                key,
+                to_value(element),
+                Value::Text(type_name(element).to_string()),
+                atom,
+                Value::Integer(id as i64),
+                Value::Null,
+                Value::Text(fullkey),
+                Value::Text(path.clone()),
+                json.clone(),
+                root.clone(),
+            ]
+        };
+        match element {
+            Json::Array(array) => {
+                for (i, element) in array.iter().enumerate() {
+                    let fullkey = format!("{}[{}]", path, i);
+                    self.rows
+                        .push(row(Value::Integer(i as i64), element, i, fullkey));
+                }
+            }
+            Json::Object(object) => {
+                for (i, (key, element)) in object.iter().enumerate() {
+                    let mut fullkey = path.clone();
+                    push_key(&mut fullkey, key);
+                    self.rows
+                        .push(row(Value::Text(key.clone()), element, i, fullkey));
+                }
+            }
+            element => self.rows.push(row(Value::Null, element, 0, path.clone())),
+        }
+        Ok(())
+    }
+
+    fn next(&mut self) -> Result<()> {
+        self.position += 1;
+        Ok(())
+    }
+
+    fn eof(&self) -> bool {
+        self.position >= self.rows.len()
+    }
+
+    fn column(&self, index: usize) -> Result<Value> {
+        self.rows
+            .get(self.position)
+            .and_then(|row| row.get(index))
+            .cloned()
+            .ok_or_else(|| SQLRiteError::General(format!("No such column: {}", index)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sql::vtab::scan_with_args;
+
+    fn json_each(args: &[&str]) -> Result<Vec<Vec<Value>>> {
+        let args = args
+            .iter()
+            .map(|arg| Value::Text(arg.to_string()))
+            .collect::<Vec<Value>>();
+        scan_with_args(&JsonEachTable, "json_each", &args)
+    }
+
+    #[test]
+    fn json_each_array_test() {
+        let rows = json_each(&[r#"[1, "two", [3]]"#]).unwrap();
+        assert_eq!(rows.len(), 3);
+        assert_eq!(
+            rows[1][..8],
+            [
+                Value::Integer(1),
+                Value::Text("two".to_string()),
+                Value::Text("text".to_string()),
+                Value::Text("two".to_string()),
+                Value::Integer(1),
+                Value::Null,
+                Value::Text("$[1]".to_string()),
+                Value::Text("$".to_string()),
+            ]
+        );
+        assert_eq!(rows[2][1], Value::Text("[3]".to_string()));
+        assert_eq!(rows[2][3], Value::Null);
+    }
+
+    #[test]
+    fn json_each_object_test() {
+        let rows = json_each(&[
+            r#"{"user": {"name": "josh", "last login": null}}"#,
+            "$.user",
+        ])
+        .unwrap();
+        let keys = rows
+            .iter()
+            .map(|row| (row[0].clone(), row[6].clone()))
+            .collect::<Vec<(Value, Value)>>();
+        assert_eq!(
+            keys,
+            vec![
+                (
+                    Value::Text("name".to_string()),
+                    Value::Text("$.user.name".to_string())
+                ),
+                (
+                    Value::Text("last login".to_string()),
+                    Value::Text("$.user.\"last login\"".to_string())
+                ),
+            ]
+        );
+
+        assert_eq!(json_each(&["5"]).unwrap()[0][0], Value::Null);
+        assert!(json_each(&["[1]", "$.missing"]).unwrap().is_empty());
+        assert!(json_each(&["[1"]).is_err());
+    }
+}
@@ -0,0 +1,515 @@
+pub mod csv;
+pub mod fts;
+pub mod json;
+pub mod rtree;
+pub mod series;
+
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use crate::error::{Result, SQLRiteError};
+use crate::sql::collation::CollationFn;
+use crate::sql::db::table::DataType;
+use crate::sql::value::Value;
+
+/// Schema of each column exposed by a virtual table
+#[derive(Debug, PartialEq, Clone)]
+pub struct VirtualColumn {
+    pub name: String,
+    pub datatype: DataType,
+    /// Hidden columns are left out of `SELECT *`. When the table is used as a table-valued
+    /// function, its arguments are matched in order against the hidden columns.
+    pub hidden: bool,
+}
+
+impl VirtualColumn {
+    pub fn new(name: &str, datatype: DataType) -> Self {
+        VirtualColumn {
+            name: name.to_string(),
+            datatype,
+            hidden: false,
+        }
+    }
+
+    /// Creates a hidden column
+    pub fn hidden(name: &str, datatype: DataType) -> Self {
+        VirtualColumn {
+            hidden: true,
+            ..VirtualColumn::new(name, datatype)
+        }
+    }
+}
+
+/// Comparison operators a virtual table can be asked to filter on
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum ConstraintOp {
+    Eq,
+    Gt,
+    Ge,
+    Lt,
+    Le,
+    /// `column MATCH value`, only meaningful to the virtual table itself
+    Match,
+}
+
+/// Formats the operator as written in SQL
+impl fmt::Display for ConstraintOp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let op = match self {
+            ConstraintOp::Eq => "=",
+            ConstraintOp::Gt => ">",
+            ConstraintOp::Ge => ">=",
+            ConstraintOp::Lt => "<",
+            ConstraintOp::Le => "<=",
+            ConstraintOp::Match => "MATCH",
+        };
+        write!(f, "{}", op)
+    }
+}
+
+/// A `column op value` constraint from the query, offered to `VirtualTable::best_index`
+#[derive(Debug, PartialEq, Clone)]
+pub struct IndexConstraint {
+    /// Position of the constrained column in `VirtualTable::columns`
+    pub column: usize,
+    pub op: ConstraintOp,
+}
+
+impl IndexConstraint {
+    /// Returns true if `column_value op value` holds, comparing text with `collation`.
+    /// Comparisons with NULL never hold, MATCH always does.
+    pub fn test(&self, column_value: &Value, value: &Value, collation: &CollationFn) -> bool {
+        if self.op == ConstraintOp::Match {
+            return true;
+        }
+        if column_value.is_null() || value.is_null() {
+            return false;
+        }
+        let ordering = column_value.compare(value, collation);
+        match self.op {
+            ConstraintOp::Eq => ordering == Ordering::Equal,
+            ConstraintOp::Gt => ordering == Ordering::Greater,
+            ConstraintOp::Ge => ordering != Ordering::Less,
+            ConstraintOp::Lt => ordering == Ordering::Less,
+            ConstraintOp::Le => ordering != Ordering::Greater,
+            ConstraintOp::Match => true,
+        }
+    }
+}
+
+/// Planning information exchanged with `VirtualTable::best_index`.
+///
+/// The engine fills in `constraints`; the virtual table flags in `used` the ones it wants
+/// to receive as arguments of `VirtualCursor::filter`, in that same order, and picks an
+/// `idx_num` identifying the chosen strategy. Comparison constraints are only a hint: the
+/// engine still checks every row it gets back, so a virtual table can safely ignore them.
+/// MATCH constraints can't be checked by the engine, the query fails if they aren't used.
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct IndexInfo {
+    pub constraints: Vec<IndexConstraint>,
+    pub used: Vec<bool>,
+    pub idx_num: i32,
+    pub estimated_cost: f64,
+}
+
+impl IndexInfo {
+    pub fn new(constraints: Vec<IndexConstraint>) -> Self {
+        IndexInfo {
+            used: vec![false; constraints.len()],
+            constraints,
+            idx_num: 0,
+            estimated_cost: 1_000_000.0,
+        }
+    }
+}
+
+/// A module creates virtual tables, it is registered on a connection under a name and
+/// instantiated with `CREATE VIRTUAL TABLE name USING module(args...)`.
+pub trait VirtualTableModule: Send + Sync {
+    /// Creates a virtual table from the arguments given in the USING clause.
+    fn create(&self, args: &[String]) -> Result<Arc<dyn VirtualTable>>;
+
+    /// Creates the virtual table `name` from the arguments given in the USING clause.
+    /// Modules whose tables need to know their own name override this method,
+    /// the default implementation calls `create`.
+    fn create_named(&self, _name: &str, args: &[String]) -> Result<Arc<dyn VirtualTable>> {
+        self.create(args)
+    }
+}
+
+/// A table whose rows come from Rust code instead of SQLRite's storage, queryable with
+/// normal SELECT syntax.
+pub trait VirtualTable: Send + Sync {
+    /// Columns exposed by the table, in order.
+    fn columns(&self) -> Vec<VirtualColumn>;
+
+    /// Chooses how the table is going to be scanned given the constraints of the query.
+    /// The default implementation always performs a full scan.
+    fn best_index(&self, _info: &mut IndexInfo) -> Result<()> {
+        Ok(())
+    }
+
+    /// Opens a new cursor to scan the table.
+    fn open(&self) -> Result<Box<dyn VirtualCursor>>;
+
+    /// Inserts a row holding a value for each column of `columns`, NULL for the ones not
+    /// given, and returns its rowid. The default implementation makes the table read-only.
+    fn insert(&self, _values: &[Value]) -> Result<i64> {
+        Err(SQLRiteError::General(
+            "virtual table may not be modified".to_string(),
+        ))
+    }
+
+    /// Deletes `row`, as read by a cursor of this table.
+    /// The default implementation makes the table read-only.
+    fn delete(&self, _row: &[Value]) -> Result<()> {
+        Err(SQLRiteError::General(
+            "virtual table may not be modified".to_string(),
+        ))
+    }
+}
+
+/// A cursor over the rows of a `VirtualTable`.
+pub trait VirtualCursor {
+    /// Starts a scan with the `idx_num` chosen by `best_index` and the values of the
+    /// constraints it flagged as used. Positions the cursor on the first row.
+    fn filter(&mut self, idx_num: i32, args: &[Value]) -> Result<()>;
+
+    /// Advances the cursor to the next row.
+    fn next(&mut self) -> Result<()>;
+
+    /// Returns true once the cursor moved past the last row.
+    fn eof(&self) -> bool;
+
+    /// Returns the value of column `index` for the current row.
+    fn column(&self, index: usize) -> Result<Value>;
+}
+
+/// Virtual table modules registered on a `Database` and the virtual tables created from them.
+/// Module names are case insensitive.
+#[derive(Clone)]
+pub struct VirtualTableRegistry {
+    modules: HashMap<String, Arc<dyn VirtualTableModule>>,
+    tables: HashMap<String, Arc<dyn VirtualTable>>,
+}
+
+impl VirtualTableRegistry {
+    /// Creates a registry with the built-in modules, which can also be used directly as
+    /// table-valued functions.
+    pub fn new() -> Self {
+        let mut registry = VirtualTableRegistry {
+            modules: HashMap::new(),
+            tables: HashMap::new(),
+        };
+        registry.register_module("csv", Arc::new(csv::CsvModule));
+        registry.register_module("fts", Arc::new(fts::FtsModule));
+        registry.register_module("generate_series", Arc::new(series::GenerateSeriesModule));
+        registry.register_module("json_each", Arc::new(json::JsonEachModule));
+        registry.register_module("rtree", Arc::new(rtree::RtreeModule));
+        registry
+    }
+
+    /// Registers `module` under `name`, replacing any module previously registered with it.
+    pub fn register_module(&mut self, name: &str, module: Arc<dyn VirtualTableModule>) {
+        self.modules.insert(name.to_lowercase(), module);
+    }
+
+    /// Returns true if a module is registered under `name`.
+    pub fn contains_module(&self, name: &str) -> bool {
+        self.modules.contains_key(&name.to_lowercase())
+    }
+
+    /// Returns the module registered under `name`, if any.
+    pub fn get_module(&self, name: &str) -> Option<&Arc<dyn VirtualTableModule>> {
+        self.modules.get(&name.to_lowercase())
+    }
+
+    /// Instantiates the module registered under `module_name` as the virtual table `table_name`.
+    pub fn create_table(
+        &mut self,
+        table_name: &str,
+        module_name: &str,
+        args: &[String],
+    ) -> Result<()> {
+        let module = match self.modules.get(&module_name.to_lowercase()) {
+            Some(module) => module,
+            None => {
+                return Err(SQLRiteError::General(format!(
+                    "no such module: {}",
+                    module_name
+                )))
+            }
+        };
+        // Tables of attached databases are named `schema.table`, modules only see the table
+        let name = table_name
+            .split_once('.')
+            .map_or(table_name, |(_, name)| name);
+        let table = module.create_named(name, args)?;
+        self.tables.insert(table_name.to_string(), table);
+        Ok(())
+    }
+
+    /// Drops the virtual table named `table_name`, returning true if there was one.
+    pub fn drop_table(&mut self, table_name: &str) -> bool {
+        self.tables.remove(table_name).is_some()
+    }
+
+    /// Returns the names of the virtual tables, sorted.
+    pub fn table_names(&self) -> Vec<String> {
+        let mut names = self.tables.keys().cloned().collect::<Vec<String>>();
+        names.sort();
+        names
+    }
+
+    /// Returns true if a virtual table named `table_name` exists.
+    pub fn contains_table(&self, table_name: &str) -> bool {
+        self.tables.contains_key(table_name)
+    }
+
+    /// Returns the virtual table named `table_name`, if any.
+    pub fn get_table(&self, table_name: &str) -> Option<&Arc<dyn VirtualTable>> {
+        self.tables.get(table_name)
+    }
+}
+
+impl Default for VirtualTableRegistry {
+    fn default() -> Self {
+        VirtualTableRegistry::new()
+    }
+}
+
+impl fmt::Debug for VirtualTableRegistry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut modules: Vec<&String> = self.modules.keys().collect();
+        modules.sort();
+        let mut tables: Vec<&String> = self.tables.keys().collect();
+        tables.sort();
+        f.debug_struct("VirtualTableRegistry")
+            .field("modules", &modules)
+            .field("tables", &tables)
+            .finish()
+    }
+}
+
+impl PartialEq for VirtualTableRegistry {
+    // Trait objects can't be compared, two registries are equal when they expose the
+    // same modules and tables
+    fn eq(&self, other: &Self) -> bool {
+        let mut modules: Vec<&String> = self.modules.keys().collect();
+        let mut other_modules: Vec<&String> = other.modules.keys().collect();
+        modules.sort();
+        other_modules.sort();
+        let mut tables: Vec<&String> = self.tables.keys().collect();
+        let mut other_tables: Vec<&String> = other.tables.keys().collect();
+        tables.sort();
+        other_tables.sort();
+        modules == other_modules && tables == other_tables
+    }
+}
+
+/// Locks the state a virtual table shares with its cursors.
+pub(crate) fn lock<T>(mutex: &Mutex<T>) -> Result<MutexGuard<'_, T>> {
+    mutex
+        .lock()
+        .map_err(|_| SQLRiteError::General("virtual table state is poisoned".to_string()))
+}
+
+/// Reads every row of a virtual table, scanning it with the strategy chosen by `best_index`.
+pub fn scan(table: &dyn VirtualTable) -> Result<Vec<Vec<Value>>> {
+    scan_with_args(table, "", &[])
+}
+
+/// Reads the rows of a virtual table used as the table-valued function `name(args...)`.
+/// Each argument constrains the hidden column at the same position to be equal to it.
+pub fn scan_with_args(
+    table: &dyn VirtualTable,
+    name: &str,
+    args: &[Value],
+) -> Result<Vec<Vec<Value>>> {
+    scan_with_constraints(table, &arg_constraints(&table.columns(), name, args)?)
+}
+
+/// Turns the arguments of the table-valued function `name(args...)` into equality
+/// constraints on the hidden columns, in order.
+pub fn arg_constraints(
+    columns: &[VirtualColumn],
+    name: &str,
+    args: &[Value],
+) -> Result<Vec<(IndexConstraint, Value)>> {
+    let hidden = columns
+        .iter()
+        .enumerate()
+        .filter(|(_, col)| col.hidden)
+        .map(|(i, _)| i)
+        .collect::<Vec<usize>>();
+    if args.len() > hidden.len() {
+        return Err(SQLRiteError::General(format!(
+            "too many arguments on {}() - max {}",
+            name,
+            hidden.len()
+        )));
+    }
+
+    Ok(hidden
+        .iter()
+        .zip(args)
+        .map(|(column, arg)| {
+            let constraint = IndexConstraint {
+                column: *column,
+                op: ConstraintOp::Eq,
+            };
+            (constraint, arg.clone())
+        })
+        .collect())
+}
+
+/// Reads the rows of a virtual table satisfying every constraint, each given with the value
+/// it compares the column with.
+pub fn scan_with_constraints(
+    table: &dyn VirtualTable,
+    constraints: &[(IndexConstraint, Value)],
+) -> Result<Vec<Vec<Value>>> {
+    let columns = table.columns();
+    let mut info = IndexInfo::new(
+        constraints
+            .iter()
+            .map(|(constraint, _)| constraint.clone())
+            .collect(),
+    );
+    table.best_index(&mut info)?;
+    if constraints
+        .iter()
+        .zip(&info.used)
+        .any(|((constraint, _), used)| constraint.op == ConstraintOp::Match && !used)
+    {
+        return Err(SQLRiteError::General(
+            "unable to use function MATCH in the requested context".to_string(),
+        ));
+    }
+    let filter_args = constraints
+        .iter()
+        .zip(&info.used)
+        .filter(|(_, used)| **used)
+        .map(|((_, value), _)| value.clone())
+        .collect::<Vec<Value>>();
+
+    let binary = |a: &str, b: &str| a.cmp(b);
+    let mut cursor = table.open()?;
+    cursor.filter(info.idx_num, &filter_args)?;
+    let mut rows: Vec<Vec<Value>> = vec![];
+    while !cursor.eof() {
+        let row = (0..columns.len())
+            .map(|i| cursor.column(i))
+            .collect::<Result<Vec<Value>>>()?;
+        // The table may have ignored some of the constraints
+        if constraints
+            .iter()
+            .all(|(constraint, value)| constraint.test(&row[constraint.column], value, &binary))
+        {
+            rows.push(row);
+        }
+        cursor.next()?;
+    }
+    Ok(rows)
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// Virtual table over a fixed list of (name, age) pairs, shared by the tests of the crate
+    pub struct PeopleTable {
+        pub people: Vec<(String, i64)>,
+    }
+
+    struct PeopleCursor {
+        people: Vec<(String, i64)>,
+        position: usize,
+    }
+
+    pub struct PeopleModule;
+
+    impl VirtualTableModule for PeopleModule {
+        fn create(&self, args: &[String]) -> Result<Arc<dyn VirtualTable>> {
+            let people = args
+                .iter()
+                .enumerate()
+                .map(|(i, name)| (name.to_string(), 20 + i as i64))
+                .collect();
+            Ok(Arc::new(PeopleTable { people }))
+        }
+    }
+
+    impl VirtualTable for PeopleTable {
+        fn columns(&self) -> Vec<VirtualColumn> {
+            vec![
+                VirtualColumn::new("name", DataType::Text),
+                VirtualColumn::new("age", DataType::Integer),
+            ]
+        }
+
+        fn open(&self) -> Result<Box<dyn VirtualCursor>> {
+            Ok(Box::new(PeopleCursor {
+                people: self.people.clone(),
+                position: 0,
+            }))
+        }
+    }
+
+    impl VirtualCursor for PeopleCursor {
+        fn filter(&mut self, _idx_num: i32, _args: &[Value]) -> Result<()> {
+            self.position = 0;
+            Ok(())
+        }
+
+        fn next(&mut self) -> Result<()> {
+            self.position += 1;
+            Ok(())
+        }
+
+        fn eof(&self) -> bool {
+            self.position >= self.people.len()
+        }
+
+        fn column(&self, index: usize) -> Result<Value> {
+            let (name, age) = &self.people[self.position];
+            match index {
+                0 => Ok(Value::Text(name.to_string())),
+                1 => Ok(Value::Integer(*age)),
+                _ => Err(SQLRiteError::General(format!("No such column: {}", index))),
+            }
+        }
+    }
+
+    #[test]
+    fn virtual_table_scan_test() {
+        let table = PeopleTable {
+            people: vec![("josh".to_string(), 30), ("mary".to_string(), 25)],
+        };
+
+        let rows = scan(&table).unwrap();
+        assert_eq!(
+            rows,
+            vec![
+                vec![Value::Text("josh".to_string()), Value::Integer(30)],
+                vec![Value::Text("mary".to_string()), Value::Integer(25)],
+            ]
+        );
+    }
+
+    #[test]
+    fn virtual_table_registry_test() {
+        let mut vtabs = VirtualTableRegistry::new();
+        vtabs.register_module("people", Arc::new(PeopleModule));
+
+        assert!(vtabs.contains_module("PEOPLE"));
+        vtabs
+            .create_table("staff", "people", &["josh".to_string()])
+            .unwrap();
+        assert!(vtabs.contains_table("staff"));
+        assert_eq!(vtabs.get_table("staff").unwrap().columns().len(), 2);
+        assert!(vtabs.create_table("other", "unknown", &[]).is_err());
+    }
+}
@@ -0,0 +1,700 @@
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+
+use crate::error::{Result, SQLRiteError};
+use crate::sql::db::table::DataType;
+use crate::sql::value::Value;
+use crate::sql::vtab::{
+    lock, ConstraintOp, IndexInfo, VirtualColumn, VirtualCursor, VirtualTable, VirtualTableModule,
+};
+
+// Bounds on the number of entries of a node of the tree, except for the root
+const MAX_ENTRIES: usize = 8;
+const MIN_ENTRIES: usize = 3;
+
+// Most constraints handed to `filter`, each one is packed in 6 bits of `idx_num`
+const MAX_ARGS: usize = 5;
+
+/// The `rtree` module, an R-tree index over boxes of 1 to 5 dimensions, answering range and
+/// intersection queries on their coordinates without scanning every row:
+///
+/// ```sql
+/// CREATE VIRTUAL TABLE idx USING rtree(id, minx, maxx, miny, maxy);
+/// INSERT INTO idx VALUES (1, 0.0, 10.0, 0.0, 5.0);
+/// SELECT id FROM idx WHERE minx <= 20.0 AND maxx >= 5.0 AND miny <= 3.0 AND maxy >= 1.0;
+/// ```
+///
+/// The first column is the integer id of the box, given automatically when it is NULL,
+/// followed by the minimum and maximum coordinate of each dimension. Unlike SQLite,
+/// coordinates are stored as 64-bit floats.
+pub struct RtreeModule;
+
+impl VirtualTableModule for RtreeModule {
+    fn create(&self, args: &[String]) -> Result<Arc<dyn VirtualTable>> {
+        if !(3..=11).contains(&args.len()) || args.len() % 2 != 1 {
+            return Err(SQLRiteError::General(
+                "rtree: wrong number of columns, expected an id and 1 to 5 pairs of coordinates"
+                    .to_string(),
+            ));
+        }
+        let mut columns: Vec<String> = vec![];
+        for arg in args {
+            let column = arg.trim().trim_matches('"').to_string();
+            if column.is_empty() || column.contains(char::is_whitespace) {
+                return Err(SQLRiteError::General(format!(
+                    "rtree: invalid column: {}",
+                    arg
+                )));
+            }
+            if columns.iter().any(|c| c.eq_ignore_ascii_case(&column)) {
+                return Err(SQLRiteError::General(format!(
+                    "rtree: duplicate column name: {}",
+                    column
+                )));
+            }
+            columns.push(column);
+        }
+        Ok(Arc::new(RtreeTable {
+            columns,
+            tree: Arc::new(Mutex::new(Rtree::new())),
+        }))
+    }
+}
+
+struct RtreeTable {
+    columns: Vec<String>,
+    tree: Arc<Mutex<Rtree>>,
+}
+
+impl VirtualTable for RtreeTable {
+    fn columns(&self) -> Vec<VirtualColumn> {
+        self.columns
+            .iter()
+            .enumerate()
+            .map(|(i, name)| {
+                let datatype = if i == 0 {
+                    DataType::Integer
+                } else {
+                    DataType::Real
+                };
+                VirtualColumn::new(name, datatype)
+            })
+            .collect()
+    }
+
+    // Every comparison is used, up to `MAX_ARGS` of them. `idx_num` packs the column of each
+    // argument of `filter` in 4 bits, followed by the kind of bound in 2 bits.
+    fn best_index(&self, info: &mut IndexInfo) -> Result<()> {
+        let mut count = 0;
+        for (i, constraint) in info.constraints.iter().enumerate() {
+            let bound = match constraint.op {
+                ConstraintOp::Eq => Bound::Eq,
+                ConstraintOp::Gt | ConstraintOp::Ge => Bound::Lower,
+                ConstraintOp::Lt | ConstraintOp::Le => Bound::Upper,
+                ConstraintOp::Match => continue,
+            };
+            if count == MAX_ARGS {
+                break;
+            }
+            info.idx_num |= ((constraint.column as i32) | ((bound as i32) << 4)) << (6 * count);
+            info.used[i] = true;
+            count += 1;
+        }
+        info.estimated_cost = if count > 0 { 100.0 } else { 1_000_000.0 };
+        Ok(())
+    }
+
+    fn open(&self) -> Result<Box<dyn VirtualCursor>> {
+        Ok(Box::new(RtreeCursor {
+            tree: self.tree.clone(),
+            rows: vec![],
+            position: 0,
+        }))
+    }
+
+    fn insert(&self, values: &[Value]) -> Result<i64> {
+        let mut tree = lock(&self.tree)?;
+        let mut rect: Rect = vec![];
+        for dimension in 0..(self.columns.len() - 1) / 2 {
+            let (min_column, max_column) = (2 * dimension + 1, 2 * dimension + 2);
+            let coordinate = |column: usize| {
+                values.get(column).and_then(Value::to_real).ok_or_else(|| {
+                    SQLRiteError::General(format!("rtree: {} can't be NULL", self.columns[column]))
+                })
+            };
+            let (min, max) = (coordinate(min_column)?, coordinate(max_column)?);
+            if min > max {
+                return Err(SQLRiteError::General(format!(
+                    "rtree constraint failed: ({}<={})",
+                    self.columns[min_column], self.columns[max_column]
+                )));
+            }
+            rect.push((min, max));
+        }
+
+        let id = match values.first().and_then(Value::to_integer) {
+            Some(id) if tree.rects.contains_key(&id) => {
+                return Err(SQLRiteError::General(format!(
+                    "UNIQUE constraint failed: {}",
+                    self.columns[0]
+                )))
+            }
+            Some(id) => id,
+            None => tree.rects.keys().next_back().map_or(1, |id| id + 1),
+        };
+        tree.insert(id, rect);
+        Ok(id)
+    }
+
+    fn delete(&self, row: &[Value]) -> Result<()> {
+        let id = row
+            .first()
+            .and_then(Value::to_integer)
+            .ok_or_else(|| SQLRiteError::General("rtree: missing id".to_string()))?;
+        lock(&self.tree)?.delete(id);
+        Ok(())
+    }
+}
+
+/// The kind of bound a constraint puts on a column
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum Bound {
+    Eq = 0,
+    Lower = 1,
+    Upper = 2,
+}
+
+impl Bound {
+    /// Returns true if a value within `min..=max` can satisfy the bound on `value`.
+    fn overlaps(self, (min, max): (f64, f64), value: f64) -> bool {
+        match self {
+            Bound::Eq => min <= value && value <= max,
+            Bound::Lower => max >= value,
+            Bound::Upper => min <= value,
+        }
+    }
+}
+
+/// A bounding box, the minimum and maximum coordinate of each dimension
+type Rect = Vec<(f64, f64)>;
+
+fn union(a: &[(f64, f64)], b: &[(f64, f64)]) -> Rect {
+    a.iter()
+        .zip(b)
+        .map(|((a_min, a_max), (b_min, b_max))| (a_min.min(*b_min), a_max.max(*b_max)))
+        .collect()
+}
+
+fn area(rect: &[(f64, f64)]) -> f64 {
+    rect.iter().map(|(min, max)| max - min).product()
+}
+
+fn contains(outer: &[(f64, f64)], inner: &[(f64, f64)]) -> bool {
+    outer
+        .iter()
+        .zip(inner)
+        .all(|((o_min, o_max), (i_min, i_max))| o_min <= i_min && i_max <= o_max)
+}
+
+#[derive(Debug)]
+enum Child {
+    Row(i64),
+    Node(Box<Node>),
+}
+
+#[derive(Debug)]
+struct Entry {
+    rect: Rect,
+    child: Child,
+}
+
+/// A node of the tree, the entries of leaves point to rows, the others to nodes
+#[derive(Debug)]
+struct Node {
+    leaf: bool,
+    entries: Vec<Entry>,
+}
+
+impl Node {
+    fn bounds(&self) -> Rect {
+        let mut entries = self.entries.iter();
+        let first = entries.next().map(|e| e.rect.clone()).unwrap_or_default();
+        entries.fold(first, |rect, entry| union(&rect, &entry.rect))
+    }
+
+    /// Inserts a leaf entry under this node, returning the node split off it if it overflowed.
+    fn insert(&mut self, entry: Entry) -> Option<Node> {
+        if self.leaf {
+            self.entries.push(entry);
+        } else {
+            // The child needing the least enlargement to hold the entry, then the smallest
+            let i = (0..self.entries.len())
+                .min_by(|a, b| {
+                    let cost = |i: &usize| {
+                        let rect = &self.entries[*i].rect;
+                        let size = area(rect);
+                        (area(&union(rect, &entry.rect)) - size, size)
+                    };
+                    cost(a)
+                        .partial_cmp(&cost(b))
+                        .unwrap_or(std::cmp::Ordering::Equal)
+                })
+                .unwrap_or(0);
+            if let Child::Node(child) = &mut self.entries[i].child {
+                let split = child.insert(entry);
+                self.entries[i].rect = child.bounds();
+                if let Some(sibling) = split {
+                    self.entries.push(Entry {
+                        rect: sibling.bounds(),
+                        child: Child::Node(Box::new(sibling)),
+                    });
+                }
+            }
+        }
+        if self.entries.len() > MAX_ENTRIES {
+            Some(self.split())
+        } else {
+            None
+        }
+    }
+
+    /// Splits the entries of this node in two groups with Guttman's quadratic algorithm,
+    /// keeping the first group and returning a new node holding the second.
+    fn split(&mut self) -> Node {
+        let mut remaining = std::mem::take(&mut self.entries);
+
+        // The seeds are the two entries that would waste the most space together
+        let mut seeds = (0, 1);
+        let mut worst = f64::NEG_INFINITY;
+        for i in 0..remaining.len() {
+            for j in i + 1..remaining.len() {
+                let (a, b) = (&remaining[i].rect, &remaining[j].rect);
+                let waste = area(&union(a, b)) - area(a) - area(b);
+                if waste > worst {
+                    worst = waste;
+                    seeds = (i, j);
+                }
+            }
+        }
+        let second = remaining.remove(seeds.1);
+        let first = remaining.remove(seeds.0);
+        let (mut rect_a, mut rect_b) = (first.rect.clone(), second.rect.clone());
+        let (mut group_a, mut group_b) = (vec![first], vec![second]);
+
+        while !remaining.is_empty() {
+            // A group that needs every remaining entry to reach the minimum gets them all
+            if group_a.len() + remaining.len() == MIN_ENTRIES {
+                group_a.append(&mut remaining);
+                break;
+            }
+            if group_b.len() + remaining.len() == MIN_ENTRIES {
+                group_b.append(&mut remaining);
+                break;
+            }
+            // The entry with the strongest preference for one of the groups goes next
+            let growth = |entry: &Entry| {
+                (
+                    area(&union(&rect_a, &entry.rect)) - area(&rect_a),
+                    area(&union(&rect_b, &entry.rect)) - area(&rect_b),
+                )
+            };
+            let mut next = 0;
+            let mut preference = f64::NEG_INFINITY;
+            for (i, entry) in remaining.iter().enumerate() {
+                let (a, b) = growth(entry);
+                if (a - b).abs() > preference {
+                    preference = (a - b).abs();
+                    next = i;
+                }
+            }
+            let entry = remaining.remove(next);
+            let (a, b) = growth(&entry);
+            let to_a = if a != b {
+                a < b
+            } else if area(&rect_a) != area(&rect_b) {
+                area(&rect_a) < area(&rect_b)
+            } else {
+                group_a.len() <= group_b.len()
+            };
+            if to_a {
+                rect_a = union(&rect_a, &entry.rect);
+                group_a.push(entry);
+            } else {
+                rect_b = union(&rect_b, &entry.rect);
+                group_b.push(entry);
+            }
+        }
+
+        self.entries = group_a;
+        Node {
+            leaf: self.leaf,
+            entries: group_b,
+        }
+    }
+
+    /// Removes the row `id` with bounding box `rect` from under this node. The entries of
+    /// the nodes left with too few entries are moved to `orphans`, to be inserted again.
+    fn remove(&mut self, id: i64, rect: &[(f64, f64)], orphans: &mut Vec<Entry>) -> bool {
+        if self.leaf {
+            return match self
+                .entries
+                .iter()
+                .position(|entry| matches!(entry.child, Child::Row(row) if row == id))
+            {
+                Some(i) => {
+                    self.entries.remove(i);
+                    true
+                }
+                None => false,
+            };
+        }
+        for i in 0..self.entries.len() {
+            if !contains(&self.entries[i].rect, rect) {
+                continue;
+            }
+            if let Child::Node(child) = &mut self.entries[i].child {
+                if child.remove(id, rect, orphans) {
+                    if child.entries.len() < MIN_ENTRIES {
+                        let entry = self.entries.remove(i);
+                        if let Child::Node(child) = entry.child {
+                            child.leaf_entries(orphans);
+                        }
+                    } else {
+                        self.entries[i].rect = child.bounds();
+                    }
+                    return true;
+                }
+            }
+        }
+        false
+    }
+
+    /// Moves every leaf entry under this node to `entries`.
+    fn leaf_entries(self, entries: &mut Vec<Entry>) {
+        if self.leaf {
+            entries.extend(self.entries);
+            return;
+        }
+        for entry in self.entries {
+            if let Child::Node(child) = entry.child {
+                child.leaf_entries(entries);
+            }
+        }
+    }
+
+    /// Collects the ids of the rows whose boxes can satisfy every bound.
+    fn search(&self, bounds: &[(usize, Bound, f64)], ids: &mut Vec<i64>) {
+        for entry in &self.entries {
+            let overlaps = bounds.iter().all(|(column, bound, value)| {
+                let (min, max) = entry.rect[(column - 1) / 2];
+                if self.leaf {
+                    // Rows are checked against the coordinate of the column itself
+                    let coordinate = if column % 2 == 1 { min } else { max };
+                    bound.overlaps((coordinate, coordinate), *value)
+                } else {
+                    bound.overlaps((min, max), *value)
+                }
+            });
+            if overlaps {
+                match &entry.child {
+                    Child::Row(id) => ids.push(*id),
+                    Child::Node(child) => child.search(bounds, ids),
+                }
+            }
+        }
+    }
+}
+
+/// An R-tree, along with the bounding box of each row by id
+#[derive(Debug)]
+struct Rtree {
+    root: Node,
+    rects: BTreeMap<i64, Rect>,
+}
+
+impl Rtree {
+    fn new() -> Self {
+        Rtree {
+            root: Node {
+                leaf: true,
+                entries: vec![],
+            },
+            rects: BTreeMap::new(),
+        }
+    }
+
+    fn insert(&mut self, id: i64, rect: Rect) {
+        self.rects.insert(id, rect.clone());
+        self.insert_entry(Entry {
+            rect,
+            child: Child::Row(id),
+        });
+    }
+
+    fn insert_entry(&mut self, entry: Entry) {
+        if let Some(sibling) = self.root.insert(entry) {
+            // The root was split, the tree grows by one level
+            let root = std::mem::replace(
+                &mut self.root,
+                Node {
+                    leaf: false,
+                    entries: vec![],
+                },
+            );
+            self.root.entries = vec![
+                Entry {
+                    rect: root.bounds(),
+                    child: Child::Node(Box::new(root)),
+                },
+                Entry {
+                    rect: sibling.bounds(),
+                    child: Child::Node(Box::new(sibling)),
+                },
+            ];
+        }
+    }
+
+    fn delete(&mut self, id: i64) {
+        let rect = match self.rects.remove(&id) {
+            Some(rect) => rect,
+            None => return,
+        };
+        let mut orphans: Vec<Entry> = vec![];
+        self.root.remove(id, &rect, &mut orphans);
+
+        // A root left with a single child is replaced by it
+        while !self.root.leaf && self.root.entries.len() == 1 {
+            if let Some(Entry {
+                child: Child::Node(child),
+                ..
+            }) = self.root.entries.pop()
+            {
+                self.root = *child;
+            }
+        }
+        if self.root.entries.is_empty() {
+            self.root.leaf = true;
+        }
+        for entry in orphans {
+            self.insert_entry(entry);
+        }
+    }
+
+    /// Returns the ids of the rows whose boxes can satisfy every bound, in id order.
+    /// An equality bound on the id column is answered without searching the tree.
+    fn search(&self, bounds: &[(usize, Bound, f64)]) -> Vec<i64> {
+        let (id_bounds, coordinate_bounds): (Vec<_>, Vec<_>) = bounds
+            .iter()
+            .copied()
+            .partition(|(column, _, _)| *column == 0);
+        let mut ids = match id_bounds.iter().find(|(_, bound, _)| *bound == Bound::Eq) {
+            Some((_, _, id)) if self.rects.contains_key(&(*id as i64)) => vec![*id as i64],
+            Some(_) => vec![],
+            None => {
+                let mut ids = vec![];
+                self.root.search(&coordinate_bounds, &mut ids);
+                ids.sort_unstable();
+                return ids;
+            }
+        };
+        ids.retain(|id| {
+            let rect = &self.rects[id];
+            coordinate_bounds.iter().all(|(column, bound, value)| {
+                let (min, max) = rect[(column - 1) / 2];
+                let coordinate = if column % 2 == 1 { min } else { max };
+                bound.overlaps((coordinate, coordinate), *value)
+            })
+        });
+        ids
+    }
+}
+
+struct RtreeCursor {
+    tree: Arc<Mutex<Rtree>>,
+    rows: Vec<Vec<Value>>,
+    position: usize,
+}
+
+impl VirtualCursor for RtreeCursor {
+    fn filter(&mut self, idx_num: i32, args: &[Value]) -> Result<()> {
+        let tree = lock(&self.tree)?;
+        self.position = 0;
+        self.rows = vec![];
+
+        let mut bounds: Vec<(usize, Bound, f64)> = vec![];
+        for (i, arg) in args.iter().enumerate() {
+            let code = idx_num >> (6 * i);
+            let bound = match (code >> 4) & 3 {
+                0 => Bound::Eq,
+                1 => Bound::Lower,
+                _ => Bound::Upper,
+            };
+            // Comparisons with NULL never hold
+            match arg.to_real() {
+                Some(value) => bounds.push(((code & 15) as usize, bound, value)),
+                None => return Ok(()),
+            }
+        }
+
+        for id in tree.search(&bounds) {
+            let mut row = vec![Value::Integer(id)];
+            for (min, max) in &tree.rects[&id] {
+                row.push(Value::Real(*min));
+                row.push(Value::Real(*max));
+            }
+            self.rows.push(row);
+        }
+        Ok(())
+    }
+
+    fn next(&mut self) -> Result<()> {
+        self.position += 1;
+        Ok(())
+    }
+
+    fn eof(&self) -> bool {
+        self.position >= self.rows.len()
+    }
+
+    fn column(&self, index: usize) -> Result<Value> {
+        self.rows
+            .get(self.position)
+            .and_then(|row| row.get(index))
+            .cloned()
+            .ok_or_else(|| SQLRiteError::General(format!("No such column: {}", index)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sql::db::database::Database;
+    use crate::sql::{process_command, process_query};
+
+    /// Checks the invariants of the tree: every node but the root holds between
+    /// `MIN_ENTRIES` and `MAX_ENTRIES` entries, every leaf is at the same depth and the
+    /// box of each entry is the bounding box of its child. Returns the depth of the leaves.
+    fn check(node: &Node, root: bool) -> usize {
+        assert!(node.entries.len() <= MAX_ENTRIES);
+        assert!(root || node.entries.len() >= MIN_ENTRIES);
+        if node.leaf {
+            return 1;
+        }
+        let depths = node
+            .entries
+            .iter()
+            .map(|entry| match &entry.child {
+                Child::Node(child) => {
+                    assert_eq!(entry.rect, child.bounds());
+                    check(child, false)
+                }
+                Child::Row(_) => panic!("row in an internal node"),
+            })
+            .collect::<Vec<usize>>();
+        assert!(depths.windows(2).all(|w| w[0] == w[1]));
+        depths[0] + 1
+    }
+
+    #[test]
+    fn rtree_insert_delete_search_test() {
+        let mut tree = Rtree::new();
+        let rect = |i: i64| {
+            let (x, y) = ((i * 37 % 101) as f64, (i * 53 % 97) as f64);
+            vec![(x, x + (i % 7) as f64), (y, y + (i % 5) as f64)]
+        };
+        for i in 1..=300 {
+            tree.insert(i, rect(i));
+        }
+        assert!(check(&tree.root, true) > 2);
+
+        // Boxes intersecting [20, 40] x [10, 30]
+        let bounds = [
+            (1, Bound::Upper, 40.0),
+            (2, Bound::Lower, 20.0),
+            (3, Bound::Upper, 30.0),
+            (4, Bound::Lower, 10.0),
+        ];
+        let brute_force = |tree: &Rtree| {
+            tree.rects
+                .iter()
+                .filter(|(_, r)| {
+                    r[0].0 <= 40.0 && r[0].1 >= 20.0 && r[1].0 <= 30.0 && r[1].1 >= 10.0
+                })
+                .map(|(id, _)| *id)
+                .collect::<Vec<i64>>()
+        };
+        let expected = brute_force(&tree);
+        assert!(!expected.is_empty());
+        assert_eq!(tree.search(&bounds), expected);
+
+        for i in (1..=300).filter(|i| i % 3 != 0) {
+            tree.delete(i);
+        }
+        check(&tree.root, true);
+        assert_eq!(tree.rects.len(), 100);
+        assert_eq!(tree.search(&bounds), brute_force(&tree));
+        assert_eq!(tree.search(&[(0, Bound::Eq, 3.0)]), vec![3]);
+        assert!(tree.search(&[(0, Bound::Eq, 4.0)]).is_empty());
+
+        for i in (1..=300).filter(|i| i % 3 == 0) {
+            tree.delete(i);
+        }
+        assert!(tree.root.leaf && tree.root.entries.is_empty());
+    }
+
+    #[test]
+    fn rtree_sql_test() {
+        let mut db = Database::new("tempdb".to_string());
+        process_command(
+            "CREATE VIRTUAL TABLE idx USING rtree(id, minx, maxx, miny, maxy);",
+            &mut db,
+        )
+        .unwrap();
+        process_command(
+            "INSERT INTO idx VALUES (1, 0, 10, 0, 10), (2, 5, 15, -5, 5), (3, 20, 30, 20, 30);",
+            &mut db,
+        )
+        .unwrap();
+        process_command(
+            "INSERT INTO idx (minx, maxx, miny, maxy) VALUES (-1, 1, -1, 1);",
+            &mut db,
+        )
+        .unwrap();
+        assert_eq!(db.counters.last_insert_rowid, 4);
+
+        let ids = |db: &Database, sql: &str| {
+            process_query(sql, db)
+                .unwrap()
+                .rows
+                .into_iter()
+                .map(|row| row[0].clone())
+                .collect::<Vec<Value>>()
+        };
+        assert_eq!(
+            ids(
+                &db,
+                "SELECT id FROM idx WHERE minx <= 12 AND maxx >= 8 AND miny <= 1 AND maxy >= 0;"
+            ),
+            vec![Value::Integer(1), Value::Integer(2)]
+        );
+        assert_eq!(
+            ids(&db, "SELECT id FROM idx WHERE maxx < 10;"),
+            vec![Value::Integer(4)]
+        );
+        assert_eq!(
+            ids(&db, "SELECT maxy FROM idx WHERE id = 3;"),
+            vec![Value::Real(30.0)]
+        );
+
+        process_command("DELETE FROM idx WHERE minx >= 5;", &mut db).unwrap();
+        assert_eq!(
+            ids(&db, "SELECT id FROM idx;"),
+            vec![Value::Integer(1), Value::Integer(4)]
+        );
+        assert!(process_command("INSERT INTO idx VALUES (1, 0, 1, 0, 1);", &mut db).is_err());
+        assert!(process_command("INSERT INTO idx VALUES (5, 2, 1, 0, 1);", &mut db).is_err());
+        assert!(
+            process_command("CREATE VIRTUAL TABLE bad USING rtree(id, minx);", &mut db).is_err()
+        );
+    }
+}
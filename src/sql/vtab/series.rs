@@ -0,0 +1,213 @@
+use std::sync::Arc;
+
+use crate::error::{Result, SQLRiteError};
+use crate::sql::db::table::DataType;
+use crate::sql::value::Value;
+use crate::sql::vtab::{
+    ConstraintOp, IndexInfo, VirtualColumn, VirtualCursor, VirtualTable, VirtualTableModule,
+};
+
+// Positions of the hidden columns holding the arguments
+const START: usize = 1;
+const STOP: usize = 2;
+const STEP: usize = 3;
+
+/// The `generate_series(start, stop, step)` table-valued function, returning the integers
+/// from `start` to `stop` in increments of `step`, 1 by default, in a column named `value`.
+/// A negative `step` returns the same values in descending order.
+///
+/// ```sql
+/// SELECT value FROM generate_series(1, 10, 3);
+/// ```
+pub struct GenerateSeriesModule;
+
+impl VirtualTableModule for GenerateSeriesModule {
+    fn create(&self, _args: &[String]) -> Result<Arc<dyn VirtualTable>> {
+        Ok(Arc::new(GenerateSeriesTable))
+    }
+}
+
+struct GenerateSeriesTable;
+
+impl VirtualTable for GenerateSeriesTable {
+    fn columns(&self) -> Vec<VirtualColumn> {
+        vec![
+            VirtualColumn::new("value", DataType::Integer),
+            VirtualColumn::hidden("start", DataType::Integer),
+            VirtualColumn::hidden("stop", DataType::Integer),
+            VirtualColumn::hidden("step", DataType::Integer),
+        ]
+    }
+
+    // Every equality constraint on start, stop and step is used, `idx_num` packs the
+    // column each argument of `filter` is for, two bits per argument.
+    fn best_index(&self, info: &mut IndexInfo) -> Result<()> {
+        let mut count = 0;
+        for (i, constraint) in info.constraints.iter().enumerate() {
+            if constraint.op == ConstraintOp::Eq && constraint.column >= START {
+                info.idx_num |= (constraint.column as i32) << (2 * count);
+                info.used[i] = true;
+                count += 1;
+            }
+        }
+        info.estimated_cost = 1.0;
+        Ok(())
+    }
+
+    fn open(&self) -> Result<Box<dyn VirtualCursor>> {
+        Ok(Box::new(GenerateSeriesCursor::default()))
+    }
+}
+
+#[derive(Default)]
+struct GenerateSeriesCursor {
+    start: i64,
+    stop: i64,
+    step: i64,
+    /// `step` as given, 0 is used as 1
+    step_arg: i64,
+    /// Current value, `None` once the series is exhausted
+    value: Option<i64>,
+}
+
+impl GenerateSeriesCursor {
+    /// Returns true if `value` is still within the bounds of the series.
+    fn in_bounds(&self, value: i64) -> bool {
+        value >= self.start && value <= self.stop
+    }
+}
+
+impl VirtualCursor for GenerateSeriesCursor {
+    fn filter(&mut self, idx_num: i32, args: &[Value]) -> Result<()> {
+        let (mut start, mut stop, mut step) = (None, None, None);
+        for (i, arg) in args.iter().enumerate() {
+            let value = match arg {
+                Value::Integer(v) => Some(*v),
+                Value::Real(v) => Some(*v as i64),
+                Value::Bool(v) => Some(i64::from(*v)),
+                Value::Null => None,
+                Value::Text(_) | Value::Blob(_) => {
+                    return Err(SQLRiteError::General(
+                        "generate_series() arguments must be integers".to_string(),
+                    ))
+                }
+            };
+            match (idx_num >> (2 * i)) & 3 {
+                1 => start = Some(value),
+                2 => stop = Some(value),
+                _ => step = Some(value),
+            }
+        }
+
+        let (start, stop) = match (start, stop) {
+            (Some(start), Some(stop)) => (start, stop),
+            _ => {
+                return Err(SQLRiteError::General(
+                    "generate_series() requires a start and a stop argument".to_string(),
+                ))
+            }
+        };
+        // Like in SQLite, a NULL argument produces an empty series
+        let (start, stop, step) = match (start, stop, step.unwrap_or(Some(1))) {
+            (Some(start), Some(stop), Some(step)) => (start, stop, step),
+            _ => {
+                self.value = None;
+                return Ok(());
+            }
+        };
+
+        self.start = start;
+        self.stop = stop;
+        self.step_arg = step;
+        self.step = if step == 0 { 1 } else { step };
+        self.value = if start > stop {
+            None
+        } else if self.step > 0 {
+            Some(start)
+        } else {
+            // Descending series start from the last value reached counting up from start
+            let span = (i128::from(stop) - i128::from(start)) as u128;
+            let span = span - span % u128::from(self.step.unsigned_abs());
+            Some((i128::from(start) + span as i128) as i64)
+        };
+        Ok(())
+    }
+
+    fn next(&mut self) -> Result<()> {
+        self.value = self
+            .value
+            .and_then(|value| value.checked_add(self.step))
+            .filter(|value| self.in_bounds(*value));
+        Ok(())
+    }
+
+    fn eof(&self) -> bool {
+        self.value.is_none()
+    }
+
+    fn column(&self, index: usize) -> Result<Value> {
+        match index {
+            0 => Ok(self.value.map_or(Value::Null, Value::Integer)),
+            START => Ok(Value::Integer(self.start)),
+            STOP => Ok(Value::Integer(self.stop)),
+            STEP => Ok(Value::Integer(self.step_arg)),
+            _ => Err(SQLRiteError::General(format!("No such column: {}", index))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sql::vtab::scan_with_args;
+
+    fn series(args: &[Value]) -> Result<Vec<i64>> {
+        let rows = scan_with_args(&GenerateSeriesTable, "generate_series", args)?;
+        Ok(rows
+            .into_iter()
+            .map(|row| match row[0] {
+                Value::Integer(v) => v,
+                _ => panic!("expected an integer"),
+            })
+            .collect())
+    }
+
+    #[test]
+    fn generate_series_test() {
+        assert_eq!(
+            series(&[Value::Integer(1), Value::Integer(5)]).unwrap(),
+            vec![1, 2, 3, 4, 5]
+        );
+        assert_eq!(
+            series(&[Value::Integer(0), Value::Integer(10), Value::Integer(4)]).unwrap(),
+            vec![0, 4, 8]
+        );
+        assert_eq!(
+            series(&[Value::Integer(0), Value::Integer(10), Value::Integer(-4)]).unwrap(),
+            vec![8, 4, 0]
+        );
+        assert_eq!(
+            series(&[Value::Integer(1), Value::Integer(3), Value::Integer(0)]).unwrap(),
+            vec![1, 2, 3]
+        );
+        assert!(series(&[Value::Integer(5), Value::Integer(1)])
+            .unwrap()
+            .is_empty());
+        assert!(series(&[Value::Null, Value::Integer(1)])
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn generate_series_invalid_arguments_test() {
+        assert!(series(&[Value::Integer(1)]).is_err());
+        assert!(series(&[Value::Text("a".to_string()), Value::Integer(1)]).is_err());
+        assert!(series(&[
+            Value::Integer(1),
+            Value::Integer(2),
+            Value::Integer(1),
+            Value::Integer(1)
+        ])
+        .is_err());
+    }
+}
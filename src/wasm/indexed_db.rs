@@ -0,0 +1,110 @@
+//! Databases kept in the IndexedDB of the browser, each the bytes of a database file stored
+//! under its name in the `databases` object store of the `sqlrite` IndexedDB database.
+use js_sys::{Function, Promise, Reflect, Uint8Array};
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+use wasm_bindgen_futures::JsFuture;
+use web_sys::{IdbDatabase, IdbFactory, IdbRequest, IdbTransaction, IdbTransactionMode};
+
+/// Name of the IndexedDB database the databases are stored in
+const DATABASE_NAME: &str = "sqlrite";
+/// Version of the IndexedDB database, raised when its object stores change
+const DATABASE_VERSION: u32 = 1;
+/// Object store holding the bytes of each database by name
+const STORE_NAME: &str = "databases";
+
+/// Reads the bytes of the database `name`, `None` if none was stored under that name.
+pub async fn load(name: &str) -> Result<Option<Vec<u8>>, JsValue> {
+    let db = open().await?;
+    let store = db
+        .transaction_with_str(STORE_NAME)?
+        .object_store(STORE_NAME)?;
+    let value = wait(&store.get(&JsValue::from_str(name))?).await;
+    db.close();
+    let value = value?;
+    Ok(match value.is_undefined() {
+        true => None,
+        false => Some(Uint8Array::new(&value).to_vec()),
+    })
+}
+
+/// Stores `bytes` as the database `name`, replacing what was stored under that name once
+/// the IndexedDB transaction writing them commits.
+pub async fn store(name: &str, bytes: &[u8]) -> Result<(), JsValue> {
+    let db = open().await?;
+    let transaction =
+        db.transaction_with_str_and_mode(STORE_NAME, IdbTransactionMode::Readwrite)?;
+    let result = async {
+        let store = transaction.object_store(STORE_NAME)?;
+        let request = store.put_with_key(&Uint8Array::from(bytes), &JsValue::from_str(name))?;
+        wait(&request).await?;
+        committed(&transaction).await
+    }
+    .await;
+    db.close();
+    result
+}
+
+/// Opens the IndexedDB database of the databases, creating its object store the first time.
+async fn open() -> Result<IdbDatabase, JsValue> {
+    // From the global scope, for workers to open it too
+    let factory = Reflect::get(&js_sys::global(), &JsValue::from_str("indexedDB"))?;
+    if factory.is_undefined() {
+        return Err(js_sys::Error::new("IndexedDB isn't available").into());
+    }
+    let factory: IdbFactory = factory.dyn_into()?;
+    let request = factory.open_with_u32(DATABASE_NAME, DATABASE_VERSION)?;
+    let opening = request.clone();
+    let upgrade = Closure::<dyn FnMut()>::new(move || {
+        if let Ok(db) = opening.result() {
+            let db: IdbDatabase = db.unchecked_into();
+            if !db.object_store_names().contains(STORE_NAME) {
+                // An error aborts the upgrade, which fails the request
+                let _ = db.create_object_store(STORE_NAME);
+            }
+        }
+    });
+    request.set_onupgradeneeded(Some(upgrade.as_ref().unchecked_ref()));
+    let db = wait(&request).await;
+    request.set_onupgradeneeded(None);
+    Ok(db?.unchecked_into())
+}
+
+/// Waits for `request` to succeed, returning its result.
+async fn wait(request: &IdbRequest) -> Result<JsValue, JsValue> {
+    let promise = Promise::new(&mut |resolve: Function, reject: Function| {
+        request.set_onsuccess(Some(&resolve));
+        request.set_onerror(Some(&reject));
+    });
+    let done = JsFuture::from(promise).await;
+    request.set_onsuccess(None);
+    request.set_onerror(None);
+    match done {
+        Ok(_) => request.result(),
+        Err(_) => Err(request_error(request.error().ok().flatten())),
+    }
+}
+
+/// Waits for `transaction` to commit.
+async fn committed(transaction: &IdbTransaction) -> Result<(), JsValue> {
+    let promise = Promise::new(&mut |resolve: Function, reject: Function| {
+        transaction.set_oncomplete(Some(&resolve));
+        transaction.set_onerror(Some(&reject));
+        transaction.set_onabort(Some(&reject));
+    });
+    let done = JsFuture::from(promise).await;
+    transaction.set_oncomplete(None);
+    transaction.set_onerror(None);
+    transaction.set_onabort(None);
+    match done {
+        Ok(_) => Ok(()),
+        Err(_) => Err(request_error(transaction.error())),
+    }
+}
+
+fn request_error(error: Option<web_sys::DomException>) -> JsValue {
+    let message = error.map_or("IndexedDB request failed".to_string(), |error| {
+        format!("IndexedDB request failed: {}", error.message())
+    });
+    js_sys::Error::new(&message).into()
+}
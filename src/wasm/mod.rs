@@ -0,0 +1,167 @@
+//! JavaScript bindings for the WebAssembly build, available with the `wasm` feature.
+//!
+//! Built with `wasm-pack build --target web -- --no-default-features --features wasm`,
+//! the engine can be used from a browser:
+//!
+//! ```js
+//! import init, { Database } from "./pkg/sqlrite.js";
+//!
+//! await init();
+//! const db = new Database();
+//! db.execute("CREATE TABLE users (id INTEGER PRIMARY KEY, name TEXT);");
+//! db.execute("INSERT INTO users (name) VALUES ('josh');"); // { message: ..., rowsAffected: 1, lastInsertRowid: 1 }
+//! const rows = db.query("SELECT * FROM users;"); // [{ id: 1, name: "josh" }]
+//! ```
+//!
+//! Statements work on the database in memory, like natively. A database opened with
+//! `Database.openIndexedDb(name)` is read from the IndexedDB of the browser, and written
+//! back there by `persist()`, in the format of a database file:
+//!
+//! ```js
+//! const db = await Database.openIndexedDb("playground");
+//! db.execute("INSERT INTO users (name) VALUES ('ana');");
+//! await db.persist();
+//! ```
+//!
+//! The other databases only live as long as the page that opened them.
+mod indexed_db;
+
+use js_sys::{Array, Object, Promise, Reflect};
+use wasm_bindgen::prelude::*;
+use wasm_bindgen_futures::future_to_promise;
+
+use crate::connection::Connection;
+use crate::error::SQLRiteError;
+use crate::sql::db::file::read_image;
+use crate::sql::value::Value;
+
+/// A connection to an in-memory database, exported to JavaScript as `Database`.
+#[wasm_bindgen(js_name = Database)]
+pub struct WasmDatabase {
+    conn: Connection,
+    /// Name the database is stored under in IndexedDB, `None` for a transient database
+    name: Option<String>,
+}
+
+#[wasm_bindgen(js_class = Database)]
+impl WasmDatabase {
+    /// Opens a new in-memory database.
+    #[wasm_bindgen(constructor)]
+    pub fn open() -> WasmDatabase {
+        WasmDatabase {
+            conn: Connection::open_in_memory(),
+            name: None,
+        }
+    }
+
+    /// Opens the database stored under `name` in IndexedDB, a new empty one if none is,
+    /// which `persist` stores there.
+    #[wasm_bindgen(js_name = openIndexedDb)]
+    pub async fn open_indexed_db(name: String) -> Result<WasmDatabase, JsValue> {
+        let mut conn = Connection::open_in_memory();
+        if let Some(bytes) = indexed_db::load(&name).await? {
+            let db = read_image(&bytes).map_err(to_js_error)?;
+            conn.database_mut().restore(db);
+        }
+        Ok(WasmDatabase {
+            conn,
+            name: Some(name),
+        })
+    }
+
+    /// Stores the database in IndexedDB under the name it was opened with, returning a
+    /// promise settled once it is written. The database is copied at once, the statements
+    /// run while it is written aren't part of it. Fails for a database not opened with
+    /// `openIndexedDb`, or within a transaction.
+    pub fn persist(&self) -> Result<Promise, JsValue> {
+        let name = self.name.clone().ok_or_else(|| {
+            js_sys::Error::new("the database wasn't opened with Database.openIndexedDb")
+        })?;
+        let db = self.conn.database();
+        if db.transaction.is_open() {
+            return Err(js_sys::Error::new(
+                "cannot persist the database within a transaction: COMMIT or ROLLBACK it first",
+            )
+            .into());
+        }
+        let bytes = db.serialize_compact().map_err(to_js_error)?;
+        Ok(future_to_promise(async move {
+            indexed_db::store(&name, &bytes).await?;
+            Ok(JsValue::UNDEFINED)
+        }))
+    }
+
+    /// Executes a single SQL statement and returns what it did, as an object with the
+    /// `message` produced by the engine, the `rowsAffected` by statements changing rows,
+    /// `null` for other statements, and the `lastInsertRowid`. Errors are thrown as
+    /// JavaScript `Error`s.
+    pub fn execute(&mut self, sql: &str) -> Result<Object, JsValue> {
+        let result = self.conn.execute(sql).map_err(to_js_error)?;
+        let object = Object::new();
+        Reflect::set(&object, &"message".into(), &result.message.into())?;
+        let rows_affected = result
+            .rows_affected
+            .map_or(JsValue::NULL, |rows| JsValue::from_f64(rows as f64));
+        Reflect::set(&object, &"rowsAffected".into(), &rows_affected)?;
+        Reflect::set(
+            &object,
+            &"lastInsertRowid".into(),
+            &JsValue::from_f64(result.last_insert_rowid as f64),
+        )?;
+        Ok(object)
+    }
+
+    /// Runs a single SELECT statement and returns its rows as an array of objects
+    /// keyed by column name.
+    pub fn query(&self, sql: &str) -> Result<Array, JsValue> {
+        let rows = self.conn.query(sql).map_err(to_js_error)?;
+        let names = rows
+            .column_names()
+            .into_iter()
+            .map(JsValue::from_str)
+            .collect::<Vec<JsValue>>();
+
+        let records = Array::new();
+        for row in rows {
+            let record = Object::new();
+            for (name, value) in names.iter().zip(row.values()) {
+                Reflect::set(&record, name, &to_js_value(value))?;
+            }
+            records.push(&record);
+        }
+        Ok(records)
+    }
+
+    /// Returns the names of the columns a SELECT statement returns, in order.
+    #[wasm_bindgen(js_name = columnNames)]
+    pub fn column_names(&self, sql: &str) -> Result<Array, JsValue> {
+        let rows = self.conn.query(sql).map_err(to_js_error)?;
+        Ok(rows
+            .column_names()
+            .into_iter()
+            .map(JsValue::from_str)
+            .collect())
+    }
+}
+
+impl Default for WasmDatabase {
+    fn default() -> Self {
+        WasmDatabase::open()
+    }
+}
+
+fn to_js_value(value: &Value) -> JsValue {
+    match value {
+        Value::Null => JsValue::NULL,
+        // Numbers in JavaScript are doubles, integers beyond 2^53 lose precision
+        Value::Integer(v) => JsValue::from_f64(*v as f64),
+        Value::Real(v) => JsValue::from_f64(*v),
+        Value::Text(v) => JsValue::from_str(v),
+        Value::Bool(v) => JsValue::from_bool(*v),
+        Value::Blob(v) => js_sys::Uint8Array::from(v.as_slice()).into(),
+    }
+}
+
+fn to_js_error(err: SQLRiteError) -> JsValue {
+    js_sys::Error::new(&err.to_string()).into()
+}
@@ -0,0 +1,32 @@
+//! Runs every sqllogictest script of `tests/logictest` against the engine.
+use std::fs;
+use std::path::Path;
+
+use sqlrite::logictest;
+
+#[test]
+fn logictest_scripts() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/logictest");
+    let mut paths = fs::read_dir(&dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "test"))
+        .collect::<Vec<_>>();
+    paths.sort();
+    assert!(!paths.is_empty());
+
+    let mut failures = vec![];
+    for path in &paths {
+        let report = logictest::run_file(path).unwrap();
+        for failure in report.failures {
+            failures.push(format!(
+                "{}:{}: {}\n{}",
+                path.display(),
+                failure.line,
+                failure.message,
+                failure.sql
+            ));
+        }
+    }
+    assert!(failures.is_empty(), "\n{}", failures.join("\n\n"));
+}